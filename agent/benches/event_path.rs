@@ -0,0 +1,65 @@
+//! Event hot path benchmarks
+//!
+//! Guards decoding and formatting of ring buffer records against
+//! regressions (e.g. reintroducing per-event copies or allocations).
+//!
+//! Run with: cargo bench --bench event_path
+
+use std::fmt::Write;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use sennet_agent::events::{self, DropEvent, EventHeader};
+
+/// Ring buffer records are 8-byte aligned
+#[repr(C, align(8))]
+struct Record([u8; 128]);
+
+fn drop_record() -> Record {
+    let mut record = Record([0u8; 128]);
+    record.0[..8].copy_from_slice(&EventHeader::of::<DropEvent>().to_bytes());
+    record.0[8..16].copy_from_slice(&1_000_000u64.to_ne_bytes());
+    record.0[16..20].copy_from_slice(&7u32.to_ne_bytes());
+    record.0[20..24].copy_from_slice(&2u32.to_ne_bytes());
+    record.0[24..26].copy_from_slice(&0x0800u16.to_ne_bytes());
+    record
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let record = drop_record();
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(1));
+
+    group.bench_function("view", |b| {
+        b.iter(|| events::view_event::<DropEvent>(black_box(&record.0)).map(|e| e.reason))
+    });
+    group.bench_function("copy", |b| b.iter(|| events::decode(black_box(&record.0))));
+    group.finish();
+}
+
+fn bench_format(c: &mut Criterion) {
+    let record = drop_record();
+    let event = events::view_event::<DropEvent>(&record.0).unwrap();
+    let mut group = c.benchmark_group("format");
+    group.throughput(Throughput::Elements(1));
+
+    let mut line = String::with_capacity(256);
+    group.bench_function("reused", |b| {
+        b.iter(|| {
+            line.clear();
+            let _ = write!(line, "reason={} ifindex={} proto={:#06x}", event.reason, event.ifindex, event.protocol);
+            black_box(line.len())
+        })
+    });
+    group.bench_function("fresh", |b| {
+        b.iter(|| {
+            let line = format!("reason={} ifindex={} proto={:#06x}", event.reason, event.ifindex, event.protocol);
+            black_box(line.len())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode, bench_format);
+criterion_main!(benches);
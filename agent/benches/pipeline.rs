@@ -0,0 +1,255 @@
+//! Event pipeline benchmarks
+//!
+//! Throughput of each pipeline stage on synthetic events: ring buffer
+//! decode, enrichment lookups, aggregation and serialization. Run before
+//! and after performance-affecting changes and include the numbers in the
+//! PR.
+//!
+//! Run with: cargo bench --bench pipeline
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use sennet_agent::coalesce::DropCoalescer;
+use sennet_agent::ebpf::{FlowInfo, FlowKey};
+use sennet_agent::events::{DropEvent, EventHeader, FlowEvent, NetfilterEvent, RawEvent, RstEvent};
+use sennet_agent::pipeline::{EnrichedEvent, InterfaceNames, Summary};
+use sennet_agent::rollup::FlowAggregator;
+use sennet_agent::{enrich, events, k8s};
+
+/// Events per benchmark iteration
+const BATCH: usize = 4096;
+
+/// Deterministic synthetic event generators
+mod synth {
+    use super::*;
+
+    /// Ring buffer records are 8-byte aligned
+    #[repr(C, align(8))]
+    #[derive(Clone, Copy)]
+    pub struct Record(pub [u8; 128]);
+
+    /// xorshift, so runs are comparable without pulling in a seeded RNG
+    pub struct Gen(u64);
+
+    impl Gen {
+        pub fn new() -> Self {
+            Self(0x9E37_79B9_7F4A_7C15)
+        }
+
+        pub fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    /// Drop records spread over a realistic handful of reasons and interfaces
+    pub fn drop_records(n: usize) -> Vec<Record> {
+        let mut rng = Gen::new();
+        (0..n)
+            .map(|i| {
+                let mut r = Record([0u8; 128]);
+                let reason = [2u32, 7, 16, 28, 37][(rng.next() % 5) as usize];
+                r.0[..8].copy_from_slice(&EventHeader::of::<DropEvent>().to_bytes());
+                r.0[8..16].copy_from_slice(&(i as u64 * 1_000).to_ne_bytes());
+                r.0[16..20].copy_from_slice(&reason.to_ne_bytes());
+                r.0[20..24].copy_from_slice(&(1 + (rng.next() % 4) as u32).to_ne_bytes());
+                r.0[24..26].copy_from_slice(&0x0800u16.to_ne_bytes());
+                r
+            })
+            .collect()
+    }
+
+    /// Decoded events: mostly drops, with netfilter, flow and reset traffic
+    pub fn raw_events(n: usize) -> Vec<RawEvent> {
+        let mut rng = Gen::new();
+        let records = drop_records(n);
+        records
+            .iter()
+            .map(|r| {
+                let header = match rng.next() % 10 {
+                    0 => EventHeader::of::<NetfilterEvent>(),
+                    1 => EventHeader::of::<FlowEvent>(),
+                    2 => EventHeader::of::<RstEvent>(),
+                    _ => EventHeader::of::<DropEvent>(),
+                };
+                let mut r = *r;
+                r.0[..8].copy_from_slice(&header.to_bytes());
+                events::decode(&r.0).unwrap()
+            })
+            .collect()
+    }
+
+    /// Short-lived flows from a few processes to a few hundred remotes
+    pub fn flows(n: usize) -> Vec<(FlowKey, FlowInfo)> {
+        let mut rng = Gen::new();
+        let procs = ["nginx", "curl", "postgres", "envoy"];
+        (0..n)
+            .map(|_| {
+                let comm = procs[(rng.next() % procs.len() as u64) as usize];
+                let mut info = FlowInfo {
+                    direction: 1,
+                    tx_bytes: rng.next() % 65536,
+                    rx_bytes: rng.next() % 65536,
+                    tx_packets: 10,
+                    rx_packets: 10,
+                    ..Default::default()
+                };
+                info.comm[..comm.len()].copy_from_slice(comm.as_bytes());
+                let key = FlowKey {
+                    src_ip: u32::from_be_bytes([10, 0, 0, 1]),
+                    dst_ip: u32::from_be_bytes([10, 1, (rng.next() % 4) as u8, (rng.next() % 64) as u8]),
+                    src_port: 32768 + (rng.next() % 28000) as u16,
+                    dst_port: 443,
+                    protocol: 6,
+                    ..Default::default()
+                };
+                (key, info)
+            })
+            .collect()
+    }
+
+    pub fn pod(i: usize) -> k8s::PodInfo {
+        k8s::PodInfo {
+            name: format!("api-{}", i),
+            namespace: "default".to_string(),
+            labels: HashMap::from([("app".to_string(), "api".to_string())]),
+            node_name: "node-1".to_string(),
+            ip: Some(format!("10.244.{}.{}", i / 256, i % 256)),
+            container_ids: vec![format!("{:064x}", i)],
+        }
+    }
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let records = synth::drop_records(BATCH);
+    let mut group = c.benchmark_group("pipeline/decode");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    group.bench_function("ring_records", |b| {
+        b.iter(|| {
+            records
+                .iter()
+                .filter_map(|r| events::decode(black_box(&r.0)))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn bench_enrichment(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pipeline/enrich");
+
+    // Interface 1 (lo) always exists, so after the first call this is a cache hit
+    let mut names = InterfaceNames::new();
+    names.lookup(1);
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("ifindex_cached", |b| b.iter(|| names.lookup(black_box(1))));
+
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let k8s = rt.block_on(async {
+        let manager = k8s::K8sManager::new().await.unwrap();
+        for i in 0..1000 {
+            let pod = synth::pod(i);
+            manager.cache_pod(&pod.container_ids[0].clone(), pod).await;
+        }
+        manager
+    });
+    let container = format!("{:064x}", 500);
+    group.bench_function("pod_by_container", |b| {
+        b.iter(|| rt.block_on(k8s.get_pod_by_container(black_box(&container))))
+    });
+    group.bench_function("pod_by_ip", |b| {
+        b.iter(|| rt.block_on(k8s.get_pod_by_ip(black_box("10.244.1.244"))))
+    });
+
+    // Severity gate in front of expensive enrichment, over a mixed storm
+    let events = synth::raw_events(BATCH);
+    group.throughput(Throughput::Elements(BATCH as u64));
+    group.bench_function("severity_gate", |b| {
+        b.iter(|| {
+            let mut gate = enrich::EnrichGate::new(enrich::Severity::Medium, 5);
+            events.iter().filter(|raw| gate.admit(raw)).count()
+        })
+    });
+    group.finish();
+}
+
+fn bench_aggregation(c: &mut Criterion) {
+    let events = synth::raw_events(BATCH);
+    let ifname: std::sync::Arc<str> = "eth0".into();
+    let flows = synth::flows(BATCH);
+    let mut group = c.benchmark_group("pipeline/aggregate");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    group.bench_function("summary", |b| {
+        b.iter(|| {
+            let mut summary = Summary::default();
+            for raw in &events {
+                summary.add(&EnrichedEvent::new(*raw, Some(ifname.clone())));
+            }
+            summary
+        })
+    });
+    group.bench_function("coalesce_drops", |b| {
+        let now = Instant::now();
+        b.iter(|| {
+            let mut coalescer = DropCoalescer::new(Duration::from_millis(100));
+            let mut emitted = 0;
+            for raw in &events {
+                if let RawEvent::Drop(e) = raw {
+                    emitted += coalescer.push(*e, now).is_some() as usize;
+                }
+            }
+            emitted + coalescer.flush_all().len()
+        })
+    });
+    group.bench_function("flow_rollups", |b| {
+        b.iter(|| {
+            let mut aggregator = FlowAggregator::new();
+            for (key, info) in &flows {
+                aggregator.add(key, info);
+            }
+            aggregator.flush()
+        })
+    });
+    group.finish();
+}
+
+fn bench_serialization(c: &mut Criterion) {
+    let ifname: std::sync::Arc<str> = "eth0".into();
+    let mut summary = Summary::default();
+    for raw in synth::raw_events(BATCH) {
+        summary.add(&EnrichedEvent::new(raw, Some(ifname.clone())));
+    }
+    let mut aggregator = FlowAggregator::new();
+    for (key, info) in synth::flows(BATCH) {
+        aggregator.add(&key, &info);
+    }
+    let window = aggregator.flush();
+
+    let mut group = c.benchmark_group("pipeline/serialize");
+    group.bench_function("summary_json", |b| {
+        b.iter(|| serde_json::to_vec(black_box(&summary)).unwrap())
+    });
+    group.throughput(Throughput::Elements(window.rollups.len() as u64));
+    group.bench_function("rollup_window_json", |b| {
+        b.iter_batched(
+            || Vec::with_capacity(64 * 1024),
+            |mut buf| {
+                serde_json::to_writer(&mut buf, black_box(&window)).unwrap();
+                buf
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode, bench_enrichment, bench_aggregation, bench_serialization);
+criterion_main!(benches);
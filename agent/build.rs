@@ -1,5 +1,14 @@
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=proto/local.proto");
+
+    // Messages and service of the local gRPC API; protoc is vendored so the
+    // build doesn't need one installed
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("vendored protoc"));
+    tonic_build::configure()
+        .build_transport(false)
+        .compile_protos(&["proto/local.proto"], &["proto"])
+        .expect("Failed to compile proto/local.proto");
     
     // If embed_bpf feature is enabled, copy the eBPF binary to OUT_DIR
     if std::env::var("CARGO_FEATURE_EMBED_BPF").is_ok() {
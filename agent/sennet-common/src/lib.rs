@@ -1,9 +1,65 @@
 //! Common types shared between userspace agent and eBPF programs
 //!
 //! This crate is compiled for both targets.
+//!
+//! The `serde` feature derives Serialize and Deserialize for the ring buffer
+//! event types, with camelCase field names and without padding fields, the
+//! way the agent's control socket streams them. The `user` feature
+//! implements `aya::Pod` for the types the agent reads from maps, and the
+//! `zerocopy` feature derives zerocopy's `FromBytes` for the ring buffer
+//! records, so both sides compile against one definition.
 
 #![cfg_attr(feature = "no-std", no_std)]
 
+/// Serde for the byte arrays in events, which are longer than serde's
+/// built-in array impls cover (`serde` feature)
+///
+/// Arrays serialize as bytes. Shorter input is zero-padded, longer input
+/// cut short.
+#[cfg(feature = "serde")]
+mod byte_array {
+    use core::fmt;
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer, const N: usize>(bytes: &[u8; N], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error> {
+        deserializer.deserialize_bytes(ByteArray::<N>)
+    }
+
+    struct ByteArray<const N: usize>;
+
+    impl<'de, const N: usize> Visitor<'de> for ByteArray<N> {
+        type Value = [u8; N];
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "at most {} bytes", N)
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<[u8; N], E> {
+            let mut array = [0; N];
+            let len = bytes.len().min(N);
+            array[..len].copy_from_slice(&bytes[..len]);
+            Ok(array)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<[u8; N], A::Error> {
+            let mut array = [0; N];
+            let mut i = 0;
+            while let Some(byte) = seq.next_element::<u8>()? {
+                if let Some(slot) = array.get_mut(i) {
+                    *slot = byte;
+                }
+                i += 1;
+            }
+            Ok(array)
+        }
+    }
+}
+
 /// Packet statistics counters
 #[repr(C)]
 #[derive(Clone, Copy, Default, Debug)]
@@ -18,8 +74,173 @@ pub struct PacketCounters {
     pub tx_bytes: u64,
     /// Dropped packets
     pub drop_count: u64,
+    /// Received packets and bytes by L4 protocol
+    pub rx_proto: ProtoCounters,
+    /// Transmitted packets and bytes by L4 protocol
+    pub tx_proto: ProtoCounters,
+}
+
+// SAFETY: PacketCounters is #[repr(C)], contains only u64 fields,
+// and has no padding. It is safe to interpret as bytes.
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for PacketCounters {}
+
+impl PacketCounters {
+    /// Count a packet of `len` bytes carrying IP `protocol` (0 = not IP)
+    /// received (`direction` 0) or sent (1); `quic` counts it as QUIC
+    /// rather than UDP
+    #[inline(always)]
+    pub fn add(&mut self, direction: u32, protocol: u8, quic: bool, len: u64) {
+        let (packets, bytes, proto) = if direction == 0 {
+            (&mut self.rx_packets, &mut self.rx_bytes, &mut self.rx_proto)
+        } else {
+            (&mut self.tx_packets, &mut self.tx_bytes, &mut self.tx_proto)
+        };
+        *packets += 1;
+        *bytes += len;
+        if quic {
+            proto.add_quic(len);
+        } else {
+            proto.add(protocol, len);
+        }
+    }
+}
+
+/// Interfaces counted separately in IFACE_COUNTERS; traffic on more is
+/// still in COUNTERS
+pub const MAX_INTERFACES: u32 = 64;
+
+/// Packets and bytes by L4 protocol, for one direction
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+pub struct ProtoCounters {
+    pub tcp_packets: u64,
+    pub tcp_bytes: u64,
+    pub udp_packets: u64,
+    pub udp_bytes: u64,
+    /// ICMP and ICMPv6
+    pub icmp_packets: u64,
+    pub icmp_bytes: u64,
+    /// Other IP protocols and non-IP frames
+    pub other_packets: u64,
+    pub other_bytes: u64,
+    /// UDP datagrams to or from port 443 that look like QUIC; not counted
+    /// under `udp` (absent from agents that predate QUIC classification)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub quic_packets: u64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub quic_bytes: u64,
+}
+
+impl ProtoCounters {
+    /// Count a packet of `len` bytes carrying IP `protocol` (0 = not IP)
+    #[inline(always)]
+    pub fn add(&mut self, protocol: u8, len: u64) {
+        let (packets, bytes) = match protocol {
+            ipproto::TCP => (&mut self.tcp_packets, &mut self.tcp_bytes),
+            ipproto::UDP => (&mut self.udp_packets, &mut self.udp_bytes),
+            ipproto::ICMP | ipproto::ICMPV6 => (&mut self.icmp_packets, &mut self.icmp_bytes),
+            _ => (&mut self.other_packets, &mut self.other_bytes),
+        };
+        *packets += 1;
+        *bytes += len;
+    }
+
+    /// Count a QUIC datagram of `len` bytes
+    #[inline(always)]
+    pub fn add_quic(&mut self, len: u64) {
+        self.quic_packets += 1;
+        self.quic_bytes += len;
+    }
+
+    /// Add another CPU's (or interface's) counts
+    pub fn merge(&mut self, other: &Self) {
+        self.tcp_packets += other.tcp_packets;
+        self.tcp_bytes += other.tcp_bytes;
+        self.udp_packets += other.udp_packets;
+        self.udp_bytes += other.udp_bytes;
+        self.icmp_packets += other.icmp_packets;
+        self.icmp_bytes += other.icmp_bytes;
+        self.other_packets += other.other_packets;
+        self.other_bytes += other.other_bytes;
+        self.quic_packets += other.quic_packets;
+        self.quic_bytes += other.quic_bytes;
+    }
+
+    /// `(name, packets, bytes)` per protocol, in display order
+    pub fn rows(&self) -> [(&'static str, u64, u64); 5] {
+        [
+            ("TCP", self.tcp_packets, self.tcp_bytes),
+            ("UDP", self.udp_packets, self.udp_bytes),
+            ("QUIC", self.quic_packets, self.quic_bytes),
+            ("ICMP", self.icmp_packets, self.icmp_bytes),
+            ("other", self.other_packets, self.other_bytes),
+        ]
+    }
+}
+
+/// TCP segments by control flag, for one direction
+///
+/// SYN+ACK counts as `syn_ack` only, so `syn` is connection attempts. FIN
+/// and RST are counted whatever else is set.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+pub struct TcpFlagCounters {
+    pub syn: u64,
+    pub syn_ack: u64,
+    pub fin: u64,
+    pub rst: u64,
+}
+
+impl TcpFlagCounters {
+    /// Count a segment with TCP header flags byte `flags`
+    #[inline(always)]
+    pub fn add(&mut self, flags: u8) {
+        if flags & tcp_flag::SYN != 0 {
+            if flags & tcp_flag::ACK != 0 {
+                self.syn_ack += 1;
+            } else {
+                self.syn += 1;
+            }
+        }
+        if flags & tcp_flag::FIN != 0 {
+            self.fin += 1;
+        }
+        if flags & tcp_flag::RST != 0 {
+            self.rst += 1;
+        }
+    }
+
+    /// Add another CPU's counts
+    pub fn merge(&mut self, other: &Self) {
+        self.syn += other.syn;
+        self.syn_ack += other.syn_ack;
+        self.fin += other.fin;
+        self.rst += other.rst;
+    }
+
+    /// Counts since `earlier`, for rates
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            syn: self.syn.saturating_sub(earlier.syn),
+            syn_ack: self.syn_ack.saturating_sub(earlier.syn_ack),
+            fin: self.fin.saturating_sub(earlier.fin),
+            rst: self.rst.saturating_sub(earlier.rst),
+        }
+    }
+
+    /// `(name, segments)` per flag, in display order
+    pub fn rows(&self) -> [(&'static str, u64); 4] {
+        [("SYN", self.syn), ("SYN-ACK", self.syn_ack), ("FIN", self.fin), ("RST", self.rst)]
+    }
 }
 
+// SAFETY: TcpFlagCounters is #[repr(C)] with only u64 fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for TcpFlagCounters {}
+
 /// Event types for RingBuf
 #[repr(u32)]
 #[derive(Clone, Copy)]
@@ -30,22 +251,133 @@ pub enum EventType {
     Anomaly = 2,
 }
 
-/// Event sent via RingBuf
+/// EtherType values (host byte order)
+pub mod eth_p {
+    pub const IP: u16 = 0x0800;
+    pub const IPV6: u16 = 0x86DD;
+    /// 802.1Q VLAN tag
+    pub const VLAN: u16 = 0x8100;
+    /// 802.1ad service tag (outer tag of QinQ)
+    pub const QINQ: u16 = 0x88A8;
+}
+
+/// Ethernet header length without VLAN tags
+pub const ETH_HLEN: usize = 14;
+/// Length of one 802.1Q/802.1ad tag
+pub const VLAN_HLEN: usize = 4;
+/// VLAN tags skipped before the network header (QinQ = 2)
+pub const MAX_VLAN_TAGS: usize = 2;
+
+/// EtherType and offset of the network header in an Ethernet frame,
+/// skipping up to [`MAX_VLAN_TAGS`] 802.1Q/802.1ad tags
+///
+/// `load_be16(offset)` reads the big-endian u16 at `offset` of the frame.
+/// The loop has a constant bound, so the eBPF verifier accepts it. A frame
+/// with more tags returns the last tag's EtherType, which matches nothing.
+#[inline(always)]
+pub fn network_header(mut load_be16: impl FnMut(usize) -> Option<u16>) -> Option<(u16, usize)> {
+    let mut offset = ETH_HLEN - 2;
+    let mut eth_proto = load_be16(offset)?;
+    for _ in 0..MAX_VLAN_TAGS {
+        if eth_proto != eth_p::VLAN && eth_proto != eth_p::QINQ {
+            break;
+        }
+        offset += VLAN_HLEN;
+        eth_proto = load_be16(offset)?;
+    }
+    Some((eth_proto, offset + 2))
+}
+
+/// IP protocol numbers (IPv4 protocol, IPv6 next header)
+pub mod ipproto {
+    pub const ICMP: u8 = 1;
+    pub const TCP: u8 = 6;
+    pub const UDP: u8 = 17;
+    pub const ICMPV6: u8 = 58;
+}
+
+/// TCP header flag bits (byte 13 of the header)
+pub mod tcp_flag {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+    pub const ACK: u8 = 0x10;
+}
+
+/// IPv4 or IPv6 address in network byte order
+///
+/// IPv4 addresses are stored IPv4-mapped (`::ffff:a.b.c.d`), so one field
+/// carries either family.
+pub type Addr128 = [u8; 16];
+
+/// IPv4-mapped form of an IPv4 address (network byte order)
+#[inline(always)]
+pub const fn ipv4_mapped(addr: [u8; 4]) -> Addr128 {
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, addr[0], addr[1], addr[2], addr[3]]
+}
+
+/// The IPv4 address an IPv4-mapped `addr` holds; None for IPv6
+#[inline(always)]
+pub fn mapped_ipv4(addr: &Addr128) -> Option<[u8; 4]> {
+    if addr[..10].iter().all(|&b| b == 0) && addr[10] == 0xff && addr[11] == 0xff {
+        Some([addr[12], addr[13], addr[14], addr[15]])
+    } else {
+        None
+    }
+}
+
+/// Packets longer than this (bytes, from the Ethernet header) are reported
+/// as `PacketEvent`s unless the LARGE_PACKET_THRESHOLD map overrides it
+pub const DEFAULT_LARGE_PACKET_THRESHOLD: u32 = 9000; // Jumbo frame size
+
+/// Event sent via RingBuf (EVENTS)
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
 pub struct PacketEvent {
-    /// Event type
+    /// Kernel timestamp in nanoseconds
+    pub timestamp_ns: u64,
+    /// Source address (zero if the packet isn't IP)
+    pub src_addr: Addr128,
+    /// Destination address
+    pub dst_addr: Addr128,
+    /// Event type (see `EventType`)
     pub event_type: u32,
     /// Packet size in bytes
     pub size: u32,
-    /// Source IP (network byte order)
-    pub src_ip: u32,
-    /// Destination IP (network byte order)
-    pub dst_ip: u32,
-    /// Protocol (TCP=6, UDP=17, etc)
+    /// Interface the packet was seen on
+    pub ifindex: u32,
+    /// EtherType (`eth_p::IP`, `eth_p::IPV6`, ...)
+    pub eth_proto: u16,
+    /// IP protocol, or IPv6 next header (TCP=6, UDP=17, etc)
     pub protocol: u8,
+    /// Direction (0=ingress, 1=egress)
+    pub direction: u8,
+    pub sample_rate: SampleRate,
     /// Padding for alignment
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub _pad: [u8; 3],
+    /// `Anomaly` only: packets per second the source sent, measured since
+    /// it last stayed under the threshold
+    pub pps: u32,
+    /// TCP/UDP source port, host byte order (0 = other protocols)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub src_port: u16,
+    /// TCP/UDP destination port
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dst_port: u16,
+    /// Padding for alignment
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _pad2: u32,
+}
+
+impl PacketEvent {
+    /// Whether this reports a source over the packet rate threshold rather
+    /// than a large packet
+    pub fn is_anomaly(&self) -> bool {
+        self.event_type == EventType::Anomaly as u32
+    }
 }
 
 // ============================================================================
@@ -99,12 +431,18 @@ pub mod drop_reason {
     pub const NEIGH_QUEUEFULL: u32 = 42;
     pub const NEIGH_DEAD: u32 = 43;
     pub const TC_EGRESS: u32 = 44;
+    pub const QDISC_DROP: u32 = 45;
+    pub const CPU_BACKLOG: u32 = 46;
+    pub const XDP: u32 = 47;
+    pub const TC_INGRESS: u32 = 48;
     // Add more as needed from kernel headers
 }
 
 /// Event for packet drops (captured from kfree_skb tracepoint)
 #[repr(C)]
 #[derive(Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
 pub struct DropEvent {
     /// Kernel timestamp in nanoseconds
     pub timestamp_ns: u64,
@@ -114,8 +452,56 @@ pub struct DropEvent {
     pub ifindex: u32,
     /// Protocol (ETH_P_IP=0x0800, ETH_P_IPV6=0x86DD, etc.)
     pub protocol: u16,
+    pub sample_rate: SampleRate,
+    /// IP protocol from the packet's own headers (0 = not parsed)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ip_protocol: u8,
+    /// Owning socket's local IP (same encoding as `FlowKey`, 0 = no socket)
+    pub src_ip: u32,
+    /// Owning socket's remote IP
+    pub dst_ip: u32,
+    /// Owning socket's local port
+    pub src_port: u16,
+    /// Owning socket's remote port
+    pub dst_port: u16,
+    /// Packet length in bytes (skb->len, 0 = unknown)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub len: u32,
     /// Padding for alignment
-    pub _pad: u16,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _pad2: u32,
+    /// Packet's source address from its IP header (IPv4-mapped for IPv4)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub src_addr: Addr128,
+    /// Packet's destination address
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dst_addr: Addr128,
+    /// Packet's TCP/UDP source port, host byte order (0 = other protocols)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub packet_src_port: u16,
+    /// Packet's TCP/UDP destination port
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub packet_dst_port: u16,
+    /// Kernel stack ID in DROP_STACKS plus one (0 = not captured)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub stack_id: u32,
+}
+
+impl DropEvent {
+    /// Whether the kernel found a socket owning the dropped packet
+    pub fn has_tuple(&self) -> bool {
+        self.src_port != 0 || self.dst_port != 0
+    }
+
+    /// Whether the kernel parsed the packet's own headers
+    pub fn has_packet_tuple(&self) -> bool {
+        self.src_addr != [0; 16] || self.dst_addr != [0; 16]
+    }
+
+    /// ID of the kernel stack that freed the packet, if the kernel captured one
+    pub fn kernel_stack_id(&self) -> Option<u32> {
+        self.stack_id.checked_sub(1)
+    }
 }
 
 /// Human-readable drop reason string
@@ -173,71 +559,216 @@ pub mod nf_hook {
     pub const POST_ROUTING: u8 = 4;
 }
 
-/// Netfilter verdicts
+/// Netfilter verdicts (NF_*)
 pub mod nf_verdict {
-    pub const DROP: u32 = 0;
-    pub const ACCEPT: u32 = 1;
-    pub const STOLEN: u32 = 2;
-    pub const QUEUE: u32 = 3;
-    pub const REPEAT: u32 = 4;
-    pub const STOP: u32 = 5;
+    pub const DROP: u8 = 0;
+    pub const ACCEPT: u8 = 1;
+    pub const STOLEN: u8 = 2;
+    pub const QUEUE: u8 = 3;
+    pub const REPEAT: u8 = 4;
+    pub const STOP: u8 = 5;
 }
 
 /// Event for netfilter hook processing (Phase 6.2)
 #[repr(C)]
 #[derive(Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
 pub struct NetfilterEvent {
     /// Kernel timestamp in nanoseconds
     pub timestamp_ns: u64,
-    /// Hook type (NF_INET_LOCAL_IN, etc.)
+    /// Hook type (`NfHook`)
     pub hook: u8,
-    /// Protocol family (2=AF_INET/IPv4, 10=AF_INET6/IPv6)
+    /// Protocol family (`NfFamily`)
     pub pf: u8,
-    /// Verdict (NF_DROP=0, NF_ACCEPT=1, etc.)
+    /// Verdict (`NfVerdict`)
     pub verdict: u8,
-    /// Padding for alignment
-    pub _pad: u8,
+    pub sample_rate: SampleRate,
     /// Input interface index
     pub ifindex_in: u32,
     /// Output interface index
     pub ifindex_out: u32,
 }
 
+impl NetfilterEvent {
+    /// None for hooks newer than this build
+    pub fn nf_hook(&self) -> Option<NfHook> {
+        NfHook::from_u8(self.hook)
+    }
+
+    pub fn nf_family(&self) -> Option<NfFamily> {
+        NfFamily::from_u8(self.pf)
+    }
+
+    pub fn nf_verdict(&self) -> Option<NfVerdict> {
+        NfVerdict::from_u8(self.verdict)
+    }
+
+    /// Whether a rule dropped the packet
+    pub fn is_drop(&self) -> bool {
+        matches!(self.nf_verdict(), Some(NfVerdict::Drop))
+    }
+
+    /// Hook and verdict, like `INPUT/DROP`
+    #[cfg(not(feature = "no-std"))]
+    pub fn hook_verdict(&self) -> String {
+        format!("{}/{}", nf_hook_str(self.hook), nf_verdict_str(self.verdict))
+    }
+}
+
+/// Netfilter hook of a `NetfilterEvent` (`nf_hook` values)
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NfHook {
+    PreRouting = nf_hook::PRE_ROUTING,
+    LocalIn = nf_hook::LOCAL_IN,
+    Forward = nf_hook::FORWARD,
+    LocalOut = nf_hook::LOCAL_OUT,
+    PostRouting = nf_hook::POST_ROUTING,
+}
+
+impl NfHook {
+    pub const fn from_u8(hook: u8) -> Option<Self> {
+        match hook {
+            nf_hook::PRE_ROUTING => Some(NfHook::PreRouting),
+            nf_hook::LOCAL_IN => Some(NfHook::LocalIn),
+            nf_hook::FORWARD => Some(NfHook::Forward),
+            nf_hook::LOCAL_OUT => Some(NfHook::LocalOut),
+            nf_hook::POST_ROUTING => Some(NfHook::PostRouting),
+            _ => None,
+        }
+    }
+
+    /// iptables chain name
+    #[cfg(not(feature = "no-std"))]
+    pub const fn name(self) -> &'static str {
+        match self {
+            NfHook::PreRouting => "PREROUTING",
+            NfHook::LocalIn => "INPUT",
+            NfHook::Forward => "FORWARD",
+            NfHook::LocalOut => "OUTPUT",
+            NfHook::PostRouting => "POSTROUTING",
+        }
+    }
+}
+
+/// Netfilter verdict of a `NetfilterEvent` (`nf_verdict` values)
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NfVerdict {
+    Drop = nf_verdict::DROP,
+    Accept = nf_verdict::ACCEPT,
+    Stolen = nf_verdict::STOLEN,
+    Queue = nf_verdict::QUEUE,
+    Repeat = nf_verdict::REPEAT,
+    Stop = nf_verdict::STOP,
+}
+
+impl NfVerdict {
+    pub const fn from_u8(verdict: u8) -> Option<Self> {
+        match verdict {
+            nf_verdict::DROP => Some(NfVerdict::Drop),
+            nf_verdict::ACCEPT => Some(NfVerdict::Accept),
+            nf_verdict::STOLEN => Some(NfVerdict::Stolen),
+            nf_verdict::QUEUE => Some(NfVerdict::Queue),
+            nf_verdict::REPEAT => Some(NfVerdict::Repeat),
+            nf_verdict::STOP => Some(NfVerdict::Stop),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "no-std"))]
+    pub const fn name(self) -> &'static str {
+        match self {
+            NfVerdict::Drop => "DROP",
+            NfVerdict::Accept => "ACCEPT",
+            NfVerdict::Stolen => "STOLEN",
+            NfVerdict::Queue => "QUEUE",
+            NfVerdict::Repeat => "REPEAT",
+            NfVerdict::Stop => "STOP",
+        }
+    }
+}
+
+/// Protocol family of a `NetfilterEvent` (NFPROTO_*)
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NfFamily {
+    /// nftables `inet` tables (IPv4 and IPv6)
+    Inet = 1,
+    Ipv4 = 2,
+    Arp = 3,
+    Netdev = 5,
+    Bridge = 7,
+    Ipv6 = 10,
+}
+
+impl NfFamily {
+    pub const fn from_u8(pf: u8) -> Option<Self> {
+        match pf {
+            1 => Some(NfFamily::Inet),
+            2 => Some(NfFamily::Ipv4),
+            3 => Some(NfFamily::Arp),
+            5 => Some(NfFamily::Netdev),
+            7 => Some(NfFamily::Bridge),
+            10 => Some(NfFamily::Ipv6),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "no-std"))]
+    pub const fn name(self) -> &'static str {
+        match self {
+            NfFamily::Inet => "inet",
+            NfFamily::Ipv4 => "IPv4",
+            NfFamily::Arp => "ARP",
+            NfFamily::Netdev => "netdev",
+            NfFamily::Bridge => "bridge",
+            NfFamily::Ipv6 => "IPv6",
+        }
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+macro_rules! display_name {
+    ($($ty:ident),*) => {
+        $(impl core::fmt::Display for $ty {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.pad(self.name())
+            }
+        })*
+    };
+}
+
+#[cfg(not(feature = "no-std"))]
+display_name!(NfHook, NfVerdict, NfFamily);
+
 /// Human-readable hook name
 #[cfg(not(feature = "no-std"))]
 pub fn nf_hook_str(hook: u8) -> &'static str {
-    use nf_hook::*;
-    match hook {
-        PRE_ROUTING => "PREROUTING",
-        LOCAL_IN => "INPUT",
-        FORWARD => "FORWARD",
-        LOCAL_OUT => "OUTPUT",
-        POST_ROUTING => "POSTROUTING",
-        _ => "UNKNOWN",
-    }
+    NfHook::from_u8(hook).map_or("UNKNOWN", NfHook::name)
 }
 
 /// Human-readable verdict name
 #[cfg(not(feature = "no-std"))]
 pub fn nf_verdict_str(verdict: u8) -> &'static str {
-    match verdict {
-        0 => "DROP",
-        1 => "ACCEPT",
-        2 => "STOLEN",
-        3 => "QUEUE",
-        4 => "REPEAT",
-        5 => "STOP",
-        _ => "UNKNOWN",
-    }
+    NfVerdict::from_u8(verdict).map_or("UNKNOWN", NfVerdict::name)
+}
+
+/// Human-readable protocol family name
+#[cfg(not(feature = "no-std"))]
+pub fn nf_family_str(pf: u8) -> &'static str {
+    NfFamily::from_u8(pf).map_or("UNKNOWN", NfFamily::name)
 }
 
 // ============================================================================
 // Flow Tracking Types (Phase 8: Process Attribution)
 // ============================================================================
 
-/// 5-tuple flow key for tracking connections
+/// 5-tuple flow key for tracking connections (FLOWS and TLS_SNI maps)
 #[repr(C)]
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
 pub struct FlowKey {
     /// Source IP address (network byte order)
     pub src_ip: u32,
@@ -250,12 +781,94 @@ pub struct FlowKey {
     /// Protocol (6=TCP, 17=UDP)
     pub protocol: u8,
     /// Padding for alignment
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub _pad: [u8; 3],
 }
 
-/// Flow information with PID attribution
+// SAFETY: FlowKey is #[repr(C)] with its padding spelled out as fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for FlowKey {}
+
+/// Top-talkers key: source and destination address as seen on the wire
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+pub struct IpPair {
+    pub src_addr: Addr128,
+    pub dst_addr: Addr128,
+}
+
+// SAFETY: IpPair is #[repr(C)] with two byte arrays and no padding
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for IpPair {}
+
+/// Traffic from one address to another, in the top-talkers map
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TalkerCounters {
+    pub packets: u64,
+    pub bytes: u64,
+    /// Kernel time of the last packet (ns)
+    pub last_seen_ns: u64,
+}
+
+// SAFETY: TalkerCounters is #[repr(C)] with only u64 fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for TalkerCounters {}
+
+/// Traffic of one cgroup, in the per-CPU CGROUP_COUNTERS map keyed by
+/// cgroup ID (the cgroup v2 directory's inode number)
 #[repr(C)]
 #[derive(Clone, Copy, Default, Debug)]
+pub struct CgroupCounters {
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+}
+
+// SAFETY: CgroupCounters is #[repr(C)] with only u64 fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for CgroupCounters {}
+
+/// TCP connection churn from the sock:inet_sock_set_state tracepoint, in
+/// the single-entry per-CPU CONN_CHURN map. Counts are since the programs
+/// were loaded.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+pub struct ConnChurn {
+    /// Connections this host opened (CLOSE -> SYN_SENT)
+    pub active_opens: u64,
+    /// Connections accepted from remotes (SYN_RECV -> ESTABLISHED)
+    pub passive_opens: u64,
+    /// Opened connections that reached CLOSE
+    pub closes: u64,
+    /// Handshakes that never completed (SYN_SENT or SYN_RECV -> CLOSE)
+    pub failed: u64,
+}
+
+// SAFETY: ConnChurn is #[repr(C)] with only u64 fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for ConnChurn {}
+
+impl ConnChurn {
+    pub fn add(&mut self, other: &ConnChurn) {
+        self.active_opens += other.active_opens;
+        self.passive_opens += other.passive_opens;
+        self.closes += other.closes;
+        self.failed += other.failed;
+    }
+
+    /// Connections opened in either direction
+    pub fn opens(&self) -> u64 {
+        self.active_opens + self.passive_opens
+    }
+}
+
+/// Flow information with PID attribution (FLOWS map)
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
 pub struct FlowInfo {
     /// Process ID that owns this flow
     pub pid: u32,
@@ -265,25 +878,61 @@ pub struct FlowInfo {
     pub comm: [u8; 16],
     /// Timestamp when flow was created (kernel time ns)
     pub start_time_ns: u64,
-    /// Total bytes received on this flow
+    /// Bytes the owning process read from this flow (tcp_cleanup_rbuf)
     pub rx_bytes: u64,
-    /// Total bytes transmitted on this flow
+    /// Bytes the owning process wrote to this flow (tcp_sendmsg)
     pub tx_bytes: u64,
-    /// Total packets received
+    /// Reads that returned data (one per recvmsg, not per segment)
     pub rx_packets: u32,
-    /// Total packets transmitted
+    /// Writes (one per sendmsg, not per segment)
     pub tx_packets: u32,
     /// Flow state (0=unknown, 1=active, 2=closing, 3=closed)
     pub state: u8,
     /// Direction (0=unknown, 1=outbound, 2=inbound)
     pub direction: u8,
+    /// Application protocol seen on the flow (`app_proto`)
+    pub app_protocol: u8,
     /// Padding
-    pub _pad: [u8; 2],
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _pad: u8,
+}
+
+// SAFETY: FlowInfo is #[repr(C)] with its padding spelled out as fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for FlowInfo {}
+
+/// Application protocols recognized on a flow (`FlowInfo::app_protocol`)
+pub mod app_proto {
+    pub const UNKNOWN: u8 = 0;
+    pub const QUIC: u8 = 1;
+}
+
+/// Lowercase name of an `app_proto` value, None if unknown
+pub fn app_proto_str(app_protocol: u8) -> Option<&'static str> {
+    match app_protocol {
+        app_proto::QUIC => Some("quic"),
+        _ => None,
+    }
+}
+
+/// Process that created a socket, in the SOCK_OWNERS map keyed by socket
+/// cookie
+///
+/// Recorded in the creating process's context, so packets the socket sends
+/// later (from softirq too) can be attributed to it.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SockOwner {
+    pub pid: u32,
+    pub tgid: u32,
+    pub comm: [u8; 16],
 }
 
 /// Flow event sent via RingBuf (for new/closed flows)
 #[repr(C)]
 #[derive(Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
 pub struct FlowEvent {
     /// Timestamp of event
     pub timestamp_ns: u64,
@@ -293,8 +942,7 @@ pub struct FlowEvent {
     pub direction: u8,
     /// Protocol
     pub protocol: u8,
-    /// Padding
-    pub _pad: u8,
+    pub sample_rate: SampleRate,
     /// Process ID
     pub pid: u32,
     /// Source IP
@@ -309,24 +957,1916 @@ pub struct FlowEvent {
     pub comm: [u8; 16],
 }
 
-/// Flow event types
-pub mod flow_event_type {
+// ============================================================================
+// TCP RST Tracking
+// ============================================================================
+
+/// TCP RST segment observed at the TC hook
+///
+/// Addresses and ports are in host byte order.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
+pub struct RstEvent {
+    /// Timestamp (ns since boot)
+    pub timestamp_ns: u64,
+    /// Source IPv4 address
+    pub src_ip: u32,
+    /// Destination IPv4 address
+    pub dst_ip: u32,
+    /// Source port
+    pub src_port: u16,
+    /// Destination port
+    pub dst_port: u16,
+    /// Interface the segment was seen on
+    pub ifindex: u32,
+    /// Direction (0=ingress, 1=egress)
+    pub direction: u8,
+    /// Full TCP flags byte (RST may be combined with ACK)
+    pub tcp_flags: u8,
+    pub sample_rate: SampleRate,
+    /// Padding for alignment
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _pad: [u8; 5],
+}
+
+/// TCP segment retransmitted by the local stack (tcp:tcp_retransmit_skb)
+///
+/// Addresses are IPv4-mapped for IPv4 sockets; ports are in host byte order.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
+pub struct RetransmitEvent {
+    /// Timestamp (ns since boot)
+    pub timestamp_ns: u64,
+    /// Local address
+    pub src_addr: Addr128,
+    /// Remote address
+    pub dst_addr: Addr128,
+    /// Local port
+    pub src_port: u16,
+    /// Remote port
+    pub dst_port: u16,
+    /// Socket state (`tcp_state`: 1 = ESTABLISHED, 2 = SYN_SENT, ...)
+    pub state: u8,
+    pub sample_rate: SampleRate,
+    /// Padding for alignment
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _pad: [u8; 2],
+}
+
+/// Bytes of the question name kept in a `DnsEvent`
+pub const DNS_NAME_LEN: usize = 64;
+
+/// DNS query or response on UDP port 53, seen at the TC or XDP hook
+///
+/// Addresses are IPv4-mapped for IPv4. The question name is copied in wire
+/// format (length-prefixed labels ending in a zero byte) and cut short at
+/// `DNS_NAME_LEN` bytes.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
+pub struct DnsEvent {
+    /// Timestamp (ns since boot)
+    pub timestamp_ns: u64,
+    /// Time since the matching query (ns); 0 for queries and for responses
+    /// whose query wasn't seen
+    pub latency_ns: u64,
+    /// Address that sent the query
+    pub client_addr: Addr128,
+    /// Address of the resolver
+    pub server_addr: Addr128,
+    /// Client's UDP port (host byte order)
+    pub client_port: u16,
+    /// Transaction ID
+    pub id: u16,
+    /// Type of the first question (1 = A, 28 = AAAA, ...); 0 if the name
+    /// didn't fit
+    pub qtype: u16,
+    /// 0 = query, 1 = response
+    pub is_response: u8,
+    /// Response code (3 = NXDOMAIN); 0 for queries
+    pub rcode: u8,
+    /// 0 = ingress, 1 = egress
+    pub direction: u8,
+    pub sample_rate: SampleRate,
+    /// Padding for alignment
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _pad: [u8; 2],
+    /// Interface index
+    pub ifindex: u32,
+    /// First question name, wire format
+    #[cfg_attr(feature = "serde", serde(with = "byte_array"))]
+    pub qname: [u8; DNS_NAME_LEN],
+}
+
+impl Default for DnsEvent {
+    fn default() -> Self {
+        // SAFETY: every field is an integer or an array of integers
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+/// Bytes of the server name kept in a `TlsSni`
+pub const TLS_SNI_LEN: usize = 64;
+
+/// UDP port QUIC datagrams are classified on (HTTP/3)
+pub const QUIC_PORT: u16 = 443;
+
+/// Whether a UDP payload looks like a QUIC packet
+///
+/// `load_u8` reads the payload byte at an offset, None past its end. Long
+/// headers (handshake packets) need the fixed bit and a known version, or
+/// version 0 for version negotiation; short headers, which carry the data
+/// once the handshake is done, only have the fixed bit to go by. Callers
+/// restrict this to `QUIC_PORT`, where that is enough.
+#[inline(always)]
+pub fn quic_packet(mut load_u8: impl FnMut(usize) -> Option<u8>) -> bool {
+    const HEADER_FORM: u8 = 0x80;
+    const FIXED_BIT: u8 = 0x40;
+
+    let Some(first) = load_u8(0) else {
+        return false;
+    };
+    if first & HEADER_FORM == 0 {
+        return first & FIXED_BIT != 0;
+    }
+    let mut version = 0u32;
+    for i in 1..5 {
+        match load_u8(i) {
+            Some(b) => version = version << 8 | b as u32,
+            None => return false,
+        }
+    }
+    match version {
+        0 => true,
+        // QUIC v1, v2 and the IETF drafts
+        0x0000_0001 | 0x6b33_43cf | 0xff00_0000..=0xff00_00ff => first & FIXED_BIT != 0,
+        _ => false,
+    }
+}
+
+/// Extensions of a ClientHello looked at before giving up on finding the
+/// server name; bounds the parse loop for the verifier
+pub const TLS_MAX_EXTENSIONS: usize = 32;
+
+/// Server name a client asked for in its TLS ClientHello, in the TLS_SNI
+/// map keyed by the outbound flow key
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TlsSni {
+    /// Length of the name in the ClientHello; more than `TLS_SNI_LEN` when
+    /// it was cut short
+    pub len: u16,
+    /// Padding for alignment
+    pub _pad: [u8; 2],
+    /// Host name, not NUL-terminated
+    pub name: [u8; TLS_SNI_LEN],
+}
+
+// SAFETY: TlsSni is #[repr(C)] with its padding spelled out as a field
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for TlsSni {}
+
+impl TlsSni {
+    /// Host name as sent; names longer than `TLS_SNI_LEN` bytes end in "…"
+    #[cfg(not(feature = "no-std"))]
+    pub fn server_name(&self) -> String {
+        let len = (self.len as usize).min(TLS_SNI_LEN);
+        let mut name = String::from_utf8_lossy(&self.name[..len]).into_owned();
+        if self.len as usize > TLS_SNI_LEN {
+            name.push('…');
+        }
+        name
+    }
+}
+
+/// Find the server name in a TLS ClientHello at the start of a TCP payload
+///
+/// `load_u8` reads the payload byte at an offset, None past its end. Only
+/// a ClientHello whose extensions fit in the segment is parsed; at most
+/// `TLS_MAX_EXTENSIONS` extensions are skipped looking for server_name.
+/// Returns the offset and length of the first host name.
+#[inline(always)]
+pub fn tls_client_hello_sni(mut load_u8: impl FnMut(usize) -> Option<u8>) -> Option<(usize, usize)> {
+    const CONTENT_HANDSHAKE: u8 = 22;
+    const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+    const EXT_SERVER_NAME: usize = 0;
+    const NAME_TYPE_HOST: u8 = 0;
+
+    let mut load_be16 = |offset: usize| Some(u16::from_be_bytes([load_u8(offset)?, load_u8(offset + 1)?]) as usize);
+    // Record header: type, version (3.x), length
+    let head = load_be16(0)?;
+    if head >> 8 != CONTENT_HANDSHAKE as usize || head & 0xff != 3 {
+        return None;
+    }
+    // Handshake header: type, 24-bit length; then the client version and
+    // the 32-byte random
+    if load_be16(5)? >> 8 != HANDSHAKE_CLIENT_HELLO as usize {
+        return None;
+    }
+    let mut offset = 5 + 4 + 2 + 32;
+    // Session ID, cipher suites and compression methods are length-prefixed
+    offset += 1 + (load_be16(offset)? >> 8);
+    offset += 2 + load_be16(offset)?;
+    offset += 1 + (load_be16(offset)? >> 8);
+    let end = offset + 2 + load_be16(offset)?;
+    offset += 2;
+
+    for _ in 0..TLS_MAX_EXTENSIONS {
+        if offset + 4 > end {
+            return None;
+        }
+        let ext_type = load_be16(offset)?;
+        let ext_len = load_be16(offset + 2)?;
+        if ext_type == EXT_SERVER_NAME {
+            // Server name list: list length, then name type and length
+            let name_type = load_be16(offset + 6)? >> 8;
+            let name_len = load_be16(offset + 7)?;
+            if name_type != NAME_TYPE_HOST as usize || name_len == 0 {
+                return None;
+            }
+            return Some((offset + 9, name_len));
+        }
+        offset += 4 + ext_len;
+    }
+    None
+}
+
+/// Conntrack lifecycle event types
+pub mod ct_event {
+    /// Entry confirmed: the connection's first packet made it through
     pub const NEW: u8 = 1;
-    pub const UPDATE: u8 = 2;
-    pub const CLOSE: u8 = 3;
+    /// Entry removed: timed out, closed, or flushed
+    pub const DESTROY: u8 = 2;
 }
 
-/// Flow direction
-pub mod flow_direction {
-    pub const UNKNOWN: u8 = 0;
-    pub const OUTBOUND: u8 = 1;
-    pub const INBOUND: u8 = 2;
+/// Conntrack status bits (`enum ip_conntrack_status`) in `ConntrackEvent::status`
+pub mod ct_status {
+    /// A packet was seen in the reply direction
+    pub const SEEN_REPLY: u32 = 1 << 1;
+    /// The connection was established (TCP handshake done, or a UDP reply
+    /// followed by more traffic); exempt from early drop
+    pub const ASSURED: u32 = 1 << 2;
+    pub const SRC_NAT: u32 = 1 << 4;
+    pub const DST_NAT: u32 = 1 << 5;
 }
 
-/// Flow state
-pub mod flow_state {
-    pub const UNKNOWN: u8 = 0;
-    pub const ACTIVE: u8 = 1;
-    pub const CLOSING: u8 = 2;
+/// Connection tracking entry created or destroyed, from the kprobes on
+/// `__nf_conntrack_confirm` and `nf_ct_delete`
+///
+/// Addresses are IPv4-mapped for IPv4. The reply tuple is the original one
+/// reversed unless the connection is NATed.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
+pub struct ConntrackEvent {
+    /// Timestamp (ns since boot)
+    pub timestamp_ns: u64,
+    /// Original direction: initiator → responder
+    pub src_addr: Addr128,
+    pub dst_addr: Addr128,
+    /// Reply direction: responder → initiator, after NAT
+    pub reply_src_addr: Addr128,
+    pub reply_dst_addr: Addr128,
+    /// Ports in host byte order (0 for protocols without ports)
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub reply_src_port: u16,
+    pub reply_dst_port: u16,
+    /// `ct_status` bits at the time of the event
+    pub status: u32,
+    /// IP protocol (6 = TCP, 17 = UDP, 1 = ICMP)
+    pub protocol: u8,
+    /// `ct_event::NEW` or `ct_event::DESTROY`
+    pub event_type: u8,
+    pub sample_rate: SampleRate,
+    /// Padding for alignment
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _pad: u8,
+}
+
+/// Most bytes of a packet a `PayloadEvent` carries
+pub const PAYLOAD_SNIPPET_MAX: usize = 128;
+
+/// Leading bytes of a packet the classifier saw, for `sennet trace
+/// --payload`
+///
+/// Only emitted while the trace rule asks for a payload and the packet
+/// passes it. `data` starts at the Ethernet header; bytes past `captured`
+/// are unset.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
+pub struct PayloadEvent {
+    /// Timestamp (ns since boot)
+    pub timestamp_ns: u64,
+    /// Interface index
+    pub ifindex: u32,
+    /// Length of the whole packet
+    pub len: u32,
+    /// From the IP header, IPv4-mapped for IPv4
+    pub src_addr: Addr128,
+    pub dst_addr: Addr128,
+    /// TCP/UDP ports in host byte order (0 for other protocols)
+    pub src_port: u16,
+    pub dst_port: u16,
+    /// Bytes of `data` filled in
+    pub captured: u16,
+    /// 0 = ingress, 1 = egress
+    pub direction: u8,
+    /// IP protocol
+    pub protocol: u8,
+    pub sample_rate: SampleRate,
+    /// Padding for alignment
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _pad: [u8; 7],
+    #[cfg_attr(feature = "serde", serde(with = "byte_array"))]
+    pub data: [u8; PAYLOAD_SNIPPET_MAX],
+}
+
+impl Default for PayloadEvent {
+    fn default() -> Self {
+        // SAFETY: every field is an integer or an array of integers
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+impl PayloadEvent {
+    /// The bytes the kernel copied
+    pub fn snippet(&self) -> &[u8] {
+        &self.data[..(self.captured as usize).min(PAYLOAD_SNIPPET_MAX)]
+    }
+}
+
+/// Bytes of the request path kept in an `HttpEvent`
+pub const HTTP_PATH_LEN: usize = 128;
+
+/// HTTP request methods in `HttpEvent::method`
+pub mod http_method {
+    pub const GET: u8 = 1;
+    pub const HEAD: u8 = 2;
+    pub const POST: u8 = 3;
+    pub const PUT: u8 = 4;
+    pub const DELETE: u8 = 5;
+    pub const PATCH: u8 = 6;
+    pub const OPTIONS: u8 = 7;
+    pub const CONNECT: u8 = 8;
+    pub const TRACE: u8 = 9;
+}
+
+/// Method tokens a request line can start with, space included
+const HTTP_METHODS: [(&[u8], u8); 9] = [
+    (b"GET ", http_method::GET),
+    (b"HEAD ", http_method::HEAD),
+    (b"POST ", http_method::POST),
+    (b"PUT ", http_method::PUT),
+    (b"DELETE ", http_method::DELETE),
+    (b"PATCH ", http_method::PATCH),
+    (b"OPTIONS ", http_method::OPTIONS),
+    (b"CONNECT ", http_method::CONNECT),
+    (b"TRACE ", http_method::TRACE),
+];
+
+/// Name of an `http_method` value
+pub fn http_method_str(method: u8) -> &'static str {
+    match method {
+        http_method::GET => "GET",
+        http_method::HEAD => "HEAD",
+        http_method::POST => "POST",
+        http_method::PUT => "PUT",
+        http_method::DELETE => "DELETE",
+        http_method::PATCH => "PATCH",
+        http_method::OPTIONS => "OPTIONS",
+        http_method::CONNECT => "CONNECT",
+        http_method::TRACE => "TRACE",
+        _ => "UNKNOWN",
+    }
+}
+
+/// HTTP/1.x request line sampled on one of the ports userspace put in
+/// HTTP_PORTS, seen at the TC or XDP hook
+///
+/// Only the first segment of a request is looked at: a request line split
+/// across segments is cut short, and pipelined requests after the first in
+/// a segment aren't seen. Addresses are IPv4-mapped for IPv4.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
+pub struct HttpEvent {
+    /// Timestamp (ns since boot)
+    pub timestamp_ns: u64,
+    /// Client address
+    pub src_addr: Addr128,
+    /// Server address
+    pub dst_addr: Addr128,
+    /// TCP ports in host byte order
+    pub src_port: u16,
+    pub dst_port: u16,
+    /// Interface index
+    pub ifindex: u32,
+    /// Bytes of `path` filled in
+    pub path_len: u16,
+    /// `http_method` value
+    pub method: u8,
+    /// 0 = ingress (this host serves the request), 1 = egress
+    pub direction: u8,
+    pub sample_rate: SampleRate,
+    /// 1 if the path went on past `path` or the segment
+    pub truncated: u8,
+    /// Padding for alignment
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _pad: [u8; 2],
+    /// Request target as sent (path and query string)
+    #[cfg_attr(feature = "serde", serde(with = "byte_array"))]
+    pub path: [u8; HTTP_PATH_LEN],
+}
+
+impl Default for HttpEvent {
+    fn default() -> Self {
+        // SAFETY: every field is an integer or an array of integers
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+impl HttpEvent {
+    /// Request target as sent; cut-short targets end in "…"
+    #[cfg(not(feature = "no-std"))]
+    pub fn path(&self) -> String {
+        let len = (self.path_len as usize).min(HTTP_PATH_LEN);
+        let mut path = String::from_utf8_lossy(&self.path[..len]).into_owned();
+        if self.truncated != 0 {
+            path.push('…');
+        }
+        path
+    }
+}
+
+/// Method and request target of an HTTP/1.x request line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HttpRequestLine {
+    /// `http_method` value
+    pub method: u8,
+    /// Offset of the request target in the payload
+    pub path_off: usize,
+    /// Bytes of the request target, at most `HTTP_PATH_LEN`
+    pub path_len: usize,
+    /// The target went on past `HTTP_PATH_LEN` bytes or the payload
+    pub truncated: bool,
+}
+
+/// Parse the request line at the start of a TCP payload
+///
+/// `load_u8` reads the payload byte at an offset, None past its end. The
+/// target is scanned for at most `HTTP_PATH_LEN` bytes, which bounds the
+/// loop for the verifier. None unless the payload starts with a known
+/// method and a target.
+#[inline(always)]
+pub fn http_request_line(mut load_u8: impl FnMut(usize) -> Option<u8>) -> Option<HttpRequestLine> {
+    // The longest token is "OPTIONS "
+    let mut head = [0u8; 8];
+    for (i, byte) in head.iter_mut().enumerate() {
+        *byte = load_u8(i)?;
+    }
+    let mut found = None;
+    for (token, method) in HTTP_METHODS {
+        if head.starts_with(token) {
+            found = Some((method, token.len()));
+            break;
+        }
+    }
+    let (method, path_off) = found?;
+
+    for i in 0..HTTP_PATH_LEN {
+        let Some(byte) = load_u8(path_off + i) else {
+            return (i > 0).then_some(HttpRequestLine { method, path_off, path_len: i, truncated: true });
+        };
+        match byte {
+            b' ' if i > 0 => return Some(HttpRequestLine { method, path_off, path_len: i, truncated: false }),
+            // Controls and spaces don't belong in a target
+            0..=b' ' | 0x7f => return None,
+            _ => {}
+        }
+    }
+    Some(HttpRequestLine { method, path_off, path_len: HTTP_PATH_LEN, truncated: true })
+}
+
+/// Flow event types
+pub mod flow_event_type {
+    pub const NEW: u8 = 1;
+    pub const UPDATE: u8 = 2;
+    pub const CLOSE: u8 = 3;
+}
+
+/// Flow direction
+pub mod flow_direction {
+    pub const UNKNOWN: u8 = 0;
+    pub const OUTBOUND: u8 = 1;
+    pub const INBOUND: u8 = 2;
+}
+
+/// Flow state
+pub mod flow_state {
+    pub const UNKNOWN: u8 = 0;
+    pub const ACTIVE: u8 = 1;
+    pub const CLOSING: u8 = 2;
     pub const CLOSED: u8 = 3;
 }
+
+// ============================================================================
+// Ring Buffer Envelope
+// ============================================================================
+
+/// Header in front of every ring buffer record, so a reader can tell what
+/// follows without knowing which ring it came from, and skip records it
+/// doesn't understand instead of misreading them
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "zerocopy", derive(zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable))]
+pub struct EventHeader {
+    /// `event_kind` of the record
+    pub kind: u8,
+    /// Layout version of the record (`RingEvent::VERSION`)
+    pub version: u8,
+    /// Bytes of the record after the header
+    pub len: u16,
+    /// Keeps the record 8-byte aligned
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _pad: u32,
+}
+
+impl EventHeader {
+    /// Header for a `T` record
+    pub const fn of<T: RingEvent>() -> Self {
+        Self { kind: T::KIND as u8, version: T::VERSION, len: core::mem::size_of::<T>() as u16, _pad: 0 }
+    }
+
+    /// Wire bytes, for building records outside the kernel (tests, benchmarks)
+    pub fn to_bytes(&self) -> [u8; EVENT_HEADER_LEN] {
+        let mut bytes = [0u8; EVENT_HEADER_LEN];
+        bytes[0] = self.kind;
+        bytes[1] = self.version;
+        bytes[2..4].copy_from_slice(&self.len.to_ne_bytes());
+        bytes
+    }
+}
+
+/// Layout version of every record kind, written into each `EventHeader`;
+/// bumped when a record changes other than by appending fields, so readers
+/// built against another layout refuse the records instead of misreading them
+pub const EVENT_VERSION: u8 = 1;
+
+/// Bytes of `EventHeader` before each record
+pub const EVENT_HEADER_LEN: usize = core::mem::size_of::<EventHeader>();
+
+/// A ring buffer record as reserved by the eBPF programs
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "camelCase"))]
+pub struct Envelope<T> {
+    pub header: EventHeader,
+    pub event: T,
+}
+
+/// A record type written to the ring buffers
+pub trait RingEvent {
+    /// `event_kind` index
+    const KIND: usize;
+    /// Bumped when the record's layout changes other than by appending
+    /// fields; readers skip versions they don't know
+    const VERSION: u8;
+}
+
+macro_rules! ring_event {
+    ($($ty:ident => $kind:ident),* $(,)?) => {
+        $(impl RingEvent for $ty {
+            const KIND: usize = event_kind::$kind;
+            const VERSION: u8 = EVENT_VERSION;
+        })*
+    };
+}
+
+ring_event! {
+    DropEvent => DROP,
+    NetfilterEvent => NETFILTER,
+    FlowEvent => FLOW,
+    RstEvent => RST,
+    PacketEvent => PACKET,
+    RetransmitEvent => RETRANSMIT,
+    DnsEvent => DNS,
+    ConntrackEvent => CONNTRACK,
+    PayloadEvent => PAYLOAD,
+    HttpEvent => HTTP,
+}
+
+// ============================================================================
+// Kernel-side Rate Limiting
+// ============================================================================
+
+/// Event kinds with their own token bucket (index into `Tunables` arrays
+/// and the RATE_LIMIT map)
+pub mod event_kind {
+    pub const DROP: usize = 0;
+    pub const NETFILTER: usize = 1;
+    pub const FLOW: usize = 2;
+    pub const RST: usize = 3;
+    pub const PACKET: usize = 4;
+    pub const RETRANSMIT: usize = 5;
+    pub const DNS: usize = 6;
+    pub const CONNTRACK: usize = 7;
+    pub const PAYLOAD: usize = 8;
+    pub const HTTP: usize = 9;
+    pub const COUNT: usize = 10;
+}
+
+/// Runtime knobs written by userspace into the TUNABLES map (one entry)
+///
+/// All-zero (the map's initial state) means no rate limiting.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Tunables {
+    /// Events per second per CPU let through unsampled (0 = unlimited)
+    pub rate_per_sec: [u32; event_kind::COUNT],
+    /// Bucket capacity in events (0 = one second's worth)
+    pub burst: [u32; event_kind::COUNT],
+    /// Once the bucket is empty, emit 1 in N events (0 = drop the excess)
+    pub sample_one_in: [u32; event_kind::COUNT],
+}
+
+// SAFETY: Tunables is #[repr(C)] and made of u32 arrays, without padding
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for Tunables {}
+
+/// Sampling marker carried by every ring buffer event
+///
+/// 0 or 1 means the event is unsampled; N > 1 means the kernel rate limiter
+/// (see `admit`) emitted it as a sample standing for 1 in N events.
+pub type SampleRate = u8;
+
+/// Per-CPU token bucket state (RATE_LIMIT map, one entry per event kind)
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TokenBucket {
+    pub tokens: u64,
+    pub last_refill_ns: u64,
+    /// Events suppressed since the last sampled one
+    pub skipped: u32,
+    pub _pad: u32,
+}
+
+/// Decide whether to emit an event of `kind` at `now_ns`
+///
+/// Returns 0 to suppress the event, 1 to emit it normally, or N > 1 to emit
+/// it as a sample standing for N events (stored in the event's
+/// `sample_rate` field).
+#[inline(always)]
+pub fn admit(bucket: &mut TokenBucket, tunables: &Tunables, kind: usize, now_ns: u64) -> SampleRate {
+    if kind >= event_kind::COUNT {
+        return 1;
+    }
+    let rate = tunables.rate_per_sec[kind] as u64;
+    if rate == 0 {
+        return 1;
+    }
+    let burst = match tunables.burst[kind] {
+        0 => rate,
+        b => b as u64,
+    };
+
+    let elapsed = now_ns.saturating_sub(bucket.last_refill_ns);
+    let refill = elapsed.saturating_mul(rate) / 1_000_000_000;
+    if refill > 0 {
+        bucket.tokens = bucket.tokens.saturating_add(refill).min(burst);
+        bucket.last_refill_ns = now_ns;
+    }
+
+    if bucket.tokens > 0 {
+        bucket.tokens -= 1;
+        bucket.skipped = 0;
+        return 1;
+    }
+
+    let one_in = tunables.sample_one_in[kind];
+    if one_in == 0 {
+        return 0;
+    }
+    let one_in = one_in.min(255);
+    bucket.skipped += 1;
+    if bucket.skipped >= one_in {
+        bucket.skipped = 0;
+        return one_in as u8;
+    }
+    0
+}
+
+/// Weight of a packet in the classifier's event path, given the
+/// PACKET_SAMPLING setting (look at 1 in `one_in` packets) and a random
+/// number
+///
+/// Returns 0 to skip the packet's events, otherwise the number of packets
+/// it stands for. 0 and 1 both mean every packet; N is capped at 255 to
+/// fit an event's `sample_rate`.
+#[inline(always)]
+pub fn packet_sample(one_in: u32, random: u32) -> u8 {
+    if one_in <= 1 {
+        return 1;
+    }
+    let one_in = one_in.min(255);
+    if random.is_multiple_of(one_in) {
+        one_in as u8
+    } else {
+        0
+    }
+}
+
+// ============================================================================
+// Per-source Rate Detection
+// ============================================================================
+
+/// Minimum time between two `Anomaly` events for the same source
+pub const SOURCE_REPORT_INTERVAL_NS: u64 = 1_000_000_000;
+
+/// Per-source token bucket (SOURCE_RATES map, keyed by `Addr128`)
+///
+/// The bucket holds one second's worth of packets at the threshold, so a
+/// source runs dry once it stays above the threshold.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SourceRate {
+    pub tokens: u64,
+    pub last_refill_ns: u64,
+    /// Start of the current measurement: when the bucket was last full, or
+    /// the source last reported
+    pub window_start_ns: u64,
+    /// Packets since `window_start_ns`
+    pub packets: u64,
+}
+
+/// Charge one packet from a source to its bucket at `now_ns`, given a
+/// threshold of `pps` packets per second
+///
+/// Returns the source's measured rate when it is over the threshold and
+/// wasn't reported within [`SOURCE_REPORT_INTERVAL_NS`], otherwise 0. A
+/// zeroed bucket (a new source) starts full.
+#[inline(always)]
+pub fn source_over_rate(rate: &mut SourceRate, pps: u32, now_ns: u64) -> u32 {
+    let burst = pps as u64;
+    if burst == 0 {
+        return 0;
+    }
+    if rate.last_refill_ns == 0 {
+        rate.tokens = burst;
+        rate.last_refill_ns = now_ns;
+    }
+
+    let elapsed = now_ns.saturating_sub(rate.last_refill_ns);
+    let refill = elapsed.saturating_mul(burst) / 1_000_000_000;
+    if refill > 0 {
+        rate.tokens = rate.tokens.saturating_add(refill).min(burst);
+        rate.last_refill_ns = now_ns;
+    }
+    if rate.tokens == burst {
+        rate.window_start_ns = now_ns;
+        rate.packets = 0;
+    }
+    rate.packets += 1;
+
+    if rate.tokens > 0 {
+        rate.tokens -= 1;
+        return 0;
+    }
+    let window = now_ns.saturating_sub(rate.window_start_ns);
+    if window < SOURCE_REPORT_INTERVAL_NS {
+        return 0;
+    }
+    let measured = rate.packets.saturating_mul(1_000_000_000) / window;
+    rate.window_start_ns = now_ns;
+    rate.packets = 0;
+    measured.min(u32::MAX as u64) as u32
+}
+
+// ============================================================================
+// Kernel-side Drop Filter
+// ============================================================================
+
+/// Drop filter written by userspace into the DROP_FILTER map (one entry)
+///
+/// Holds the terms of the daemon's filter expression the kernel can check;
+/// userspace evaluates the whole expression, so this only has to let
+/// through a superset of it. All-zero (the map's initial state) passes
+/// every drop.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct DropFilter {
+    /// Drop reasons to discard, one bit per reason below 256
+    pub skip_reasons: [u64; 4],
+    /// Owning socket's local address, compared under `src_mask` (0 = any)
+    pub src_addr: u32,
+    pub src_mask: u32,
+    /// Owning socket's remote address, compared under `dst_mask` (0 = any)
+    pub dst_addr: u32,
+    pub dst_mask: u32,
+    /// Owning socket's local port (0 = any)
+    pub src_port: u16,
+    /// Owning socket's remote port (0 = any)
+    pub dst_port: u16,
+    pub _pad: u32,
+}
+
+// SAFETY: DropFilter is #[repr(C)] with its padding spelled out as a field
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for DropFilter {}
+
+/// Whether a drop passes `filter`; addresses and ports as in `DropEvent`
+#[inline(always)]
+pub fn drop_filter_admits(filter: &DropFilter, reason: u32, src_ip: u32, dst_ip: u32, src_port: u16, dst_port: u16) -> bool {
+    if let Some(word) = filter.skip_reasons.get((reason / 64) as usize) {
+        if word & (1 << (reason % 64)) != 0 {
+            return false;
+        }
+    }
+    src_ip & filter.src_mask == filter.src_addr
+        && dst_ip & filter.dst_mask == filter.dst_addr
+        && (filter.src_port == 0 || filter.src_port == src_port)
+        && (filter.dst_port == 0 || filter.dst_port == dst_port)
+}
+
+/// Offsets of the fields `kfree_skb` reads from its tracepoint record
+///
+/// The record layout changes between kernels (drop reasons arrived in 5.17,
+/// `rx_sk` later pushed `protocol` and `reason` back), so the agent reads
+/// the tracepoint's format file at load time and writes the offsets into
+/// the KFREE_SKB_LAYOUT map. Offsets count from the start of the record,
+/// common fields included.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct KfreeSkbLayout {
+    /// `void *skbaddr` (0 = not set, use `DEFAULT`)
+    pub skbaddr: u16,
+    /// `unsigned short protocol`
+    pub protocol: u16,
+    /// `enum skb_drop_reason reason` (0 = the kernel records none)
+    pub reason: u16,
+    pub _pad: u16,
+}
+
+impl KfreeSkbLayout {
+    /// Layout of 5.17 through 6.x kernels without `rx_sk`, used until the
+    /// agent writes one
+    pub const DEFAULT: Self = Self { skbaddr: 8, protocol: 24, reason: 28, _pad: 0 };
+}
+
+// SAFETY: KfreeSkbLayout is #[repr(C)] with only u16 fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for KfreeSkbLayout {}
+
+/// Offsets of the fields `tcp_retransmit_skb` reads from its tracepoint
+/// record, written by the agent into the TCP_RETRANSMIT_SKB_LAYOUT map like
+/// `KfreeSkbLayout`
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct TcpRetransmitSkbLayout {
+    /// `int state` (0 = not set, use `DEFAULT`)
+    pub state: u16,
+    /// `__u16 sport`
+    pub sport: u16,
+    /// `__u16 dport`
+    pub dport: u16,
+    /// `__u8 saddr_v6[16]`
+    pub saddr_v6: u16,
+    /// `__u8 daddr_v6[16]`
+    pub daddr_v6: u16,
+    pub _pad: u16,
+}
+
+impl TcpRetransmitSkbLayout {
+    /// Layout of 4.16 through 6.x kernels, used until the agent writes one
+    pub const DEFAULT: Self = Self { state: 24, sport: 28, dport: 30, saddr_v6: 42, daddr_v6: 58, _pad: 0 };
+}
+
+// SAFETY: TcpRetransmitSkbLayout is #[repr(C)] with only u16 fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for TcpRetransmitSkbLayout {}
+
+/// Offsets of the fields `tcp_probe` reads from its tracepoint record,
+/// written by the agent into the TCP_PROBE_LAYOUT map like `KfreeSkbLayout`
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct TcpProbeLayout {
+    /// `__u8 daddr[sizeof(struct sockaddr_in6)]`, the remote end (0 = not
+    /// set, use `DEFAULT`)
+    pub daddr: u16,
+    /// `__u32 srtt`
+    pub srtt: u16,
+}
+
+impl TcpProbeLayout {
+    /// Layout of 4.16 through 6.x kernels, used until the agent writes one
+    pub const DEFAULT: Self = Self { daddr: 36, srtt: 100 };
+}
+
+// SAFETY: TcpProbeLayout is #[repr(C)] with only u16 fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for TcpProbeLayout {}
+
+/// Offsets of the fields `inet_sock_set_state` reads from its tracepoint
+/// record, written by the agent into the INET_SOCK_SET_STATE_LAYOUT map
+/// like `KfreeSkbLayout`
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct InetSockSetStateLayout {
+    /// `int oldstate` (0 = not set, use `DEFAULT`)
+    pub oldstate: u16,
+    /// `int newstate`
+    pub newstate: u16,
+    /// `__u16 sport`
+    pub sport: u16,
+    /// `__u16 dport`
+    pub dport: u16,
+    /// `__u16 family`
+    pub family: u16,
+    /// `__u16 protocol`
+    pub protocol: u16,
+    /// `__u8 saddr[4]`
+    pub saddr: u16,
+    /// `__u8 daddr[4]`
+    pub daddr: u16,
+}
+
+impl InetSockSetStateLayout {
+    /// Layout of 4.16 through 6.x kernels, used until the agent writes one
+    pub const DEFAULT: Self =
+        Self { oldstate: 16, newstate: 20, sport: 24, dport: 26, family: 28, protocol: 30, saddr: 32, daddr: 36 };
+}
+
+// SAFETY: InetSockSetStateLayout is #[repr(C)] with only u16 fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for InetSockSetStateLayout {}
+
+/// Offsets of the `struct sk_buff` fields the programs read
+///
+/// The struct moves with the kernel version and config, so the agent looks
+/// the fields up in the kernel's BTF at load time and writes the offsets
+/// into the SKB_LAYOUT map. Offsets count from the start of the struct.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct SkbLayout {
+    /// `struct sock *sk`
+    pub sk: u16,
+    /// `__u16 network_header`, counted from `head`
+    pub network_header: u16,
+    /// `unsigned char *head` (0 = not set, use `DEFAULT`)
+    pub head: u16,
+    /// `unsigned long _nfct`, the conntrack entry and ctinfo
+    pub nfct: u16,
+    /// `struct net_device *dev`
+    pub dev: u16,
+    /// `int skb_iif`, the interface the skb arrived on
+    pub skb_iif: u16,
+    /// `int ifindex` of the `struct net_device` that `dev` points to
+    pub dev_ifindex: u16,
+    /// `unsigned int len`
+    pub len: u16,
+}
+
+impl SkbLayout {
+    /// Layout of x86_64 5.15 through 6.x distro configs, used until the
+    /// agent writes one
+    pub const DEFAULT: Self = Self {
+        sk: 24,
+        network_header: 184,
+        head: 200,
+        nfct: 104,
+        dev: 16,
+        skb_iif: 144,
+        dev_ifindex: 224,
+        len: 112,
+    };
+}
+
+// SAFETY: SkbLayout is #[repr(C)] with only u16 fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for SkbLayout {}
+
+/// Offsets of the `struct nf_conn` fields the conntrack kprobes read,
+/// written by the agent from BTF into the NF_CONN_LAYOUT map like
+/// `SkbLayout`
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct NfConnLayout {
+    /// `tuplehash[IP_CT_DIR_ORIGINAL].tuple` (0 = not set, use `DEFAULT`)
+    pub original: u16,
+    /// `tuplehash[IP_CT_DIR_REPLY].tuple`
+    pub reply: u16,
+    /// `unsigned long status`
+    pub status: u16,
+}
+
+impl NfConnLayout {
+    /// Layout of x86_64 5.10 through 6.x, used until the agent writes one
+    pub const DEFAULT: Self = Self { original: 32, reply: 88, status: 128 };
+}
+
+// SAFETY: NfConnLayout is #[repr(C)] with only u16 fields
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for NfConnLayout {}
+
+/// Filter `sennet trace` sets while it reads the pinned ring buffers itself
+///
+/// Checked by `kfree_skb` and the TC/XDP programs after `DropFilter`, so
+/// only events the trace can show use up ring space. The rule lapses at
+/// `expires_ns`, so a trace that is killed can't leave it behind.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct TraceRule {
+    /// Reasons, addresses and ports, as for the pipeline's drop filter
+    pub filter: DropFilter,
+    /// IP protocol of resets, packets and parsed drops (0 = any)
+    pub protocol: u8,
+    pub _pad: [u8; 7],
+    /// `bpf_ktime_get_ns` time the rule stops applying at (0 = no rule)
+    pub expires_ns: u64,
+}
+
+// SAFETY: TraceRule is #[repr(C)] with its padding spelled out as a field
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for TraceRule {}
+
+/// Packets `sennet trace --payload` has the classifiers copy the head of
+/// into PAYLOAD_EVENTS
+///
+/// Kept apart from TRACE_RULE: the trace sets this one while it streams the
+/// other events from the agent, whose pipeline a trace rule would thin out.
+/// Lapses at `rule.expires_ns` like a trace rule.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct PayloadRule {
+    /// Addresses, ports and protocol of the packets to copy
+    pub rule: TraceRule,
+    /// Leading bytes copied (0 = none, at most `PAYLOAD_SNIPPET_MAX`)
+    pub len: u8,
+    pub _pad: [u8; 7],
+}
+
+// SAFETY: PayloadRule is #[repr(C)] with its padding spelled out as a field
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for PayloadRule {}
+
+/// Whether an event passes `rule` at kernel time `now_ns`
+///
+/// `None` marks what the event doesn't carry (unparsed drops have no IP protocol,
+/// packets other than TCP/UDP no ports, only drops a reason), which passes as in the
+/// userspace filter. Addresses are IPv4 in host order, 0 if unknown.
+#[inline(always)]
+pub fn trace_rule_admits(
+    rule: &TraceRule,
+    now_ns: u64,
+    reason: Option<u32>,
+    src_ip: u32,
+    dst_ip: u32,
+    ports: Option<(u16, u16)>,
+    protocol: Option<u8>,
+) -> bool {
+    if now_ns >= rule.expires_ns {
+        return true;
+    }
+    let filter = &rule.filter;
+    if let Some(reason) = reason {
+        if let Some(word) = filter.skip_reasons.get((reason / 64) as usize) {
+            if word & (1 << (reason % 64)) != 0 {
+                return false;
+            }
+        }
+    }
+    if src_ip & filter.src_mask != filter.src_addr || dst_ip & filter.dst_mask != filter.dst_addr {
+        return false;
+    }
+    if let Some((src_port, dst_port)) = ports {
+        if (filter.src_port != 0 && filter.src_port != src_port) || (filter.dst_port != 0 && filter.dst_port != dst_port) {
+            return false;
+        }
+    }
+    match protocol {
+        Some(protocol) => rule.protocol == 0 || rule.protocol == protocol,
+        None => true,
+    }
+}
+
+// ============================================================================
+// Enforcement (sennet block)
+// ============================================================================
+
+/// Entry of the BLOCKLIST map: packets to or from `addr` (all-zero = any
+/// address) on `port` (0 = any port) are dropped while enforcement is on
+///
+/// With an address, `port` is the peer's; without one, it matches either
+/// end of a TCP or UDP packet.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockKey {
+    /// IPv4-mapped for IPv4
+    pub addr: Addr128,
+    /// Host byte order
+    pub port: u16,
+    pub _pad: u16,
+}
+
+// SAFETY: BlockKey is #[repr(C)] with its padding spelled out as a field
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for BlockKey {}
+
+impl BlockKey {
+    /// Keys a packet with peer `remote` is checked against, most specific
+    /// first; `ports` are (source, destination) and `ingress` says which is
+    /// the peer's
+    #[inline(always)]
+    pub fn candidates(remote: Addr128, ports: Option<(u16, u16)>, ingress: bool) -> [Option<BlockKey>; 4] {
+        let key = |addr, port| Some(BlockKey { addr, port, _pad: 0 });
+        match ports {
+            Some((src, dst)) => {
+                let peer_port = if ingress { src } else { dst };
+                [key(remote, peer_port), key(remote, 0), key([0; 16], src), key([0; 16], dst)]
+            }
+            None => [key(remote, 0), None, None, None],
+        }
+    }
+}
+
+// ============================================================================
+// Log2 Histograms
+// ============================================================================
+
+/// Buckets per histogram: bucket 0 counts zeros, bucket i counts values in
+/// [2^(i-1), 2^i), and the last one everything above
+pub const HIST_BUCKETS: usize = 32;
+
+/// Map slot holding the sum of recorded values, after the buckets
+pub const HIST_SUM_SLOT: u32 = HIST_BUCKETS as u32;
+
+/// Entries of an eBPF array map holding one histogram: buckets, then the sum
+pub const HIST_SLOTS: u32 = HIST_SUM_SLOT + 1;
+
+/// Bucket of `value`: its number of significant bits, capped at the last bucket
+///
+/// Written as a shift cascade so the verifier sees a bounded result.
+#[inline(always)]
+pub fn log2_bucket(value: u64) -> u32 {
+    if value == 0 {
+        return 0;
+    }
+    let mut v = value;
+    let mut bits = 1;
+    if v >> 32 != 0 {
+        v >>= 32;
+        bits += 32;
+    }
+    if v >> 16 != 0 {
+        v >>= 16;
+        bits += 16;
+    }
+    if v >> 8 != 0 {
+        v >>= 8;
+        bits += 8;
+    }
+    if v >> 4 != 0 {
+        v >>= 4;
+        bits += 4;
+    }
+    if v >> 2 != 0 {
+        v >>= 2;
+        bits += 2;
+    }
+    if v >> 1 != 0 {
+        bits += 1;
+    }
+    if bits >= HIST_BUCKETS as u32 {
+        HIST_BUCKETS as u32 - 1
+    } else {
+        bits
+    }
+}
+
+/// Histogram with power-of-two buckets, for latencies in any unit
+///
+/// The eBPF side keeps one in a per-CPU array map of `HIST_SLOTS` u64
+/// entries, incrementing slot `log2_bucket(value)` and adding the value to
+/// `HIST_SUM_SLOT`; userspace rebuilds it with `from_slots` and merges CPUs.
+/// RTT_HISTOGRAMS holds whole histograms as per-CPU hash map values instead,
+/// one per remote address.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Log2Histogram {
+    pub buckets: [u64; HIST_BUCKETS],
+    pub sum: u64,
+}
+
+impl Default for Log2Histogram {
+    fn default() -> Self {
+        Self { buckets: [0; HIST_BUCKETS], sum: 0 }
+    }
+}
+
+impl Log2Histogram {
+    /// Histogram from map slots laid out as buckets then sum; missing slots are zero
+    pub fn from_slots(slots: &[u64]) -> Self {
+        let mut hist = Self::default();
+        for (bucket, slot) in hist.buckets.iter_mut().zip(slots) {
+            *bucket = *slot;
+        }
+        hist.sum = slots.get(HIST_SUM_SLOT as usize).copied().unwrap_or(0);
+        hist
+    }
+
+    #[inline(always)]
+    pub fn record(&mut self, value: u64) {
+        let bucket = log2_bucket(value) as usize;
+        if let Some(count) = self.buckets.get_mut(bucket) {
+            *count = count.wrapping_add(1);
+        }
+        self.sum = self.sum.wrapping_add(value);
+    }
+
+    /// Add another histogram's counts, e.g. another CPU's
+    pub fn merge(&mut self, other: &Log2Histogram) {
+        for (count, more) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *count = count.wrapping_add(*more);
+        }
+        self.sum = self.sum.wrapping_add(other.sum);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Largest value bucket `index` holds (the last bucket is unbounded)
+    pub fn upper_bound(index: usize) -> u64 {
+        match index {
+            0 => 0,
+            i if i + 1 >= HIST_BUCKETS => u64::MAX,
+            i => (1u64 << i) - 1,
+        }
+    }
+
+    /// Estimated value at percentile `p` (0-100), interpolating linearly
+    /// within the bucket it falls in; None if empty
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        let total = self.count();
+        if total == 0 {
+            return None;
+        }
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * total as f64).max(1.0);
+        let mut seen = 0u64;
+        for (index, &count) in self.buckets.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            if (seen + count) as f64 >= rank {
+                let low = if index == 0 { 0.0 } else { (1u64 << (index - 1)) as f64 };
+                // The open last bucket is reported at its lower bound
+                let high = if index + 1 >= HIST_BUCKETS { low } else { Self::upper_bound(index) as f64 };
+                let within = (rank - seen as f64) / count as f64;
+                return Some(low + (high - low) * within);
+            }
+            seen += count;
+        }
+        None
+    }
+
+    /// Prometheus text exposition as `name` with `labels`, buckets up to the
+    /// highest non-empty one; `scale` converts recorded units to the
+    /// metric's (1e-9 for nanoseconds recorded as a `_seconds` metric)
+    #[cfg(not(feature = "no-std"))]
+    pub fn render_prometheus(&self, name: &str, labels: &[(&str, &str)], scale: f64) -> String {
+        use std::fmt::Write;
+
+        let label_text = |extra: Option<(&str, &str)>| {
+            let pairs: Vec<String> = labels
+                .iter()
+                .copied()
+                .chain(extra)
+                .map(|(key, value)| {
+                    let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+                    format!("{}=\"{}\"", key, value)
+                })
+                .collect();
+            if pairs.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", pairs.join(","))
+            }
+        };
+
+        let mut out = String::new();
+        let last = self.buckets.iter().rposition(|&count| count != 0).unwrap_or(0);
+        let mut cumulative = 0u64;
+        for (index, &count) in self.buckets.iter().enumerate().take((last + 1).min(HIST_BUCKETS - 1)) {
+            cumulative += count;
+            let le = format!("{}", Self::upper_bound(index) as f64 * scale);
+            let _ = writeln!(out, "{}_bucket{} {}", name, label_text(Some(("le", &le))), cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{} {}", name, label_text(Some(("le", "+Inf"))), self.count());
+        let _ = writeln!(out, "{}_sum{} {}", name, label_text(None), self.sum as f64 * scale);
+        let _ = writeln!(out, "{}_count{} {}", name, label_text(None), self.count());
+        out
+    }
+}
+
+// ============================================================================
+// Schema Handshake
+// ============================================================================
+
+/// Bumped when a shared type changes meaning without changing layout
+/// (byte order, units, enum values)
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Layout hash of the types shared with the eBPF object, embedded in the
+/// object (`sennet_schema` section) and stored in its SCHEMA map
+pub const SCHEMA_HASH: u64 = schema_hash!();
+
+/// FNV-1a hasher usable in const context, for `schema_hash!`
+#[derive(Clone, Copy, Debug)]
+pub struct SchemaHasher(u64);
+
+impl SchemaHasher {
+    pub const fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub const fn bytes(self, bytes: &[u8]) -> Self {
+        let mut hash = self.0;
+        let mut i = 0;
+        while i < bytes.len() {
+            hash ^= bytes[i] as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+            i += 1;
+        }
+        Self(hash)
+    }
+
+    pub const fn number(self, value: usize) -> Self {
+        self.bytes(&(value as u64).to_le_bytes())
+    }
+
+    pub const fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for SchemaHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Feed a struct's name, size, alignment and field offsets to a hasher
+#[macro_export]
+macro_rules! layout_hash {
+    ($hasher:expr, $ty:ident { $($field:ident),* $(,)? }) => {
+        $hasher
+            .bytes(stringify!($ty).as_bytes())
+            .number(core::mem::size_of::<$ty>())
+            .number(core::mem::align_of::<$ty>())
+            $(.bytes(stringify!($field).as_bytes()).number(core::mem::offset_of!($ty, $field)))*
+    };
+}
+
+/// Layout hash of the shared types as named where it is expanded
+#[macro_export]
+macro_rules! schema_hash {
+    () => {{
+        let hasher = $crate::SchemaHasher::new().number($crate::SCHEMA_VERSION as usize);
+        let hasher = hasher.number($crate::HIST_BUCKETS);
+        let hasher = $crate::layout_hash!(hasher, ProtoCounters {
+            tcp_packets, tcp_bytes, udp_packets, udp_bytes, icmp_packets, icmp_bytes, other_packets, other_bytes,
+            quic_packets, quic_bytes,
+        });
+        let hasher = $crate::layout_hash!(hasher, PacketCounters {
+            rx_packets, rx_bytes, tx_packets, tx_bytes, drop_count, rx_proto, tx_proto,
+        });
+        let hasher = $crate::layout_hash!(hasher, TcpFlagCounters { syn, syn_ack, fin, rst });
+        let hasher = $crate::layout_hash!(hasher, IpPair { src_addr, dst_addr });
+        let hasher = $crate::layout_hash!(hasher, TalkerCounters { packets, bytes, last_seen_ns });
+        let hasher = $crate::layout_hash!(hasher, Log2Histogram { buckets, sum });
+        let hasher = $crate::layout_hash!(hasher, CgroupCounters { rx_packets, rx_bytes, tx_packets, tx_bytes });
+        let hasher = $crate::layout_hash!(hasher, ConnChurn { active_opens, passive_opens, closes, failed });
+        let hasher = $crate::layout_hash!(hasher, EventHeader { kind, version, len });
+        let hasher = hasher
+            .number(<DropEvent as RingEvent>::VERSION as usize)
+            .number(<NetfilterEvent as RingEvent>::VERSION as usize)
+            .number(<FlowEvent as RingEvent>::VERSION as usize)
+            .number(<RstEvent as RingEvent>::VERSION as usize)
+            .number(<PacketEvent as RingEvent>::VERSION as usize)
+            .number(<RetransmitEvent as RingEvent>::VERSION as usize)
+            .number(<DnsEvent as RingEvent>::VERSION as usize)
+            .number(<ConntrackEvent as RingEvent>::VERSION as usize)
+            .number(<PayloadEvent as RingEvent>::VERSION as usize)
+            .number(<HttpEvent as RingEvent>::VERSION as usize);
+        let hasher = $crate::layout_hash!(hasher, PacketEvent {
+            timestamp_ns, src_addr, dst_addr, event_type, size, ifindex, eth_proto, protocol, direction, sample_rate, pps,
+            src_port, dst_port,
+        });
+        let hasher = $crate::layout_hash!(hasher, DropEvent {
+            timestamp_ns, reason, ifindex, protocol, sample_rate, ip_protocol, src_ip, dst_ip, src_port, dst_port, len,
+            src_addr, dst_addr, packet_src_port, packet_dst_port, stack_id,
+        });
+        let hasher = $crate::layout_hash!(hasher, KfreeSkbLayout { skbaddr, protocol, reason });
+        let hasher = $crate::layout_hash!(hasher, TcpRetransmitSkbLayout { state, sport, dport, saddr_v6, daddr_v6 });
+        let hasher = $crate::layout_hash!(hasher, TcpProbeLayout { daddr, srtt });
+        let hasher =
+            $crate::layout_hash!(hasher, InetSockSetStateLayout { oldstate, newstate, sport, dport, family, protocol, saddr, daddr });
+        let hasher = $crate::layout_hash!(hasher, SkbLayout { sk, network_header, head, nfct, dev, skb_iif, dev_ifindex, len });
+        let hasher = $crate::layout_hash!(hasher, NfConnLayout { original, reply, status });
+        let hasher = $crate::layout_hash!(hasher, NetfilterEvent {
+            timestamp_ns, hook, pf, verdict, sample_rate, ifindex_in, ifindex_out,
+        });
+        let hasher = $crate::layout_hash!(hasher, FlowKey { src_ip, dst_ip, src_port, dst_port, protocol });
+        let hasher = $crate::layout_hash!(hasher, FlowInfo {
+            pid, tgid, comm, start_time_ns, rx_bytes, tx_bytes, rx_packets, tx_packets, state, direction,
+            app_protocol,
+        });
+        let hasher = $crate::layout_hash!(hasher, FlowEvent {
+            timestamp_ns, event_type, direction, protocol, sample_rate, pid, src_ip, dst_ip, src_port, dst_port, comm,
+        });
+        let hasher = $crate::layout_hash!(hasher, RstEvent {
+            timestamp_ns, src_ip, dst_ip, src_port, dst_port, ifindex, direction, tcp_flags, sample_rate,
+        });
+        let hasher = $crate::layout_hash!(hasher, RetransmitEvent {
+            timestamp_ns, src_addr, dst_addr, src_port, dst_port, state, sample_rate,
+        });
+        let hasher = $crate::layout_hash!(hasher, DnsEvent {
+            timestamp_ns, latency_ns, client_addr, server_addr, client_port, id, qtype, is_response, rcode,
+            direction, sample_rate, ifindex, qname,
+        });
+        let hasher = $crate::layout_hash!(hasher, ConntrackEvent {
+            timestamp_ns, src_addr, dst_addr, reply_src_addr, reply_dst_addr, src_port, dst_port, reply_src_port,
+            reply_dst_port, status, protocol, event_type, sample_rate,
+        });
+        let hasher = $crate::layout_hash!(hasher, PayloadEvent {
+            timestamp_ns, ifindex, len, src_addr, dst_addr, src_port, dst_port, captured, direction, protocol,
+            sample_rate, data,
+        });
+        let hasher = $crate::layout_hash!(hasher, HttpEvent {
+            timestamp_ns, src_addr, dst_addr, src_port, dst_port, ifindex, path_len, method, direction, sample_rate,
+            truncated, path,
+        });
+        let hasher = $crate::layout_hash!(hasher, Tunables { rate_per_sec, burst, sample_one_in });
+        let hasher = $crate::layout_hash!(hasher, DropFilter {
+            skip_reasons, src_addr, src_mask, dst_addr, dst_mask, src_port, dst_port,
+        });
+        let hasher = $crate::layout_hash!(hasher, TraceRule { filter, protocol, expires_ns });
+        let hasher = $crate::layout_hash!(hasher, PayloadRule { rule, len });
+        let hasher = $crate::layout_hash!(hasher, BlockKey { addr, port });
+        let hasher = $crate::layout_hash!(hasher, TlsSni { len, name });
+        hasher.finish()
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tunables(rate: u32, sample: u32) -> Tunables {
+        let mut t = Tunables::default();
+        t.rate_per_sec[event_kind::DROP] = rate;
+        t.sample_one_in[event_kind::DROP] = sample;
+        t
+    }
+
+    #[test]
+    fn test_unlimited_by_default() {
+        let mut bucket = TokenBucket::default();
+        let t = Tunables::default();
+        for i in 0..1000 {
+            assert_eq!(admit(&mut bucket, &t, event_kind::DROP, i), 1);
+        }
+    }
+
+    #[test]
+    fn test_bucket_then_sampling() {
+        let mut bucket = TokenBucket::default();
+        let t = tunables(10, 5);
+        let now = 5_000_000_000;
+
+        // Burst of one second's worth passes unsampled
+        let passed = (0..10).filter(|_| admit(&mut bucket, &t, event_kind::DROP, now) == 1).count();
+        assert_eq!(passed, 10);
+
+        // Then 1 in 5 is emitted, marked with its weight
+        let out: Vec<u8> = (0..10).map(|_| admit(&mut bucket, &t, event_kind::DROP, now)).collect();
+        assert_eq!(out, [0, 0, 0, 0, 5, 0, 0, 0, 0, 5]);
+
+        // Tokens come back over time
+        assert_eq!(admit(&mut bucket, &t, event_kind::DROP, now + 100_000_000), 1);
+    }
+
+    #[test]
+    fn test_packet_sample() {
+        assert_eq!(packet_sample(0, 7), 1);
+        assert_eq!(packet_sample(1, 7), 1);
+
+        // 1 in N packets passes, weighted N
+        let out: Vec<u8> = (0..8).map(|r| packet_sample(4, r)).collect();
+        assert_eq!(out, [4, 0, 0, 0, 4, 0, 0, 0]);
+        assert_eq!(packet_sample(1000, 0), 255);
+    }
+
+    #[test]
+    fn test_source_over_rate() {
+        const MS: u64 = 1_000_000;
+        let mut rate = SourceRate::default();
+        assert_eq!(source_over_rate(&mut rate, 0, 1), 0);
+
+        // 100 pps for two seconds stays under a 100 pps threshold
+        let mut now = 1_000 * MS;
+        for _ in 0..200 {
+            now += 10 * MS;
+            assert_eq!(source_over_rate(&mut rate, 100, now), 0);
+        }
+
+        // 1000 pps drains the bucket in ~0.1s, then reports once a second
+        let mut reports = Vec::new();
+        for _ in 0..2500 {
+            now += MS;
+            match source_over_rate(&mut rate, 100, now) {
+                0 => {}
+                pps => reports.push(pps),
+            }
+        }
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|&pps| (990..=1000).contains(&pps)), "{reports:?}");
+    }
+
+    #[test]
+    fn test_drop_filter() {
+        assert!(drop_filter_admits(&DropFilter::default(), 23, 0, 0, 0, 0));
+
+        let filter = DropFilter {
+            skip_reasons: [1 << drop_reason::TCP_OLD_DATA, 0, 0, 0],
+            dst_addr: 0x0a00_0000,
+            dst_mask: 0xff00_0000,
+            dst_port: 443,
+            ..Default::default()
+        };
+        assert!(drop_filter_admits(&filter, drop_reason::NO_SOCKET, 1, 0x0a01_0203, 50000, 443));
+        assert!(!drop_filter_admits(&filter, drop_reason::TCP_OLD_DATA, 1, 0x0a01_0203, 50000, 443));
+        assert!(!drop_filter_admits(&filter, drop_reason::NO_SOCKET, 1, 0x0b01_0203, 50000, 443));
+        assert!(!drop_filter_admits(&filter, drop_reason::NO_SOCKET, 1, 0x0a01_0203, 50000, 80));
+        // Reasons past the bitmap are never skipped
+        assert!(drop_filter_admits(&filter, 300, 1, 0x0a01_0203, 50000, 443));
+    }
+
+    #[test]
+    fn test_trace_rule() {
+        let rule = TraceRule {
+            filter: DropFilter { dst_addr: 0x0a00_0005, dst_mask: u32::MAX, dst_port: 443, ..Default::default() },
+            protocol: ipproto::TCP,
+            expires_ns: 1_000,
+            ..Default::default()
+        };
+        let rst = |dst, dport| trace_rule_admits(&rule, 10, None, 1, dst, Some((40000, dport)), Some(ipproto::TCP));
+        assert!(rst(0x0a00_0005, 443));
+        assert!(!rst(0x0a00_0005, 80));
+        assert!(!rst(0x0a00_0006, 443));
+        // Packets without ports; UDP doesn't match
+        assert!(trace_rule_admits(&rule, 10, None, 1, 0x0a00_0005, None, Some(ipproto::TCP)));
+        assert!(!trace_rule_admits(&rule, 10, None, 1, 0x0a00_0005, None, Some(ipproto::UDP)));
+        // Lapsed, or never set
+        assert!(trace_rule_admits(&rule, 1_000, None, 1, 2, Some((1, 2)), Some(ipproto::UDP)));
+        assert!(trace_rule_admits(&TraceRule::default(), 10, Some(2), 1, 2, None, None));
+        assert_eq!(core::mem::size_of::<TraceRule>(), 72);
+        assert_eq!(core::mem::size_of::<PayloadRule>(), 80);
+    }
+
+    #[test]
+    fn test_block_key_candidates() {
+        let peer = ipv4_mapped([10, 0, 0, 5]);
+        let key = |addr, port| Some(BlockKey { addr, port, _pad: 0 });
+        // Reply from 10.0.0.5:443 to a local ephemeral port
+        assert_eq!(
+            BlockKey::candidates(peer, Some((443, 40000)), true),
+            [key(peer, 443), key(peer, 0), key([0; 16], 443), key([0; 16], 40000)]
+        );
+        // The request going out
+        assert_eq!(BlockKey::candidates(peer, Some((40000, 443)), false)[0], key(peer, 443));
+        assert_eq!(BlockKey::candidates(peer, None, true), [key(peer, 0), None, None, None]);
+        assert_eq!(core::mem::size_of::<BlockKey>(), 20);
+    }
+
+    #[test]
+    fn test_network_header_skips_vlan_tags() {
+        let frame = |tags: &[u16], proto: u16| {
+            let mut frame = vec![0u8; 12];
+            for tag in tags {
+                frame.extend_from_slice(&tag.to_be_bytes());
+                frame.extend_from_slice(&[0x00, 0x64]); // VID 100
+            }
+            frame.extend_from_slice(&proto.to_be_bytes());
+            frame
+        };
+        let parse = |frame: &[u8]| {
+            network_header(|off| frame.get(off..off + 2).map(|b| u16::from_be_bytes([b[0], b[1]])))
+        };
+
+        assert_eq!(parse(&frame(&[], eth_p::IP)), Some((eth_p::IP, 14)));
+        assert_eq!(parse(&frame(&[eth_p::VLAN], eth_p::IPV6)), Some((eth_p::IPV6, 18)));
+        assert_eq!(parse(&frame(&[eth_p::QINQ, eth_p::VLAN], eth_p::IP)), Some((eth_p::IP, 22)));
+        // A third tag isn't followed
+        assert_eq!(parse(&frame(&[eth_p::QINQ, eth_p::VLAN, eth_p::VLAN], eth_p::IP)), Some((eth_p::VLAN, 22)));
+        // Truncated inside a tag
+        assert_eq!(parse(&frame(&[eth_p::VLAN], eth_p::IP)[..15]), None);
+    }
+
+    #[test]
+    fn test_tls_client_hello_sni() {
+        // ClientHello with a session ID, two cipher suites, one compression
+        // method, then supported_versions ahead of server_name
+        let client_hello = |name: &[u8]| {
+            let mut sni = vec![0, 0];
+            sni.extend_from_slice(&((name.len() + 5) as u16).to_be_bytes());
+            sni.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+            sni.push(0);
+            sni.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            sni.extend_from_slice(name);
+            let mut extensions = vec![0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04];
+            extensions.extend_from_slice(&sni);
+
+            let mut body = vec![0x03, 0x03];
+            body.extend_from_slice(&[0xaa; 32]);
+            body.push(4);
+            body.extend_from_slice(&[0xbb; 4]);
+            body.extend_from_slice(&[0x00, 0x04, 0x13, 0x01, 0x13, 0x02, 0x01, 0x00]);
+            body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+            body.extend_from_slice(&extensions);
+
+            let mut record = vec![22, 0x03, 0x01];
+            record.extend_from_slice(&((body.len() + 4) as u16).to_be_bytes());
+            record.extend_from_slice(&[1, 0]);
+            record.extend_from_slice(&(body.len() as u16).to_be_bytes());
+            record.extend_from_slice(&body);
+            record
+        };
+        let parse = |payload: &[u8]| tls_client_hello_sni(|off| payload.get(off).copied());
+
+        let hello = client_hello(b"api.example.com");
+        let (offset, len) = parse(&hello).unwrap();
+        assert_eq!(&hello[offset..offset + len], b"api.example.com");
+
+        // Cut off inside the extensions
+        assert_eq!(parse(&hello[..hello.len() - 20]), None);
+        // Not a handshake record, not a ClientHello
+        let mut other = hello.clone();
+        other[0] = 23;
+        assert_eq!(parse(&other), None);
+        let mut other = hello.clone();
+        other[5] = 2;
+        assert_eq!(parse(&other), None);
+        assert_eq!(parse(b"GET / HTTP/1.1\r\n"), None);
+    }
+
+    #[test]
+    fn test_tls_server_name() {
+        let mut sni = TlsSni { len: 11, _pad: [0; 2], name: [b'x'; TLS_SNI_LEN] };
+        sni.name[..11].copy_from_slice(b"example.com");
+        assert_eq!(sni.server_name(), "example.com");
+        sni.len = 200;
+        assert!(sni.server_name().ends_with("xx…"));
+        assert_eq!(sni.server_name().chars().count(), TLS_SNI_LEN + 1);
+    }
+
+    #[test]
+    fn test_http_request_line() {
+        let parse = |payload: &[u8]| http_request_line(|off| payload.get(off).copied());
+
+        let line = parse(b"GET /api/v1/users?id=7 HTTP/1.1\r\nHost: example.com\r\n").unwrap();
+        assert_eq!((line.method, line.path_off, line.path_len, line.truncated), (http_method::GET, 4, 18, false));
+        let line = parse(b"OPTIONS * HTTP/1.1\r\n").unwrap();
+        assert_eq!((line.method, line.path_off, line.path_len), (http_method::OPTIONS, 8, 1));
+
+        // Target cut off by the segment, or longer than the event keeps
+        let line = parse(b"POST /upload/chunk").unwrap();
+        assert_eq!((line.method, line.path_len, line.truncated), (http_method::POST, 13, true));
+        let mut long = b"PUT /".to_vec();
+        long.extend_from_slice(&[b'a'; 200]);
+        let line = parse(&long).unwrap();
+        assert_eq!((line.path_len, line.truncated), (HTTP_PATH_LEN, true));
+
+        assert_eq!(parse(b"HTTP/1.1 200 OK\r\n"), None);
+        assert_eq!(parse(b"GET  / HTTP/1.1\r\n"), None);
+        assert_eq!(parse(b"GET /a\r\nb HTTP/1.1"), None);
+        assert_eq!(parse(b"GETX / HTTP/1.1\r\n"), None);
+        assert_eq!(parse(b"GET "), None);
+        assert_eq!(http_method_str(http_method::DELETE), "DELETE");
+    }
+
+    #[test]
+    fn test_ipv4_mapped() {
+        let addr = ipv4_mapped([10, 1, 2, 3]);
+        assert_eq!(mapped_ipv4(&addr), Some([10, 1, 2, 3]));
+        let mut v6 = [0u8; 16];
+        v6[0] = 0x20;
+        v6[1] = 0x01;
+        assert_eq!(mapped_ipv4(&v6), None);
+        assert_eq!(core::mem::size_of::<DropEvent>(), 80);
+        assert_eq!(core::mem::size_of::<PacketEvent>(), 72);
+        assert_eq!(core::mem::size_of::<RetransmitEvent>(), 48);
+        assert_eq!(core::mem::size_of::<DnsEvent>(), 128);
+        assert_eq!(core::mem::size_of::<ConntrackEvent>(), 88);
+        assert_eq!(core::mem::size_of::<PayloadEvent>(), 64 + PAYLOAD_SNIPPET_MAX);
+        assert_eq!(core::mem::size_of::<HttpEvent>(), 56 + HTTP_PATH_LEN);
+    }
+
+    #[test]
+    fn test_netfilter_enums() {
+        assert_eq!(NfHook::from_u8(nf_hook::LOCAL_IN), Some(NfHook::LocalIn));
+        assert_eq!(NfHook::from_u8(5), None);
+        assert_eq!(NfVerdict::from_u8(NfVerdict::Stolen as u8), Some(NfVerdict::Stolen));
+        assert_eq!(NfFamily::from_u8(10), Some(NfFamily::Ipv6));
+        assert_eq!(format!("{}/{}", NfHook::LocalIn, NfVerdict::Drop), "INPUT/DROP");
+        assert_eq!(format!("{:>6}", NfFamily::Ipv4), "  IPv4");
+        assert_eq!((nf_hook_str(9), nf_verdict_str(1), nf_family_str(0)), ("UNKNOWN", "ACCEPT", "UNKNOWN"));
+    }
+
+    #[test]
+    fn test_event_header() {
+        assert_eq!(EVENT_HEADER_LEN, 8);
+        let header = EventHeader::of::<DropEvent>();
+        assert_eq!((header.kind, header.version, header.len), (event_kind::DROP as u8, 1, 80));
+        // The record keeps its own alignment behind the header
+        assert_eq!(core::mem::offset_of!(Envelope<DnsEvent>, event), EVENT_HEADER_LEN);
+        assert_eq!(core::mem::size_of::<Envelope<ConntrackEvent>>(), 96);
+    }
+
+    #[test]
+    fn test_proto_counters() {
+        let mut counters = ProtoCounters::default();
+        counters.add(ipproto::UDP, 1200);
+        counters.add(ipproto::UDP, 80);
+        counters.add(ipproto::ICMPV6, 64);
+        counters.add(0, 60);
+        assert_eq!((counters.udp_packets, counters.udp_bytes), (2, 1280));
+        assert_eq!((counters.icmp_packets, counters.other_packets, counters.tcp_packets), (1, 1, 0));
+    }
+
+    #[test]
+    fn test_quic_packet() {
+        let load = |bytes: &'static [u8]| move |i: usize| bytes.get(i).copied();
+        // Initial (long header, v1), short header, version negotiation, v2
+        assert!(quic_packet(load(&[0xc3, 0x00, 0x00, 0x00, 0x01, 0x08])));
+        assert!(quic_packet(load(&[0x41, 0x12, 0x34])));
+        assert!(quic_packet(load(&[0x80, 0x00, 0x00, 0x00, 0x00])));
+        assert!(quic_packet(load(&[0xd0, 0x6b, 0x33, 0x43, 0xcf])));
+        // Fixed bit clear, unknown version, truncated, empty
+        assert!(!quic_packet(load(&[0x01, 0x02])));
+        assert!(!quic_packet(load(&[0xc0, 0x12, 0x34, 0x56, 0x78])));
+        assert!(!quic_packet(load(&[0xc3, 0x00, 0x00])));
+        assert!(!quic_packet(load(&[])));
+
+        let mut counters = ProtoCounters::default();
+        counters.add_quic(1252);
+        let mut totals = PacketCounters::default();
+        totals.add(1, ipproto::UDP, true, 1252);
+        totals.add(0, ipproto::UDP, false, 80);
+        assert_eq!((totals.tx_packets, totals.tx_proto.quic_packets, totals.rx_proto.udp_bytes), (1, 1, 80));
+        assert_eq!((counters.quic_packets, counters.quic_bytes, counters.udp_packets), (1, 1252, 0));
+        assert_eq!(app_proto_str(app_proto::QUIC), Some("quic"));
+        assert_eq!(app_proto_str(app_proto::UNKNOWN), None);
+    }
+
+    #[test]
+    fn test_tcp_flag_counters() {
+        let mut counters = TcpFlagCounters::default();
+        counters.add(tcp_flag::SYN);
+        counters.add(tcp_flag::SYN | tcp_flag::ACK);
+        counters.add(tcp_flag::FIN | tcp_flag::ACK);
+        counters.add(tcp_flag::RST | tcp_flag::ACK);
+        counters.add(tcp_flag::ACK);
+        assert_eq!((counters.syn, counters.syn_ack, counters.fin, counters.rst), (1, 1, 1, 1));
+    }
+
+    #[test]
+    fn test_schema_hash_tracks_layout() {
+        #[repr(C)]
+        struct Before {
+            a: u32,
+            b: u16,
+        }
+        #[repr(C)]
+        struct After {
+            a: u32,
+            b: u32,
+        }
+        let before = layout_hash!(SchemaHasher::new(), Before { a, b }).finish();
+        let after = layout_hash!(SchemaHasher::new(), After { a, b }).finish();
+        assert_ne!(before, after);
+        assert_eq!(before, layout_hash!(SchemaHasher::new(), Before { a, b }).finish());
+        assert_ne!(SCHEMA_HASH, 0);
+    }
+
+    #[test]
+    fn test_log2_buckets() {
+        assert_eq!(log2_bucket(0), 0);
+        assert_eq!(log2_bucket(1), 1);
+        assert_eq!(log2_bucket(2), 2);
+        assert_eq!(log2_bucket(3), 2);
+        assert_eq!(log2_bucket(1023), 10);
+        assert_eq!(log2_bucket(1024), 11);
+        assert_eq!(log2_bucket(u64::MAX), HIST_BUCKETS as u32 - 1);
+        for value in [1u64, 5, 700, 1 << 20, (1 << 30) + 3] {
+            let bucket = log2_bucket(value) as usize;
+            assert!(value <= Log2Histogram::upper_bound(bucket));
+            assert!(value > Log2Histogram::upper_bound(bucket - 1));
+        }
+    }
+
+    #[test]
+    fn test_histogram_merge_and_percentiles() {
+        let mut hist = Log2Histogram::default();
+        assert_eq!(hist.percentile(50.0), None);
+        for value in 1..=100u64 {
+            hist.record(value * 1000);
+        }
+        let mut other = Log2Histogram::default();
+        other.record(1 << 40);
+        hist.merge(&other);
+        assert_eq!(hist.count(), 101);
+        assert_eq!(hist.sum, 5_050_000 + (1 << 40));
+
+        // Estimates stay within the bucket holding the true value
+        let p50 = hist.percentile(50.0).unwrap();
+        assert!((32_768.0..=65_535.0).contains(&p50), "p50 = {}", p50);
+        let p99 = hist.percentile(99.0).unwrap();
+        assert!((65_536.0..=131_071.0).contains(&p99), "p99 = {}", p99);
+        // The overflow bucket reports its lower bound
+        assert_eq!(hist.percentile(100.0), Some((1u64 << 30) as f64));
+
+        // Map slots round-trip
+        let mut slots = [0u64; HIST_SLOTS as usize];
+        slots[..HIST_BUCKETS].copy_from_slice(&hist.buckets);
+        slots[HIST_SUM_SLOT as usize] = hist.sum;
+        assert_eq!(Log2Histogram::from_slots(&slots), hist);
+    }
+
+    #[test]
+    fn test_histogram_prometheus() {
+        let mut hist = Log2Histogram::default();
+        hist.record(0);
+        hist.record(3);
+        hist.record(3);
+        let text = hist.render_prometheus("rtt", &[("iface", "eth0")], 1.0);
+        assert_eq!(
+            text,
+            "rtt_bucket{iface=\"eth0\",le=\"0\"} 1\n\
+             rtt_bucket{iface=\"eth0\",le=\"1\"} 1\n\
+             rtt_bucket{iface=\"eth0\",le=\"3\"} 3\n\
+             rtt_bucket{iface=\"eth0\",le=\"+Inf\"} 3\n\
+             rtt_sum{iface=\"eth0\"} 6\n\
+             rtt_count{iface=\"eth0\"} 3\n"
+        );
+    }
+
+    #[test]
+    fn test_excess_dropped_without_sampling() {
+        let mut bucket = TokenBucket::default();
+        let t = tunables(1, 0);
+        assert_eq!(admit(&mut bucket, &t, event_kind::DROP, 1_000_000_000), 1);
+        assert_eq!(admit(&mut bucket, &t, event_kind::DROP, 1_000_000_000), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_events_serde() {
+        let drop = DropEvent { reason: drop_reason::NO_SOCKET, src_addr: ipv4_mapped([10, 0, 0, 1]), ..Default::default() };
+        let json = serde_json::to_value(Envelope { header: EventHeader::of::<DropEvent>(), event: drop }).unwrap();
+        assert_eq!(json["event"]["reason"], drop_reason::NO_SOCKET);
+        assert!(json["event"].get("_pad2").is_none());
+        let back: Envelope<DropEvent> = serde_json::from_value(json).unwrap();
+        assert_eq!(back.header, EventHeader::of::<DropEvent>());
+        assert_eq!(back.event.src_addr, drop.src_addr);
+
+        let mut http = HttpEvent {
+            timestamp_ns: 1,
+            src_addr: ipv4_mapped([10, 0, 0, 1]),
+            dst_addr: ipv4_mapped([10, 0, 0, 2]),
+            src_port: 40000,
+            dst_port: 80,
+            ifindex: 2,
+            path_len: 4,
+            method: http_method::GET,
+            direction: 1,
+            sample_rate: 0,
+            truncated: 0,
+            _pad: [0; 2],
+            path: [0; HTTP_PATH_LEN],
+        };
+        http.path[..4].copy_from_slice(b"/api");
+        let back: HttpEvent = serde_json::from_str(&serde_json::to_string(&http).unwrap()).unwrap();
+        assert_eq!(&back.path[..], &http.path[..]);
+    }
+}
@@ -1,29 +1,60 @@
 //! Sennet eBPF TC Classifier & Drop Tracer
 //!
 //! This program attaches to:
-//! 1. TC (Traffic Control) hook - counts packets/bytes for ingress/egress
+//! 1. TC (Traffic Control) hook - counts packets/bytes for ingress/egress,
+//!    or XDP for ingress where the agent runs in `attach_mode: xdp`
 //! 2. kfree_skb tracepoint - captures packet drop reasons (Phase 6.1)
-//! 3. nf_hook_slow tracepoint - captures netfilter hook/verdict (Phase 6.2)
-//! 4. kprobes for tcp_connect/inet_csk_accept/tcp_close - flow tracking (Phase 8)
+//! 3. nf_hook_slow fexit - captures netfilter hook/verdict (Phase 6.2)
+//! 4. kprobes for tcp_connect/inet_csk_accept/tcp_close - flow tracking (Phase 8),
+//!    and tcp_sendmsg/tcp_cleanup_rbuf - per-flow byte counts
+//! 5. TC hook also reports TCP RST segments for reset cause analysis, and
+//!    sources sending more packets per second than the agent allows; on
+//!    egress it records the server name of TLS ClientHellos to port 443;
+//!    requests on the ports in HTTP_PORTS have their request line sampled
+//! 6. kprobes for __nf_conntrack_confirm/nf_ct_delete - conntrack lifecycle
+//! 7. cgroup/skb ingress and egress - per-cgroup (container) traffic, when
+//!    the agent attaches them to a cgroup
+//! 8. sock:inet_sock_set_state tracepoint - TCP opens/closes and connection
+//!    churn, replacing the tcp_connect/tcp_close kprobes where available
+//!
+//! The TC and XDP programs only drop packets when the agent turns on
+//! enforcement (ENFORCE), and then only those `sennet block` listed.
+//!
+//! The `sennet_schema` section carries the layout hash of the shared types;
+//! the loader refuses an object whose hash differs from its own.
 
 #![no_std]
 #![no_main]
 
 use aya_ebpf::{
-    bindings::TC_ACT_PIPE,
-    macros::{classifier, map, tracepoint, kprobe},
-    maps::{PerCpuArray, RingBuf, LruHashMap},
-    programs::{TcContext, TracePointContext, ProbeContext},
-    helpers::{bpf_ktime_get_ns, bpf_get_current_pid_tgid, bpf_get_current_comm},
+    bindings::{xdp_action, TC_ACT_PIPE, TC_ACT_SHOT},
+    macros::{cgroup_skb, cgroup_sock, classifier, fexit, map, tracepoint, kprobe, kretprobe, xdp},
+    maps::{Array, HashMap, PerCpuArray, PerCpuHashMap, RingBuf, LruHashMap, LruPerCpuHashMap, StackTrace},
+    programs::{FExitContext, SkBuffContext, SockContext, TcContext, TracePointContext, ProbeContext, RetProbeContext, XdpContext},
+    helpers::{bpf_ktime_get_ns, bpf_get_prandom_u32, bpf_get_current_pid_tgid, bpf_get_current_comm, bpf_get_socket_cookie, bpf_probe_read_kernel, bpf_skb_cgroup_id},
 };
 // use aya_log_ebpf::info; // Reserved for future logging
-use sennet_common::{PacketCounters, PacketEvent, DropEvent, NetfilterEvent, FlowKey, FlowInfo, FlowEvent};
+use sennet_common::{
+    PacketCounters, TcpFlagCounters, PacketEvent, DropEvent, NetfilterEvent, FlowKey, FlowInfo, FlowEvent, SockOwner, RstEvent, RetransmitEvent, DnsEvent, ConntrackEvent, PayloadEvent, HttpEvent, TlsSni, IpPair, TalkerCounters, CgroupCounters, ConnChurn, KfreeSkbLayout, TcpRetransmitSkbLayout, TcpProbeLayout, InetSockSetStateLayout, SkbLayout, NfConnLayout,
+    Log2Histogram, DNS_NAME_LEN, DEFAULT_LARGE_PACKET_THRESHOLD, PAYLOAD_SNIPPET_MAX, TLS_SNI_LEN, HTTP_PATH_LEN, QUIC_PORT, MAX_INTERFACES,
+    Tunables, TokenBucket, DropFilter, TraceRule, PayloadRule, BlockKey, SourceRate, EventType, Envelope, EventHeader, RingEvent, NfVerdict, Addr128, app_proto, event_kind, ct_event, eth_p, ipproto, tcp_flag, ipv4_mapped, mapped_ipv4, network_header, packet_sample, source_over_rate, tls_client_hello_sni, http_request_line, quic_packet,
+    SCHEMA_HASH,
+};
 
 /// Per-CPU counters for packet statistics
 /// Index 0 = ingress, Index 1 = egress
 #[map]
 static COUNTERS: PerCpuArray<PacketCounters> = PerCpuArray::with_max_entries(2, 0);
 
+/// Per-CPU counters like COUNTERS for each attached interface, by ifindex
+#[map]
+static IFACE_COUNTERS: PerCpuHashMap<u32, PacketCounters> = PerCpuHashMap::with_max_entries(MAX_INTERFACES, 0);
+
+/// Per-CPU TCP segments by control flag (SYN, SYN+ACK, FIN, RST)
+/// Index 0 = ingress, Index 1 = egress
+#[map]
+static TCP_FLAG_COUNTERS: PerCpuArray<TcpFlagCounters> = PerCpuArray::with_max_entries(2, 0);
+
 /// Ring buffer for events (large packets, anomalies)
 #[map]
 static EVENTS: RingBuf = RingBuf::with_byte_size(256 * 1024, 0); // 256KB
@@ -32,6 +63,14 @@ static EVENTS: RingBuf = RingBuf::with_byte_size(256 * 1024, 0); // 256KB
 #[map]
 static DROP_EVENTS: RingBuf = RingBuf::with_byte_size(64 * 1024, 0); // 64KB
 
+/// Kernel stacks of dropped packets, referenced by `DropEvent::stack_id`
+#[map]
+static DROP_STACKS: StackTrace = StackTrace::with_max_entries(1024, 0);
+
+/// `bpf_get_stackid` flag: a new stack whose hash collides with a stored
+/// one replaces it, so the map keeps up with current drop paths
+const BPF_F_REUSE_STACKID: u64 = 1 << 10;
+
 /// Ring buffer for netfilter events (Phase 6.2)
 #[map]
 static NF_EVENTS: RingBuf = RingBuf::with_byte_size(32 * 1024, 0); // 32KB
@@ -41,190 +80,1555 @@ static NF_EVENTS: RingBuf = RingBuf::with_byte_size(32 * 1024, 0); // 32KB
 #[map]
 static FLOWS: LruHashMap<FlowKey, FlowInfo> = LruHashMap::with_max_entries(65536, 0); // 64K flows
 
+/// Creating process per socket cookie, from the cgroup/sock_create program;
+/// lets the egress program attribute flows the kprobes don't see (UDP)
+#[map]
+static SOCK_OWNERS: LruHashMap<u64, SockOwner> = LruHashMap::with_max_entries(65536, 0);
+
+/// Bytes and packets per source/destination pair, for top talkers
+#[map]
+static TOP_TALKERS: LruHashMap<IpPair, TalkerCounters> = LruHashMap::with_max_entries(16384, 0);
+
+/// Token bucket per ingress source address, for `Anomaly` events when
+/// SOURCE_PPS_THRESHOLD is set
+#[map]
+static SOURCE_RATES: LruHashMap<Addr128, SourceRate> = LruHashMap::with_max_entries(16384, 0);
+
+/// Traffic per cgroup ID, counted by the cgroup/skb programs
+#[map]
+static CGROUP_COUNTERS: LruPerCpuHashMap<u64, CgroupCounters> = LruPerCpuHashMap::with_max_entries(4096, 0);
+
+/// TCP connection opens, closes and failed handshakes (single entry)
+#[map]
+static CONN_CHURN: PerCpuArray<ConnChurn> = PerCpuArray::with_max_entries(1, 0);
+
+/// Smoothed RTT per remote address in microseconds, merged over CPUs by
+/// `sennet latency`
+#[map]
+static RTT_HISTOGRAMS: LruPerCpuHashMap<Addr128, Log2Histogram> = LruPerCpuHashMap::with_max_entries(4096, 0);
+
+/// Send time of outstanding ICMP echo requests by destination, ID and
+/// sequence number
+#[map]
+static ICMP_ECHOES: LruHashMap<IcmpEchoKey, u64> = LruHashMap::with_max_entries(4096, 0);
+
+/// Per-CPU ICMP echo round-trip times (microseconds) keyed by the pinged
+/// address
+#[map]
+static ICMP_RTT: LruPerCpuHashMap<Addr128, Log2Histogram> = LruPerCpuHashMap::with_max_entries(4096, 0);
+
 /// Ring buffer for flow events (new/close) (Phase 8)
 #[map]
 static FLOW_EVENTS: RingBuf = RingBuf::with_byte_size(64 * 1024, 0); // 64KB
 
-/// Large packet threshold (bytes)
-const LARGE_PACKET_THRESHOLD: u32 = 9000; // Jumbo frame size
+/// Ring buffer for TCP RST segments seen at the TC hook
+#[map]
+static RST_EVENTS: RingBuf = RingBuf::with_byte_size(64 * 1024, 0); // 64KB
+
+/// Ring buffer for TCP retransmissions (tcp_retransmit_skb tracepoint)
+#[map]
+static RETRANSMIT_EVENTS: RingBuf = RingBuf::with_byte_size(32 * 1024, 0); // 32KB
+
+/// Ring buffer for DNS queries and responses seen at the TC/XDP hook
+#[map]
+static DNS_EVENTS: RingBuf = RingBuf::with_byte_size(64 * 1024, 0); // 64KB
+
+/// Ring buffer for conntrack entries created and destroyed
+#[map]
+static CT_EVENTS: RingBuf = RingBuf::with_byte_size(64 * 1024, 0); // 64KB
+
+/// Ring buffer for packet heads `sennet trace --payload` asked for
+#[map]
+static PAYLOAD_EVENTS: RingBuf = RingBuf::with_byte_size(64 * 1024, 0); // 64KB
+
+/// Ring buffer for HTTP request lines on the ports in HTTP_PORTS
+#[map]
+static HTTP_EVENTS: RingBuf = RingBuf::with_byte_size(64 * 1024, 0); // 64KB
+
+/// Server ports whose HTTP/1.x requests are sampled (value unused); empty
+/// unless userspace configures some
+#[map]
+static HTTP_PORTS: HashMap<u16, u8> = HashMap::with_max_entries(64, 0);
+
+/// Send time of DNS queries awaiting a response, to time the response
+#[map]
+static DNS_QUERIES: LruHashMap<DnsQueryKey, u64> = LruHashMap::with_max_entries(8192, 0);
+
+/// Server names from TLS ClientHellos, keyed by the outbound flow
+#[map]
+static TLS_SNI: LruHashMap<FlowKey, TlsSni> = LruHashMap::with_max_entries(16384, 0);
+
+/// Runtime knobs set by userspace (rate limits), single entry
+#[map]
+static TUNABLES: Array<Tunables> = Array::with_max_entries(1, 0);
+
+/// Classifier event sampling set by userspace: look at 1 in N packets
+/// (0 or 1 = every packet), single entry
+#[map]
+static PACKET_SAMPLING: Array<u32> = Array::with_max_entries(1, 0);
+
+/// Size above which packets are reported in EVENTS, in bytes (single
+/// entry, 0 = DEFAULT_LARGE_PACKET_THRESHOLD; written by the agent)
+#[map]
+static LARGE_PACKET_THRESHOLD: Array<u32> = Array::with_max_entries(1, 0);
+
+/// Packets per second above which an ingress source is reported as an
+/// `Anomaly` in EVENTS (single entry, 0 = disabled; written by the agent)
+#[map]
+static SOURCE_PPS_THRESHOLD: Array<u32> = Array::with_max_entries(1, 0);
+
+/// Drop filter set by userspace (reasons, socket addresses), single entry
+#[map]
+static DROP_FILTER: Array<DropFilter> = Array::with_max_entries(1, 0);
+
+/// Filter set by `sennet trace` while it reads the rings, single entry
+#[map]
+static TRACE_RULE: Array<TraceRule> = Array::with_max_entries(1, 0);
+
+/// Packets `sennet trace --payload` wants the heads of, single entry
+#[map]
+static PAYLOAD_RULE: Array<PayloadRule> = Array::with_max_entries(1, 0);
+
+/// Endpoints `sennet block` lists, with the packets dropped for each
+#[map]
+static BLOCKLIST: HashMap<BlockKey, u64> = HashMap::with_max_entries(1024, 0);
+
+/// Nonzero when the agent runs with `enforcement: true`, single entry;
+/// BLOCKLIST is ignored otherwise
+#[map]
+static ENFORCE: Array<u32> = Array::with_max_entries(1, 0);
+
+/// kfree_skb record field offsets, written by the agent from the
+/// tracepoint's format file before attaching, single entry
+#[map]
+static KFREE_SKB_LAYOUT: Array<KfreeSkbLayout> = Array::with_max_entries(1, 0);
+
+/// tcp_retransmit_skb record field offsets, like KFREE_SKB_LAYOUT
+#[map]
+static TCP_RETRANSMIT_SKB_LAYOUT: Array<TcpRetransmitSkbLayout> = Array::with_max_entries(1, 0);
+
+/// tcp_probe record field offsets, like KFREE_SKB_LAYOUT
+#[map]
+static TCP_PROBE_LAYOUT: Array<TcpProbeLayout> = Array::with_max_entries(1, 0);
+
+/// inet_sock_set_state record field offsets, like KFREE_SKB_LAYOUT
+#[map]
+static INET_SOCK_SET_STATE_LAYOUT: Array<InetSockSetStateLayout> = Array::with_max_entries(1, 0);
+
+/// struct sk_buff field offsets, written by the agent from the kernel's
+/// BTF before attaching, single entry
+#[map]
+static SKB_LAYOUT: Array<SkbLayout> = Array::with_max_entries(1, 0);
+
+/// struct nf_conn field offsets, written by the agent from the kernel's
+/// (or nf_conntrack's) BTF before attaching, single entry
+#[map]
+static NF_CONN_LAYOUT: Array<NfConnLayout> = Array::with_max_entries(1, 0);
+
+/// Per-CPU token buckets, one per event kind
+#[map]
+static RATE_LIMIT: PerCpuArray<TokenBucket> = PerCpuArray::with_max_entries(event_kind::COUNT as u32, 0);
+
+/// Per-CPU count of ring buffer reservations that failed (ring full), one per event kind
+#[map]
+static RESERVE_FAILURES: PerCpuArray<u64> = PerCpuArray::with_max_entries(event_kind::COUNT as u32, 0);
+
+/// Schema hash written by the loader once verified, for readers of the pinned maps
+#[map]
+static SCHEMA: Array<u64> = Array::with_max_entries(1, 0);
+
+/// Layout hash of the shared types this object was built with
+#[no_mangle]
+#[used]
+#[link_section = "sennet_schema"]
+static SENNET_SCHEMA: [u8; 8] = SCHEMA_HASH.to_le_bytes();
+
 
 // =============================================================================
 // TC Classifiers (Traffic Counting)
 // =============================================================================
 
+/// Packet access shared by the TC and XDP hooks
+trait Packet {
+    /// Length of the packet, from the Ethernet header
+    fn len(&self) -> u32;
+    /// Read a `T` at `offset`; Err when it runs past the packet
+    fn load<T: Copy>(&self, offset: usize) -> Result<T, ()>;
+    /// Copy up to `dst.len()` bytes from `offset`; the number copied, Err
+    /// if none
+    fn load_bytes(&self, offset: usize, dst: &mut [u8]) -> Result<usize, ()>;
+    fn ifindex(&self) -> u32;
+}
+
+impl Packet for TcContext {
+    #[inline(always)]
+    fn len(&self) -> u32 {
+        TcContext::len(self)
+    }
+
+    #[inline(always)]
+    fn load<T: Copy>(&self, offset: usize) -> Result<T, ()> {
+        TcContext::load(self, offset).map_err(|_| ())
+    }
+
+    #[inline(always)]
+    fn load_bytes(&self, offset: usize, dst: &mut [u8]) -> Result<usize, ()> {
+        TcContext::load_bytes(self, offset, dst).map_err(|_| ())
+    }
+
+    #[inline(always)]
+    fn ifindex(&self) -> u32 {
+        unsafe { (*self.skb.skb).ifindex }
+    }
+}
+
+impl Packet for XdpContext {
+    #[inline(always)]
+    fn len(&self) -> u32 {
+        (self.data_end() - self.data()) as u32
+    }
+
+    /// Direct packet access, bounds-checked against `data_end` as the
+    /// verifier requires
+    #[inline(always)]
+    fn load<T: Copy>(&self, offset: usize) -> Result<T, ()> {
+        let start = self.data() + offset;
+        if start + core::mem::size_of::<T>() > self.data_end() {
+            return Err(());
+        }
+        Ok(unsafe { core::ptr::read_unaligned(start as *const T) })
+    }
+
+    /// Byte by byte, since `bpf_xdp_load_bytes` needs 5.18; `dst` is at
+    /// most `PAYLOAD_SNIPPET_MAX` long, which keeps the loop bounded
+    #[inline(always)]
+    fn load_bytes(&self, offset: usize, dst: &mut [u8]) -> Result<usize, ()> {
+        let mut copied = 0;
+        for byte in dst.iter_mut() {
+            let Ok(value) = self.load::<u8>(offset + copied) else {
+                break;
+            };
+            *byte = value;
+            copied += 1;
+        }
+        if copied == 0 {
+            return Err(());
+        }
+        Ok(copied)
+    }
+
+    #[inline(always)]
+    fn ifindex(&self) -> u32 {
+        unsafe { (*self.ctx).ingress_ifindex }
+    }
+}
+
 /// TC classifier for ingress traffic
+///
+/// Blocked packets are shot here and reach kfree_skb as TC_INGRESS drops.
 #[classifier]
 pub fn tc_ingress(ctx: TcContext) -> i32 {
-    match process_packet(&ctx, 0) {
+    let _ = process_packet(&ctx, 0);
+    if blocked(&ctx, 0) {
+        return TC_ACT_SHOT;
+    }
+    // TC_ACT_PIPE = pass to next filter/continue
+    TC_ACT_PIPE
+}
+
+/// TC classifier for egress traffic
+#[classifier]
+pub fn tc_egress(ctx: TcContext) -> i32 {
+    let _ = process_packet(&ctx, 1);
+    if blocked(&ctx, 1) {
+        return TC_ACT_SHOT;
+    }
+    TC_ACT_PIPE
+}
+
+/// XDP program for ingress traffic, replacing `tc_ingress` in XDP mode:
+/// counts at the driver, before an skb is allocated
+///
+/// Only drops blocked packets, which never reach kfree_skb: they show up
+/// in the BLOCKLIST counts alone.
+#[xdp]
+pub fn xdp_ingress(ctx: XdpContext) -> u32 {
+    let _ = process_packet(&ctx, 0);
+    if blocked(&ctx, 0) {
+        return xdp_action::XDP_DROP;
+    }
+    xdp_action::XDP_PASS
+}
+
+/// Whether enforcement is on and the packet's peer, or a port at either
+/// end, is in BLOCKLIST; counts the hit against the matching entry
+#[inline(always)]
+fn blocked<P: Packet>(ctx: &P, direction: u32) -> bool {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    if !matches!(ENFORCE.get(0), Some(&on) if on != 0) {
+        return false;
+    }
+    let Some((eth_proto, l3)) = network_header(|offset| ctx.load(offset).ok().map(u16::from_be)) else {
+        return false;
+    };
+    let Some((src_addr, dst_addr, protocol)) = ip_header(ctx, eth_proto, l3) else {
+        return false;
+    };
+    let ingress = direction == 0;
+    let ports = if protocol == ipproto::TCP || protocol == ipproto::UDP {
+        l4_header(ctx, eth_proto, l3).and_then(|l4| {
+            let src: u16 = ctx.load(l4).ok()?;
+            let dst: u16 = ctx.load(l4 + 2).ok()?;
+            Some((u16::from_be(src), u16::from_be(dst)))
+        })
+    } else {
+        None
+    };
+    let remote = if ingress { src_addr } else { dst_addr };
+    for key in BlockKey::candidates(remote, ports, ingress).iter().flatten() {
+        if let Some(hits) = BLOCKLIST.get_ptr_mut(key) {
+            unsafe { AtomicU64::from_ptr(hits).fetch_add(1, Ordering::Relaxed) };
+            return true;
+        }
+    }
+    false
+}
+
+/// Process a packet and update counters
+#[inline(always)]
+fn process_packet<P: Packet>(ctx: &P, direction: u32) -> Result<(), ()> {
+    let len = ctx.len() as u64;
+
+    // Find the network header past any VLAN tags (trunk ports, QinQ)
+    let header = network_header(|offset| ctx.load(offset).ok().map(u16::from_be));
+    let protocol = header.and_then(|(eth_proto, l3)| l4_protocol(ctx, eth_proto, l3)).unwrap_or(0);
+    let quic = protocol == ipproto::UDP
+        && header.is_some_and(|(eth_proto, l3)| quic_datagram(ctx, eth_proto, l3));
+
+    // Update counters
+    if let Some(counters) = COUNTERS.get_ptr_mut(direction) {
+        unsafe { (*counters).add(direction, protocol, quic, len) };
+    }
+    let ifindex = ctx.ifindex();
+    match IFACE_COUNTERS.get_ptr_mut(&ifindex) {
+        Some(counters) => unsafe { (*counters).add(direction, protocol, quic, len) },
+        None => {
+            let mut counters = PacketCounters::default();
+            counters.add(direction, protocol, quic, len);
+            // Fails once MAX_INTERFACES are counted
+            let _ = IFACE_COUNTERS.insert(&ifindex, &counters, 0);
+        }
+    }
+
+    let (eth_proto, l3) = header.ok_or(())?;
+
+    count_talker(ctx, eth_proto, l3, len);
+    if direction == 0 {
+        let _ = detect_rate_anomaly(ctx, eth_proto, l3);
+    }
+    if protocol == ipproto::TCP {
+        count_tcp_flags(ctx, direction, eth_proto, l3);
+        if direction == 1 {
+            let _ = record_tls_sni(ctx, eth_proto, l3);
+        }
+    }
+
+    // Counters above are exact; events below only look at sampled packets
+    let weight = sample_packet();
+
+    // DNS queries are remembered even when unsampled, to time responses
+    let _ = detect_dns(ctx, direction as u8, eth_proto, l3, weight);
+    if protocol == ipproto::ICMP || protocol == ipproto::ICMPV6 {
+        let _ = track_icmp_echo(ctx, direction, eth_proto, l3);
+    }
+
+    if weight == 0 {
+        return Ok(());
+    }
+
+    // Packet heads for `sennet trace --payload`
+    let _ = capture_payload(ctx, direction as u8, eth_proto, l3, weight);
+
+    if protocol == ipproto::TCP {
+        let _ = sample_http_request(ctx, direction as u8, eth_proto, l3, weight);
+    }
+
+    // Check for large packets and emit event
+    if len > large_packet_threshold() as u64 {
+        emit_large_packet_event(ctx, len as u32, direction as u8, eth_proto, l3, weight)?;
+    }
+
+    // Report TCP resets (ignore parse failures, the packet still passes)
+    let _ = detect_tcp_rst(ctx, direction as u8, eth_proto, l3, weight);
+
+    Ok(())
+}
+
+/// Fill in the header of a record reserved in a ring buffer and return
+/// where its event goes
+///
+/// # Safety
+///
+/// `envelope` must point to a reserved, writable `Envelope<T>`.
+#[inline(always)]
+unsafe fn event_body<T: RingEvent>(envelope: *mut Envelope<T>) -> *mut T {
+    (*envelope).header = EventHeader::of::<T>();
+    core::ptr::addr_of_mut!((*envelope).event)
+}
+
+/// Size above which packets are reported, from LARGE_PACKET_THRESHOLD (0 =
+/// the default)
+#[inline(always)]
+fn large_packet_threshold() -> u32 {
+    match LARGE_PACKET_THRESHOLD.get(0) {
+        Some(&bytes) if bytes > 0 => bytes,
+        _ => DEFAULT_LARGE_PACKET_THRESHOLD,
+    }
+}
+
+/// Apply PACKET_SAMPLING to the current packet
+///
+/// Returns 0 to skip its events, otherwise the number of packets it stands
+/// for, to be folded into each event's `sample_rate`.
+#[inline(always)]
+fn sample_packet() -> u8 {
+    match PACKET_SAMPLING.get(0) {
+        Some(&one_in) if one_in > 1 => packet_sample(one_in, unsafe { bpf_get_prandom_u32() }),
+        _ => 1,
+    }
+}
+
+/// Add the packet to its source/destination pair in TOP_TALKERS
+///
+/// Counters are added atomically since other CPUs update the same entry.
+#[inline(always)]
+fn count_talker<P: Packet>(ctx: &P, eth_proto: u16, l3: usize, len: u64) {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    const BPF_NOEXIST: u64 = 1;
+
+    let Some((src_addr, dst_addr, _)) = ip_header(ctx, eth_proto, l3) else {
+        return;
+    };
+    let key = IpPair { src_addr, dst_addr };
+    let now = unsafe { bpf_ktime_get_ns() };
+    match TOP_TALKERS.get_ptr_mut(&key) {
+        Some(entry) => unsafe {
+            AtomicU64::from_ptr(&mut (*entry).packets).fetch_add(1, Ordering::Relaxed);
+            AtomicU64::from_ptr(&mut (*entry).bytes).fetch_add(len, Ordering::Relaxed);
+            (*entry).last_seen_ns = now;
+        },
+        None => {
+            // Another CPU may have inserted it meanwhile; losing one packet is fine
+            let first = TalkerCounters { packets: 1, bytes: len, last_seen_ns: now };
+            let _ = TOP_TALKERS.insert(&key, &first, BPF_NOEXIST);
+        }
+    }
+}
+
+/// IP protocol (IPv6: first next header) of a packet whose network header
+/// starts at `l3`; None for non-IP frames
+#[inline(always)]
+fn l4_protocol<P: Packet>(ctx: &P, eth_proto: u16, l3: usize) -> Option<u8> {
+    match eth_proto {
+        eth_p::IP => ctx.load(l3 + 9).ok(),
+        eth_p::IPV6 => ctx.load(l3 + 6).ok(),
+        _ => None,
+    }
+}
+
+/// Source address, destination address and protocol of an IPv4 or IPv6
+/// packet whose network header starts at `l3`; None for other EtherTypes
+/// or truncated headers
+///
+/// IPv6 extension headers aren't walked: the protocol is the first next
+/// header.
+#[inline(always)]
+fn ip_header<P: Packet>(ctx: &P, eth_proto: u16, l3: usize) -> Option<(Addr128, Addr128, u8)> {
+    match eth_proto {
+        eth_p::IP => {
+            let src: [u8; 4] = ctx.load(l3 + 12).ok()?;
+            let dst: [u8; 4] = ctx.load(l3 + 16).ok()?;
+            let protocol: u8 = ctx.load(l3 + 9).ok()?;
+            Some((ipv4_mapped(src), ipv4_mapped(dst), protocol))
+        }
+        eth_p::IPV6 => {
+            let src: Addr128 = ctx.load(l3 + 8).ok()?;
+            let dst: Addr128 = ctx.load(l3 + 24).ok()?;
+            let next_header: u8 = ctx.load(l3 + 6).ok()?;
+            Some((src, dst, next_header))
+        }
+        _ => None,
+    }
+}
+
+/// Offset of the L4 header of an IPv4 or IPv6 packet whose network header
+/// starts at `l3`; None for a malformed IPv4 header length
+///
+/// As in [`ip_header`], IPv6 extension headers aren't walked.
+#[inline(always)]
+fn l4_header<P: Packet>(ctx: &P, eth_proto: u16, l3: usize) -> Option<usize> {
+    if eth_proto != eth_p::IP {
+        return Some(l3 + 40);
+    }
+    // IHL is the low nibble of the first IP byte, in 32-bit words
+    let ver_ihl: u8 = ctx.load(l3).ok()?;
+    let ihl = ((ver_ihl & 0x0f) as usize) * 4;
+    if ihl < 20 {
+        return None;
+    }
+    Some(l3 + ihl)
+}
+
+/// TCP/UDP source and destination port, host byte order; None for other
+/// protocols or a truncated header
+#[inline(always)]
+fn l4_ports<P: Packet>(ctx: &P, eth_proto: u16, l3: usize, protocol: u8) -> Option<(u16, u16)> {
+    if protocol != ipproto::TCP && protocol != ipproto::UDP {
+        return None;
+    }
+    let l4 = l4_header(ctx, eth_proto, l3)?;
+    Some((u16::from_be(ctx.load(l4).ok()?), u16::from_be(ctx.load(l4 + 2).ok()?)))
+}
+
+/// Whether a UDP datagram to or from `QUIC_PORT` carries a QUIC packet
+#[inline(always)]
+fn quic_datagram<P: Packet>(ctx: &P, eth_proto: u16, l3: usize) -> bool {
+    let Some(udp_off) = l4_header(ctx, eth_proto, l3) else {
+        return false;
+    };
+    let (Ok(src_port), Ok(dst_port)) = (ctx.load::<u16>(udp_off), ctx.load::<u16>(udp_off + 2)) else {
+        return false;
+    };
+    if u16::from_be(src_port) != QUIC_PORT && u16::from_be(dst_port) != QUIC_PORT {
+        return false;
+    }
+    // Payload follows the 8-byte UDP header
+    quic_packet(|offset| ctx.load(udp_off + 8 + offset).ok())
+}
+
+/// Count the control flags of a TCP segment in TCP_FLAG_COUNTERS
+#[inline(always)]
+fn count_tcp_flags<P: Packet>(ctx: &P, direction: u32, eth_proto: u16, l3: usize) {
+    let Some(tcp_off) = l4_header(ctx, eth_proto, l3) else {
+        return;
+    };
+    let Ok(flags) = ctx.load::<u8>(tcp_off + 13) else {
+        return;
+    };
+    if let Some(counters) = TCP_FLAG_COUNTERS.get_ptr_mut(direction) {
+        unsafe { (*counters).add(flags) };
+    }
+}
+
+/// Emit a large packet event to ring buffer
+#[inline(always)]
+fn emit_large_packet_event<P: Packet>(
+    ctx: &P,
+    size: u32,
+    direction: u8,
+    eth_proto: u16,
+    l3: usize,
+    weight: u8,
+) -> Result<(), ()> {
+    let (src_addr, dst_addr, protocol) = ip_header(ctx, eth_proto, l3).unwrap_or(([0; 16], [0; 16], 0));
+    let ports = l4_ports(ctx, eth_proto, l3, protocol);
+    // Filtered packets don't use up rate limit tokens
+    let ipv4 = |addr: &Addr128| mapped_ipv4(addr).map(u32::from_be_bytes).unwrap_or(0);
+    if !trace_admits(None, ipv4(&src_addr), ipv4(&dst_addr), ports, Some(protocol)) {
+        return Ok(());
+    }
+    let sample_rate = rate_limit(event_kind::PACKET).saturating_mul(weight);
+    if sample_rate == 0 {
+        return Ok(());
+    }
+
+    // Try to reserve space in ring buffer
+    if let Some(mut entry) = EVENTS.reserve::<Envelope<PacketEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
+        unsafe {
+            (*event).timestamp_ns = bpf_ktime_get_ns();
+            (*event).src_addr = src_addr;
+            (*event).dst_addr = dst_addr;
+            (*event).event_type = EventType::LargePacket as u32;
+            (*event).size = size;
+            (*event).ifindex = ctx.ifindex();
+            (*event).eth_proto = eth_proto;
+            (*event).protocol = protocol;
+            (*event).direction = direction;
+            (*event).sample_rate = sample_rate;
+            (*event)._pad = [0; 3];
+            (*event).pps = 0;
+            let (src_port, dst_port) = ports.unwrap_or((0, 0));
+            (*event).src_port = src_port;
+            (*event).dst_port = dst_port;
+            (*event)._pad2 = 0;
+        }
+        entry.submit(0);
+    } else {
+        reserve_failed(event_kind::PACKET);
+    }
+    Ok(())
+}
+
+/// Charge an ingress packet to its source in SOURCE_RATES and emit an
+/// `Anomaly` PacketEvent once a second while the source is over
+/// SOURCE_PPS_THRESHOLD
+///
+/// Runs for every packet, before sampling, so rates are exact. Concurrent
+/// updates from other CPUs may lose a packet now and then, which only
+/// makes detection slightly later.
+#[inline(always)]
+fn detect_rate_anomaly<P: Packet>(ctx: &P, eth_proto: u16, l3: usize) -> Result<(), ()> {
+    const BPF_NOEXIST: u64 = 1;
+
+    let pps = match SOURCE_PPS_THRESHOLD.get(0) {
+        Some(&pps) if pps > 0 => pps,
+        _ => return Ok(()),
+    };
+    let (src_addr, dst_addr, protocol) = ip_header(ctx, eth_proto, l3).ok_or(())?;
+    let now = unsafe { bpf_ktime_get_ns() };
+    let rate = match SOURCE_RATES.get_ptr_mut(&src_addr) {
+        Some(rate) => rate,
+        None => {
+            let _ = SOURCE_RATES.insert(&src_addr, &SourceRate::default(), BPF_NOEXIST);
+            SOURCE_RATES.get_ptr_mut(&src_addr).ok_or(())?
+        }
+    };
+    let measured = source_over_rate(unsafe { &mut *rate }, pps, now);
+    if measured == 0 {
+        return Ok(());
+    }
+    let sample_rate = rate_limit(event_kind::PACKET);
+    if sample_rate == 0 {
+        return Ok(());
+    }
+
+    if let Some(mut entry) = EVENTS.reserve::<Envelope<PacketEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
+        unsafe {
+            (*event).timestamp_ns = now;
+            (*event).src_addr = src_addr;
+            (*event).dst_addr = dst_addr;
+            (*event).event_type = EventType::Anomaly as u32;
+            (*event).size = ctx.len();
+            (*event).ifindex = ctx.ifindex();
+            (*event).eth_proto = eth_proto;
+            (*event).protocol = protocol;
+            (*event).direction = 0;
+            (*event).sample_rate = sample_rate;
+            (*event)._pad = [0; 3];
+            (*event).pps = measured;
+            let (src_port, dst_port) = l4_ports(ctx, eth_proto, l3, protocol).unwrap_or((0, 0));
+            (*event).src_port = src_port;
+            (*event).dst_port = dst_port;
+            (*event)._pad2 = 0;
+        }
+        entry.submit(0);
+    } else {
+        reserve_failed(event_kind::PACKET);
+    }
+    Ok(())
+}
+
+/// Emit a PayloadEvent with the first bytes of an IP packet, if the
+/// payload rule is set and the packet passes it
+///
+/// `weight` is the packet's PACKET_SAMPLING weight.
+#[inline(always)]
+fn capture_payload<P: Packet>(ctx: &P, direction: u8, eth_proto: u16, l3: usize, weight: u8) -> Result<(), ()> {
+    let payload = PAYLOAD_RULE.get(0).ok_or(())?;
+    let wanted = (payload.len as usize).min(PAYLOAD_SNIPPET_MAX);
+    let now = unsafe { bpf_ktime_get_ns() };
+    if wanted == 0 || now >= payload.rule.expires_ns {
+        return Ok(());
+    }
+    let (src_addr, dst_addr, protocol) = ip_header(ctx, eth_proto, l3).ok_or(())?;
+    let ports = l4_ports(ctx, eth_proto, l3, protocol);
+    let ipv4 = |addr: &Addr128| mapped_ipv4(addr).map(u32::from_be_bytes).unwrap_or(0);
+    if !sennet_common::trace_rule_admits(&payload.rule, now, None, ipv4(&src_addr), ipv4(&dst_addr), ports, Some(protocol)) {
+        return Ok(());
+    }
+    let sample_rate = rate_limit(event_kind::PAYLOAD).saturating_mul(weight);
+    if sample_rate == 0 {
+        return Ok(());
+    }
+
+    if let Some(mut entry) = PAYLOAD_EVENTS.reserve::<Envelope<PayloadEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
+        let (src_port, dst_port) = ports.unwrap_or((0, 0));
+        unsafe {
+            (*event).timestamp_ns = bpf_ktime_get_ns();
+            (*event).ifindex = ctx.ifindex();
+            (*event).len = ctx.len();
+            (*event).src_addr = src_addr;
+            (*event).dst_addr = dst_addr;
+            (*event).src_port = src_port;
+            (*event).dst_port = dst_port;
+            (*event).direction = direction;
+            (*event).protocol = protocol;
+            (*event).sample_rate = sample_rate;
+            (*event)._pad = [0; 7];
+            let captured = ctx.load_bytes(0, &mut (*event).data[..wanted]).unwrap_or(0);
+            (*event).captured = captured as u16;
+        }
+        entry.submit(0);
+    } else {
+        reserve_failed(event_kind::PAYLOAD);
+    }
+    Ok(())
+}
+
+/// Emit an HttpEvent for a segment to one of the HTTP_PORTS that starts
+/// with an HTTP/1.x request line
+///
+/// `weight` is the packet's PACKET_SAMPLING weight.
+#[inline(always)]
+fn sample_http_request<P: Packet>(ctx: &P, direction: u8, eth_proto: u16, l3: usize, weight: u8) -> Result<(), ()> {
+    let tcp_off = l4_header(ctx, eth_proto, l3).ok_or(())?;
+    let dst_port = u16::from_be(ctx.load(tcp_off + 2)?);
+    if unsafe { HTTP_PORTS.get(&dst_port) }.is_none() {
+        return Ok(());
+    }
+    // Data offset is the high nibble of byte 12, in 32-bit words
+    let doff: u8 = ctx.load(tcp_off + 12)?;
+    let payload = tcp_off + ((doff >> 4) as usize) * 4;
+    let Some(line) = http_request_line(|offset| ctx.load(payload + offset).ok()) else {
+        return Ok(());
+    };
+
+    let (src_addr, dst_addr, _) = ip_header(ctx, eth_proto, l3).ok_or(())?;
+    let src_port = u16::from_be(ctx.load(tcp_off)?);
+    let ipv4 = |addr: &Addr128| mapped_ipv4(addr).map(u32::from_be_bytes).unwrap_or(0);
+    if !trace_admits(None, ipv4(&src_addr), ipv4(&dst_addr), Some((src_port, dst_port)), Some(ipproto::TCP)) {
+        return Ok(());
+    }
+    let sample_rate = rate_limit(event_kind::HTTP).saturating_mul(weight);
+    if sample_rate == 0 {
+        return Ok(());
+    }
+
+    if let Some(mut entry) = HTTP_EVENTS.reserve::<Envelope<HttpEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
+        unsafe {
+            (*event).timestamp_ns = bpf_ktime_get_ns();
+            (*event).src_addr = src_addr;
+            (*event).dst_addr = dst_addr;
+            (*event).src_port = src_port;
+            (*event).dst_port = dst_port;
+            (*event).ifindex = ctx.ifindex();
+            (*event).path_len = line.path_len as u16;
+            (*event).method = line.method;
+            (*event).direction = direction;
+            (*event).sample_rate = sample_rate;
+            (*event).truncated = line.truncated as u8;
+            (*event)._pad = [0; 2];
+            (*event).path = [0; HTTP_PATH_LEN];
+            // Copies what the segment holds past the target too; userspace
+            // stops at path_len
+            let _ = ctx.load_bytes(payload + line.path_off, &mut (*event).path);
+        }
+        entry.submit(0);
+    } else {
+        reserve_failed(event_kind::HTTP);
+    }
+    Ok(())
+}
+
+/// Whether an event passes the trace rule, if `sennet trace` set one
+///
+/// Arguments as for `sennet_common::trace_rule_admits`.
+#[inline(always)]
+fn trace_admits(reason: Option<u32>, src_ip: u32, dst_ip: u32, ports: Option<(u16, u16)>, protocol: Option<u8>) -> bool {
+    match TRACE_RULE.get(0) {
+        Some(rule) if rule.expires_ns != 0 => {
+            let now = unsafe { bpf_ktime_get_ns() };
+            sennet_common::trace_rule_admits(rule, now, reason, src_ip, dst_ip, ports, protocol)
+        }
+        _ => true,
+    }
+}
+
+/// Apply the token bucket for `kind`
+///
+/// Returns 0 to suppress the event, otherwise the value for its
+/// `sample_rate` field (1 = unsampled, N = sampled 1 in N).
+#[inline(always)]
+fn rate_limit(kind: usize) -> u8 {
+    let Some(tunables) = TUNABLES.get(0) else {
+        return 1;
+    };
+    let Some(bucket) = RATE_LIMIT.get_ptr_mut(kind as u32) else {
+        return 1;
+    };
+    sennet_common::admit(unsafe { &mut *bucket }, tunables, kind, unsafe { bpf_ktime_get_ns() })
+}
+
+/// Count an event lost because its ring buffer was full
+#[inline(always)]
+fn reserve_failed(kind: usize) {
+    if let Some(count) = RESERVE_FAILURES.get_ptr_mut(kind as u32) {
+        unsafe { *count += 1 };
+    }
+}
+
+/// Emit an RstEvent if the packet is an IPv4 TCP segment with RST set
+///
+/// `weight` is the packet's PACKET_SAMPLING weight.
+#[inline(always)]
+fn detect_tcp_rst<P: Packet>(ctx: &P, direction: u8, eth_proto: u16, l3: usize, weight: u8) -> Result<(), ()> {
+    const IPPROTO_TCP: u8 = 6;
+
+    if eth_proto != eth_p::IP {
+        return Ok(());
+    }
+    let protocol: u8 = ctx.load(l3 + 9)?;
+    if protocol != IPPROTO_TCP {
+        return Ok(());
+    }
+
+    let Some(tcp_off) = l4_header(ctx, eth_proto, l3) else {
+        return Ok(());
+    };
+    let flags: u8 = ctx.load(tcp_off + 13)?;
+    if flags & tcp_flag::RST == 0 {
+        return Ok(());
+    }
+
+    let src_ip: u32 = ctx.load(l3 + 12)?;
+    let dst_ip: u32 = ctx.load(l3 + 16)?;
+    let src_port: u16 = ctx.load(tcp_off)?;
+    let dst_port: u16 = ctx.load(tcp_off + 2)?;
+
+    let ports = (u16::from_be(src_port), u16::from_be(dst_port));
+    if !trace_admits(None, u32::from_be(src_ip), u32::from_be(dst_ip), Some(ports), Some(IPPROTO_TCP)) {
+        return Ok(());
+    }
+
+    let sample_rate = rate_limit(event_kind::RST).saturating_mul(weight);
+    if sample_rate == 0 {
+        return Ok(());
+    }
+
+    if let Some(mut entry) = RST_EVENTS.reserve::<Envelope<RstEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
+        unsafe {
+            (*event).timestamp_ns = bpf_ktime_get_ns();
+            (*event).src_ip = u32::from_be(src_ip);
+            (*event).dst_ip = u32::from_be(dst_ip);
+            (*event).src_port = u16::from_be(src_port);
+            (*event).dst_port = u16::from_be(dst_port);
+            (*event).ifindex = ctx.ifindex();
+            (*event).direction = direction;
+            (*event).tcp_flags = flags;
+            (*event).sample_rate = sample_rate;
+            (*event)._pad = [0; 5];
+        }
+        entry.submit(0);
+    } else {
+        reserve_failed(event_kind::RST);
+    }
+    Ok(())
+}
+
+/// DNS_QUERIES key: who asked, and the transaction ID
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DnsQueryKey {
+    client_addr: Addr128,
+    client_port: u16,
+    id: u16,
+}
+
+/// Emit a DnsEvent for a DNS query to, or response from, UDP port 53
+///
+/// Responses are timed against the query with the same client address,
+/// port and transaction ID. Queries are recorded even when `weight` (the
+/// packet's PACKET_SAMPLING weight) is 0 so sampled responses still get
+/// their latency. IPv6 packets with extension headers are skipped.
+#[inline(always)]
+fn detect_dns<P: Packet>(ctx: &P, direction: u8, eth_proto: u16, l3: usize, weight: u8) -> Result<(), ()> {
+    const IPPROTO_UDP: u8 = 17;
+    const DNS_PORT: u16 = 53;
+    const BPF_ANY: u64 = 0;
+
+    let (src_addr, dst_addr, protocol) = ip_header(ctx, eth_proto, l3).ok_or(())?;
+    if protocol != IPPROTO_UDP {
+        return Ok(());
+    }
+    let Some(udp_off) = l4_header(ctx, eth_proto, l3) else {
+        return Ok(());
+    };
+    let src_port = u16::from_be(ctx.load(udp_off)?);
+    let dst_port = u16::from_be(ctx.load(udp_off + 2)?);
+    if src_port != DNS_PORT && dst_port != DNS_PORT {
+        return Ok(());
+    }
+
+    // Header: ID, flags (QR is the top bit, RCODE the low nibble), counts
+    let dns_off = udp_off + 8;
+    let id = u16::from_be(ctx.load(dns_off)?);
+    let flags = u16::from_be(ctx.load(dns_off + 2)?);
+    let is_response = flags & 0x8000 != 0;
+    let (client_addr, server_addr, client_port) = match is_response {
+        false if dst_port == DNS_PORT => (src_addr, dst_addr, src_port),
+        true if src_port == DNS_PORT => (dst_addr, src_addr, dst_port),
+        _ => return Ok(()),
+    };
+
+    let now = unsafe { bpf_ktime_get_ns() };
+    let key = DnsQueryKey { client_addr, client_port, id };
+    let latency_ns = if is_response {
+        let sent = unsafe { DNS_QUERIES.get(&key) }.copied();
+        let _ = DNS_QUERIES.remove(&key);
+        sent.map(|sent| now.saturating_sub(sent)).unwrap_or(0)
+    } else {
+        let _ = DNS_QUERIES.insert(&key, &now, BPF_ANY);
+        0
+    };
+
+    if weight == 0 {
+        return Ok(());
+    }
+    let ipv4 = |addr: &Addr128| mapped_ipv4(addr).map(u32::from_be_bytes).unwrap_or(0);
+    if !trace_admits(None, ipv4(&src_addr), ipv4(&dst_addr), Some((src_port, dst_port)), Some(IPPROTO_UDP)) {
+        return Ok(());
+    }
+    let sample_rate = rate_limit(event_kind::DNS).saturating_mul(weight);
+    if sample_rate == 0 {
+        return Ok(());
+    }
+
+    if let Some(mut entry) = DNS_EVENTS.reserve::<Envelope<DnsEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
+        unsafe {
+            (*event).timestamp_ns = now;
+            (*event).latency_ns = latency_ns;
+            (*event).client_addr = client_addr;
+            (*event).server_addr = server_addr;
+            (*event).client_port = client_port;
+            (*event).id = id;
+            (*event).qtype = 0;
+            (*event).is_response = is_response as u8;
+            (*event).rcode = if is_response { (flags & 0x0f) as u8 } else { 0 };
+            (*event).direction = direction;
+            (*event).sample_rate = sample_rate;
+            (*event)._pad = [0; 2];
+            (*event).ifindex = ctx.ifindex();
+            (*event).qname = [0; DNS_NAME_LEN];
+
+            // The question follows the 12-byte header; copy its name up to
+            // the terminating zero, then read the type after it
+            let name_off = dns_off + 12;
+            for i in 0..DNS_NAME_LEN {
+                let Ok(byte) = ctx.load::<u8>(name_off + i) else {
+                    break;
+                };
+                (*event).qname[i] = byte;
+                if byte == 0 {
+                    (*event).qtype = ctx.load::<u16>(name_off + i + 1).map(u16::from_be).unwrap_or(0);
+                    break;
+                }
+            }
+        }
+        entry.submit(0);
+    } else {
+        reserve_failed(event_kind::DNS);
+    }
+    Ok(())
+}
+
+/// Remember the server name of a TLS ClientHello sent to port 443 in
+/// TLS_SNI, under the flow key the kprobes use for the connection
+///
+/// Every ClientHello is looked at, sampled or not. Only IPv4 is covered,
+/// like FLOWS.
+#[inline(always)]
+fn record_tls_sni<P: Packet>(ctx: &P, eth_proto: u16, l3: usize) -> Result<(), ()> {
+    const HTTPS_PORT: u16 = 443;
+    const BPF_ANY: u64 = 0;
+
+    if eth_proto != eth_p::IP {
+        return Ok(());
+    }
+    let tcp_off = l4_header(ctx, eth_proto, l3).ok_or(())?;
+    let dst_port = u16::from_be(ctx.load(tcp_off + 2)?);
+    if dst_port != HTTPS_PORT {
+        return Ok(());
+    }
+    // Data offset is the high nibble of byte 12, in 32-bit words
+    let doff: u8 = ctx.load(tcp_off + 12)?;
+    let payload = tcp_off + ((doff >> 4) as usize) * 4;
+    let Some((name_off, name_len)) = tls_client_hello_sni(|offset| ctx.load(payload + offset).ok()) else {
+        return Ok(());
+    };
+
+    let src: [u8; 4] = ctx.load(l3 + 12)?;
+    let dst: [u8; 4] = ctx.load(l3 + 16)?;
+    let key = FlowKey {
+        src_ip: u32::from_be_bytes(src),
+        dst_ip: u32::from_be_bytes(dst),
+        src_port: u16::from_be(ctx.load(tcp_off)?),
+        dst_port,
+        protocol: ipproto::TCP,
+        _pad: [0; 3],
+    };
+    let mut sni = TlsSni { len: name_len as u16, _pad: [0; 2], name: [0; TLS_SNI_LEN] };
+    // Copies what the segment holds past the name too; userspace stops at len
+    ctx.load_bytes(payload + name_off, &mut sni.name)?;
+    let _ = TLS_SNI.insert(&key, &sni, BPF_ANY);
+    Ok(())
+}
+
+/// ICMP_ECHOES key: the pinged address, and the echo's identifier and
+/// sequence number as sent (network byte order)
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IcmpEchoKey {
+    remote: Addr128,
+    id: u16,
+    seq: u16,
+}
+
+/// Time ICMP echoes this host sends: requests leaving are remembered,
+/// replies arriving with the same address, ID and sequence number record
+/// the round trip in ICMP_RTT
+///
+/// Every packet is timed, sampled or not. Echoes from remotes pinging this
+/// host aren't timed: their round trip is the remote's to measure.
+#[inline(always)]
+fn track_icmp_echo<P: Packet>(ctx: &P, direction: u32, eth_proto: u16, l3: usize) -> Result<(), ()> {
+    const ICMP_ECHO_REPLY: u8 = 0;
+    const ICMP_ECHO_REQUEST: u8 = 8;
+    const ICMPV6_ECHO_REQUEST: u8 = 128;
+    const ICMPV6_ECHO_REPLY: u8 = 129;
+    const BPF_ANY: u64 = 0;
+    const BPF_NOEXIST: u64 = 1;
+
+    let (src_addr, dst_addr, _) = ip_header(ctx, eth_proto, l3).ok_or(())?;
+    let icmp_off = l4_header(ctx, eth_proto, l3).ok_or(())?;
+    // Header: type, code, checksum, then the echo identifier and sequence
+    let icmp_type: u8 = ctx.load(icmp_off)?;
+    let id: u16 = ctx.load(icmp_off + 4)?;
+    let seq: u16 = ctx.load(icmp_off + 6)?;
+
+    let (request, reply) = match eth_proto {
+        eth_p::IP => (ICMP_ECHO_REQUEST, ICMP_ECHO_REPLY),
+        _ => (ICMPV6_ECHO_REQUEST, ICMPV6_ECHO_REPLY),
+    };
+    let now = unsafe { bpf_ktime_get_ns() };
+    if direction == 1 && icmp_type == request {
+        let key = IcmpEchoKey { remote: dst_addr, id, seq };
+        let _ = ICMP_ECHOES.insert(&key, &now, BPF_ANY);
+    } else if direction == 0 && icmp_type == reply {
+        let key = IcmpEchoKey { remote: src_addr, id, seq };
+        let Some(sent) = (unsafe { ICMP_ECHOES.get(&key) }).copied() else {
+            return Ok(());
+        };
+        let _ = ICMP_ECHOES.remove(&key);
+        let rtt_us = now.saturating_sub(sent) / 1000;
+        match ICMP_RTT.get_ptr_mut(&src_addr) {
+            Some(hist) => unsafe { (*hist).record(rtt_us) },
+            None => {
+                let mut first = Log2Histogram::default();
+                first.record(rtt_us);
+                let _ = ICMP_RTT.insert(&src_addr, &first, BPF_NOEXIST);
+            }
+        }
+    }
+    Ok(())
+}
+
+// =============================================================================
+// kfree_skb Tracepoint (Phase 6.1: Drop Reason Tracing)
+// =============================================================================
+
+/// Tracepoint for kernel packet drops
+/// 
+/// Attaches to: tracepoint/skb/kfree_skb
+/// 
+/// Context format (Linux 5.17+):
+///   struct {
+///       void *skbaddr;           // offset 0
+///       void *location;          // offset 8
+///       unsigned short protocol; // offset 16
+///       enum skb_drop_reason reason; // offset 20 (Linux 5.17+)
+///   }
+#[tracepoint]
+pub fn kfree_skb(ctx: TracePointContext) -> u32 {
+    match try_kfree_skb(&ctx) {
         Ok(ret) => ret,
-        Err(_) => TC_ACT_PIPE,
+        Err(_) => 0,
     }
 }
 
-/// TC classifier for egress traffic
-#[classifier]
-pub fn tc_egress(ctx: TcContext) -> i32 {
-    match process_packet(&ctx, 1) {
-        Ok(ret) => ret,
-        Err(_) => TC_ACT_PIPE,
+#[inline(always)]
+fn try_kfree_skb(ctx: &TracePointContext) -> Result<u32, ()> {
+    // Field offsets come from the tracepoint's format file; kernels before
+    // 5.17 have no reason field and report 0
+    let layout = kfree_skb_layout();
+    let reason: u32 = match layout.reason {
+        0 => 0,
+        offset => unsafe { ctx.read_at(offset as usize).unwrap_or(0) },
+    };
+    
+    // Only emit events for interesting drop reasons (not NOT_SPECIFIED=1)
+    // Reason 0 means we couldn't read it (older kernel)
+    if reason > 1 {
+        // Filtered drops don't use up rate limit tokens
+        let skb: *const u8 = unsafe { ctx.read_at(layout.skbaddr as usize) }.unwrap_or(core::ptr::null());
+        let (src_ip, dst_ip, src_port, dst_port) = skb_socket_tuple(skb);
+        let packet = skb_packet_tuple(skb);
+        // Filters see the owning socket's tuple, or the packet's own when
+        // no socket owns it (ingress drops before socket lookup)
+        let (filter_tuple, ip_protocol) = match packet {
+            Some(p) if src_port == 0 && dst_port == 0 => {
+                let v4 = |addr: &Addr128| mapped_ipv4(addr).map(u32::from_be_bytes).unwrap_or(0);
+                ((v4(&p.src_addr), v4(&p.dst_addr), p.src_port, p.dst_port), Some(p.protocol))
+            }
+            Some(p) => ((src_ip, dst_ip, src_port, dst_port), Some(p.protocol)),
+            None => ((src_ip, dst_ip, src_port, dst_port), None),
+        };
+        let (f_src, f_dst, f_sport, f_dport) = filter_tuple;
+        if let Some(filter) = DROP_FILTER.get(0) {
+            if !sennet_common::drop_filter_admits(filter, reason, f_src, f_dst, f_sport, f_dport) {
+                return Ok(0);
+            }
+        }
+        if !trace_admits(Some(reason), f_src, f_dst, Some((f_sport, f_dport)), ip_protocol) {
+            return Ok(0);
+        }
+        let sample_rate = rate_limit(event_kind::DROP);
+        if sample_rate == 0 {
+            return Ok(0);
+        }
+        if let Some(mut entry) = DROP_EVENTS.reserve::<Envelope<DropEvent>>(0) {
+            let event = unsafe { event_body(entry.as_mut_ptr()) };
+            unsafe {
+                (*event).timestamp_ns = bpf_ktime_get_ns();
+                (*event).reason = reason;
+                (*event).protocol = ctx.read_at(layout.protocol as usize).unwrap_or(0);
+                (*event).ifindex = skb_ifindex(skb);
+                (*event).sample_rate = sample_rate;
+                (*event).src_ip = src_ip;
+                (*event).dst_ip = dst_ip;
+                (*event).src_port = src_port;
+                (*event).dst_port = dst_port;
+                (*event).len = skb_len(skb);
+                (*event)._pad2 = 0;
+                let p = packet.unwrap_or(PacketTuple::EMPTY);
+                (*event).ip_protocol = p.protocol;
+                (*event).src_addr = p.src_addr;
+                (*event).dst_addr = p.dst_addr;
+                (*event).packet_src_port = p.src_port;
+                (*event).packet_dst_port = p.dst_port;
+                // IDs are stored plus one so a zeroed event means "no stack"
+                (*event).stack_id = match DROP_STACKS.get_stackid(ctx, BPF_F_REUSE_STACKID) {
+                    Ok(id) => id as u32 + 1,
+                    Err(_) => 0,
+                };
+            }
+            entry.submit(0);
+        } else {
+            reserve_failed(event_kind::DROP);
+        }
+    }
+    
+    Ok(0)
+}
+
+/// Where this kernel's kfree_skb record keeps its fields; the built-in
+/// layout until the agent writes one
+#[inline(always)]
+fn kfree_skb_layout() -> KfreeSkbLayout {
+    match KFREE_SKB_LAYOUT.get(0) {
+        Some(layout) if layout.skbaddr != 0 => *layout,
+        _ => KfreeSkbLayout::DEFAULT,
+    }
+}
+
+/// Where this kernel's struct sk_buff keeps the fields read here; the
+/// built-in layout until the agent writes one
+#[inline(always)]
+fn skb_layout() -> SkbLayout {
+    match SKB_LAYOUT.get(0) {
+        Some(layout) if layout.head != 0 => *layout,
+        _ => SkbLayout::DEFAULT,
+    }
+}
+
+/// Where this kernel's struct nf_conn keeps the tuples and status; the
+/// built-in layout until the agent writes one
+#[inline(always)]
+fn nf_conn_layout() -> NfConnLayout {
+    match NF_CONN_LAYOUT.get(0) {
+        Some(layout) if layout.original != 0 => *layout,
+        _ => NfConnLayout::DEFAULT,
+    }
+}
+
+/// 4-tuple of the socket owning the dropped skb, all zero if it has none
+///
+/// Read with [`sock_tuple`] like the flow kprobes, so the result matches an
+/// outbound `FlowKey` and userspace can join drops against the FLOWS map.
+/// Only locally owned skbs (egress, socket receive queues) carry a socket;
+/// drops before socket lookup stay unattributed.
+#[inline(always)]
+fn skb_socket_tuple(skb: *const u8) -> (u32, u32, u16, u16) {
+    if skb.is_null() {
+        return (0, 0, 0, 0);
+    }
+    let sk = skb_layout().sk as usize;
+    match unsafe { bpf_probe_read_kernel(skb.add(sk) as *const *const u8) } {
+        Ok(sk) => sock_tuple(sk),
+        Err(_) => (0, 0, 0, 0),
+    }
+}
+
+/// Local-first 4-tuple of an IPv4 socket, all zero for a null pointer
+///
+/// Reads `struct sock_common`, whose leading fields have the same layout on
+/// every kernel:
+///   __be32 skc_daddr;            // offset 0
+///   __be32 skc_rcv_saddr;        // offset 4
+///   unsigned int skc_hash;       // offset 8
+///   __be16 skc_dport;            // offset 12
+///   __u16 skc_num;               // offset 14 (host byte order)
+///
+/// Addresses come back as `u32::from_be_bytes` of the octets and ports in
+/// host byte order, the encoding of `FlowKey` and the trace filters.
+#[inline(always)]
+fn sock_tuple(sk: *const u8) -> (u32, u32, u16, u16) {
+    if sk.is_null() {
+        return (0, 0, 0, 0);
+    }
+    unsafe {
+        let dst_ip: [u8; 4] = bpf_probe_read_kernel(sk as *const [u8; 4]).unwrap_or([0; 4]);
+        let src_ip: [u8; 4] = bpf_probe_read_kernel(sk.add(4) as *const [u8; 4]).unwrap_or([0; 4]);
+        let dst_port: u16 = bpf_probe_read_kernel(sk.add(12) as *const u16).unwrap_or(0);
+        let src_port: u16 = bpf_probe_read_kernel(sk.add(14) as *const u16).unwrap_or(0);
+        (u32::from_be_bytes(src_ip), u32::from_be_bytes(dst_ip), src_port, u16::from_be(dst_port))
+    }
+}
+
+/// Addresses, ports and protocol from a dropped packet's own headers
+#[derive(Clone, Copy)]
+struct PacketTuple {
+    src_addr: Addr128,
+    dst_addr: Addr128,
+    /// Host byte order, 0 unless TCP or UDP
+    src_port: u16,
+    dst_port: u16,
+    protocol: u8,
+}
+
+impl PacketTuple {
+    const EMPTY: Self = Self { src_addr: [0; 16], dst_addr: [0; 16], src_port: 0, dst_port: 0, protocol: 0 };
+}
+
+/// 5-tuple from the IP and TCP/UDP headers of the dropped skb, None for
+/// non-IP packets or a network header that isn't set yet
+///
+/// Unlike [`skb_socket_tuple`] this works for drops before socket lookup,
+/// such as NO_SOCKET and netfilter drops on ingress. IPv6 extension headers
+/// aren't walked: the protocol is the first next header.
+#[inline(always)]
+fn skb_packet_tuple(skb: *const u8) -> Option<PacketTuple> {
+    if skb.is_null() {
+        return None;
+    }
+    // The network header offset counts from `head` and is ~0 when unset
+    let layout = skb_layout();
+    let head: *const u8 = read_kernel(unsafe { skb.add(layout.head as usize) })?;
+    let network: u16 = read_kernel(unsafe { skb.add(layout.network_header as usize) })?;
+    if head.is_null() || network == u16::MAX {
+        return None;
+    }
+    let l3 = unsafe { head.add(network as usize) };
+    let at = |offset: usize| unsafe { l3.add(offset) };
+
+    // The version nibble also rejects offsets that don't match this kernel
+    let ver_ihl: u8 = read_kernel(at(0))?;
+    let (src_addr, dst_addr, protocol, l4) = match ver_ihl >> 4 {
+        4 => {
+            let ihl = ((ver_ihl & 0x0f) as usize) * 4;
+            if ihl < 20 {
+                return None;
+            }
+            let src: [u8; 4] = read_kernel(at(12))?;
+            let dst: [u8; 4] = read_kernel(at(16))?;
+            (ipv4_mapped(src), ipv4_mapped(dst), read_kernel(at(9))?, ihl)
+        }
+        6 => (read_kernel(at(8))?, read_kernel(at(24))?, read_kernel(at(6))?, 40),
+        _ => return None,
+    };
+    let (src_port, dst_port) = match protocol {
+        ipproto::TCP | ipproto::UDP => {
+            let ports: [u8; 4] = read_kernel(at(l4)).unwrap_or([0; 4]);
+            (u16::from_be_bytes([ports[0], ports[1]]), u16::from_be_bytes([ports[2], ports[3]]))
+        }
+        _ => (0, 0),
+    };
+    Some(PacketTuple { src_addr, dst_addr, src_port, dst_port, protocol })
+}
+
+/// Read a `T` from kernel memory at `ptr`
+#[inline(always)]
+fn read_kernel<T>(ptr: *const u8) -> Option<T> {
+    unsafe { bpf_probe_read_kernel(ptr as *const T) }.ok()
+}
+
+/// Interface the skb is on, or arrived on once `dev` is cleared; 0 if
+/// neither can be read
+#[inline(always)]
+fn skb_ifindex(skb: *const u8) -> u32 {
+    if skb.is_null() {
+        return 0;
+    }
+    let layout = skb_layout();
+    let dev: *const u8 = read_kernel(unsafe { skb.add(layout.dev as usize) }).unwrap_or(core::ptr::null());
+    match dev_ifindex(dev) {
+        0 => read_kernel::<i32>(unsafe { skb.add(layout.skb_iif as usize) }).unwrap_or(0) as u32,
+        ifindex => ifindex,
     }
 }
 
-/// Process a packet and update counters
+/// Index of a `struct net_device`, 0 for a null pointer
 #[inline(always)]
-fn process_packet(ctx: &TcContext, direction: u32) -> Result<i32, ()> {
-    let len = ctx.len() as u64;
-
-    // Update counters
-    if let Some(counters) = COUNTERS.get_ptr_mut(direction) {
-        let counters = unsafe { &mut *counters };
-        if direction == 0 {
-            // Ingress
-            counters.rx_packets += 1;
-            counters.rx_bytes += len;
-        } else {
-            // Egress
-            counters.tx_packets += 1;
-            counters.tx_bytes += len;
-        }
+fn dev_ifindex(dev: *const u8) -> u32 {
+    if dev.is_null() {
+        return 0;
     }
+    read_kernel::<i32>(unsafe { dev.add(skb_layout().dev_ifindex as usize) }).unwrap_or(0) as u32
+}
 
-    // Check for large packets and emit event
-    if len > LARGE_PACKET_THRESHOLD as u64 {
-        emit_large_packet_event(ctx, len as u32)?;
+/// Length of the dropped skb in bytes, 0 if it can't be read
+#[inline(always)]
+fn skb_len(skb: *const u8) -> u32 {
+    if skb.is_null() {
+        return 0;
     }
+    read_kernel(unsafe { skb.add(skb_layout().len as usize) }).unwrap_or(0)
+}
 
-    // TC_ACT_PIPE = pass to next filter/continue
-    Ok(TC_ACT_PIPE)
+// =============================================================================
+// nf_hook_slow fexit (Phase 6.2: Netfilter Hook Tracing)
+// =============================================================================
+
+/// fexit on the netfilter slow path, which runs a hook's rules for a packet
+///
+/// Attaches to: fexit/nf_hook_slow (needs kernel BTF)
+///
+///   int nf_hook_slow(struct sk_buff *skb, struct nf_hook_state *state,
+///                    const struct nf_hook_entries *e, unsigned int s)
+///
+/// `struct nf_hook_state` starts with `u8 hook; u8 pf;`. The return value
+/// is the verdict: 1 when every rule accepted, 0 when a rule stole or
+/// queued the packet, and a negative errno when one dropped it. Accepted
+/// packets aren't recorded, since every packet passing a hook would be.
+#[fexit(function = "nf_hook_slow")]
+pub fn nf_hook_slow(ctx: FExitContext) -> u32 {
+    match try_nf_hook_slow(&ctx) {
+        Ok(ret) => ret,
+        Err(_) => 0,
+    }
 }
 
-/// Emit a large packet event to ring buffer
 #[inline(always)]
-fn emit_large_packet_event(_ctx: &TcContext, size: u32) -> Result<(), ()> {
-    // Try to reserve space in ring buffer
-    if let Some(mut entry) = EVENTS.reserve::<PacketEvent>(0) {
-        let event = entry.as_mut_ptr();
+fn try_nf_hook_slow(ctx: &FExitContext) -> Result<u32, ()> {
+    let state: *const u8 = unsafe { ctx.arg(1) };
+    let ret: i32 = unsafe { ctx.arg(4) };
+    let verdict = match ret {
+        1 => return Ok(0),
+        0 => NfVerdict::Stolen as u8,
+        _ => NfVerdict::Drop as u8,
+    };
+    if state.is_null() {
+        return Ok(0);
+    }
+    let hook: u8 = read_kernel(state).ok_or(())?;
+    let pf: u8 = read_kernel(unsafe { state.add(1) }).ok_or(())?;
+    // struct nf_hook_state: `struct net_device *in, *out` follow hook, pf
+    // and the padding, unchanged since 4.10
+    let device = |offset| dev_ifindex(read_kernel(unsafe { state.add(offset) }).unwrap_or(core::ptr::null()));
+    let (ifindex_in, ifindex_out) = (device(8), device(16));
+
+    let sample_rate = rate_limit(event_kind::NETFILTER);
+    if sample_rate == 0 {
+        return Ok(0);
+    }
+    if let Some(mut entry) = NF_EVENTS.reserve::<Envelope<NetfilterEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
         unsafe {
-            (*event).event_type = 1; // LargePacket
-            (*event).size = size;
-            
-            // Simple IPv4 parsing (assuming Ethernet header is 14 bytes)
-            // Offset 14+12=26 (Src IP), 14+16=30 (Dst IP)
-            // Note: In real world, need to check EthType and proper bounds
-            let src_offset = 14 + 12; // Eth(14) + IP_Offset(12)
-            let dst_offset = 14 + 16;
-            
-            // Default to 0 if we can't read
-            (*event).src_ip = _ctx.load(src_offset).unwrap_or(0);
-            (*event).dst_ip = _ctx.load(dst_offset).unwrap_or(0);
-            (*event).protocol = _ctx.load(14 + 9).unwrap_or(0); // Protocol at offset 9
-            
-            (*event)._pad = [0; 3];
+            (*event).timestamp_ns = bpf_ktime_get_ns();
+            (*event).hook = hook;
+            (*event).pf = pf;
+            (*event).verdict = verdict;
+            (*event).sample_rate = sample_rate;
+            (*event).ifindex_in = ifindex_in;
+            (*event).ifindex_out = ifindex_out;
         }
         entry.submit(0);
+    } else {
+        reserve_failed(event_kind::NETFILTER);
     }
-    Ok(())
+
+    Ok(0)
 }
 
 // =============================================================================
-// kfree_skb Tracepoint (Phase 6.1: Drop Reason Tracing)
+// tcp_retransmit_skb Tracepoint (Retransmission Tracing)
 // =============================================================================
 
-/// Tracepoint for kernel packet drops
-/// 
-/// Attaches to: tracepoint/skb/kfree_skb
-/// 
-/// Context format (Linux 5.17+):
+/// Tracepoint for TCP segments retransmitted by the local stack
+///
+/// Attaches to: tracepoint/tcp/tcp_retransmit_skb
+///
+/// Context format (Linux 4.16+), offsets past the 8-byte common header:
 ///   struct {
-///       void *skbaddr;           // offset 0
-///       void *location;          // offset 8
-///       unsigned short protocol; // offset 16
-///       enum skb_drop_reason reason; // offset 20 (Linux 5.17+)
+///       const void *skbaddr;     // offset 8
+///       const void *skaddr;      // offset 16
+///       int state;               // offset 24
+///       __u16 sport;             // offset 28 (host byte order)
+///       __u16 dport;             // offset 30
+///       __u16 family;            // offset 32
+///       __u8 saddr[4];           // offset 34
+///       __u8 daddr[4];           // offset 38
+///       __u8 saddr_v6[16];       // offset 42 (IPv4-mapped for IPv4 sockets)
+///       __u8 daddr_v6[16];       // offset 58
 ///   }
 #[tracepoint]
-pub fn kfree_skb(ctx: TracePointContext) -> u32 {
-    match try_kfree_skb(&ctx) {
+pub fn tcp_retransmit_skb(ctx: TracePointContext) -> u32 {
+    match try_tcp_retransmit_skb(&ctx) {
         Ok(ret) => ret,
         Err(_) => 0,
     }
 }
 
+/// Where this kernel's tcp_retransmit_skb record keeps its fields; the
+/// built-in layout until the agent writes one
 #[inline(always)]
-fn try_kfree_skb(ctx: &TracePointContext) -> Result<u32, ()> {
-    // Read drop reason from tracepoint context
-    // Note: Offset 20 is for Linux 5.17+ where sk_drop_reason is available
-    // On older kernels, this field doesn't exist and we'll get garbage/0
-    let reason: u32 = unsafe { ctx.read_at(20).unwrap_or(0) };
-    
-    // Only emit events for interesting drop reasons (not NOT_SPECIFIED=1)
-    // Reason 0 means we couldn't read it (older kernel)
-    if reason > 1 {
-        if let Some(mut entry) = DROP_EVENTS.reserve::<DropEvent>(0) {
-            let event = entry.as_mut_ptr();
-            unsafe {
-                (*event).timestamp_ns = bpf_ktime_get_ns();
-                (*event).reason = reason;
-                // Protocol is at offset 16 (unsigned short)
-                (*event).protocol = ctx.read_at(16).unwrap_or(0);
-                (*event).ifindex = 0; // TODO: Extract from skb if needed
-                (*event)._pad = 0;
-            }
-            entry.submit(0);
+fn tcp_retransmit_skb_layout() -> TcpRetransmitSkbLayout {
+    match TCP_RETRANSMIT_SKB_LAYOUT.get(0) {
+        Some(layout) if layout.state != 0 => *layout,
+        _ => TcpRetransmitSkbLayout::DEFAULT,
+    }
+}
+
+#[inline(always)]
+fn try_tcp_retransmit_skb(ctx: &TracePointContext) -> Result<u32, ()> {
+    const IPPROTO_TCP: u8 = 6;
+
+    let layout = tcp_retransmit_skb_layout();
+    let (state, src_port, dst_port, src_addr, dst_addr) = unsafe {
+        let state: i32 = ctx.read_at(layout.state as usize).map_err(|_| ())?;
+        let src_port: u16 = ctx.read_at(layout.sport as usize).map_err(|_| ())?;
+        let dst_port: u16 = ctx.read_at(layout.dport as usize).map_err(|_| ())?;
+        let src_addr: Addr128 = ctx.read_at(layout.saddr_v6 as usize).map_err(|_| ())?;
+        let dst_addr: Addr128 = ctx.read_at(layout.daddr_v6 as usize).map_err(|_| ())?;
+        (state, src_port, dst_port, src_addr, dst_addr)
+    };
+
+    // Filtered retransmits don't use up rate limit tokens
+    let ipv4 = |addr: &Addr128| mapped_ipv4(addr).map(u32::from_be_bytes).unwrap_or(0);
+    if !trace_admits(None, ipv4(&src_addr), ipv4(&dst_addr), Some((src_port, dst_port)), Some(IPPROTO_TCP)) {
+        return Ok(0);
+    }
+    let sample_rate = rate_limit(event_kind::RETRANSMIT);
+    if sample_rate == 0 {
+        return Ok(0);
+    }
+
+    if let Some(mut entry) = RETRANSMIT_EVENTS.reserve::<Envelope<RetransmitEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
+        unsafe {
+            (*event).timestamp_ns = bpf_ktime_get_ns();
+            (*event).src_addr = src_addr;
+            (*event).dst_addr = dst_addr;
+            (*event).src_port = src_port;
+            (*event).dst_port = dst_port;
+            (*event).state = state as u8;
+            (*event).sample_rate = sample_rate;
+            (*event)._pad = [0; 2];
         }
+        entry.submit(0);
+    } else {
+        reserve_failed(event_kind::RETRANSMIT);
     }
-    
     Ok(0)
 }
 
 // =============================================================================
-// nf_hook_slow Tracepoint (Phase 6.2: Netfilter Hook Tracing)
+// tcp_probe Tracepoint (RTT Measurement)
 // =============================================================================
 
-/// Tracepoint for netfilter slow path processing
-/// 
-/// Attaches to: tracepoint/netfilter/nf_hook_slow_start or similar
-/// 
-/// This captures when packets traverse netfilter hooks and their verdicts.
-/// Note: The exact tracepoint name and format varies by kernel version.
+/// Tracepoint for segments received on established TCP connections,
+/// recording the socket's smoothed RTT against the remote address
+///
+/// Attaches to: tracepoint/tcp/tcp_probe
+///
+/// Context format (kernels with the `family` field), offsets past the
+/// 8-byte common header:
+///   struct {
+///       __u8 saddr[28];          // offset 8 (sockaddr_in or sockaddr_in6)
+///       __u8 daddr[28];          // offset 36 (the remote end)
+///       __u16 sport;             // offset 64
+///       __u16 dport;             // offset 66
+///       __u16 family;            // offset 68
+///       ...
+///       __u32 srtt;              // offset 100 (microseconds)
+///   }
 #[tracepoint]
-pub fn nf_hook_slow(ctx: TracePointContext) -> u32 {
-    match try_nf_hook_slow(&ctx) {
+pub fn tcp_probe(ctx: TracePointContext) -> u32 {
+    match try_tcp_probe(&ctx) {
         Ok(ret) => ret,
         Err(_) => 0,
     }
 }
 
+/// Where this kernel's tcp_probe record keeps its fields; the built-in
+/// layout until the agent writes one
 #[inline(always)]
-fn try_nf_hook_slow(ctx: &TracePointContext) -> Result<u32, ()> {
-    // Read hook state from tracepoint context
-    // struct nf_hook_state layout (approximate, varies by kernel):
-    //   u8 hook;      // offset 0
-    //   u8 pf;        // offset 1
-    //   ... other fields
-    let hook: u8 = unsafe { ctx.read_at(0).unwrap_or(255) };
-    let pf: u8 = unsafe { ctx.read_at(1).unwrap_or(0) };
-    
-    // For nf_hook_slow_finish tracepoint, verdict is typically at a later offset
-    // For now, we'll record all hook invocations
-    let verdict: u8 = unsafe { ctx.read_at(8).unwrap_or(1) }; // Default ACCEPT=1
-    
-    // Only record DROP events or interesting hooks
-    if verdict == 0 || hook <= 4 { // NF_DROP or valid hook types
-        if let Some(mut entry) = NF_EVENTS.reserve::<NetfilterEvent>(0) {
-            let event = entry.as_mut_ptr();
-            unsafe {
-                (*event).timestamp_ns = bpf_ktime_get_ns();
-                (*event).hook = hook;
-                (*event).pf = pf;
-                (*event).verdict = verdict;
-                (*event)._pad = 0;
-                (*event).ifindex_in = 0;  // TODO: Extract from context
-                (*event).ifindex_out = 0; // TODO: Extract from context
-            }
-            entry.submit(0);
+fn tcp_probe_layout() -> TcpProbeLayout {
+    match TCP_PROBE_LAYOUT.get(0) {
+        Some(layout) if layout.daddr != 0 => *layout,
+        _ => TcpProbeLayout::DEFAULT,
+    }
+}
+
+#[inline(always)]
+fn try_tcp_probe(ctx: &TracePointContext) -> Result<u32, ()> {
+    const AF_INET: u16 = 2;
+    const AF_INET6: u16 = 10;
+    const BPF_NOEXIST: u64 = 1;
+
+    let layout = tcp_probe_layout();
+    let daddr = layout.daddr as usize;
+    let (family, srtt_us) = unsafe {
+        let family: u16 = ctx.read_at(daddr).map_err(|_| ())?;
+        let srtt_us: u32 = ctx.read_at(layout.srtt as usize).map_err(|_| ())?;
+        (family, srtt_us)
+    };
+    // No RTT sample yet on this connection
+    if srtt_us == 0 {
+        return Ok(0);
+    }
+
+    // sin_addr follows sin_family and sin_port; sin6_addr also sin6_flowinfo
+    let remote: Addr128 = match family {
+        AF_INET => ipv4_mapped(unsafe { ctx.read_at::<[u8; 4]>(daddr + 4) }.map_err(|_| ())?),
+        AF_INET6 => unsafe { ctx.read_at(daddr + 8) }.map_err(|_| ())?,
+        _ => return Ok(0),
+    };
+
+    match RTT_HISTOGRAMS.get_ptr_mut(&remote) {
+        Some(hist) => unsafe { (*hist).record(srtt_us as u64) },
+        None => {
+            let mut first = Log2Histogram::default();
+            first.record(srtt_us as u64);
+            let _ = RTT_HISTOGRAMS.insert(&remote, &first, BPF_NOEXIST);
         }
     }
-    
     Ok(0)
 }
 
@@ -257,19 +1661,8 @@ fn try_tcp_connect(ctx: &ProbeContext) -> Result<u32, ()> {
     let comm = bpf_get_current_comm().unwrap_or([0; 16]);
     
     // Read socket info from first argument (struct sock *sk)
-    // sock structure offsets vary by kernel, using common offsets:
-    //   __sk_common.skc_daddr (dest IP): offset ~4
-    //   __sk_common.skc_rcv_saddr (src IP): offset ~0  
-    //   __sk_common.skc_dport (dest port): offset ~12
-    //   __sk_common.skc_num (src port): offset ~14
     let sk: *const u8 = ctx.arg(0).ok_or(())?;
-    
-    // Read socket addresses (these offsets work for many kernels)
-    // For a production system, use BTF or vmlinux.h for proper offsets
-    let src_ip: u32 = unsafe { core::ptr::read_unaligned(sk.add(4) as *const u32) };
-    let dst_ip: u32 = unsafe { core::ptr::read_unaligned(sk.add(0) as *const u32) };
-    let dst_port: u16 = unsafe { core::ptr::read_unaligned(sk.add(12) as *const u16) };
-    let src_port: u16 = unsafe { core::ptr::read_unaligned(sk.add(14) as *const u16) };
+    let (src_ip, dst_ip, src_port, dst_port) = sock_tuple(sk);
     
     // Create flow key
     let key = FlowKey {
@@ -293,21 +1686,26 @@ fn try_tcp_connect(ctx: &ProbeContext) -> Result<u32, ()> {
         tx_packets: 0,
         state: 1, // ACTIVE
         direction: 1, // OUTBOUND
-        _pad: [0; 2],
+        app_protocol: app_proto::UNKNOWN,
+        _pad: 0,
     };
     
     // Insert into flow map
     let _ = FLOWS.insert(&key, &info, 0);
     
     // Emit flow event
-    if let Some(mut entry) = FLOW_EVENTS.reserve::<FlowEvent>(0) {
-        let event = entry.as_mut_ptr();
+    let sample_rate = rate_limit(event_kind::FLOW);
+    if sample_rate == 0 {
+        return Ok(0);
+    }
+    if let Some(mut entry) = FLOW_EVENTS.reserve::<Envelope<FlowEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
         unsafe {
             (*event).timestamp_ns = bpf_ktime_get_ns();
             (*event).event_type = 1; // NEW
             (*event).direction = 1; // OUTBOUND
             (*event).protocol = 6; // TCP
-            (*event)._pad = 0;
+            (*event).sample_rate = sample_rate;
             (*event).pid = pid;
             (*event).src_ip = src_ip;
             (*event).dst_ip = dst_ip;
@@ -316,18 +1714,21 @@ fn try_tcp_connect(ctx: &ProbeContext) -> Result<u32, ()> {
             (*event).comm = comm;
         }
         entry.submit(0);
+    } else {
+        reserve_failed(event_kind::FLOW);
     }
     
     Ok(0)
 }
 
-/// kprobe for inet_csk_accept - track inbound TCP connections
+/// kretprobe for inet_csk_accept - track inbound TCP connections
 /// 
 /// Attaches to: kretprobe/inet_csk_accept
 /// 
-/// This captures when a process accepts an incoming TCP connection.
-#[kprobe]
-pub fn inet_csk_accept(ctx: ProbeContext) -> u32 {
+/// This captures when a process accepts an incoming TCP connection. The
+/// accepted socket is the return value; the argument is the listener.
+#[kretprobe]
+pub fn inet_csk_accept(ctx: RetProbeContext) -> u32 {
     match try_inet_csk_accept(&ctx) {
         Ok(ret) => ret,
         Err(_) => 0,
@@ -335,7 +1736,7 @@ pub fn inet_csk_accept(ctx: ProbeContext) -> u32 {
 }
 
 #[inline(always)]
-fn try_inet_csk_accept(ctx: &ProbeContext) -> Result<u32, ()> {
+fn try_inet_csk_accept(ctx: &RetProbeContext) -> Result<u32, ()> {
     // Get PID/TGID
     let pid_tgid = bpf_get_current_pid_tgid();
     let pid = (pid_tgid >> 32) as u32;
@@ -344,14 +1745,12 @@ fn try_inet_csk_accept(ctx: &ProbeContext) -> Result<u32, ()> {
     // Get process name
     let comm = bpf_get_current_comm().unwrap_or([0; 16]);
     
-    // Read socket info from return value or first argument
-    let sk: *const u8 = ctx.arg(0).ok_or(())?;
-    
-    // Read socket addresses
-    let src_ip: u32 = unsafe { core::ptr::read_unaligned(sk.add(4) as *const u32) };
-    let dst_ip: u32 = unsafe { core::ptr::read_unaligned(sk.add(0) as *const u32) };
-    let dst_port: u16 = unsafe { core::ptr::read_unaligned(sk.add(12) as *const u16) };
-    let src_port: u16 = unsafe { core::ptr::read_unaligned(sk.add(14) as *const u16) };
+    // Read socket info from the return value (NULL if accept failed)
+    let sk: *const u8 = ctx.ret().ok_or(())?;
+    if sk.is_null() {
+        return Ok(0);
+    }
+    let (src_ip, dst_ip, src_port, dst_port) = sock_tuple(sk);
     
     // Create flow key (swap src/dst for inbound)
     let key = FlowKey {
@@ -375,21 +1774,26 @@ fn try_inet_csk_accept(ctx: &ProbeContext) -> Result<u32, ()> {
         tx_packets: 0,
         state: 1, // ACTIVE
         direction: 2, // INBOUND
-        _pad: [0; 2],
+        app_protocol: app_proto::UNKNOWN,
+        _pad: 0,
     };
     
     // Insert into flow map
     let _ = FLOWS.insert(&key, &info, 0);
     
     // Emit flow event
-    if let Some(mut entry) = FLOW_EVENTS.reserve::<FlowEvent>(0) {
-        let event = entry.as_mut_ptr();
+    let sample_rate = rate_limit(event_kind::FLOW);
+    if sample_rate == 0 {
+        return Ok(0);
+    }
+    if let Some(mut entry) = FLOW_EVENTS.reserve::<Envelope<FlowEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
         unsafe {
             (*event).timestamp_ns = bpf_ktime_get_ns();
             (*event).event_type = 1; // NEW
             (*event).direction = 2; // INBOUND
             (*event).protocol = 6; // TCP
-            (*event)._pad = 0;
+            (*event).sample_rate = sample_rate;
             (*event).pid = pid;
             (*event).src_ip = dst_ip;
             (*event).dst_ip = src_ip;
@@ -398,6 +1802,8 @@ fn try_inet_csk_accept(ctx: &ProbeContext) -> Result<u32, ()> {
             (*event).comm = comm;
         }
         entry.submit(0);
+    } else {
+        reserve_failed(event_kind::FLOW);
     }
     
     Ok(0)
@@ -425,11 +1831,7 @@ fn try_tcp_close(ctx: &ProbeContext) -> Result<u32, ()> {
     
     // Read socket info
     let sk: *const u8 = ctx.arg(0).ok_or(())?;
-    
-    let src_ip: u32 = unsafe { core::ptr::read_unaligned(sk.add(4) as *const u32) };
-    let dst_ip: u32 = unsafe { core::ptr::read_unaligned(sk.add(0) as *const u32) };
-    let dst_port: u16 = unsafe { core::ptr::read_unaligned(sk.add(12) as *const u16) };
-    let src_port: u16 = unsafe { core::ptr::read_unaligned(sk.add(14) as *const u16) };
+    let (src_ip, dst_ip, src_port, dst_port) = sock_tuple(sk);
     
     // Create flow key
     let key = FlowKey {
@@ -445,14 +1847,18 @@ fn try_tcp_close(ctx: &ProbeContext) -> Result<u32, ()> {
     let _ = FLOWS.remove(&key);
     
     // Emit close event
-    if let Some(mut entry) = FLOW_EVENTS.reserve::<FlowEvent>(0) {
-        let event = entry.as_mut_ptr();
+    let sample_rate = rate_limit(event_kind::FLOW);
+    if sample_rate == 0 {
+        return Ok(0);
+    }
+    if let Some(mut entry) = FLOW_EVENTS.reserve::<Envelope<FlowEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
         unsafe {
             (*event).timestamp_ns = bpf_ktime_get_ns();
             (*event).event_type = 3; // CLOSE
             (*event).direction = 0; // UNKNOWN
             (*event).protocol = 6;
-            (*event)._pad = 0;
+            (*event).sample_rate = sample_rate;
             (*event).pid = pid;
             (*event).src_ip = src_ip;
             (*event).dst_ip = dst_ip;
@@ -461,11 +1867,527 @@ fn try_tcp_close(ctx: &ProbeContext) -> Result<u32, ()> {
             (*event).comm = comm;
         }
         entry.submit(0);
+    } else {
+        reserve_failed(event_kind::FLOW);
     }
     
     Ok(0)
 }
 
+/// kprobe for tcp_sendmsg - count bytes the owning process sends
+///
+/// Attaches to: kprobe/tcp_sendmsg (sk, msg, size)
+///
+/// `size` is what the process asked to send; a short write counts in full.
+#[kprobe]
+pub fn tcp_sendmsg(ctx: ProbeContext) -> u32 {
+    let (Some(sk), Some(size)) = (ctx.arg::<*const u8>(0), ctx.arg::<u64>(2)) else {
+        return 0;
+    };
+    count_flow_bytes(sk, size, true);
+    0
+}
+
+/// kprobe for tcp_cleanup_rbuf - count bytes the owning process reads
+///
+/// Attaches to: kprobe/tcp_cleanup_rbuf (sk, copied)
+///
+/// Called once per recvmsg with the bytes copied to userspace; `copied`
+/// is negative on error.
+#[kprobe]
+pub fn tcp_cleanup_rbuf(ctx: ProbeContext) -> u32 {
+    let (Some(sk), Some(copied)) = (ctx.arg::<*const u8>(0), ctx.arg::<i32>(1)) else {
+        return 0;
+    };
+    if copied > 0 {
+        count_flow_bytes(sk, copied as u64, false);
+    }
+    0
+}
+
+/// Add one transfer to the socket's FLOWS entry
+///
+/// Outbound flows are keyed local-first, inbound ones remote-first, so both
+/// orientations are tried. Sockets opened before the programs loaded have
+/// no entry and aren't counted. Counters are added atomically since the
+/// socket may be used from several CPUs.
+#[inline(always)]
+fn count_flow_bytes(sk: *const u8, bytes: u64, tx: bool) {
+    use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+    let (src_ip, dst_ip, src_port, dst_port) = sock_tuple(sk);
+    if src_port == 0 && dst_port == 0 {
+        return;
+    }
+    let outbound = FlowKey { src_ip, dst_ip, src_port, dst_port, protocol: ipproto::TCP, _pad: [0; 3] };
+    let inbound = FlowKey { src_ip: dst_ip, dst_ip: src_ip, src_port: dst_port, dst_port: src_port, ..outbound };
+    let Some(info) = FLOWS.get_ptr_mut(&outbound).or_else(|| FLOWS.get_ptr_mut(&inbound)) else {
+        return;
+    };
+    unsafe {
+        if tx {
+            AtomicU64::from_ptr(&mut (*info).tx_bytes).fetch_add(bytes, Ordering::Relaxed);
+            AtomicU32::from_ptr(&mut (*info).tx_packets).fetch_add(1, Ordering::Relaxed);
+        } else {
+            AtomicU64::from_ptr(&mut (*info).rx_bytes).fetch_add(bytes, Ordering::Relaxed);
+            AtomicU32::from_ptr(&mut (*info).rx_packets).fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// =============================================================================
+// inet_sock_set_state Tracepoint (TCP Connection Lifecycle)
+// =============================================================================
+
+/// Tracepoint for TCP state changes: counts connection churn and reports
+/// opens and closes to FLOWS and FLOW_EVENTS
+///
+/// Attaches to: tracepoint/sock/inet_sock_set_state (4.16+)
+///
+/// Context format (stable since 4.16), offsets past the 8-byte common
+/// header:
+///   struct {
+///       const void *skaddr;      // offset 8
+///       int oldstate;            // offset 16
+///       int newstate;            // offset 20
+///       __u16 sport;             // offset 24 (host byte order)
+///       __u16 dport;             // offset 26
+///       __u16 family;            // offset 28
+///       __u16 protocol;          // offset 30
+///       __u8 saddr[4];           // offset 32
+///       __u8 daddr[4];           // offset 36
+///       __u8 saddr_v6[16];       // offset 40
+///       __u8 daddr_v6[16];       // offset 56
+///   }
+///
+/// Active opens (CLOSE -> SYN_SENT) run in the connecting process, so they
+/// carry its PID. Passive opens (SYN_RECV -> ESTABLISHED) complete in
+/// softirq context: the flow is recorded without a process and the NEW
+/// event is left to the inet_csk_accept kprobe, which runs in the
+/// accepting one. Closes take the PID recorded at open. FLOWS only holds
+/// IPv4 connections; churn counts both families.
+#[tracepoint]
+pub fn inet_sock_set_state(ctx: TracePointContext) -> u32 {
+    match try_inet_sock_set_state(&ctx) {
+        Ok(ret) => ret,
+        Err(_) => 0,
+    }
+}
+
+/// Where this kernel's inet_sock_set_state record keeps its fields; the
+/// built-in layout until the agent writes one
+#[inline(always)]
+fn inet_sock_set_state_layout() -> InetSockSetStateLayout {
+    match INET_SOCK_SET_STATE_LAYOUT.get(0) {
+        Some(layout) if layout.oldstate != 0 => *layout,
+        _ => InetSockSetStateLayout::DEFAULT,
+    }
+}
+
+#[inline(always)]
+fn try_inet_sock_set_state(ctx: &TracePointContext) -> Result<u32, ()> {
+    const TCP_ESTABLISHED: i32 = 1;
+    const TCP_SYN_SENT: i32 = 2;
+    const TCP_SYN_RECV: i32 = 3;
+    const TCP_CLOSE: i32 = 7;
+    const TCP_LISTEN: i32 = 10;
+    const AF_INET: u16 = 2;
+    const BPF_NOEXIST: u64 = 1;
+
+    let layout = inet_sock_set_state_layout();
+    let (oldstate, newstate, protocol) = unsafe {
+        let oldstate: i32 = ctx.read_at(layout.oldstate as usize).map_err(|_| ())?;
+        let newstate: i32 = ctx.read_at(layout.newstate as usize).map_err(|_| ())?;
+        let protocol: u16 = ctx.read_at(layout.protocol as usize).map_err(|_| ())?;
+        (oldstate, newstate, protocol)
+    };
+    if protocol != ipproto::TCP as u16 {
+        return Ok(0);
+    }
+
+    // Listeners and sockets that never left CLOSE aren't connections
+    let transition = match (oldstate, newstate) {
+        (TCP_CLOSE, TCP_SYN_SENT) => Transition::ActiveOpen,
+        (TCP_SYN_RECV, TCP_ESTABLISHED) => Transition::PassiveOpen,
+        (TCP_SYN_SENT | TCP_SYN_RECV, TCP_CLOSE) => Transition::Failed,
+        (TCP_CLOSE | TCP_LISTEN, TCP_CLOSE) => return Ok(0),
+        (_, TCP_CLOSE) => Transition::Close,
+        _ => return Ok(0),
+    };
+    if let Some(churn) = CONN_CHURN.get_ptr_mut(0) {
+        let churn = unsafe { &mut *churn };
+        match transition {
+            Transition::ActiveOpen => churn.active_opens += 1,
+            Transition::PassiveOpen => churn.passive_opens += 1,
+            Transition::Close => churn.closes += 1,
+            Transition::Failed => churn.failed += 1,
+        }
+    }
+
+    let (family, src_port, dst_port, src_ip, dst_ip) = unsafe {
+        let family: u16 = ctx.read_at(layout.family as usize).map_err(|_| ())?;
+        let src_port: u16 = ctx.read_at(layout.sport as usize).map_err(|_| ())?;
+        let dst_port: u16 = ctx.read_at(layout.dport as usize).map_err(|_| ())?;
+        let src_ip: [u8; 4] = ctx.read_at(layout.saddr as usize).map_err(|_| ())?;
+        let dst_ip: [u8; 4] = ctx.read_at(layout.daddr as usize).map_err(|_| ())?;
+        (family, src_port, dst_port, u32::from_be_bytes(src_ip), u32::from_be_bytes(dst_ip))
+    };
+    if family != AF_INET {
+        return Ok(0);
+    }
+
+    // Local end first; inbound flows are keyed remote -> local
+    let outbound = FlowKey { src_ip, dst_ip, src_port, dst_port, protocol: ipproto::TCP, _pad: [0; 3] };
+    let inbound = FlowKey { src_ip: dst_ip, dst_ip: src_ip, src_port: dst_port, dst_port: src_port, ..outbound };
+
+    match transition {
+        Transition::ActiveOpen => {
+            let pid_tgid = bpf_get_current_pid_tgid();
+            let info = FlowInfo {
+                pid: (pid_tgid >> 32) as u32,
+                tgid: pid_tgid as u32,
+                comm: bpf_get_current_comm().unwrap_or([0; 16]),
+                start_time_ns: unsafe { bpf_ktime_get_ns() },
+                state: 1,     // ACTIVE
+                direction: 1, // OUTBOUND
+                ..Default::default()
+            };
+            let _ = FLOWS.insert(&outbound, &info, 0);
+            emit_flow_event(1, &outbound, &info); // NEW
+        }
+        Transition::PassiveOpen => {
+            let info = FlowInfo {
+                start_time_ns: unsafe { bpf_ktime_get_ns() },
+                state: 1,     // ACTIVE
+                direction: 2, // INBOUND
+                ..Default::default()
+            };
+            let _ = FLOWS.insert(&inbound, &info, BPF_NOEXIST);
+        }
+        Transition::Close | Transition::Failed => {
+            let (key, info) = match unsafe { FLOWS.get(&outbound) } {
+                Some(info) => (outbound, *info),
+                None => match unsafe { FLOWS.get(&inbound) } {
+                    Some(info) => (inbound, *info),
+                    // Opened before the agent loaded: direction unknown
+                    None => (outbound, FlowInfo::default()),
+                },
+            };
+            let _ = FLOWS.remove(&key);
+            emit_flow_event(3, &key, &info); // CLOSE
+        }
+    }
+    Ok(0)
+}
+
+/// TCP state change counted in CONN_CHURN
+#[derive(Clone, Copy)]
+enum Transition {
+    ActiveOpen,
+    PassiveOpen,
+    Close,
+    Failed,
+}
+
+/// Send a FlowEvent for `key`, attributed to the process and direction in
+/// `info`
+#[inline(always)]
+fn emit_flow_event(event_type: u8, key: &FlowKey, info: &FlowInfo) {
+    let sample_rate = rate_limit(event_kind::FLOW);
+    if sample_rate == 0 {
+        return;
+    }
+    if let Some(mut entry) = FLOW_EVENTS.reserve::<Envelope<FlowEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
+        unsafe {
+            (*event).timestamp_ns = bpf_ktime_get_ns();
+            (*event).event_type = event_type;
+            (*event).direction = info.direction;
+            (*event).protocol = key.protocol;
+            (*event).sample_rate = sample_rate;
+            (*event).pid = info.pid;
+            (*event).src_ip = key.src_ip;
+            (*event).dst_ip = key.dst_ip;
+            (*event).src_port = key.src_port;
+            (*event).dst_port = key.dst_port;
+            (*event).comm = info.comm;
+        }
+        entry.submit(0);
+    } else {
+        reserve_failed(event_kind::FLOW);
+    }
+}
+
+// =============================================================================
+// cgroup/skb programs (per-container traffic accounting)
+// =============================================================================
+
+/// cgroup/skb program for packets delivered to sockets in the cgroup
+///
+/// Attached by `EbpfManager::attach_cgroup`. The skb's cgroup is that of
+/// the owning socket, so attaching at a parent (or the root) counts every
+/// descendant cgroup under its own ID. Never drops.
+#[cgroup_skb]
+pub fn cgroup_skb_ingress(ctx: SkBuffContext) -> i32 {
+    count_cgroup(&ctx, 0);
+    1
+}
+
+/// cgroup/skb program for packets sent by sockets in the cgroup
+#[cgroup_skb]
+pub fn cgroup_skb_egress(ctx: SkBuffContext) -> i32 {
+    count_cgroup(&ctx, 1);
+    1
+}
+
+/// Add the packet to its cgroup in CGROUP_COUNTERS (direction 0 = rx)
+#[inline(always)]
+fn count_cgroup(ctx: &SkBuffContext, direction: u32) {
+    let cgroup_id = unsafe { bpf_skb_cgroup_id(ctx.skb.skb) };
+    if cgroup_id == 0 {
+        return;
+    }
+    let len = ctx.len() as u64;
+    match CGROUP_COUNTERS.get_ptr_mut(&cgroup_id) {
+        Some(counters) => {
+            let counters = unsafe { &mut *counters };
+            if direction == 0 {
+                counters.rx_packets += 1;
+                counters.rx_bytes += len;
+            } else {
+                counters.tx_packets += 1;
+                counters.tx_bytes += len;
+            }
+        }
+        None => {
+            let first = if direction == 0 {
+                CgroupCounters { rx_packets: 1, rx_bytes: len, ..Default::default() }
+            } else {
+                CgroupCounters { tx_packets: 1, tx_bytes: len, ..Default::default() }
+            };
+            let _ = CGROUP_COUNTERS.insert(&cgroup_id, &first, 0);
+        }
+    }
+}
+
+// =============================================================================
+// cgroup/sock and egress programs (process attribution)
+// =============================================================================
+
+/// cgroup/sock_create program: remember which process created each socket
+///
+/// Attached by `EbpfManager::attach_flow_attribution`. Socket creation
+/// always runs in the creating process, unlike the packets the socket
+/// sends later, so this is where the PID is reliable. Never denies.
+#[cgroup_sock(sock_create)]
+pub fn sock_create(ctx: SockContext) -> i32 {
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let owner = SockOwner {
+        pid: (pid_tgid >> 32) as u32,
+        tgid: pid_tgid as u32,
+        comm: bpf_get_current_comm().unwrap_or([0; 16]),
+    };
+    let cookie = unsafe { bpf_get_socket_cookie(ctx.sock as *mut core::ffi::c_void) };
+    let _ = SOCK_OWNERS.insert(&cookie, &owner, 0);
+    1
+}
+
+/// cgroup/skb egress program: attribute IPv4 TCP/UDP packets to the
+/// process that created their socket
+///
+/// UDP flows, which no kprobe tracks, are added to FLOWS on their first
+/// packet and their sends counted, and marked QUIC when they carry it to
+/// port 443; TCP flows recorded without a process
+/// (passive opens completed in softirq) get one. Sockets created before
+/// the program was attached have no owner and are skipped. Never drops.
+#[cgroup_skb]
+pub fn flow_attribution_egress(ctx: SkBuffContext) -> i32 {
+    let _ = attribute_egress(&ctx);
+    1
+}
+
+#[inline(always)]
+fn attribute_egress(ctx: &SkBuffContext) -> Result<(), ()> {
+    use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+    const BPF_NOEXIST: u64 = 1;
+
+    let cookie = unsafe { bpf_get_socket_cookie(ctx.skb.skb as *mut core::ffi::c_void) };
+    let owner = unsafe { SOCK_OWNERS.get(&cookie) }.ok_or(())?;
+
+    // cgroup/skb data starts at the network header
+    let version_ihl: u8 = ctx.load(0).map_err(|_| ())?;
+    if version_ihl >> 4 != 4 {
+        return Ok(());
+    }
+    let protocol: u8 = ctx.load(9).map_err(|_| ())?;
+    if protocol != ipproto::TCP && protocol != ipproto::UDP {
+        return Ok(());
+    }
+    let l4 = ((version_ihl & 0x0f) as usize) * 4;
+    let src: [u8; 4] = ctx.load(12).map_err(|_| ())?;
+    let dst: [u8; 4] = ctx.load(16).map_err(|_| ())?;
+    let src_port: u16 = ctx.load(l4).map_err(|_| ())?;
+    let dst_port: u16 = ctx.load(l4 + 2).map_err(|_| ())?;
+    let key = FlowKey {
+        src_ip: u32::from_be_bytes(src),
+        dst_ip: u32::from_be_bytes(dst),
+        src_port: u16::from_be(src_port),
+        dst_port: u16::from_be(dst_port),
+        protocol,
+        _pad: [0; 3],
+    };
+    // Any QUIC datagram marks the flow, not just the first one sent
+    let app_protocol = if protocol == ipproto::UDP
+        && key.dst_port == QUIC_PORT
+        && quic_packet(|offset| ctx.load(l4 + 8 + offset).ok())
+    {
+        app_proto::QUIC
+    } else {
+        app_proto::UNKNOWN
+    };
+
+    match FLOWS.get_ptr_mut(&key) {
+        Some(info) => unsafe {
+            if (*info).pid == 0 {
+                (*info).pid = owner.pid;
+                (*info).tgid = owner.tgid;
+                (*info).comm = owner.comm;
+            }
+            if app_protocol != app_proto::UNKNOWN {
+                (*info).app_protocol = app_protocol;
+            }
+            if protocol == ipproto::UDP {
+                AtomicU64::from_ptr(&mut (*info).tx_bytes).fetch_add(ctx.len() as u64, Ordering::Relaxed);
+                AtomicU32::from_ptr(&mut (*info).tx_packets).fetch_add(1, Ordering::Relaxed);
+            }
+        },
+        None if protocol == ipproto::UDP => {
+            let info = FlowInfo {
+                pid: owner.pid,
+                tgid: owner.tgid,
+                comm: owner.comm,
+                start_time_ns: unsafe { bpf_ktime_get_ns() },
+                tx_bytes: ctx.len() as u64,
+                tx_packets: 1,
+                state: 1,     // ACTIVE
+                direction: 1, // OUTBOUND
+                app_protocol,
+                ..Default::default()
+            };
+            // Another CPU may have inserted it meanwhile; it reports the flow
+            if FLOWS.insert(&key, &info, BPF_NOEXIST).is_ok() {
+                emit_flow_event(1, &key, &info); // NEW
+            }
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Conntrack kprobes (connection tracking lifecycle)
+// =============================================================================
+
+/// kprobe for __nf_conntrack_confirm - a new conntrack entry is inserted
+///
+/// Attaches to: kprobe/__nf_conntrack_confirm
+///
+/// Called once per connection, when its first packet leaves the last
+/// netfilter hook; the entry's tuples are final by then.
+#[kprobe]
+pub fn nf_conntrack_confirm(ctx: ProbeContext) -> u32 {
+    match try_nf_conntrack_confirm(&ctx) {
+        Ok(ret) => ret,
+        Err(_) => 0,
+    }
+}
+
+#[inline(always)]
+fn try_nf_conntrack_confirm(ctx: &ProbeContext) -> Result<u32, ()> {
+    // The low 3 bits of sk_buff::_nfct hold ctinfo
+    const NFCT_PTRMASK: u64 = !7;
+
+    let skb: *const u8 = ctx.arg(0).ok_or(())?;
+    let nfct: u64 = read_kernel(unsafe { skb.add(skb_layout().nfct as usize) }).ok_or(())?;
+    emit_conntrack((nfct & NFCT_PTRMASK) as *const u8, ct_event::NEW)
+}
+
+/// kprobe for nf_ct_delete - a conntrack entry is removed
+///
+/// Attaches to: kprobe/nf_ct_delete
+///
+/// Covers timeouts (garbage collection), TCP teardown and flushes.
+#[kprobe]
+pub fn nf_ct_delete(ctx: ProbeContext) -> u32 {
+    match try_nf_ct_delete(&ctx) {
+        Ok(ret) => ret,
+        Err(_) => 0,
+    }
+}
+
+#[inline(always)]
+fn try_nf_ct_delete(ctx: &ProbeContext) -> Result<u32, ()> {
+    let ct: *const u8 = ctx.arg(0).ok_or(())?;
+    emit_conntrack(ct, ct_event::DESTROY)
+}
+
+/// Copy the tuples and status of `struct nf_conn` at `ct` into CT_EVENTS
+///
+/// The tuples and status are found through [`nf_conn_layout`]. Within a
+/// tuple, which is the same on every kernel: src.u3 (+0), src.u.all (+16),
+/// src.l3num (+18), dst.u3 (+20), dst.u.all (+36), dst.protonum (+38).
+#[inline(always)]
+fn emit_conntrack(ct: *const u8, event_type: u8) -> Result<u32, ()> {
+    const NFPROTO_IPV4: u16 = 2;
+    const NFPROTO_IPV6: u16 = 10;
+
+    if ct.is_null() {
+        return Ok(0);
+    }
+    let layout = nf_conn_layout();
+    let (original, reply) = (layout.original as usize, layout.reply as usize);
+    let at = |offset: usize| unsafe { ct.add(offset) };
+    let l3num: u16 = read_kernel(at(original + 18)).ok_or(())?;
+    if l3num != NFPROTO_IPV4 && l3num != NFPROTO_IPV6 {
+        return Ok(0);
+    }
+    let addr = |offset: usize| -> Option<Addr128> {
+        match l3num {
+            NFPROTO_IPV4 => Some(ipv4_mapped(read_kernel(at(offset))?)),
+            _ => read_kernel(at(offset)),
+        }
+    };
+    let port = |offset: usize| read_kernel::<u16>(at(offset)).map(u16::from_be).unwrap_or(0);
+
+    let sample_rate = rate_limit(event_kind::CONNTRACK);
+    if sample_rate == 0 {
+        return Ok(0);
+    }
+    if let Some(mut entry) = CT_EVENTS.reserve::<Envelope<ConntrackEvent>>(0) {
+        let event = unsafe { event_body(entry.as_mut_ptr()) };
+        unsafe {
+            (*event).timestamp_ns = bpf_ktime_get_ns();
+            (*event).src_addr = addr(original).unwrap_or([0; 16]);
+            (*event).dst_addr = addr(original + 20).unwrap_or([0; 16]);
+            (*event).reply_src_addr = addr(reply).unwrap_or([0; 16]);
+            (*event).reply_dst_addr = addr(reply + 20).unwrap_or([0; 16]);
+            (*event).src_port = port(original + 16);
+            (*event).dst_port = port(original + 36);
+            (*event).reply_src_port = port(reply + 16);
+            (*event).reply_dst_port = port(reply + 36);
+            (*event).status = read_kernel::<u64>(at(layout.status as usize)).unwrap_or(0) as u32;
+            (*event).protocol = read_kernel(at(original + 38)).unwrap_or(0);
+            (*event).event_type = event_type;
+            (*event).sample_rate = sample_rate;
+            (*event)._pad = 0;
+        }
+        entry.submit(0);
+    } else {
+        reserve_failed(event_kind::CONNTRACK);
+    }
+    Ok(0)
+}
+
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
     unsafe { core::hint::unreachable_unchecked() }
@@ -0,0 +1,389 @@
+//! Local REST API
+//!
+//! Opt-in listener (`api_listen`) serving read-only JSON for a dashboard
+//! page or Grafana:
+//!
+//! - `GET /api/v1/status`: daemon state
+//! - `GET /api/v1/counters`: interface packet counters
+//! - `GET /api/v1/flows`: active flows
+//! - `GET /api/v1/drops`: kernel drop counter, ring losses and the last
+//!   pipeline window
+//! - `GET /api/v1/events?follow=1`: Server-Sent Events, one `data:` line of
+//!   [`StreamRecord`](crate::control::StreamRecord) JSON per event or gap, until the client disconnects;
+//!   `kinds=drop,rst` limits the event kinds
+//! - `/api/v1/grafana/...`: JSON and Infinity datasource endpoints, see
+//!   [`grafana`](crate::grafana)
+//!
+//! When `api_token` is set, requests must carry `Authorization: Bearer
+//! <token>`, or `?token=<token>` for EventSource, which can't set headers.
+//! Without a token the API only binds loopback addresses.
+
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{debug, info, warn};
+
+use crate::control::ControlState;
+use crate::ebpf::{comm_to_string, flow_direction_str, format_ip, FlowInfo, FlowKey};
+use crate::http::{http_response, Request};
+use crate::pipeline::RingKind;
+
+/// Largest request head accepted
+const MAX_REQUEST: usize = 8192;
+
+/// Largest request body accepted (Grafana queries)
+const MAX_BODY: usize = 64 * 1024;
+
+const GRAFANA_PREFIX: &str = "/api/v1/grafana";
+
+/// Interval of SSE comments that keep proxies from closing an idle stream
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// An active flow as `/api/v1/flows` returns it
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowRecord {
+    pub src_ip: String,
+    pub dst_ip: String,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub protocol: u8,
+    pub pid: u32,
+    pub comm: String,
+    /// "IN" or "OUT"
+    pub direction: &'static str,
+    pub state: u8,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u32,
+    pub tx_packets: u32,
+    pub start_time_ns: u64,
+    /// Server name from the TLS ClientHello, for outbound HTTPS flows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+    /// Application protocol recognized on the flow ("quic")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_protocol: Option<&'static str>,
+}
+
+impl FlowRecord {
+    pub fn new(key: &FlowKey, info: &FlowInfo) -> Self {
+        Self {
+            src_ip: format_ip(key.src_ip),
+            dst_ip: format_ip(key.dst_ip),
+            src_port: key.src_port,
+            dst_port: key.dst_port,
+            protocol: key.protocol,
+            pid: info.pid,
+            comm: comm_to_string(&info.comm),
+            direction: flow_direction_str(info.direction),
+            state: info.state,
+            rx_bytes: info.rx_bytes,
+            tx_bytes: info.tx_bytes,
+            rx_packets: info.rx_packets,
+            tx_packets: info.tx_packets,
+            start_time_ns: info.start_time_ns,
+            server_name: None,
+            app_protocol: sennet_common::app_proto_str(info.app_protocol),
+        }
+    }
+}
+
+/// Serve the API on `addr` until the task is aborted
+pub async fn serve(addr: String, token: Option<String>, state: Arc<ControlState>) {
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to bind REST API on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("Serving REST API on http://{}/api/v1", addr);
+    let token: Option<Arc<str>> = token.map(Into::into);
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                debug!("REST API accept failed: {}", e);
+                continue;
+            }
+        };
+        tokio::spawn(handle_connection(stream, token.clone(), state.clone()));
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, token: Option<Arc<str>>, state: Arc<ControlState>) {
+    let head = match tokio::time::timeout(Duration::from_secs(5), read_head(&mut stream)).await {
+        Ok(Ok(head)) => head,
+        _ => return,
+    };
+    let Some(request) = Request::parse(&head) else {
+        let response = http_response("400 Bad Request", "text/plain", "bad request\n");
+        let _ = stream.write_all(response.as_bytes()).await;
+        return;
+    };
+
+    let response = match authorize(&request, token.as_deref()) {
+        Err(status) => error_response(status, "missing or invalid token"),
+        Ok(()) if request.path.starts_with(GRAFANA_PREFIX) => match read_body(&mut stream, &head, &request).await {
+            Ok(body) => grafana(&request, body, state).await,
+            Err(status) => error_response(status, "unreadable request body"),
+        },
+        Ok(()) if request.method != "GET" => error_response("405 Method Not Allowed", "method not allowed"),
+        Ok(()) if request.path == "/api/v1/events" => {
+            if request.param("follow").as_deref() != Some("1") {
+                error_response("400 Bad Request", "events is a stream: use /api/v1/events?follow=1")
+            } else {
+                match parse_kinds(request.param("kinds").as_deref()) {
+                    Ok(kinds) => return stream_events(stream, &state, kinds).await,
+                    Err(e) => error_response("400 Bad Request", &e),
+                }
+            }
+        }
+        Ok(()) => respond(request.path, state).await,
+    };
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Read until the end of the request head
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    Ok(head)
+}
+
+/// Read the request body: what `head` already holds past the blank line,
+/// then the rest up to `Content-Length`. Err holds the status to answer with
+async fn read_body(stream: &mut TcpStream, head: &[u8], request: &Request<'_>) -> Result<Vec<u8>, &'static str> {
+    let length = request.content_length().ok_or("400 Bad Request")?;
+    if length > MAX_BODY {
+        return Err("413 Payload Too Large");
+    }
+    let start = head.windows(4).position(|w| w == b"\r\n\r\n").map_or(head.len(), |end| end + 4);
+    let mut body = head[start..].to_vec();
+    body.truncate(length);
+    let mut buf = [0u8; 4096];
+    while body.len() < length {
+        let n = match tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await {
+            Ok(Ok(n)) if n > 0 => n,
+            Ok(_) => return Err("400 Bad Request"),
+            Err(_) => return Err("408 Request Timeout"),
+        };
+        body.extend_from_slice(&buf[..n.min(length - body.len())]);
+    }
+    Ok(body)
+}
+
+/// Check the request's token; Err holds the status to answer with
+fn authorize(request: &Request, token: Option<&str>) -> Result<(), &'static str> {
+    let Some(token) = token else {
+        return Ok(());
+    };
+    let presented = request
+        .header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string)
+        .or_else(|| request.param("token"));
+    match presented {
+        Some(presented) if constant_time_eq(presented.trim().as_bytes(), token.as_bytes()) => Ok(()),
+        _ => Err("401 Unauthorized"),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// `kinds` query parameter: comma-separated event kinds (absent = all)
+fn parse_kinds(kinds: Option<&str>) -> Result<Vec<RingKind>, String> {
+    kinds
+        .unwrap_or("")
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| RingKind::from_name(name).ok_or_else(|| format!("unknown event kind: {}", name)))
+        .collect()
+}
+
+async fn respond(path: &str, state: Arc<ControlState>) -> String {
+    let path = path.to_string();
+    let result = tokio::task::spawn_blocking(move || -> Option<anyhow::Result<serde_json::Value>> {
+        Some(match path.as_str() {
+            "/api/v1/status" => serde_json::to_value(state.status()).map_err(Into::into),
+            "/api/v1/counters" => crate::ebpf::read_pinned_counters().and_then(|c| Ok(serde_json::to_value(c)?)),
+            "/api/v1/flows" => crate::ebpf::read_pinned_flows().and_then(|flows| {
+                let names: std::collections::HashMap<_, _> =
+                    crate::ebpf::read_pinned_tls_sni().unwrap_or_default().into_iter().collect();
+                let records: Vec<FlowRecord> = flows
+                    .iter()
+                    .map(|(k, i)| FlowRecord { server_name: names.get(k).cloned(), ..FlowRecord::new(k, i) })
+                    .collect();
+                Ok(serde_json::to_value(records)?)
+            }),
+            "/api/v1/drops" => serde_json::to_value(state.drops()).map_err(Into::into),
+            _ => return None,
+        })
+    })
+    .await
+    .unwrap_or_else(|e| Some(Err(e.into())));
+
+    match result {
+        Some(Ok(body)) => http_response("200 OK", "application/json", &body.to_string()),
+        Some(Err(e)) => error_response("503 Service Unavailable", &format!("{:#}", e)),
+        None => error_response("404 Not Found", "not found"),
+    }
+}
+
+async fn grafana(request: &Request<'_>, body: Vec<u8>, state: Arc<ControlState>) -> String {
+    let method = request.method.to_string();
+    let route = request.path[GRAFANA_PREFIX.len()..].to_string();
+    let params = crate::grafana::Params {
+        target: request.param("target"),
+        from: request.param("from"),
+        to: request.param("to"),
+    };
+    let result = tokio::task::spawn_blocking(move || crate::grafana::respond(&method, &route, &params, &body, &state))
+        .await
+        .unwrap_or_else(|e| Err(("500 Internal Server Error", e.to_string())));
+    match result {
+        Ok(body) => http_response("200 OK", "application/json", &body.to_string()),
+        Err((status, message)) => error_response(status, &message),
+    }
+}
+
+fn error_response(status: &str, message: &str) -> String {
+    http_response(status, "application/json", &serde_json::json!({ "error": message }).to_string())
+}
+
+/// Send events as Server-Sent Events until the client disconnects
+async fn stream_events(stream: TcpStream, state: &Arc<ControlState>, kinds: Vec<RingKind>) {
+    let (mut read, mut write) = stream.into_split();
+    let head = "HTTP/1.0 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+    if write.write_all(head.as_bytes()).await.is_err() {
+        return;
+    }
+    let mut subscription = state.subscribe().only(kinds);
+    let mut keepalive = tokio::time::interval_at(tokio::time::Instant::now() + KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL);
+    let mut discard = [0u8; 256];
+    loop {
+        let records = tokio::select! {
+            records = subscription.next() => match records {
+                Some(records) => records,
+                None => return,
+            },
+            _ = keepalive.tick() => {
+                if write.write_all(b": keepalive\n\n").await.is_err() {
+                    return;
+                }
+                continue;
+            }
+            read = read.read(&mut discard) => match read {
+                Ok(n) if n > 0 => continue,
+                _ => return,
+            },
+        };
+        for record in &records {
+            let Ok(data) = serde_json::to_string(record) else { continue };
+            if write.write_all(format!("data: {}\n\n", data).as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::DaemonStatus;
+    use crate::events::DropEvent;
+    use crate::pipeline::{EnrichedEvent, RawEvent};
+    use tokio::sync::broadcast;
+
+    #[test]
+    fn test_authorize() {
+        let request = |raw: &'static [u8]| Request::parse(raw).unwrap();
+        let bearer = request(b"GET /api/v1/flows HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n");
+        let query = request(b"GET /api/v1/events?follow=1&token=s3cret HTTP/1.1\r\n\r\n");
+        let wrong = request(b"GET /api/v1/flows HTTP/1.1\r\nAuthorization: Bearer s3cre\r\n\r\n");
+        let none = request(b"GET /api/v1/flows HTTP/1.1\r\n\r\n");
+
+        assert!(authorize(&bearer, Some("s3cret")).is_ok());
+        assert!(authorize(&query, Some("s3cret")).is_ok());
+        assert_eq!(authorize(&wrong, Some("s3cret")), Err("401 Unauthorized"));
+        assert_eq!(authorize(&none, Some("s3cret")), Err("401 Unauthorized"));
+        assert!(authorize(&none, None).is_ok());
+
+        assert_eq!(parse_kinds(Some("drop,rst")).unwrap(), [RingKind::Drop, RingKind::Rst]);
+        assert!(parse_kinds(None).unwrap().is_empty());
+        assert!(parse_kinds(Some("drops")).is_err());
+    }
+
+    async fn get(addr: std::net::SocketAddr, target: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer s3cret\r\n\r\n", target);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        stream
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_serve_status_and_events() {
+        let (tap, _) = broadcast::channel(16);
+        let status = DaemonStatus { version: "9.9.9".into(), ..Default::default() };
+        let state = ControlState::new(status, Some(tap.clone()), Default::default(), Default::default());
+
+        // Find a free port, then serve on it
+        let addr = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let server = tokio::spawn(serve(addr.to_string(), Some("s3cret".into()), state));
+        let mut attempts = 0;
+        while TcpStream::connect(addr).await.is_err() && attempts < 100 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            attempts += 1;
+        }
+
+        let mut response = String::new();
+        get(addr, "/api/v1/status").await.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"), "{}", response);
+        assert!(response.contains(r#""version":"9.9.9""#));
+
+        response.clear();
+        get(addr, "/api/v1/nope").await.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.0 404"));
+
+        // Publish until the subscription is in place and an event comes back
+        let publisher = tokio::spawn(async move {
+            let drop = DropEvent { timestamp_ns: 5, reason: 2, ..Default::default() };
+            loop {
+                let _ = tap.send(EnrichedEvent::new(RawEvent::Drop(drop), None));
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        let mut stream = get(addr, "/api/v1/events?follow=1&kinds=drop").await;
+        let mut received = Vec::new();
+        let mut buf = [0u8; 1024];
+        let found = tokio::time::timeout(Duration::from_secs(5), async {
+            while !String::from_utf8_lossy(&received).contains("data: {") {
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0);
+                received.extend_from_slice(&buf[..n]);
+            }
+        })
+        .await;
+        assert!(found.is_ok());
+        let received = String::from_utf8_lossy(&received);
+        assert!(received.contains("Content-Type: text/event-stream"));
+        assert!(received.contains(r#"data: {"type":"event","raw":{"kind":"drop""#), "{}", received);
+
+        publisher.abort();
+        server.abort();
+    }
+}
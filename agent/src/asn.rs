@@ -0,0 +1,216 @@
+//! ASN Attribution
+//!
+//! Maps remote IPv4 addresses to Autonomous System Numbers using an
+//! offline database, so flow bandwidth can be aggregated per network
+//! operator (cloud provider, CDN, ISP).
+//!
+//! Database format is the iptoasn.com TSV (`ip2asn-v4.tsv`):
+//! `range_start  range_end  AS_number  country_code  AS_description`
+//! Addresses may be dotted-quad or decimal integers.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+
+use crate::ebpf::{ipv4_addr, FlowInfo, FlowKey};
+
+/// Default database file name looked up in the state directory
+pub const DEFAULT_DB_FILE: &str = "ip2asn-v4.tsv";
+
+/// One announced address range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsnRecord {
+    pub start: u32,
+    pub end: u32,
+    pub asn: u32,
+    pub country: String,
+    pub name: String,
+}
+
+/// Sorted, non-overlapping ASN ranges
+#[derive(Debug, Default)]
+pub struct AsnDb {
+    ranges: Vec<AsnRecord>,
+}
+
+impl AsnDb {
+    /// Load a database from a TSV file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read ASN database: {}", path.display()))?;
+        let db = Self::parse(&content);
+        if db.is_empty() {
+            anyhow::bail!("ASN database {} contains no usable ranges", path.display());
+        }
+        Ok(db)
+    }
+
+    /// Load the configured database, or the default one in `state_dir`
+    pub fn load_default(configured: Option<&Path>, state_dir: &Path) -> Option<Self> {
+        let path: PathBuf = configured
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| state_dir.join(DEFAULT_DB_FILE));
+        match Self::load(&path) {
+            Ok(db) => Some(db),
+            Err(e) => {
+                if configured.is_some() {
+                    tracing::warn!("{}", e);
+                }
+                None
+            }
+        }
+    }
+
+    /// Parse TSV content, skipping malformed and unrouted ("AS0") lines
+    pub fn parse(content: &str) -> Self {
+        let mut ranges: Vec<AsnRecord> = content
+            .lines()
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|line| {
+                let mut cols = line.split('\t');
+                let start = parse_addr(cols.next()?)?;
+                let end = parse_addr(cols.next()?)?;
+                let asn: u32 = cols.next()?.trim().trim_start_matches("AS").parse().ok()?;
+                if asn == 0 || end < start {
+                    return None;
+                }
+                Some(AsnRecord {
+                    start,
+                    end,
+                    asn,
+                    country: cols.next().unwrap_or("").trim().to_string(),
+                    name: cols.next().unwrap_or("").trim().to_string(),
+                })
+            })
+            .collect();
+        ranges.sort_by_key(|r| r.start);
+        Self { ranges }
+    }
+
+    /// Find the range containing `ip`
+    pub fn lookup(&self, ip: Ipv4Addr) -> Option<&AsnRecord> {
+        let ip = u32::from(ip);
+        let idx = self.ranges.partition_point(|r| r.start <= ip);
+        let record = self.ranges.get(idx.checked_sub(1)?)?;
+        (ip <= record.end).then_some(record)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+fn parse_addr(s: &str) -> Option<u32> {
+    let s = s.trim();
+    s.parse::<Ipv4Addr>()
+        .map(u32::from)
+        .ok()
+        .or_else(|| s.parse::<u32>().ok())
+}
+
+/// Bandwidth attributed to a single ASN
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AsnUsage {
+    /// AS number (0 = unknown / private address space)
+    pub asn: u32,
+    pub name: String,
+    pub country: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub flows: u64,
+}
+
+impl AsnUsage {
+    pub fn total_bytes(&self) -> u64 {
+        self.rx_bytes + self.tx_bytes
+    }
+}
+
+/// Aggregate flow bytes by the ASN of the remote endpoint
+///
+/// Results are sorted by total bytes, largest first.
+pub fn aggregate(db: &AsnDb, flows: &[(FlowKey, FlowInfo)]) -> Vec<AsnUsage> {
+    let mut by_asn: HashMap<u32, AsnUsage> = HashMap::new();
+
+    for (key, info) in flows {
+        // Outbound: remote is the destination; inbound: remote is the source
+        let remote = if info.direction == 1 { key.dst_ip } else { key.src_ip };
+        let usage = match db.lookup(ipv4_addr(remote)) {
+            Some(rec) => by_asn.entry(rec.asn).or_insert_with(|| AsnUsage {
+                asn: rec.asn,
+                name: rec.name.clone(),
+                country: rec.country.clone(),
+                ..Default::default()
+            }),
+            None => by_asn.entry(0).or_insert_with(|| AsnUsage {
+                name: "Unknown / private".to_string(),
+                ..Default::default()
+            }),
+        };
+        usage.rx_bytes += info.rx_bytes;
+        usage.tx_bytes += info.tx_bytes;
+        usage.flows += 1;
+    }
+
+    let mut result: Vec<AsnUsage> = by_asn.into_values().collect();
+    result.sort_by(|a, b| b.total_bytes().cmp(&a.total_bytes()).then(a.asn.cmp(&b.asn)));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DB: &str = "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+                      8.8.8.0\t8.8.8.255\t15169\tUS\tGOOGLE\n\
+                      10.0.0.0\t10.255.255.255\t0\tNone\tNot routed\n\
+                      134743040\t134743295\t15169\tUS\tGOOGLE\n";
+
+    fn flow(remote: Ipv4Addr, direction: u8, rx: u64, tx: u64) -> (FlowKey, FlowInfo) {
+        let local = u32::from_be_bytes([192, 168, 1, 10]);
+        let remote = u32::from_be_bytes(remote.octets());
+        let (src_ip, dst_ip) = if direction == 1 { (local, remote) } else { (remote, local) };
+        let key = FlowKey { src_ip, dst_ip, ..Default::default() };
+        let info = FlowInfo { direction, rx_bytes: rx, tx_bytes: tx, ..Default::default() };
+        (key, info)
+    }
+
+    #[test]
+    fn test_parse_and_lookup() {
+        let db = AsnDb::parse(DB);
+        // AS0 line is dropped
+        assert_eq!(db.len(), 3);
+        assert_eq!(db.lookup(Ipv4Addr::new(8, 8, 8, 8)).unwrap().asn, 15169);
+        // Decimal range 134743040 = 8.8.4.0
+        assert_eq!(db.lookup(Ipv4Addr::new(8, 8, 4, 4)).unwrap().name, "GOOGLE");
+        assert!(db.lookup(Ipv4Addr::new(10, 1, 2, 3)).is_none());
+        assert!(db.lookup(Ipv4Addr::new(0, 0, 0, 1)).is_none());
+        assert!(db.lookup(Ipv4Addr::new(1, 0, 1, 0)).is_none());
+    }
+
+    #[test]
+    fn test_aggregate_by_remote_asn() {
+        let db = AsnDb::parse(DB);
+        let flows = vec![
+            flow(Ipv4Addr::new(8, 8, 8, 8), 1, 100, 1000),
+            flow(Ipv4Addr::new(8, 8, 4, 4), 1, 50, 500),
+            flow(Ipv4Addr::new(1, 0, 0, 1), 0, 10, 20),
+            flow(Ipv4Addr::new(10, 0, 0, 5), 1, 5, 5),
+        ];
+        let usage = aggregate(&db, &flows);
+        assert_eq!(usage.len(), 3);
+        assert_eq!(usage[0].asn, 15169);
+        assert_eq!(usage[0].flows, 2);
+        assert_eq!(usage[0].tx_bytes, 1500);
+        assert_eq!(usage[1].asn, 13335);
+        assert_eq!(usage[2].asn, 0);
+    }
+}
@@ -0,0 +1,160 @@
+//! Drop Attribution
+//!
+//! Joins kernel drops that carry their socket's 4-tuple against the
+//! PID-attributed flow table, so drop output can name the application
+//! experiencing them ("drops affecting nginx (pid 1234)").
+//!
+//! Drop tuples use the same encoding as an outbound `FlowKey`; inbound flows
+//! are keyed remote-first, so lookups also try the reversed tuple.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::ebpf::{comm_to_string, DropEvent, FlowInfo, FlowKey};
+
+/// (src_ip, dst_ip, src_port, dst_port)
+type Tuple = (u32, u32, u16, u16);
+/// (process, total drops, drops by reason)
+type OwnerDrops<'a> = (&'a Owner, u64, Vec<(u32, u64)>);
+
+/// Process owning a flow
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Owner {
+    pub pid: u32,
+    pub comm: String,
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (pid {})", self.comm, self.pid)
+    }
+}
+
+/// Flow table indexed by 4-tuple
+#[derive(Debug, Default)]
+pub struct FlowOwners {
+    owners: HashMap<Tuple, Owner>,
+}
+
+impl FlowOwners {
+    pub fn new(flows: &[(FlowKey, FlowInfo)]) -> Self {
+        let owners = flows
+            .iter()
+            .map(|(key, info)| {
+                let owner = Owner { pid: info.pid, comm: comm_to_string(&info.comm) };
+                ((key.src_ip, key.dst_ip, key.src_port, key.dst_port), owner)
+            })
+            .collect();
+        Self { owners }
+    }
+
+    /// Current flows from the running agent, or its pinned FLOWS map
+    #[cfg(target_os = "linux")]
+    pub fn load() -> Self {
+        let flows = crate::control::Client::connect()
+            .and_then(|mut client| client.flows().ok())
+            .or_else(|| crate::ebpf::read_pinned_flows().ok())
+            .unwrap_or_default();
+        Self::new(&flows)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+
+    /// Process whose flow the dropped packet belonged to
+    pub fn lookup(&self, event: &DropEvent) -> Option<&Owner> {
+        if !event.has_tuple() {
+            return None;
+        }
+        let (src, dst, sport, dport) = (event.src_ip, event.dst_ip, event.src_port, event.dst_port);
+        self.owners
+            .get(&(src, dst, sport, dport))
+            .or_else(|| self.owners.get(&(dst, src, dport, sport)))
+    }
+}
+
+/// Drops counted per affected process and drop reason
+#[derive(Debug, Default)]
+pub struct DropTally {
+    by_owner: BTreeMap<Owner, BTreeMap<u32, u64>>,
+}
+
+impl DropTally {
+    pub fn add(&mut self, owner: &Owner, reason: u32, count: u64) {
+        *self
+            .by_owner
+            .entry(owner.clone())
+            .or_default()
+            .entry(reason)
+            .or_default() += count;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_owner.is_empty()
+    }
+
+    /// Most affected process first
+    pub fn summary(&self) -> Vec<OwnerDrops<'_>> {
+        let mut rows: Vec<_> = self
+            .by_owner
+            .iter()
+            .map(|(owner, reasons)| {
+                let mut reasons: Vec<(u32, u64)> = reasons.iter().map(|(&r, &n)| (r, n)).collect();
+                reasons.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+                (owner, reasons.iter().map(|(_, n)| n).sum(), reasons)
+            })
+            .collect();
+        rows.sort_by_key(|&(_, total, _)| std::cmp::Reverse(total));
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(src_ip: u32, dst_ip: u32, src_port: u16, dst_port: u16, pid: u32, comm: &str) -> (FlowKey, FlowInfo) {
+        let mut info = FlowInfo { pid, ..Default::default() };
+        info.comm[..comm.len()].copy_from_slice(comm.as_bytes());
+        (FlowKey { src_ip, dst_ip, src_port, dst_port, protocol: 6, ..Default::default() }, info)
+    }
+
+    fn drop_on(src_ip: u32, dst_ip: u32, src_port: u16, dst_port: u16) -> DropEvent {
+        DropEvent { reason: 7, src_ip, dst_ip, src_port, dst_port, ..Default::default() }
+    }
+
+    #[test]
+    fn test_lookup_matches_both_directions() {
+        let owners = FlowOwners::new(&[
+            // Outbound: local first
+            flow(1, 2, 40000, 443, 10, "curl"),
+            // Inbound: remote first
+            flow(3, 1, 51000, 80, 20, "nginx"),
+        ]);
+
+        assert_eq!(owners.lookup(&drop_on(1, 2, 40000, 443)).unwrap().comm, "curl");
+        // The socket sees the inbound flow from the local side
+        let owner = owners.lookup(&drop_on(1, 3, 80, 51000)).unwrap();
+        assert_eq!(owner.to_string(), "nginx (pid 20)");
+
+        assert!(owners.lookup(&drop_on(1, 2, 40001, 443)).is_none());
+        assert!(owners.lookup(&DropEvent { reason: 7, ..Default::default() }).is_none());
+    }
+
+    #[test]
+    fn test_tally_orders_by_total() {
+        let nginx = Owner { pid: 20, comm: "nginx".into() };
+        let curl = Owner { pid: 10, comm: "curl".into() };
+        let mut tally = DropTally::default();
+        tally.add(&curl, 7, 1);
+        tally.add(&nginx, 22, 2);
+        tally.add(&nginx, 7, 5);
+
+        let summary = tally.summary();
+        assert_eq!(summary[0].0, &nginx);
+        assert_eq!(summary[0].1, 7);
+        assert_eq!(summary[0].2, vec![(7, 5), (22, 2)]);
+        assert_eq!(summary[1].1, 1);
+    }
+}
@@ -0,0 +1,109 @@
+//! Remote Command Audit Log
+//!
+//! Every command the control plane sends, other than no-ops, is appended to
+//! `audit.log` in the state directory as one JSON line when it arrives, and
+//! again with the outcome once the agent has acted on it (or refused to,
+//! because it isn't in `allowed_commands`):
+//!
+//! ```text
+//! {"timestamp":"2026-10-16T09:12:03.114Z","command":"upgrade","outcome":"received"}
+//! {"timestamp":"2026-10-16T09:12:03.115Z","command":"upgrade","outcome":"denied","detail":"not in allowed_commands"}
+//! ```
+//!
+//! The file is only ever opened for appending; the agent never rewrites or
+//! rotates it.
+
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// File name under the state directory
+pub const AUDIT_LOG: &str = "audit.log";
+
+/// What happened to a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Received,
+    /// Not in `allowed_commands`
+    Denied,
+    Succeeded,
+    Failed,
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub command: String,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Append-only log of remote commands
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(state_dir: &Path) -> Self {
+        Self { path: state_dir.join(AUDIT_LOG) }
+    }
+
+    /// Append an entry; failures are logged, not returned, so an unwritable
+    /// state directory doesn't stop the agent from running
+    pub fn record(&self, command: &str, outcome: Outcome, detail: Option<&str>) {
+        let entry = AuditEntry {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            command: command.to_string(),
+            outcome,
+            detail: detail.map(str::to_string),
+        };
+        if let Err(e) = self.append(&entry) {
+            warn!("Failed to write audit log {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn append(&self, entry: &AuditEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        // One write per line, so concurrent appends don't interleave
+        options.open(&self.path)?.write_all(line.as_bytes())
+    }
+}
+
+/// Whether `allowed` (the `allowed_commands` config) permits a command
+pub fn is_allowed(allowed: &[String], command: &str) -> bool {
+    allowed.iter().any(|name| name == command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_appends_lines() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let log = AuditLog::new(dir.path());
+        log.record("upgrade", Outcome::Received, None);
+        log.record("upgrade", Outcome::Denied, Some("not in allowed_commands"));
+
+        let contents = std::fs::read_to_string(dir.path().join(AUDIT_LOG)).unwrap();
+        let entries: Vec<AuditEntry> = contents.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].outcome, Outcome::Received);
+        assert!(!contents.lines().next().unwrap().contains("detail"));
+        assert_eq!(entries[1].detail.as_deref(), Some("not in allowed_commands"));
+
+        let allowed = vec!["reconfigure".to_string()];
+        assert!(is_allowed(&allowed, "reconfigure"));
+        assert!(!is_allowed(&allowed, "upgrade"));
+    }
+}
@@ -0,0 +1,271 @@
+//! Black-Box Recorder
+//!
+//! With `blackbox_minutes` set, the daemon keeps the last N minutes of
+//! pipeline summaries and packet counters on disk, so that after an incident
+//! `sennet export --last 10m` can show what led up to it even if nobody was
+//! watching.
+//!
+//! Each pipeline flush appends a [`Record`] to a file for the current minute
+//! under `<state_dir>/blackbox/`. Starting a new minute deletes files that
+//! have aged out, so the ring holds about N files whatever the event rate;
+//! the size of each is bounded by the pipeline's flush interval and
+//! `Summary::MAX_NOTABLE`.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+use crate::ebpf::PacketCounters;
+use crate::pipeline::{SinkFn, Summary};
+
+/// Directory of the ring inside the state directory
+pub const DIR: &str = "blackbox";
+
+/// One pipeline window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    /// RFC 3339 time the window ended
+    pub time: String,
+    /// Packet counters at the end of the window (None without eBPF)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counters: Option<PacketCounters>,
+    pub summary: serde_json::Value,
+}
+
+/// Writer for the on-disk ring
+pub struct BlackBox {
+    dir: PathBuf,
+    minutes: u64,
+    /// Minute (since the Unix epoch) of the open file
+    current: Option<(u64, File)>,
+}
+
+fn unix_minute(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs() / 60).unwrap_or(0)
+}
+
+/// Minute a ring file holds, from its name
+fn file_minute(path: &Path) -> Option<u64> {
+    path.extension().filter(|ext| *ext == "jsonl")?;
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+impl BlackBox {
+    pub fn new(state_dir: &Path, minutes: u64) -> Self {
+        Self { dir: state_dir.join(DIR), minutes, current: None }
+    }
+
+    /// Append a record written at `now`
+    pub fn append(&mut self, record: &Record, now: SystemTime) -> Result<()> {
+        let minute = unix_minute(now);
+        let file = match self.current.take() {
+            Some((open, file)) if open == minute => file,
+            _ => {
+                fs::create_dir_all(&self.dir)
+                    .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+                let path = self.dir.join(format!("{}.jsonl", minute));
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                self.prune(minute);
+                file
+            }
+        };
+        let (_, file) = self.current.insert((minute, file));
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        file.write_all(&line).context("Failed to write black-box record")
+    }
+
+    /// Delete files older than the retention
+    fn prune(&self, minute: u64) {
+        let oldest = minute.saturating_sub(self.minutes);
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if file_minute(&path).is_some_and(|m| m < oldest) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+/// Pipeline sink writing each window, with the packet counters, to the ring
+pub fn sink(state_dir: &Path, minutes: u64) -> SinkFn {
+    let mut blackbox = BlackBox::new(state_dir, minutes);
+    Box::new(move |summary: &Summary| {
+        let now = SystemTime::now();
+        let record = Record {
+            time: crate::clock::rfc3339(summary.window_end.unwrap_or(now)),
+            counters: crate::ebpf::read_pinned_counters().ok(),
+            summary: serde_json::to_value(summary).unwrap_or_default(),
+        };
+        if let Err(e) = blackbox.append(&record, now) {
+            crate::selfmetrics::global().exporter_errors.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            debug!("Could not write black-box record: {:#}", e);
+        }
+    })
+}
+
+/// Records written at or after `since`, oldest first
+pub fn read_since(state_dir: &Path, since: SystemTime) -> Result<Vec<Record>> {
+    let dir = state_dir.join(DIR);
+    let entries = fs::read_dir(&dir).with_context(|| format!("No black-box recording at {}", dir.display()))?;
+    let first = unix_minute(since);
+    let mut files: Vec<(u64, PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter_map(|path| Some((file_minute(&path)?, path)))
+        .filter(|(minute, _)| *minute >= first)
+        .collect();
+    files.sort();
+
+    let since: chrono::DateTime<chrono::Utc> = since.into();
+    let mut records = Vec::new();
+    for (_, path) in files {
+        let Ok(file) = File::open(&path) else { continue };
+        // A line being written as we read doesn't parse and is skipped
+        records.extend(
+            BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str::<Record>(&line).ok())
+                .filter(|record| {
+                    chrono::DateTime::parse_from_rfc3339(&record.time).is_ok_and(|time| time >= since)
+                }),
+        );
+    }
+    Ok(records)
+}
+
+/// Parse a duration like "90s", "10m" or "2h"; a bare number is minutes
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let n: u64 = number.parse().with_context(|| format!("Invalid duration: {}", s))?;
+    let secs = match unit {
+        "s" => n,
+        "" | "m" => n * 60,
+        "h" => n * 3600,
+        _ => anyhow::bail!("Invalid duration: {} (use s, m or h)", s),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+/// Run `sennet export`: write the black-box records of the last few minutes
+pub fn export(args: &[String]) -> Result<()> {
+    let mut last = Duration::from_secs(600);
+    let mut out: Option<PathBuf> = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--last" | "-l" => last = parse_duration(args.next().context("--last needs a duration")?)?,
+            "--out" | "-o" => out = Some(PathBuf::from(args.next().context("--out needs a file")?)),
+            other => anyhow::bail!("Unknown option: {}", other),
+        }
+    }
+
+    let config = crate::config::Config::load().ok();
+    if config.as_ref().is_some_and(|c| c.blackbox_minutes == 0) {
+        eprintln!("{}: blackbox_minutes is not set; showing what an earlier run recorded", "Note".yellow());
+    }
+    let state_dir = config.map(|c| c.state_dir).unwrap_or_else(crate::config::default_state_dir);
+    let records = read_since(&state_dir, SystemTime::now() - last)?;
+
+    let mut lines = Vec::new();
+    for record in &records {
+        serde_json::to_writer(&mut lines, record)?;
+        lines.push(b'\n');
+    }
+    match &out {
+        Some(path) => fs::write(path, &lines).with_context(|| format!("Failed to write {}", path.display()))?,
+        None => std::io::stdout().write_all(&lines)?,
+    }
+
+    match (records.first(), records.last()) {
+        (Some(first), Some(last)) => eprintln!(
+            "Exported {} windows recorded between {} and {}{}",
+            records.len(),
+            first.time,
+            last.time,
+            out.map(|p| format!(" to {}", p.display())).unwrap_or_default()
+        ),
+        _ => eprintln!("{}: nothing recorded in the last {}s", "Note".yellow(), last.as_secs()),
+    }
+    Ok(())
+}
+
+/// Print export command help
+pub fn print_help() {
+    println!("{}", "sennet export - Export the black-box recording".bold());
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet export [OPTIONS]");
+    println!();
+    println!("{}", "OPTIONS:".yellow());
+    println!("    {}  How far back to go: 90s, 10m, 2h (default: 10m)", "-l, --last <D>".cyan());
+    println!("    {}  Write to a file instead of stdout", "-o, --out <F>".cyan());
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sennet export --last 10m -o incident.jsonl");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    Output is one JSON line per pipeline window: its summary and the packet");
+    println!("    counters when it ended. The agent records them when blackbox_minutes and");
+    println!("    pipeline.enabled are set in its config.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record(time: SystemTime, events: u64) -> Record {
+        Record {
+            time: crate::clock::rfc3339(time),
+            counters: Some(PacketCounters { drop_count: events, ..Default::default() }),
+            summary: serde_json::json!({ "events": events }),
+        }
+    }
+
+    #[test]
+    fn test_ring_keeps_recent_minutes() {
+        let dir = TempDir::new().unwrap();
+        let mut blackbox = BlackBox::new(dir.path(), 5);
+        let start = UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+
+        for minute in 0..10u64 {
+            let now = start + Duration::from_secs(minute * 60);
+            blackbox.append(&record(now, minute), now).unwrap();
+            blackbox.append(&record(now + Duration::from_secs(30), minute), now + Duration::from_secs(30)).unwrap();
+        }
+        // Minutes 4 through 9 are left
+        let files = fs::read_dir(dir.path().join(DIR)).unwrap().count();
+        assert_eq!(files, 6);
+
+        let since = start + Duration::from_secs(8 * 60 + 15);
+        let records = read_since(dir.path(), since).unwrap();
+        let events: Vec<u64> = records.iter().map(|r| r.summary["events"].as_u64().unwrap()).collect();
+        assert_eq!(events, vec![8, 9, 9]);
+        assert_eq!(records[2].counters.unwrap().drop_count, 9);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("15").unwrap(), Duration::from_secs(900));
+        assert!(parse_duration("10d").is_err());
+        assert!(parse_duration("m").is_err());
+    }
+}
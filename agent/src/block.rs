@@ -0,0 +1,297 @@
+//! Blocklist CLI Command
+//!
+//! Edits the addresses and ports the TC/XDP programs drop when the agent
+//! runs with `enforcement: true`. Entries are saved under the state
+//! directory, loaded into the BLOCKLIST map when the agent starts, and
+//! written to the running agent's pinned map right away. Packets dropped
+//! by TC show up as TC_INGRESS/TC_EGRESS drops in `sennet trace` and the
+//! event pipeline; every entry also counts the packets it dropped.
+//! Usage: sennet block <add|remove|list|clear> [TARGET]...
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+use crate::ebpf::BlockKey;
+
+/// File under the state directory holding the saved blocklist, one target
+/// per line
+const BLOCKLIST_FILE: &str = "blocklist";
+
+/// Print help for the block command
+pub fn print_help() {
+    println!("{}", "Sennet Block - Drop Traffic to and from Addresses or Ports".bold());
+    println!("Manage the blocklist the agent enforces in its TC/XDP programs.");
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet block add <TARGET>...     Block targets");
+    println!("    sennet block remove <TARGET>...  Unblock targets");
+    println!("    sennet block list [--json]       Show targets and the packets each dropped");
+    println!("    sennet block clear               Unblock everything");
+    println!();
+    println!("{}", "TARGETS:".yellow());
+    println!("    10.0.0.5             All traffic to or from an address");
+    println!("    10.0.0.5:443         Traffic to or from port 443 of an address");
+    println!("    [2001:db8::1]:443    The same for IPv6");
+    println!("    :23                  TCP/UDP traffic with port 23 at either end");
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sudo sennet block add 203.0.113.7     # Cut off a scanner");
+    println!("    sudo sennet block add :23             # No telnet in or out");
+    println!("    sudo sennet block remove 203.0.113.7");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    - Nothing is dropped unless the agent runs with enforcement: true");
+    println!("    - Changes apply to the running agent at once and are kept across restarts");
+    println!("    - Requires root to update the pinned maps and the state directory");
+}
+
+/// Parse a target: `IP`, `IP:PORT`, `[IPv6]:PORT` or `:PORT`
+pub fn parse_target(target: &str) -> Result<BlockKey> {
+    let (ip, port) = if let Some(port) = target.strip_prefix(':') {
+        (None, parse_port(port)?)
+    } else if let Ok(ip) = target.parse::<IpAddr>() {
+        (Some(ip), 0)
+    } else if let Ok(addr) = target.parse::<SocketAddr>() {
+        if addr.port() == 0 {
+            anyhow::bail!("Invalid target '{}': port 0 never matches; leave the port out to block every port", target);
+        }
+        (Some(addr.ip()), addr.port())
+    } else {
+        anyhow::bail!("Invalid target '{}': expected IP, IP:PORT, [IPv6]:PORT or :PORT", target);
+    };
+
+    let addr = match ip {
+        Some(ip) if ip.is_unspecified() => {
+            anyhow::bail!("Invalid target '{}': use :PORT to block a port on every address", target)
+        }
+        Some(IpAddr::V4(v4)) => v4.to_ipv6_mapped().octets(),
+        Some(IpAddr::V6(v6)) => v6.octets(),
+        None => [0; 16],
+    };
+    Ok(BlockKey { addr, port, _pad: 0 })
+}
+
+fn parse_port(port: &str) -> Result<u16> {
+    match port.parse::<u16>() {
+        Ok(0) | Err(_) => anyhow::bail!("Invalid port '{}': expected 1-65535", port),
+        Ok(port) => Ok(port),
+    }
+}
+
+/// Format a blocklist entry the way [`parse_target`] reads it
+pub fn format_target(key: &BlockKey) -> String {
+    if key.addr == [0; 16] {
+        return format!(":{}", key.port);
+    }
+    let ip = crate::ebpf::ip_addr(&key.addr);
+    if key.port == 0 {
+        ip.to_string()
+    } else {
+        SocketAddr::new(ip, key.port).to_string()
+    }
+}
+
+fn blocklist_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(BLOCKLIST_FILE)
+}
+
+/// Saved blocklist under `state_dir` (empty if none was saved)
+///
+/// Blank lines and `#` comments are skipped, so the file can be edited by
+/// hand.
+pub fn load(state_dir: &Path) -> Result<Vec<BlockKey>> {
+    let path = blocklist_path(state_dir);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| parse_target(line).with_context(|| format!("In {}", path.display())))
+        .collect()
+}
+
+fn save(state_dir: &Path, keys: &[BlockKey]) -> Result<()> {
+    std::fs::create_dir_all(state_dir)?;
+    let path = blocklist_path(state_dir);
+    let mut contents = String::from("# Managed by `sennet block`\n");
+    for key in keys {
+        contents.push_str(&format_target(key));
+        contents.push('\n');
+    }
+    std::fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Run the block command
+pub fn run(args: &[String]) -> Result<()> {
+    let config = crate::config::Config::load().ok();
+    let state_dir = config
+        .as_ref()
+        .map(|c| c.state_dir.clone())
+        .unwrap_or_else(crate::config::default_state_dir);
+    let enforcing = config.as_ref().is_some_and(|c| c.enforcement);
+
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => ("list", args),
+    };
+    match command {
+        "add" | "remove" | "rm" => {
+            let block = command == "add";
+            if rest.is_empty() {
+                anyhow::bail!("sennet block {} needs at least one target; see `sennet block --help`", command);
+            }
+            let targets = rest.iter().map(|t| parse_target(t)).collect::<Result<Vec<_>>>()?;
+            let mut saved = load(&state_dir)?;
+            for key in &targets {
+                saved.retain(|k| k != key);
+                if block {
+                    saved.push(*key);
+                }
+            }
+            save(&state_dir, &saved)?;
+            apply(&targets, block);
+            let verb = if block { "Blocked" } else { "Unblocked" };
+            for key in &targets {
+                println!("{} {}", verb, format_target(key));
+            }
+            if block && !enforcing {
+                eprintln!(
+                    "{}: enforcement is off; set enforcement: true in the config and restart the agent to drop traffic",
+                    "Note".yellow()
+                );
+            }
+        }
+        "clear" => {
+            let mut targets = load(&state_dir)?;
+            save(&state_dir, &[])?;
+            if let Ok((pinned, _)) = crate::ebpf::read_pinned_blocklist() {
+                targets.extend(pinned.into_iter().map(|(key, _)| key));
+            }
+            apply(&targets, false);
+            println!("Blocklist cleared");
+        }
+        "list" => list(&state_dir, rest.iter().any(|a| a == "--json"))?,
+        other => anyhow::bail!("Unknown block command '{}'; see `sennet block --help`", other),
+    }
+    Ok(())
+}
+
+/// Write `targets` to the running agent's pinned BLOCKLIST map, if any
+fn apply(targets: &[BlockKey], block: bool) {
+    for key in targets {
+        if let Err(e) = crate::ebpf::update_pinned_blocklist(key, block) {
+            eprintln!(
+                "{}: the running agent was not updated ({}); the change applies when it next starts",
+                "Note".yellow(),
+                e
+            );
+            return;
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListedTarget {
+    target: String,
+    /// Packets dropped since the agent loaded the entry (None = agent not
+    /// running, or the entry is saved but not loaded)
+    dropped_packets: Option<u64>,
+}
+
+fn list(state_dir: &Path, json: bool) -> Result<()> {
+    let saved = load(state_dir)?;
+    let (pinned, enforcing) = match crate::ebpf::read_pinned_blocklist() {
+        Ok((pinned, enforcing)) => (pinned, Some(enforcing)),
+        Err(_) => (Vec::new(), None),
+    };
+
+    let mut listed: Vec<ListedTarget> = saved
+        .iter()
+        .map(|key| ListedTarget {
+            target: format_target(key),
+            dropped_packets: pinned.iter().find(|(k, _)| k == key).map(|(_, hits)| *hits),
+        })
+        .collect();
+    // Entries added to the running agent some other way
+    for (key, hits) in &pinned {
+        if !saved.contains(key) {
+            listed.push(ListedTarget { target: format_target(key), dropped_packets: Some(*hits) });
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&listed)?);
+        return Ok(());
+    }
+
+    match enforcing {
+        Some(true) => println!("Enforcement: {}", "on".red().bold()),
+        Some(false) => println!("Enforcement: {} (set enforcement: true to drop traffic)", "off".green()),
+        None => println!("Enforcement: {}", "agent not running, or needs root to check".dimmed()),
+    }
+    if listed.is_empty() {
+        println!("{}", "Blocklist is empty.".yellow());
+        return Ok(());
+    }
+    println!("{:<47} {:>12}", "TARGET".cyan(), "DROPPED".cyan());
+    for entry in &listed {
+        let dropped = entry.dropped_packets.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string());
+        println!("{:<47} {:>12}", entry.target, dropped);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_target() {
+        let v4 = parse_target("10.0.0.5").unwrap();
+        assert_eq!(sennet_common::mapped_ipv4(&v4.addr), Some([10, 0, 0, 5]));
+        assert_eq!(v4.port, 0);
+
+        let v4_port = parse_target("10.0.0.5:443").unwrap();
+        assert_eq!(v4_port.addr, v4.addr);
+        assert_eq!(v4_port.port, 443);
+
+        let v6 = parse_target("[2001:db8::1]:53").unwrap();
+        assert_eq!(v6.addr, "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        assert_eq!(v6.port, 53);
+
+        assert_eq!(parse_target(":23").unwrap(), BlockKey { addr: [0; 16], port: 23, _pad: 0 });
+
+        for bad in ["", ":0", ":http", "10.0.0.5:70000", "0.0.0.0", "[::]:80", "example.com"] {
+            assert!(parse_target(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_format_target_round_trips() {
+        for target in ["10.0.0.5", "10.0.0.5:443", "2001:db8::1", "[2001:db8::1]:53", ":23"] {
+            assert_eq!(format_target(&parse_target(target).unwrap()), target);
+        }
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let dir = TempDir::new().unwrap();
+        assert!(load(dir.path()).unwrap().is_empty());
+
+        let keys = vec![parse_target("10.0.0.5").unwrap(), parse_target(":23").unwrap()];
+        save(dir.path(), &keys).unwrap();
+        assert_eq!(load(dir.path()).unwrap(), keys);
+
+        std::fs::write(dir.path().join(BLOCKLIST_FILE), "# comment\n\n  10.0.0.5  \nbogus\n").unwrap();
+        assert!(load(dir.path()).is_err());
+    }
+}
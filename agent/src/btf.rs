@@ -2,10 +2,25 @@
 //!
 //! Provides utilities for checking kernel BTF support and handling
 //! fallbacks for systems without BTF.
+//!
+//! The eBPF programs can't relocate their kernel struct reads, so the agent
+//! looks the fields up in the kernel's BTF instead and hands the offsets to
+//! the programs through single-entry layout maps (SKB_LAYOUT,
+//! NF_CONN_LAYOUT), written before the programs are attached.
 
+use anyhow::{Context, Result};
 use std::path::Path;
 use tracing::{info, warn};
 
+use crate::ebpf::{NfConnLayout, SkbLayout};
+
+/// Kernel BTF as exposed by sysfs, with one file per loaded module next to
+/// vmlinux's
+const VMLINUX_BTF: &str = "/sys/kernel/btf/vmlinux";
+
+/// Modules whose structs the layouts read, when not built in
+const LAYOUT_MODULES: &[&str] = &["nf_conntrack"];
+
 /// BTF availability status
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BtfStatus {
@@ -99,29 +114,322 @@ pub struct EbpfCapabilities {
     pub kernel_version: Option<(u32, u32, u32)>,
     pub kernel_supported: bool,
     pub can_use_core: bool,
+    /// BPF ring buffers (5.8+), which every event map is
+    pub ringbuf: bool,
+    /// fentry/fexit programs, verified against kernel BTF (5.5+)
+    pub fexit: bool,
+    /// sock:inet_sock_set_state tracepoint (4.16+); without it TCP opens and
+    /// closes come from kprobes reading `struct sock` at static offsets
+    pub sock_state_tracepoint: bool,
+    /// kfree_skb records carry a drop reason (5.17+)
+    pub drop_reasons: bool,
+}
+
+/// One row of the feature matrix logged at startup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feature {
+    pub name: &'static str,
+    pub available: bool,
+    /// What it needs, or what is used instead
+    pub note: &'static str,
+}
+
+impl EbpfCapabilities {
+    /// Capabilities implied by BTF availability and the kernel version;
+    /// `ringbuf` is taken from a probe when one could be made
+    pub fn from_checks(btf_status: BtfStatus, kernel_version: Option<(u32, u32, u32)>, ringbuf: Option<bool>) -> Self {
+        let at_least = |major: u32, minor: u32| kernel_version.is_some_and(|(ma, mi, _)| (ma, mi) >= (major, minor));
+        let btf = btf_status == BtfStatus::Available;
+        let kernel_supported = at_least(5, 10);
+        Self {
+            can_use_core: btf && kernel_supported,
+            ringbuf: ringbuf.unwrap_or_else(|| at_least(5, 8)),
+            fexit: btf && at_least(5, 5),
+            sock_state_tracepoint: at_least(4, 16),
+            drop_reasons: at_least(5, 17),
+            btf_status,
+            kernel_version,
+            kernel_supported,
+        }
+    }
+
+    /// What the agent can load on this kernel
+    pub fn features(&self) -> Vec<Feature> {
+        let row = |name, available, note| Feature { name, available, note };
+        vec![
+            row("CO-RE", self.can_use_core, "needs kernel BTF and 5.10+; static offsets otherwise"),
+            row("Ring buffer events", self.ringbuf, "needs 5.8+"),
+            row("Netfilter tracing (fexit)", self.fexit, "needs kernel BTF and 5.5+"),
+            row("TCP state tracepoint", self.sock_state_tracepoint, "needs 4.16+; tcp_connect/tcp_close kprobes otherwise"),
+            row("Drop reasons", self.drop_reasons, "needs 5.17+"),
+        ]
+    }
+
+    /// Log the feature matrix
+    pub fn log_features(&self) {
+        let version = match self.kernel_version {
+            Some((major, minor, patch)) => format!("{}.{}.{}", major, minor, patch),
+            None => "unknown".to_string(),
+        };
+        info!("eBPF features on kernel {}:", version);
+        for feature in self.features() {
+            if feature.available {
+                info!("  {:<28} yes", feature.name);
+            } else {
+                warn!("  {:<28} no ({})", feature.name, feature.note);
+            }
+        }
+    }
+}
+
+/// Whether the kernel creates BPF ring buffer maps (None = couldn't tell,
+/// e.g. without CAP_BPF)
+#[cfg(target_os = "linux")]
+pub fn probe_ringbuf() -> Option<bool> {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    const BPF_MAP_CREATE: libc::c_long = 0;
+    const BPF_MAP_TYPE_RINGBUF: u32 = 27;
+
+    // union bpf_attr, map_type through max_entries; the rest stays zero
+    let mut attr = [0u32; 32];
+    attr[0] = BPF_MAP_TYPE_RINGBUF;
+    // A power of two and a multiple of the page size
+    attr[3] = 64 * 1024;
+    // SAFETY: attr outlives the call and is as large as the size passed
+    let fd = unsafe {
+        libc::syscall(libc::SYS_bpf, BPF_MAP_CREATE, attr.as_ptr(), std::mem::size_of_val(&attr) as u32)
+    };
+    if fd >= 0 {
+        // SAFETY: the fd was just created and is owned here
+        drop(unsafe { OwnedFd::from_raw_fd(fd as i32) });
+        return Some(true);
+    }
+    match std::io::Error::last_os_error().raw_os_error() {
+        Some(libc::EINVAL) => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn probe_ringbuf() -> Option<bool> {
+    None
+}
+
+/// BTF type kinds (`BTF_KIND_*`) looked at or skipped over
+mod kind {
+    pub const INT: u8 = 1;
+    pub const ARRAY: u8 = 3;
+    pub const STRUCT: u8 = 4;
+    pub const UNION: u8 = 5;
+    pub const ENUM: u8 = 6;
+    pub const TYPEDEF: u8 = 8;
+    pub const VOLATILE: u8 = 9;
+    pub const CONST: u8 = 10;
+    pub const RESTRICT: u8 = 11;
+    pub const FUNC_PROTO: u8 = 13;
+    pub const VAR: u8 = 14;
+    pub const DATASEC: u8 = 15;
+    pub const DECL_TAG: u8 = 17;
+    pub const TYPE_TAG: u8 = 18;
+    pub const ENUM64: u8 = 19;
+}
+
+/// One member of a struct or union
+#[derive(Debug, Clone, Copy)]
+struct Member {
+    name_off: u32,
+    type_id: u32,
+    bit_offset: u32,
+}
+
+/// One BTF type, reduced to what layout lookups need
+#[derive(Debug, Clone, Default)]
+struct BtfType {
+    name_off: u32,
+    kind: u8,
+    /// Byte size of ints, enums, structs and unions; the referenced type
+    /// of pointers, typedefs and modifiers
+    size_or_type: u32,
+    members: Vec<Member>,
+}
+
+/// Kernel types from BTF, for looking up struct layouts
+#[derive(Debug, Default)]
+pub struct KernelTypes {
+    /// Indexed by type ID; ID 0 is void
+    types: Vec<BtfType>,
+    strings: Vec<u8>,
+}
+
+impl KernelTypes {
+    /// Read the running kernel's BTF, and that of the loaded modules in
+    /// `LAYOUT_MODULES`
+    pub fn read() -> Result<Self> {
+        let data = std::fs::read(VMLINUX_BTF).with_context(|| format!("Failed to read {}", VMLINUX_BTF))?;
+        let mut types = Self::parse(&data)?;
+        let dir = Path::new(VMLINUX_BTF).parent().unwrap_or(Path::new("/"));
+        for module in LAYOUT_MODULES {
+            // Absent when built in or not loaded
+            if let Ok(data) = std::fs::read(dir.join(module)) {
+                types.extend(&data).with_context(|| format!("Invalid BTF for module {}", module))?;
+            }
+        }
+        Ok(types)
+    }
+
+    /// Parse raw BTF (little-endian, as the kernel exposes it on x86_64 and
+    /// arm64)
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut types = Self { types: vec![BtfType::default()], strings: Vec::new() };
+        types.extend(data)?;
+        Ok(types)
+    }
+
+    /// Add split BTF built on the types already parsed, as for a module:
+    /// its type IDs and string offsets carry on from theirs
+    pub fn extend(&mut self, data: &[u8]) -> Result<()> {
+        const BTF_MAGIC: u16 = 0xeb9f;
+
+        let u32_at = |at: usize| -> Result<u32> {
+            let bytes = data.get(at..at + 4).context("BTF truncated")?;
+            Ok(u32::from_le_bytes(bytes.try_into()?))
+        };
+        if data.get(0..2) != Some(&BTF_MAGIC.to_le_bytes()[..]) {
+            anyhow::bail!("Not little-endian BTF");
+        }
+        let hdr_len = u32_at(4)? as usize;
+        let (type_off, type_len) = (u32_at(8)? as usize, u32_at(12)? as usize);
+        let (str_off, str_len) = (u32_at(16)? as usize, u32_at(20)? as usize);
+        let strings = data
+            .get(hdr_len + str_off..hdr_len + str_off + str_len)
+            .context("BTF string section out of bounds")?;
+
+        let mut types = Vec::new();
+        let mut at = hdr_len + type_off;
+        let end = at + type_len;
+        while at < end {
+            let (name_off, info, size_or_type) = (u32_at(at)?, u32_at(at + 4)?, u32_at(at + 8)?);
+            at += 12;
+            let vlen = (info & 0xffff) as usize;
+            let kind = ((info >> 24) & 0x1f) as u8;
+            let bitfields = info >> 31 == 1;
+            let mut ty = BtfType { name_off, kind, size_or_type, ..Default::default() };
+            match kind {
+                kind::INT | kind::VAR | kind::DECL_TAG => at += 4,
+                kind::ARRAY => at += 12,
+                kind::STRUCT | kind::UNION => {
+                    for _ in 0..vlen {
+                        let offset = u32_at(at + 8)?;
+                        ty.members.push(Member {
+                            name_off: u32_at(at)?,
+                            type_id: u32_at(at + 4)?,
+                            // With bitfields the top byte holds the field's width
+                            bit_offset: if bitfields { offset & 0xff_ffff } else { offset },
+                        });
+                        at += 12;
+                    }
+                }
+                kind::ENUM | kind::FUNC_PROTO => at += 8 * vlen,
+                kind::DATASEC | kind::ENUM64 => at += 12 * vlen,
+                0..=kind::TYPE_TAG => {}
+                _ => anyhow::bail!("Unknown BTF kind {}", kind),
+            }
+            types.push(ty);
+        }
+        self.types.extend(types);
+        self.strings.extend_from_slice(strings);
+        Ok(())
+    }
+
+    fn name(&self, name_off: u32) -> &str {
+        let rest = self.strings.get(name_off as usize..).unwrap_or_default();
+        let len = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        std::str::from_utf8(&rest[..len]).unwrap_or_default()
+    }
+
+    /// The struct called `name` (not a forward declaration)
+    fn find_struct(&self, name: &str) -> Option<&BtfType> {
+        self.types.iter().find(|ty| ty.kind == kind::STRUCT && ty.size_or_type != 0 && self.name(ty.name_off) == name)
+    }
+
+    /// Bit offset of `member` in `ty`, looking inside anonymous structs and
+    /// unions
+    fn member_bits(&self, ty: &BtfType, member: &str) -> Option<u32> {
+        ty.members.iter().find_map(|m| {
+            if m.name_off != 0 {
+                return (self.name(m.name_off) == member).then_some(m.bit_offset);
+            }
+            let inner = self.types.get(self.resolve(m.type_id) as usize)?;
+            Some(m.bit_offset + self.member_bits(inner, member)?)
+        })
+    }
+
+    /// `id` with typedefs and qualifiers stripped
+    fn resolve(&self, mut id: u32) -> u32 {
+        while let Some(ty) = self.types.get(id as usize) {
+            match ty.kind {
+                kind::TYPEDEF | kind::VOLATILE | kind::CONST | kind::RESTRICT | kind::TYPE_TAG => id = ty.size_or_type,
+                _ => break,
+            }
+        }
+        id
+    }
+
+    /// Byte offset of `member` in `struct <name>`, None if either is
+    /// missing, the member is a bitfield or the offset doesn't fit a u16
+    pub fn member_offset(&self, name: &str, member: &str) -> Option<u16> {
+        let bits = self.member_bits(self.find_struct(name)?, member)?;
+        if bits % 8 != 0 {
+            return None;
+        }
+        u16::try_from(bits / 8).ok()
+    }
+
+    /// Size of `struct <name>` in bytes
+    pub fn struct_size(&self, name: &str) -> Option<u16> {
+        u16::try_from(self.find_struct(name)?.size_or_type).ok()
+    }
+}
+
+/// Offsets for the programs reading struct sk_buff, None if a field is
+/// missing
+pub fn skb_layout(types: &KernelTypes) -> Option<SkbLayout> {
+    Some(SkbLayout {
+        sk: types.member_offset("sk_buff", "sk")?,
+        network_header: types.member_offset("sk_buff", "network_header")?,
+        head: types.member_offset("sk_buff", "head")?,
+        nfct: types.member_offset("sk_buff", "_nfct")?,
+        dev: types.member_offset("sk_buff", "dev")?,
+        skb_iif: types.member_offset("sk_buff", "skb_iif")?,
+        dev_ifindex: types.member_offset("net_device", "ifindex")?,
+        len: types.member_offset("sk_buff", "len")?,
+    })
+}
+
+/// Offsets for the conntrack kprobes, None if nf_conntrack's types are
+/// missing (the module isn't loaded)
+pub fn nf_conn_layout(types: &KernelTypes) -> Option<NfConnLayout> {
+    // tuplehash[2]: each entry is a hash node followed by the tuple
+    let original = types.member_offset("nf_conn", "tuplehash")?.checked_add(types.member_offset("nf_conntrack_tuple_hash", "tuple")?)?;
+    Some(NfConnLayout {
+        original,
+        reply: original.checked_add(types.struct_size("nf_conntrack_tuple_hash")?)?,
+        status: types.member_offset("nf_conn", "status")?,
+    })
 }
 
 /// Check all eBPF capabilities
 pub fn check_ebpf_capabilities() -> EbpfCapabilities {
-    let btf_status = check_btf_support();
-    let kernel_version = check_kernel_version();
-    let kernel_supported = is_kernel_supported();
-    
-    // CO-RE requires both BTF and supported kernel
-    let can_use_core = btf_status == BtfStatus::Available && kernel_supported;
-    
-    if can_use_core {
+    let caps = EbpfCapabilities::from_checks(check_btf_support(), check_kernel_version(), probe_ringbuf());
+
+    if caps.can_use_core {
         info!("eBPF CO-RE: enabled (BTF available, kernel supported)");
     } else {
         warn!("eBPF CO-RE: disabled (using static offsets fallback)");
     }
-    
-    EbpfCapabilities {
-        btf_status,
-        kernel_version,
-        kernel_supported,
-        can_use_core,
-    }
+
+    caps
 }
 
 #[cfg(test)]
@@ -141,9 +449,143 @@ mod tests {
         println!("Kernel version: {:?}", version);
     }
 
+    #[test]
+    fn test_from_checks() {
+        let modern = EbpfCapabilities::from_checks(BtfStatus::Available, Some((6, 1, 0)), None);
+        assert!(modern.features().iter().all(|f| f.available));
+
+        let old = EbpfCapabilities::from_checks(BtfStatus::NotAvailable, Some((5, 4, 0)), None);
+        assert!(!old.can_use_core && !old.ringbuf && !old.fexit && !old.drop_reasons);
+        assert!(old.sock_state_tracepoint);
+
+        // A probe wins over the version, for distribution backports
+        let backported = EbpfCapabilities::from_checks(BtfStatus::Available, Some((4, 18, 0)), Some(true));
+        assert!(backported.ringbuf);
+        assert!(!EbpfCapabilities::from_checks(BtfStatus::Unknown, None, None).ringbuf);
+    }
+
     #[test]
     fn test_capabilities() {
         let caps = check_ebpf_capabilities();
         println!("eBPF Capabilities: {:?}", caps);
     }
+
+    /// Raw BTF laid out as the kernel exposes it
+    struct BtfWriter {
+        types: Vec<u8>,
+        strings: Vec<u8>,
+        next_id: u32,
+        /// Strings of the base BTF, for split BTF
+        base_strings: u32,
+    }
+
+    impl BtfWriter {
+        fn new() -> Self {
+            Self { types: Vec::new(), strings: vec![0], next_id: 1, base_strings: 0 }
+        }
+
+        /// Split BTF on top of `base`, as for a module
+        fn split(base: &Self) -> Self {
+            Self { types: Vec::new(), strings: Vec::new(), next_id: base.next_id, base_strings: base.strings.len() as u32 }
+        }
+
+        fn name(&mut self, name: &str) -> u32 {
+            if name.is_empty() {
+                return 0;
+            }
+            let off = self.base_strings + self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            off
+        }
+
+        /// Add a type record followed by `extra` and return its ID
+        fn ty(&mut self, name: &str, kind: u8, vlen: u32, size_or_type: u32, extra: &[u32]) -> u32 {
+            let name_off = self.name(name);
+            for word in [name_off, (kind as u32) << 24 | vlen, size_or_type].iter().chain(extra) {
+                self.types.extend_from_slice(&word.to_le_bytes());
+            }
+            self.next_id += 1;
+            self.next_id - 1
+        }
+
+        /// Add a struct or union of (name, type, byte offset) members
+        fn members(&mut self, name: &str, kind: u8, size: u32, members: &[(&str, u32, u32)]) -> u32 {
+            let mut extra = Vec::new();
+            for &(member, type_id, offset) in members {
+                extra.extend([self.name(member), type_id, offset * 8]);
+            }
+            self.ty(name, kind, members.len() as u32, size, &extra)
+        }
+
+        fn finish(&self) -> Vec<u8> {
+            let mut data = vec![0x9f, 0xeb, 1, 0];
+            for word in [24, 0, self.types.len() as u32, self.types.len() as u32, self.strings.len() as u32] {
+                data.extend_from_slice(&word.to_le_bytes());
+            }
+            data.extend(&self.types);
+            data.extend(&self.strings);
+            data
+        }
+    }
+
+    #[test]
+    fn test_struct_layout() {
+        let mut btf = BtfWriter::new();
+        let int = btf.ty("int", kind::INT, 0, 4, &[32]);
+        let ptr = btf.ty("", 2, 0, 0, &[]);
+        // Skipped over: two parameters
+        btf.ty("", kind::FUNC_PROTO, 2, int, &[0, int, 0, int]);
+        let sk = btf.members("", kind::UNION, 8, &[("sk", ptr, 0), ("ip_defrag_offset", int, 0)]);
+        // struct_group(): an anonymous struct and a named copy in a union
+        let headers = btf.members("", kind::STRUCT, 4, &[("transport_header", int, 0), ("network_header", int, 2)]);
+        let group = btf.members("", kind::UNION, 4, &[("", headers, 0), ("headers", headers, 0)]);
+        let group = btf.ty("", kind::CONST, 0, group, &[]);
+        btf.ty("sk_buff", 7, 0, 0, &[]);
+        let sk_buff = [
+            ("next", ptr, 0),
+            ("dev", ptr, 16),
+            ("", sk, 24),
+            ("_nfct", ptr, 104),
+            ("len", int, 112),
+            ("skb_iif", int, 144),
+            ("", group, 184),
+            ("head", ptr, 200),
+        ];
+        btf.members("sk_buff", kind::STRUCT, 232, &sk_buff);
+        btf.members("net_device", kind::STRUCT, 256, &[("name", int, 0), ("ifindex", int, 224)]);
+
+        let mut types = KernelTypes::parse(&btf.finish()).unwrap();
+        assert_eq!(types.member_offset("sk_buff", "sk"), Some(24));
+        assert_eq!(types.member_offset("sk_buff", "network_header"), Some(186));
+        assert_eq!(types.member_offset("sk_buff", "data_len"), None);
+        assert_eq!(types.struct_size("sk_buff"), Some(232));
+        let layout = SkbLayout { sk: 24, network_header: 186, head: 200, nfct: 104, dev: 16, skb_iif: 144, dev_ifindex: 224, len: 112 };
+        assert_eq!(skb_layout(&types), Some(layout));
+        assert_eq!(nf_conn_layout(&types), None);
+
+        // nf_conntrack built as a module
+        let mut module = BtfWriter::split(&btf);
+        let node = module.members("hlist_nulls_node", kind::STRUCT, 16, &[("next", ptr, 0), ("pprev", ptr, 8)]);
+        let tuple = module.members("nf_conntrack_tuple", kind::STRUCT, 40, &[("src", int, 0), ("dst", int, 20)]);
+        let hash = module.members("nf_conntrack_tuple_hash", kind::STRUCT, 56, &[("hnnode", node, 0), ("tuple", tuple, 16)]);
+        let tuplehash = module.ty("", kind::ARRAY, 0, 0, &[hash, int, 2]);
+        module.members("nf_conn", kind::STRUCT, 256, &[("ct_general", int, 0), ("tuplehash", tuplehash, 16), ("status", int, 128)]);
+        types.extend(&module.finish()).unwrap();
+        assert_eq!(nf_conn_layout(&types), Some(NfConnLayout { original: 32, reply: 88, status: 128 }));
+        assert_eq!(types.member_offset("sk_buff", "head"), Some(200));
+
+        assert!(KernelTypes::parse(b"not btf").is_err());
+    }
+
+    #[test]
+    fn test_running_kernel_layout() {
+        // Only where the kernel exposes its BTF
+        let Ok(types) = KernelTypes::read() else {
+            return;
+        };
+        let layout = skb_layout(&types).unwrap();
+        assert!(layout.head > layout.network_header && layout.nfct > layout.sk);
+        assert!(layout.skb_iif > layout.len && layout.len > layout.dev && layout.dev_ifindex != 0);
+    }
 }
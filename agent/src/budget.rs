@@ -0,0 +1,210 @@
+//! Memory Budget
+//!
+//! One knob, `memory_budget_mb`, sizes the kernel flow map, the ring
+//! buffers and the userspace event queues at startup. Left at 0, the budget
+//! is derived from installed RAM, so the same config suits a 256MB edge box
+//! and a 256GB server.
+//!
+//! The budget is split roughly half to the flow map, a quarter to ring
+//! buffers and a quarter to userspace queues. Sizes are estimates of
+//! kernel/allocator overhead, not hard limits; actual usage is reported in
+//! heartbeat metrics.
+
+use crate::ebpf::MapSizes;
+use crate::pipeline::PipelineConfig;
+
+/// Smallest budget we will size for
+pub const MIN_BUDGET_MB: u64 = 16;
+
+/// Upper bound of the automatic budget
+pub const MAX_AUTO_BUDGET_MB: u64 = 512;
+
+/// Automatic budget is 1/64 of RAM (~1.5%)
+const AUTO_RAM_DIVISOR: u64 = 64;
+
+/// Budget when RAM can't be determined
+const FALLBACK_BUDGET_MB: u64 = 64;
+
+/// Kernel memory per FLOWS entry: key, value and LRU hash bookkeeping
+const FLOW_ENTRY_BYTES: u64 = 160;
+
+/// Userspace memory per queued event
+const QUEUED_EVENT_BYTES: u64 = 96;
+
+const MIN_FLOW_ENTRIES: u64 = 4096;
+const MAX_FLOW_ENTRIES: u64 = 1 << 20;
+
+/// Ring buffers must be a power-of-two multiple of the page size
+const MIN_RING_BYTES: u64 = 16 * 1024;
+const MAX_RING_BYTES: u64 = 16 * 1024 * 1024;
+
+const MIN_QUEUE_EVENTS: u64 = 1024;
+const MAX_QUEUE_EVENTS: u64 = 1 << 20;
+
+/// Resolved sizes for kernel maps and userspace queues
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    pub budget_mb: u64,
+    /// Whether the budget was derived from RAM rather than configured
+    pub automatic: bool,
+    pub maps: MapSizes,
+    pub reader_capacity: usize,
+    pub enrich_capacity: usize,
+}
+
+impl MemoryBudget {
+    /// Resolve the configured budget (0 = derive from installed RAM)
+    pub fn resolve(configured_mb: u64) -> Self {
+        if configured_mb > 0 {
+            Self::from_mb(configured_mb, false)
+        } else {
+            Self::from_mb(auto_budget_mb(total_ram_mb()), true)
+        }
+    }
+
+    fn from_mb(budget_mb: u64, automatic: bool) -> Self {
+        let budget_mb = budget_mb.max(MIN_BUDGET_MB);
+        let total = budget_mb << 20;
+        let maps_bytes = total / 2;
+        let rings_bytes = total / 4;
+        let queue_bytes = total / 4;
+
+        // Ring buffers split by expected volume: 4:2:2:2:1:1:2:2:2:1
+        let ring = |parts: u64| ring_bytes(rings_bytes * parts / 19);
+        let maps = MapSizes {
+            flow_entries: (maps_bytes / FLOW_ENTRY_BYTES).clamp(MIN_FLOW_ENTRIES, MAX_FLOW_ENTRIES) as u32,
+            events_ring_bytes: ring(4),
+            drop_ring_bytes: ring(2),
+            flow_ring_bytes: ring(2),
+            rst_ring_bytes: ring(2),
+            nf_ring_bytes: ring(1),
+            retransmit_ring_bytes: ring(1),
+            dns_ring_bytes: ring(2),
+            conntrack_ring_bytes: ring(2),
+            http_ring_bytes: ring(2),
+            // Only filled while `sennet trace --payload` runs
+            payload_ring_bytes: ring(1),
+        };
+
+        // Two thirds of queued events sit between reader and enrichment
+        let queued = queue_bytes / QUEUED_EVENT_BYTES;
+        let queue = |n: u64| n.clamp(MIN_QUEUE_EVENTS, MAX_QUEUE_EVENTS) as usize;
+
+        Self {
+            budget_mb,
+            automatic,
+            maps,
+            reader_capacity: queue(queued * 2 / 3),
+            enrich_capacity: queue(queued / 3),
+        }
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.budget_mb << 20
+    }
+
+    /// Cap pipeline queue capacities to the budget
+    ///
+    /// Capacities configured below the budget are kept.
+    pub fn apply(&self, pipeline: &mut PipelineConfig) {
+        pipeline.reader_capacity = pipeline.reader_capacity.min(self.reader_capacity);
+        pipeline.enrich_capacity = pipeline.enrich_capacity.min(self.enrich_capacity);
+    }
+}
+
+fn auto_budget_mb(ram_mb: Option<u64>) -> u64 {
+    ram_mb
+        .map(|mb| mb / AUTO_RAM_DIVISOR)
+        .unwrap_or(FALLBACK_BUDGET_MB)
+        .clamp(MIN_BUDGET_MB, MAX_AUTO_BUDGET_MB)
+}
+
+/// Largest power of two not above `bytes`, within ring buffer limits
+fn ring_bytes(bytes: u64) -> u32 {
+    let bytes = bytes.clamp(MIN_RING_BYTES, MAX_RING_BYTES);
+    (1u64 << (63 - bytes.leading_zeros())) as u32
+}
+
+/// Installed RAM in MB
+fn total_ram_mb() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    parse_meminfo_kb(&meminfo, "MemTotal").map(|kb| kb / 1024)
+}
+
+fn parse_meminfo_kb(meminfo: &str, field: &str) -> Option<u64> {
+    meminfo.lines().find_map(|line| {
+        let (name, rest) = line.split_once(':')?;
+        if name != field {
+            return None;
+        }
+        rest.split_whitespace().next()?.parse().ok()
+    })
+}
+
+/// Resident memory of this process in bytes
+pub fn resident_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_meminfo_kb(&status, "VmRSS").map(|kb| kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_budget_scales_with_ram() {
+        // 256MB edge box: minimum budget
+        assert_eq!(auto_budget_mb(Some(256)), MIN_BUDGET_MB);
+        // 16GB host: 256MB
+        assert_eq!(auto_budget_mb(Some(16 * 1024)), 256);
+        // 256GB server: capped
+        assert_eq!(auto_budget_mb(Some(256 * 1024)), MAX_AUTO_BUDGET_MB);
+        assert_eq!(auto_budget_mb(None), FALLBACK_BUDGET_MB);
+    }
+
+    #[test]
+    fn test_sizes_follow_budget() {
+        let small = MemoryBudget::from_mb(16, false);
+        let large = MemoryBudget::from_mb(512, false);
+
+        assert_eq!(small.maps.flow_entries, 52428);
+        assert_eq!(large.maps.flow_entries, MAX_FLOW_ENTRIES as u32);
+        assert!(small.maps.drop_ring_bytes < large.maps.drop_ring_bytes);
+        assert!(small.reader_capacity < large.reader_capacity);
+
+        for budget in [small, large] {
+            let m = budget.maps;
+            for ring in [
+                m.events_ring_bytes,
+                m.drop_ring_bytes,
+                m.flow_ring_bytes,
+                m.rst_ring_bytes,
+                m.nf_ring_bytes,
+                m.retransmit_ring_bytes,
+                m.dns_ring_bytes,
+                m.conntrack_ring_bytes,
+                m.http_ring_bytes,
+                m.payload_ring_bytes,
+            ] {
+                assert!(ring.is_power_of_two());
+                assert!(ring as u64 >= MIN_RING_BYTES && ring as u64 <= MAX_RING_BYTES);
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_caps_pipeline_queues() {
+        let budget = MemoryBudget::from_mb(16, false);
+        let mut pipeline = PipelineConfig { reader_capacity: usize::MAX, enrich_capacity: 16, ..Default::default() };
+        budget.apply(&mut pipeline);
+        assert_eq!(pipeline.reader_capacity, budget.reader_capacity);
+        assert_eq!(pipeline.enrich_capacity, 16);
+    }
+
+    #[test]
+    fn test_parse_meminfo() {
+        let meminfo = "MemTotal:       16318412 kB\nMemFree:         1234 kB\n";
+        assert_eq!(parse_meminfo_kb(meminfo, "MemTotal"), Some(16318412));
+        assert_eq!(parse_meminfo_kb(meminfo, "MemAvailable"), None);
+    }
+}
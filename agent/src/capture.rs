@@ -0,0 +1,238 @@
+//! Packet Capture (macOS)
+//!
+//! macOS has no eBPF, so `sennet trace` and `sennet top` watch the wire
+//! with libpcap instead. Kernel drop reasons are not visible from a
+//! capture; what is visible are the packets a failed connection produces:
+//!
+//! - TCP resets (`TCP_RESET`)
+//! - ICMP destination unreachable (`ICMP_UNREACH_*`), reported for the
+//!   packet quoted inside the ICMP message, i.e. the flow that failed
+//!
+//! Only these packets pass the capture filter, so the kernel copies little.
+//! IPv4 only.
+
+use std::net::Ipv4Addr;
+
+use crate::filter::{Field, Value};
+
+/// Capture filter selecting resets and unreachables
+pub const SIGNAL_FILTER: &str = "tcp[tcpflags] & tcp-rst != 0 or icmp[icmptype] == icmp-unreach";
+
+/// pcap link types the parser understands
+pub const LINKTYPE_NULL: i32 = 0;
+pub const LINKTYPE_ETHERNET: i32 = 1;
+pub const LINKTYPE_RAW: i32 = 12;
+
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+/// A captured reset or unreachable, for the flow it affects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signal {
+    /// `TCP_RESET` or `ICMP_UNREACH_<code>`
+    pub reason: String,
+    pub proto: u8,
+    pub src: Ipv4Addr,
+    pub src_port: u16,
+    pub dst: Ipv4Addr,
+    pub dst_port: u16,
+}
+
+impl Signal {
+    pub fn proto_name(&self) -> &'static str {
+        match self.proto {
+            IPPROTO_TCP => "tcp",
+            IPPROTO_UDP => "udp",
+            IPPROTO_ICMP => "icmp",
+            _ => "ip",
+        }
+    }
+}
+
+impl crate::filter::Subject for Signal {
+    fn carries(&self, field: Field) -> bool {
+        matches!(field, Field::Src | Field::Dst | Field::Sport | Field::Dport | Field::Proto | Field::Family)
+    }
+
+    fn get(&self, field: Field) -> Option<Value<'_>> {
+        match field {
+            Field::Src => Some(Value::Addr(self.src)),
+            Field::Dst => Some(Value::Addr(self.dst)),
+            Field::Sport => Some(Value::Num(self.src_port.into())),
+            Field::Dport => Some(Value::Num(self.dst_port.into())),
+            Field::Proto => Some(Value::Num(self.proto.into())),
+            Field::Family => Some(Value::Text("ipv4".into())),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Signal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}:{} → {}:{}", self.proto_name(), self.src, self.src_port, self.dst, self.dst_port)
+    }
+}
+
+fn unreach_code_str(code: u8) -> &'static str {
+    match code {
+        0 => "NET",
+        1 => "HOST",
+        2 => "PROTOCOL",
+        3 => "PORT",
+        4 => "NEEDFRAG",
+        9 | 10 | 13 => "FILTER_PROHIB",
+        _ => "OTHER",
+    }
+}
+
+/// IPv4 header at the start of `data`: (proto, src, dst, payload)
+fn ipv4(data: &[u8]) -> Option<(u8, Ipv4Addr, Ipv4Addr, &[u8])> {
+    if data.len() < 20 || data[0] >> 4 != 4 {
+        return None;
+    }
+    let ihl = ((data[0] & 0x0f) as usize) * 4;
+    let src = Ipv4Addr::new(data[12], data[13], data[14], data[15]);
+    let dst = Ipv4Addr::new(data[16], data[17], data[18], data[19]);
+    Some((data[9], src, dst, data.get(ihl..)?))
+}
+
+fn ports(proto: u8, l4: &[u8]) -> (u16, u16) {
+    match (proto, l4) {
+        (IPPROTO_TCP | IPPROTO_UDP, [a, b, c, d, ..]) => (u16::from_be_bytes([*a, *b]), u16::from_be_bytes([*c, *d])),
+        _ => (0, 0),
+    }
+}
+
+/// Skip the link-layer header to the IPv4 packet
+fn network_layer(linktype: i32, data: &[u8]) -> Option<&[u8]> {
+    match linktype {
+        LINKTYPE_ETHERNET => {
+            // Past 802.1Q and QinQ tags, like the eBPF classifier
+            let (ethertype, offset) = sennet_common::network_header(|offset| {
+                Some(u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]))
+            })?;
+            (ethertype == sennet_common::eth_p::IP).then(|| data.get(offset..)).flatten()
+        }
+        // Loopback: 4-byte address family in host order, AF_INET = 2
+        LINKTYPE_NULL => {
+            let family = u32::from_ne_bytes(data.get(..4)?.try_into().ok()?);
+            (family == 2).then(|| &data[4..])
+        }
+        LINKTYPE_RAW => Some(data),
+        _ => None,
+    }
+}
+
+/// Decode a captured frame into a [`Signal`], if it is one
+pub fn parse(linktype: i32, frame: &[u8]) -> Option<Signal> {
+    let (proto, src, dst, l4) = ipv4(network_layer(linktype, frame)?)?;
+    match proto {
+        IPPROTO_TCP => {
+            let flags = *l4.get(13)?;
+            if flags & 0x04 == 0 {
+                return None;
+            }
+            let (src_port, dst_port) = ports(proto, l4);
+            Some(Signal { reason: "TCP_RESET".to_string(), proto, src, src_port, dst, dst_port })
+        }
+        IPPROTO_ICMP => {
+            if *l4.first()? != 3 {
+                return None;
+            }
+            // The quoted header of the packet that could not be delivered
+            let (inner_proto, inner_src, inner_dst, inner_l4) = ipv4(l4.get(8..)?)?;
+            let (src_port, dst_port) = ports(inner_proto, inner_l4);
+            Some(Signal {
+                reason: format!("ICMP_UNREACH_{}", unreach_code_str(l4[1])),
+                proto: inner_proto,
+                src: inner_src,
+                src_port,
+                dst: inner_dst,
+                dst_port,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Live capture of [`SIGNAL_FILTER`] on `interface` (default: pcap's pick)
+#[cfg(target_os = "macos")]
+pub fn open(interface: Option<&str>) -> anyhow::Result<pcap::Capture<pcap::Active>> {
+    use anyhow::Context;
+
+    let device = match interface {
+        Some(name) => pcap::Device::from(name),
+        None => pcap::Device::lookup()?.context("No capture device found")?,
+    };
+    let name = device.name.clone();
+    let mut capture = pcap::Capture::from_device(device)?
+        .snaplen(256)
+        .timeout(250)
+        .immediate_mode(true)
+        .open()
+        .with_context(|| format!("Failed to capture on {} (needs root or access to /dev/bpf*)", name))?;
+    capture.filter(SIGNAL_FILTER, true)?;
+    Ok(capture)
+}
+
+/// Next signal from `capture`; `Ok(None)` on the read timeout
+#[cfg(target_os = "macos")]
+pub fn next_signal(capture: &mut pcap::Capture<pcap::Active>) -> anyhow::Result<Option<Signal>> {
+    let linktype = capture.get_datalink().0;
+    match capture.next_packet() {
+        Ok(packet) => Ok(parse(linktype, packet.data)),
+        Err(pcap::Error::TimeoutExpired) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip_header(proto: u8, src: [u8; 4], dst: [u8; 4]) -> Vec<u8> {
+        let mut h = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, proto, 0, 0];
+        h.extend(src);
+        h.extend(dst);
+        h
+    }
+
+    #[test]
+    fn test_parse_tcp_reset() {
+        let mut frame = vec![0u8; 12];
+        frame.extend([0x08, 0x00]);
+        frame.extend(ip_header(IPPROTO_TCP, [10, 0, 0, 5], [10, 0, 0, 1]));
+        let mut tcp = vec![0u8; 20];
+        tcp[..4].copy_from_slice(&[0x1f, 0x90, 0xc3, 0x50]); // 8080 → 50000
+        tcp[13] = 0x14; // RST|ACK
+        frame.extend(tcp);
+
+        let signal = parse(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(signal.reason, "TCP_RESET");
+        assert_eq!(signal.to_string(), "tcp 10.0.0.5:8080 → 10.0.0.1:50000");
+
+        // A plain ACK is not a signal
+        let last = frame.len() - 7;
+        frame[last] = 0x10;
+        assert_eq!(parse(LINKTYPE_ETHERNET, &frame), None);
+    }
+
+    #[test]
+    fn test_parse_icmp_unreachable_reports_inner_flow() {
+        let mut packet = ip_header(IPPROTO_ICMP, [192, 168, 1, 1], [192, 168, 1, 20]);
+        packet.extend([3, 3, 0, 0, 0, 0, 0, 0]); // port unreachable
+        packet.extend(ip_header(IPPROTO_UDP, [192, 168, 1, 20], [192, 168, 1, 1]));
+        packet.extend([0xd4, 0x31, 0x00, 0x35]); // 54321 → 53
+
+        let signal = parse(LINKTYPE_RAW, &packet).unwrap();
+        assert_eq!(signal.reason, "ICMP_UNREACH_PORT");
+        assert_eq!(signal.to_string(), "udp 192.168.1.20:54321 → 192.168.1.1:53");
+        assert_eq!(parse(LINKTYPE_NULL, &[0; 3]), None);
+
+        let args: Vec<String> = ["--dst", "192.168.1.1:53", "--proto", "udp"].iter().map(|s| s.to_string()).collect();
+        assert!(crate::trace::TraceFilter::parse(&args).unwrap().matches_flow(&signal));
+        let args: Vec<String> = ["--proto", "tcp"].iter().map(|s| s.to_string()).collect();
+        assert!(!crate::trace::TraceFilter::parse(&args).unwrap().matches_flow(&signal));
+    }
+}
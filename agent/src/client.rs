@@ -4,8 +4,15 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 use crate::config::Config;
+use crate::asn::AsnUsage;
+use crate::daemonset::PodIdentity;
+use crate::ebpf::ConnChurn;
+use crate::flowexport::FlowBatch;
+use crate::latency::TargetLatency;
+use crate::retransmits::RetransmitRate;
 
 /// Metrics summary sent with heartbeat
 #[derive(Debug, Clone, Default, Serialize)]
@@ -17,6 +24,39 @@ pub struct MetricsSummary {
     pub tx_bytes: u64,
     pub drop_count: u64,
     pub uptime_seconds: u64,
+    /// Current conntrack table entries (0 if nf_conntrack not loaded)
+    pub conntrack_count: u64,
+    /// Conntrack table size (nf_conntrack_max)
+    pub conntrack_max: u64,
+    /// Packets dropped because the conntrack table was full
+    pub conntrack_drops: u64,
+    /// Entries evicted early to make room for new connections
+    pub conntrack_early_drops: u64,
+    /// Heartbeat round-trip time percentiles over the recent history
+    pub heartbeat_rtt_p50_ms: f64,
+    pub heartbeat_rtt_p95_ms: f64,
+    /// Latency to configured SLO targets
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub latency_targets: Vec<TargetLatency>,
+    /// Top remote ASNs by flow bytes (requires an ASN database)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub top_asns: Vec<AsnUsage>,
+    /// Destinations with the most TCP retransmits recently (requires the pipeline)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub top_retransmits: Vec<RetransmitRate>,
+    /// TCP connections opened, closed and failed since the eBPF programs
+    /// loaded (requires the inet_sock_set_state tracepoint)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_churn: Option<ConnChurn>,
+    /// Agent resident memory and the configured/derived memory budget
+    pub memory_rss_bytes: u64,
+    pub memory_budget_bytes: u64,
+    /// Events lost so far per ring buffer (kernel and userspace), nonzero only
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub events_lost: BTreeMap<String, u64>,
+    /// kfree_skb drops since the previous heartbeat, by drop reason name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub drops_by_reason: BTreeMap<String, u64>,
 }
 
 /// Heartbeat request payload
@@ -27,6 +67,12 @@ pub struct HeartbeatRequest {
     pub current_version: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metrics: Option<MetricsSummary>,
+    /// nodeName, podName and podNamespace when running as a DaemonSet
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub pod: Option<PodIdentity>,
+    /// Deployment labels from the config
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
 }
 
 /// Command from server
@@ -45,6 +91,21 @@ impl Default for Command {
     }
 }
 
+impl Command {
+    /// Commands that act on the agent, by `allowed_commands` name
+    pub const ACTIONS: [&'static str; 2] = ["upgrade", "reconfigure"];
+
+    /// Name used in `allowed_commands` and the audit log
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::CommandUnspecified => "unspecified",
+            Command::CommandNoop => "noop",
+            Command::CommandUpgrade => "upgrade",
+            Command::CommandReconfigure => "reconfigure",
+        }
+    }
+}
+
 /// Heartbeat response from server
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -75,33 +136,58 @@ impl SentinelClient {
     }
 
     /// Send a heartbeat to the control plane
+    #[tracing::instrument(name = "heartbeat.rpc", skip_all, fields(agent_id = %request.agent_id))]
     pub fn heartbeat(&self, request: &HeartbeatRequest) -> Result<HeartbeatResponse> {
         let url = format!("{}/sentinel.v1.SentinelService/Heartbeat", self.base_url);
         
         // Serialize request body for signing
         let body = serde_json::to_vec(request)
             .context("Failed to serialize request")?;
-        
+
+        let response = self
+            .post_signed(&url, &body)
+            .context("Failed to send heartbeat request")?;
+
+        let resp: HeartbeatResponse = response
+            .into_json()
+            .context("Failed to parse heartbeat response")?;
+
+        Ok(resp)
+    }
+
+    /// Upload a crash report (the JSON written by the panic hook)
+    pub fn report_crash(&self, report: &[u8]) -> Result<()> {
+        let url = format!("{}/api/agent/crash", self.base_url);
+        self.post_signed(&url, report)
+            .context("Failed to upload crash report")?;
+        Ok(())
+    }
+
+    /// Send a batch of flow records for cross-node correlation
+    pub fn export_flows(&self, batch: &FlowBatch) -> Result<()> {
+        let url = format!("{}/sentinel.v1.SentinelService/ExportFlows", self.base_url);
+        let body = serde_json::to_vec(batch).context("Failed to serialize flow batch")?;
+        self.post_signed(&url, &body)
+            .context("Failed to send flow batch")?;
+        Ok(())
+    }
+
+    /// POST a JSON body with the API key and request signature headers
+    fn post_signed(&self, url: &str, body: &[u8]) -> Result<ureq::Response> {
         // Generate timestamp and signature
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .map(|d| d.as_secs() as i64)
             .unwrap_or(0);
-        let signature = crate::crypto::sign_request(&self.api_key, timestamp, &body);
+        let signature = crate::crypto::sign_request(&self.api_key, timestamp, body);
 
-        let response = ureq::post(&url)
+        let response = ureq::post(url)
             .set("Authorization", &format!("Bearer {}", self.api_key))
             .set("Content-Type", "application/json")
             .set("X-Sennet-Timestamp", &timestamp.to_string())
             .set("X-Sennet-Signature", &signature)
-            .send_bytes(&body)
-            .context("Failed to send heartbeat request")?;
-
-        let resp: HeartbeatResponse = response
-            .into_json()
-            .context("Failed to parse heartbeat response")?;
-
-        Ok(resp)
+            .send_bytes(body)?;
+        Ok(response)
     }
 }
 
@@ -121,7 +207,10 @@ mod tests {
                 tx_bytes: 500,
                 drop_count: 0,
                 uptime_seconds: 3600,
+                ..Default::default()
             }),
+            pod: None,
+            labels: BTreeMap::new(),
         };
 
         let json = serde_json::to_string(&request).unwrap();
@@ -130,6 +219,68 @@ mod tests {
         assert!(json.contains("rxPackets"));
     }
 
+    #[test]
+    fn test_metrics_conntrack_serialization() {
+        let metrics = MetricsSummary { conntrack_count: 900, conntrack_max: 1000, ..Default::default() };
+        let json = serde_json::to_string(&metrics).unwrap();
+        assert!(json.contains(r#""conntrackCount":900"#));
+        assert!(json.contains(r#""conntrackMax":1000"#));
+    }
+
+    #[test]
+    fn test_metrics_connection_churn_serialization() {
+        let metrics = MetricsSummary::default();
+        assert!(!serde_json::to_string(&metrics).unwrap().contains("connectionChurn"));
+
+        let metrics = MetricsSummary {
+            connection_churn: Some(ConnChurn { active_opens: 3, ..Default::default() }),
+            ..metrics
+        };
+        let json = serde_json::to_string(&metrics).unwrap();
+        assert!(json.contains(r#""connectionChurn":{"activeOpens":3,"passiveOpens":0,"closes":0,"failed":0}"#));
+    }
+
+    #[test]
+    fn test_heartbeat_request_labels() {
+        let request = HeartbeatRequest {
+            agent_id: "test-uuid".to_string(),
+            current_version: "1.0.0".to_string(),
+            metrics: None,
+            pod: None,
+            labels: BTreeMap::new(),
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(!json.contains("labels"));
+
+        let request = HeartbeatRequest {
+            labels: BTreeMap::from([("env".to_string(), "prod".to_string())]),
+            ..request
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains(r#""labels":{"env":"prod"}"#));
+    }
+
+    #[test]
+    fn test_heartbeat_request_pod() {
+        let request = HeartbeatRequest {
+            agent_id: "test-uuid".to_string(),
+            current_version: "1.0.0".to_string(),
+            metrics: None,
+            pod: None,
+            labels: BTreeMap::new(),
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(!json.contains("nodeName"));
+
+        let request = HeartbeatRequest {
+            pod: Some(PodIdentity { node_name: "worker-1".to_string(), ..Default::default() }),
+            ..request
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains(r#""nodeName":"worker-1""#));
+        assert!(!json.contains("podName"));
+    }
+
     #[test]
     fn test_heartbeat_response_deserialization() {
         let json = r#"{
@@ -0,0 +1,170 @@
+//! Event Timestamps
+//!
+//! eBPF events are stamped with `bpf_ktime_get_ns`, which reads
+//! CLOCK_MONOTONIC: nanoseconds since boot, not counting time the host spent
+//! suspended. They are mapped to wall-clock time with an offset
+//! (CLOCK_REALTIME − CLOCK_MONOTONIC) computed once and re-checked every
+//! `RECHECK_INTERVAL`, so NTP steps are picked up.
+//!
+//! A suspend moves the offset by its duration. It shows up as growth of
+//! CLOCK_BOOTTIME − CLOCK_MONOTONIC, which is checked on every conversion;
+//! events stamped before the suspend was noticed (still queued in a ring
+//! buffer) keep the offset from before it.
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often the offset is recomputed without a suspend
+const RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Change in suspended time treated as a suspend rather than jitter
+const SUSPEND_THRESHOLD_NS: i64 = 1_000_000;
+
+/// The three clocks, in nanoseconds, read back to back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clocks {
+    pub realtime: i64,
+    pub monotonic: i64,
+    pub boottime: i64,
+}
+
+impl Clocks {
+    #[cfg(target_os = "linux")]
+    pub fn read() -> Self {
+        let read = |clock| {
+            let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+            // SAFETY: ts is a valid timespec to write into
+            unsafe { libc::clock_gettime(clock, &mut ts) };
+            ts.tv_sec * 1_000_000_000 + ts.tv_nsec
+        };
+        Self {
+            monotonic: read(libc::CLOCK_MONOTONIC),
+            realtime: read(libc::CLOCK_REALTIME),
+            boottime: read(libc::CLOCK_BOOTTIME),
+        }
+    }
+
+    /// No kernel event timestamps to convert: treat them as Unix time
+    #[cfg(not(target_os = "linux"))]
+    pub fn read() -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as i64).unwrap_or(0);
+        Self { realtime: now, monotonic: 0, boottime: 0 }
+    }
+
+    /// Time spent suspended since boot
+    fn suspended(&self) -> i64 {
+        self.boottime - self.monotonic
+    }
+}
+
+/// Converts `bpf_ktime_get_ns` timestamps to wall-clock time
+#[derive(Debug)]
+pub struct WallClock {
+    /// CLOCK_REALTIME − CLOCK_MONOTONIC
+    offset_ns: i64,
+    suspended_ns: i64,
+    checked_ns: i64,
+    /// Latest timestamp converted so far
+    last_ns: u64,
+    /// (last timestamp before a suspend was noticed, offset before it)
+    before_suspend: Option<(u64, i64)>,
+}
+
+impl WallClock {
+    pub fn new(clocks: Clocks) -> Self {
+        Self {
+            offset_ns: clocks.realtime - clocks.monotonic,
+            suspended_ns: clocks.suspended(),
+            checked_ns: clocks.monotonic,
+            last_ns: 0,
+            before_suspend: None,
+        }
+    }
+
+    /// Wall-clock time of `ktime_ns`, given the clocks now
+    pub fn convert(&mut self, ktime_ns: u64, now: Clocks) -> SystemTime {
+        if (now.suspended() - self.suspended_ns).abs() > SUSPEND_THRESHOLD_NS {
+            self.before_suspend = Some((self.last_ns, self.offset_ns));
+            self.resync(now);
+        } else if now.monotonic - self.checked_ns >= RECHECK_INTERVAL.as_nanos() as i64 {
+            self.resync(now);
+        }
+
+        let offset = match self.before_suspend {
+            Some((last, offset)) if ktime_ns <= last => offset,
+            _ => self.offset_ns,
+        };
+        self.last_ns = self.last_ns.max(ktime_ns);
+        let unix_ns = (ktime_ns as i64).saturating_add(offset).max(0);
+        UNIX_EPOCH + Duration::from_nanos(unix_ns as u64)
+    }
+
+    fn resync(&mut self, now: Clocks) {
+        self.offset_ns = now.realtime - now.monotonic;
+        self.suspended_ns = now.suspended();
+        self.checked_ns = now.monotonic;
+    }
+}
+
+/// Wall-clock time of an event timestamp, through a process-wide `WallClock`
+pub fn wall_time(ktime_ns: u64) -> SystemTime {
+    static CLOCK: Mutex<Option<WallClock>> = Mutex::new(None);
+
+    let now = Clocks::read();
+    let mut clock = CLOCK.lock().unwrap_or_else(|e| e.into_inner());
+    clock.get_or_insert_with(|| WallClock::new(now)).convert(ktime_ns, now)
+}
+
+/// RFC 3339 in UTC with nanoseconds, as exports carry it
+pub fn rfc3339(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: i64 = 1_000_000_000;
+
+    fn clocks(realtime: i64, monotonic: i64, suspended: i64) -> Clocks {
+        Clocks { realtime, monotonic, boottime: monotonic + suspended }
+    }
+
+    fn unix_ns(time: SystemTime) -> i64 {
+        time.duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64
+    }
+
+    #[test]
+    fn test_convert_applies_boot_offset() {
+        // Booted 100s before 1_700_000_000
+        let boot = 1_700_000_000 * SEC - 100 * SEC;
+        let mut clock = WallClock::new(clocks(boot + 100 * SEC, 100 * SEC, 0));
+
+        let time = clock.convert(99 * SEC as u64, clocks(boot + 100 * SEC, 100 * SEC, 0));
+        assert_eq!(unix_ns(time), boot + 99 * SEC);
+        assert_eq!(rfc3339(time), "2023-11-14T22:13:19.000000000Z");
+    }
+
+    #[test]
+    fn test_suspend_moves_offset_for_later_events_only() {
+        let boot = 1_000 * SEC;
+        let mut clock = WallClock::new(clocks(boot + 10 * SEC, 10 * SEC, 0));
+        assert_eq!(unix_ns(clock.convert(10 * SEC as u64, clocks(boot + 10 * SEC, 10 * SEC, 0))), boot + 10 * SEC);
+
+        // Suspended for an hour: monotonic barely moved, wall time did
+        let resumed = clocks(boot + 3_611 * SEC, 11 * SEC, 3_600 * SEC);
+        let after = clock.convert(11 * SEC as u64, resumed);
+        assert_eq!(unix_ns(after), boot + 3_611 * SEC);
+        // Stamped before the suspend, converted after it
+        let queued = clock.convert(10 * SEC as u64 - 1, resumed);
+        assert_eq!(unix_ns(queued), boot + 10 * SEC - 1);
+    }
+
+    #[test]
+    fn test_recheck_picks_up_clock_steps() {
+        let mut clock = WallClock::new(clocks(5_000 * SEC, 10 * SEC, 0));
+        // NTP stepped the clock forward 2s; noticed on the next recheck
+        let stepped = clocks(5_003 * SEC, 11 * SEC, 0);
+        assert_eq!(unix_ns(clock.convert(11 * SEC as u64, stepped)), 5_003 * SEC);
+    }
+}
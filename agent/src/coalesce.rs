@@ -0,0 +1,212 @@
+//! Drop Event Coalescing
+//!
+//! During drop storms the kernel reports the same drop thousands of times
+//! per second. Identical drops seen within a short window are merged into
+//! one record carrying a count and the first/last kernel timestamps, so
+//! enrichment and export run once per burst instead of once per packet.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::ebpf::DropEvent;
+
+/// Fields that make two drops "the same"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DropKey {
+    pub reason: u32,
+    pub ifindex: u32,
+    pub protocol: u16,
+    /// Owning socket, so bursts stay attributable to one flow
+    pub tuple: (u32, u32, u16, u16),
+    /// The packet's own addresses, ports and IP protocol, so drops of
+    /// socketless packets from different sources aren't merged
+    pub packet: ([u8; 16], [u8; 16], u16, u16, u8),
+}
+
+impl From<&DropEvent> for DropKey {
+    fn from(event: &DropEvent) -> Self {
+        Self {
+            reason: event.reason,
+            ifindex: event.ifindex,
+            protocol: event.protocol,
+            tuple: (event.src_ip, event.dst_ip, event.src_port, event.dst_port),
+            packet: (
+                event.src_addr,
+                event.dst_addr,
+                event.packet_src_port,
+                event.packet_dst_port,
+                event.ip_protocol,
+            ),
+        }
+    }
+}
+
+/// One or more identical drops
+#[derive(Debug, Clone, Copy)]
+pub struct CoalescedDrop {
+    /// First drop of the burst (its timestamp is the first timestamp)
+    pub event: DropEvent,
+    /// Kernel drops represented, including those sampled away
+    pub count: u64,
+    pub last_timestamp_ns: u64,
+}
+
+struct Pending {
+    drop: CoalescedDrop,
+    opened: Instant,
+}
+
+/// Merges identical drops within a fixed window
+pub struct DropCoalescer {
+    window: Duration,
+    pending: HashMap<DropKey, Pending>,
+}
+
+impl DropCoalescer {
+    /// Distinct keys held open at once; beyond this, drops pass through
+    pub const MAX_PENDING: usize = 1024;
+
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Add a drop observed at `now`
+    ///
+    /// Returns a record that is ready to be emitted: the previous burst for
+    /// this key if its window has closed, or the drop itself if coalescing
+    /// is disabled or too many keys are open.
+    pub fn push(&mut self, event: DropEvent, now: Instant) -> Option<CoalescedDrop> {
+        let weight = u64::from(event.sample_rate.max(1));
+        let single = CoalescedDrop {
+            event,
+            count: weight,
+            last_timestamp_ns: event.timestamp_ns,
+        };
+        if self.window.is_zero() {
+            return Some(single);
+        }
+
+        let key = DropKey::from(&event);
+        let at_capacity = self.pending.len() >= Self::MAX_PENDING;
+        match self.pending.get_mut(&key) {
+            Some(p) if now.duration_since(p.opened) < self.window => {
+                p.drop.count += weight;
+                p.drop.last_timestamp_ns = p.drop.last_timestamp_ns.max(event.timestamp_ns);
+                None
+            }
+            Some(p) => {
+                let closed = std::mem::replace(p, Pending { drop: single, opened: now });
+                Some(closed.drop)
+            }
+            None if at_capacity => Some(single),
+            None => {
+                self.pending.insert(key, Pending { drop: single, opened: now });
+                None
+            }
+        }
+    }
+
+    /// Remove and return bursts whose window closed before `now`
+    pub fn flush_expired(&mut self, now: Instant) -> Vec<CoalescedDrop> {
+        let window = self.window;
+        let mut expired = Vec::new();
+        self.pending.retain(|_, p| {
+            if now.duration_since(p.opened) >= window {
+                expired.push(p.drop);
+                false
+            } else {
+                true
+            }
+        });
+        expired
+    }
+
+    /// Remove and return all open bursts
+    pub fn flush_all(&mut self) -> Vec<CoalescedDrop> {
+        self.pending.drain().map(|(_, p)| p.drop).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drop_at(reason: u32, ts: u64) -> DropEvent {
+        DropEvent { timestamp_ns: ts, reason, ifindex: 2, ..Default::default() }
+    }
+
+    #[test]
+    fn test_identical_drops_merge_within_window() {
+        let mut c = DropCoalescer::new(Duration::from_millis(100));
+        let t0 = Instant::now();
+
+        for i in 0..1000 {
+            assert!(c.push(drop_at(7, 1_000 + i), t0 + Duration::from_micros(i)).is_none());
+        }
+        // Different reason gets its own record
+        assert!(c.push(drop_at(2, 5_000), t0).is_none());
+        assert!(c.flush_expired(t0 + Duration::from_millis(50)).is_empty());
+
+        let mut out = c.flush_expired(t0 + Duration::from_millis(100));
+        out.sort_by_key(|d| d.event.reason);
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[1].count, 1000);
+        assert_eq!(out[1].event.timestamp_ns, 1_000);
+        assert_eq!(out[1].last_timestamp_ns, 1_999);
+    }
+
+    #[test]
+    fn test_late_drop_closes_previous_burst() {
+        let mut c = DropCoalescer::new(Duration::from_millis(10));
+        let t0 = Instant::now();
+        c.push(drop_at(7, 1), t0);
+        c.push(drop_at(7, 2), t0);
+
+        let closed = c.push(drop_at(7, 3), t0 + Duration::from_millis(20)).unwrap();
+        assert_eq!(closed.count, 2);
+        // The late drop starts a new burst
+        let rest = c.flush_all();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].event.timestamp_ns, 3);
+    }
+
+    #[test]
+    fn test_packet_sources_stay_separate() {
+        let mut c = DropCoalescer::new(Duration::from_millis(100));
+        let t0 = Instant::now();
+        // NO_SOCKET drops have no owning socket, only the packet's headers
+        let from = |src: u8, sport: u16| DropEvent {
+            src_addr: sennet_common::ipv4_mapped([198, 51, 100, src]),
+            dst_addr: sennet_common::ipv4_mapped([10, 0, 0, 1]),
+            packet_src_port: sport,
+            packet_dst_port: 443,
+            ip_protocol: 6,
+            ..drop_at(2, 1)
+        };
+
+        c.push(from(1, 40000), t0);
+        c.push(from(1, 40000), t0);
+        c.push(from(2, 40000), t0);
+        c.push(from(1, 40001), t0);
+
+        let mut out = c.flush_all();
+        out.sort_by_key(|d| (d.event.src_addr, d.event.packet_src_port));
+        assert_eq!(out.iter().map(|d| d.count).collect::<Vec<_>>(), vec![2, 1, 1]);
+        assert_eq!(out[2].event.src_addr, sennet_common::ipv4_mapped([198, 51, 100, 2]));
+    }
+
+    #[test]
+    fn test_zero_window_passes_through() {
+        let mut c = DropCoalescer::new(Duration::ZERO);
+        let out = c.push(drop_at(7, 1), Instant::now()).unwrap();
+        assert_eq!(out.count, 1);
+        assert!(c.flush_all().is_empty());
+    }
+}
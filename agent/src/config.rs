@@ -4,26 +4,98 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::fs;
 
+use crate::ebpf::AttachMode;
+use crate::logging::LogFormat;
+use crate::pipeline::PipelineConfig;
+use crate::ratelimit::RateLimitConfig;
+
 /// Agent configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     /// API key for authentication with the control plane
+    #[serde(default)]
     pub api_key: String,
 
     /// URL of the Sennet control plane
+    #[serde(default)]
     pub server_url: String,
 
+    /// Run without a control plane: no heartbeats, upgrade checks or crash
+    /// uploads; api_key and server_url may be left out
+    #[serde(default)]
+    pub offline: bool,
+
     /// Log level (trace, debug, info, warn, error)
     #[serde(default = "default_log_level")]
     pub log_level: String,
 
+    /// Log output format (text or json)
+    #[serde(default)]
+    pub log_format: LogFormat,
+
+    /// Log to the systemd journal when started by systemd (text format only)
+    #[serde(default = "default_true")]
+    pub log_journald: bool,
+
+    /// Write logs to this file instead of stdout (None = stdout)
+    #[serde(default)]
+    pub log_file: Option<PathBuf>,
+
+    /// Rotate the log file when it reaches this size in MB
+    #[serde(default = "default_log_file_max_size")]
+    pub log_file_max_size_mb: u64,
+
+    /// Rotated log files to keep besides the current one
+    #[serde(default = "default_log_file_max_files")]
+    pub log_file_max_files: usize,
+
     /// Network interface to monitor (None = auto-detect)
     #[serde(default)]
     pub interface: Option<String>,
 
+    /// Interfaces to monitor together, replacing `interface` when set
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+
+    /// Monitor every up, non-loopback interface (ignored when `interfaces`
+    /// is set)
+    #[serde(default)]
+    pub all_interfaces: bool,
+
+    /// Hook counting ingress packets: TC classifier, or XDP for high
+    /// packet rates
+    #[serde(default)]
+    pub attach_mode: AttachMode,
+
+    /// Look at 1 in N packets for large-packet and reset events
+    /// (0 or 1 = every packet, max 255); counters stay exact
+    #[serde(default)]
+    pub packet_sample_one_in: u32,
+
+    /// Packets longer than this many bytes are reported as large-packet
+    /// events
+    #[serde(default = "default_large_packet_threshold")]
+    pub large_packet_threshold: u32,
+
+    /// Report ingress sources sending more than this many packets per
+    /// second as rate anomalies (0 = disabled)
+    #[serde(default)]
+    pub source_pps_threshold: u32,
+
+    /// Drop packets matching the `sennet block` blocklist in the TC/XDP
+    /// programs instead of only observing them
+    #[serde(default)]
+    pub enforcement: bool,
+
+    /// Server ports whose HTTP/1.x request lines are sampled for
+    /// `sennet http` (empty = off)
+    #[serde(default)]
+    pub http_ports: Vec<u16>,
+
     /// Heartbeat interval in seconds
     #[serde(default = "default_heartbeat_interval")]
     pub heartbeat_interval_secs: u64,
@@ -32,6 +104,119 @@ pub struct Config {
     #[serde(default = "default_state_dir")]
     pub state_dir: PathBuf,
 
+    /// Conntrack table utilization (percent) at which to raise an alert
+    #[serde(default = "default_conntrack_alert_pct")]
+    pub conntrack_alert_pct: u8,
+
+    /// HTTP(S) endpoints to probe for latency SLOs
+    #[serde(default)]
+    pub latency_targets: Vec<String>,
+
+    /// Interval between latency probes in seconds
+    #[serde(default = "default_latency_probe_interval")]
+    pub latency_probe_interval_secs: u64,
+
+    /// Offline ASN database (iptoasn TSV) for per-ASN bandwidth
+    /// (None = `<state_dir>/ip2asn-v4.tsv` if present)
+    #[serde(default)]
+    pub asn_db_path: Option<PathBuf>,
+
+    /// Daemon event pipeline settings
+    #[serde(default)]
+    pub pipeline: PipelineConfig,
+
+    /// Drain the kernel flow map into rollups every N seconds (0 = disabled)
+    #[serde(default)]
+    pub flow_rollup_interval_secs: u64,
+
+    /// Send flow records to the control plane for cross-node correlation
+    /// every N seconds (0 = disabled)
+    #[serde(default)]
+    pub flow_export_interval_secs: u64,
+
+    /// Keep the last N minutes of pipeline summaries and packet counters on
+    /// disk for `sennet export` (0 = disabled; needs the pipeline)
+    #[serde(default)]
+    pub blackbox_minutes: u64,
+
+    /// Kernel-side per-event-type rate limits
+    #[serde(default)]
+    pub rate_limits: RateLimitConfig,
+
+    /// Memory budget for maps, ring buffers and queues in MB (0 = derive from RAM)
+    #[serde(default)]
+    pub memory_budget_mb: u64,
+
+    /// Address of the local HTTP listener for /metrics, /healthz and
+    /// /readyz, e.g. "127.0.0.1:9464" (None = disabled)
+    #[serde(default)]
+    pub http_listen: Option<String>,
+
+    /// /readyz fails if no heartbeat succeeded within this many seconds
+    #[serde(default = "default_ready_heartbeat_window")]
+    pub ready_heartbeat_window_secs: u64,
+
+    /// OTLP/HTTP collector for internal tracing spans, e.g.
+    /// "http://otel-collector:4318" (None = disabled)
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+
+    /// Send crash reports from previous runs to the control plane on start
+    #[serde(default)]
+    pub crash_report_upload: bool,
+
+    /// Drop capabilities not needed at runtime once eBPF programs are
+    /// loaded (keeps CAP_BPF and CAP_NET_ADMIN)
+    #[serde(default = "default_true")]
+    pub drop_privileges: bool,
+
+    /// Leave the maps pinned under /sys/fs/bpf/sennet when the agent stops,
+    /// so their last contents can still be read
+    #[serde(default)]
+    pub keep_pinned_maps: bool,
+
+    /// Pin the maps under /sys/fs/bpf/sennet-<name> instead, so several
+    /// agents can run on one host (None = /sys/fs/bpf/sennet)
+    #[serde(default)]
+    pub pin_namespace: Option<String>,
+
+    /// Tracing probes to detach once loaded: drops, netfilter, flows. They
+    /// can be attached again through the control socket (`sennet probes`)
+    #[serde(default)]
+    pub disabled_probes: Vec<String>,
+
+    /// Unix socket the CLI uses to query the running daemon (None = disabled)
+    #[serde(default = "default_control_socket")]
+    pub control_socket: Option<PathBuf>,
+
+    /// Group given read/write access to the control and gRPC sockets
+    #[serde(default = "default_control_socket_group")]
+    pub control_socket_group: String,
+
+    /// Unix socket of the local gRPC API, e.g. "/run/sennet-grpc.sock",
+    /// with the control socket's mode and group (None = disabled)
+    #[serde(default)]
+    pub grpc_listen: Option<PathBuf>,
+
+    /// Address of the read-only REST API, e.g. "127.0.0.1:9465"
+    /// (None = disabled)
+    #[serde(default)]
+    pub api_listen: Option<String>,
+
+    /// Bearer token the REST API requires (None = no auth, loopback only)
+    #[serde(default)]
+    pub api_token: Option<String>,
+
+    /// Deployment metadata (env, team, region) attached to heartbeats,
+    /// metrics, logs, summaries and flow rollups
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+
+    /// Control-plane commands the agent acts on; others are logged and
+    /// ignored (names from `Command::ACTIONS`)
+    #[serde(default = "default_allowed_commands")]
+    pub allowed_commands: Vec<String>,
+
     /// Path where config was loaded from (not serialized)
     #[serde(skip)]
     pub config_path: PathBuf,
@@ -41,11 +226,76 @@ fn default_log_level() -> String {
     "info".to_string()
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_log_file_max_size() -> u64 {
+    10
+}
+
+fn default_log_file_max_files() -> usize {
+    5
+}
+
+fn default_ready_heartbeat_window() -> u64 {
+    300
+}
+
+fn default_control_socket() -> Option<PathBuf> {
+    cfg!(unix).then(|| PathBuf::from("/run/sennet.sock"))
+}
+
+fn default_allowed_commands() -> Vec<String> {
+    crate::client::Command::ACTIONS.iter().map(|c| c.to_string()).collect()
+}
+
+/// Ports from a comma-separated variable; entries that aren't ports are
+/// skipped
+fn ports_from_env(value: &str) -> Vec<u16> {
+    value.split(',').filter_map(|p| p.trim().parse().ok()).filter(|&p| p != 0).collect()
+}
+
+/// Comma-separated list; empty allows nothing
+fn list_from_env(value: &str) -> Vec<String> {
+    value.split(',').map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect()
+}
+
+fn default_control_socket_group() -> String {
+    "sennet".to_string()
+}
+
+/// Whether a listen address is loopback; errors if it isn't an IP and port
+fn is_loopback(listen: &str) -> Result<bool> {
+    let addr: std::net::SocketAddr = listen
+        .parse()
+        .map_err(|_| anyhow::anyhow!("'{}' must be an IP address and port", listen))?;
+    Ok(addr.ip().is_loopback())
+}
+
+/// SENNET_CONTROL_SOCKET or SENNET_GRPC_LISTEN value; empty disables the
+/// socket
+fn socket_from_env(value: String) -> Option<PathBuf> {
+    (!value.is_empty()).then(|| PathBuf::from(value))
+}
+
+fn default_large_packet_threshold() -> u32 {
+    sennet_common::DEFAULT_LARGE_PACKET_THRESHOLD
+}
+
 fn default_heartbeat_interval() -> u64 {
     30
 }
 
-fn default_state_dir() -> PathBuf {
+pub(crate) fn default_conntrack_alert_pct() -> u8 {
+    90
+}
+
+fn default_latency_probe_interval() -> u64 {
+    60
+}
+
+pub fn default_state_dir() -> PathBuf {
     if cfg!(unix) {
         PathBuf::from("/var/lib/sennet")
     } else {
@@ -55,27 +305,137 @@ fn default_state_dir() -> PathBuf {
     }
 }
 
+/// Default state directory of the agent with `pin_namespace`:
+/// `default_state_dir()`, or `/var/lib/sennet-<namespace>`
+pub fn state_dir_for(namespace: Option<&str>) -> PathBuf {
+    match namespace {
+        Some(namespace) => namespaced(&default_state_dir(), namespace),
+        None => default_state_dir(),
+    }
+}
+
+/// `path` with `-<namespace>` after its file stem, e.g.
+/// `/run/sennet-<namespace>.sock` for `/run/sennet.sock`
+pub fn namespaced(path: &Path, namespace: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push("-");
+    name.push(namespace);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
 impl Config {
     /// Load configuration from default locations or environment
     pub fn load() -> Result<Self> {
         // Check env vars first - takes priority
-        if let (Ok(api_key), Ok(server_url)) = (
-            std::env::var("SENNET_API_KEY"),
-            std::env::var("SENNET_SERVER_URL"),
-        ) {
-            let config = Config {
-                api_key,
-                server_url,
+        let offline = std::env::var("SENNET_OFFLINE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(false);
+        let api_key = std::env::var("SENNET_API_KEY");
+        let server_url = std::env::var("SENNET_SERVER_URL");
+        if offline || (api_key.is_ok() && server_url.is_ok()) {
+            let mut config = Config {
+                api_key: api_key.unwrap_or_default(),
+                server_url: server_url.unwrap_or_default(),
+                offline,
                 log_level: std::env::var("SENNET_LOG_LEVEL").unwrap_or_else(|_| default_log_level()),
+                log_format: std::env::var("SENNET_LOG_FORMAT")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_default(),
+                log_journald: true,
+                log_file: std::env::var("SENNET_LOG_FILE").ok().map(PathBuf::from),
+                log_file_max_size_mb: default_log_file_max_size(),
+                log_file_max_files: default_log_file_max_files(),
                 interface: std::env::var("SENNET_INTERFACE").ok(),
+                interfaces: std::env::var("SENNET_INTERFACES").map(|s| list_from_env(&s)).unwrap_or_default(),
+                all_interfaces: std::env::var("SENNET_ALL_INTERFACES")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(false),
+                attach_mode: std::env::var("SENNET_ATTACH_MODE")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_default(),
+                packet_sample_one_in: std::env::var("SENNET_PACKET_SAMPLE_ONE_IN")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_default(),
+                large_packet_threshold: std::env::var("SENNET_LARGE_PACKET_THRESHOLD")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_else(default_large_packet_threshold),
+                source_pps_threshold: std::env::var("SENNET_SOURCE_PPS_THRESHOLD")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0),
+                enforcement: std::env::var("SENNET_ENFORCEMENT")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(false),
+                http_ports: std::env::var("SENNET_HTTP_PORTS").map(|s| ports_from_env(&s)).unwrap_or_default(),
                 heartbeat_interval_secs: std::env::var("SENNET_HEARTBEAT_INTERVAL")
                     .ok()
                     .and_then(|s| s.parse().ok())
                     .unwrap_or_else(default_heartbeat_interval),
                 state_dir: default_state_dir(),
+                conntrack_alert_pct: std::env::var("SENNET_CONNTRACK_ALERT_PCT")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_else(default_conntrack_alert_pct),
+                latency_targets: std::env::var("SENNET_LATENCY_TARGETS")
+                    .map(|s| s.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect())
+                    .unwrap_or_default(),
+                latency_probe_interval_secs: default_latency_probe_interval(),
+                asn_db_path: std::env::var("SENNET_ASN_DB").ok().map(PathBuf::from),
+                pipeline: PipelineConfig::default(),
+                flow_rollup_interval_secs: 0,
+                flow_export_interval_secs: 0,
+                blackbox_minutes: 0,
+                rate_limits: RateLimitConfig::default(),
+                memory_budget_mb: std::env::var("SENNET_MEMORY_BUDGET_MB")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0),
+                http_listen: std::env::var("SENNET_HTTP_LISTEN").ok(),
+                ready_heartbeat_window_secs: default_ready_heartbeat_window(),
+                otlp_endpoint: std::env::var("SENNET_OTLP_ENDPOINT").ok(),
+                crash_report_upload: std::env::var("SENNET_CRASH_REPORT_UPLOAD")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(false),
+                drop_privileges: std::env::var("SENNET_DROP_PRIVILEGES")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(true),
+                keep_pinned_maps: std::env::var("SENNET_KEEP_PINNED_MAPS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(false),
+                pin_namespace: std::env::var("SENNET_PIN_NAMESPACE").ok().filter(|s| !s.is_empty()),
+                disabled_probes: std::env::var("SENNET_DISABLED_PROBES").map(|s| list_from_env(&s)).unwrap_or_default(),
+                control_socket: std::env::var("SENNET_CONTROL_SOCKET")
+                    .map(socket_from_env)
+                    .unwrap_or_else(|_| default_control_socket()),
+                control_socket_group: default_control_socket_group(),
+                grpc_listen: std::env::var("SENNET_GRPC_LISTEN").ok().and_then(socket_from_env),
+                api_listen: std::env::var("SENNET_API_LISTEN").ok(),
+                api_token: std::env::var("SENNET_API_TOKEN").ok(),
+                labels: match std::env::var("SENNET_LABELS") {
+                    Ok(list) => crate::labels::parse_list(&list)?,
+                    Err(_) => BTreeMap::new(),
+                },
+                allowed_commands: std::env::var("SENNET_ALLOWED_COMMANDS")
+                    .map(|s| list_from_env(&s))
+                    .unwrap_or_else(|_| default_allowed_commands()),
                 config_path: PathBuf::from("env"),
             };
             config.validate()?;
+            config.namespace_paths();
             return Ok(config);
         }
 
@@ -89,7 +449,7 @@ impl Config {
         }
 
         anyhow::bail!(
-            "No configuration found. Tried: {:?}\nOr set SENNET_API_KEY and SENNET_SERVER_URL environment variables.",
+            "No configuration found. Tried: {:?}\nOr set SENNET_API_KEY and SENNET_SERVER_URL (or SENNET_OFFLINE=true) environment variables.",
             paths
         );
     }
@@ -111,17 +471,117 @@ impl Config {
         if let Ok(server_url) = std::env::var("SENNET_SERVER_URL") {
             config.server_url = server_url;
         }
+        if let Some(offline) = std::env::var("SENNET_OFFLINE").ok().and_then(|s| s.parse().ok()) {
+            config.offline = offline;
+        }
         if let Ok(log_level) = std::env::var("SENNET_LOG_LEVEL") {
             config.log_level = log_level;
         }
+        if let Ok(log_file) = std::env::var("SENNET_LOG_FILE") {
+            config.log_file = Some(PathBuf::from(log_file));
+        }
+        if let Some(format) = std::env::var("SENNET_LOG_FORMAT").ok().and_then(|s| s.parse().ok()) {
+            config.log_format = format;
+        }
         if let Ok(interface) = std::env::var("SENNET_INTERFACE") {
             config.interface = Some(interface);
         }
+        if let Ok(interfaces) = std::env::var("SENNET_INTERFACES") {
+            config.interfaces = list_from_env(&interfaces);
+        }
+        if let Some(all) = std::env::var("SENNET_ALL_INTERFACES").ok().and_then(|s| s.parse().ok()) {
+            config.all_interfaces = all;
+        }
+        if let Some(mode) = std::env::var("SENNET_ATTACH_MODE").ok().and_then(|s| s.parse().ok()) {
+            config.attach_mode = mode;
+        }
+        if let Some(one_in) = std::env::var("SENNET_PACKET_SAMPLE_ONE_IN").ok().and_then(|s| s.parse().ok()) {
+            config.packet_sample_one_in = one_in;
+        }
+        if let Some(bytes) = std::env::var("SENNET_LARGE_PACKET_THRESHOLD").ok().and_then(|s| s.parse().ok()) {
+            config.large_packet_threshold = bytes;
+        }
+        if let Some(pps) = std::env::var("SENNET_SOURCE_PPS_THRESHOLD").ok().and_then(|s| s.parse().ok()) {
+            config.source_pps_threshold = pps;
+        }
+        if let Some(enforce) = std::env::var("SENNET_ENFORCEMENT").ok().and_then(|s| s.parse().ok()) {
+            config.enforcement = enforce;
+        }
+        if let Ok(ports) = std::env::var("SENNET_HTTP_PORTS") {
+            config.http_ports = ports_from_env(&ports);
+        }
+        if let Ok(asn_db) = std::env::var("SENNET_ASN_DB") {
+            config.asn_db_path = Some(PathBuf::from(asn_db));
+        }
+        if let Ok(targets) = std::env::var("SENNET_LATENCY_TARGETS") {
+            config.latency_targets = targets.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+        }
+        if let Some(pct) = std::env::var("SENNET_CONNTRACK_ALERT_PCT").ok().and_then(|s| s.parse().ok()) {
+            config.conntrack_alert_pct = pct;
+        }
+        if let Some(mb) = std::env::var("SENNET_MEMORY_BUDGET_MB").ok().and_then(|s| s.parse().ok()) {
+            config.memory_budget_mb = mb;
+        }
+        if let Ok(listen) = std::env::var("SENNET_HTTP_LISTEN") {
+            config.http_listen = Some(listen);
+        }
+        if let Ok(endpoint) = std::env::var("SENNET_OTLP_ENDPOINT") {
+            config.otlp_endpoint = Some(endpoint);
+        }
+        if let Some(upload) = std::env::var("SENNET_CRASH_REPORT_UPLOAD").ok().and_then(|s| s.parse().ok()) {
+            config.crash_report_upload = upload;
+        }
+        if let Some(drop) = std::env::var("SENNET_DROP_PRIVILEGES").ok().and_then(|s| s.parse().ok()) {
+            config.drop_privileges = drop;
+        }
+        if let Some(keep) = std::env::var("SENNET_KEEP_PINNED_MAPS").ok().and_then(|s| s.parse().ok()) {
+            config.keep_pinned_maps = keep;
+        }
+        if let Ok(namespace) = std::env::var("SENNET_PIN_NAMESPACE") {
+            config.pin_namespace = Some(namespace).filter(|s| !s.is_empty());
+        }
+        if let Ok(probes) = std::env::var("SENNET_DISABLED_PROBES") {
+            config.disabled_probes = list_from_env(&probes);
+        }
+        if let Ok(socket) = std::env::var("SENNET_CONTROL_SOCKET") {
+            config.control_socket = socket_from_env(socket);
+        }
+        if let Ok(listen) = std::env::var("SENNET_GRPC_LISTEN") {
+            config.grpc_listen = socket_from_env(listen);
+        }
+        if let Ok(listen) = std::env::var("SENNET_API_LISTEN") {
+            config.api_listen = Some(listen);
+        }
+        if let Ok(token) = std::env::var("SENNET_API_TOKEN") {
+            config.api_token = Some(token);
+        }
+        if let Ok(list) = std::env::var("SENNET_LABELS") {
+            config.labels.extend(crate::labels::parse_list(&list)?);
+        }
+        if let Ok(commands) = std::env::var("SENNET_ALLOWED_COMMANDS") {
+            config.allowed_commands = list_from_env(&commands);
+        }
 
         config.validate()?;
+        config.namespace_paths();
         Ok(config)
     }
 
+    /// Give an agent with a `pin_namespace` its own state directory and
+    /// control socket, unless those were configured, so agents sharing a
+    /// host don't share an identity or a socket
+    fn namespace_paths(&mut self) {
+        let Some(namespace) = self.pin_namespace.as_deref() else {
+            return;
+        };
+        if self.state_dir == default_state_dir() {
+            self.state_dir = state_dir_for(Some(namespace));
+        }
+        if self.control_socket == default_control_socket() {
+            self.control_socket = self.control_socket.as_deref().map(|path| namespaced(path, namespace));
+        }
+    }
+
     /// Get the path where config was loaded from
     pub fn config_path(&self) -> &Path {
         &self.config_path
@@ -129,17 +589,74 @@ impl Config {
 
     /// Validate the configuration
     fn validate(&self) -> Result<()> {
-        if self.api_key.is_empty() {
-            anyhow::bail!("api_key cannot be empty");
+        if !self.offline {
+            if self.api_key.is_empty() {
+                anyhow::bail!("api_key cannot be empty (set offline: true to run without a control plane)");
+            }
+            if !self.api_key.starts_with("sk_") {
+                anyhow::bail!("api_key must start with 'sk_'");
+            }
+            if self.server_url.is_empty() {
+                anyhow::bail!("server_url cannot be empty (set offline: true to run without a control plane)");
+            }
+            if !self.server_url.starts_with("http://") && !self.server_url.starts_with("https://") {
+                anyhow::bail!("server_url must start with http:// or https://");
+            }
+        }
+        if self.large_packet_threshold == 0 {
+            anyhow::bail!("large_packet_threshold must be greater than 0");
+        }
+        if !(1..=100).contains(&self.conntrack_alert_pct) {
+            anyhow::bail!("conntrack_alert_pct must be between 1 and 100, got {}", self.conntrack_alert_pct);
         }
-        if !self.api_key.starts_with("sk_") {
-            anyhow::bail!("api_key must start with 'sk_'");
+        for target in &self.latency_targets {
+            if !target.starts_with("http://") && !target.starts_with("https://") {
+                anyhow::bail!("latency target '{}' must start with http:// or https://", target);
+            }
+        }
+        for name in self.labels.keys() {
+            if !crate::labels::is_valid_name(name) {
+                anyhow::bail!("label '{}' must match [a-zA-Z_][a-zA-Z0-9_]* and not start with __", name);
+            }
+        }
+        for command in &self.allowed_commands {
+            if !crate::client::Command::ACTIONS.contains(&command.as_str()) {
+                anyhow::bail!(
+                    "allowed_commands: unknown command '{}' (expected {})",
+                    command,
+                    crate::client::Command::ACTIONS.join(", ")
+                );
+            }
+        }
+        if let Some(namespace) = &self.pin_namespace {
+            if !crate::ebpf::is_valid_pin_namespace(namespace) {
+                anyhow::bail!("pin_namespace '{}' must be letters, digits, '-', '_' or '.'", namespace);
+            }
         }
-        if self.server_url.is_empty() {
-            anyhow::bail!("server_url cannot be empty");
+        #[cfg(target_os = "linux")]
+        crate::pipeline::check_reader_cpus(&self.pipeline.reader_cpus)?;
+        for probe in &self.disabled_probes {
+            probe.parse::<crate::ebpf::Probe>().map_err(|e| anyhow::anyhow!("disabled_probes: {}", e))?;
         }
-        if !self.server_url.starts_with("http://") && !self.server_url.starts_with("https://") {
-            anyhow::bail!("server_url must start with http:// or https://");
+        // The gRPC API has no authentication; the socket's mode and group
+        // decide who can use it
+        if let Some(listen) = &self.grpc_listen {
+            if !listen.is_absolute() {
+                anyhow::bail!(
+                    "grpc_listen '{}' must be an absolute Unix socket path, e.g. /run/sennet-grpc.sock",
+                    listen.display()
+                );
+            }
+        }
+        if let Some(token) = &self.api_token {
+            if token.trim().is_empty() {
+                anyhow::bail!("api_token cannot be empty");
+            }
+        }
+        if let Some(listen) = &self.api_listen {
+            if self.api_token.is_none() && !is_loopback(listen)? {
+                anyhow::bail!("api_listen '{}' needs api_token unless it is a loopback address", listen);
+            }
         }
         Ok(())
     }
@@ -202,6 +719,8 @@ log_level: debug
         assert_eq!(config.server_url, "https://sennet.example.com");
         assert_eq!(config.log_level, "debug");
         assert!(config.interface.is_none());
+        assert!(config.interfaces.is_empty());
+        assert!(!config.all_interfaces);
     }
 
     #[test]
@@ -211,12 +730,16 @@ log_level: debug
 api_key: sk_test123456789
 server_url: https://sennet.example.com
 interface: eth0
+interfaces: [eth0, eth1]
+attach_mode: xdp
 "#;
         let path = create_test_config(&dir, config_content);
         
         let config = Config::load_from_file(&path).unwrap();
         
         assert_eq!(config.interface, Some("eth0".to_string()));
+        assert_eq!(config.interfaces, vec!["eth0", "eth1"]);
+        assert_eq!(config.attach_mode, AttachMode::Xdp);
     }
 
     #[test]
@@ -262,7 +785,172 @@ server_url: https://sennet.example.com
         let config = Config::load_from_file(&path).unwrap();
         
         assert_eq!(config.log_level, "info");
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert_eq!(config.attach_mode, AttachMode::Tc);
+        assert_eq!(config.large_packet_threshold, 9000);
+        assert_eq!(config.source_pps_threshold, 0);
+        assert!(config.log_journald);
+        assert!(config.log_file.is_none());
+        assert_eq!(config.log_file_max_size_mb, 10);
+        assert_eq!(config.log_file_max_files, 5);
         assert_eq!(config.heartbeat_interval_secs, 30);
+        assert_eq!(config.conntrack_alert_pct, 90);
+        assert!(config.latency_targets.is_empty());
+        assert_eq!(config.latency_probe_interval_secs, 60);
+        assert!(!config.pipeline.enabled);
+        assert_eq!(config.flow_rollup_interval_secs, 0);
+        assert_eq!(config.flow_export_interval_secs, 0);
+        assert_eq!(config.blackbox_minutes, 0);
+        assert!(!config.enforcement);
+        assert!(config.http_ports.is_empty());
+        assert!(!config.rate_limits.is_enabled());
+        assert_eq!(config.pipeline.reader_capacity, 8192);
+        assert_eq!(config.memory_budget_mb, 0);
+        assert!(config.http_listen.is_none());
+        assert_eq!(config.ready_heartbeat_window_secs, 300);
+        assert!(config.otlp_endpoint.is_none());
+        assert!(!config.crash_report_upload);
+        assert!(config.drop_privileges);
+        assert!(!config.keep_pinned_maps);
+        assert!(config.pin_namespace.is_none());
+        assert!(config.disabled_probes.is_empty());
+        assert!(!config.offline);
+        assert_eq!(config.control_socket.as_deref(), Some(Path::new("/run/sennet.sock")));
+        assert_eq!(config.control_socket_group, "sennet");
+        assert!(config.grpc_listen.is_none());
+        assert!(config.api_listen.is_none());
+        assert!(config.api_token.is_none());
+        assert_eq!(config.allowed_commands, ["upgrade", "reconfigure"]);
+        assert!(config.labels.is_empty());
+    }
+
+    #[test]
+    fn test_offline_without_credentials() {
+        let dir = TempDir::new().unwrap();
+        let path = create_test_config(&dir, "log_level: debug\n");
+        assert!(Config::load_from_file(&path).unwrap_err().to_string().contains("offline"));
+
+        let path = create_test_config(&dir, "offline: true\n");
+        let config = Config::load_from_file(&path).unwrap();
+        assert!(config.offline);
+        assert!(config.api_key.is_empty());
+    }
+
+    #[test]
+    fn test_latency_targets() {
+        let dir = TempDir::new().unwrap();
+        let config_content = r#"
+api_key: sk_test123456789
+server_url: https://sennet.example.com
+latency_targets:
+  - https://api.example.com/health
+"#;
+        let path = create_test_config(&dir, config_content);
+        let config = Config::load_from_file(&path).unwrap();
+        assert_eq!(config.latency_targets, vec!["https://api.example.com/health"]);
+
+        let bad = create_test_config(&dir, r#"
+api_key: sk_test123456789
+server_url: https://sennet.example.com
+latency_targets:
+  - api.example.com
+"#);
+        assert!(Config::load_from_file(&bad).is_err());
+    }
+
+    #[test]
+    fn test_conntrack_alert_pct_range() {
+        let dir = TempDir::new().unwrap();
+        let path = create_test_config(&dir, "offline: true\nconntrack_alert_pct: 100\n");
+        assert_eq!(Config::load_from_file(&path).unwrap().conntrack_alert_pct, 100);
+
+        for pct in [0, 101] {
+            let path = create_test_config(&dir, &format!("offline: true\nconntrack_alert_pct: {}\n", pct));
+            assert!(Config::load_from_file(&path).is_err(), "{}", pct);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_reader_cpus_must_be_pinnable() {
+        let dir = TempDir::new().unwrap();
+        let path = create_test_config(&dir, "offline: true\npipeline:\n  reader_cpus: [0]\n");
+        assert_eq!(Config::load_from_file(&path).unwrap().pipeline.reader_cpus, [0]);
+
+        // Past CPU_SETSIZE, where CPU_SET would panic
+        let path = create_test_config(&dir, "offline: true\npipeline:\n  reader_cpus: [0, 4096]\n");
+        let err = Config::load_from_file(&path).unwrap_err();
+        assert!(format!("{:#}", err).contains("CPU 4096 is out of range"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_grpc_listen_unix_socket_only() {
+        let dir = TempDir::new().unwrap();
+        let path = create_test_config(&dir, "offline: true\ngrpc_listen: /run/sennet-grpc.sock\n");
+        let config = Config::load_from_file(&path).unwrap();
+        assert_eq!(config.grpc_listen.as_deref(), Some(Path::new("/run/sennet-grpc.sock")));
+
+        // TCP addresses, loopback or not, are reachable by every local user
+        for listen in ["127.0.0.1:50051", "0.0.0.0:50051", "localhost:50051"] {
+            let path = create_test_config(&dir, &format!("offline: true\ngrpc_listen: \"{}\"\n", listen));
+            assert!(Config::load_from_file(&path).is_err(), "{}", listen);
+        }
+    }
+
+    #[test]
+    fn test_labels() {
+        let dir = TempDir::new().unwrap();
+        let path = create_test_config(&dir, "offline: true\nlabels:\n  env: prod\n  team: netops\n");
+        let config = Config::load_from_file(&path).unwrap();
+        assert_eq!(config.labels.get("team").map(String::as_str), Some("netops"));
+
+        let path = create_test_config(&dir, "offline: true\nlabels:\n  cost-center: \"42\"\n");
+        assert!(Config::load_from_file(&path).unwrap_err().to_string().contains("cost-center"));
+    }
+
+    #[test]
+    fn test_pin_namespace_paths() {
+        let dir = TempDir::new().unwrap();
+        let path = create_test_config(&dir, "offline: true\npin_namespace: test\n");
+        let config = Config::load_from_file(&path).unwrap();
+        assert_eq!(config.state_dir, state_dir_for(Some("test")));
+        #[cfg(unix)]
+        {
+            assert_eq!(config.state_dir, Path::new("/var/lib/sennet-test"));
+            assert_eq!(config.control_socket.as_deref(), Some(Path::new("/run/sennet-test.sock")));
+        }
+
+        // Configured paths are kept
+        let content = "offline: true\npin_namespace: test\nstate_dir: /srv/sennet\ncontrol_socket: /run/a.sock\n";
+        let config = Config::load_from_file(&create_test_config(&dir, content)).unwrap();
+        assert_eq!(config.state_dir, Path::new("/srv/sennet"));
+        assert_eq!(config.control_socket.as_deref(), Some(Path::new("/run/a.sock")));
+
+        let path = create_test_config(&dir, "offline: true\n");
+        assert_eq!(Config::load_from_file(&path).unwrap().state_dir, default_state_dir());
+    }
+
+    #[test]
+    fn test_allowed_commands() {
+        let dir = TempDir::new().unwrap();
+        let path = create_test_config(&dir, "offline: true\nallowed_commands: []\n");
+        assert!(Config::load_from_file(&path).unwrap().allowed_commands.is_empty());
+
+        let path = create_test_config(&dir, "offline: true\nallowed_commands: [upgrade, restart]\n");
+        assert!(Config::load_from_file(&path).unwrap_err().to_string().contains("restart"));
+    }
+
+    #[test]
+    fn test_api_listen_needs_token_off_loopback() {
+        let dir = TempDir::new().unwrap();
+        let path = create_test_config(&dir, "offline: true\napi_listen: \"127.0.0.1:9465\"\n");
+        assert!(Config::load_from_file(&path).is_ok());
+
+        let path = create_test_config(&dir, "offline: true\napi_listen: \"0.0.0.0:9465\"\n");
+        assert!(Config::load_from_file(&path).unwrap_err().to_string().contains("api_token"));
+
+        let path = create_test_config(&dir, "offline: true\napi_listen: \"0.0.0.0:9465\"\napi_token: s3cret\n");
+        assert_eq!(Config::load_from_file(&path).unwrap().api_token.as_deref(), Some("s3cret"));
     }
 
     // Note: Tests that use env vars can't run in parallel safely.
@@ -0,0 +1,876 @@
+//! Conntrack Table Monitoring
+//!
+//! Reads netfilter connection tracking utilization from procfs:
+//! - /proc/sys/net/netfilter/nf_conntrack_count (current entries)
+//! - /proc/sys/net/netfilter/nf_conntrack_max (table size)
+//! - /proc/net/stat/nf_conntrack (per-CPU insert/drop/eviction stats)
+//!
+//! When the table fills, the kernel drops new connections with a
+//! NETFILTER_DROP reason, which is otherwise indistinguishable from a
+//! firewall rule drop. The monitor raises an alert before that happens and
+//! flags drops that coincide with table exhaustion.
+//!
+//! Conntrack entries are also used to resolve NAT: for a SNAT/DNAT'd
+//! connection the original and reply tuples differ, which gives the address
+//! the upstream network actually sees for a local flow.
+//!
+//! `sennet conntrack` lists the tracked connections with their state, or
+//! with `--watch` follows entries being created and destroyed as the
+//! conntrack kprobes report them.
+//! Usage: sennet conntrack [OPTIONS]
+
+use anyhow::Result;
+use colored::Colorize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+#[cfg(target_os = "linux")]
+use std::fs;
+
+/// Snapshot of conntrack table utilization and eviction statistics
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConntrackStats {
+    /// Current number of tracked connections
+    pub count: u64,
+    /// Maximum table size (nf_conntrack_max)
+    pub max: u64,
+    /// Packets dropped because a new entry could not be created (table full)
+    pub drop: u64,
+    /// Entries evicted early to make room for new connections
+    pub early_drop: u64,
+    /// Failed insertions (races or table full)
+    pub insert_failed: u64,
+    /// Invalid packets that could not be tracked
+    pub invalid: u64,
+}
+
+impl ConntrackStats {
+    /// Table utilization in percent (0.0 - 100.0)
+    pub fn utilization_pct(&self) -> f64 {
+        if self.max == 0 {
+            return 0.0;
+        }
+        (self.count as f64 / self.max as f64) * 100.0
+    }
+
+    /// Total packets lost to table exhaustion (drops + failed inserts)
+    pub fn exhaustion_drops(&self) -> u64 {
+        self.drop + self.insert_failed
+    }
+}
+
+/// Read current conntrack statistics
+///
+/// Returns None if the nf_conntrack module is not loaded.
+#[cfg(target_os = "linux")]
+pub fn read_stats() -> Option<ConntrackStats> {
+    let count = read_u64("/proc/sys/net/netfilter/nf_conntrack_count")?;
+    let max = read_u64("/proc/sys/net/netfilter/nf_conntrack_max")?;
+
+    let mut stats = ConntrackStats {
+        count,
+        max,
+        ..Default::default()
+    };
+
+    // Per-CPU stats are optional (may be hidden in containers)
+    if let Ok(content) = fs::read_to_string("/proc/net/stat/nf_conntrack") {
+        let totals = parse_stat_table(&content);
+        stats.drop = totals.get("drop").copied().unwrap_or(0);
+        stats.early_drop = totals.get("early_drop").copied().unwrap_or(0);
+        stats.insert_failed = totals.get("insert_failed").copied().unwrap_or(0);
+        stats.invalid = totals.get("invalid").copied().unwrap_or(0);
+    }
+
+    Some(stats)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_stats() -> Option<ConntrackStats> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn read_u64(path: &str) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Parse /proc/net/stat/nf_conntrack into column totals
+///
+/// Format: a header line with column names followed by one line of
+/// hex values per CPU. Columns vary by kernel version, so values are
+/// summed by name rather than position.
+pub fn parse_stat_table(content: &str) -> HashMap<String, u64> {
+    let mut lines = content.lines();
+    let mut totals = HashMap::new();
+
+    let header: Vec<&str> = match lines.next() {
+        Some(h) => h.split_whitespace().collect(),
+        None => return totals,
+    };
+
+    for line in lines {
+        for (name, value) in header.iter().zip(line.split_whitespace()) {
+            // "entries" is a global gauge repeated on every CPU line, not a counter
+            if *name == "entries" {
+                continue;
+            }
+            if let Ok(v) = u64::from_str_radix(value, 16) {
+                *totals.entry(name.to_string()).or_insert(0) += v;
+            }
+        }
+    }
+
+    totals
+}
+
+/// Alert severity for conntrack utilization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertLevel {
+    /// Utilization crossed the configured threshold
+    Warning,
+    /// Table is full and packets are being dropped
+    Critical,
+}
+
+/// Alert raised by the conntrack monitor
+#[derive(Debug, Clone)]
+pub struct ConntrackAlert {
+    pub level: AlertLevel,
+    pub utilization_pct: f64,
+    /// Exhaustion drops since the previous check
+    pub new_drops: u64,
+    pub message: String,
+}
+
+/// Percentage points utilization must fall below the threshold before a
+/// warning clears, so a table hovering at the threshold doesn't flap
+const HYSTERESIS_PCT: f64 = 5.0;
+
+/// Tracks conntrack utilization over time and raises alerts
+pub struct ConntrackMonitor {
+    threshold_pct: f64,
+    last: Option<ConntrackStats>,
+    /// Level of the condition currently alerted on, None while healthy
+    level: Option<AlertLevel>,
+}
+
+impl ConntrackMonitor {
+    /// Create a monitor that warns at `threshold_pct` utilization
+    pub fn new(threshold_pct: u8) -> Self {
+        Self {
+            threshold_pct: threshold_pct.min(100) as f64,
+            last: None,
+            level: None,
+        }
+    }
+
+    /// Evaluate a new snapshot, returning an alert when the table enters a
+    /// worse state; staying in a state or easing off raises nothing
+    pub fn check(&mut self, stats: &ConntrackStats) -> Option<ConntrackAlert> {
+        let new_drops = match &self.last {
+            Some(prev) => stats.exhaustion_drops().saturating_sub(prev.exhaustion_drops()),
+            None => 0,
+        };
+        let utilization = stats.utilization_pct();
+        self.last = Some(stats.clone());
+
+        // Once warned, stay warned until utilization is clearly lower
+        let clear_below = match self.level {
+            Some(_) => self.threshold_pct - HYSTERESIS_PCT,
+            None => self.threshold_pct,
+        };
+        let level = if new_drops > 0 {
+            Some(AlertLevel::Critical)
+        } else if utilization >= clear_below {
+            Some(AlertLevel::Warning)
+        } else {
+            None
+        };
+        let previous = std::mem::replace(&mut self.level, level);
+
+        match (previous, level) {
+            (Some(AlertLevel::Critical), Some(AlertLevel::Critical)) => None,
+            (_, Some(AlertLevel::Critical)) => Some(ConntrackAlert {
+                level: AlertLevel::Critical,
+                utilization_pct: utilization,
+                new_drops,
+                message: format!(
+                    "conntrack table full ({}/{} entries): {} packets dropped; NETFILTER_DROP events in this window are likely conntrack exhaustion, not firewall rules",
+                    stats.count, stats.max, new_drops
+                ),
+            }),
+            (None, Some(AlertLevel::Warning)) => Some(ConntrackAlert {
+                level: AlertLevel::Warning,
+                utilization_pct: utilization,
+                new_drops: 0,
+                message: format!(
+                    "conntrack table {:.1}% full ({}/{} entries); raise nf_conntrack_max or shorten timeouts before new connections are dropped",
+                    utilization, stats.count, stats.max
+                ),
+            }),
+            (Some(_), None) => {
+                info!("conntrack utilization back to {:.1}% ({}/{})", utilization, stats.count, stats.max);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Run the monitor loop forever, logging alerts
+    pub async fn run(mut self, interval: Duration) {
+        if read_stats().is_none() {
+            debug!("nf_conntrack not loaded, conntrack monitoring disabled");
+            return;
+        }
+
+        info!("Starting conntrack monitor (threshold: {:.0}%)", self.threshold_pct);
+
+        loop {
+            if let Some(stats) = read_stats() {
+                if let Some(alert) = self.check(&stats) {
+                    match alert.level {
+                        AlertLevel::Warning => {
+                            warn!(utilization_pct = alert.utilization_pct, "{}", alert.message)
+                        }
+                        AlertLevel::Critical => {
+                            error!(new_drops = alert.new_drops, "{}", alert.message)
+                        }
+                    }
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// One direction of a conntrack connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tuple {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub sport: u16,
+    pub dport: u16,
+}
+
+impl Tuple {
+    /// The same tuple seen from the other end
+    pub fn reversed(&self) -> Tuple {
+        Tuple {
+            src: self.dst,
+            dst: self.src,
+            sport: self.dport,
+            dport: self.sport,
+        }
+    }
+}
+
+impl fmt::Display for Tuple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} → {}", SocketFmt(self.src, self.sport), SocketFmt(self.dst, self.dport))
+    }
+}
+
+/// ip:port formatting with IPv6 brackets
+struct SocketFmt(IpAddr, u16);
+
+impl fmt::Display for SocketFmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            IpAddr::V4(ip) => write!(f, "{}:{}", ip, self.1),
+            IpAddr::V6(ip) => write!(f, "[{}]:{}", ip, self.1),
+        }
+    }
+}
+
+/// Kind of address translation applied to a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatKind {
+    Snat,
+    Dnat,
+    Both,
+}
+
+impl NatKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NatKind::Snat => "SNAT",
+            NatKind::Dnat => "DNAT",
+            NatKind::Both => "SNAT+DNAT",
+        }
+    }
+}
+
+/// A tracked connection with its original and reply tuples
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConntrackEntry {
+    /// IP protocol number (6 = TCP, 17 = UDP)
+    pub protocol: u8,
+    /// Tuple as sent by the initiator
+    pub original: Tuple,
+    /// Tuple as expected from the responder (after NAT)
+    pub reply: Tuple,
+    /// Protocol state (TCP: ESTABLISHED, TIME_WAIT, ...); None for
+    /// stateless protocols
+    pub state: Option<String>,
+    /// Seconds until the entry expires unless more packets arrive
+    pub timeout_secs: u32,
+    /// Seen traffic in both directions long enough not to be evicted early
+    pub assured: bool,
+    /// No reply seen yet
+    pub unreplied: bool,
+}
+
+impl ConntrackEntry {
+    /// Type of NAT applied, or None for untranslated connections
+    pub fn nat_kind(&self) -> Option<NatKind> {
+        let expected = self.original.reversed();
+        let snat = self.reply.dst != expected.dst || self.reply.dport != expected.dport;
+        let dnat = self.reply.src != expected.src || self.reply.sport != expected.sport;
+        match (snat, dnat) {
+            (true, true) => Some(NatKind::Both),
+            (true, false) => Some(NatKind::Snat),
+            (false, true) => Some(NatKind::Dnat),
+            (false, false) => None,
+        }
+    }
+
+    /// Original-direction tuple after translation (what the wire sees)
+    pub fn translated(&self) -> Tuple {
+        self.reply.reversed()
+    }
+}
+
+/// Parse one conntrack entry line
+///
+/// Accepts both /proc/net/nf_conntrack lines
+/// (`ipv4 2 tcp 6 117 ESTABLISHED src=.. dst=.. sport=.. dport=.. src=.. ...`)
+/// and `conntrack -L` output, which omits the leading address family.
+/// Protocols without ports (ICMP) are skipped.
+pub fn parse_entry(line: &str) -> Option<ConntrackEntry> {
+    let mut tokens = line.split_whitespace();
+    let mut protocol = None;
+    let mut prev = "";
+    let mut addrs: Vec<IpAddr> = Vec::with_capacity(4);
+    let mut ports: Vec<u16> = Vec::with_capacity(4);
+    let mut timeout_secs = None;
+    let mut state = None;
+    let mut assured = false;
+    let mut unreplied = false;
+
+    for token in tokens.by_ref() {
+        if protocol.is_none() {
+            if matches!(prev, "tcp" | "udp" | "sctp" | "udplite" | "dccp" | "unknown") {
+                protocol = token.parse::<u8>().ok();
+            }
+            prev = token;
+            continue;
+        }
+        match token.split_once('=') {
+            Some(("src" | "dst", value)) => addrs.push(value.parse().ok()?),
+            Some(("sport" | "dport", value)) => ports.push(value.parse().ok()?),
+            Some(_) => {}
+            None if token == "[ASSURED]" => assured = true,
+            None if token == "[UNREPLIED]" => unreplied = true,
+            // The timeout follows the protocol number, then the state if
+            // the protocol has one, all before the first key=value
+            None if addrs.is_empty() && timeout_secs.is_none() => timeout_secs = token.parse().ok(),
+            None if addrs.is_empty() => state = Some(token.to_string()),
+            None => {}
+        }
+    }
+
+    if addrs.len() < 4 || ports.len() < 4 {
+        return None;
+    }
+
+    Some(ConntrackEntry {
+        protocol: protocol?,
+        original: Tuple { src: addrs[0], dst: addrs[1], sport: ports[0], dport: ports[1] },
+        reply: Tuple { src: addrs[2], dst: addrs[3], sport: ports[2], dport: ports[3] },
+        state,
+        timeout_secs: timeout_secs.unwrap_or(0),
+        assured,
+        unreplied,
+    })
+}
+
+/// Read all conntrack entries
+///
+/// Uses /proc/net/nf_conntrack when available, falling back to the
+/// `conntrack` CLI on kernels built without CONFIG_NF_CONNTRACK_PROCFS.
+#[cfg(target_os = "linux")]
+pub fn read_entries() -> Result<Vec<ConntrackEntry>> {
+    let content = match fs::read_to_string("/proc/net/nf_conntrack") {
+        Ok(c) => c,
+        Err(_) => match std::process::Command::new("conntrack").arg("-L").output() {
+            Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout).into_owned(),
+            _ => anyhow::bail!("conntrack entries unavailable (no /proc/net/nf_conntrack and no conntrack CLI)"),
+        },
+    };
+    Ok(content.lines().filter_map(parse_entry).collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_entries() -> Result<Vec<ConntrackEntry>> {
+    anyhow::bail!("Connection tracking requires Linux")
+}
+
+/// Read all conntrack entries that have NAT applied
+pub fn read_nat_entries() -> Vec<ConntrackEntry> {
+    match read_entries() {
+        Ok(entries) => entries.into_iter().filter(|e| e.nat_kind().is_some()).collect(),
+        Err(e) => {
+            debug!("{}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Index of NAT'd connections for looking up local flows
+#[derive(Debug, Default)]
+pub struct NatTable {
+    entries: Vec<ConntrackEntry>,
+    index: HashMap<(u8, Tuple), usize>,
+}
+
+impl NatTable {
+    /// Build a table from conntrack entries
+    pub fn new(entries: Vec<ConntrackEntry>) -> Self {
+        let mut index = HashMap::with_capacity(entries.len() * 2);
+        for (i, entry) in entries.iter().enumerate() {
+            // Locally initiated: the socket holds the original tuple
+            index.insert((entry.protocol, entry.original), i);
+            // Locally accepted after DNAT: the socket holds the reply tuple,
+            // seen from the initiator's side
+            index.entry((entry.protocol, entry.reply.reversed())).or_insert(i);
+        }
+        Self { entries, index }
+    }
+
+    /// Load the current NAT table from the kernel
+    pub fn load() -> Self {
+        Self::new(read_nat_entries())
+    }
+
+    /// Find the conntrack entry for a flow tuple, in either orientation
+    pub fn lookup(&self, protocol: u8, tuple: &Tuple) -> Option<&ConntrackEntry> {
+        self.index
+            .get(&(protocol, *tuple))
+            .or_else(|| self.index.get(&(protocol, tuple.reversed())))
+            .map(|&i| &self.entries[i])
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Options for the conntrack command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConntrackOptions {
+    /// Only entries of this IP protocol
+    pub protocol: Option<u8>,
+    /// Only entries in this state (case-insensitive)
+    pub state: Option<String>,
+    /// Only translated entries
+    pub nat_only: bool,
+    pub limit: usize,
+    /// Follow new and destroyed entries instead of listing the table
+    pub watch: bool,
+    pub timeout_secs: u64,
+}
+
+impl Default for ConntrackOptions {
+    fn default() -> Self {
+        Self {
+            protocol: None,
+            state: None,
+            nat_only: false,
+            limit: 50,
+            watch: false,
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// Parse command line arguments for the conntrack command
+pub fn parse_args(args: &[String]) -> ConntrackOptions {
+    let mut opts = ConntrackOptions::default();
+    let mut i = 0;
+
+    while i < args.len() {
+        match args[i].as_str() {
+            "--proto" | "-p" if i + 1 < args.len() => {
+                opts.protocol = match args[i + 1].to_lowercase().as_str() {
+                    "tcp" => Some(6),
+                    "udp" => Some(17),
+                    "sctp" => Some(132),
+                    other => other.parse().ok(),
+                };
+                i += 1;
+            }
+            "--state" if i + 1 < args.len() => {
+                opts.state = Some(args[i + 1].to_uppercase());
+                i += 1;
+            }
+            "--nat" => opts.nat_only = true,
+            "--limit" if i + 1 < args.len() => {
+                opts.limit = args[i + 1].parse().unwrap_or(50);
+                i += 1;
+            }
+            "--watch" | "-w" => opts.watch = true,
+            "--timeout" | "-t" if i + 1 < args.len() => {
+                opts.timeout_secs = args[i + 1].parse().unwrap_or(10);
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    opts
+}
+
+/// Print help for the conntrack command
+pub fn print_help() {
+    println!("{}", "Sennet Conntrack - Tracked Connections".bold());
+    println!("List netfilter's tracked connections with their state and NAT, or follow");
+    println!("entries being created and destroyed.");
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet conntrack [OPTIONS]");
+    println!();
+    println!("{}", "OPTIONS:".yellow());
+    println!("    -p, --proto <PROTO>    Only tcp, udp, sctp or a protocol number");
+    println!("    --state <STATE>        Only entries in this state (e.g. ESTABLISHED; not with --watch)");
+    println!("    --nat                  Only translated connections");
+    println!("    --limit <N>            Entries to list (default: 50)");
+    println!("    -w, --watch            Follow new and destroyed entries");
+    println!("    -t, --timeout <SECS>   How long to watch (default: 10)");
+    println!("    -h, --help             Show this help message");
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sennet conntrack --proto tcp --state established");
+    println!("    sennet conntrack --nat             # Masqueraded and port-forwarded");
+    println!("    sennet conntrack --watch -t 60     # A minute of connection churn");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    - Listing reads /proc/net/nf_conntrack, or `conntrack -L` without it");
+    println!("    - --watch requires the agent to be running (reads pinned eBPF maps)");
+    println!("    - Entries are reported when confirmed (first packet passed the");
+    println!("      firewall) and when destroyed; state changes in between are not");
+    println!("    - Counts honor kernel sampling and rate limits (rate_limits.conntrack)");
+}
+
+impl ConntrackEntry {
+    /// True if the entry passes the protocol, state and NAT filters
+    fn matches(&self, opts: &ConntrackOptions) -> bool {
+        opts.protocol.is_none_or(|p| p == self.protocol)
+            && opts.state.as_ref().is_none_or(|s| self.state.as_ref() == Some(s))
+            && (!opts.nat_only || self.nat_kind().is_some())
+    }
+
+    /// State column: the protocol state, else whether replies were seen
+    fn state_str(&self) -> &str {
+        match (&self.state, self.unreplied) {
+            (Some(state), _) => state,
+            (None, true) => "UNREPLIED",
+            (None, false) => "REPLIED",
+        }
+    }
+}
+
+/// Run the conntrack command
+pub fn run(args: &[String]) -> Result<()> {
+    let opts = parse_args(args);
+    if opts.watch {
+        return watch(&opts);
+    }
+
+    let entries = read_entries()?;
+    let total = entries.len();
+    let shown: Vec<_> = entries.iter().filter(|e| e.matches(&opts)).collect();
+
+    println!("{}", "Sennet Conntrack".bold());
+    println!("{}", "═".repeat(100));
+    if shown.is_empty() {
+        println!("{}", "No matching conntrack entries.".yellow());
+        return Ok(());
+    }
+    println!(
+        "{:<5} {:<12} {:>7}  {:<60} {}",
+        "PROTO".cyan(),
+        "STATE".cyan(),
+        "TIMEOUT".cyan(),
+        "CONNECTION".cyan(),
+        "NAT".cyan()
+    );
+    println!("{}", "─".repeat(100));
+    for entry in shown.iter().take(opts.limit) {
+        let nat = match entry.nat_kind() {
+            Some(kind) => format!("{} {}", kind.as_str(), entry.translated()),
+            None => String::new(),
+        };
+        let state = if entry.assured { entry.state_str().green() } else { entry.state_str().normal() };
+        println!(
+            "{:<5} {:<12} {:>6}s  {:<60} {}",
+            crate::ebpf::ip_proto_str(entry.protocol),
+            state,
+            entry.timeout_secs,
+            entry.original.to_string(),
+            nat.dimmed()
+        );
+    }
+
+    let mut by_state: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in &shown {
+        *by_state.entry(entry.state_str()).or_insert(0) += 1;
+    }
+    println!("{}", "─".repeat(100));
+    let states: Vec<String> = by_state.iter().map(|(state, n)| format!("{} {}", n, state)).collect();
+    println!("{} of {} entries: {}", shown.len(), total, states.join(", "));
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn watch(opts: &ConntrackOptions) -> Result<()> {
+    use crate::ebpf::{describe_conntrack, open_pinned_ringbuf, ConntrackEvent};
+    use crate::events::RingKind;
+    use std::time::Instant;
+
+    let mut ct_rb = open_pinned_ringbuf("ct_events").map_err(|e| {
+        anyhow::anyhow!("{}\nIs the agent running with an eBPF build that supports conntrack tracing?", e)
+    })?;
+
+    println!("{}", "Sennet Conntrack".bold());
+    println!("Watching conntrack entries for {}s...", opts.timeout_secs.to_string().yellow());
+    println!("{}", "─".repeat(100));
+
+    // The kernel side doesn't report protocol states
+    let filter = ConntrackOptions { state: None, ..opts.clone() };
+    let start = Instant::now();
+    let timeout = Duration::from_secs(opts.timeout_secs);
+    let mut losses = crate::ebpf::LossTracker::new();
+    let (mut created, mut destroyed, mut total_lost) = (0u64, 0u64, 0u64);
+
+    while start.elapsed() < timeout {
+        while let Some(item) = ct_rb.next() {
+            let Some(event) = crate::events::view_event::<ConntrackEvent>(&item) else {
+                continue;
+            };
+            let ip = crate::ebpf::ip_addr;
+            let entry = ConntrackEntry {
+                protocol: event.protocol,
+                original: Tuple {
+                    src: ip(&event.src_addr),
+                    dst: ip(&event.dst_addr),
+                    sport: event.src_port,
+                    dport: event.dst_port,
+                },
+                reply: Tuple {
+                    src: ip(&event.reply_src_addr),
+                    dst: ip(&event.reply_dst_addr),
+                    sport: event.reply_src_port,
+                    dport: event.reply_dst_port,
+                },
+                state: None,
+                timeout_secs: 0,
+                assured: event.status & sennet_common::ct_status::ASSURED != 0,
+                unreplied: event.status & sennet_common::ct_status::SEEN_REPLY == 0,
+            };
+            if !entry.matches(&filter) {
+                continue;
+            }
+            let weight = u64::from(event.sample_rate.max(1));
+            let line = describe_conntrack(event);
+            if event.event_type == sennet_common::ct_event::NEW {
+                created += weight;
+                println!("{}", line.green());
+            } else {
+                destroyed += weight;
+                // Torn down without an answer: refused or filtered upstream
+                println!("{}", if entry.unreplied { line.yellow() } else { line.normal() });
+            }
+        }
+        total_lost += losses.poll()[RingKind::Conntrack.index()];
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    println!("{}", "─".repeat(100));
+    println!("{} new, {} destroyed in {:.1}s", created, destroyed, start.elapsed().as_secs_f64());
+    if total_lost > 0 {
+        println!("{}: {} conntrack events were lost during capture", "Warning".yellow(), total_lost);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch(_opts: &ConntrackOptions) -> Result<()> {
+    println!("{}: Conntrack tracing requires Linux with eBPF support", "Error".red());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STAT_SAMPLE: &str = "\
+entries  clashres found new invalid ignore delete chainlength insert insert_failed drop early_drop icmp_error  expect_new expect_create expect_delete search_restart
+0000012c  00000000 00000000 00000000 00000010 00000000 00000000 00000000 00000000 00000001 00000002 00000003 00000000  00000000 00000000 00000000 00000000
+0000012c  00000000 00000000 00000000 00000005 00000000 00000000 00000000 00000000 00000000 0000000a 00000001 00000000  00000000 00000000 00000000 00000000
+";
+
+    #[test]
+    fn test_parse_stat_table() {
+        let totals = parse_stat_table(STAT_SAMPLE);
+        assert_eq!(totals.get("drop"), Some(&12));
+        assert_eq!(totals.get("early_drop"), Some(&4));
+        assert_eq!(totals.get("insert_failed"), Some(&1));
+        assert_eq!(totals.get("invalid"), Some(&21));
+        // entries is a gauge and must not be summed per CPU
+        assert!(!totals.contains_key("entries"));
+    }
+
+    #[test]
+    fn test_parse_stat_table_empty() {
+        assert!(parse_stat_table("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_entry_snat() {
+        let line = "ipv4     2 tcp      6 431999 ESTABLISHED src=10.244.1.5 dst=93.184.216.34 sport=40000 dport=443 src=93.184.216.34 dst=192.168.1.10 sport=443 dport=61000 [ASSURED] mark=0 zone=0 use=2";
+        let entry = parse_entry(line).unwrap();
+        assert_eq!(entry.protocol, 6);
+        assert_eq!(entry.nat_kind(), Some(NatKind::Snat));
+        let translated = entry.translated();
+        assert_eq!(translated.src, "192.168.1.10".parse::<IpAddr>().unwrap());
+        assert_eq!(translated.sport, 61000);
+        assert_eq!(translated.dport, 443);
+    }
+
+    #[test]
+    fn test_parse_entry_dnat_conntrack_cli() {
+        let line = "udp      17 29 src=10.0.0.9 dst=10.96.0.10 sport=5353 dport=53 src=10.244.2.7 dst=10.0.0.9 sport=53 dport=5353 mark=0 use=1";
+        let entry = parse_entry(line).unwrap();
+        assert_eq!(entry.protocol, 17);
+        assert_eq!(entry.nat_kind(), Some(NatKind::Dnat));
+    }
+
+    #[test]
+    fn test_parse_entry_no_nat_and_icmp() {
+        let plain = "ipv4 2 tcp 6 100 ESTABLISHED src=10.0.0.1 dst=10.0.0.2 sport=1000 dport=22 src=10.0.0.2 dst=10.0.0.1 sport=22 dport=1000 use=1";
+        assert_eq!(parse_entry(plain).unwrap().nat_kind(), None);
+        let icmp = "ipv4 2 icmp 1 29 src=10.0.0.1 dst=10.0.0.2 type=8 code=0 id=1 src=10.0.0.2 dst=10.0.0.1 type=0 code=0 id=1 use=1";
+        assert!(parse_entry(icmp).is_none());
+    }
+
+    #[test]
+    fn test_nat_table_lookup_both_orientations() {
+        let line = "tcp 6 100 ESTABLISHED src=1.2.3.4 dst=203.0.113.1 sport=5000 dport=80 src=10.244.0.7 dst=1.2.3.4 sport=8080 dport=5000 use=1";
+        let table = NatTable::new(vec![parse_entry(line).unwrap()]);
+
+        // Local socket accepted on the DNAT target
+        let local = Tuple {
+            src: "1.2.3.4".parse().unwrap(),
+            dst: "10.244.0.7".parse().unwrap(),
+            sport: 5000,
+            dport: 8080,
+        };
+        let entry = table.lookup(6, &local).expect("should match reply tuple");
+        assert_eq!(entry.original.dport, 80);
+        assert!(table.lookup(17, &local).is_none());
+    }
+
+    #[test]
+    fn test_parse_entry_state_and_flags() {
+        let line = "ipv4     2 tcp      6 431999 ESTABLISHED src=10.0.0.1 dst=10.0.0.2 sport=1000 dport=22 src=10.0.0.2 dst=10.0.0.1 sport=22 dport=1000 [ASSURED] mark=0 use=2";
+        let entry = parse_entry(line).unwrap();
+        assert_eq!(entry.state.as_deref(), Some("ESTABLISHED"));
+        assert_eq!(entry.timeout_secs, 431999);
+        assert!(entry.assured && !entry.unreplied);
+
+        // UDP has no state; `conntrack -L` omits the address family
+        let udp = "udp      17 29 src=10.0.0.9 dst=10.96.0.10 sport=5353 dport=53 [UNREPLIED] src=10.96.0.10 dst=10.0.0.9 sport=53 dport=5353 mark=0 use=1";
+        let entry = parse_entry(udp).unwrap();
+        assert_eq!((entry.state.as_deref(), entry.timeout_secs), (None, 29));
+        assert!(entry.unreplied);
+        assert_eq!(entry.state_str(), "UNREPLIED");
+    }
+
+    #[test]
+    fn test_filters() {
+        let tcp = parse_entry("tcp 6 100 ESTABLISHED src=10.0.0.1 dst=10.0.0.2 sport=1000 dport=22 src=10.0.0.2 dst=10.0.0.1 sport=22 dport=1000 use=1").unwrap();
+        let args = |args: &[&str]| parse_args(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+
+        assert!(tcp.matches(&args(&["--proto", "tcp", "--state", "established"])));
+        assert!(!tcp.matches(&args(&["-p", "udp"])));
+        assert!(!tcp.matches(&args(&["--state", "TIME_WAIT"])));
+        assert!(!tcp.matches(&args(&["--nat"])));
+
+        let opts = args(&["--watch", "-t", "30", "--limit", "5"]);
+        assert_eq!(opts, ConntrackOptions { watch: true, timeout_secs: 30, limit: 5, ..Default::default() });
+        assert_eq!(parse_args(&[]), ConntrackOptions::default());
+    }
+
+    #[test]
+    fn test_utilization() {
+        let stats = ConntrackStats { count: 900, max: 1000, ..Default::default() };
+        assert!((stats.utilization_pct() - 90.0).abs() < f64::EPSILON);
+        assert_eq!(ConntrackStats::default().utilization_pct(), 0.0);
+    }
+
+    #[test]
+    fn test_monitor_warns_at_threshold() {
+        let mut monitor = ConntrackMonitor::new(80);
+        let low = ConntrackStats { count: 100, max: 1000, ..Default::default() };
+        assert!(monitor.check(&low).is_none());
+
+        let high = ConntrackStats { count: 850, max: 1000, ..Default::default() };
+        let alert = monitor.check(&high).expect("should warn above threshold");
+        assert_eq!(alert.level, AlertLevel::Warning);
+    }
+
+    #[test]
+    fn test_monitor_critical_on_new_drops() {
+        let mut monitor = ConntrackMonitor::new(90);
+        let before = ConntrackStats { count: 1000, max: 1000, drop: 5, ..Default::default() };
+        // First sample only establishes a baseline for drop deltas
+        assert_eq!(monitor.check(&before).unwrap().level, AlertLevel::Warning);
+
+        let after = ConntrackStats { count: 1000, max: 1000, drop: 12, insert_failed: 1, ..Default::default() };
+        let alert = monitor.check(&after).unwrap();
+        assert_eq!(alert.level, AlertLevel::Critical);
+        assert_eq!(alert.new_drops, 8);
+    }
+
+    #[test]
+    fn test_monitor_alerts_on_state_change_only() {
+        let mut monitor = ConntrackMonitor::new(80);
+        let at = |count, drop| ConntrackStats { count, max: 1000, drop, ..Default::default() };
+
+        assert_eq!(monitor.check(&at(850, 0)).unwrap().level, AlertLevel::Warning);
+        // Still above the threshold: already warned
+        assert!(monitor.check(&at(900, 0)).is_none());
+        // Below the threshold but within the hysteresis band: no re-warning
+        assert!(monitor.check(&at(780, 0)).is_none());
+        assert!(monitor.check(&at(820, 0)).is_none());
+
+        // Drops escalate once, then stay quiet while they continue
+        assert_eq!(monitor.check(&at(1000, 3)).unwrap().level, AlertLevel::Critical);
+        assert!(monitor.check(&at(1000, 9)).is_none());
+        // Drops stop: back to a standing warning, not a new alert
+        assert!(monitor.check(&at(1000, 9)).is_none());
+
+        // Clears only below threshold - hysteresis, then warns afresh
+        assert!(monitor.check(&at(740, 9)).is_none());
+        assert_eq!(monitor.check(&at(810, 9)).unwrap().level, AlertLevel::Warning);
+    }
+}
@@ -0,0 +1,857 @@
+//! Control Socket
+//!
+//! The daemon listens on a Unix socket (`/run/sennet.sock` by default) that
+//! CLI commands query instead of opening the pinned maps. The maps need
+//! CAP_BPF; the socket is mode 0660 and owned by the `sennet` group, so
+//! members of that group can run `status`, `top`, `flows`, `top-talkers`,
+//! `latency` and `trace` without root.
+//!
+//! The protocol is line-delimited JSON. Each request names a method and is
+//! answered by one line:
+//!
+//! ```text
+//! → {"method":"counters"}
+//! ← {"result":{"rxPackets":1024,"rxBytes":88211,...}}
+//! ← {"error":"Pinned map not found at /sys/fs/bpf/sennet/counters"}
+//! ```
+//!
+//! Methods are `status`, `counters`, `flows`, `tls_sni`, `top_talkers`, `rtt`, `icmp_rtt`, `drops`, `probes`,
+//! `set_probe` and `events`. `set_probe` takes `"params":{"probe":"drops","attached":false}`
+//! and is only answered for root and the agent's own user. After
+//! `events` the connection carries a [`StreamRecord`] per line until the
+//! client hangs up. Events come from the pipeline tap when the pipeline
+//! runs; otherwise a thread reads the pinned ring buffers while anyone is
+//! subscribed. The gRPC API ([`crate::grpc`]) serves the same state.
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+#[cfg(target_os = "linux")]
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, warn};
+
+use crate::drops::SharedDrops;
+use crate::ebpf::{EbpfManager, FlowInfo, FlowKey, LossTracker, PacketCounters, Probe, ProbeState, RemoteRtt, TopTalker};
+use crate::grafana::History;
+use crate::pipeline::{EnrichedEvent, RawEvent, RingKind, SinkFn, Summary};
+
+/// Socket path used when the config can't be read
+pub const DEFAULT_SOCKET: &str = "/run/sennet.sock";
+
+/// `ring` of a gap record for events a slow client missed
+pub const STREAM_GAP: &str = "stream";
+
+/// Longest request line the agent accepts; longer ones close the connection
+const MAX_REQUEST_LEN: u64 = 64 * 1024;
+
+/// How long the CLI waits for a reply
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Ring buffer poll interval of the fallback reader thread
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const RING_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// One request line
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<serde_json::Value>,
+}
+
+/// Params of `set_probe`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetProbeParams {
+    pub probe: Probe,
+    pub attached: bool,
+}
+
+/// Reply to a request: exactly one of `result` and `error` is set
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    result: Option<T>,
+    error: Option<String>,
+}
+
+/// Daemon state returned by `status`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonStatus {
+    pub version: String,
+    pub agent_id: String,
+    pub pid: u32,
+    pub uptime_secs: u64,
+    pub interface: String,
+    pub ebpf_attached: bool,
+    pub offline: bool,
+    /// Seconds since the last successful heartbeat (None = none yet)
+    pub heartbeat_age_secs: Option<u64>,
+    pub pipeline: bool,
+    /// Deployment labels from the config
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Drop totals returned by `drops`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DropsReport {
+    /// Kernel drop counter since the programs were loaded
+    pub drop_count: u64,
+    /// Events lost to full ring buffers, by ring
+    pub events_lost: BTreeMap<String, u64>,
+    /// Summary of the last pipeline flush window (None = pipeline disabled)
+    pub last_window: Option<serde_json::Value>,
+    /// kfree_skb drops since the agent started, by drop reason name
+    #[serde(default)]
+    pub by_reason: BTreeMap<String, u64>,
+}
+
+/// One line of the `events` stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StreamRecord {
+    /// A kernel event; a drop may stand for a coalesced burst of `count`
+    Event {
+        raw: Box<RawEvent>,
+        count: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interface: Option<String>,
+        /// RFC 3339 wall-clock time (absent from older agents)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        time: Option<String>,
+    },
+    /// Events this client never saw: `ring` names a kernel ring buffer that
+    /// was full, or is [`STREAM_GAP`] if the client fell behind the daemon
+    Gap { ring: String, lost: u64 },
+}
+
+impl From<&EnrichedEvent> for StreamRecord {
+    fn from(event: &EnrichedEvent) -> Self {
+        StreamRecord::Event {
+            raw: Box::new(event.raw),
+            count: event.count,
+            interface: event.ifname.as_deref().map(str::to_string),
+            time: Some(crate::clock::rfc3339(event.time)),
+        }
+    }
+}
+
+/// Recent pipeline windows, kept by [`summary_sink`]: the last non-empty
+/// summary as JSON for `drops`, and the history the REST API's Grafana
+/// endpoints chart
+#[derive(Clone, Default)]
+pub struct LastWindow {
+    latest: Arc<Mutex<Option<serde_json::Value>>>,
+    history: Arc<History>,
+}
+
+impl LastWindow {
+    pub fn latest(&self) -> Option<serde_json::Value> {
+        self.latest.lock().unwrap().clone()
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+}
+
+/// Pipeline sink that keeps the last non-empty summary for `drops` and
+/// every window, with the packet counters at its end, for the history
+pub fn summary_sink(last: LastWindow) -> SinkFn {
+    Box::new(move |summary: &Summary| {
+        last.history.record(summary, crate::ebpf::read_pinned_counters().ok());
+        if summary.is_empty() {
+            return;
+        }
+        if let Ok(value) = serde_json::to_value(summary) {
+            *last.latest.lock().unwrap() = Some(value);
+        }
+    })
+}
+
+// ============================================================================
+// Daemon side
+// ============================================================================
+
+/// `probes` or `set_probe` request, run by the task that owns the
+/// [`EbpfManager`]
+pub struct ProbeCommand {
+    /// Probe to attach (true) or detach (false); None only lists them
+    set: Option<(Probe, bool)>,
+    reply: oneshot::Sender<Result<Vec<ProbeState>>>,
+}
+
+impl ProbeCommand {
+    pub fn run(self, manager: &mut EbpfManager) {
+        let result = match self.set {
+            Some((probe, attach)) => manager.set_probe(probe, attach).map(|changed| {
+                if changed {
+                    let verb = if attach { "attached" } else { "detached" };
+                    tracing::info!("Probe {} {} over the control socket", probe.name(), verb);
+                }
+            }),
+            None => Ok(()),
+        };
+        let _ = self.reply.send(result.map(|()| manager.probes()));
+    }
+}
+
+/// State shared by control connections
+pub struct ControlState {
+    status: DaemonStatus,
+    started: Instant,
+    events: broadcast::Sender<EnrichedEvent>,
+    /// No pipeline tap: events come from the pinned ring buffers
+    reads_rings: bool,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    reader_running: AtomicBool,
+    last_window: LastWindow,
+    drops: SharedDrops,
+    /// Set once the eBPF programs are loaded
+    probe_commands: OnceLock<mpsc::Sender<ProbeCommand>>,
+}
+
+impl ControlState {
+    /// `status` holds the fields fixed at startup; `tap` is the pipeline's
+    /// event tap, or None to read the pinned ring buffers on demand
+    pub fn new(
+        status: DaemonStatus,
+        tap: Option<broadcast::Sender<EnrichedEvent>>,
+        last_window: LastWindow,
+        drops: SharedDrops,
+    ) -> Arc<Self> {
+        let reads_rings = tap.is_none();
+        let events = tap.unwrap_or_else(|| broadcast::channel(crate::pipeline::TAP_CAPACITY).0);
+        Arc::new(Self {
+            status,
+            started: Instant::now(),
+            events,
+            reads_rings,
+            reader_running: AtomicBool::new(false),
+            last_window,
+            drops,
+            probe_commands: OnceLock::new(),
+        })
+    }
+
+    /// Answer `probes` and `set_probe` by sending them to `commands`
+    pub fn set_probe_commands(&self, commands: mpsc::Sender<ProbeCommand>) {
+        let _ = self.probe_commands.set(commands);
+    }
+
+    /// Hand a probe request to the manager's task and wait for its answer
+    fn probe_command(&self, set: Option<(Probe, bool)>) -> Result<Vec<ProbeState>> {
+        let commands = self.probe_commands.get().context("The agent has no eBPF programs loaded")?;
+        let (reply, answer) = oneshot::channel();
+        commands
+            .blocking_send(ProbeCommand { set, reply })
+            .map_err(|_| anyhow::anyhow!("The agent is shutting down"))?;
+        answer.blocking_recv().map_err(|_| anyhow::anyhow!("The agent is shutting down"))?
+    }
+
+    pub fn status(&self) -> DaemonStatus {
+        let metrics = crate::selfmetrics::global();
+        DaemonStatus {
+            uptime_secs: self.started.elapsed().as_secs(),
+            ebpf_attached: metrics.ebpf_attached(),
+            heartbeat_age_secs: metrics.heartbeat_age_secs(),
+            ..self.status.clone()
+        }
+    }
+
+    pub fn drops(&self) -> DropsReport {
+        let lost = crate::selfmetrics::global().events_lost();
+        DropsReport {
+            drop_count: crate::ebpf::read_pinned_counters().map(|c| c.drop_count).unwrap_or(0),
+            events_lost: RingKind::ALL.iter().map(|kind| (kind.name().to_string(), lost[kind.index()])).collect(),
+            last_window: self.last_window.latest(),
+            by_reason: self.drops.total().top(usize::MAX).into_iter().map(|(r, n)| (r.to_string(), n)).collect(),
+        }
+    }
+
+    /// Recent pipeline windows (empty while the pipeline is disabled)
+    pub fn history(&self) -> &History {
+        self.last_window.history()
+    }
+
+    /// Answer a request; blocking, as the map reads are syscalls
+    ///
+    /// `privileged` callers (root or the agent's user) may change the
+    /// agent's state; the others can only read it.
+    fn dispatch(&self, request: &Request, privileged: bool) -> Result<serde_json::Value> {
+        Ok(match request.method.as_str() {
+            "status" => serde_json::to_value(self.status())?,
+            "counters" => serde_json::to_value(crate::ebpf::read_pinned_counters()?)?,
+            "interface_counters" => serde_json::to_value(crate::ebpf::read_pinned_interface_counters()?)?,
+            "flows" => serde_json::to_value(crate::ebpf::read_pinned_flows()?)?,
+            "tls_sni" => serde_json::to_value(crate::ebpf::read_pinned_tls_sni()?)?,
+            "top_talkers" => serde_json::to_value(crate::ebpf::read_pinned_top_talkers()?)?,
+            "rtt" => serde_json::to_value(crate::ebpf::read_pinned_rtt()?)?,
+            "icmp_rtt" => serde_json::to_value(crate::ebpf::read_pinned_icmp_rtt()?)?,
+            "drops" => serde_json::to_value(self.drops())?,
+            "probes" => serde_json::to_value(self.probe_command(None)?)?,
+            "set_probe" => {
+                let params: SetProbeParams = request
+                    .params
+                    .clone()
+                    .and_then(|params| serde_json::from_value(params).ok())
+                    .context(r#"set_probe needs params {"probe":NAME,"attached":BOOL}"#)?;
+                if !privileged {
+                    anyhow::bail!("set_probe is only allowed for root and the agent's user");
+                }
+                serde_json::to_value(self.probe_command(Some((params.probe, params.attached)))?)?
+            }
+            other => anyhow::bail!("unknown method: {}", other),
+        })
+    }
+
+    /// Start receiving events, with gaps where some were lost
+    pub fn subscribe(self: &Arc<Self>) -> Subscription {
+        let events = self.events.subscribe();
+        if self.reads_rings {
+            self.start_ring_reader();
+        }
+        Subscription {
+            events,
+            losses: LossTracker::new(),
+            loss_tick: tokio::time::interval(Duration::from_secs(1)),
+            kinds: Vec::new(),
+        }
+    }
+
+    /// Read the pinned ring buffers on a thread until nobody is subscribed
+    #[cfg(target_os = "linux")]
+    fn start_ring_reader(self: &Arc<Self>) {
+        if self.reader_running.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let state = self.clone();
+        let spawned = std::thread::Builder::new()
+            .name("sennet-control-rings".into())
+            .spawn(move || state.read_rings());
+        if let Err(e) = spawned {
+            warn!("Failed to start control socket ring reader: {}", e);
+            self.reader_running.store(false, Ordering::Release);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn start_ring_reader(self: &Arc<Self>) {}
+
+    #[cfg(target_os = "linux")]
+    fn read_rings(&self) {
+        let mut rings: Vec<_> = RingKind::ALL
+            .iter()
+            .filter(|kind| kind.daemon_reads())
+            .filter_map(|kind| crate::ebpf::open_pinned_ringbuf(kind.pin_name()).ok())
+            .collect();
+        if rings.is_empty() {
+            debug!("No pinned ring buffers to stream from");
+        }
+        let mut names = crate::pipeline::InterfaceNames::new();
+        let mut reported = Vec::new();
+        loop {
+            for rb in &mut rings {
+                while let Some(item) = rb.next() {
+                    match crate::events::try_decode(&item) {
+                        Ok(raw) => {
+                            let ifname = raw.ifindex().and_then(|i| names.lookup(i));
+                            let _ = self.events.send(EnrichedEvent::new(raw, ifname));
+                        }
+                        Err(e) if !reported.contains(&e) => {
+                            warn!("Not streaming ring buffer records: {}", e);
+                            reported.push(e);
+                        }
+                        Err(_) => {}
+                    }
+                }
+            }
+            if rings.is_empty() || self.events.receiver_count() == 0 {
+                self.reader_running.store(false, Ordering::Release);
+                // A client may have subscribed between the check and the store
+                if rings.is_empty()
+                    || self.events.receiver_count() == 0
+                    || self.reader_running.swap(true, Ordering::AcqRel)
+                {
+                    return;
+                }
+            }
+            std::thread::sleep(RING_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Bind the control socket, replacing a stale one
+///
+/// Call before dropping privileges: giving the socket to `group` needs
+/// CAP_CHOWN unless the agent's user is a member. If the group doesn't exist
+/// or the chown fails, only the agent's user can connect.
+pub fn bind(path: &Path, group: &str) -> Result<UnixListener> {
+    if UnixStream::connect(path).is_ok() {
+        anyhow::bail!("{} is in use by another agent", path.display());
+    }
+    let _ = std::fs::remove_file(path);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("Failed to bind {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o660))?;
+    let gid = std::fs::read_to_string("/etc/group").ok().and_then(|groups| group_id(&groups, group));
+    match gid {
+        Some(gid) => {
+            if let Err(e) = std::os::unix::fs::chown(path, None, Some(gid)) {
+                warn!("Failed to give {} to group {}: {}", path.display(), group, e);
+            }
+        }
+        None => debug!("Group {} not found; only the agent's user can use the control socket", group),
+    }
+    Ok(listener)
+}
+
+/// Remove the socket file on shutdown
+pub fn remove(path: &Path) {
+    let _ = std::fs::remove_file(path);
+}
+
+/// Look up a group ID in group(5) contents
+fn group_id(groups: &str, name: &str) -> Option<u32> {
+    groups.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        (fields.next()? == name).then_some(())?;
+        fields.nth(1)?.parse().ok()
+    })
+}
+
+/// Accept control connections until the task is aborted
+pub async fn serve(listener: UnixListener, state: Arc<ControlState>) {
+    let listener = match listener
+        .set_nonblocking(true)
+        .and_then(|()| tokio::net::UnixListener::from_std(listener))
+    {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Control socket unavailable: {}", e);
+            return;
+        }
+    };
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_connection(stream, state.clone()));
+            }
+            Err(e) => {
+                warn!("Control socket accept failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+async fn handle_connection(stream: tokio::net::UnixStream, state: Arc<ControlState>) {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let agent_uid = unsafe { libc::geteuid() };
+    let privileged = stream.peer_cred().is_ok_and(|cred| cred.uid() == 0 || cred.uid() == agent_uid);
+    let (read, mut write) = stream.into_split();
+    let mut read = tokio::io::BufReader::new(read);
+    let mut line = Vec::new();
+    loop {
+        match read_request(&mut read, &mut line).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                let _ = write_line(&mut write, &serde_json::json!({ "error": e.to_string() })).await;
+                return;
+            }
+        }
+        let request = match serde_json::from_slice::<Request>(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                let reply = serde_json::json!({ "error": format!("invalid request: {}", e) });
+                if write_line(&mut write, &reply).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+        if request.method == "events" {
+            stream_events(read, write, &state).await;
+            return;
+        }
+        let dispatch_state = state.clone();
+        let result = tokio::task::spawn_blocking(move || dispatch_state.dispatch(&request, privileged))
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+        let reply = match result {
+            Ok(value) => serde_json::json!({ "result": value }),
+            Err(e) => serde_json::json!({ "error": format!("{:#}", e) }),
+        };
+        if write_line(&mut write, &reply).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Read one request line into `line`, without the newline
+///
+/// Returns false at end of stream. Lines over `MAX_REQUEST_LEN` are an
+/// error, so a client can't make the agent buffer without bound.
+async fn read_request<R: AsyncBufRead + Unpin>(read: &mut R, line: &mut Vec<u8>) -> std::io::Result<bool> {
+    line.clear();
+    let n = read.take(MAX_REQUEST_LEN + 1).read_until(b'\n', line).await?;
+    if n == 0 {
+        return Ok(false);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    } else if n as u64 > MAX_REQUEST_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("request exceeds {} bytes", MAX_REQUEST_LEN),
+        ));
+    }
+    Ok(true)
+}
+
+/// Events for one client, shared by the control socket and the gRPC API
+pub struct Subscription {
+    events: broadcast::Receiver<EnrichedEvent>,
+    losses: LossTracker,
+    loss_tick: tokio::time::Interval,
+    kinds: Vec<RingKind>,
+}
+
+impl Subscription {
+    /// Only pass events of these kinds (empty = all); gaps always pass
+    pub fn only(mut self, kinds: Vec<RingKind>) -> Self {
+        self.kinds = kinds;
+        self
+    }
+
+    /// Wait for the next records; None once the event source is gone
+    ///
+    /// Cancel safe, so it can be raced against the client hanging up.
+    pub async fn next(&mut self) -> Option<Vec<StreamRecord>> {
+        tokio::select! {
+            event = self.events.recv() => match event {
+                Ok(event) if self.kinds.is_empty() || self.kinds.contains(&event.raw.kind()) => {
+                    Some(vec![StreamRecord::from(&event)])
+                }
+                Ok(_) => Some(Vec::new()),
+                Err(RecvError::Lagged(lost)) => Some(vec![StreamRecord::Gap { ring: STREAM_GAP.to_string(), lost }]),
+                Err(RecvError::Closed) => None,
+            },
+            _ = self.loss_tick.tick() => {
+                let lost = self.losses.poll();
+                Some(RingKind::ALL
+                    .iter()
+                    .filter(|kind| lost[kind.index()] > 0)
+                    .map(|kind| StreamRecord::Gap { ring: kind.name().to_string(), lost: lost[kind.index()] })
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Send events until the client hangs up; reads only to notice that
+async fn stream_events<R: AsyncRead + Unpin>(
+    mut read: R,
+    mut write: OwnedWriteHalf,
+    state: &Arc<ControlState>,
+) {
+    let mut subscription = state.subscribe();
+    // Anything the client sends after subscribing is discarded unbuffered
+    let mut discard = [0u8; 256];
+    loop {
+        let records = tokio::select! {
+            records = subscription.next() => match records {
+                Some(records) => records,
+                None => return,
+            },
+            n = read.read(&mut discard) => match n {
+                Ok(n) if n > 0 => continue,
+                _ => return,
+            },
+        };
+        for record in &records {
+            if write_line(&mut write, record).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn write_line<T: Serialize>(write: &mut OwnedWriteHalf, value: &T) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(value).map_err(std::io::Error::other)?;
+    line.push(b'\n');
+    write.write_all(&line).await
+}
+
+// ============================================================================
+// CLI side
+// ============================================================================
+
+/// Socket the CLI should use; None if disabled in the config
+///
+/// Non-root users usually can't read the config, so fall back to the
+/// default path rather than giving up. The default path, from either, is
+/// that of the agent whose pinned maps the CLI reads (see
+/// [`crate::ebpf::pin_dir`]), so both come from the same agent.
+pub fn socket_path() -> Option<PathBuf> {
+    let socket = match crate::config::Config::load() {
+        Ok(config) => config.control_socket?,
+        Err(_) => PathBuf::from(DEFAULT_SOCKET),
+    };
+    match crate::ebpf::pin_namespace() {
+        Some(namespace) if socket == Path::new(DEFAULT_SOCKET) => Some(crate::config::namespaced(&socket, &namespace)),
+        _ => Some(socket),
+    }
+}
+
+/// Blocking connection to the daemon, for CLI commands
+pub struct Client {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl Client {
+    /// Connect to the running daemon; None if it isn't listening
+    pub fn connect() -> Option<Self> {
+        Self::connect_to(&socket_path()?).ok()
+    }
+
+    pub fn connect_to(path: &Path) -> Result<Self> {
+        let stream = UnixStream::connect(path).with_context(|| format!("Failed to connect to {}", path.display()))?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Self { stream, reader })
+    }
+
+    fn send(&mut self, method: &str, params: Option<serde_json::Value>) -> Result<()> {
+        let mut line = serde_json::to_vec(&Request { method: method.to_string(), params })?;
+        line.push(b'\n');
+        self.stream.write_all(&line).context("Failed to send request to the agent")
+    }
+
+    /// Call a method and decode its result
+    pub fn call<T: DeserializeOwned>(&mut self, method: &str) -> Result<T> {
+        self.call_with(method, None)
+    }
+
+    /// Call a method that takes params
+    pub fn call_with<T: DeserializeOwned>(&mut self, method: &str, params: Option<serde_json::Value>) -> Result<T> {
+        self.send(method, params)?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            anyhow::bail!("The agent closed the connection");
+        }
+        parse_response(&line)
+    }
+
+    pub fn status(&mut self) -> Result<DaemonStatus> {
+        self.call("status")
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))] // top and trace are Linux-only
+    pub fn counters(&mut self) -> Result<PacketCounters> {
+        self.call("counters")
+    }
+
+    /// Counters per attached interface, by ifindex
+    pub fn interface_counters(&mut self) -> Result<BTreeMap<u32, PacketCounters>> {
+        self.call("interface_counters")
+    }
+
+    pub fn flows(&mut self) -> Result<Vec<(FlowKey, FlowInfo)>> {
+        self.call("flows")
+    }
+
+    /// Server names from TLS ClientHellos, by outbound flow key
+    pub fn tls_sni(&mut self) -> Result<Vec<(FlowKey, String)>> {
+        self.call("tls_sni")
+    }
+
+    pub fn top_talkers(&mut self) -> Result<Vec<TopTalker>> {
+        self.call("top_talkers")
+    }
+
+    pub fn rtt(&mut self) -> Result<Vec<RemoteRtt>> {
+        self.call("rtt")
+    }
+
+    pub fn icmp_rtt(&mut self) -> Result<Vec<RemoteRtt>> {
+        self.call("icmp_rtt")
+    }
+
+    pub fn drops(&mut self) -> Result<DropsReport> {
+        self.call("drops")
+    }
+
+    pub fn probes(&mut self) -> Result<Vec<ProbeState>> {
+        self.call("probes")
+    }
+
+    /// Attach or detach a probe; returns the probes afterwards
+    pub fn set_probe(&mut self, probe: Probe, attached: bool) -> Result<Vec<ProbeState>> {
+        let params = serde_json::to_value(SetProbeParams { probe, attached })?;
+        self.call_with("set_probe", Some(params))
+    }
+
+    /// Turn the connection into an event stream
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))] // top and trace are Linux-only
+    pub fn events(mut self) -> Result<EventStream> {
+        self.send("events", None)?;
+        Ok(EventStream {
+            pending: self.reader.buffer().to_vec(),
+            stream: self.stream,
+        })
+    }
+}
+
+fn parse_response<T: DeserializeOwned>(line: &str) -> Result<T> {
+    let response: Response<T> = serde_json::from_str(line).context("Invalid response from the agent")?;
+    match (response.result, response.error) {
+        (_, Some(error)) => anyhow::bail!("{}", error),
+        (Some(result), None) => Ok(result),
+        (None, None) => anyhow::bail!("Empty response from the agent"),
+    }
+}
+
+/// Events streamed by the daemon
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))] // top and trace are Linux-only
+pub struct EventStream {
+    stream: UnixStream,
+    /// Bytes of an incomplete line
+    pending: Vec<u8>,
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))] // top and trace are Linux-only
+impl EventStream {
+    /// Records that arrive within `timeout`; fails once the daemon hangs up
+    pub fn poll(&mut self, timeout: Duration) -> Result<Vec<StreamRecord>> {
+        let mut buf = [0u8; 16 * 1024];
+        self.stream.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        // Wait for the first chunk, then take whatever else is queued
+        for _ in 0..64 {
+            match self.stream.read(&mut buf) {
+                Ok(0) => anyhow::bail!("The agent closed the event stream"),
+                Ok(n) => self.pending.extend_from_slice(&buf[..n]),
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => break,
+                Err(e) => return Err(e.into()),
+            }
+            self.stream.set_nonblocking(true)?;
+        }
+        self.stream.set_nonblocking(false)?;
+        Ok(take_records(&mut self.pending))
+    }
+}
+
+/// Decode the complete lines in `pending`, leaving a trailing partial line
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))] // top and trace are Linux-only
+fn take_records(pending: &mut Vec<u8>) -> Vec<StreamRecord> {
+    let Some(end) = pending.iter().rposition(|&b| b == b'\n') else {
+        return Vec::new();
+    };
+    let records = pending[..end]
+        .split(|&b| b == b'\n')
+        .filter_map(|line| serde_json::from_slice(line).ok())
+        .collect();
+    pending.drain(..=end);
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::DropEvent;
+
+    fn drop_event(reason: u32) -> EnrichedEvent {
+        let raw = RawEvent::Drop(DropEvent { timestamp_ns: 42, reason, ifindex: 2, protocol: 0x0800, ..Default::default() });
+        EnrichedEvent::new(raw, Some("eth0".into()))
+    }
+
+    #[test]
+    fn test_stream_records() {
+        let line = serde_json::to_string(&StreamRecord::from(&drop_event(7))).unwrap();
+        assert!(line.contains(r#""type":"event""#) && line.contains(r#""kind":"drop""#));
+
+        let mut pending = format!("{}\n{}\n{{\"type\":\"ga", line, r#"{"type":"gap","ring":"drop","lost":3}"#).into_bytes();
+        let records = take_records(&mut pending);
+        assert_eq!(pending, br#"{"type":"ga"#);
+        assert!(matches!(
+            &records[0],
+            StreamRecord::Event { raw, count: 1, interface: Some(name), .. }
+                if matches!(**raw, RawEvent::Drop(e) if e.reason == 7) && name == "eth0"
+        ));
+        assert!(matches!(&records[1], StreamRecord::Gap { ring, lost: 3 } if ring == "drop"));
+    }
+
+    #[test]
+    fn test_parse_response_and_group() {
+        assert_eq!(parse_response::<u64>(r#"{"result":5}"#).unwrap(), 5);
+        let err = parse_response::<u64>(r#"{"error":"unknown method: nope"}"#).unwrap_err();
+        assert_eq!(err.to_string(), "unknown method: nope");
+
+        let groups = "root:x:0:\nsennet:x:998:alice,bob\n";
+        assert_eq!(group_id(groups, "sennet"), Some(998));
+        assert_eq!(group_id(groups, "wheel"), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_serve_requests_and_events() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("sennet.sock");
+        let listener = bind(&path, "no-such-group").unwrap();
+        let (tap, _) = broadcast::channel(16);
+        let status = DaemonStatus { agent_id: "agent-1".into(), ..Default::default() };
+        let state = ControlState::new(status, Some(tap.clone()), LastWindow::default(), SharedDrops::default());
+        let server = tokio::spawn(serve(listener, state));
+
+        let client_path = path.clone();
+        let client = tokio::task::spawn_blocking(move || {
+            let mut client = Client::connect_to(&client_path).unwrap();
+            assert_eq!(client.status().unwrap().agent_id, "agent-1");
+            assert!(client.call::<u64>("nope").unwrap_err().to_string().contains("unknown method"));
+            assert!(client.probes().unwrap_err().to_string().contains("no eBPF programs"));
+            let missing_params = client.call::<Vec<ProbeState>>("set_probe").unwrap_err();
+            assert!(missing_params.to_string().contains("needs params"));
+
+            // An oversized request gets an error and the connection is closed
+            let mut raw = UnixStream::connect(&client_path).unwrap();
+            raw.write_all(&vec![b'a'; MAX_REQUEST_LEN as usize + 1]).unwrap();
+            let mut reply = String::new();
+            BufReader::new(&raw).read_to_string(&mut reply).unwrap();
+            assert!(reply.contains("request exceeds"), "{}", reply);
+
+            let mut events = client.events().unwrap();
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                if let Some(record) = events.poll(Duration::from_millis(50)).unwrap().into_iter().next() {
+                    return record;
+                }
+            }
+            panic!("no event received");
+        });
+
+        // The stream subscribes once the request arrives; keep sending until then
+        while !client.is_finished() {
+            let _ = tap.send(drop_event(2));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let record = client.await.unwrap();
+        assert!(matches!(record, StreamRecord::Event { raw, .. } if matches!(*raw, RawEvent::Drop(e) if e.reason == 2)));
+        server.abort();
+    }
+}
@@ -0,0 +1,180 @@
+//! Kubernetes DaemonSet Awareness
+//!
+//! When the agent runs as a DaemonSet pod, the manifest passes the node and
+//! pod names through the downward API:
+//!
+//! ```yaml
+//! env:
+//!   - name: NODE_NAME
+//!     valueFrom: { fieldRef: { fieldPath: spec.nodeName } }
+//!   - name: POD_NAME
+//!     valueFrom: { fieldRef: { fieldPath: metadata.name } }
+//!   - name: POD_NAMESPACE
+//!     valueFrom: { fieldRef: { fieldPath: metadata.namespace } }
+//! ```
+//!
+//! [`PodIdentity`] reads them so heartbeats name the node and pod, and the
+//! K8s watchers only list pods scheduled on this node.
+//!
+//! The container filesystem is thrown away with the pod, and with it
+//! `state.json` and the agent ID. [`select_state_dir`] looks at the mounts
+//! at startup and moves the default state directory onto a hostPath or
+//! emptyDir volume if one is mounted for it.
+
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Node and pod this agent runs as, from the downward API
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodIdentity {
+    pub node_name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub pod_name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub pod_namespace: String,
+}
+
+impl PodIdentity {
+    /// Read NODE_NAME, POD_NAME and POD_NAMESPACE; None outside a DaemonSet
+    pub fn from_env() -> Option<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let get = |name: &str| var(name).map(|v| v.trim().to_string()).unwrap_or_default();
+        let identity = PodIdentity {
+            node_name: get("NODE_NAME"),
+            pod_name: get("POD_NAME"),
+            pod_namespace: get("POD_NAMESPACE"),
+        };
+        (!identity.node_name.is_empty() || !identity.pod_name.is_empty()).then_some(identity)
+    }
+
+    /// Field selector limiting pod watches to this node
+    pub fn node_selector(&self) -> Option<String> {
+        (!self.node_name.is_empty()).then(|| format!("spec.nodeName={}", self.node_name))
+    }
+}
+
+impl std::fmt::Display for PodIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.pod_namespace.is_empty(), self.pod_name.is_empty()) {
+            (_, true) => write!(f, "node {}", self.node_name),
+            (true, false) => write!(f, "pod {} on node {}", self.pod_name, self.node_name),
+            (false, false) => write!(
+                f,
+                "pod {}/{} on node {}",
+                self.pod_namespace, self.pod_name, self.node_name
+            ),
+        }
+    }
+}
+
+/// Kind of volume backing a mount point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeKind {
+    /// Survives pod restarts and rescheduling onto the same node
+    HostPath,
+    /// Survives container restarts, lost when the pod is deleted
+    EmptyDir,
+}
+
+impl VolumeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VolumeKind::HostPath => "hostPath",
+            VolumeKind::EmptyDir => "emptyDir",
+        }
+    }
+}
+
+/// A writable volume mount visible in /proc/self/mountinfo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateMount {
+    pub path: PathBuf,
+    pub kind: VolumeKind,
+}
+
+/// Writable mounts whose path names sennet, from mountinfo(5) lines
+///
+/// The kubelet backs emptyDir volumes with a directory under
+/// `kubernetes.io~empty-dir`; any other bind mount is taken as a hostPath.
+fn parse_state_mounts(mountinfo: &str) -> Vec<StateMount> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (root, mount_point, options) = (fields.get(3)?, fields.get(4)?, fields.get(5)?);
+            if !options.split(',').any(|o| o == "rw") || !mount_point.contains("sennet") {
+                return None;
+            }
+            let kind = if root.contains("kubernetes.io~empty-dir") {
+                VolumeKind::EmptyDir
+            } else {
+                VolumeKind::HostPath
+            };
+            Some(StateMount { path: PathBuf::from(mount_point), kind })
+        })
+        .collect()
+}
+
+/// Choose a state directory from the mounts; the default wins if mounted
+fn choose_state_mount(mounts: &[StateMount], default: &Path) -> Option<StateMount> {
+    mounts
+        .iter()
+        .find(|m| m.path == default)
+        .or_else(|| mounts.iter().find(|m| m.kind == VolumeKind::HostPath))
+        .or_else(|| mounts.first())
+        .cloned()
+}
+
+/// State directory volume for a pod whose state_dir is the default
+///
+/// Returns None when no volume is mounted for it; the caller then keeps the
+/// default in the container filesystem.
+pub fn select_state_dir(default: &Path) -> Option<StateMount> {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    choose_state_mount(&parse_state_mounts(&mountinfo), default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pod_identity_from_vars() {
+        assert_eq!(PodIdentity::from_vars(|_| None), None);
+
+        let vars = |name: &str| match name {
+            "NODE_NAME" => Some("worker-1".to_string()),
+            "POD_NAME" => Some("sennet-x7k2p".to_string()),
+            "POD_NAMESPACE" => Some("sennet".to_string()),
+            _ => None,
+        };
+        let pod = PodIdentity::from_vars(vars).unwrap();
+        assert_eq!(pod.node_selector().as_deref(), Some("spec.nodeName=worker-1"));
+        assert_eq!(pod.to_string(), "pod sennet/sennet-x7k2p on node worker-1");
+    }
+
+    #[test]
+    fn test_state_mounts() {
+        let mountinfo = "\
+1434 1400 0:120 / / rw,relatime - overlay overlay rw
+1450 1434 259:1 /var/lib/kubelet/pods/0c1f/volumes/kubernetes.io~empty-dir/scratch /tmp/sennet rw,relatime - ext4 /dev/nvme0n1p1 rw
+1451 1434 259:1 /var/lib/sennet /var/lib/sennet rw,relatime - ext4 /dev/nvme0n1p1 rw
+1452 1434 259:1 /etc/sennet /etc/sennet ro,relatime - ext4 /dev/nvme0n1p1 rw
+";
+        let mounts = parse_state_mounts(mountinfo);
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0].kind, VolumeKind::EmptyDir);
+
+        let chosen = choose_state_mount(&mounts, Path::new("/var/lib/sennet")).unwrap();
+        assert_eq!(chosen, StateMount { path: PathBuf::from("/var/lib/sennet"), kind: VolumeKind::HostPath });
+
+        // Without a hostPath the emptyDir is still better than the container layer
+        let chosen = choose_state_mount(&mounts[..1], Path::new("/var/lib/sennet")).unwrap();
+        assert_eq!(chosen.path, PathBuf::from("/tmp/sennet"));
+    }
+}
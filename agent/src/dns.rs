@@ -0,0 +1,307 @@
+//! DNS Resolution CLI Command
+//!
+//! Watches the DNS queries and responses the classifier parses out of
+//! UDP port 53 traffic and summarizes them: query rate, NXDOMAIN rate and
+//! the resolvers that take longest to answer. Latency is measured in the
+//! kernel, from each query to the response carrying the same client
+//! address, port and transaction ID.
+//! Usage: sennet dns [OPTIONS]
+
+use anyhow::Result;
+use colored::Colorize;
+use sennet_common::Log2Histogram;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use crate::ebpf::{dns_name, ip_addr, DnsEvent};
+
+/// DNS response code for a name that does not exist
+const RCODE_NXDOMAIN: u8 = 3;
+
+/// Options for the dns command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsOptions {
+    pub timeout_secs: u64,
+    pub limit: usize,
+}
+
+impl Default for DnsOptions {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 10,
+            limit: 10,
+        }
+    }
+}
+
+/// Parse command line arguments for the dns command
+pub fn parse_args(args: &[String]) -> DnsOptions {
+    let mut opts = DnsOptions::default();
+    let mut i = 0;
+
+    while i < args.len() {
+        match args[i].as_str() {
+            "--timeout" | "-t" if i + 1 < args.len() => {
+                opts.timeout_secs = args[i + 1].parse().unwrap_or(10);
+                i += 1;
+            }
+            "--limit" if i + 1 < args.len() => {
+                opts.limit = args[i + 1].parse().unwrap_or(10);
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    opts
+}
+
+/// Print help for the dns command
+pub fn print_help() {
+    println!("{}", "Sennet DNS - Query Rates and Resolver Latency".bold());
+    println!("Capture DNS lookups and report query rate, NXDOMAIN rate and slow resolvers.");
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet dns [OPTIONS]");
+    println!();
+    println!("{}", "OPTIONS:".yellow());
+    println!("    -t, --timeout <SECS>   Capture duration (default: 10)");
+    println!("    --limit <N>            Resolvers and names to list (default: 10)");
+    println!("    -h, --help             Show this help message");
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sennet dns                 # Ten seconds of lookups");
+    println!("    sennet dns -t 60           # A minute, for quieter hosts");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    - Requires the agent to be running (reads pinned eBPF maps)");
+    println!("    - Only plain DNS over UDP port 53; DoT, DoH and TCP fallback are not seen");
+    println!("    - Counts honor kernel sampling and rate limits (rate_limits.dns)");
+}
+
+/// Responses and latency from one resolver
+#[derive(Debug, Default)]
+pub struct ResolverStats {
+    pub responses: u64,
+    pub nxdomain: u64,
+    /// Query-to-response latency in microseconds
+    pub latency_us: Log2Histogram,
+}
+
+/// Aggregated report of observed lookups
+#[derive(Debug, Default)]
+pub struct DnsReport {
+    pub queries: u64,
+    pub responses: u64,
+    pub nxdomain: u64,
+    pub resolvers: BTreeMap<IpAddr, ResolverStats>,
+    /// Queried name → queries
+    pub names: BTreeMap<String, u64>,
+    /// Name → NXDOMAIN responses
+    pub nxdomain_names: BTreeMap<String, u64>,
+}
+
+impl DnsReport {
+    /// Count one ring event, weighted by its kernel sample rate
+    pub fn add(&mut self, event: &DnsEvent) {
+        let weight = u64::from(event.sample_rate.max(1));
+        let name = dns_name(&event.qname);
+        if event.is_response == 0 {
+            self.queries += weight;
+            *self.names.entry(name).or_insert(0) += weight;
+            return;
+        }
+
+        self.responses += weight;
+        let resolver = self.resolvers.entry(ip_addr(&event.server_addr)).or_default();
+        resolver.responses += weight;
+        // Responses without a matching query (evicted, or sent before the
+        // agent started) have no latency
+        if event.latency_ns > 0 {
+            resolver.latency_us.record(event.latency_ns / 1000);
+        }
+        if event.rcode == RCODE_NXDOMAIN {
+            self.nxdomain += weight;
+            resolver.nxdomain += weight;
+            *self.nxdomain_names.entry(name).or_insert(0) += weight;
+        }
+    }
+
+    /// Share of responses that were NXDOMAIN, in percent
+    pub fn nxdomain_pct(&self) -> f64 {
+        if self.responses == 0 {
+            return 0.0;
+        }
+        self.nxdomain as f64 * 100.0 / self.responses as f64
+    }
+
+    /// Resolvers with timed responses, slowest p95 first
+    pub fn slow_resolvers(&self) -> Vec<(IpAddr, &ResolverStats, f64)> {
+        let mut slow: Vec<_> = self
+            .resolvers
+            .iter()
+            .filter_map(|(&addr, stats)| Some((addr, stats, stats.latency_us.percentile(95.0)? / 1000.0)))
+            .collect();
+        slow.sort_by(|a, b| b.2.total_cmp(&a.2));
+        slow
+    }
+
+    pub fn print(&self, elapsed_secs: f64, limit: usize) {
+        println!();
+        println!("{}", "DNS Summary".bold());
+        println!("{}", "─".repeat(70));
+        if self.queries == 0 && self.responses == 0 {
+            println!("{}", "No DNS lookups observed.".green());
+            return;
+        }
+
+        let per_sec = |n: u64| n as f64 / elapsed_secs.max(1.0);
+        println!("  Queries      {:>8}  ({:.1}/s)", self.queries, per_sec(self.queries));
+        println!("  Responses    {:>8}  ({:.1}/s)", self.responses, per_sec(self.responses));
+        let nx = format!("{:>8}  ({:.1}% of responses)", self.nxdomain, self.nxdomain_pct());
+        println!("  NXDOMAIN     {}", if self.nxdomain_pct() >= 10.0 { nx.red() } else { nx.normal() });
+
+        let slow = self.slow_resolvers();
+        if !slow.is_empty() {
+            println!();
+            println!("{}", "Slowest resolvers:".bold());
+            println!("  {:<39} {:>9} {:>9} {:>9} {:>9}", "RESOLVER", "RESPONSES", "P50", "P95", "NXDOMAIN");
+            for (addr, stats, p95_ms) in slow.into_iter().take(limit) {
+                let p50_ms = stats.latency_us.percentile(50.0).unwrap_or(0.0) / 1000.0;
+                println!(
+                    "  {:<39} {:>9} {:>7.1}ms {:>7.1}ms {:>9}",
+                    addr.to_string(),
+                    stats.responses,
+                    p50_ms,
+                    p95_ms,
+                    stats.nxdomain
+                );
+            }
+        }
+
+        for (title, names) in [("Top names:", &self.names), ("Top NXDOMAIN names:", &self.nxdomain_names)] {
+            if names.is_empty() {
+                continue;
+            }
+            println!();
+            println!("{}", title.bold());
+            let mut names: Vec<_> = names.iter().collect();
+            names.sort_by(|a, b| b.1.cmp(a.1));
+            for (name, count) in names.into_iter().take(limit) {
+                println!("  {:>8}  {}", count, name);
+            }
+        }
+    }
+}
+
+/// Run the dns command
+pub fn run(args: &[String]) -> Result<()> {
+    let opts = parse_args(args);
+
+    println!("{}", "Sennet DNS".bold());
+    println!("Capturing DNS lookups for {}s...", opts.timeout_secs.to_string().yellow());
+    println!("{}", "─".repeat(70));
+
+    #[cfg(target_os = "linux")]
+    {
+        run_linux(&opts)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        println!("{}: DNS capture requires Linux with eBPF support", "Error".red());
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run_linux(opts: &DnsOptions) -> Result<()> {
+    use crate::ebpf::open_pinned_ringbuf;
+    use std::time::{Duration, Instant};
+
+    let mut dns_rb = open_pinned_ringbuf("dns_events").map_err(|e| {
+        anyhow::anyhow!("{}\nIs the agent running with an eBPF build that supports DNS parsing?", e)
+    })?;
+
+    let mut report = DnsReport::default();
+    let start = Instant::now();
+    let timeout = Duration::from_secs(opts.timeout_secs);
+    let mut losses = crate::ebpf::LossTracker::new();
+    let mut total_lost = 0;
+
+    while start.elapsed() < timeout {
+        while let Some(item) = dns_rb.next() {
+            if let Some(event) = crate::events::view_event::<DnsEvent>(&item) {
+                report.add(event);
+            }
+        }
+        total_lost += losses.poll()[crate::events::RingKind::Dns.index()];
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    report.print(start.elapsed().as_secs_f64(), opts.limit);
+    if total_lost > 0 {
+        println!("{}: {} DNS events were lost during capture; counts above are incomplete", "Warning".yellow(), total_lost);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(name: &str, server: [u8; 4], is_response: u8, rcode: u8, latency_ns: u64) -> DnsEvent {
+        let mut e = DnsEvent {
+            server_addr: sennet_common::ipv4_mapped(server),
+            is_response,
+            rcode,
+            latency_ns,
+            ..Default::default()
+        };
+        let mut at = 0;
+        for label in name.split('.') {
+            e.qname[at] = label.len() as u8;
+            e.qname[at + 1..at + 1 + label.len()].copy_from_slice(label.as_bytes());
+            at += 1 + label.len();
+        }
+        e
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = DnsReport::default();
+        report.add(&event("example.com", [10, 0, 0, 53], 0, 0, 0));
+        report.add(&event("example.com", [10, 0, 0, 53], 1, 0, 2_000_000));
+        report.add(&event("nope.example", [10, 0, 0, 53], 1, RCODE_NXDOMAIN, 1_000_000));
+        report.add(&event("example.com", [8, 8, 8, 8], 1, 0, 80_000_000));
+        // No matching query: counted, but not timed
+        report.add(&event("example.com", [9, 9, 9, 9], 1, 0, 0));
+
+        assert_eq!(report.queries, 1);
+        assert_eq!(report.responses, 4);
+        assert_eq!(report.nxdomain, 1);
+        assert_eq!(report.nxdomain_pct(), 25.0);
+        assert_eq!(report.names.get("example.com"), Some(&1));
+        assert_eq!(report.nxdomain_names.get("nope.example"), Some(&1));
+
+        let slow = report.slow_resolvers();
+        assert_eq!(slow.len(), 2);
+        assert_eq!(slow[0].0, "8.8.8.8".parse::<IpAddr>().unwrap());
+        assert_eq!(slow[1].1.nxdomain, 1);
+    }
+
+    #[test]
+    fn test_sampled_events_count_for_their_weight() {
+        let mut report = DnsReport::default();
+        report.add(&DnsEvent { sample_rate: 10, ..event("example.com", [10, 0, 0, 53], 0, 0, 0) });
+        assert_eq!(report.queries, 10);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args: Vec<String> = ["-t", "30", "--limit", "5"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(parse_args(&args), DnsOptions { timeout_secs: 30, limit: 5 });
+        assert_eq!(parse_args(&[]), DnsOptions::default());
+    }
+}
@@ -88,26 +88,7 @@ pub fn get_container_id_from_pid(pid: u32) -> Option<String> {
         // Parse cgroup line: hierarchy-ID:controller:path
         let parts: Vec<&str> = line.split(':').collect();
         if parts.len() >= 3 {
-            let path = parts[2];
-            
-            // Docker format: /docker/<container_id>
-            if path.starts_with("/docker/") {
-                return path.strip_prefix("/docker/")
-                    .map(|s| s.to_string());
-            }
-            
-            // Docker with systemd cgroup: /system.slice/docker-<id>.scope
-            if let Some(id) = extract_docker_systemd_id(path) {
-                return Some(id);
-            }
-            
-            // Containerd format: /system.slice/containerd-<id>.scope
-            if let Some(id) = extract_containerd_id(path) {
-                return Some(id);
-            }
-            
-            // Podman format: /user.slice/user-1000.slice/.../libpod-<id>.scope
-            if let Some(id) = extract_podman_id(path) {
+            if let Some(id) = container_id_from_cgroup_path(parts[2]) {
                 return Some(id);
             }
         }
@@ -121,6 +102,21 @@ pub fn get_container_id_from_pid(_pid: u32) -> Option<String> {
     None
 }
 
+/// Container ID named by a cgroup path, for Docker, containerd and Podman
+pub fn container_id_from_cgroup_path(path: &str) -> Option<String> {
+    // Docker format: /docker/<container_id>
+    if let Some(id) = path.strip_prefix("/docker/") {
+        return Some(id.to_string());
+    }
+
+    // Docker with systemd cgroup: /system.slice/docker-<id>.scope
+    // Containerd format: /system.slice/containerd-<id>.scope
+    // Podman format: /user.slice/user-1000.slice/.../libpod-<id>.scope
+    extract_docker_systemd_id(path)
+        .or_else(|| extract_containerd_id(path))
+        .or_else(|| extract_podman_id(path))
+}
+
 /// Extract container ID from Docker systemd cgroup path
 fn extract_docker_systemd_id(path: &str) -> Option<String> {
     // Format: /system.slice/docker-<id>.scope
@@ -273,6 +269,16 @@ mod tests {
         assert_eq!(id, Some("abc123def456".to_string()));
     }
 
+    #[test]
+    fn test_container_id_from_cgroup_path() {
+        assert_eq!(container_id_from_cgroup_path("/docker/abc123def456"), Some("abc123def456".to_string()));
+        assert_eq!(
+            container_id_from_cgroup_path("/kubepods.slice/kubepods-pod1.slice/cri-containerd-abc123def456.scope"),
+            Some("abc123def456".to_string())
+        );
+        assert_eq!(container_id_from_cgroup_path("/system.slice/sshd.service"), None);
+    }
+
     #[test]
     fn test_detect_runtime() {
         // Just ensure it doesn't panic
@@ -0,0 +1,400 @@
+//! Doctor
+//!
+//! `sennet doctor` checks whether this host can load and run the eBPF
+//! programs, and says what to change when it can't. Besides kernel version,
+//! BTF and capabilities it looks for the security layers that turn a load
+//! into a bare `EPERM`:
+//!
+//! - kernel lockdown: `confidentiality` mode blocks reading kernel memory
+//!   from BPF and the tracefs files the tracepoints are resolved through
+//! - SELinux: an enforcing policy that confines the agent's domain needs
+//!   `bpf` and `capability2` permissions
+//! - AppArmor: an enforcing profile needs the capabilities and the bpffs and
+//!   tracefs paths
+//!
+//! For SELinux and AppArmor, recent denials for `sennet` in the audit log are
+//! turned into the rules that would allow them.
+
+use anyhow::Result;
+use colored::*;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::privileges;
+use crate::service::InitSystem;
+
+/// Audit logs searched for denials, newest content last
+const AUDIT_LOGS: &[&str] = &["/var/log/audit/audit.log", "/var/log/kern.log"];
+
+/// Lines read from the end of each audit log
+const AUDIT_TAIL_LINES: usize = 5000;
+
+/// Tracefs mount points, newer first
+const TRACEFS_PATHS: &[&str] = &["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+/// SELinux domains that are not confined by policy
+const UNCONFINED_DOMAINS: &[&str] = &["unconfined_t", "unconfined_service_t", "spc_t", "kernel_t"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Result of one check
+#[derive(Debug)]
+struct Finding {
+    check: &'static str,
+    status: Status,
+    detail: String,
+    /// Suggested changes, one per line
+    fix: Vec<String>,
+}
+
+impl Finding {
+    fn new(check: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self { check, status, detail: detail.into(), fix: Vec::new() }
+    }
+
+    fn fix(mut self, line: impl Into<String>) -> Self {
+        self.fix.push(line.into());
+        self
+    }
+
+    fn print(&self) {
+        let mark = match self.status {
+            Status::Ok => "✓".green(),
+            Status::Warn => "!".yellow(),
+            Status::Fail => "✗".red(),
+        };
+        println!("{} {:<14} {}", mark, self.check, self.detail);
+        for line in &self.fix {
+            println!("  {:<14} {}", "", line.dimmed());
+        }
+    }
+}
+
+/// Kernel lockdown mode from /sys/kernel/security/lockdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lockdown {
+    None,
+    Integrity,
+    Confidentiality,
+}
+
+/// `none [integrity] confidentiality` → the bracketed mode
+fn parse_lockdown(content: &str) -> Option<Lockdown> {
+    let mode = content.split('[').nth(1)?.split(']').next()?;
+    match mode {
+        "none" => Some(Lockdown::None),
+        "integrity" => Some(Lockdown::Integrity),
+        "confidentiality" => Some(Lockdown::Confidentiality),
+        _ => None,
+    }
+}
+
+/// Value of `key=` in an audit record, without quotes
+fn audit_field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let start = line
+        .match_indices(key)
+        .find(|(i, _)| *i == 0 || line.as_bytes()[i - 1] == b' ')?
+        .0
+        + key.len();
+    let rest = line[start..].strip_prefix('=')?;
+    if let Some(quoted) = rest.strip_prefix('"') {
+        quoted.split('"').next()
+    } else {
+        rest.split_whitespace().next()
+    }
+}
+
+/// Type field of an SELinux context (`user:role:type:level`)
+fn selinux_type(context: &str) -> Option<&str> {
+    context.split(':').nth(2)
+}
+
+/// `allow` rule for an SELinux AVC denial of the agent
+fn selinux_rule(line: &str) -> Option<String> {
+    if !line.contains("avc:") || !line.contains("denied") || audit_field(line, "comm") != Some("sennet") {
+        return None;
+    }
+    let perms = line.split('{').nth(1)?.split('}').next()?.trim();
+    let source = selinux_type(audit_field(line, "scontext")?)?;
+    let target = selinux_type(audit_field(line, "tcontext")?)?;
+    let class = audit_field(line, "tclass")?;
+    let target = if target == source { "self" } else { target };
+    Some(format!("allow {} {}:{} {{ {} }};", source, target, class, perms))
+}
+
+/// AppArmor profile rule for a `DENIED` record of the agent
+fn apparmor_rule(line: &str) -> Option<String> {
+    if audit_field(line, "apparmor") != Some("DENIED") || audit_field(line, "comm") != Some("sennet") {
+        return None;
+    }
+    if let Some(cap) = audit_field(line, "capname") {
+        return Some(format!("capability {},", cap));
+    }
+    let name = audit_field(line, "name")?;
+    let mask = audit_field(line, "requested_mask").unwrap_or("r");
+    Some(format!("{} {},", name, mask))
+}
+
+/// Distinct rules for recent denials, in first-seen order
+fn denial_rules(logs: &str, rule: fn(&str) -> Option<String>) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    logs.lines()
+        .filter_map(rule)
+        .filter(|r| seen.insert(r.clone()))
+        .collect()
+}
+
+fn read_audit_logs() -> String {
+    AUDIT_LOGS
+        .iter()
+        .filter_map(|path| crate::service::tail_file(Path::new(path), AUDIT_TAIL_LINES))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Security label of the running agent, falling back to this process
+fn agent_label(pid: Option<u32>, attr: &str) -> Option<String> {
+    let read = |pid: &str| fs::read_to_string(format!("/proc/{}/attr/{}", pid, attr)).ok();
+    pid.and_then(|p| read(&p.to_string()))
+        .or_else(|| read("self"))
+        .map(|s| s.trim_end_matches('\0').trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+fn check_kernel() -> Finding {
+    match crate::btf::check_kernel_version() {
+        Some((major, minor, patch)) => {
+            let version = format!("{}.{}.{}", major, minor, patch);
+            if (major, minor) >= (5, 10) {
+                Finding::new("Kernel", Status::Ok, version)
+            } else {
+                Finding::new("Kernel", Status::Fail, format!("{} (5.10+ required)", version))
+            }
+        }
+        None => Finding::new("Kernel", Status::Warn, "could not read /proc/sys/kernel/osrelease"),
+    }
+}
+
+fn check_btf() -> Finding {
+    if Path::new("/sys/kernel/btf/vmlinux").exists() {
+        Finding::new("BTF", Status::Ok, "/sys/kernel/btf/vmlinux")
+    } else {
+        Finding::new("BTF", Status::Warn, "/sys/kernel/btf/vmlinux missing; CO-RE programs fall back to fixed offsets")
+            .fix("use a kernel built with CONFIG_DEBUG_INFO_BTF=y")
+    }
+}
+
+fn check_capabilities() -> Finding {
+    let missing = privileges::check();
+    if missing.is_empty() {
+        Finding::new("Capabilities", Status::Ok, "can load and attach programs")
+    } else {
+        Finding::new("Capabilities", Status::Fail, format!("missing {}", missing.join(", ")))
+            .fix("sudo sennet install (file capabilities), or run the service with AmbientCapabilities")
+    }
+}
+
+fn check_bpffs() -> Finding {
+    let mounted = fs::read_to_string("/proc/mounts")
+        .map(|m| m.lines().any(|l| l.split_whitespace().nth(2) == Some("bpf")))
+        .unwrap_or(false);
+    if mounted {
+        Finding::new("bpffs", Status::Ok, "mounted")
+    } else {
+        Finding::new("bpffs", Status::Warn, "not mounted; maps are not pinned, so top, trace and flows see no data")
+            .fix("sudo mount -t bpf bpf /sys/fs/bpf")
+    }
+}
+
+fn check_tracefs() -> Finding {
+    for root in TRACEFS_PATHS {
+        let id = Path::new(root).join("events/skb/kfree_skb/id");
+        match fs::read_to_string(&id) {
+            Ok(_) => return Finding::new("tracefs", Status::Ok, root.to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                return Finding::new("tracefs", Status::Fail, format!("{}: permission denied", id.display()))
+                    .fix("tracepoints cannot be resolved; see the lockdown, SELinux and AppArmor findings");
+            }
+            Err(_) => continue,
+        }
+    }
+    Finding::new("tracefs", Status::Warn, "not mounted or kfree_skb tracepoint missing; drop tracing unavailable")
+        .fix("sudo mount -t tracefs tracefs /sys/kernel/tracing")
+}
+
+fn check_lockdown() -> Finding {
+    let mode = fs::read_to_string("/sys/kernel/security/lockdown").ok().and_then(|s| parse_lockdown(&s));
+    match mode {
+        None => Finding::new("Lockdown", Status::Ok, "not supported by this kernel"),
+        Some(Lockdown::None) => Finding::new("Lockdown", Status::Ok, "none"),
+        Some(Lockdown::Integrity) => {
+            Finding::new("Lockdown", Status::Ok, "integrity (blocks bpf_probe_write_user, which the agent does not use)")
+        }
+        Some(Lockdown::Confidentiality) => Finding::new(
+            "Lockdown",
+            Status::Fail,
+            "confidentiality: BPF cannot read kernel memory and tracefs is closed",
+        )
+        .fix("boot with lockdown=integrity on the kernel command line")
+        .fix("or, if Secure Boot enabled lockdown, disable it for the kernel (mokutil --disable-validation)"),
+    }
+}
+
+fn check_selinux(pid: Option<u32>, audit: &str) -> Finding {
+    match fs::read_to_string("/sys/fs/selinux/enforce") {
+        Err(_) => return Finding::new("SELinux", Status::Ok, "disabled"),
+        Ok(s) if s.trim() != "1" => return Finding::new("SELinux", Status::Ok, "permissive"),
+        Ok(_) => {}
+    }
+    let context = agent_label(pid, "current").unwrap_or_default();
+    let domain = selinux_type(&context).unwrap_or("unknown");
+    let rules = denial_rules(audit, selinux_rule);
+    if !rules.is_empty() {
+        let mut finding = Finding::new(
+            "SELinux",
+            Status::Fail,
+            format!("enforcing; {} denied the agent {} time(s)", domain, rules.len()),
+        )
+        .fix("add to a local policy module (sennet.te), then: checkmodule -M -m -o sennet.mod sennet.te && semodule_package -o sennet.pp -m sennet.mod && semodule -i sennet.pp");
+        for rule in rules {
+            finding = finding.fix(rule);
+        }
+        return finding;
+    }
+    if !UNCONFINED_DOMAINS.contains(&domain) {
+        return Finding::new("SELinux", Status::Warn, format!("enforcing; agent runs confined as {}", domain))
+            .fix(format!("allow {} self:bpf {{ map_create map_read map_write prog_load prog_run }};", domain))
+            .fix(format!("allow {} self:capability2 {{ bpf perfmon }};", domain))
+            .fix(format!("allow {} tracefs_t:dir search;", domain));
+    }
+    Finding::new("SELinux", Status::Ok, format!("enforcing; agent runs as {}", domain))
+}
+
+fn check_apparmor(pid: Option<u32>, audit: &str) -> Finding {
+    let enabled = fs::read_to_string("/sys/module/apparmor/parameters/enabled").map(|s| s.trim() == "Y");
+    if !enabled.unwrap_or(false) {
+        return Finding::new("AppArmor", Status::Ok, "disabled");
+    }
+    // The stacked-LSM path first; attr/current belongs to SELinux when both are present
+    let label = agent_label(pid, "apparmor/current").unwrap_or_else(|| "unconfined".to_string());
+    let rules = denial_rules(audit, apparmor_rule);
+    if !rules.is_empty() {
+        let mut finding = Finding::new(
+            "AppArmor",
+            Status::Fail,
+            format!("profile {} denied the agent {} time(s)", label, rules.len()),
+        )
+        .fix("add to the profile, then: sudo apparmor_parser -r <profile file>");
+        for rule in rules {
+            finding = finding.fix(rule);
+        }
+        return finding;
+    }
+    if label.ends_with("(enforce)") {
+        return Finding::new("AppArmor", Status::Warn, format!("agent confined by {}", label))
+            .fix("the profile needs: capability bpf, capability perfmon, capability net_admin,")
+            .fix("/sys/fs/bpf/** rw, /sys/kernel/tracing/** r, /sys/kernel/debug/tracing/** r,");
+    }
+    Finding::new("AppArmor", Status::Ok, label)
+}
+
+/// Run all checks; exits with status 1 if any failed
+pub fn run() -> Result<()> {
+    println!("{}", "Sennet Doctor".bold().cyan());
+    println!("{}", "=============".bold().cyan());
+
+    if !cfg!(target_os = "linux") {
+        println!("eBPF checks apply to Linux only; on this platform the agent reads interface counters.");
+        return Ok(());
+    }
+
+    let pid = InitSystem::detect().main_pid();
+    let audit = read_audit_logs();
+    let findings = [
+        check_kernel(),
+        check_btf(),
+        check_capabilities(),
+        check_bpffs(),
+        check_tracefs(),
+        check_lockdown(),
+        check_selinux(pid, &audit),
+        check_apparmor(pid, &audit),
+    ];
+    for finding in &findings {
+        finding.print();
+    }
+
+    println!();
+    match findings.iter().map(|f| f.status).max() {
+        Some(Status::Fail) => {
+            println!("{}", "The agent cannot load its eBPF programs until the failures above are fixed.".red());
+            std::process::exit(1);
+        }
+        Some(Status::Warn) => println!("{}", "The agent can run; some features may be unavailable.".yellow()),
+        _ => println!("{}", "No problems found.".green()),
+    }
+    Ok(())
+}
+
+pub fn print_help() {
+    println!("{}", "sennet doctor - Check whether this host can run the eBPF programs".bold());
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet doctor");
+    println!();
+    println!("Checks kernel version, BTF, capabilities, bpffs, tracefs, kernel lockdown,");
+    println!("SELinux and AppArmor. Denials of the agent in the audit log are turned into");
+    println!("suggested policy rules. Exits with status 1 if a check failed.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lockdown() {
+        assert_eq!(parse_lockdown("none [integrity] confidentiality\n"), Some(Lockdown::Integrity));
+        assert_eq!(parse_lockdown("[none] integrity confidentiality\n"), Some(Lockdown::None));
+        assert_eq!(parse_lockdown("none integrity [confidentiality]"), Some(Lockdown::Confidentiality));
+        assert_eq!(parse_lockdown(""), None);
+    }
+
+    #[test]
+    fn test_denial_rules() {
+        let audit = concat!(
+            "type=AVC msg=audit(1718000000.123:456): avc:  denied  { map_create } for  pid=812 comm=\"sennet\" ",
+            "scontext=system_u:system_r:sennet_t:s0 tcontext=system_u:system_r:sennet_t:s0 tclass=bpf permissive=0\n",
+            "type=AVC msg=audit(1718000000.124:457): avc:  denied  { search } for  pid=812 comm=\"sennet\" name=\"tracing\" ",
+            "scontext=system_u:system_r:sennet_t:s0 tcontext=system_u:object_r:tracefs_t:s0 tclass=dir permissive=0\n",
+            "type=AVC msg=audit(1718000000.125:458): avc:  denied  { map_create } for  pid=812 comm=\"sennet\" ",
+            "scontext=system_u:system_r:sennet_t:s0 tcontext=system_u:system_r:sennet_t:s0 tclass=bpf permissive=0\n",
+            "type=AVC msg=audit(1718000000.126:459): avc:  denied  { read } for  pid=9 comm=\"sshd\" ",
+            "scontext=system_u:system_r:sshd_t:s0 tcontext=system_u:object_r:shadow_t:s0 tclass=file permissive=0\n",
+        );
+        assert_eq!(
+            denial_rules(audit, selinux_rule),
+            vec![
+                "allow sennet_t self:bpf { map_create };",
+                "allow sennet_t tracefs_t:dir { search };",
+            ]
+        );
+
+        let kern = concat!(
+            "audit: type=1400 audit(1718000000.1:20): apparmor=\"DENIED\" operation=\"capable\" ",
+            "profile=\"/usr/local/bin/sennet\" pid=812 comm=\"sennet\" capability=39  capname=\"bpf\"\n",
+            "audit: type=1400 audit(1718000000.2:21): apparmor=\"DENIED\" operation=\"open\" ",
+            "profile=\"/usr/local/bin/sennet\" name=\"/sys/kernel/tracing/events/skb/kfree_skb/id\" pid=812 ",
+            "comm=\"sennet\" requested_mask=\"r\" denied_mask=\"r\" fsuid=0 ouid=0\n",
+        );
+        assert_eq!(
+            denial_rules(kern, apparmor_rule),
+            vec!["capability bpf,", "/sys/kernel/tracing/events/skb/kfree_skb/id r,"]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Drop Summaries
+//!
+//! kfree_skb drop events grouped by drop reason. The daemon keeps a
+//! [`SharedDrops`] filled from the DROP_EVENTS ring: through
+//! [`SharedDrops::sink`] while the event pipeline reads the ring, otherwise
+//! by draining it with `EbpfManager::read_drop_summary`. Heartbeats carry
+//! the drops since the previous heartbeat; the control socket's `drops`
+//! method, and so `sennet status`, the totals since the agent started.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::ebpf::{drop_reason_str, DropEvent};
+use crate::pipeline::{SinkFn, Summary};
+
+/// Drop events by kernel drop reason
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DropSummary {
+    pub events: u64,
+    /// Drop reason → events
+    pub by_reason: BTreeMap<u32, u64>,
+}
+
+impl DropSummary {
+    /// Count one ring event, weighted by its kernel sample rate
+    pub fn add_event(&mut self, event: &DropEvent) {
+        self.add(event.reason, u64::from(event.sample_rate.max(1)));
+    }
+
+    pub fn add(&mut self, reason: u32, count: u64) {
+        self.events += count;
+        *self.by_reason.entry(reason).or_insert(0) += count;
+    }
+
+    pub fn merge(&mut self, other: &DropSummary) {
+        for (&reason, &count) in &other.by_reason {
+            self.add(reason, count);
+        }
+    }
+
+    /// Reasons by name, most frequent first, at most `limit`
+    pub fn top(&self, limit: usize) -> Vec<(&'static str, u64)> {
+        let mut named: BTreeMap<&'static str, u64> = BTreeMap::new();
+        for (&reason, &count) in &self.by_reason {
+            *named.entry(drop_reason_str(reason)).or_insert(0) += count;
+        }
+        let mut top: Vec<_> = named.into_iter().collect();
+        top.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        top.truncate(limit);
+        top
+    }
+}
+
+/// Drop totals shared between whatever drains the ring and the heartbeat
+/// loop and control socket that report them
+#[derive(Clone, Default)]
+pub struct SharedDrops {
+    /// Since the agent started, and since the last heartbeat
+    inner: Arc<Mutex<(DropSummary, DropSummary)>>,
+}
+
+impl SharedDrops {
+    pub fn add(&self, summary: &DropSummary) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.0.merge(summary);
+        inner.1.merge(summary);
+    }
+
+    /// Sink adding each window's drops
+    pub fn sink(&self) -> SinkFn {
+        let shared = self.clone();
+        Box::new(move |summary: &Summary| {
+            if summary.drops_by_reason.is_empty() {
+                return;
+            }
+            shared.add(&DropSummary {
+                events: summary.drops_by_reason.values().sum(),
+                by_reason: summary.drops_by_reason.clone(),
+            });
+        })
+    }
+
+    /// Drops since the agent started
+    pub fn total(&self) -> DropSummary {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).0.clone()
+    }
+
+    /// Drops since the last call
+    pub fn take_recent(&self) -> DropSummary {
+        std::mem::take(&mut self.inner.lock().unwrap_or_else(|e| e.into_inner()).1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_and_shared() {
+        let mut summary = DropSummary::default();
+        summary.add_event(&DropEvent { reason: 2, ..Default::default() });
+        summary.add_event(&DropEvent { reason: 2, sample_rate: 4, ..Default::default() });
+        summary.add_event(&DropEvent { reason: 7, ..Default::default() });
+        assert_eq!(summary.events, 6);
+        assert_eq!(summary.top(1), vec![(drop_reason_str(2), 5)]);
+
+        let shared = SharedDrops::default();
+        shared.add(&summary);
+        assert_eq!(shared.take_recent(), summary);
+        assert_eq!(shared.take_recent(), DropSummary::default());
+        shared.add(&summary);
+        assert_eq!(shared.total().events, 12);
+    }
+}
@@ -1,46 +1,167 @@
 //! eBPF Loader and Manager
 //!
-//! Loads and manages the TC eBPF programs and kfree_skb tracepoint.
+//! Loads and manages the TC (or XDP) eBPF programs and kfree_skb tracepoint.
 //! Reads counters and drop events.
 //! On non-Linux platforms, provides a mock implementation.
 //!
-//! Note: Types mirror sennet-common for binary compatibility with eBPF programs.
+//! Note: Map and event types are re-exported from sennet-common, which the
+//! eBPF programs are built against, so both sides share one layout.
 //! These types are used by: heartbeat (metrics), tui (live display), trace (drop events).
 
 use anyhow::Result;
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
 
-/// Packet counters structure (mirrors eBPF side in sennet-common)
-/// Must implement Pod trait for use with aya maps
-#[repr(C)]
-#[derive(Clone, Copy, Default, Debug)]
+pub use crate::events::{ConntrackEvent, DnsEvent, DropEvent, HttpEvent, NetfilterEvent, PacketEvent, PayloadEvent, RetransmitEvent, RstEvent};
+use crate::events::RingKind;
+
+/// bpffs directory where the agent pins its maps, without a
+/// `pin_namespace`
+#[allow(dead_code)] // Used on Linux
+pub const PIN_PATH: &str = "/sys/fs/bpf/sennet";
+
+static PIN_DIR: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+
+/// Whether `namespace` can name a pin directory
+pub fn is_valid_pin_namespace(namespace: &str) -> bool {
+    !namespace.is_empty()
+        && !namespace.starts_with('.')
+        && namespace.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Pin directory of the agent with `namespace`: `PIN_PATH`, or
+/// `PIN_PATH-<namespace>`
+pub fn pin_dir_for(namespace: Option<&str>) -> PathBuf {
+    match namespace {
+        Some(namespace) => PathBuf::from(format!("{}-{}", PIN_PATH, namespace)),
+        None => PathBuf::from(PIN_PATH),
+    }
+}
+
+/// Pin maps under the directory of `namespace`, and read them from there
+///
+/// The daemon calls this before loading; without it [`pin_dir`] finds the
+/// directory itself. Has no effect once the directory was picked.
+pub fn set_pin_namespace(namespace: Option<&str>) {
+    let _ = PIN_DIR.set(pin_dir_for(namespace));
+}
+
+/// Directory the maps are pinned under
+///
+/// Unless [`set_pin_namespace`] picked it: the one named by
+/// `SENNET_PIN_NAMESPACE`, else `PIN_PATH` if an agent pinned there, else
+/// the only namespaced directory holding pins.
+pub fn pin_dir() -> &'static Path {
+    PIN_DIR.get_or_init(|| {
+        if let Some(namespace) = std::env::var("SENNET_PIN_NAMESPACE").ok().filter(|s| !s.is_empty()) {
+            return pin_dir_for(Some(&namespace));
+        }
+        let default = PathBuf::from(PIN_PATH);
+        if default.join("schema").exists() {
+            return default;
+        }
+        match pinned_namespaces().as_slice() {
+            [only] => pin_dir_for(Some(only)),
+            _ => default,
+        }
+    })
+}
+
+/// Namespace of the agent whose maps [`pin_dir`] reads, None for `PIN_PATH`
+pub fn pin_namespace() -> Option<String> {
+    let dir = pin_dir().to_str()?;
+    dir.strip_prefix(PIN_PATH)?.strip_prefix('-').map(String::from)
+}
+
+/// Where to look when no maps are pinned in [`pin_dir`]: empty, or a line
+/// listing the namespaced agents to pick from
+pub fn pin_namespace_hint() -> String {
+    let namespaces = pinned_namespaces();
+    if namespaces.is_empty() {
+        return String::new();
+    }
+    format!(
+        "\nAgents with a pin_namespace are running: {}; pick one with SENNET_PIN_NAMESPACE",
+        namespaces.join(", ")
+    )
+}
+
+/// Namespaces of the agents that pinned maps under `PIN_PATH-<namespace>`
+pub fn pinned_namespaces() -> Vec<String> {
+    let (parent, prefix) = PIN_PATH.rsplit_once('/').unwrap_or(("/", PIN_PATH));
+    let prefix = format!("{}-", prefix);
+    let Ok(entries) = std::fs::read_dir(parent) else {
+        return Vec::new();
+    };
+    let mut namespaces: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("schema").exists())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_prefix(&prefix).map(String::from))
+        .collect();
+    namespaces.sort();
+    namespaces
+}
+
+/// Mount point of the cgroup v2 hierarchy
+#[allow(dead_code)] // Used on Linux
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Interface counters, from the COUNTERS and TCP_FLAG_COUNTERS maps
+#[derive(Clone, Copy, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PacketCounters {
     pub rx_packets: u64,
     pub rx_bytes: u64,
     pub tx_packets: u64,
     pub tx_bytes: u64,
     pub drop_count: u64,
+    /// Absent from agents older than the per-protocol counters
+    #[serde(default)]
+    pub rx_proto: ProtoCounters,
+    #[serde(default)]
+    pub tx_proto: ProtoCounters,
+    /// Absent from agents older than the TCP flag counters
+    #[serde(default)]
+    pub rx_tcp_flags: TcpFlagCounters,
+    #[serde(default)]
+    pub tx_tcp_flags: TcpFlagCounters,
 }
 
-// SAFETY: PacketCounters is #[repr(C)], contains only u64 fields,
-// and has no padding. It is safe to interpret as bytes.
-#[cfg(target_os = "linux")]
-unsafe impl aya::Pod for PacketCounters {}
+#[allow(dead_code)] // Used on Linux
+impl PacketCounters {
+    /// Add one CPU's COUNTERS value at `direction` (0 = ingress, 1 = egress)
+    pub(crate) fn add_kernel(&mut self, direction: u32, counters: &KernelCounters) {
+        if direction == 0 {
+            self.rx_packets += counters.rx_packets;
+            self.rx_bytes += counters.rx_bytes;
+            self.drop_count += counters.drop_count;
+            self.rx_proto.merge(&counters.rx_proto);
+        } else {
+            self.tx_packets += counters.tx_packets;
+            self.tx_bytes += counters.tx_bytes;
+            self.tx_proto.merge(&counters.tx_proto);
+        }
+    }
 
-/// Drop event structure (mirrors eBPF side in sennet-common)
-/// Used for kfree_skb tracepoint events
-#[repr(C)]
-#[derive(Clone, Copy, Default, Debug)]
-#[allow(dead_code)] // Used on Linux; exposed for cross-platform API consistency
-pub struct DropEvent {
-    pub timestamp_ns: u64,
-    pub reason: u32,
-    pub ifindex: u32,
-    pub protocol: u16,
-    pub _pad: u16,
+    /// Add one CPU's TCP_FLAG_COUNTERS value at `direction`
+    pub(crate) fn add_tcp_flags(&mut self, direction: u32, flags: &TcpFlagCounters) {
+        if direction == 0 {
+            self.rx_tcp_flags.merge(flags);
+        } else {
+            self.tx_tcp_flags.merge(flags);
+        }
+    }
 }
 
-#[cfg(target_os = "linux")]
-unsafe impl aya::Pod for DropEvent {}
+/// COUNTERS map value: the kernel layout, without the flag counters
+pub(crate) use sennet_common::PacketCounters as KernelCounters;
+
+/// Packets and bytes by L4 protocol, shared with the eBPF side
+pub use sennet_common::ProtoCounters;
+
+/// TCP segments by control flag, shared with the eBPF side
+pub use sennet_common::TcpFlagCounters;
 
 /// Human-readable drop reason string (from sk_drop_reason enum)
 #[allow(dead_code)] // Used on Linux
@@ -75,6 +196,8 @@ pub fn drop_reason_str(reason: u32) -> &'static str {
         41 => "NEIGH_FAILED",
         42 => "NEIGH_QUEUEFULL",
         44 => "TC_EGRESS",
+        47 => "XDP",
+        48 => "TC_INGRESS",
         _ => "UNKNOWN",
     }
 }
@@ -91,112 +214,474 @@ pub fn eth_proto_str(proto: u16) -> &'static str {
     }
 }
 
-/// Netfilter event structure (mirrors eBPF side in sennet-common)
-/// Used for nf_hook_slow tracepoint events (Phase 6.2)
-#[repr(C)]
-#[derive(Clone, Copy, Default, Debug)]
-#[allow(dead_code)] // Used on Linux
-pub struct NetfilterEvent {
-    pub timestamp_ns: u64,
-    pub hook: u8,
-    pub pf: u8,
-    pub verdict: u8,
-    pub _pad: u8,
-    pub ifindex_in: u32,
-    pub ifindex_out: u32,
+/// Human-readable IP protocol (or IPv6 next header)
+#[allow(dead_code)]
+pub fn ip_proto_str(proto: u8) -> &'static str {
+    match proto {
+        1 => "ICMP",
+        6 => "TCP",
+        17 => "UDP",
+        47 => "GRE",
+        50 => "ESP",
+        58 => "ICMPv6",
+        132 => "SCTP",
+        _ => "OTHER",
+    }
 }
 
-#[cfg(target_os = "linux")]
-unsafe impl aya::Pod for NetfilterEvent {}
+/// One-line description of a large packet: size, protocol and addresses;
+/// for a rate anomaly, the source's packet rate
+#[allow(dead_code)]
+pub fn describe_packet(e: &PacketEvent) -> String {
+    if e.is_anomaly() {
+        return format!(
+            "{} pps from {} (last: {} → {})",
+            e.pps,
+            format_addr(&e.src_addr),
+            ip_proto_str(e.protocol),
+            format_addr(&e.dst_addr)
+        );
+    }
+    match e.eth_proto {
+        0x0800 | 0x86DD => format!(
+            "{} B {} {} → {}",
+            e.size,
+            ip_proto_str(e.protocol),
+            format_endpoint(&e.src_addr, e.src_port),
+            format_endpoint(&e.dst_addr, e.dst_port)
+        ),
+        proto => format!("{} B eth={}", e.size, eth_proto_str(proto)),
+    }
+}
 
-/// Human-readable hook name
-#[allow(dead_code)] // Used on Linux
-pub fn nf_hook_str(hook: u8) -> &'static str {
-    match hook {
-        0 => "PREROUTING",
-        1 => "INPUT",
-        2 => "FORWARD",
-        3 => "OUTPUT",
-        4 => "POSTROUTING",
+/// One-line description of a payload snippet: packet size, protocol and
+/// endpoints
+#[allow(dead_code)]
+pub fn describe_payload(e: &PayloadEvent) -> String {
+    let endpoint = |addr: &[u8; 16], port: u16| match port {
+        0 => ip_addr(addr).to_string(),
+        port => std::net::SocketAddr::new(ip_addr(addr), port).to_string(),
+    };
+    format!(
+        "{} B {} {} → {}",
+        e.len,
+        ip_proto_str(e.protocol),
+        endpoint(&e.src_addr, e.src_port),
+        endpoint(&e.dst_addr, e.dst_port)
+    )
+}
+
+/// One-line description of an HTTP request: method, target and endpoints
+#[allow(dead_code)]
+pub fn describe_http(e: &HttpEvent) -> String {
+    format!(
+        "{} {} {} → {}",
+        sennet_common::http_method_str(e.method),
+        e.path(),
+        std::net::SocketAddr::new(ip_addr(&e.src_addr), e.src_port),
+        std::net::SocketAddr::new(ip_addr(&e.dst_addr), e.dst_port)
+    )
+}
+
+/// Hex dump of `bytes`, 16 to a line with offsets and printable ASCII,
+/// like `tcpdump -X`
+pub fn hex_lines(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk.chunks(2).map(|pair| pair.iter().map(|b| format!("{:02x}", b)).collect()).collect();
+            let ascii: String = chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+            format!("0x{:04x}:  {:<41}{}", i * 16, hex.join(" "), ascii)
+        })
+        .collect()
+}
+
+/// Human-readable TCP socket state (`tcp_states.h`)
+#[allow(dead_code)]
+pub fn tcp_state_str(state: u8) -> &'static str {
+    match state {
+        1 => "ESTABLISHED",
+        2 => "SYN_SENT",
+        3 => "SYN_RECV",
+        4 => "FIN_WAIT1",
+        5 => "FIN_WAIT2",
+        6 => "TIME_WAIT",
+        7 => "CLOSE",
+        8 => "CLOSE_WAIT",
+        9 => "LAST_ACK",
+        10 => "LISTEN",
+        11 => "CLOSING",
+        12 => "NEW_SYN_RECV",
         _ => "UNKNOWN",
     }
 }
 
-/// Human-readable verdict name
-#[allow(dead_code)] // Used on Linux
-pub fn nf_verdict_str(verdict: u8) -> &'static str {
-    match verdict {
-        0 => "DROP",
-        1 => "ACCEPT",
-        2 => "STOLEN",
-        3 => "QUEUE",
-        4 => "REPEAT",
-        5 => "STOP",
-        _ => "UNKNOWN",
+/// IP protocol and endpoints of a dropped packet from its own headers, as
+/// "TCP 192.0.2.1:40000 → 10.0.0.7:443"; None if the kernel didn't parse them
+#[allow(dead_code)]
+pub fn describe_drop_packet(e: &DropEvent) -> Option<String> {
+    if !e.has_packet_tuple() {
+        return None;
+    }
+    Some(format!(
+        "{} {} → {}",
+        ip_proto_str(e.ip_protocol),
+        format_endpoint(&e.src_addr, e.packet_src_port),
+        format_endpoint(&e.dst_addr, e.packet_dst_port)
+    ))
+}
+
+/// Address and port as "192.0.2.1:40000" or "[2001:db8::1]:443"; just the
+/// address if `port` is 0
+#[allow(dead_code)]
+pub fn format_endpoint(addr: &[u8; 16], port: u16) -> String {
+    match port {
+        0 => ip_addr(addr).to_string(),
+        port => std::net::SocketAddr::new(ip_addr(addr), port).to_string(),
+    }
+}
+
+/// One-line description of a retransmission: endpoints and socket state
+#[allow(dead_code)]
+pub fn describe_retransmit(e: &RetransmitEvent) -> String {
+    format!(
+        "{} → {} {}",
+        std::net::SocketAddr::new(ip_addr(&e.src_addr), e.src_port),
+        std::net::SocketAddr::new(ip_addr(&e.dst_addr), e.dst_port),
+        tcp_state_str(e.state)
+    )
+}
+
+/// One-line description of a conntrack event: the original tuple, the
+/// reply tuple when NAT rewrote it, and the status on destroy
+#[allow(dead_code)]
+pub fn describe_conntrack(e: &ConntrackEvent) -> String {
+    use sennet_common::{ct_event, ct_status};
+
+    let endpoint = |addr: &[u8; 16], port: u16| match port {
+        0 => ip_addr(addr).to_string(),
+        port => std::net::SocketAddr::new(ip_addr(addr), port).to_string(),
+    };
+    let what = match e.event_type {
+        ct_event::NEW => "new",
+        ct_event::DESTROY => "destroy",
+        _ => "?",
+    };
+    let mut line = format!(
+        "{} {} {} → {}",
+        what,
+        ip_proto_str(e.protocol),
+        endpoint(&e.src_addr, e.src_port),
+        endpoint(&e.dst_addr, e.dst_port)
+    );
+    // Without NAT the reply tuple is the original one reversed
+    if (e.reply_src_addr, e.reply_src_port, e.reply_dst_addr, e.reply_dst_port)
+        != (e.dst_addr, e.dst_port, e.src_addr, e.src_port)
+    {
+        line += &format!(
+            " (reply {} → {})",
+            endpoint(&e.reply_src_addr, e.reply_src_port),
+            endpoint(&e.reply_dst_addr, e.reply_dst_port)
+        );
+    }
+    if e.event_type == ct_event::DESTROY {
+        line += if e.status & ct_status::ASSURED != 0 {
+            " ASSURED"
+        } else if e.status & ct_status::SEEN_REPLY != 0 {
+            " REPLIED"
+        } else {
+            " UNREPLIED"
+        };
+    }
+    line
+}
+
+/// Dotted name from a DNS wire-format name (length-prefixed labels)
+///
+/// Stops at the terminating zero, a compression pointer or the end of
+/// `wire`; a name cut short by the end of `wire` ends in "…". The root
+/// name is ".".
+#[allow(dead_code)]
+pub fn dns_name(wire: &[u8]) -> String {
+    let mut labels: Vec<String> = Vec::new();
+    let mut rest = wire;
+    let mut complete = false;
+    while let Some((&len, tail)) = rest.split_first() {
+        if len == 0 || len & 0xc0 != 0 {
+            complete = true;
+            break;
+        }
+        let label = &tail[..(len as usize).min(tail.len())];
+        labels.push(label.iter().map(|&b| if b.is_ascii_graphic() { b as char } else { '?' }).collect());
+        rest = &tail[label.len()..];
+    }
+    match (labels.is_empty(), complete) {
+        (true, true) => ".".to_string(),
+        (_, true) => labels.join("."),
+        (_, false) => format!("{}…", labels.join(".")),
+    }
+}
+
+/// Human-readable DNS response code (RFC 1035, RFC 2136)
+#[allow(dead_code)]
+pub fn dns_rcode_str(rcode: u8) -> &'static str {
+    match rcode {
+        0 => "NOERROR",
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        _ => "OTHER",
+    }
+}
+
+/// Human-readable DNS query type
+#[allow(dead_code)]
+pub fn dns_qtype_str(qtype: u16) -> String {
+    match qtype {
+        1 => "A".to_string(),
+        2 => "NS".to_string(),
+        5 => "CNAME".to_string(),
+        6 => "SOA".to_string(),
+        12 => "PTR".to_string(),
+        15 => "MX".to_string(),
+        16 => "TXT".to_string(),
+        28 => "AAAA".to_string(),
+        33 => "SRV".to_string(),
+        65 => "HTTPS".to_string(),
+        other => format!("TYPE{}", other),
+    }
+}
+
+/// One-line description of a DNS query or response
+#[allow(dead_code)]
+pub fn describe_dns(e: &DnsEvent) -> String {
+    let name = dns_name(&e.qname);
+    let qtype = dns_qtype_str(e.qtype);
+    if e.is_response == 0 {
+        return format!("{} {} → {}", qtype, name, format_addr(&e.server_addr));
     }
+    let latency = if e.latency_ns > 0 { format!(" in {:.1}ms", e.latency_ns as f64 / 1e6) } else { String::new() };
+    format!("{} {} {} from {}{}", qtype, name, dns_rcode_str(e.rcode), format_addr(&e.server_addr), latency)
 }
 
+/// Rate limiter settings for the TUNABLES map, shared with the eBPF side
+pub use sennet_common::Tunables;
+
+/// Kernel drop filter for the DROP_FILTER map, shared with the eBPF side;
+/// built by `Filter::drop_filter`
+pub use sennet_common::DropFilter;
+
+/// Filter `sennet trace` writes to the pinned TRACE_RULE map, shared with
+/// the eBPF side; built by `Filter::trace_rule`
+pub use sennet_common::TraceRule;
+
+/// Packets `sennet trace --payload` writes to the pinned PAYLOAD_RULE map,
+/// shared with the eBPF side
+pub use sennet_common::PayloadRule;
+
+/// BLOCKLIST entry, shared with the eBPF side
+pub use sennet_common::BlockKey;
+
+/// kfree_skb record field offsets for the KFREE_SKB_LAYOUT map, shared with
+/// the eBPF side; built by `tracefmt::kfree_skb_layout`
+pub use sennet_common::KfreeSkbLayout;
+
+/// tcp_retransmit_skb record field offsets for the TCP_RETRANSMIT_SKB_LAYOUT
+/// map, shared with the eBPF side; built by `tracefmt::tcp_retransmit_skb_layout`
+pub use sennet_common::TcpRetransmitSkbLayout;
+
+/// tcp_probe record field offsets for the TCP_PROBE_LAYOUT map, shared with
+/// the eBPF side; built by `tracefmt::tcp_probe_layout`
+pub use sennet_common::TcpProbeLayout;
+
+/// inet_sock_set_state record field offsets for the
+/// INET_SOCK_SET_STATE_LAYOUT map, shared with the eBPF side; built by
+/// `tracefmt::inet_sock_set_state_layout`
+pub use sennet_common::InetSockSetStateLayout;
+
+/// struct sk_buff field offsets for the SKB_LAYOUT map, shared with the
+/// eBPF side; built by `btf::skb_layout`
+pub use sennet_common::SkbLayout;
+
+/// struct nf_conn field offsets for the NF_CONN_LAYOUT map, shared with the
+/// eBPF side; built by `btf::nf_conn_layout`
+pub use sennet_common::NfConnLayout;
+
+/// Netfilter hook, verdict and family names, shared with the eBPF side
+pub use sennet_common::{nf_family_str, nf_hook_str, nf_verdict_str, NfFamily, NfHook, NfVerdict};
+
 // ============================================================================
 // Flow Tracking Types (Phase 8: Process Attribution)
 // ============================================================================
 
-/// 5-tuple flow key for tracking connections (mirrors eBPF side)
-#[repr(C)]
-#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
-#[allow(dead_code)]
-pub struct FlowKey {
-    pub src_ip: u32,
-    pub dst_ip: u32,
-    pub src_port: u16,
-    pub dst_port: u16,
-    pub protocol: u8,
-    pub _pad: [u8; 3],
+/// Flow key and info, shared with the eBPF side (not mirrored)
+pub use sennet_common::{FlowInfo, FlowKey};
+
+/// TLS server name, shared with the eBPF side
+pub use sennet_common::{TlsSni, TLS_SNI_LEN};
+
+/// Top-talkers key and counters, shared with the eBPF side
+pub use sennet_common::{IpPair, TalkerCounters};
+
+/// One row of the top-talkers view
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopTalker {
+    pub src: std::net::IpAddr,
+    pub dst: std::net::IpAddr,
+    pub packets: u64,
+    pub bytes: u64,
+    /// Kernel time of the last packet (ns)
+    pub last_seen_ns: u64,
 }
 
-#[cfg(target_os = "linux")]
-unsafe impl aya::Pod for FlowKey {}
+/// Top-talkers map entries, busiest pair first
+pub fn rank_top_talkers(entries: impl IntoIterator<Item = (IpPair, TalkerCounters)>) -> Vec<TopTalker> {
+    let mut talkers: Vec<TopTalker> = entries
+        .into_iter()
+        .map(|(pair, counters)| TopTalker {
+            src: ip_addr(&pair.src_addr),
+            dst: ip_addr(&pair.dst_addr),
+            packets: counters.packets,
+            bytes: counters.bytes,
+            last_seen_ns: counters.last_seen_ns,
+        })
+        .collect();
+    talkers.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.packets.cmp(&a.packets)));
+    talkers
+}
 
-/// Flow information with PID attribution (mirrors eBPF side)
-#[repr(C)]
-#[derive(Clone, Copy, Default, Debug)]
-#[allow(dead_code)]
-pub struct FlowInfo {
-    pub pid: u32,
-    pub tgid: u32,
-    pub comm: [u8; 16],
-    pub start_time_ns: u64,
+/// Traffic of one cgroup on one CPU, shared with the eBPF side
+pub use sennet_common::CgroupCounters;
+
+/// TCP connection churn, shared with the eBPF side; per CPU in the kernel
+/// map, summed by [`read_pinned_conn_churn`]
+pub use sennet_common::ConnChurn;
+
+/// Traffic of one cgroup, summed over CPUs
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CgroupTraffic {
+    /// cgroup v2 ID (the inode number of the cgroup's directory)
+    pub cgroup_id: u64,
+    /// Path below [`CGROUP_ROOT`], None if the cgroup is gone
+    pub path: Option<String>,
+    /// Container named by the path (Docker, containerd, Podman)
+    pub container_id: Option<String>,
+    pub rx_packets: u64,
     pub rx_bytes: u64,
+    pub tx_packets: u64,
     pub tx_bytes: u64,
-    pub rx_packets: u32,
-    pub tx_packets: u32,
-    pub state: u8,
-    pub direction: u8,
-    pub _pad: [u8; 2],
 }
 
+/// CGROUP_COUNTERS entries merged over CPUs, busiest cgroup first
+///
+/// `paths` maps cgroup IDs to their paths, as from [`cgroup_paths`].
+pub fn summarize_cgroups(
+    entries: impl IntoIterator<Item = (u64, Vec<CgroupCounters>)>,
+    paths: &std::collections::HashMap<u64, String>,
+) -> Vec<CgroupTraffic> {
+    let mut cgroups: Vec<CgroupTraffic> = entries
+        .into_iter()
+        .map(|(cgroup_id, per_cpu)| {
+            let path = paths.get(&cgroup_id).cloned();
+            let mut traffic = CgroupTraffic {
+                cgroup_id,
+                container_id: path.as_deref().and_then(crate::docker::container_id_from_cgroup_path),
+                path,
+                rx_packets: 0,
+                rx_bytes: 0,
+                tx_packets: 0,
+                tx_bytes: 0,
+            };
+            for cpu in &per_cpu {
+                traffic.rx_packets += cpu.rx_packets;
+                traffic.rx_bytes += cpu.rx_bytes;
+                traffic.tx_packets += cpu.tx_packets;
+                traffic.tx_bytes += cpu.tx_bytes;
+            }
+            traffic
+        })
+        .collect();
+    cgroups.sort_by(|a, b| (b.rx_bytes + b.tx_bytes).cmp(&(a.rx_bytes + a.tx_bytes)).then(a.cgroup_id.cmp(&b.cgroup_id)));
+    cgroups
+}
+
+/// cgroup ID → path below [`CGROUP_ROOT`] of every current cgroup
+///
+/// On cgroup v2 a cgroup's ID is its directory's inode number.
 #[cfg(target_os = "linux")]
-unsafe impl aya::Pod for FlowInfo {}
+pub fn cgroup_paths() -> std::collections::HashMap<u64, String> {
+    use std::os::unix::fs::MetadataExt;
 
-/// Flow event from RingBuf (mirrors eBPF side)
-#[repr(C)]
-#[derive(Clone, Copy, Default, Debug)]
-#[allow(dead_code)]
-pub struct FlowEvent {
-    pub timestamp_ns: u64,
-    pub event_type: u8,
-    pub direction: u8,
-    pub protocol: u8,
-    pub _pad: u8,
-    pub pid: u32,
-    pub src_ip: u32,
-    pub dst_ip: u32,
-    pub src_port: u16,
-    pub dst_port: u16,
-    pub comm: [u8; 16],
+    let root = std::path::Path::new(CGROUP_ROOT);
+    let mut paths = std::collections::HashMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(meta) = std::fs::metadata(&dir) else { continue };
+        let relative = dir.strip_prefix(root).map(|p| format!("/{}", p.display())).unwrap_or_default();
+        paths.insert(meta.ino(), relative);
+        let Ok(children) = std::fs::read_dir(&dir) else { continue };
+        pending.extend(children.filter_map(|e| e.ok()).filter(|e| e.file_type().is_ok_and(|t| t.is_dir())).map(|e| e.path()));
+    }
+    paths
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn cgroup_paths() -> std::collections::HashMap<u64, String> {
+    std::collections::HashMap::new()
 }
 
+/// Smoothed RTTs to one remote address on one CPU, in microseconds (the
+/// shared histogram layout, as RTT_HISTOGRAMS and ICMP_RTT values)
+#[repr(transparent)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RttHistogram(pub sennet_common::Log2Histogram);
+
 #[cfg(target_os = "linux")]
-unsafe impl aya::Pod for FlowEvent {}
+unsafe impl aya::Pod for RttHistogram {}
+
+/// RTT percentiles to one remote address, as shown by `sennet latency`
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteRtt {
+    pub remote: std::net::IpAddr,
+    /// Segments received, each sampling the connection's smoothed RTT, or
+    /// echo replies timed (`sennet latency --icmp`)
+    pub samples: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// RTT map entries merged over CPUs, most sampled remote first
+pub fn summarize_rtt(entries: impl IntoIterator<Item = ([u8; 16], Vec<RttHistogram>)>) -> Vec<RemoteRtt> {
+    let mut rtts: Vec<RemoteRtt> = entries
+        .into_iter()
+        .filter_map(|(remote, per_cpu)| {
+            let mut hist = sennet_common::Log2Histogram::default();
+            for cpu in &per_cpu {
+                hist.merge(&cpu.0);
+            }
+            let samples = hist.count();
+            let ms = |p| hist.percentile(p).unwrap_or(0.0) / 1000.0;
+            (samples > 0).then(|| RemoteRtt {
+                remote: ip_addr(&remote),
+                samples,
+                mean_ms: hist.sum as f64 / samples as f64 / 1000.0,
+                p50_ms: ms(50.0),
+                p90_ms: ms(90.0),
+                p99_ms: ms(99.0),
+            })
+        })
+        .collect();
+    rtts.sort_by(|a, b| b.samples.cmp(&a.samples).then(a.remote.cmp(&b.remote)));
+    rtts
+}
 
 /// Human-readable flow direction
 #[allow(dead_code)]
@@ -233,125 +718,956 @@ pub fn format_ip(ip: u32) -> String {
     format!("{}.{}.{}.{}", bytes[0], bytes[1], bytes[2], bytes[3])
 }
 
-#[cfg(target_os = "linux")]
-use {
-    aya::{
-        include_bytes_aligned,
-        programs::{tc, SchedClassifier, TcAttachType, TracePoint, KProbe},
-        maps::{PerCpuArray, HashMap as LruHashMap},
-        Bpf,
-    },
-    std::path::Path,
-};
+/// Convert an eBPF IPv4 address to Ipv4Addr (same byte order as format_ip)
+pub fn ipv4_addr(ip: u32) -> Ipv4Addr {
+    Ipv4Addr::from(ip.to_be_bytes())
+}
 
-/// eBPF program manager
-/// 
-/// On Linux: Loads and attaches TC classifiers and tracepoints
-/// On other platforms: Provides a mock implementation for development
-#[allow(dead_code)] // Used only on Linux; mock on other platforms
-pub struct EbpfManager {
-    interface: String,
-    #[cfg(target_os = "linux")]
-    bpf: Bpf,
-    /// Whether drop tracing is active (kfree_skb tracepoint attached)
-    pub drop_tracing_enabled: bool,
-    /// Whether netfilter tracing is active (nf_hook_slow tracepoint attached)
-    pub nf_tracing_enabled: bool,
-    /// Whether flow tracking is active (tcp_connect/inet_csk_accept kprobes attached) (Phase 8)
-    pub flow_tracing_enabled: bool,
+/// Address from a 16-byte event field (network byte order), unmapping
+/// IPv4-mapped addresses
+pub fn ip_addr(addr: &[u8; 16]) -> std::net::IpAddr {
+    match sennet_common::mapped_ipv4(addr) {
+        Some(v4) => Ipv4Addr::from(v4).into(),
+        None => std::net::Ipv6Addr::from(*addr).into(),
+    }
 }
 
-#[allow(dead_code)] // Methods used on Linux; mock impl on other platforms
-impl EbpfManager {
-    /// Load and attach eBPF programs to the specified interface
-    #[cfg(target_os = "linux")]
-    pub fn load_and_attach(interface: &str) -> Result<Self> {
-        tracing::info!("Loading eBPF programs...");
-        
-        // Load the eBPF binary with proper alignment for ELF parsing
-        // NOTE: Must use include_bytes_aligned! instead of include_bytes! because
-        // the ELF parser requires 8-byte aligned memory, which include_bytes! doesn't guarantee
-        #[cfg(feature = "embed_bpf")]
-        let ebpf_bytes: &[u8] = include_bytes_aligned!(concat!(env!("OUT_DIR"), "/sennet_ebpf.bin"));
-        
-        #[cfg(not(feature = "embed_bpf"))]
-        let ebpf_bytes: &[u8] = include_bytes_aligned!("../sennet-ebpf/target/bpfel-unknown-none/release/sennet-ebpf");
-        
-        // Debug: Log embedded binary info
-        tracing::info!("eBPF binary size: {} bytes", ebpf_bytes.len());
-        if ebpf_bytes.len() >= 4 {
-            tracing::info!(
-                "eBPF ELF magic: {:02x} {:02x} {:02x} {:02x} (expected: 7f 45 4c 46 = ELF)",
-                ebpf_bytes[0], ebpf_bytes[1], ebpf_bytes[2], ebpf_bytes[3]
-            );
-        }
-        
-        // Comprehensive ELF64 header inspection
-        if ebpf_bytes.len() >= 64 {
-            // ELF64 header fields (all offsets for 64-bit ELF)
-            let ei_class = ebpf_bytes[4];  // 1=32-bit, 2=64-bit
-            let ei_data = ebpf_bytes[5];   // 1=LE, 2=BE
-            let ei_version = ebpf_bytes[6];
-            let ei_osabi = ebpf_bytes[7];
-            
-            let e_type = u16::from_le_bytes([ebpf_bytes[16], ebpf_bytes[17]]);
-            let e_machine = u16::from_le_bytes([ebpf_bytes[18], ebpf_bytes[19]]);
-            let e_version = u32::from_le_bytes([ebpf_bytes[20], ebpf_bytes[21], ebpf_bytes[22], ebpf_bytes[23]]);
-            
-            // Key fields for alignment validation
-            let e_ehsize = u16::from_le_bytes([ebpf_bytes[52], ebpf_bytes[53]]);  // ELF header size
-            let e_phentsize = u16::from_le_bytes([ebpf_bytes[54], ebpf_bytes[55]]); // Program header entry size
-            let e_phnum = u16::from_le_bytes([ebpf_bytes[56], ebpf_bytes[57]]);     // Number of program headers
-            let e_shentsize = u16::from_le_bytes([ebpf_bytes[58], ebpf_bytes[59]]); // Section header entry size
-            let e_shnum = u16::from_le_bytes([ebpf_bytes[60], ebpf_bytes[61]]);     // Number of section headers
-            let e_shstrndx = u16::from_le_bytes([ebpf_bytes[62], ebpf_bytes[63]]);  // Section name string table index
-            
-            // Section header offset (8 bytes at offset 40)
-            let e_shoff = u64::from_le_bytes([
-                ebpf_bytes[40], ebpf_bytes[41], ebpf_bytes[42], ebpf_bytes[43],
-                ebpf_bytes[44], ebpf_bytes[45], ebpf_bytes[46], ebpf_bytes[47]
-            ]);
-            
-            tracing::info!("=== ELF64 Header Inspection ===");
-            tracing::info!("EI_CLASS: {} (expected 2 for 64-bit)", ei_class);
-            tracing::info!("EI_DATA: {} (expected 1 for little-endian)", ei_data);
-            tracing::info!("EI_VERSION: {}", ei_version);
-            tracing::info!("EI_OSABI: {}", ei_osabi);
-            tracing::info!("e_type: {} (1=REL, 2=EXEC, 3=DYN)", e_type);
-            tracing::info!("e_machine: {} (expected 247 for eBPF)", e_machine);
-            tracing::info!("e_version: {}", e_version);
-            tracing::info!("e_ehsize: {} (expected 64 for ELF64)", e_ehsize);
-            tracing::info!("e_phentsize: {}", e_phentsize);
-            tracing::info!("e_phnum: {}", e_phnum);
-            tracing::info!("e_shentsize: {} (expected 64 for ELF64)", e_shentsize);
-            tracing::info!("e_shnum: {}", e_shnum);
-            tracing::info!("e_shstrndx: {}", e_shstrndx);
-            tracing::info!("e_shoff: {} (section headers at byte offset)", e_shoff);
-            
-            // Validate critical alignment requirements
-            if e_ehsize != 64 {
-                tracing::error!("INVALID: ELF header size {} != 64", e_ehsize);
-            }
-            if e_shentsize != 64 && e_shentsize != 0 {
-                tracing::error!("INVALID: Section header entry size {} != 64", e_shentsize);
-            }
-            if e_shoff as usize > ebpf_bytes.len() {
-                tracing::error!("INVALID: Section header offset {} > file size {}", e_shoff, ebpf_bytes.len());
-            }
-            if e_shoff % 8 != 0 {
-                tracing::error!("INVALID: Section header offset {} not 8-byte aligned", e_shoff);
-            }
-            if e_machine != 247 {
-                tracing::error!("INVALID: e_machine {} is not eBPF (247)", e_machine);
-            }
+/// Format a 16-byte event address: dotted quad for IPv4, RFC 5952 for IPv6
+pub fn format_addr(addr: &[u8; 16]) -> String {
+    ip_addr(addr).to_string()
+}
+
+/// Layout hash of the shared types, compared with the one embedded in the
+/// eBPF object and stored in the pinned SCHEMA map
+pub use sennet_common::SCHEMA_HASH;
+
+/// Contents of the ELF section `name`, for the schema hash embedded in the
+/// eBPF object (64-bit little-endian ELF only)
+#[allow(dead_code)] // Used on Linux
+fn elf_section<'a>(elf: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let u16_at = |at: usize| Some(u16::from_le_bytes(elf.get(at..at + 2)?.try_into().ok()?) as usize);
+    let u32_at = |at: usize| Some(u32::from_le_bytes(elf.get(at..at + 4)?.try_into().ok()?) as usize);
+    let u64_at = |at: usize| Some(u64::from_le_bytes(elf.get(at..at + 8)?.try_into().ok()?) as usize);
+    if elf.get(..6)? != b"\x7fELF\x02\x01" {
+        return None;
+    }
+    let (shoff, shentsize, shnum, shstrndx) = (u64_at(40)?, u16_at(58)?, u16_at(60)?, u16_at(62)?);
+    let header = |index: usize| shoff.checked_add(index.checked_mul(shentsize)?);
+    let data = |at: usize| elf.get(u64_at(at + 24)?..u64_at(at + 24)?.checked_add(u64_at(at + 32)?)?);
+    let names = data(header(shstrndx)?)?;
+    (0..shnum).find_map(|index| {
+        let at = header(index)?;
+        let start = u32_at(at)?;
+        let end = start + names.get(start..)?.iter().position(|&b| b == 0)?;
+        if names[start..end] == *name.as_bytes() {
+            data(at)
+        } else {
+            None
         }
-        
-        // Check for BTF sections
-        let has_btf = ebpf_bytes.windows(4).any(|w| w == b".BTF");
-        tracing::info!("eBPF contains BTF sections: {}", has_btf);
-        
-        let mut bpf = match Bpf::load(ebpf_bytes) {
-            Ok(b) => b,
+    })
+}
+
+/// Schema hash recorded in the pinned SCHEMA map, if an agent pinned one
+#[cfg(target_os = "linux")]
+fn pinned_schema() -> Result<Option<u64>> {
+    use aya::maps::{Array, Map, MapData};
+
+    let pin = pin_dir().join("schema");
+    if !pin.exists() {
+        return Ok(None);
+    }
+    let schema: Array<_, u64> = Array::try_from(Map::Array(MapData::from_pin(&pin)?))?;
+    Ok(Some(schema.get(&0, 0)?))
+}
+
+/// Refuse pinned maps whose layouts differ from this build's
+#[cfg(target_os = "linux")]
+pub fn check_pinned_schema() -> Result<()> {
+    match pinned_schema()? {
+        Some(hash) if hash == SCHEMA_HASH => Ok(()),
+        Some(hash) => anyhow::bail!(
+            "Pinned maps use schema {:016x} but this sennet expects {:016x}; run the same version as the agent",
+            hash,
+            SCHEMA_HASH
+        ),
+        None => anyhow::bail!("Pinned maps carry no schema hash (the agent predates it); restart the agent"),
+    }
+}
+
+/// Remove the pins under `dir` and then `dir` itself; returns the number
+/// of pins removed (0 if `dir` doesn't exist)
+///
+/// The directory is kept if something was pinned into it meanwhile.
+pub fn remove_pins(dir: &Path) -> std::io::Result<usize> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut removed = 0;
+    for entry in entries {
+        std::fs::remove_file(entry?.path())?;
+        removed += 1;
+    }
+    let _ = std::fs::remove_dir(dir);
+    Ok(removed)
+}
+
+/// Read active flows from the pinned FLOWS map of the running agent
+#[cfg(target_os = "linux")]
+pub fn read_pinned_flows() -> Result<Vec<(FlowKey, FlowInfo)>> {
+    use aya::maps::{Map, MapData};
+
+    let pin = pin_dir().join("flows");
+    if !pin.exists() {
+        anyhow::bail!("Pinned flows map not found at {}", pin.display());
+    }
+    check_pinned_schema()?;
+    let map = Map::LruHashMap(MapData::from_pin(&pin)?);
+    let flows_map: LruHashMap<_, FlowKey, FlowInfo> = LruHashMap::try_from(map)?;
+    Ok(flows_map.iter().filter_map(|item| item.ok()).collect())
+}
+
+/// Server names the running agent saw in TLS ClientHellos, by outbound
+/// flow key
+#[cfg(target_os = "linux")]
+pub fn read_pinned_tls_sni() -> Result<Vec<(FlowKey, String)>> {
+    use aya::maps::{Map, MapData};
+
+    let pin = pin_dir().join("tls_sni");
+    if !pin.exists() {
+        anyhow::bail!("Pinned TLS SNI map not found at {} (agent predates TLS SNI parsing)", pin.display());
+    }
+    check_pinned_schema()?;
+    let map = Map::LruHashMap(MapData::from_pin(&pin)?);
+    let names: LruHashMap<_, FlowKey, TlsSni> = LruHashMap::try_from(map)?;
+    Ok(names.iter().filter_map(|item| item.ok()).map(|(key, sni)| (key, sni.server_name())).collect())
+}
+
+/// Write the running agent's pinned TRACE_RULE map; the default rule
+/// clears it
+#[cfg(target_os = "linux")]
+pub fn set_pinned_trace_rule(rule: &TraceRule) -> Result<()> {
+    use aya::maps::{Array, Map, MapData};
+
+    let pin = pin_dir().join("trace_rule");
+    if !pin.exists() {
+        anyhow::bail!("Pinned trace rule map not found at {} (agent predates kernel trace filters)", pin.display());
+    }
+    check_pinned_schema()?;
+    let mut array: Array<_, TraceRule> = Array::try_from(Map::Array(MapData::from_pin(&pin)?))?;
+    array.set(0, *rule, 0)?;
+    Ok(())
+}
+
+/// Write the running agent's pinned PAYLOAD_RULE map; the default rule
+/// clears it
+#[cfg(target_os = "linux")]
+pub fn set_pinned_payload_rule(rule: &PayloadRule) -> Result<()> {
+    use aya::maps::{Array, Map, MapData};
+
+    let pin = pin_dir().join("payload_rule");
+    if !pin.exists() {
+        anyhow::bail!("Pinned payload rule map not found at {} (agent predates payload capture)", pin.display());
+    }
+    check_pinned_schema()?;
+    let mut array: Array<_, PayloadRule> = Array::try_from(Map::Array(MapData::from_pin(&pin)?))?;
+    array.set(0, *rule, 0)?;
+    Ok(())
+}
+
+/// Entries of the running agent's pinned BLOCKLIST map with the packets
+/// each has dropped, and whether the ENFORCE switch is on
+#[cfg(target_os = "linux")]
+pub fn read_pinned_blocklist() -> Result<(Vec<(BlockKey, u64)>, bool)> {
+    use aya::maps::{Array, HashMap, Map, MapData};
+
+    let pin = pin_dir().join("blocklist");
+    if !pin.exists() {
+        anyhow::bail!("Pinned blocklist map not found at {} (agent predates enforcement)", pin.display());
+    }
+    check_pinned_schema()?;
+    let blocklist: HashMap<_, BlockKey, u64> = HashMap::try_from(Map::HashMap(MapData::from_pin(&pin)?))?;
+    let mut entries: Vec<_> = blocklist.iter().filter_map(|item| item.ok()).collect();
+    entries.sort();
+    let enforce: Array<_, u32> =
+        Array::try_from(Map::Array(MapData::from_pin(pin_dir().join("enforce"))?))?;
+    Ok((entries, enforce.get(&0, 0)? != 0))
+}
+
+/// Add (`block`) or remove an entry of the running agent's pinned BLOCKLIST
+/// map; adding an entry already present keeps its hit count
+#[cfg(target_os = "linux")]
+pub fn update_pinned_blocklist(key: &BlockKey, block: bool) -> Result<()> {
+    use aya::maps::{HashMap, Map, MapData};
+
+    let pin = pin_dir().join("blocklist");
+    if !pin.exists() {
+        anyhow::bail!("Pinned blocklist map not found at {} (agent predates enforcement)", pin.display());
+    }
+    check_pinned_schema()?;
+    let mut blocklist: HashMap<_, BlockKey, u64> = HashMap::try_from(Map::HashMap(MapData::from_pin(&pin)?))?;
+    if !block {
+        // Removing an absent entry is not an error: the goal is reached
+        let _ = blocklist.remove(key);
+    } else if blocklist.get(key, 0).is_err() {
+        blocklist.insert(key, 0, 0)?;
+    }
+    Ok(())
+}
+
+/// Address pairs from the pinned TOP_TALKERS map, busiest first
+#[cfg(target_os = "linux")]
+pub fn read_pinned_top_talkers() -> Result<Vec<TopTalker>> {
+    use aya::maps::{Map, MapData};
+
+    let pin = pin_dir().join("top_talkers");
+    if !pin.exists() {
+        anyhow::bail!("Pinned top talkers map not found at {}", pin.display());
+    }
+    check_pinned_schema()?;
+    let map = Map::LruHashMap(MapData::from_pin(&pin)?);
+    let talkers: LruHashMap<_, IpPair, TalkerCounters> = LruHashMap::try_from(map)?;
+    Ok(rank_top_talkers(talkers.iter().filter_map(|item| item.ok())))
+}
+
+/// RTT percentiles per remote address from the pinned RTT_HISTOGRAMS map
+#[cfg(target_os = "linux")]
+pub fn read_pinned_rtt() -> Result<Vec<RemoteRtt>> {
+    read_pinned_rtt_map("rtt_histograms")
+}
+
+/// ICMP echo round trips per pinged address from the pinned ICMP_RTT map
+#[cfg(target_os = "linux")]
+pub fn read_pinned_icmp_rtt() -> Result<Vec<RemoteRtt>> {
+    read_pinned_rtt_map("icmp_rtt")
+}
+
+#[cfg(target_os = "linux")]
+fn read_pinned_rtt_map(name: &str) -> Result<Vec<RemoteRtt>> {
+    use aya::maps::{Map, MapData, PerCpuHashMap};
+
+    let pin = pin_dir().join(name);
+    if !pin.exists() {
+        anyhow::bail!("Pinned RTT map not found at {}", pin.display());
+    }
+    check_pinned_schema()?;
+    let map = Map::PerCpuLruHashMap(MapData::from_pin(&pin)?);
+    let histograms: PerCpuHashMap<_, [u8; 16], RttHistogram> = PerCpuHashMap::try_from(map)?;
+    Ok(summarize_rtt(histograms.iter().filter_map(|item| item.ok()).map(|(remote, per_cpu)| (remote, per_cpu.to_vec()))))
+}
+
+/// Per-cgroup traffic from the pinned CGROUP_COUNTERS map, busiest first
+///
+/// Empty unless the programs were attached with [`EbpfManager::attach_cgroup`].
+#[cfg(target_os = "linux")]
+pub fn read_pinned_cgroup_counters() -> Result<Vec<CgroupTraffic>> {
+    use aya::maps::{Map, MapData, PerCpuHashMap};
+
+    let pin = pin_dir().join("cgroup_counters");
+    if !pin.exists() {
+        anyhow::bail!("Pinned cgroup counters map not found at {}", pin.display());
+    }
+    check_pinned_schema()?;
+    let map = Map::PerCpuLruHashMap(MapData::from_pin(&pin)?);
+    let counters: PerCpuHashMap<_, u64, CgroupCounters> = PerCpuHashMap::try_from(map)?;
+    let entries = counters.iter().filter_map(|item| item.ok()).map(|(id, per_cpu)| (id, per_cpu.to_vec()));
+    Ok(summarize_cgroups(entries, &cgroup_paths()))
+}
+
+/// TCP connection churn from the pinned CONN_CHURN map, summed over CPUs
+#[cfg(target_os = "linux")]
+pub fn read_pinned_conn_churn() -> Result<ConnChurn> {
+    use aya::maps::{Map, MapData};
+
+    let pin = pin_dir().join("conn_churn");
+    if !pin.exists() {
+        anyhow::bail!("Pinned connection churn map not found at {}", pin.display());
+    }
+    check_pinned_schema()?;
+    let churn: PerCpuArray<_, ConnChurn> = PerCpuArray::try_from(Map::PerCpuArray(MapData::from_pin(&pin)?))?;
+    let mut total = ConnChurn::default();
+    churn.get(&0, 0)?.iter().for_each(|cpu_val| total.add(cpu_val));
+    Ok(total)
+}
+
+/// Interface counters from the pinned COUNTERS and TCP_FLAG_COUNTERS maps,
+/// summed over CPUs
+///
+/// Index 0 holds ingress (rx and drops), index 1 egress (tx).
+#[cfg(target_os = "linux")]
+pub fn read_pinned_counters() -> Result<PacketCounters> {
+    use aya::maps::{Map, MapData};
+
+    let pin = pin_dir().join("counters");
+    if !pin.exists() {
+        anyhow::bail!("Pinned map not found at {}", pin.display());
+    }
+    check_pinned_schema()?;
+    let map = Map::PerCpuArray(MapData::from_pin(&pin)?);
+    let counters: PerCpuArray<_, KernelCounters> = PerCpuArray::try_from(map)?;
+    // Missing when the agent predates the flag counters
+    let tcp_flags: Option<PerCpuArray<_, TcpFlagCounters>> = MapData::from_pin(pin_dir().join("tcp_flag_counters"))
+        .ok()
+        .and_then(|data| PerCpuArray::try_from(Map::PerCpuArray(data)).ok());
+
+    let mut total = PacketCounters::default();
+    for direction in 0..2 {
+        if let Ok(values) = counters.get(&direction, 0) {
+            values.iter().for_each(|cpu_val| total.add_kernel(direction, cpu_val));
+        }
+        if let Some(Ok(values)) = tcp_flags.as_ref().map(|map| map.get(&direction, 0)) {
+            values.iter().for_each(|cpu_val| total.add_tcp_flags(direction, cpu_val));
+        }
+    }
+    Ok(total)
+}
+
+/// Counters per interface from the pinned IFACE_COUNTERS map, by ifindex
+#[cfg(target_os = "linux")]
+pub fn read_pinned_interface_counters() -> Result<BTreeMap<u32, PacketCounters>> {
+    use aya::maps::{Map, MapData, PerCpuHashMap};
+
+    let pin = pin_dir().join("iface_counters");
+    if !pin.exists() {
+        anyhow::bail!("Pinned map not found at {} (the agent predates per-interface counters)", pin.display());
+    }
+    check_pinned_schema()?;
+    let map: PerCpuHashMap<_, u32, KernelCounters> = PerCpuHashMap::try_from(Map::PerCpuHashMap(MapData::from_pin(&pin)?))?;
+    Ok(fold_interface_counters(map.iter().filter_map(|item| item.ok()).map(|(ifindex, per_cpu)| (ifindex, per_cpu.to_vec()))))
+}
+
+/// Sum each interface's per-CPU values; both directions share an entry
+#[allow(dead_code)] // Used on Linux
+fn fold_interface_counters(entries: impl Iterator<Item = (u32, Vec<KernelCounters>)>) -> BTreeMap<u32, PacketCounters> {
+    entries
+        .map(|(ifindex, per_cpu)| {
+            let mut total = PacketCounters::default();
+            for cpu_val in &per_cpu {
+                total.add_kernel(0, cpu_val);
+                total.add_kernel(1, cpu_val);
+            }
+            (ifindex, total)
+        })
+        .collect()
+}
+
+/// Delete entries of the pinned FLOWS map, returning the ones deleted
+///
+/// Each entry is only deleted if it still holds the snapshot given for it,
+/// so a flow that moved since it was read keeps its counters (and its
+/// process, which the kernel only records when the flow is created).
+#[cfg(target_os = "linux")]
+pub fn remove_pinned_flows(snapshots: &[(FlowKey, FlowInfo)]) -> Result<Vec<(FlowKey, FlowInfo)>> {
+    use aya::maps::{Map, MapData};
+
+    let pin = pin_dir().join("flows");
+    if !pin.exists() {
+        anyhow::bail!("Pinned flows map not found at {}", pin.display());
+    }
+    check_pinned_schema()?;
+    let map = Map::LruHashMap(MapData::from_pin(&pin)?);
+    let mut flows_map: LruHashMap<_, FlowKey, FlowInfo> = LruHashMap::try_from(map)?;
+    let mut removed = Vec::new();
+    for (key, snapshot) in snapshots {
+        // Already gone if the LRU evicted it meanwhile
+        if flows_map.get(key, 0).is_ok_and(|current| current == *snapshot) && flows_map.remove(key).is_ok() {
+            removed.push((*key, *snapshot));
+        }
+    }
+    Ok(removed)
+}
+
+/// Open a pinned RingBuf map of the running agent by pin name
+#[cfg(target_os = "linux")]
+pub fn open_pinned_ringbuf(name: &str) -> Result<aya::maps::RingBuf<aya::maps::MapData>> {
+    use aya::maps::{Map, MapData};
+
+    let pin = pin_dir().join(name);
+    if !pin.exists() {
+        anyhow::bail!("Pinned map not found at {}", pin.display());
+    }
+    check_pinned_schema()?;
+    let map = Map::RingBuf(MapData::from_pin(&pin)?);
+    Ok(map.try_into()?)
+}
+
+/// The running agent's pinned DROP_STACKS map, for resolving
+/// `DropEvent::stack_id`
+#[cfg(target_os = "linux")]
+pub fn open_pinned_drop_stacks() -> Result<aya::maps::StackTraceMap<aya::maps::MapData>> {
+    use aya::maps::{Map, MapData};
+
+    let pin = pin_dir().join("drop_stacks");
+    if !pin.exists() {
+        anyhow::bail!("Pinned drop stack map not found at {}", pin.display());
+    }
+    let map = Map::StackTraceMap(MapData::from_pin(&pin)?);
+    Ok(map.try_into()?)
+}
+
+/// Number of entries in the pinned FLOWS map, without copying them
+#[cfg(target_os = "linux")]
+pub fn count_pinned_flows() -> Result<u64> {
+    use aya::maps::{Map, MapData};
+
+    let pin = pin_dir().join("flows");
+    if !pin.exists() {
+        anyhow::bail!("Pinned flows map not found at {}", pin.display());
+    }
+    check_pinned_schema()?;
+    let map = Map::LruHashMap(MapData::from_pin(&pin)?);
+    let flows_map: LruHashMap<_, FlowKey, FlowInfo> = LruHashMap::try_from(map)?;
+    Ok(flows_map.keys().filter(|key| key.is_ok()).count() as u64)
+}
+
+/// Failed ring buffer reservations per event kind, summed over CPUs
+///
+/// Indexed like `RingKind::ALL`. Fails if the loaded eBPF object predates
+/// the RESERVE_FAILURES map.
+#[cfg(target_os = "linux")]
+pub fn read_pinned_reserve_failures() -> Result<[u64; RingKind::ALL.len()]> {
+    use aya::maps::{Map, MapData};
+
+    let pin = pin_dir().join("reserve_failures");
+    if !pin.exists() {
+        anyhow::bail!("Pinned map not found at {}", pin.display());
+    }
+    check_pinned_schema()?;
+    let map = Map::PerCpuArray(MapData::from_pin(&pin)?);
+    let failures: PerCpuArray<_, u64> = PerCpuArray::try_from(map)?;
+    let mut totals = [0u64; RingKind::ALL.len()];
+    for (index, total) in totals.iter_mut().enumerate() {
+        if let Ok(values) = failures.get(&(index as u32), 0) {
+            *total = values.iter().sum();
+        }
+    }
+    Ok(totals)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_pinned_flows() -> Result<Vec<(FlowKey, FlowInfo)>> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_pinned_tls_sni() -> Result<Vec<(FlowKey, String)>> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_pinned_blocklist() -> Result<(Vec<(BlockKey, u64)>, bool)> {
+    anyhow::bail!("eBPF not supported on this platform")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn update_pinned_blocklist(_key: &BlockKey, _block: bool) -> Result<()> {
+    anyhow::bail!("eBPF not supported on this platform")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_pinned_top_talkers() -> Result<Vec<TopTalker>> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_pinned_rtt() -> Result<Vec<RemoteRtt>> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_pinned_icmp_rtt() -> Result<Vec<RemoteRtt>> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_pinned_cgroup_counters() -> Result<Vec<CgroupTraffic>> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_pinned_conn_churn() -> Result<ConnChurn> {
+    anyhow::bail!("eBPF not supported on this platform")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn count_pinned_flows() -> Result<u64> {
+    anyhow::bail!("eBPF not supported on this platform")
+}
+
+#[cfg(not(target_os = "linux"))]
+#[cfg_attr(windows, allow(dead_code))] // Read by the control socket on Unix
+pub fn read_pinned_counters() -> Result<PacketCounters> {
+    anyhow::bail!("eBPF not supported on this platform")
+}
+
+#[cfg(not(target_os = "linux"))]
+#[cfg_attr(windows, allow(dead_code))] // Read by the control socket on Unix
+pub fn read_pinned_interface_counters() -> Result<BTreeMap<u32, PacketCounters>> {
+    anyhow::bail!("eBPF not supported on this platform")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_pinned_reserve_failures() -> Result<[u64; RingKind::ALL.len()]> {
+    anyhow::bail!("eBPF not supported on this platform")
+}
+
+/// Events lost in the kernel between polls, per ring buffer
+///
+/// For commands that read ring buffers themselves (`trace`, the TUI), so
+/// they can mark where their output has gaps.
+pub struct LossTracker {
+    last: Option<[u64; RingKind::ALL.len()]>,
+}
+
+impl Default for LossTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LossTracker {
+    pub fn new() -> Self {
+        Self { last: read_pinned_reserve_failures().ok() }
+    }
+
+    /// Events lost since the previous poll, indexed like `RingKind::ALL`
+    ///
+    /// All zero if the loaded eBPF object doesn't count reservation failures.
+    pub fn poll(&mut self) -> [u64; RingKind::ALL.len()] {
+        self.advance(read_pinned_reserve_failures().ok())
+    }
+
+    fn advance(&mut self, now: Option<[u64; RingKind::ALL.len()]>) -> [u64; RingKind::ALL.len()] {
+        let mut lost = [0; RingKind::ALL.len()];
+        if let (Some(prev), Some(now)) = (self.last, now) {
+            for (lost, (prev, now)) in lost.iter_mut().zip(prev.iter().zip(now)) {
+                *lost = now.saturating_sub(*prev);
+            }
+        }
+        if now.is_some() {
+            self.last = now;
+        }
+        lost
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn remove_pinned_flows(_snapshots: &[(FlowKey, FlowInfo)]) -> Result<Vec<(FlowKey, FlowInfo)>> {
+    Ok(Vec::new())
+}
+
+/// Kernel map sizes applied at load time
+///
+/// Defaults match the sizes compiled into the eBPF object. Ring buffer
+/// sizes must be a power-of-two multiple of the page size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapSizes {
+    pub flow_entries: u32,
+    pub events_ring_bytes: u32,
+    pub drop_ring_bytes: u32,
+    pub nf_ring_bytes: u32,
+    pub flow_ring_bytes: u32,
+    pub rst_ring_bytes: u32,
+    pub retransmit_ring_bytes: u32,
+    pub dns_ring_bytes: u32,
+    pub conntrack_ring_bytes: u32,
+    pub http_ring_bytes: u32,
+    pub payload_ring_bytes: u32,
+}
+
+impl Default for MapSizes {
+    fn default() -> Self {
+        Self {
+            flow_entries: 65536,
+            events_ring_bytes: 256 * 1024,
+            drop_ring_bytes: 64 * 1024,
+            nf_ring_bytes: 32 * 1024,
+            flow_ring_bytes: 64 * 1024,
+            rst_ring_bytes: 64 * 1024,
+            retransmit_ring_bytes: 32 * 1024,
+            dns_ring_bytes: 64 * 1024,
+            conntrack_ring_bytes: 64 * 1024,
+            http_ring_bytes: 64 * 1024,
+            payload_ring_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// Hook counting ingress packets on the monitored interface
+///
+/// XDP runs in the driver before an skb is allocated, so it costs much less
+/// per packet on high-rate hosts. It only sees ingress: egress is counted by
+/// the TC classifier in both modes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachMode {
+    /// TC classifier on ingress
+    #[default]
+    Tc,
+    /// XDP in native driver mode, or generic mode where the driver lacks support
+    Xdp,
+}
+
+impl std::str::FromStr for AttachMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tc" => Ok(AttachMode::Tc),
+            "xdp" => Ok(AttachMode::Xdp),
+            other => anyhow::bail!("Unknown attach mode '{}' (expected tc or xdp)", other),
+        }
+    }
+}
+
+/// Tracing programs that can be detached and attached again while the
+/// agent runs, so the costlier ones only run while debugging
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Probe {
+    /// kfree_skb tracepoint
+    Drops,
+    /// nf_hook_slow fexit
+    Netfilter,
+    /// inet_sock_set_state tracepoint, or the tcp_connect/tcp_close
+    /// kprobes, plus inet_csk_accept and the flow byte kprobes
+    Flows,
+}
+
+impl Probe {
+    pub const ALL: [Probe; 3] = [Probe::Drops, Probe::Netfilter, Probe::Flows];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Probe::Drops => "drops",
+            Probe::Netfilter => "netfilter",
+            Probe::Flows => "flows",
+        }
+    }
+}
+
+impl std::str::FromStr for Probe {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Probe::ALL.into_iter().find(|probe| probe.name() == s).ok_or_else(|| {
+            let names: Vec<_> = Probe::ALL.iter().map(Probe::name).collect();
+            anyhow::anyhow!("Unknown probe '{}' (expected {})", s, names.join(", "))
+        })
+    }
+}
+
+/// Whether a [`Probe`] is loaded and attached
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeState {
+    pub probe: Probe,
+    /// Loaded at startup; a probe that failed to load can't be attached later
+    pub available: bool,
+    pub attached: bool,
+}
+
+/// kprobes counting the bytes moved on tracked flows
+#[allow(dead_code)] // Used on Linux
+const FLOW_BYTE_KPROBES: [&str; 2] = ["tcp_sendmsg", "tcp_cleanup_rbuf"];
+
+#[cfg(target_os = "linux")]
+use {
+    aya::{
+        include_bytes_aligned,
+        programs::{tc, CgroupSkb, CgroupSkbAttachType, CgroupSock, FExit, SchedClassifier, TcAttachType, TracePoint, KProbe, Xdp, XdpFlags},
+        programs::{fexit::FExitLinkId, kprobe::KProbeLinkId, trace_point::TracePointLinkId},
+        maps::{PerCpuArray, HashMap as LruHashMap},
+        Bpf, BpfLoader, Btf,
+    },
+};
+
+/// Links of the attached tracing programs (see [`Probe`])
+#[cfg(target_os = "linux")]
+#[derive(Default)]
+struct ProbeLinks {
+    kfree_skb: Option<TracePointLinkId>,
+    nf_hook_slow: Option<FExitLinkId>,
+    sock_state: Option<TracePointLinkId>,
+    /// Program, symbol and link of each flow kprobe
+    flow_kprobes: Vec<(&'static str, &'static str, KProbeLinkId)>,
+}
+
+#[cfg(target_os = "linux")]
+fn find_program<'a>(bpf: &'a mut Bpf, name: &str) -> Result<&'a mut aya::programs::Program> {
+    bpf.program_mut(name).ok_or_else(|| anyhow::anyhow!("{} program not found", name))
+}
+
+/// eBPF program manager
+/// 
+/// On Linux: Loads and attaches TC classifiers and tracepoints
+/// On other platforms: Provides a mock implementation for development
+#[allow(dead_code)] // Used only on Linux; mock on other platforms
+pub struct EbpfManager {
+    /// Interfaces the classifiers are attached to
+    interfaces: Vec<String>,
+    #[cfg(target_os = "linux")]
+    bpf: Bpf,
+    /// Interfaces loading added the clsact qdisc to, rather than finding one
+    #[cfg(target_os = "linux")]
+    added_clsact: Vec<String>,
+    #[cfg(target_os = "linux")]
+    probe_links: ProbeLinks,
+    /// Flow programs attached at load, attached again by set_probe
+    #[cfg(target_os = "linux")]
+    flow_programs: Vec<(&'static str, &'static str)>,
+    /// Probes attached at load, which set_probe can switch
+    #[cfg(target_os = "linux")]
+    available_probes: Vec<Probe>,
+    /// Hook counting ingress packets
+    pub attach_mode: AttachMode,
+    /// Whether drop tracing is active (kfree_skb tracepoint attached)
+    pub drop_tracing_enabled: bool,
+    /// Whether netfilter tracing is active (nf_hook_slow fexit attached)
+    pub nf_tracing_enabled: bool,
+    /// Whether flow tracking is active (inet_sock_set_state tracepoint or tcp_connect kprobe attached) (Phase 8)
+    pub flow_tracing_enabled: bool,
+    /// Whether TCP state changes are traced (inet_sock_set_state tracepoint
+    /// attached), replacing the tcp_connect/tcp_close kprobes and counting
+    /// connection churn
+    pub sock_state_tracing_enabled: bool,
+    /// Whether retransmission tracing is active (tcp_retransmit_skb tracepoint attached)
+    pub retransmit_tracing_enabled: bool,
+    /// Whether RTT measurement is active (tcp_probe tracepoint attached)
+    pub rtt_tracing_enabled: bool,
+    /// Whether conntrack tracing is active (__nf_conntrack_confirm/nf_ct_delete kprobes attached)
+    pub conntrack_tracing_enabled: bool,
+}
+
+/// Attach the ingress (`mode`) and egress classifiers, already loaded, to
+/// `interface`
+///
+/// Returns whether the clsact qdisc had to be added.
+#[cfg(target_os = "linux")]
+fn attach_classifiers(bpf: &mut Bpf, interface: &str, mode: AttachMode) -> Result<bool> {
+    tracing::info!("Attaching TC classifiers to interface {}", interface);
+
+    // Add clsact qdisc to the interface (fails if it already exists)
+    let added_clsact = tc::qdisc_add_clsact(interface).is_ok();
+
+    match mode {
+        AttachMode::Tc => {
+            let ingress: &mut SchedClassifier = bpf.program_mut("tc_ingress").unwrap().try_into()?;
+            ingress.attach(interface, TcAttachType::Ingress)?;
+        }
+        AttachMode::Xdp => {
+            let ingress: &mut Xdp = bpf.program_mut("xdp_ingress").unwrap().try_into()?;
+            // Native mode needs driver support; generic mode works everywhere
+            if let Err(e) = ingress.attach(interface, XdpFlags::DRV_MODE) {
+                tracing::warn!("Native XDP unavailable on {} ({}), using generic XDP", interface, e);
+                ingress.attach(interface, XdpFlags::SKB_MODE)?;
+            }
+            tracing::info!("Attached XDP program for ingress on {}", interface);
+        }
+    }
+
+    let egress: &mut SchedClassifier = bpf.program_mut("tc_egress").unwrap().try_into()?;
+    egress.attach(interface, TcAttachType::Egress)?;
+    Ok(added_clsact)
+}
+
+/// Write the field offsets `layout` derives from the `system`/`event`
+/// tracepoint's format file into the single-entry array `map_name`
+///
+/// Returns the layout written; a missing map, format file or field is
+/// logged and leaves the map zeroed.
+#[cfg(target_os = "linux")]
+fn write_tracepoint_layout<T: aya::Pod>(
+    bpf: &mut Bpf,
+    map_name: &str,
+    system: &str,
+    event: &str,
+    layout: fn(&crate::tracefmt::TracepointFormat) -> Option<T>,
+) -> Result<Option<T>> {
+    let Some(map) = bpf.map_mut(map_name) else {
+        return Ok(None);
+    };
+    let format = match crate::tracefmt::TracepointFormat::read(system, event) {
+        Ok(format) => format,
+        Err(e) => {
+            tracing::warn!("{}; {} offsets not set", e, event);
+            return Ok(None);
+        }
+    };
+    let Some(layout) = layout(&format) else {
+        tracing::warn!("Unexpected {} record format; offsets not set", event);
+        return Ok(None);
+    };
+    aya::maps::Array::<_, T>::try_from(map)?.set(0, layout, 0)?;
+    Ok(Some(layout))
+}
+
+/// Write the layout `layout` builds from the kernel's BTF into the
+/// single-entry array `map_name`
+///
+/// Without BTF, or with a field missing, the map stays zeroed and the
+/// programs use their built-in offsets.
+#[cfg(target_os = "linux")]
+fn write_btf_layout<T: aya::Pod>(
+    bpf: &mut Bpf,
+    map_name: &str,
+    types: Option<&crate::btf::KernelTypes>,
+    layout: fn(&crate::btf::KernelTypes) -> Option<T>,
+) -> Result<Option<T>> {
+    let (Some(map), Some(types)) = (bpf.map_mut(map_name), types) else {
+        return Ok(None);
+    };
+    let Some(layout) = layout(types) else {
+        tracing::warn!("Kernel BTF lacks a field {} needs; using built-in offsets", map_name);
+        return Ok(None);
+    };
+    aya::maps::Array::<_, T>::try_from(map)?.set(0, layout, 0)?;
+    Ok(Some(layout))
+}
+
+#[allow(dead_code)] // Methods used on Linux; mock impl on other platforms
+impl EbpfManager {
+    /// Load and attach eBPF programs to the specified interface
+    pub fn load_and_attach(interface: &str) -> Result<Self> {
+        Self::load_and_attach_with(interface, &MapSizes::default(), AttachMode::default())
+    }
+
+    /// Load and attach eBPF programs, sizing maps from `sizes` and counting
+    /// ingress with the `mode` hook
+    pub fn load_and_attach_with(interface: &str, sizes: &MapSizes, mode: AttachMode) -> Result<Self> {
+        Self::load_and_attach_all(&[interface.to_string()], sizes, mode)
+    }
+
+    /// Load the eBPF programs once and attach the classifiers to each of
+    /// `interfaces`
+    ///
+    /// With several interfaces, one that fails to attach is skipped with a
+    /// warning; loading fails only if none could be attached.
+    #[cfg(target_os = "linux")]
+    pub fn load_and_attach_all(interfaces: &[String], sizes: &MapSizes, mode: AttachMode) -> Result<Self> {
+        tracing::info!("Loading eBPF programs...");
+
+        // Programs are picked by what the kernel supports
+        let caps = crate::btf::check_ebpf_capabilities();
+        caps.log_features();
+        if !caps.ringbuf {
+            // The classifiers write to ring buffers too, so there is no
+            // subset of the object that loads without them
+            anyhow::bail!("This kernel has no BPF ring buffers (5.8+), which the eBPF object's event maps need");
+        }
+        
+        // Load the eBPF binary with proper alignment for ELF parsing
+        // NOTE: Must use include_bytes_aligned! instead of include_bytes! because
+        // the ELF parser requires 8-byte aligned memory, which include_bytes! doesn't guarantee
+        #[cfg(feature = "embed_bpf")]
+        let ebpf_bytes: &[u8] = include_bytes_aligned!(concat!(env!("OUT_DIR"), "/sennet_ebpf.bin"));
+        
+        #[cfg(not(feature = "embed_bpf"))]
+        let ebpf_bytes: &[u8] = include_bytes_aligned!("../sennet-ebpf/target/bpfel-unknown-none/release/sennet-ebpf");
+        
+        // Debug: Log embedded binary info
+        tracing::info!("eBPF binary size: {} bytes", ebpf_bytes.len());
+        if ebpf_bytes.len() >= 4 {
+            tracing::info!(
+                "eBPF ELF magic: {:02x} {:02x} {:02x} {:02x} (expected: 7f 45 4c 46 = ELF)",
+                ebpf_bytes[0], ebpf_bytes[1], ebpf_bytes[2], ebpf_bytes[3]
+            );
+        }
+        
+        // Comprehensive ELF64 header inspection
+        if ebpf_bytes.len() >= 64 {
+            // ELF64 header fields (all offsets for 64-bit ELF)
+            let ei_class = ebpf_bytes[4];  // 1=32-bit, 2=64-bit
+            let ei_data = ebpf_bytes[5];   // 1=LE, 2=BE
+            let ei_version = ebpf_bytes[6];
+            let ei_osabi = ebpf_bytes[7];
+            
+            let e_type = u16::from_le_bytes([ebpf_bytes[16], ebpf_bytes[17]]);
+            let e_machine = u16::from_le_bytes([ebpf_bytes[18], ebpf_bytes[19]]);
+            let e_version = u32::from_le_bytes([ebpf_bytes[20], ebpf_bytes[21], ebpf_bytes[22], ebpf_bytes[23]]);
+            
+            // Key fields for alignment validation
+            let e_ehsize = u16::from_le_bytes([ebpf_bytes[52], ebpf_bytes[53]]);  // ELF header size
+            let e_phentsize = u16::from_le_bytes([ebpf_bytes[54], ebpf_bytes[55]]); // Program header entry size
+            let e_phnum = u16::from_le_bytes([ebpf_bytes[56], ebpf_bytes[57]]);     // Number of program headers
+            let e_shentsize = u16::from_le_bytes([ebpf_bytes[58], ebpf_bytes[59]]); // Section header entry size
+            let e_shnum = u16::from_le_bytes([ebpf_bytes[60], ebpf_bytes[61]]);     // Number of section headers
+            let e_shstrndx = u16::from_le_bytes([ebpf_bytes[62], ebpf_bytes[63]]);  // Section name string table index
+            
+            // Section header offset (8 bytes at offset 40)
+            let e_shoff = u64::from_le_bytes([
+                ebpf_bytes[40], ebpf_bytes[41], ebpf_bytes[42], ebpf_bytes[43],
+                ebpf_bytes[44], ebpf_bytes[45], ebpf_bytes[46], ebpf_bytes[47]
+            ]);
+            
+            tracing::info!("=== ELF64 Header Inspection ===");
+            tracing::info!("EI_CLASS: {} (expected 2 for 64-bit)", ei_class);
+            tracing::info!("EI_DATA: {} (expected 1 for little-endian)", ei_data);
+            tracing::info!("EI_VERSION: {}", ei_version);
+            tracing::info!("EI_OSABI: {}", ei_osabi);
+            tracing::info!("e_type: {} (1=REL, 2=EXEC, 3=DYN)", e_type);
+            tracing::info!("e_machine: {} (expected 247 for eBPF)", e_machine);
+            tracing::info!("e_version: {}", e_version);
+            tracing::info!("e_ehsize: {} (expected 64 for ELF64)", e_ehsize);
+            tracing::info!("e_phentsize: {}", e_phentsize);
+            tracing::info!("e_phnum: {}", e_phnum);
+            tracing::info!("e_shentsize: {} (expected 64 for ELF64)", e_shentsize);
+            tracing::info!("e_shnum: {}", e_shnum);
+            tracing::info!("e_shstrndx: {}", e_shstrndx);
+            tracing::info!("e_shoff: {} (section headers at byte offset)", e_shoff);
+            
+            // Validate critical alignment requirements
+            if e_ehsize != 64 {
+                tracing::error!("INVALID: ELF header size {} != 64", e_ehsize);
+            }
+            if e_shentsize != 64 && e_shentsize != 0 {
+                tracing::error!("INVALID: Section header entry size {} != 64", e_shentsize);
+            }
+            if e_shoff as usize > ebpf_bytes.len() {
+                tracing::error!("INVALID: Section header offset {} > file size {}", e_shoff, ebpf_bytes.len());
+            }
+            if e_shoff % 8 != 0 {
+                tracing::error!("INVALID: Section header offset {} not 8-byte aligned", e_shoff);
+            }
+            if e_machine != 247 {
+                tracing::error!("INVALID: e_machine {} is not eBPF (247)", e_machine);
+            }
+        }
+        
+        // Check for BTF sections
+        let has_btf = ebpf_bytes.windows(4).any(|w| w == b".BTF");
+        tracing::info!("eBPF contains BTF sections: {}", has_btf);
+
+        // An object built against other layouts would be misread silently
+        let object_schema = elf_section(ebpf_bytes, "sennet_schema")
+            .and_then(|bytes| Some(u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?)));
+        match object_schema {
+            Some(hash) if hash == SCHEMA_HASH => tracing::info!("eBPF schema: {:016x}", hash),
+            Some(hash) => anyhow::bail!(
+                "eBPF object schema {:016x} does not match this agent ({:016x}); rebuild sennet-ebpf from the same sources",
+                hash,
+                SCHEMA_HASH
+            ),
+            None => anyhow::bail!("eBPF object carries no schema hash; rebuild sennet-ebpf from the same sources"),
+        }
+        
+        // Maps missing from an older object are ignored by the loader
+        let mut bpf = match BpfLoader::new()
+            .set_max_entries("FLOWS", sizes.flow_entries)
+            .set_max_entries("EVENTS", sizes.events_ring_bytes)
+            .set_max_entries("DROP_EVENTS", sizes.drop_ring_bytes)
+            .set_max_entries("NF_EVENTS", sizes.nf_ring_bytes)
+            .set_max_entries("FLOW_EVENTS", sizes.flow_ring_bytes)
+            .set_max_entries("RST_EVENTS", sizes.rst_ring_bytes)
+            .set_max_entries("RETRANSMIT_EVENTS", sizes.retransmit_ring_bytes)
+            .set_max_entries("DNS_EVENTS", sizes.dns_ring_bytes)
+            .set_max_entries("CT_EVENTS", sizes.conntrack_ring_bytes)
+            .set_max_entries("HTTP_EVENTS", sizes.http_ring_bytes)
+            .set_max_entries("PAYLOAD_EVENTS", sizes.payload_ring_bytes)
+            .load(ebpf_bytes)
+        {
+            Ok(b) => b,
             Err(e) => {
                 // Log detailed error chain
                 tracing::error!("eBPF load failed: {}", e);
@@ -368,49 +1684,118 @@ impl EbpfManager {
         };
         
         // Pin path for maps
-        let pin_path = Path::new("/sys/fs/bpf/sennet");
+        let pin_path = pin_dir();
         if !pin_path.exists() {
             std::fs::create_dir_all(pin_path)?;
         }
 
+        // Pins left by an agent of another schema would be reused by readers
+        // and block ours; replace them
+        if !matches!(pinned_schema(), Ok(Some(hash)) if hash == SCHEMA_HASH) {
+            let stale: Vec<_> = std::fs::read_dir(pin_path)?.filter_map(|entry| entry.ok()).collect();
+            if !stale.is_empty() {
+                tracing::warn!("Replacing {} maps pinned by an agent with a different schema", stale.len());
+                for entry in stale {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+        if let Some(map) = bpf.map_mut("SCHEMA") {
+            aya::maps::Array::<_, u64>::try_from(&mut *map)?.set(0, SCHEMA_HASH, 0)?;
+            let _ = map.pin(pin_path.join("schema"));
+        }
+
         // Pin COUNTERS map
-        tracing::info!("Pinning maps to /sys/fs/bpf/sennet...");
+        tracing::info!("Pinning maps to {}...", pin_path.display());
         if let Some(map) = bpf.map_mut("COUNTERS") {
             let _ = map.pin(pin_path.join("counters")); // Ignore if already pinned
         }
+        if let Some(map) = bpf.map_mut("TCP_FLAG_COUNTERS") {
+            let _ = map.pin(pin_path.join("tcp_flag_counters"));
+        }
+        if let Some(map) = bpf.map_mut("IFACE_COUNTERS") {
+            let _ = map.pin(pin_path.join("iface_counters"));
+        }
         
         // Pin DROP_EVENTS map (Phase 6.1)
         if let Some(map) = bpf.map_mut("DROP_EVENTS") {
             let _ = map.pin(pin_path.join("drop_events")); // Ignore if already pinned
         }
+        if let Some(map) = bpf.map_mut("DROP_STACKS") {
+            let _ = map.pin(pin_path.join("drop_stacks"));
+        }
 
-        // Attach TC Programs
-        tracing::info!("Attaching TC classifiers to interface {}", interface);
-        
-        // Add clsact qdisc to the interface (ignore error if it already exists)
-        let _ = tc::qdisc_add_clsact(interface);
-        
-        let ingress: &mut SchedClassifier = bpf.program_mut("tc_ingress").unwrap().try_into()?;
-        ingress.load()?;
-        ingress.attach(interface, TcAttachType::Ingress)?;
-
+        // Load the TC/XDP programs once; attach_classifiers attaches them
+        match mode {
+            AttachMode::Tc => {
+                let ingress: &mut SchedClassifier = bpf.program_mut("tc_ingress").unwrap().try_into()?;
+                ingress.load()?;
+            }
+            AttachMode::Xdp => {
+                let ingress: &mut Xdp = bpf
+                    .program_mut("xdp_ingress")
+                    .ok_or_else(|| anyhow::anyhow!("eBPF object has no xdp_ingress program; rebuild sennet-ebpf"))?
+                    .try_into()?;
+                ingress.load()?;
+            }
+        }
         let egress: &mut SchedClassifier = bpf.program_mut("tc_egress").unwrap().try_into()?;
         egress.load()?;
-        egress.attach(interface, TcAttachType::Egress)?;
+        let mut attached = Vec::new();
+        let mut added_clsact = Vec::new();
+        for interface in interfaces {
+            match attach_classifiers(&mut bpf, interface, mode) {
+                Ok(added) => {
+                    if added {
+                        added_clsact.push(interface.clone());
+                    }
+                    attached.push(interface.clone());
+                }
+                Err(e) if interfaces.len() == 1 => return Err(e),
+                Err(e) => tracing::warn!("Skipping interface {}: {:#}", interface, e),
+            }
+        }
+        if attached.is_empty() {
+            anyhow::bail!("Could not attach to any of {}", interfaces.join(", "));
+        }
+
+        // Links of the tracing programs, kept so they can be detached and
+        // attached again at runtime (set_probe)
+        let mut probe_links = ProbeLinks::default();
 
         // Try to attach kfree_skb tracepoint (Phase 6.1)
         // This may fail on older kernels or if tracepoint doesn't exist
         let mut drop_tracing_enabled = false;
+        // Record layouts vary by kernel; without the format file kfree_skb
+        // falls back to its built-in offsets
+        let layout = write_tracepoint_layout(&mut bpf, "KFREE_SKB_LAYOUT", "skb", "kfree_skb", crate::tracefmt::kfree_skb_layout)?;
+        if layout.is_some_and(|layout| layout.reason == 0) {
+            tracing::warn!("kfree_skb records no drop reason on this kernel (needs 5.17+)");
+        }
+        // Kernel struct offsets, for the programs that read sk_buffs
+        let kernel_types = match crate::btf::KernelTypes::read() {
+            Ok(types) => Some(types),
+            Err(e) => {
+                tracing::warn!("{:#}; kernel struct offsets not set", e);
+                None
+            }
+        };
+        write_btf_layout(&mut bpf, "SKB_LAYOUT", kernel_types.as_ref(), crate::btf::skb_layout)?;
+        write_btf_layout(&mut bpf, "NF_CONN_LAYOUT", kernel_types.as_ref(), crate::btf::nf_conn_layout)?;
         if let Some(prog) = bpf.program_mut("kfree_skb") {
             match prog.try_into() as Result<&mut TracePoint, _> {
                 Ok(tp) => {
                     if let Err(e) = tp.load() {
                         tracing::warn!("Failed to load kfree_skb tracepoint: {}", e);
-                    } else if let Err(e) = tp.attach("skb", "kfree_skb") {
-                        tracing::warn!("Failed to attach kfree_skb tracepoint: {}", e);
                     } else {
-                        tracing::info!("Attached kfree_skb tracepoint for drop reason tracing");
-                        drop_tracing_enabled = true;
+                        match tp.attach("skb", "kfree_skb") {
+                            Ok(link) => {
+                                tracing::info!("Attached kfree_skb tracepoint for drop reason tracing");
+                                drop_tracing_enabled = true;
+                                probe_links.kfree_skb = Some(link);
+                            }
+                            Err(e) => tracing::warn!("Failed to attach kfree_skb tracepoint: {}", e),
+                        }
                     }
                 }
                 Err(e) => {
@@ -421,143 +1806,714 @@ impl EbpfManager {
             tracing::debug!("kfree_skb program not found in eBPF binary");
         }
 
-        // Try to attach nf_hook_slow tracepoint (Phase 6.2)
-        let mut nf_tracing_enabled = false;
-        if let Some(prog) = bpf.program_mut("nf_hook_slow") {
-            match prog.try_into() as Result<&mut TracePoint, _> {
-                Ok(tp) => {
-                    if let Err(e) = tp.load() {
-                        tracing::warn!("Failed to load nf_hook_slow tracepoint: {}", e);
-                    } else if let Err(e) = tp.attach("netfilter", "nf_hook_slow") {
-                        tracing::warn!("Failed to attach nf_hook_slow tracepoint: {}", e);
-                    } else {
-                        tracing::info!("Attached nf_hook_slow tracepoint for netfilter tracing");
-                        nf_tracing_enabled = true;
-                    }
-                }
-                Err(e) => {
-                    tracing::warn!("nf_hook_slow program not a tracepoint: {}", e);
-                }
-            }
-        } else {
-            tracing::debug!("nf_hook_slow program not found in eBPF binary");
+        // Try to attach nf_hook_slow fexit (Phase 6.2); fexit programs are
+        // verified against the kernel's BTF, which older kernels lack
+        let mut nf_tracing_enabled = false;
+        if !caps.fexit {
+            tracing::info!("Skipping nf_hook_slow fexit: netfilter tracing needs kernel BTF and 5.5+");
+        } else if let Some(prog) = bpf.program_mut("nf_hook_slow") {
+            match prog.try_into() as Result<&mut FExit, _> {
+                Ok(fexit) => match Btf::from_sys_fs() {
+                    Ok(btf) => {
+                        if let Err(e) = fexit.load("nf_hook_slow", &btf) {
+                            tracing::warn!("Failed to load nf_hook_slow fexit: {}", e);
+                        } else {
+                            match fexit.attach() {
+                                Ok(link) => {
+                                    tracing::info!("Attached nf_hook_slow fexit for netfilter tracing");
+                                    nf_tracing_enabled = true;
+                                    probe_links.nf_hook_slow = Some(link);
+                                }
+                                Err(e) => tracing::warn!("Failed to attach nf_hook_slow fexit: {}", e),
+                            }
+                        }
+                    }
+                    Err(e) => tracing::warn!("Netfilter tracing needs kernel BTF: {}", e),
+                },
+                Err(e) => {
+                    tracing::warn!("nf_hook_slow program not an fexit: {}", e);
+                }
+            }
+        } else {
+            tracing::debug!("nf_hook_slow program not found in eBPF binary");
+        }
+
+        // Pin NF_EVENTS map if available
+        if let Some(map) = bpf.map_mut("NF_EVENTS") {
+            let _ = map.pin(pin_path.join("nf_events"));
+        }
+
+        // Try to attach tcp_retransmit_skb tracepoint
+        let mut retransmit_tracing_enabled = false;
+        let layout = crate::tracefmt::tcp_retransmit_skb_layout;
+        write_tracepoint_layout(&mut bpf, "TCP_RETRANSMIT_SKB_LAYOUT", "tcp", "tcp_retransmit_skb", layout)?;
+        if let Some(prog) = bpf.program_mut("tcp_retransmit_skb") {
+            match prog.try_into() as Result<&mut TracePoint, _> {
+                Ok(tp) => {
+                    if let Err(e) = tp.load() {
+                        tracing::warn!("Failed to load tcp_retransmit_skb tracepoint: {}", e);
+                    } else if let Err(e) = tp.attach("tcp", "tcp_retransmit_skb") {
+                        tracing::warn!("Failed to attach tcp_retransmit_skb tracepoint: {}", e);
+                    } else {
+                        tracing::info!("Attached tcp_retransmit_skb tracepoint for retransmission tracing");
+                        retransmit_tracing_enabled = true;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("tcp_retransmit_skb program not a tracepoint: {}", e);
+                }
+            }
+        } else {
+            tracing::debug!("tcp_retransmit_skb program not found in eBPF binary");
+        }
+
+        // Pin RETRANSMIT_EVENTS map if available
+        if let Some(map) = bpf.map_mut("RETRANSMIT_EVENTS") {
+            let _ = map.pin(pin_path.join("retransmit_events"));
+        }
+
+        // Try to attach tcp_probe tracepoint
+        let mut rtt_tracing_enabled = false;
+        write_tracepoint_layout(&mut bpf, "TCP_PROBE_LAYOUT", "tcp", "tcp_probe", crate::tracefmt::tcp_probe_layout)?;
+        if let Some(prog) = bpf.program_mut("tcp_probe") {
+            match prog.try_into() as Result<&mut TracePoint, _> {
+                Ok(tp) => {
+                    if let Err(e) = tp.load() {
+                        tracing::warn!("Failed to load tcp_probe tracepoint: {}", e);
+                    } else if let Err(e) = tp.attach("tcp", "tcp_probe") {
+                        tracing::warn!("Failed to attach tcp_probe tracepoint: {}", e);
+                    } else {
+                        tracing::info!("Attached tcp_probe tracepoint for RTT measurement");
+                        rtt_tracing_enabled = true;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("tcp_probe program not a tracepoint: {}", e);
+                }
+            }
+        } else {
+            tracing::debug!("tcp_probe program not found in eBPF binary");
+        }
+
+        // Pin RTT_HISTOGRAMS for `sennet latency`
+        if let Some(map) = bpf.map_mut("RTT_HISTOGRAMS") {
+            let _ = map.pin(pin_path.join("rtt_histograms"));
+        }
+
+        // ICMP_RTT is filled by the classifiers, for `sennet latency --icmp`
+        if let Some(map) = bpf.map_mut("ICMP_RTT") {
+            let _ = map.pin(pin_path.join("icmp_rtt"));
+        }
+
+        // inet_sock_set_state tracepoint - TCP opens and closes with the
+        // connecting process, plus connection churn (4.16+); older kernels
+        // go straight to the kprobes below
+        let mut sock_state_tracing_enabled = false;
+        if caps.sock_state_tracepoint {
+            let layout = crate::tracefmt::inet_sock_set_state_layout;
+            write_tracepoint_layout(&mut bpf, "INET_SOCK_SET_STATE_LAYOUT", "sock", "inet_sock_set_state", layout)?;
+        }
+        if let Some(prog) = bpf.program_mut("inet_sock_set_state").filter(|_| caps.sock_state_tracepoint) {
+            match prog.try_into() as Result<&mut TracePoint, _> {
+                Ok(tp) => {
+                    if let Err(e) = tp.load() {
+                        tracing::warn!("Failed to load inet_sock_set_state tracepoint: {}", e);
+                    } else {
+                        match tp.attach("sock", "inet_sock_set_state") {
+                            Ok(link) => {
+                                tracing::info!("Attached inet_sock_set_state tracepoint for TCP connection lifecycle");
+                                sock_state_tracing_enabled = true;
+                                probe_links.sock_state = Some(link);
+                            }
+                            Err(e) => tracing::warn!("Failed to attach inet_sock_set_state tracepoint: {}", e),
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("inet_sock_set_state program not a tracepoint: {}", e);
+                }
+            }
+        } else {
+            tracing::debug!("inet_sock_set_state program not found in eBPF binary");
+        }
+
+        if let Some(map) = bpf.map_mut("CONN_CHURN") {
+            let _ = map.pin(pin_path.join("conn_churn"));
+        }
+
+        // Try to attach flow tracking kprobes (Phase 8)
+        let mut flow_tracing_enabled = sock_state_tracing_enabled;
+
+        // tcp_connect kprobe - track outbound connections, unless the
+        // tracepoint already reports them
+        if let Some(prog) = bpf.program_mut("tcp_connect").filter(|_| !sock_state_tracing_enabled) {
+            match prog.try_into() as Result<&mut KProbe, _> {
+                Ok(kp) => {
+                    if let Err(e) = kp.load() {
+                        tracing::warn!("Failed to load tcp_connect kprobe: {}", e);
+                    } else {
+                        match kp.attach("tcp_connect", 0) {
+                            Ok(link) => {
+                                tracing::info!("Attached tcp_connect kprobe for outbound flow tracking");
+                                flow_tracing_enabled = true;
+                                probe_links.flow_kprobes.push(("tcp_connect", "tcp_connect", link));
+                            }
+                            Err(e) => tracing::warn!("Failed to attach tcp_connect kprobe: {}", e),
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("tcp_connect program not a kprobe: {}", e);
+                }
+            }
+        }
+        
+        // inet_csk_accept kprobe - track inbound connections; kept with
+        // the tracepoint, which sees passive opens in softirq context
+        // without the accepting process
+        if let Some(prog) = bpf.program_mut("inet_csk_accept") {
+            match prog.try_into() as Result<&mut KProbe, _> {
+                Ok(kp) => {
+                    if let Err(e) = kp.load() {
+                        tracing::warn!("Failed to load inet_csk_accept kprobe: {}", e);
+                    } else {
+                        match kp.attach("inet_csk_accept", 0) {
+                            Ok(link) => {
+                                tracing::info!("Attached inet_csk_accept kprobe for inbound flow tracking");
+                                probe_links.flow_kprobes.push(("inet_csk_accept", "inet_csk_accept", link));
+                            }
+                            Err(e) => tracing::warn!("Failed to attach inet_csk_accept kprobe: {}", e),
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("inet_csk_accept program not a kprobe: {}", e);
+                }
+            }
+        }
+        
+        // tcp_close kprobe - track connection closures
+        if let Some(prog) = bpf.program_mut("tcp_close").filter(|_| !sock_state_tracing_enabled) {
+            match prog.try_into() as Result<&mut KProbe, _> {
+                Ok(kp) => {
+                    if let Err(e) = kp.load() {
+                        tracing::warn!("Failed to load tcp_close kprobe: {}", e);
+                    } else {
+                        match kp.attach("tcp_close", 0) {
+                            Ok(link) => {
+                                tracing::info!("Attached tcp_close kprobe for flow cleanup");
+                                probe_links.flow_kprobes.push(("tcp_close", "tcp_close", link));
+                            }
+                            Err(e) => tracing::warn!("Failed to attach tcp_close kprobe: {}", e),
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("tcp_close program not a kprobe: {}", e);
+                }
+            }
+        }
+        
+        // tcp_sendmsg/tcp_cleanup_rbuf kprobes - bytes moved on tracked flows
+        for symbol in FLOW_BYTE_KPROBES {
+            let Some(prog) = bpf.program_mut(symbol) else {
+                tracing::debug!("{} program not found in eBPF binary", symbol);
+                continue;
+            };
+            match prog.try_into() as Result<&mut KProbe, _> {
+                Ok(kp) => {
+                    if let Err(e) = kp.load() {
+                        tracing::warn!("Failed to load {} kprobe: {}", symbol, e);
+                    } else {
+                        match kp.attach(symbol, 0) {
+                            Ok(link) => {
+                                tracing::info!("Attached {} kprobe for flow byte counts", symbol);
+                                probe_links.flow_kprobes.push((symbol, symbol, link));
+                            }
+                            Err(e) => tracing::warn!("Failed to attach {} kprobe: {}", symbol, e),
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("{} program not a kprobe: {}", symbol, e);
+                }
+            }
+        }
+
+        // Conntrack kprobes - entry confirmed (program name differs from the
+        // symbol: Rust identifiers can't start with "__")
+        let mut conntrack_tracing_enabled = false;
+        for (program, symbol) in [("nf_conntrack_confirm", "__nf_conntrack_confirm"), ("nf_ct_delete", "nf_ct_delete")] {
+            let Some(prog) = bpf.program_mut(program) else {
+                tracing::debug!("{} program not found in eBPF binary", program);
+                continue;
+            };
+            match prog.try_into() as Result<&mut KProbe, _> {
+                Ok(kp) => {
+                    if let Err(e) = kp.load() {
+                        tracing::warn!("Failed to load {} kprobe: {}", program, e);
+                    } else if let Err(e) = kp.attach(symbol, 0) {
+                        // nf_conntrack is a module; absent until something loads it
+                        tracing::warn!("Failed to attach {} kprobe (is nf_conntrack loaded?): {}", symbol, e);
+                    } else {
+                        tracing::info!("Attached {} kprobe for conntrack tracing", symbol);
+                        conntrack_tracing_enabled = true;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("{} program not a kprobe: {}", program, e);
+                }
+            }
+        }
+
+        // Pin CT_EVENTS map if available
+        if let Some(map) = bpf.map_mut("CT_EVENTS") {
+            let _ = map.pin(pin_path.join("ct_events"));
+        }
+
+        // Pin FLOWS map if available
+        if let Some(map) = bpf.map_mut("FLOWS") {
+            let _ = map.pin(pin_path.join("flows"));
+        }
+        if let Some(map) = bpf.map_mut("TLS_SNI") {
+            let _ = map.pin(pin_path.join("tls_sni"));
+        }
+        
+        // Pin CGROUP_COUNTERS; the cgroup/skb programs are attached on
+        // request (attach_cgroup)
+        if let Some(map) = bpf.map_mut("CGROUP_COUNTERS") {
+            let _ = map.pin(pin_path.join("cgroup_counters"));
+        }
+
+        // Pin TOP_TALKERS for `sennet top-talkers`
+        if let Some(map) = bpf.map_mut("TOP_TALKERS") {
+            let _ = map.pin(pin_path.join("top_talkers"));
+        }
+        
+        // Pin FLOW_EVENTS map if available
+        if let Some(map) = bpf.map_mut("FLOW_EVENTS") {
+            let _ = map.pin(pin_path.join("flow_events"));
+        }
+        
+        // Pin RST_EVENTS map if available (filled by the TC classifiers)
+        if let Some(map) = bpf.map_mut("RST_EVENTS") {
+            let _ = map.pin(pin_path.join("rst_events"));
+        }
+
+        // Pin EVENTS map (large packets, filled by the TC/XDP programs)
+        if let Some(map) = bpf.map_mut("EVENTS") {
+            let _ = map.pin(pin_path.join("events"));
+        }
+
+        // Pin DNS_EVENTS map (filled by the TC/XDP programs)
+        if let Some(map) = bpf.map_mut("DNS_EVENTS") {
+            let _ = map.pin(pin_path.join("dns_events"));
+        }
+
+        // Pin PAYLOAD_EVENTS for `sennet trace --payload`, its only reader
+        if let Some(map) = bpf.map_mut("PAYLOAD_EVENTS") {
+            let _ = map.pin(pin_path.join("payload_events"));
+        }
+
+        // Pin HTTP_EVENTS for `sennet http`, its only reader
+        if let Some(map) = bpf.map_mut("HTTP_EVENTS") {
+            let _ = map.pin(pin_path.join("http_events"));
+        }
+        if let Some(map) = bpf.map_mut("HTTP_PORTS") {
+            let _ = map.pin(pin_path.join("http_ports"));
+        }
+
+        // Pin TUNABLES so rate limits can be changed without reloading
+        if let Some(map) = bpf.map_mut("TUNABLES") {
+            let _ = map.pin(pin_path.join("tunables"));
+        }
+
+        // Pin PACKET_SAMPLING so the sampling rate can be changed without reloading
+        if let Some(map) = bpf.map_mut("PACKET_SAMPLING") {
+            let _ = map.pin(pin_path.join("packet_sampling"));
+        }
+
+        // Pin LARGE_PACKET_THRESHOLD so the threshold can be changed without reloading
+        if let Some(map) = bpf.map_mut("LARGE_PACKET_THRESHOLD") {
+            let _ = map.pin(pin_path.join("large_packet_threshold"));
+        }
+
+        // Pin SOURCE_PPS_THRESHOLD so the threshold can be changed without reloading
+        if let Some(map) = bpf.map_mut("SOURCE_PPS_THRESHOLD") {
+            let _ = map.pin(pin_path.join("source_pps_threshold"));
+        }
+
+        // Pin DROP_FILTER so the filter can be changed without reloading
+        if let Some(map) = bpf.map_mut("DROP_FILTER") {
+            let _ = map.pin(pin_path.join("drop_filter"));
+        }
+
+        // Pin TRACE_RULE so `sennet trace` can filter in the kernel
+        if let Some(map) = bpf.map_mut("TRACE_RULE") {
+            let _ = map.pin(pin_path.join("trace_rule"));
+        }
+
+        // Pin PAYLOAD_RULE so `sennet trace --payload` can ask for packet heads
+        if let Some(map) = bpf.map_mut("PAYLOAD_RULE") {
+            let _ = map.pin(pin_path.join("payload_rule"));
+        }
+
+        // Pin BLOCKLIST and ENFORCE so `sennet block` can edit the
+        // blocklist of the running agent
+        if let Some(map) = bpf.map_mut("BLOCKLIST") {
+            let _ = map.pin(pin_path.join("blocklist"));
+        }
+        if let Some(map) = bpf.map_mut("ENFORCE") {
+            let _ = map.pin(pin_path.join("enforce"));
+        }
+
+        // Pin RESERVE_FAILURES for self-metrics (events lost to full rings)
+        if let Some(map) = bpf.map_mut("RESERVE_FAILURES") {
+            let _ = map.pin(pin_path.join("reserve_failures"));
+        }
+
+        Ok(Self {
+            interfaces: attached,
+            bpf,
+            added_clsact,
+            flow_programs: probe_links.flow_kprobes.iter().map(|&(program, symbol, _)| (program, symbol)).collect(),
+            available_probes: Probe::ALL
+                .into_iter()
+                .filter(|&probe| match probe {
+                    Probe::Drops => probe_links.kfree_skb.is_some(),
+                    Probe::Netfilter => probe_links.nf_hook_slow.is_some(),
+                    Probe::Flows => probe_links.sock_state.is_some() || !probe_links.flow_kprobes.is_empty(),
+                })
+                .collect(),
+            probe_links,
+            attach_mode: mode,
+            drop_tracing_enabled,
+            nf_tracing_enabled,
+            flow_tracing_enabled,
+            sock_state_tracing_enabled,
+            retransmit_tracing_enabled,
+            rtt_tracing_enabled,
+            conntrack_tracing_enabled,
+        })
+    }
+
+    /// Detach every program and, unless `keep_pinned`, remove the map pins
+    ///
+    /// The clsact qdiscs loading added are deleted too. Dropping the
+    /// manager without calling this still detaches the programs, but leaves
+    /// the qdisc and the pins behind; readers holding a pinned map open keep
+    /// it alive until they close it.
+    #[cfg(target_os = "linux")]
+    pub fn shutdown(self, keep_pinned: bool) {
+        let Self { bpf, added_clsact, .. } = self;
+        // Programs detach when their links are dropped with the object
+        drop(bpf);
+        for interface in &added_clsact {
+            match std::process::Command::new("tc").args(["qdisc", "del", "dev", interface, "clsact"]).output() {
+                Ok(output) if output.status.success() => tracing::info!("Removed clsact qdisc from {}", interface),
+                Ok(output) => tracing::warn!(
+                    "Failed to remove clsact qdisc from {}: {}",
+                    interface,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Err(e) => tracing::warn!("Failed to remove clsact qdisc from {} (is tc installed?): {}", interface, e),
+            }
+        }
+        if keep_pinned {
+            return;
+        }
+        match remove_pins(pin_dir()) {
+            Ok(0) => {}
+            Ok(removed) => tracing::info!("Unpinned {} maps from {}", removed, pin_dir().display()),
+            Err(e) => tracing::warn!("Failed to unpin maps from {}: {}", pin_dir().display(), e),
+        }
+    }
+
+    /// Write rate limiter settings into the TUNABLES map
+    #[cfg(target_os = "linux")]
+    pub fn set_tunables(&mut self, tunables: &Tunables) -> Result<()> {
+        let map = self
+            .bpf
+            .map_mut("TUNABLES")
+            .ok_or_else(|| anyhow::anyhow!("TUNABLES map not found (eBPF object predates rate limiting)"))?;
+        let mut array: aya::maps::Array<_, Tunables> = aya::maps::Array::try_from(map)?;
+        array.set(0, *tunables, 0)?;
+        Ok(())
+    }
+
+    /// Make the classifiers look at 1 in `one_in` packets for events
+    /// (0 or 1 = every packet; max 255)
+    ///
+    /// Packet counters and top talkers still count every packet.
+    #[cfg(target_os = "linux")]
+    pub fn set_packet_sampling(&mut self, one_in: u32) -> Result<()> {
+        let map = self
+            .bpf
+            .map_mut("PACKET_SAMPLING")
+            .ok_or_else(|| anyhow::anyhow!("PACKET_SAMPLING map not found (eBPF object predates packet sampling)"))?;
+        let mut array: aya::maps::Array<_, u32> = aya::maps::Array::try_from(map)?;
+        array.set(0, one_in.min(255), 0)?;
+        Ok(())
+    }
+
+    /// Report packets longer than `bytes` as large-packet events (0 = the
+    /// default, [`sennet_common::DEFAULT_LARGE_PACKET_THRESHOLD`])
+    #[cfg(target_os = "linux")]
+    pub fn set_large_packet_threshold(&mut self, bytes: u32) -> Result<()> {
+        let map = self.bpf.map_mut("LARGE_PACKET_THRESHOLD").ok_or_else(|| {
+            anyhow::anyhow!("LARGE_PACKET_THRESHOLD map not found (eBPF object predates the configurable threshold)")
+        })?;
+        let mut array: aya::maps::Array<_, u32> = aya::maps::Array::try_from(map)?;
+        array.set(0, bytes, 0)?;
+        Ok(())
+    }
+
+    /// Fill the BLOCKLIST map with `keys` and make the classifiers drop
+    /// matching packets
+    #[cfg(target_os = "linux")]
+    pub fn enable_enforcement(&mut self, keys: &[BlockKey]) -> Result<()> {
+        let map = self
+            .bpf
+            .map_mut("BLOCKLIST")
+            .ok_or_else(|| anyhow::anyhow!("BLOCKLIST map not found (eBPF object predates enforcement)"))?;
+        let mut blocklist: aya::maps::HashMap<_, BlockKey, u64> = aya::maps::HashMap::try_from(map)?;
+        for key in keys {
+            blocklist.insert(key, 0, 0)?;
+        }
+        let map = self
+            .bpf
+            .map_mut("ENFORCE")
+            .ok_or_else(|| anyhow::anyhow!("ENFORCE map not found (eBPF object predates enforcement)"))?;
+        let mut array: aya::maps::Array<_, u32> = aya::maps::Array::try_from(map)?;
+        array.set(0, 1, 0)?;
+        Ok(())
+    }
+
+    /// Report ingress sources above `pps` packets per second as rate
+    /// anomalies (0 = off)
+    #[cfg(target_os = "linux")]
+    pub fn set_source_pps_threshold(&mut self, pps: u32) -> Result<()> {
+        let map = self.bpf.map_mut("SOURCE_PPS_THRESHOLD").ok_or_else(|| {
+            anyhow::anyhow!("SOURCE_PPS_THRESHOLD map not found (eBPF object predates rate anomaly detection)")
+        })?;
+        let mut array: aya::maps::Array<_, u32> = aya::maps::Array::try_from(map)?;
+        array.set(0, pps, 0)?;
+        Ok(())
+    }
+
+    /// Sample HTTP/1.x request lines sent to `ports` (empty = off)
+    #[cfg(target_os = "linux")]
+    pub fn set_http_ports(&mut self, ports: &[u16]) -> Result<()> {
+        let map = self.bpf.map_mut("HTTP_PORTS").ok_or_else(|| {
+            anyhow::anyhow!("HTTP_PORTS map not found (eBPF object predates HTTP request sampling)")
+        })?;
+        let mut http_ports: aya::maps::HashMap<_, u16, u8> = aya::maps::HashMap::try_from(map)?;
+        for port in ports {
+            http_ports.insert(port, 1, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Write the kernel drop filter into the DROP_FILTER map
+    #[cfg(target_os = "linux")]
+    pub fn set_drop_filter(&mut self, filter: &DropFilter) -> Result<()> {
+        let map = self
+            .bpf
+            .map_mut("DROP_FILTER")
+            .ok_or_else(|| anyhow::anyhow!("DROP_FILTER map not found (eBPF object predates drop filters)"))?;
+        let mut array: aya::maps::Array<_, DropFilter> = aya::maps::Array::try_from(map)?;
+        array.set(0, *filter, 0)?;
+        Ok(())
+    }
+
+    /// Take ownership of the event ring buffers for the daemon pipeline
+    ///
+    /// Maps stay pinned, so CLI readers can still open them (they then
+    /// compete with the daemon for records). Payload snippets and HTTP
+    /// requests are left to the commands that read them.
+    #[cfg(target_os = "linux")]
+    pub fn take_ring_buffers(&mut self) -> Vec<(RingKind, aya::maps::RingBuf<aya::maps::MapData>)> {
+        RingKind::ALL
+            .iter()
+            .filter(|kind| kind.daemon_reads())
+            .filter_map(|kind| {
+                let map = self.bpf.take_map(kind.map_name())?;
+                aya::maps::RingBuf::try_from(map).ok().map(|rb| (*kind, rb))
+            })
+            .collect()
+    }
+
+    /// Drain DROP_EVENTS and count the drops by reason
+    ///
+    /// Fails once [`take_ring_buffers`](Self::take_ring_buffers) handed the
+    /// ring to the event pipeline; fold its windows in with
+    /// [`SharedDrops::sink`](crate::drops::SharedDrops::sink) instead.
+    #[cfg(target_os = "linux")]
+    pub fn read_drop_summary(&mut self) -> Result<crate::drops::DropSummary> {
+        let map = self
+            .bpf
+            .map_mut("DROP_EVENTS")
+            .ok_or_else(|| anyhow::anyhow!("DROP_EVENTS map not found, or read by the event pipeline"))?;
+        let mut ring = aya::maps::RingBuf::try_from(map)?;
+        let mut summary = crate::drops::DropSummary::default();
+        while let Some(item) = ring.next() {
+            if let Some(event) = crate::events::view_event::<DropEvent>(&item) {
+                summary.add_event(event);
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Read current counters from eBPF maps
+    #[cfg(target_os = "linux")]
+    pub fn read_counters(&self) -> Result<PacketCounters> {
+        let counters_map: PerCpuArray<_, KernelCounters> = 
+            PerCpuArray::try_from(self.bpf.map("COUNTERS").unwrap())?;
+        let tcp_flags_map: PerCpuArray<_, TcpFlagCounters> = PerCpuArray::try_from(
+            self.bpf.map("TCP_FLAG_COUNTERS").ok_or_else(|| anyhow::anyhow!("TCP_FLAG_COUNTERS map not found"))?,
+        )?;
+
+        // Sum across all CPUs; index 0 is ingress, 1 egress
+        let mut total = PacketCounters::default();
+        for direction in 0..2 {
+            for cpu_val in counters_map.get(&direction, 0)?.iter() {
+                total.add_kernel(direction, cpu_val);
+            }
+            for cpu_val in tcp_flags_map.get(&direction, 0)?.iter() {
+                total.add_tcp_flags(direction, cpu_val);
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// Interfaces the classifiers are attached to
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    /// Attach the classifiers to `interface`, which appeared after loading
+    ///
+    /// Returns false if they are already attached to it.
+    #[cfg(target_os = "linux")]
+    pub fn attach_interface(&mut self, interface: &str) -> Result<bool> {
+        if self.interfaces.iter().any(|i| i == interface) {
+            return Ok(false);
+        }
+        if attach_classifiers(&mut self.bpf, interface, self.attach_mode)? {
+            self.added_clsact.push(interface.to_string());
         }
+        self.interfaces.push(interface.to_string());
+        Ok(true)
+    }
 
-        // Pin NF_EVENTS map if available
-        if let Some(map) = bpf.map_mut("NF_EVENTS") {
-            let _ = map.pin(pin_path.join("nf_events"));
+    /// Whether each [`Probe`] is loaded and attached
+    #[cfg(target_os = "linux")]
+    pub fn probes(&self) -> Vec<ProbeState> {
+        Probe::ALL
+            .into_iter()
+            .map(|probe| ProbeState {
+                probe,
+                available: self.available_probes.contains(&probe),
+                attached: self.probe_attached(probe),
+            })
+            .collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn probe_attached(&self, probe: Probe) -> bool {
+        let links = &self.probe_links;
+        match probe {
+            Probe::Drops => links.kfree_skb.is_some(),
+            Probe::Netfilter => links.nf_hook_slow.is_some(),
+            Probe::Flows => links.sock_state.is_some() || !links.flow_kprobes.is_empty(),
         }
+    }
 
-        // Try to attach flow tracking kprobes (Phase 8)
-        let mut flow_tracing_enabled = false;
-        
-        // tcp_connect kprobe - track outbound connections
-        if let Some(prog) = bpf.program_mut("tcp_connect") {
-            match prog.try_into() as Result<&mut KProbe, _> {
-                Ok(kp) => {
-                    if let Err(e) = kp.load() {
-                        tracing::warn!("Failed to load tcp_connect kprobe: {}", e);
-                    } else if let Err(e) = kp.attach("tcp_connect", 0) {
-                        tracing::warn!("Failed to attach tcp_connect kprobe: {}", e);
-                    } else {
-                        tracing::info!("Attached tcp_connect kprobe for outbound flow tracking");
-                        flow_tracing_enabled = true;
-                    }
+    /// Attach or detach `probe`, which stays loaded; returns false if it
+    /// already was
+    ///
+    /// Only probes attached at load can be switched: the others failed to
+    /// load or attach on this kernel.
+    #[cfg(target_os = "linux")]
+    pub fn set_probe(&mut self, probe: Probe, attach: bool) -> Result<bool> {
+        if !self.available_probes.contains(&probe) {
+            anyhow::bail!("The {} probe could not be attached at startup on this kernel", probe.name());
+        }
+        if self.probe_attached(probe) == attach {
+            return Ok(false);
+        }
+        let links = &mut self.probe_links;
+        match probe {
+            Probe::Drops => {
+                let tp: &mut TracePoint = find_program(&mut self.bpf, "kfree_skb")?.try_into()?;
+                match links.kfree_skb.take() {
+                    Some(link) => tp.detach(link)?,
+                    None => links.kfree_skb = Some(tp.attach("skb", "kfree_skb")?),
                 }
-                Err(e) => {
-                    tracing::warn!("tcp_connect program not a kprobe: {}", e);
+                self.drop_tracing_enabled = attach;
+            }
+            Probe::Netfilter => {
+                let fexit: &mut FExit = find_program(&mut self.bpf, "nf_hook_slow")?.try_into()?;
+                match links.nf_hook_slow.take() {
+                    Some(link) => fexit.detach(link)?,
+                    None => links.nf_hook_slow = Some(fexit.attach()?),
                 }
+                self.nf_tracing_enabled = attach;
             }
-        }
-        
-        // inet_csk_accept kprobe - track inbound connections
-        if let Some(prog) = bpf.program_mut("inet_csk_accept") {
-            match prog.try_into() as Result<&mut KProbe, _> {
-                Ok(kp) => {
-                    if let Err(e) = kp.load() {
-                        tracing::warn!("Failed to load inet_csk_accept kprobe: {}", e);
-                    } else if let Err(e) = kp.attach("inet_csk_accept", 0) {
-                        tracing::warn!("Failed to attach inet_csk_accept kprobe: {}", e);
-                    } else {
-                        tracing::info!("Attached inet_csk_accept kprobe for inbound flow tracking");
-                    }
+            Probe::Flows if attach => {
+                if self.sock_state_tracing_enabled {
+                    let tp: &mut TracePoint = find_program(&mut self.bpf, "inet_sock_set_state")?.try_into()?;
+                    links.sock_state = Some(tp.attach("sock", "inet_sock_set_state")?);
                 }
-                Err(e) => {
-                    tracing::warn!("inet_csk_accept program not a kprobe: {}", e);
+                for &(name, symbol) in &self.flow_programs {
+                    let kp: &mut KProbe = find_program(&mut self.bpf, name)?.try_into()?;
+                    links.flow_kprobes.push((name, symbol, kp.attach(symbol, 0)?));
                 }
+                self.flow_tracing_enabled = true;
             }
-        }
-        
-        // tcp_close kprobe - track connection closures
-        if let Some(prog) = bpf.program_mut("tcp_close") {
-            match prog.try_into() as Result<&mut KProbe, _> {
-                Ok(kp) => {
-                    if let Err(e) = kp.load() {
-                        tracing::warn!("Failed to load tcp_close kprobe: {}", e);
-                    } else if let Err(e) = kp.attach("tcp_close", 0) {
-                        tracing::warn!("Failed to attach tcp_close kprobe: {}", e);
-                    } else {
-                        tracing::info!("Attached tcp_close kprobe for flow cleanup");
-                    }
+            Probe::Flows => {
+                if let Some(link) = links.sock_state.take() {
+                    let tp: &mut TracePoint = find_program(&mut self.bpf, "inet_sock_set_state")?.try_into()?;
+                    tp.detach(link)?;
                 }
-                Err(e) => {
-                    tracing::warn!("tcp_close program not a kprobe: {}", e);
+                for (name, _, link) in links.flow_kprobes.drain(..) {
+                    let kp: &mut KProbe = find_program(&mut self.bpf, name)?.try_into()?;
+                    kp.detach(link)?;
                 }
+                self.flow_tracing_enabled = false;
             }
         }
-        
-        // Pin FLOWS map if available
-        if let Some(map) = bpf.map_mut("FLOWS") {
-            let _ = map.pin(pin_path.join("flows"));
-        }
-        
-        // Pin FLOW_EVENTS map if available
-        if let Some(map) = bpf.map_mut("FLOW_EVENTS") {
-            let _ = map.pin(pin_path.join("flow_events"));
-        }
-
-        Ok(Self {
-            interface: interface.to_string(),
-            bpf,
-            drop_tracing_enabled,
-            nf_tracing_enabled,
-            flow_tracing_enabled,
-        })
+        Ok(true)
     }
 
-    /// Read current counters from eBPF maps
+    /// Stop tracking `interface` (ifindex `index`) after it was removed
+    ///
+    /// Its programs and qdisc went with it; its IFACE_COUNTERS entry is
+    /// deleted so a later interface reusing the index starts from zero.
+    /// Returns whether it was attached.
     #[cfg(target_os = "linux")]
-    pub fn read_counters(&self) -> Result<PacketCounters> {
-        let counters_map: PerCpuArray<_, PacketCounters> = 
-            PerCpuArray::try_from(self.bpf.map("COUNTERS").unwrap())?;
-        
-        // Sum across all CPUs
-        let mut total = PacketCounters::default();
-        
-        // Helper to sum counters for a given index
-        let sum_values = |index: u32| -> Result<PacketCounters> {
-            let values = counters_map.get(&index, 0)?;
-            let mut sum = PacketCounters::default();
-            for cpu_val in values.iter() {
-                sum.rx_packets += cpu_val.rx_packets;
-                sum.rx_bytes += cpu_val.rx_bytes;
-                sum.tx_packets += cpu_val.tx_packets;
-                sum.tx_bytes += cpu_val.tx_bytes;
-                sum.drop_count += cpu_val.drop_count;
-            }
-            Ok(sum)
+    pub fn forget_interface(&mut self, index: u32, interface: &str) -> bool {
+        let Some(pos) = self.interfaces.iter().position(|i| i == interface) else {
+            return false;
         };
+        self.interfaces.remove(pos);
+        self.added_clsact.retain(|i| i != interface);
+        if let Some(map) = self.bpf.map_mut("IFACE_COUNTERS") {
+            if let Ok(mut counters) = aya::maps::PerCpuHashMap::<_, u32, KernelCounters>::try_from(map) {
+                let _ = counters.remove(&index);
+            }
+        }
+        true
+    }
 
-        let ingress = sum_values(0)?;
-        let egress = sum_values(1)?;
-
-        total.rx_packets = ingress.rx_packets;
-        total.rx_bytes = ingress.rx_bytes;
-        total.tx_packets = egress.tx_packets;
-        total.tx_bytes = egress.tx_bytes;
-        total.drop_count = ingress.drop_count;
-
-        Ok(total)
+    /// Counters per attached interface, by ifindex
+    #[cfg(target_os = "linux")]
+    pub fn read_interface_counters(&self) -> Result<BTreeMap<u32, PacketCounters>> {
+        let map: aya::maps::PerCpuHashMap<_, u32, KernelCounters> = aya::maps::PerCpuHashMap::try_from(
+            self.bpf.map("IFACE_COUNTERS").ok_or_else(|| anyhow::anyhow!("IFACE_COUNTERS map not found"))?,
+        )?;
+        Ok(fold_interface_counters(map.iter().filter_map(|item| item.ok()).map(|(ifindex, per_cpu)| (ifindex, per_cpu.to_vec()))))
     }
 
     /// Read all active flows from eBPF LRU HashMap (Phase 8)
@@ -576,15 +2532,122 @@ impl EbpfManager {
         Ok(flows)
     }
 
+    /// Source/destination pairs by traffic, busiest first
+    #[cfg(target_os = "linux")]
+    pub fn read_top_talkers(&self) -> Result<Vec<TopTalker>> {
+        let talkers: LruHashMap<_, IpPair, TalkerCounters> = LruHashMap::try_from(
+            self.bpf.map("TOP_TALKERS").ok_or_else(|| anyhow::anyhow!("TOP_TALKERS map not found"))?,
+        )?;
+        Ok(rank_top_talkers(talkers.iter().filter_map(|item| item.ok())))
+    }
+
+    /// RTT percentiles per remote address, most sampled first
+    #[cfg(target_os = "linux")]
+    pub fn read_rtt(&self) -> Result<Vec<RemoteRtt>> {
+        self.read_rtt_map("RTT_HISTOGRAMS")
+    }
+
+    /// ICMP echo round-trip percentiles per pinged address, most sampled
+    /// first
+    #[cfg(target_os = "linux")]
+    pub fn read_icmp_rtt(&self) -> Result<Vec<RemoteRtt>> {
+        self.read_rtt_map("ICMP_RTT")
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_rtt_map(&self, name: &str) -> Result<Vec<RemoteRtt>> {
+        let histograms: aya::maps::PerCpuHashMap<_, [u8; 16], RttHistogram> = aya::maps::PerCpuHashMap::try_from(
+            self.bpf.map(name).ok_or_else(|| anyhow::anyhow!("{} map not found", name))?,
+        )?;
+        Ok(summarize_rtt(histograms.iter().filter_map(|item| item.ok()).map(|(remote, per_cpu)| (remote, per_cpu.to_vec()))))
+    }
+
+    /// Count the traffic of the cgroup v2 directory `path` and of every
+    /// cgroup below it, each under its own ID
+    ///
+    /// Attaching at [`CGROUP_ROOT`] covers every container on the host.
+    /// Packets are attributed by their socket's cgroup, not by address, so
+    /// containers sharing the host network are told apart too.
+    #[cfg(target_os = "linux")]
+    pub fn attach_cgroup(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let cgroup = std::fs::File::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open cgroup {}: {}", path.display(), e))?;
+        for (name, attach_type) in [
+            ("cgroup_skb_ingress", CgroupSkbAttachType::Ingress),
+            ("cgroup_skb_egress", CgroupSkbAttachType::Egress),
+        ] {
+            let prog: &mut CgroupSkb = self
+                .bpf
+                .program_mut(name)
+                .ok_or_else(|| anyhow::anyhow!("{} program not found in eBPF binary", name))?
+                .try_into()?;
+            // Loaded once, attached to as many cgroups as asked
+            if prog.fd().is_err() {
+                prog.load()?;
+            }
+            prog.attach(&cgroup, attach_type)?;
+        }
+        tracing::info!("Attached cgroup/skb programs to {}", path.display());
+        Ok(())
+    }
+
+    /// Attribute flows to the process that created their socket, for
+    /// sockets created in the cgroup v2 directory `path` or below it
+    ///
+    /// Adds UDP flows to FLOWS, which the TCP kprobes never see, and fills
+    /// in the process of TCP flows opened in softirq. Attach at
+    /// [`CGROUP_ROOT`] to cover the whole host; sockets created before this
+    /// call are not attributed.
+    #[cfg(target_os = "linux")]
+    pub fn attach_flow_attribution(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let cgroup = std::fs::File::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open cgroup {}: {}", path.display(), e))?;
+
+        let prog: &mut CgroupSock = self
+            .bpf
+            .program_mut("sock_create")
+            .ok_or_else(|| anyhow::anyhow!("sock_create program not found in eBPF binary"))?
+            .try_into()?;
+        prog.load()?;
+        prog.attach(&cgroup)?;
+
+        let prog: &mut CgroupSkb = self
+            .bpf
+            .program_mut("flow_attribution_egress")
+            .ok_or_else(|| anyhow::anyhow!("flow_attribution_egress program not found in eBPF binary"))?
+            .try_into()?;
+        prog.load()?;
+        prog.attach(&cgroup, CgroupSkbAttachType::Egress)?;
+        tracing::info!("Attached flow attribution programs to {}", path.display());
+        Ok(())
+    }
+
+    /// Per-cgroup traffic, busiest first
+    #[cfg(target_os = "linux")]
+    pub fn read_cgroup_counters(&self) -> Result<Vec<CgroupTraffic>> {
+        let counters: aya::maps::PerCpuHashMap<_, u64, CgroupCounters> = aya::maps::PerCpuHashMap::try_from(
+            self.bpf.map("CGROUP_COUNTERS").ok_or_else(|| anyhow::anyhow!("CGROUP_COUNTERS map not found"))?,
+        )?;
+        let entries = counters.iter().filter_map(|item| item.ok()).map(|(id, per_cpu)| (id, per_cpu.to_vec()));
+        Ok(summarize_cgroups(entries, &cgroup_paths()))
+    }
+
     // Stub for non-Linux platforms
     #[cfg(not(target_os = "linux"))]
-    pub fn load_and_attach(interface: &str) -> Result<Self> {
+    pub fn load_and_attach_all(interfaces: &[String], _sizes: &MapSizes, mode: AttachMode) -> Result<Self> {
         tracing::warn!("eBPF not supported on this platform, using mock");
         Ok(Self {
-            interface: interface.to_string(),
+            interfaces: interfaces.to_vec(),
+            attach_mode: mode,
             drop_tracing_enabled: false,
             nf_tracing_enabled: false,
             flow_tracing_enabled: false,
+            sock_state_tracing_enabled: false,
+            retransmit_tracing_enabled: false,
+            rtt_tracing_enabled: false,
+            conntrack_tracing_enabled: false,
         })
     }
 
@@ -593,14 +2656,113 @@ impl EbpfManager {
         Ok(PacketCounters::default())
     }
 
+    #[cfg(not(target_os = "linux"))]
+    pub fn read_interface_counters(&self) -> Result<BTreeMap<u32, PacketCounters>> {
+        Ok(BTreeMap::new())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn read_drop_summary(&mut self) -> Result<crate::drops::DropSummary> {
+        Ok(Default::default())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn attach_interface(&mut self, interface: &str) -> Result<bool> {
+        if self.interfaces.iter().any(|i| i == interface) {
+            return Ok(false);
+        }
+        self.interfaces.push(interface.to_string());
+        Ok(true)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn probes(&self) -> Vec<ProbeState> {
+        Probe::ALL.into_iter().map(|probe| ProbeState { probe, available: false, attached: false }).collect()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_probe(&mut self, _probe: Probe, _attach: bool) -> Result<bool> {
+        anyhow::bail!("eBPF not supported on this platform")
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn forget_interface(&mut self, _index: u32, interface: &str) -> bool {
+        let before = self.interfaces.len();
+        self.interfaces.retain(|i| i != interface);
+        self.interfaces.len() != before
+    }
+
     #[cfg(not(target_os = "linux"))]
     pub fn read_flows(&self) -> Result<Vec<(FlowKey, FlowInfo)>> {
         Ok(Vec::new())
     }
 
-    /// Get the attached interface name
-    pub fn interface(&self) -> &str {
-        &self.interface
+    #[cfg(not(target_os = "linux"))]
+    pub fn read_top_talkers(&self) -> Result<Vec<TopTalker>> {
+        Ok(Vec::new())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn read_rtt(&self) -> Result<Vec<RemoteRtt>> {
+        Ok(Vec::new())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn read_icmp_rtt(&self) -> Result<Vec<RemoteRtt>> {
+        Ok(Vec::new())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn attach_cgroup(&mut self, _path: impl AsRef<std::path::Path>) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn attach_flow_attribution(&mut self, _path: impl AsRef<std::path::Path>) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn read_cgroup_counters(&self) -> Result<Vec<CgroupTraffic>> {
+        Ok(Vec::new())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn shutdown(self, _keep_pinned: bool) {}
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_tunables(&mut self, _tunables: &Tunables) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_packet_sampling(&mut self, _one_in: u32) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_large_packet_threshold(&mut self, _bytes: u32) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_source_pps_threshold(&mut self, _pps: u32) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_http_ports(&mut self, _ports: &[u16]) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn enable_enforcement(&mut self, _keys: &[BlockKey]) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_drop_filter(&mut self, _filter: &DropFilter) -> Result<()> {
+        Ok(())
     }
 }
 
@@ -615,6 +2777,145 @@ mod tests {
         assert_eq!(counters.tx_packets, 0);
     }
 
+    #[test]
+    fn test_remove_pins() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let pins = dir.path().join("sennet");
+        assert_eq!(remove_pins(&pins).unwrap(), 0);
+
+        std::fs::create_dir(&pins).unwrap();
+        for name in ["counters", "flows", "schema"] {
+            std::fs::write(pins.join(name), b"").unwrap();
+        }
+        assert_eq!(remove_pins(&pins).unwrap(), 3);
+        assert!(!pins.exists());
+    }
+
+    #[test]
+    fn test_pin_dir_for() {
+        assert_eq!(pin_dir_for(None), Path::new("/sys/fs/bpf/sennet"));
+        assert_eq!(pin_dir_for(Some("eth1")), Path::new("/sys/fs/bpf/sennet-eth1"));
+        for valid in ["eth1", "prod", "agent_2.test"] {
+            assert!(is_valid_pin_namespace(valid), "{valid}");
+        }
+        for invalid in ["", "..", "a/b", ".hidden", "with space"] {
+            assert!(!is_valid_pin_namespace(invalid), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_probe_names() {
+        for probe in Probe::ALL {
+            assert_eq!(probe.name().parse::<Probe>().unwrap(), probe);
+            assert_eq!(serde_json::to_value(probe).unwrap(), probe.name());
+        }
+        assert!("kfree_skb".parse::<Probe>().is_err());
+    }
+
+    #[test]
+    fn test_proto_counters_merge() {
+        let mut total = ProtoCounters::default();
+        total.merge(&ProtoCounters { udp_packets: 3, udp_bytes: 3000, ..Default::default() });
+        total.merge(&ProtoCounters { udp_packets: 1, udp_bytes: 100, tcp_packets: 2, ..Default::default() });
+        total.merge(&ProtoCounters { quic_packets: 2, quic_bytes: 2500, ..Default::default() });
+        assert_eq!(total.rows()[1], ("UDP", 4, 3100));
+        assert_eq!(total.rows()[2], ("QUIC", 2, 2500));
+        assert_eq!(total.rows()[0].1, 2);
+
+        // Counters from agents without the breakdown still parse
+        let old: PacketCounters = serde_json::from_str(r#"{"rxPackets":5,"rxBytes":500,"txPackets":0,"txBytes":0,"dropCount":0}"#).unwrap();
+        assert_eq!((old.rx_packets, old.rx_proto), (5, ProtoCounters::default()));
+        let before_quic: ProtoCounters = serde_json::from_str(
+            r#"{"tcpPackets":1,"tcpBytes":60,"udpPackets":0,"udpBytes":0,"icmpPackets":0,"icmpBytes":0,"otherPackets":0,"otherBytes":0}"#,
+        )
+        .unwrap();
+        assert_eq!((before_quic.tcp_packets, before_quic.quic_packets), (1, 0));
+    }
+
+    #[test]
+    fn test_packet_counters_fold_directions() {
+        let mut total = PacketCounters::default();
+        let cpu = KernelCounters { rx_packets: 2, tx_packets: 7, drop_count: 1, ..Default::default() };
+        total.add_kernel(0, &cpu);
+        total.add_kernel(1, &cpu);
+        assert_eq!((total.rx_packets, total.tx_packets, total.drop_count), (2, 7, 1));
+
+        total.add_tcp_flags(0, &TcpFlagCounters { syn: 3, rst: 1, ..Default::default() });
+        total.add_tcp_flags(0, &TcpFlagCounters { syn: 2, ..Default::default() });
+        total.add_tcp_flags(1, &TcpFlagCounters { syn_ack: 5, ..Default::default() });
+        assert_eq!(total.rx_tcp_flags, TcpFlagCounters { syn: 5, rst: 1, ..Default::default() });
+        assert_eq!(total.tx_tcp_flags.rows()[1], ("SYN-ACK", 5));
+
+        let later = TcpFlagCounters { syn: 9, rst: 1, ..Default::default() };
+        assert_eq!(later.since(&total.rx_tcp_flags), TcpFlagCounters { syn: 4, ..Default::default() });
+    }
+
+    #[test]
+    fn test_fold_interface_counters() {
+        let eth0 = vec![
+            KernelCounters { rx_packets: 2, rx_bytes: 120, tx_packets: 1, ..Default::default() },
+            KernelCounters { rx_packets: 1, tx_packets: 3, tx_bytes: 900, ..Default::default() },
+        ];
+        let eth1 = vec![KernelCounters { tx_packets: 4, ..Default::default() }];
+        let folded = fold_interface_counters(vec![(2, eth0), (3, eth1)].into_iter());
+        assert_eq!(folded.len(), 2);
+        assert_eq!((folded[&2].rx_packets, folded[&2].rx_bytes, folded[&2].tx_packets, folded[&2].tx_bytes), (3, 120, 4, 900));
+        assert_eq!((folded[&3].rx_packets, folded[&3].tx_packets), (0, 4));
+    }
+
+    #[test]
+    fn test_rank_top_talkers() {
+        let pair = |src: [u8; 4], dst: [u8; 4]| IpPair {
+            src_addr: sennet_common::ipv4_mapped(src),
+            dst_addr: sennet_common::ipv4_mapped(dst),
+        };
+        let counters = |packets, bytes| TalkerCounters { packets, bytes, last_seen_ns: 0 };
+        let talkers = rank_top_talkers([
+            (pair([10, 0, 0, 1], [10, 0, 0, 2]), counters(10, 1_000)),
+            (pair([10, 0, 0, 3], [1, 1, 1, 1]), counters(900, 1_200_000)),
+        ]);
+        assert_eq!(talkers[0].src.to_string(), "10.0.0.3");
+        assert_eq!(talkers[0].dst.to_string(), "1.1.1.1");
+        assert_eq!(talkers[1].bytes, 1_000);
+    }
+
+    #[test]
+    fn test_summarize_cgroups() {
+        let rx = |bytes| CgroupCounters { rx_packets: 1, rx_bytes: bytes, ..Default::default() };
+        let tx = |bytes| CgroupCounters { tx_packets: 1, tx_bytes: bytes, ..Default::default() };
+        let paths = [(7, "/system.slice/docker-abc123def456.scope".to_string())].into_iter().collect();
+        let cgroups = summarize_cgroups([(3, vec![rx(100)]), (7, vec![rx(500), tx(700)])], &paths);
+        assert_eq!((cgroups[0].cgroup_id, cgroups[0].rx_bytes, cgroups[0].tx_packets), (7, 500, 1));
+        assert_eq!(cgroups[0].container_id.as_deref(), Some("abc123def456"));
+        // Removed since it was counted
+        assert_eq!((cgroups[1].path.as_deref(), cgroups[1].container_id.as_deref()), (None, None));
+    }
+
+    #[test]
+    fn test_summarize_rtt() {
+        let per_cpu = |values: &[u64]| {
+            let mut hist = RttHistogram::default();
+            for &value in values {
+                hist.0.record(value);
+            }
+            hist
+        };
+        let near = sennet_common::ipv4_mapped([10, 0, 0, 1]);
+        let far = sennet_common::ipv4_mapped([1, 1, 1, 1]);
+        let rtts = summarize_rtt([
+            (near, vec![per_cpu(&[200]), per_cpu(&[])]),
+            (far, vec![per_cpu(&[30_000, 30_000]), per_cpu(&[90_000])]),
+            (sennet_common::ipv4_mapped([10, 0, 0, 9]), vec![per_cpu(&[])]),
+        ]);
+        assert_eq!(rtts.len(), 2);
+        assert_eq!((rtts[0].remote.to_string(), rtts[0].samples), ("1.1.1.1".to_string(), 3));
+        assert_eq!(rtts[0].mean_ms, 50.0);
+        // Percentiles fall in the bucket holding the true value
+        assert!((16.384..32.768).contains(&rtts[0].p50_ms), "{:?}", rtts[0]);
+        assert!((65.536..131.072).contains(&rtts[0].p99_ms), "{:?}", rtts[0]);
+        assert_eq!(rtts[1].samples, 1);
+    }
+
     #[test]
     fn test_drop_reason_str() {
         assert_eq!(drop_reason_str(7), "NETFILTER_DROP");
@@ -635,12 +2936,170 @@ mod tests {
         assert_eq!(nf_verdict_str(1), "ACCEPT");
     }
 
+    #[test]
+    fn test_format_addr() {
+        assert_eq!(format_addr(&sennet_common::ipv4_mapped([192, 168, 1, 10])), "192.168.1.10");
+        let v6 = "2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap().octets();
+        assert_eq!(format_addr(&v6), "2001:db8::1");
+
+        let packet = PacketEvent { size: 9216, eth_proto: 0x86DD, protocol: 6, src_addr: v6, dst_addr: v6, ..Default::default() };
+        assert_eq!(describe_packet(&packet), "9216 B TCP 2001:db8::1 → 2001:db8::1");
+        let packet = PacketEvent { src_port: 40000, dst_port: 443, ..packet };
+        assert_eq!(describe_packet(&packet), "9216 B TCP [2001:db8::1]:40000 → [2001:db8::1]:443");
+        let arp = PacketEvent { size: 9216, eth_proto: 0x0806, ..Default::default() };
+        assert_eq!(describe_packet(&arp), "9216 B eth=ARP");
+        let flood = PacketEvent { event_type: 2, pps: 52000, eth_proto: 0x0800, protocol: 17, src_addr: v6, dst_addr: v6, ..Default::default() };
+        assert!(flood.is_anomaly());
+        assert_eq!(describe_packet(&flood), "52000 pps from 2001:db8::1 (last: UDP → 2001:db8::1)");
+
+        let retransmit = RetransmitEvent {
+            src_addr: sennet_common::ipv4_mapped([10, 0, 0, 5]),
+            dst_addr: v6,
+            src_port: 40000,
+            dst_port: 443,
+            state: 1,
+            ..Default::default()
+        };
+        assert_eq!(describe_retransmit(&retransmit), "10.0.0.5:40000 → [2001:db8::1]:443 ESTABLISHED");
+
+        let drop = DropEvent {
+            ip_protocol: 17,
+            src_addr: sennet_common::ipv4_mapped([192, 0, 2, 1]),
+            dst_addr: sennet_common::ipv4_mapped([10, 0, 0, 7]),
+            packet_src_port: 5353,
+            packet_dst_port: 53,
+            ..Default::default()
+        };
+        assert_eq!(describe_drop_packet(&drop).as_deref(), Some("UDP 192.0.2.1:5353 → 10.0.0.7:53"));
+        let icmp = DropEvent { ip_protocol: 58, src_addr: v6, dst_addr: v6, ..Default::default() };
+        assert_eq!(describe_drop_packet(&icmp).as_deref(), Some("ICMPv6 2001:db8::1 → 2001:db8::1"));
+        assert_eq!(describe_drop_packet(&DropEvent::default()), None);
+    }
+
+    #[test]
+    fn test_describe_conntrack() {
+        use sennet_common::{ct_event, ct_status, ipv4_mapped};
+
+        let new = ConntrackEvent {
+            src_addr: ipv4_mapped([10, 0, 0, 5]),
+            dst_addr: ipv4_mapped([1, 1, 1, 1]),
+            reply_src_addr: ipv4_mapped([1, 1, 1, 1]),
+            reply_dst_addr: ipv4_mapped([10, 0, 0, 5]),
+            src_port: 40000,
+            dst_port: 443,
+            reply_src_port: 443,
+            reply_dst_port: 40000,
+            protocol: 6,
+            event_type: ct_event::NEW,
+            ..Default::default()
+        };
+        assert_eq!(describe_conntrack(&new), "new TCP 10.0.0.5:40000 → 1.1.1.1:443");
+
+        // Masqueraded, torn down after replies
+        let destroyed = ConntrackEvent {
+            reply_dst_addr: ipv4_mapped([192, 0, 2, 1]),
+            reply_dst_port: 61000,
+            status: ct_status::SEEN_REPLY | ct_status::ASSURED | ct_status::SRC_NAT,
+            event_type: ct_event::DESTROY,
+            ..new
+        };
+        assert_eq!(
+            describe_conntrack(&destroyed),
+            "destroy TCP 10.0.0.5:40000 → 1.1.1.1:443 (reply 1.1.1.1:443 → 192.0.2.1:61000) ASSURED"
+        );
+    }
+
+    #[test]
+    fn test_hex_lines() {
+        let bytes: Vec<u8> = b"\x45\x00\x00\x3cGET / HTTP/1.1\r\n".to_vec();
+        let lines = hex_lines(&bytes);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "0x0000:  4500 003c 4745 5420 2f20 4854 5450 2f31  E..<GET / HTTP/1");
+        assert_eq!(lines[1], "0x0010:  2e31 0d0a                                .1..");
+        assert!(hex_lines(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_dns_name() {
+        assert_eq!(dns_name(b"\x03www\x07example\x03com\x00\x00\x01"), "www.example.com");
+        assert_eq!(dns_name(b"\x00"), ".");
+        // Cut short by the event's name buffer
+        assert_eq!(dns_name(b"\x03www\x07exam"), "www.exam…");
+
+        let mut query = DnsEvent { qtype: 28, server_addr: sennet_common::ipv4_mapped([1, 1, 1, 1]), ..Default::default() };
+        query.qname[..13].copy_from_slice(b"\x07example\x03com\x00");
+        assert_eq!(describe_dns(&query), "AAAA example.com → 1.1.1.1");
+        let response = DnsEvent { is_response: 1, rcode: 3, latency_ns: 12_340_000, ..query };
+        assert_eq!(describe_dns(&response), "AAAA example.com NXDOMAIN from 1.1.1.1 in 12.3ms");
+    }
+
+    #[test]
+    fn test_describe_http() {
+        let mut event = HttpEvent {
+            method: sennet_common::http_method::POST,
+            src_addr: sennet_common::ipv4_mapped([10, 0, 0, 5]),
+            dst_addr: sennet_common::ipv4_mapped([10, 0, 1, 7]),
+            src_port: 40000,
+            dst_port: 8080,
+            path_len: 9,
+            ..Default::default()
+        };
+        event.path[..9].copy_from_slice(b"/v1/users");
+        assert_eq!(describe_http(&event), "POST /v1/users 10.0.0.5:40000 → 10.0.1.7:8080");
+        event.truncated = 1;
+        assert!(describe_http(&event).starts_with("POST /v1/users… "));
+    }
+
+    #[test]
+    fn test_elf_section() {
+        // ELF header, then section data, then three section headers:
+        // null, "sennet_schema" and the name table
+        let names = b"\0sennet_schema\0.shstrtab\0";
+        let payload = 0x1122_3344_5566_7788u64.to_le_bytes();
+        let mut elf = vec![0u8; 64];
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        let payload_at = elf.len();
+        elf.extend_from_slice(&payload);
+        let names_at = elf.len();
+        elf.extend_from_slice(names);
+        let shoff = elf.len();
+        let section = |name: u32, offset: usize, size: usize| {
+            let mut header = vec![0u8; 64];
+            header[..4].copy_from_slice(&name.to_le_bytes());
+            header[24..32].copy_from_slice(&(offset as u64).to_le_bytes());
+            header[32..40].copy_from_slice(&(size as u64).to_le_bytes());
+            header
+        };
+        elf.extend(section(0, 0, 0));
+        elf.extend(section(1, payload_at, payload.len()));
+        elf.extend(section(15, names_at, names.len()));
+        elf[40..48].copy_from_slice(&(shoff as u64).to_le_bytes());
+        elf[58..60].copy_from_slice(&64u16.to_le_bytes());
+        elf[60..62].copy_from_slice(&3u16.to_le_bytes());
+        elf[62..64].copy_from_slice(&2u16.to_le_bytes());
+
+        assert_eq!(elf_section(&elf, "sennet_schema"), Some(&payload[..]));
+        assert_eq!(elf_section(&elf, "maps"), None);
+        assert_eq!(elf_section(&elf[..100], "sennet_schema"), None);
+    }
+
+    #[test]
+    fn test_loss_tracker_deltas() {
+        let mut tracker = LossTracker { last: None };
+        // No baseline yet: nothing to compare against
+        assert_eq!(tracker.advance(Some([5, 0, 0, 0, 0, 0, 0, 0, 0, 0])), [0; 10]);
+        assert_eq!(tracker.advance(Some([8, 0, 2, 0, 1, 4, 6, 1, 0, 3])), [3, 0, 2, 0, 1, 4, 6, 1, 0, 3]);
+        // A failed read keeps the previous baseline
+        assert_eq!(tracker.advance(None), [0; 10]);
+        assert_eq!(tracker.advance(Some([9, 0, 2, 0, 1, 4, 6, 1, 0, 3])), [1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
     // This test only works on non-Linux (mock mode) or requires root on Linux
     #[test]
     #[cfg(not(target_os = "linux"))]
     fn test_mock_manager() {
         let manager = EbpfManager::load_and_attach("lo").unwrap();
-        assert_eq!(manager.interface(), "lo");
+        assert_eq!(manager.interfaces(), ["lo"]);
         let counters = manager.read_counters().unwrap();
         assert_eq!(counters.rx_packets, 0);
     }
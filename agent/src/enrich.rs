@@ -0,0 +1,455 @@
+//! Tiered Enrichment
+//!
+//! Every event updates the cheap per-window counters. Expensive context —
+//! ASN lookups, container attribution from /proc — is only gathered for
+//! events that clear a severity threshold and a per-key budget, so a drop
+//! storm costs one lookup per distinct cause per window instead of one per
+//! packet and CPU usage stays flat.
+//!
+//! The lookups themselves are [`EventEnricher`]s, chosen by name in
+//! `pipeline.enrichers` (see `plugins`).
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::asn::AsnDb;
+use crate::ebpf::{dns_rcode_str, drop_reason_str, flow_event_type_str, ipv4_addr, nf_hook_str, tcp_state_str};
+use crate::events::RawEvent;
+use crate::k8s::SharedK8s;
+
+/// How much an event deserves a closer look
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    /// Classify from the raw event alone (no lookups)
+    pub fn of(raw: &RawEvent) -> Self {
+        match raw {
+            RawEvent::Drop(e) => match e.reason {
+                5 | 7 => Severity::High,    // SOCKET_FILTER, NETFILTER_DROP
+                2 | 37 => Severity::Medium, // NO_SOCKET, IP_OUTNOROUTES
+                _ => Severity::Low,
+            },
+            RawEvent::Netfilter(e) if e.is_drop() => Severity::High,
+            RawEvent::Netfilter(_) => Severity::Low,
+            RawEvent::Rst(_) => Severity::Medium,
+            RawEvent::Flow(_) => Severity::Low,
+            RawEvent::Packet(e) if e.is_anomaly() => Severity::Medium,
+            RawEvent::Packet(_) => Severity::Low,
+            RawEvent::Retransmit(_) => Severity::Low,
+            // SERVFAIL = 2
+            RawEvent::Dns(e) if e.is_response == 1 && e.rcode == 2 => Severity::Medium,
+            RawEvent::Dns(_) => Severity::Low,
+            RawEvent::Conntrack(_) => Severity::Low,
+            RawEvent::Payload(_) => Severity::Low,
+            RawEvent::Http(_) => Severity::Low,
+        }
+    }
+}
+
+/// Events sharing a key share one enrichment budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum GateKey {
+    Drop { reason: u32, ifindex: u32 },
+    Netfilter { hook: u8 },
+    Rst { remote: u32, port: u16 },
+    Flow { pid: u32 },
+    Packet { ifindex: u32 },
+    Retransmit { remote: [u8; 16] },
+    Dns { server: [u8; 16], rcode: u8 },
+    Conntrack { remote: [u8; 16], event_type: u8 },
+}
+
+impl From<&RawEvent> for GateKey {
+    fn from(raw: &RawEvent) -> Self {
+        match raw {
+            RawEvent::Drop(e) => GateKey::Drop { reason: e.reason, ifindex: e.ifindex },
+            RawEvent::Netfilter(e) => GateKey::Netfilter { hook: e.hook },
+            RawEvent::Rst(e) if e.direction == 0 => GateKey::Rst { remote: e.src_ip, port: e.dst_port },
+            RawEvent::Rst(e) => GateKey::Rst { remote: e.dst_ip, port: e.src_port },
+            RawEvent::Flow(e) => GateKey::Flow { pid: e.pid },
+            RawEvent::Packet(e) => GateKey::Packet { ifindex: e.ifindex },
+            RawEvent::Payload(e) => GateKey::Packet { ifindex: e.ifindex },
+            RawEvent::Http(e) => GateKey::Packet { ifindex: e.ifindex },
+            RawEvent::Retransmit(e) => GateKey::Retransmit { remote: e.dst_addr },
+            RawEvent::Dns(e) => GateKey::Dns { server: e.server_addr, rcode: e.rcode },
+            RawEvent::Conntrack(e) => GateKey::Conntrack { remote: e.dst_addr, event_type: e.event_type },
+        }
+    }
+}
+
+/// Decides which events get expensive enrichment
+pub struct EnrichGate {
+    min_severity: Severity,
+    per_key: u32,
+    seen: HashMap<GateKey, u32>,
+}
+
+impl EnrichGate {
+    /// Distinct keys tracked per window; events with new keys beyond this are skipped
+    pub const MAX_KEYS: usize = 4096;
+
+    pub fn new(min_severity: Severity, per_key: u32) -> Self {
+        Self {
+            min_severity,
+            per_key,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Whether `raw` should be enriched; counts against its key's budget
+    pub fn admit(&mut self, raw: &RawEvent) -> bool {
+        if Severity::of(raw) < self.min_severity || self.per_key == 0 {
+            return false;
+        }
+        let key = GateKey::from(raw);
+        let at_capacity = self.seen.len() >= Self::MAX_KEYS;
+        match self.seen.get_mut(&key) {
+            Some(n) if *n >= self.per_key => false,
+            Some(n) => {
+                *n += 1;
+                true
+            }
+            None if at_capacity => false,
+            None => {
+                self.seen.insert(key, 1);
+                true
+            }
+        }
+    }
+
+    /// Start a new window with fresh budgets
+    pub fn reset(&mut self) {
+        self.seen.clear();
+    }
+}
+
+/// Context gathered for events that passed the gate
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeepContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<Ipv4Addr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_name: Option<String>,
+    /// ISO 3166 country code of the remote address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Fields set by enrichers outside this crate
+    #[serde(flatten, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// One source of context for events that passed the gate
+///
+/// Enrichers run in the aggregate task, in configured order, so they must
+/// answer from memory rather than block on I/O. Each fills in what it knows
+/// and leaves the rest; later enrichers see what earlier ones found.
+pub trait EventEnricher: Send {
+    /// Name used in config.yaml and logs
+    fn name(&self) -> &str;
+
+    fn enrich(&self, raw: &RawEvent, context: &mut DeepContext);
+}
+
+/// Runs the configured enrichers over an event
+pub struct DeepEnricher {
+    enrichers: Vec<Box<dyn EventEnricher>>,
+}
+
+impl Default for DeepEnricher {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl DeepEnricher {
+    /// ASN lookups (with a database) and container attribution
+    pub fn new(asn_db: Option<Arc<AsnDb>>) -> Self {
+        let mut enrichers: Vec<Box<dyn EventEnricher>> = Vec::new();
+        if let Some(db) = asn_db {
+            enrichers.push(Box::new(AsnEnricher(db)));
+        }
+        enrichers.push(Box::new(ContainerEnricher));
+        Self { enrichers }
+    }
+
+    /// Exactly these enrichers, in this order
+    pub fn with_enrichers(enrichers: Vec<Box<dyn EventEnricher>>) -> Self {
+        Self { enrichers }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.enrichers.iter().map(|e| e.name()).collect()
+    }
+
+    pub fn enrich(&self, raw: &RawEvent) -> DeepContext {
+        let mut context = DeepContext {
+            remote: remote_addr(raw),
+            ..Default::default()
+        };
+        for enricher in &self.enrichers {
+            enricher.enrich(raw, &mut context);
+        }
+        context
+    }
+}
+
+/// Address of the other end, for events that record one
+pub fn remote_addr(raw: &RawEvent) -> Option<Ipv4Addr> {
+    match raw {
+        // Flow addresses are in network byte order
+        RawEvent::Flow(e) if e.direction == 1 => Some(ipv4_addr(e.dst_ip)),
+        RawEvent::Flow(e) => Some(ipv4_addr(e.src_ip)),
+        // RST addresses are in host byte order; direction 0 = inbound
+        RawEvent::Rst(e) if e.direction == 0 => Some(Ipv4Addr::from(e.src_ip)),
+        RawEvent::Rst(e) => Some(Ipv4Addr::from(e.dst_ip)),
+        // Lookups are IPv4-only; direction 0 = ingress
+        RawEvent::Packet(e) => {
+            let remote = if e.direction == 0 { &e.src_addr } else { &e.dst_addr };
+            sennet_common::mapped_ipv4(remote).map(Ipv4Addr::from)
+        }
+        RawEvent::Retransmit(e) => sennet_common::mapped_ipv4(&e.dst_addr).map(Ipv4Addr::from),
+        RawEvent::Dns(e) => sennet_common::mapped_ipv4(&e.server_addr).map(Ipv4Addr::from),
+        RawEvent::Conntrack(e) => sennet_common::mapped_ipv4(&e.dst_addr).map(Ipv4Addr::from),
+        RawEvent::Payload(e) => {
+            let remote = if e.direction == 0 { &e.src_addr } else { &e.dst_addr };
+            sennet_common::mapped_ipv4(remote).map(Ipv4Addr::from)
+        }
+        RawEvent::Http(e) => {
+            let remote = if e.direction == 0 { &e.src_addr } else { &e.dst_addr };
+            sennet_common::mapped_ipv4(remote).map(Ipv4Addr::from)
+        }
+        RawEvent::Drop(_) | RawEvent::Netfilter(_) => None,
+    }
+}
+
+/// Process behind the event, for events that record one
+fn event_pid(raw: &RawEvent) -> Option<u32> {
+    match raw {
+        RawEvent::Flow(e) if e.pid != 0 => Some(e.pid),
+        _ => None,
+    }
+}
+
+/// ASN and AS name of the remote address (`asn`)
+pub struct AsnEnricher(pub Arc<AsnDb>);
+
+impl EventEnricher for AsnEnricher {
+    fn name(&self) -> &str {
+        "asn"
+    }
+
+    fn enrich(&self, _raw: &RawEvent, context: &mut DeepContext) {
+        if let Some(record) = context.remote.and_then(|ip| self.0.lookup(ip)) {
+            context.asn = Some(record.asn);
+            context.as_name = Some(record.name.clone());
+        }
+    }
+}
+
+/// Country of the remote address, from the ip2asn table's country column (`geoip`)
+pub struct GeoIpEnricher(pub Arc<AsnDb>);
+
+impl EventEnricher for GeoIpEnricher {
+    fn name(&self) -> &str {
+        "geoip"
+    }
+
+    fn enrich(&self, _raw: &RawEvent, context: &mut DeepContext) {
+        let record = context.remote.and_then(|ip| self.0.lookup(ip));
+        // ip2asn writes "None" for ranges without a registered country
+        if let Some(country) = record.map(|r| r.country.as_str()).filter(|c| !c.is_empty() && *c != "None") {
+            context.country = Some(country.to_string());
+        }
+    }
+}
+
+/// Container of the process behind the event, from its cgroup (`container`)
+pub struct ContainerEnricher;
+
+impl EventEnricher for ContainerEnricher {
+    fn name(&self) -> &str {
+        "container"
+    }
+
+    fn enrich(&self, raw: &RawEvent, context: &mut DeepContext) {
+        if context.container_id.is_none() {
+            context.container_id = event_pid(raw).and_then(crate::k8s::container_id_from_pid);
+        }
+    }
+}
+
+/// Pod and namespace of the event's container (`k8s`)
+///
+/// Answers from the pod cache of the daemon's Kubernetes watcher; until the
+/// watcher has connected (or outside a cluster) it adds nothing.
+pub struct K8sEnricher {
+    k8s: SharedK8s,
+}
+
+impl K8sEnricher {
+    pub fn new(k8s: SharedK8s) -> Self {
+        Self { k8s }
+    }
+}
+
+impl EventEnricher for K8sEnricher {
+    fn name(&self) -> &str {
+        "k8s"
+    }
+
+    fn enrich(&self, raw: &RawEvent, context: &mut DeepContext) {
+        let Some(manager) = self.k8s.get() else {
+            return;
+        };
+        if context.container_id.is_none() {
+            context.container_id = event_pid(raw).and_then(crate::k8s::container_id_from_pid);
+        }
+        if let Some(pod) = context.container_id.as_deref().and_then(|id| manager.cached_pod(id)) {
+            context.pod = Some(pod.name);
+            context.namespace = Some(pod.namespace);
+        }
+    }
+}
+
+/// High-severity event with its enrichment, as reported in summaries
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotableEvent {
+    pub severity: Severity,
+    /// drop, netfilter, flow or rst
+    pub kind: &'static str,
+    /// Drop reason, netfilter hook, flow event type or RST direction
+    pub detail: &'static str,
+    pub count: u64,
+    pub timestamp_ns: u64,
+    /// RFC 3339 wall-clock time
+    pub time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    #[serde(flatten)]
+    pub context: DeepContext,
+}
+
+impl NotableEvent {
+    pub fn new(raw: &RawEvent, count: u64, interface: Option<&str>, time: SystemTime, context: DeepContext) -> Self {
+        let (kind, detail) = match raw {
+            RawEvent::Drop(e) => ("drop", drop_reason_str(e.reason)),
+            RawEvent::Netfilter(e) => ("netfilter", nf_hook_str(e.hook)),
+            RawEvent::Flow(e) => ("flow", flow_event_type_str(e.event_type)),
+            RawEvent::Rst(e) if e.direction == 0 => ("rst", "in"),
+            RawEvent::Rst(_) => ("rst", "out"),
+            RawEvent::Packet(e) if e.is_anomaly() => ("packet", "rate_anomaly"),
+            RawEvent::Packet(_) => ("packet", "large"),
+            RawEvent::Retransmit(e) => ("retransmit", tcp_state_str(e.state)),
+            RawEvent::Dns(e) if e.is_response == 0 => ("dns", "query"),
+            RawEvent::Dns(e) => ("dns", dns_rcode_str(e.rcode)),
+            RawEvent::Conntrack(e) if e.event_type == sennet_common::ct_event::NEW => ("conntrack", "new"),
+            RawEvent::Conntrack(_) => ("conntrack", "destroy"),
+            RawEvent::Payload(_) => ("payload", "snippet"),
+            RawEvent::Http(e) => ("http", sennet_common::http_method_str(e.method)),
+        };
+        Self {
+            severity: Severity::of(raw),
+            kind,
+            detail,
+            count,
+            timestamp_ns: raw.timestamp_ns(),
+            time: crate::clock::rfc3339(time),
+            interface: interface.map(str::to_string),
+            context,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ebpf::{DropEvent, RstEvent};
+
+    fn drop_event(reason: u32) -> RawEvent {
+        RawEvent::Drop(DropEvent { reason, ifindex: 2, ..Default::default() })
+    }
+
+    #[test]
+    fn test_severity() {
+        assert_eq!(Severity::of(&drop_event(7)), Severity::High);
+        assert_eq!(Severity::of(&drop_event(2)), Severity::Medium);
+        assert_eq!(Severity::of(&drop_event(16)), Severity::Low);
+        assert!(Severity::High > Severity::Low);
+    }
+
+    #[test]
+    fn test_gate_budgets_per_key() {
+        let mut gate = EnrichGate::new(Severity::Medium, 3);
+        let admitted = (0..1000).filter(|_| gate.admit(&drop_event(7))).count();
+        assert_eq!(admitted, 3);
+        // A different cause has its own budget; low severity never passes
+        assert!(gate.admit(&drop_event(2)));
+        assert!(!gate.admit(&drop_event(16)));
+
+        gate.reset();
+        assert!(gate.admit(&drop_event(7)));
+    }
+
+    #[test]
+    fn test_deep_enrich_rst_asn() {
+        let db = AsnDb::parse("1.1.1.0\t1.1.1.255\t13335\tUS\tCLOUDFLARENET\n");
+        let enricher = DeepEnricher::new(Some(Arc::new(db)));
+        let rst = RawEvent::Rst(RstEvent {
+            src_ip: u32::from(Ipv4Addr::new(1, 1, 1, 1)),
+            direction: 0,
+            ..Default::default()
+        });
+        let ctx = enricher.enrich(&rst);
+        assert_eq!(ctx.remote, Some(Ipv4Addr::new(1, 1, 1, 1)));
+        assert_eq!(ctx.asn, Some(13335));
+        assert_eq!(enricher.enrich(&drop_event(7)), DeepContext::default());
+    }
+
+    #[test]
+    fn test_enrichers_run_in_order() {
+        struct Tag;
+        impl EventEnricher for Tag {
+            fn name(&self) -> &str {
+                "tag"
+            }
+            fn enrich(&self, _raw: &RawEvent, context: &mut DeepContext) {
+                let known = context.country.clone().unwrap_or_default();
+                context.attributes.insert("region".to_string(), format!("{}-east", known));
+            }
+        }
+
+        let db = Arc::new(AsnDb::parse("1.1.1.0\t1.1.1.255\t13335\tUS\tCLOUDFLARENET\n"));
+        let enricher = DeepEnricher::with_enrichers(vec![Box::new(GeoIpEnricher(db)), Box::new(Tag)]);
+        assert_eq!(enricher.names(), ["geoip", "tag"]);
+        let rst = RawEvent::Rst(RstEvent {
+            dst_ip: u32::from(Ipv4Addr::new(1, 1, 1, 1)),
+            direction: 1,
+            ..Default::default()
+        });
+        let ctx = enricher.enrich(&rst);
+        assert_eq!(ctx.country.as_deref(), Some("US"));
+        assert_eq!(ctx.asn, None);
+        assert_eq!(ctx.attributes["region"], "US-east");
+        let json = serde_json::to_value(&ctx).unwrap();
+        assert_eq!(json["region"], "US-east");
+    }
+}
@@ -0,0 +1,421 @@
+//! Ring Buffer Event Records
+//!
+//! Record types the eBPF programs write to ring buffers, re-exported from
+//! sennet-common, and the helpers used to decode them. Records are plain
+//! `repr(C)` integers, so zerocopy can validate them by size and alignment
+//! and hand out a reference into the ring buffer instead of copying.
+//!
+//! Every record starts with an [`EventHeader`] naming its kind, layout
+//! version and length. [`decode`] dispatches on the header rather than on
+//! the ring the record came from, so kinds can share a ring, and records of
+//! a kind or version this build doesn't know are skipped instead of being
+//! misread.
+//!
+//! sennet-common's `serde` feature lets the control socket stream records to
+//! the CLI, and its `zerocopy` feature lets [`view`] borrow them.
+
+use serde::{Deserialize, Serialize};
+use zerocopy::{FromBytes, Immutable, KnownLayout};
+
+pub use sennet_common::{
+    ConntrackEvent, DnsEvent, DropEvent, EventHeader, FlowEvent, HttpEvent, NetfilterEvent, PacketEvent, PayloadEvent,
+    RetransmitEvent, RingEvent, RstEvent, DNS_NAME_LEN, EVENT_HEADER_LEN, EVENT_VERSION, HTTP_PATH_LEN,
+    PAYLOAD_SNIPPET_MAX,
+};
+
+/// Borrow a record in place
+///
+/// Ring buffer records are 8-byte aligned, so this succeeds for anything
+/// read from the kernel. Returns None if `bytes` is too short or misaligned.
+pub fn view<T: FromBytes + KnownLayout + Immutable>(bytes: &[u8]) -> Option<&T> {
+    T::ref_from_prefix(bytes).ok().map(|(record, _)| record)
+}
+
+/// Copy a record out of `bytes` regardless of alignment; None if too short
+pub fn read<T: FromBytes>(bytes: &[u8]) -> Option<T> {
+    T::read_from_prefix(bytes).ok().map(|(record, _)| record)
+}
+
+/// Split a ring buffer record into its header and event bytes; None if
+/// it is shorter than its header says
+pub fn split(bytes: &[u8]) -> Option<(EventHeader, &[u8])> {
+    let header: EventHeader = read(bytes)?;
+    let body = bytes.get(EVENT_HEADER_LEN..EVENT_HEADER_LEN + header.len as usize)?;
+    Some((header, body))
+}
+
+/// Whether `header` introduces a `T` this build can read: same kind and
+/// version, and at least as long (newer builds may append fields)
+fn readable<T: RingEvent>(header: &EventHeader) -> bool {
+    header.kind as usize == T::KIND
+        && header.version == T::VERSION
+        && header.len as usize >= std::mem::size_of::<T>()
+}
+
+/// Borrow the `T` in a ring buffer record, past its header
+///
+/// None if the record holds another kind or an unknown version, or is
+/// truncated or misaligned.
+pub fn view_event<T: RingEvent + FromBytes + KnownLayout + Immutable>(bytes: &[u8]) -> Option<&T> {
+    let (header, body) = split(bytes)?;
+    readable::<T>(&header).then(|| view(body))?
+}
+
+/// Copy the `T` out of a ring buffer record; None as for [`view_event`]
+pub fn read_event<T: RingEvent + FromBytes>(bytes: &[u8]) -> Option<T> {
+    let (header, body) = split(bytes)?;
+    readable::<T>(&header).then(|| read(body))?
+}
+
+/// Why a ring buffer record could not be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Undecodable {
+    /// Shorter than its header, or than the length the header gives
+    Truncated,
+    /// Kind index this build doesn't know
+    UnknownKind(u8),
+    /// Known kind written with another layout version, or shorter than this
+    /// build's record
+    Layout { kind: RingKind, version: u8, len: u16 },
+}
+
+impl std::fmt::Display for Undecodable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Undecodable::Truncated => write!(f, "truncated ring buffer record"),
+            Undecodable::UnknownKind(kind) => write!(
+                f,
+                "unknown event kind {} (this build knows {}); the eBPF object is newer than the agent",
+                kind,
+                RingKind::ALL.len()
+            ),
+            Undecodable::Layout { kind, version, len } => write!(
+                f,
+                "{} event with layout version {} and {} bytes, expected version {} and at least {} bytes; \
+                 the eBPF object and the agent come from different builds",
+                kind.name(),
+                version,
+                len,
+                EVENT_VERSION,
+                kind.record_len()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Undecodable {}
+
+/// Decode a ring buffer record by its header, saying why when it can't be
+pub fn try_decode(bytes: &[u8]) -> Result<RawEvent, Undecodable> {
+    let (header, _) = split(bytes).ok_or(Undecodable::Truncated)?;
+    let kind = *RingKind::ALL.get(header.kind as usize).ok_or(Undecodable::UnknownKind(header.kind))?;
+    let raw = match kind {
+        RingKind::Drop => read_event(bytes).map(RawEvent::Drop),
+        RingKind::Netfilter => read_event(bytes).map(RawEvent::Netfilter),
+        RingKind::Flow => read_event(bytes).map(RawEvent::Flow),
+        RingKind::Rst => read_event(bytes).map(RawEvent::Rst),
+        RingKind::Packet => read_event(bytes).map(RawEvent::Packet),
+        RingKind::Retransmit => read_event(bytes).map(RawEvent::Retransmit),
+        RingKind::Dns => read_event(bytes).map(RawEvent::Dns),
+        RingKind::Conntrack => read_event(bytes).map(RawEvent::Conntrack),
+        RingKind::Payload => read_event(bytes).map(RawEvent::Payload),
+        RingKind::Http => read_event(bytes).map(RawEvent::Http),
+    };
+    raw.ok_or(Undecodable::Layout { kind, version: header.version, len: header.len })
+}
+
+/// Decode a ring buffer record by its header
+///
+/// None for kinds and versions this build doesn't know, and for truncated
+/// records; [`try_decode`] says which.
+pub fn decode(bytes: &[u8]) -> Option<RawEvent> {
+    try_decode(bytes).ok()
+}
+
+/// Raw event as read from a kernel ring buffer
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum RawEvent {
+    Drop(DropEvent),
+    Netfilter(NetfilterEvent),
+    Flow(FlowEvent),
+    Rst(RstEvent),
+    Packet(PacketEvent),
+    Retransmit(RetransmitEvent),
+    Dns(DnsEvent),
+    Conntrack(ConntrackEvent),
+    Payload(PayloadEvent),
+    Http(HttpEvent),
+}
+
+impl RawEvent {
+    /// Interface index the event was observed on, if known
+    pub fn ifindex(&self) -> Option<u32> {
+        match self {
+            RawEvent::Drop(e) => Some(e.ifindex),
+            RawEvent::Netfilter(e) => Some(e.ifindex_in).filter(|&i| i != 0).or(Some(e.ifindex_out)),
+            RawEvent::Rst(e) => Some(e.ifindex),
+            RawEvent::Packet(e) => Some(e.ifindex),
+            RawEvent::Dns(e) => Some(e.ifindex),
+            RawEvent::Payload(e) => Some(e.ifindex),
+            RawEvent::Http(e) => Some(e.ifindex),
+            RawEvent::Flow(_) | RawEvent::Retransmit(_) | RawEvent::Conntrack(_) => None,
+        }
+        .filter(|&i| i != 0)
+    }
+
+    /// Kernel events this record stands for (N if the kernel sampled 1 in N)
+    pub fn sample_weight(&self) -> u64 {
+        let rate = match self {
+            RawEvent::Drop(e) => e.sample_rate,
+            RawEvent::Netfilter(e) => e.sample_rate,
+            RawEvent::Flow(e) => e.sample_rate,
+            RawEvent::Rst(e) => e.sample_rate,
+            RawEvent::Packet(e) => e.sample_rate,
+            RawEvent::Retransmit(e) => e.sample_rate,
+            RawEvent::Dns(e) => e.sample_rate,
+            RawEvent::Conntrack(e) => e.sample_rate,
+            RawEvent::Payload(e) => e.sample_rate,
+            RawEvent::Http(e) => e.sample_rate,
+        };
+        u64::from(rate.max(1))
+    }
+
+    /// Ring buffer the record was read from
+    pub fn kind(&self) -> RingKind {
+        match self {
+            RawEvent::Drop(_) => RingKind::Drop,
+            RawEvent::Netfilter(_) => RingKind::Netfilter,
+            RawEvent::Flow(_) => RingKind::Flow,
+            RawEvent::Rst(_) => RingKind::Rst,
+            RawEvent::Packet(_) => RingKind::Packet,
+            RawEvent::Retransmit(_) => RingKind::Retransmit,
+            RawEvent::Dns(_) => RingKind::Dns,
+            RawEvent::Conntrack(_) => RingKind::Conntrack,
+            RawEvent::Payload(_) => RingKind::Payload,
+            RawEvent::Http(_) => RingKind::Http,
+        }
+    }
+
+    /// Kernel timestamp in nanoseconds
+    pub fn timestamp_ns(&self) -> u64 {
+        match self {
+            RawEvent::Drop(e) => e.timestamp_ns,
+            RawEvent::Netfilter(e) => e.timestamp_ns,
+            RawEvent::Flow(e) => e.timestamp_ns,
+            RawEvent::Rst(e) => e.timestamp_ns,
+            RawEvent::Packet(e) => e.timestamp_ns,
+            RawEvent::Retransmit(e) => e.timestamp_ns,
+            RawEvent::Dns(e) => e.timestamp_ns,
+            RawEvent::Conntrack(e) => e.timestamp_ns,
+            RawEvent::Payload(e) => e.timestamp_ns,
+            RawEvent::Http(e) => e.timestamp_ns,
+        }
+    }
+}
+
+/// Kind of ring buffer, used to decode records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingKind {
+    Drop,
+    Netfilter,
+    Flow,
+    Rst,
+    Packet,
+    Retransmit,
+    Dns,
+    Conntrack,
+    Payload,
+    Http,
+}
+
+impl RingKind {
+    /// Map name in the eBPF object
+    pub fn map_name(&self) -> &'static str {
+        match self {
+            RingKind::Drop => "DROP_EVENTS",
+            RingKind::Netfilter => "NF_EVENTS",
+            RingKind::Flow => "FLOW_EVENTS",
+            RingKind::Rst => "RST_EVENTS",
+            RingKind::Packet => "EVENTS",
+            RingKind::Retransmit => "RETRANSMIT_EVENTS",
+            RingKind::Dns => "DNS_EVENTS",
+            RingKind::Conntrack => "CT_EVENTS",
+            RingKind::Payload => "PAYLOAD_EVENTS",
+            RingKind::Http => "HTTP_EVENTS",
+        }
+    }
+
+    pub const ALL: [RingKind; 10] = [
+        RingKind::Drop,
+        RingKind::Netfilter,
+        RingKind::Flow,
+        RingKind::Rst,
+        RingKind::Packet,
+        RingKind::Retransmit,
+        RingKind::Dns,
+        RingKind::Conntrack,
+        RingKind::Payload,
+        RingKind::Http,
+    ];
+
+    /// Position in `ALL`; matches `sennet_common::event_kind`
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// File name of the map pin under `ebpf::PIN_PATH`
+    pub fn pin_name(&self) -> &'static str {
+        match self {
+            RingKind::Drop => "drop_events",
+            RingKind::Netfilter => "nf_events",
+            RingKind::Flow => "flow_events",
+            RingKind::Rst => "rst_events",
+            RingKind::Packet => "events",
+            RingKind::Retransmit => "retransmit_events",
+            RingKind::Dns => "dns_events",
+            RingKind::Conntrack => "ct_events",
+            RingKind::Payload => "payload_events",
+            RingKind::Http => "http_events",
+        }
+    }
+
+    /// Bytes of this build's record, after the header
+    pub fn record_len(&self) -> usize {
+        match self {
+            RingKind::Drop => std::mem::size_of::<DropEvent>(),
+            RingKind::Netfilter => std::mem::size_of::<NetfilterEvent>(),
+            RingKind::Flow => std::mem::size_of::<FlowEvent>(),
+            RingKind::Rst => std::mem::size_of::<RstEvent>(),
+            RingKind::Packet => std::mem::size_of::<PacketEvent>(),
+            RingKind::Retransmit => std::mem::size_of::<RetransmitEvent>(),
+            RingKind::Dns => std::mem::size_of::<DnsEvent>(),
+            RingKind::Conntrack => std::mem::size_of::<ConntrackEvent>(),
+            RingKind::Payload => std::mem::size_of::<PayloadEvent>(),
+            RingKind::Http => std::mem::size_of::<HttpEvent>(),
+        }
+    }
+
+    /// Short name used in metrics labels
+    pub fn name(&self) -> &'static str {
+        match self {
+            RingKind::Drop => "drop",
+            RingKind::Netfilter => "netfilter",
+            RingKind::Flow => "flow",
+            RingKind::Rst => "rst",
+            RingKind::Packet => "packet",
+            RingKind::Retransmit => "retransmit",
+            RingKind::Dns => "dns",
+            RingKind::Conntrack => "conntrack",
+            RingKind::Payload => "payload",
+            RingKind::Http => "http",
+        }
+    }
+
+    /// Whether the daemon consumes this ring; packet contents only go to
+    /// `sennet trace --payload` and request paths (which can carry tokens)
+    /// to `sennet http`, which read the pinned rings as root
+    pub fn daemon_reads(&self) -> bool {
+        !matches!(self, RingKind::Payload | RingKind::Http)
+    }
+
+    /// Kind with the given short name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ring buffer records are 8-byte aligned; mimic that in tests
+    #[repr(C, align(8))]
+    struct Aligned([u8; 256]);
+
+    /// A zeroed `T` record behind its header
+    fn record<T: RingEvent>() -> Aligned {
+        let mut buf = Aligned([0u8; 256]);
+        buf.0[..EVENT_HEADER_LEN].copy_from_slice(&EventHeader::of::<T>().to_bytes());
+        buf
+    }
+
+    #[test]
+    fn test_decode_short_record() {
+        assert!(decode(&[0u8; 4]).is_none());
+        let buf = record::<DropEvent>();
+        let len = EVENT_HEADER_LEN + std::mem::size_of::<DropEvent>();
+        assert!(matches!(decode(&buf.0[..len]), Some(RawEvent::Drop(_))));
+        assert!(decode(&buf.0[..len - 1]).is_none());
+    }
+
+    #[test]
+    fn test_decode_dispatches_on_header() {
+        let buf = record::<ConntrackEvent>();
+        assert!(matches!(decode(&buf.0), Some(RawEvent::Conntrack(_))));
+        assert!(view_event::<DropEvent>(&buf.0).is_none());
+        assert!(view_event::<ConntrackEvent>(&buf.0).is_some());
+
+        // Unknown kinds and versions are skipped, not misread
+        let mut unknown = record::<DropEvent>();
+        unknown.0[0] = RingKind::ALL.len() as u8;
+        assert!(decode(&unknown.0).is_none());
+        let mut newer = record::<DropEvent>();
+        newer.0[1] = 2;
+        assert!(decode(&newer.0).is_none());
+
+        // A longer record of the same version has fields appended
+        let mut longer = record::<DnsEvent>();
+        let len = std::mem::size_of::<DnsEvent>() + 8;
+        longer.0[2..4].copy_from_slice(&(len as u16).to_ne_bytes());
+        assert!(matches!(decode(&longer.0[..EVENT_HEADER_LEN + len]), Some(RawEvent::Dns(_))));
+    }
+
+    #[test]
+    fn test_try_decode_explains_mismatch() {
+        assert_eq!(try_decode(&[0u8; 4]).unwrap_err(), Undecodable::Truncated);
+
+        let mut unknown = record::<DropEvent>();
+        unknown.0[0] = 200;
+        assert_eq!(try_decode(&unknown.0).unwrap_err(), Undecodable::UnknownKind(200));
+
+        let mut newer = record::<FlowEvent>();
+        newer.0[1] = EVENT_VERSION + 1;
+        let err = try_decode(&newer.0).unwrap_err();
+        let len = std::mem::size_of::<FlowEvent>() as u16;
+        assert_eq!(err, Undecodable::Layout { kind: RingKind::Flow, version: EVENT_VERSION + 1, len });
+        assert!(err.to_string().contains("different builds"), "{}", err);
+
+        // A shorter record of the right version is a layout mismatch too
+        let mut shorter = record::<DnsEvent>();
+        shorter.0[2..4].copy_from_slice(&8u16.to_ne_bytes());
+        assert!(matches!(try_decode(&shorter.0), Err(Undecodable::Layout { kind: RingKind::Dns, len: 8, .. })));
+    }
+
+    #[test]
+    fn test_view_borrows_in_place() {
+        let mut buf = Aligned([0u8; 256]);
+        buf.0[8..12].copy_from_slice(&7u32.to_ne_bytes());
+
+        let event = view::<DropEvent>(&buf.0).unwrap();
+        assert_eq!(event.reason, 7);
+        assert_eq!(event as *const DropEvent as *const u8, buf.0.as_ptr());
+
+        // Misaligned input can't be borrowed, but can still be copied
+        let shifted = &buf.0[1..];
+        assert!(view::<DropEvent>(shifted).is_none());
+        assert!(read::<DropEvent>(shifted).is_some());
+        assert!(view::<DropEvent>(&buf.0[..4]).is_none());
+    }
+
+    #[test]
+    fn test_dns_event_round_trip() {
+        let mut event = DnsEvent { id: 0x1234, rcode: 3, is_response: 1, ..Default::default() };
+        event.qname[..4].copy_from_slice(b"\x02io\x00");
+        let json = serde_json::to_string(&RawEvent::Dns(event)).unwrap();
+        let Ok(RawEvent::Dns(back)) = serde_json::from_str::<RawEvent>(&json) else {
+            panic!("{}", json);
+        };
+        assert_eq!((back.id, back.rcode, back.qname), (0x1234, 3, event.qname));
+    }
+}
@@ -0,0 +1,896 @@
+//! Filter Expressions
+//!
+//! One expression language selects events for `trace`, flows for `flows`
+//! and kernel events for the daemon pipeline (`pipeline.filter`):
+//!
+//! ```text
+//! dst == 10.0.0.0/24 && proto == tcp && port == 443 && reason != NOT_SPECIFIED
+//! ```
+//!
+//! Comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`, and `~` for "contains")
+//! combine with `&&`, `||`, `!` and parentheses, or `and`, `or`, `not`.
+//! `host` and `port` match either end of a connection. A comparison on a
+//! field an event type never records, like `proto` on a kfree_skb drop, is
+//! skipped; one on a field this particular event lacks, like the address of
+//! a drop with no owning socket, is false.
+//!
+//! A parsed [`Filter`] is a userspace predicate over any [`Subject`].
+//! [`Filter::drop_filter`] compiles the terms the kernel can check into the
+//! DROP_FILTER map entry, so unwanted drops never reach a ring buffer.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::net::Ipv4Addr;
+
+use crate::ebpf::{comm_to_string, drop_reason_str, ipv4_addr, DropFilter, FlowInfo, FlowKey, NfFamily, TraceRule};
+use crate::events::RawEvent;
+use crate::rollup::RollupKey;
+
+/// A field expressions can compare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Src,
+    Dst,
+    /// Either address
+    Host,
+    Sport,
+    Dport,
+    /// Either port
+    Port,
+    /// L4 protocol: tcp, udp, icmp or a number
+    Proto,
+    /// ipv4 or ipv6
+    Family,
+    /// Kernel drop reason, by name or number
+    Reason,
+    Pid,
+    Comm,
+}
+
+impl Field {
+    pub const ALL: [Field; 11] = [
+        Field::Src,
+        Field::Dst,
+        Field::Host,
+        Field::Sport,
+        Field::Dport,
+        Field::Port,
+        Field::Proto,
+        Field::Family,
+        Field::Reason,
+        Field::Pid,
+        Field::Comm,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Src => "src",
+            Field::Dst => "dst",
+            Field::Host => "host",
+            Field::Sport => "sport",
+            Field::Dport => "dport",
+            Field::Port => "port",
+            Field::Proto => "proto",
+            Field::Family => "family",
+            Field::Reason => "reason",
+            Field::Pid => "pid",
+            Field::Comm => "comm",
+        }
+    }
+
+    /// The single-ended fields a field stands for
+    fn ends(self) -> &'static [Field] {
+        match self {
+            Field::Host => &[Field::Src, Field::Dst],
+            Field::Port => &[Field::Sport, Field::Dport],
+            Field::Src => &[Field::Src],
+            Field::Dst => &[Field::Dst],
+            Field::Sport => &[Field::Sport],
+            Field::Dport => &[Field::Dport],
+            Field::Proto => &[Field::Proto],
+            Field::Family => &[Field::Family],
+            Field::Reason => &[Field::Reason],
+            Field::Pid => &[Field::Pid],
+            Field::Comm => &[Field::Comm],
+        }
+    }
+}
+
+/// Value of a field in a record
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<'a> {
+    Addr(Ipv4Addr),
+    Num(u64),
+    Text(Cow<'a, str>),
+}
+
+/// Something a filter can be evaluated against
+pub trait Subject {
+    /// Value of a single-ended field (not `host` or `port`), None if this
+    /// record lacks it
+    fn get(&self, field: Field) -> Option<Value<'_>>;
+
+    /// Whether records of this type record `field` at all
+    fn carries(&self, field: Field) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+/// Right-hand side of a comparison, checked against its field when parsed
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    /// Address and prefix length; the address is already masked
+    Net(u32, u8),
+    Num(u64),
+    /// Lowercased
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Cmp(Field, Op, Literal),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// A parsed filter expression; the empty filter passes everything
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    text: String,
+    expr: Option<Expr>,
+}
+
+fn prefix_mask(prefix: u8) -> u32 {
+    match prefix {
+        0 => 0,
+        p => u32::MAX << (32 - p.min(32)),
+    }
+}
+
+impl Filter {
+    pub fn parse(text: &str) -> Result<Self> {
+        let tokens = tokenize(text).with_context(|| format!("Invalid filter `{}`", text))?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = if parser.tokens.is_empty() {
+            None
+        } else {
+            let expr = parser.or().with_context(|| format!("Invalid filter `{}`", text))?;
+            if let Some(token) = parser.tokens.get(parser.pos) {
+                bail!("Invalid filter `{}`: unexpected {}", text, token);
+            }
+            Some(expr)
+        };
+        Ok(Self { text: text.trim().to_string(), expr })
+    }
+
+    /// Filter passing what both pass
+    pub fn and(self, other: Filter) -> Filter {
+        let group = |text: &str| {
+            if text.contains("||") || text.to_lowercase().contains(" or ") {
+                format!("({})", text)
+            } else {
+                text.to_string()
+            }
+        };
+        match (self.expr, other.expr) {
+            (Some(a), Some(b)) => Filter {
+                text: format!("{} && {}", group(&self.text), group(&other.text)),
+                expr: Some(Expr::And(Box::new(a), Box::new(b))),
+            },
+            (Some(a), None) => Filter { text: self.text, expr: Some(a) },
+            (None, expr) => Filter { text: other.text, expr },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expr.is_none()
+    }
+
+    pub fn matches<S: Subject + ?Sized>(&self, subject: &S) -> bool {
+        self.expr.as_ref().is_none_or(|expr| expr.eval(subject))
+    }
+
+    /// Fail if the expression uses a field `what` has no use for
+    pub fn check_fields(&self, fields: &[Field], what: &str) -> Result<()> {
+        let mut used = Vec::new();
+        if let Some(expr) = &self.expr {
+            expr.fields(&mut used);
+        }
+        match used.into_iter().find(|field| !fields.contains(field)) {
+            Some(field) => bail!(
+                "`{}` doesn't apply to {} (fields: {})",
+                field.name(),
+                what,
+                fields.iter().map(|f| f.name()).collect::<Vec<_>>().join(", ")
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Kernel prefilter for drops: the top-level `&&` terms on drop reason,
+    /// `src`, `dst`, `sport` and `dport` that the kernel can check
+    ///
+    /// Everything else passes, so the result lets through a superset of
+    /// what [`Filter::matches`] accepts.
+    pub fn drop_filter(&self) -> DropFilter {
+        let mut kernel = DropFilter::default();
+        let mut terms = Vec::new();
+        if let Some(expr) = &self.expr {
+            expr.conjuncts(&mut terms);
+        }
+        for term in terms {
+            if let Some(allowed) = term.reason_set() {
+                for (skip, allowed) in kernel.skip_reasons.iter_mut().zip(allowed) {
+                    *skip |= !allowed;
+                }
+                continue;
+            }
+            match term {
+                Expr::Not(inner) => {
+                    if let Some(denied) = inner.reason_set() {
+                        for (skip, denied) in kernel.skip_reasons.iter_mut().zip(denied) {
+                            *skip |= denied;
+                        }
+                    }
+                }
+                Expr::Cmp(Field::Reason, Op::Ne, Literal::Num(reason)) if *reason < 256 => {
+                    kernel.skip_reasons[(*reason / 64) as usize] |= 1 << (reason % 64);
+                }
+                Expr::Cmp(Field::Src, Op::Eq, Literal::Net(addr, prefix)) if kernel.src_mask == 0 => {
+                    kernel.src_addr = *addr;
+                    kernel.src_mask = prefix_mask(*prefix);
+                }
+                Expr::Cmp(Field::Dst, Op::Eq, Literal::Net(addr, prefix)) if kernel.dst_mask == 0 => {
+                    kernel.dst_addr = *addr;
+                    kernel.dst_mask = prefix_mask(*prefix);
+                }
+                Expr::Cmp(Field::Sport, Op::Eq, Literal::Num(port @ 1..=65535)) => kernel.src_port = *port as u16,
+                Expr::Cmp(Field::Dport, Op::Eq, Literal::Num(port @ 1..=65535)) => kernel.dst_port = *port as u16,
+                _ => {}
+            }
+        }
+        kernel
+    }
+
+    /// Kernel prefilter for `sennet trace`: [`Filter::drop_filter`] plus a
+    /// top-level `proto ==` term, lapsing at `expires_ns`
+    pub fn trace_rule(&self, expires_ns: u64) -> TraceRule {
+        let mut terms = Vec::new();
+        if let Some(expr) = &self.expr {
+            expr.conjuncts(&mut terms);
+        }
+        let protocol = terms.iter().find_map(|term| match term {
+            Expr::Cmp(Field::Proto, Op::Eq, Literal::Num(proto @ 1..=255)) => Some(*proto as u8),
+            _ => None,
+        });
+        TraceRule {
+            filter: self.drop_filter(),
+            protocol: protocol.unwrap_or(0),
+            expires_ns,
+            ..Default::default()
+        }
+    }
+}
+
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Serialize for Filter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+impl<'de> Deserialize<'de> for Filter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Filter::parse(&text).map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+impl Expr {
+    fn eval<S: Subject + ?Sized>(&self, subject: &S) -> bool {
+        match self {
+            Expr::Cmp(field, op, literal) => {
+                let ends = field.ends();
+                if !ends.iter().any(|end| subject.carries(*end)) {
+                    return true;
+                }
+                let mut values = ends.iter().filter_map(|end| subject.get(*end)).peekable();
+                if values.peek().is_none() {
+                    return false;
+                }
+                // `host != X`: neither end is X; `host == X`: either is
+                if *op == Op::Ne {
+                    values.all(|value| compare(&value, *op, literal))
+                } else {
+                    values.any(|value| compare(&value, *op, literal))
+                }
+            }
+            Expr::Not(inner) => !inner.eval(subject),
+            Expr::And(a, b) => a.eval(subject) && b.eval(subject),
+            Expr::Or(a, b) => a.eval(subject) || b.eval(subject),
+        }
+    }
+
+    fn fields(&self, out: &mut Vec<Field>) {
+        match self {
+            Expr::Cmp(field, _, _) => out.push(*field),
+            Expr::Not(inner) => inner.fields(out),
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.fields(out);
+                b.fields(out);
+            }
+        }
+    }
+
+    fn conjuncts<'a>(&'a self, out: &mut Vec<&'a Expr>) {
+        match self {
+            Expr::And(a, b) => {
+                a.conjuncts(out);
+                b.conjuncts(out);
+            }
+            term => out.push(term),
+        }
+    }
+
+    /// Drop reasons (bitmap, reasons below 256) if this is `reason == A` or
+    /// a disjunction of such terms
+    fn reason_set(&self) -> Option<[u64; 4]> {
+        match self {
+            Expr::Cmp(Field::Reason, Op::Eq, Literal::Num(reason)) if *reason < 256 => {
+                let mut set = [0u64; 4];
+                set[(*reason / 64) as usize] |= 1 << (reason % 64);
+                Some(set)
+            }
+            Expr::Or(a, b) => {
+                let (a, b) = (a.reason_set()?, b.reason_set()?);
+                Some([a[0] | b[0], a[1] | b[1], a[2] | b[2], a[3] | b[3]])
+            }
+            _ => None,
+        }
+    }
+}
+
+fn compare(value: &Value, op: Op, literal: &Literal) -> bool {
+    match (value, literal) {
+        (Value::Addr(addr), Literal::Net(net, prefix)) => {
+            let inside = u32::from(*addr) & prefix_mask(*prefix) == *net;
+            if op == Op::Ne { !inside } else { inside }
+        }
+        (Value::Num(n), Literal::Num(want)) => match op {
+            Op::Eq => n == want,
+            Op::Ne => n != want,
+            Op::Lt => n < want,
+            Op::Le => n <= want,
+            Op::Gt => n > want,
+            Op::Ge => n >= want,
+            Op::Contains => false,
+        },
+        (Value::Text(text), Literal::Text(want)) => match op {
+            Op::Eq => text.eq_ignore_ascii_case(want),
+            Op::Ne => !text.eq_ignore_ascii_case(want),
+            Op::Contains => text.to_lowercase().contains(want.as_str()),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Code of a drop reason given by name (as trace prints it) or number
+pub fn reason_code(value: &str) -> Result<u64> {
+    if let Ok(code) = value.parse() {
+        return Ok(code);
+    }
+    (0..256u32)
+        .find(|&code| {
+            let name = drop_reason_str(code);
+            name != "UNKNOWN" && name.eq_ignore_ascii_case(value)
+        })
+        .map(u64::from)
+        .with_context(|| format!("unknown drop reason `{}`", value))
+}
+
+fn literal(field: Field, op: Op, value: &str) -> Result<Literal> {
+    let equality = matches!(op, Op::Eq | Op::Ne);
+    let lower = value.to_lowercase();
+    match field {
+        Field::Src | Field::Dst | Field::Host | Field::Proto | Field::Family | Field::Reason if !equality => {
+            bail!("`{}` can only be compared with == or !=", field.name())
+        }
+        Field::Src | Field::Dst | Field::Host => {
+            let (addr, prefix) = match value.split_once('/') {
+                Some((addr, prefix)) => (addr, prefix.parse().ok().filter(|p| *p <= 32).with_context(|| format!("invalid prefix length in `{}`", value))?),
+                None => (value, 32),
+            };
+            let addr: Ipv4Addr = addr.parse().with_context(|| format!("`{}` is not an IPv4 address or network", value))?;
+            Ok(Literal::Net(u32::from(addr) & prefix_mask(prefix), prefix))
+        }
+        Field::Sport | Field::Dport | Field::Port | Field::Pid if op == Op::Contains => {
+            bail!("`{}` is a number; ~ compares text", field.name())
+        }
+        Field::Sport | Field::Dport | Field::Port | Field::Pid => {
+            Ok(Literal::Num(value.parse().with_context(|| format!("`{}` is not a number", value))?))
+        }
+        Field::Proto => Ok(Literal::Num(match lower.as_str() {
+            "icmp" => 1,
+            "tcp" => 6,
+            "udp" => 17,
+            other => other.parse().with_context(|| format!("unknown protocol `{}` (tcp, udp, icmp or a number)", value))?,
+        })),
+        Field::Family => match lower.as_str() {
+            "ipv4" | "ipv6" => Ok(Literal::Text(lower)),
+            _ => bail!("family is ipv4 or ipv6, not `{}`", value),
+        },
+        Field::Reason => Ok(Literal::Num(reason_code(value)?)),
+        Field::Comm if matches!(op, Op::Eq | Op::Ne | Op::Contains) => Ok(Literal::Text(lower)),
+        Field::Comm => bail!("`comm` can only be compared with ==, != or ~"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    And,
+    Or,
+    Op(Op),
+    Word(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => f.write_str("`(`"),
+            Token::Close => f.write_str("`)`"),
+            Token::Not => f.write_str("`!`"),
+            Token::And => f.write_str("`&&`"),
+            Token::Or => f.write_str("`||`"),
+            Token::Op(_) => f.write_str("operator"),
+            Token::Word(word) => write!(f, "`{}`", word),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    const SPECIAL: &str = "()&|!=<>~\"'";
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        chars.next();
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' | '|' => {
+                if chars.next_if_eq(&c).is_none() {
+                    bail!("expected `{}{}`", c, c);
+                }
+                if c == '&' { Token::And } else { Token::Or }
+            }
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '=' => {
+                chars.next_if_eq(&'=');
+                Token::Op(Op::Eq)
+            }
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '~' => Token::Op(Op::Contains),
+            '"' | '\'' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(ch) => word.push(ch),
+                        None => bail!("unterminated quote"),
+                    }
+                }
+                Token::Word(word)
+            }
+            _ => {
+                let mut word = c.to_string();
+                while let Some(ch) = chars.next_if(|ch| !ch.is_whitespace() && !SPECIAL.contains(*ch)) {
+                    word.push(ch);
+                }
+                match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent: `||` binds looser than `&&`, which binds looser than `!`
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.tokens.get(self.pos) == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::Open) {
+            let expr = self.or()?;
+            if !self.eat(&Token::Close) {
+                bail!("missing `)`");
+            }
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let name = match self.next() {
+            Some(Token::Word(name)) => name,
+            Some(token) => bail!("expected a field name, found {}", token),
+            None => bail!("expected a field name at the end"),
+        };
+        let field = Field::ALL
+            .into_iter()
+            .find(|field| field.name().eq_ignore_ascii_case(&name))
+            .with_context(|| {
+                let names: Vec<&str> = Field::ALL.iter().map(|f| f.name()).collect();
+                format!("unknown field `{}` (fields: {})", name, names.join(", "))
+            })?;
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            _ => bail!("expected ==, !=, <, <=, >, >= or ~ after `{}`", name),
+        };
+        let value = match self.next() {
+            Some(Token::Word(value)) => value,
+            _ => bail!("expected a value to compare `{}` with", name),
+        };
+        Ok(Expr::Cmp(field, op, literal(field, op, &value)?))
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Subjects
+
+/// Address or port of a connection tuple in the eBPF encoding
+fn endpoint(field: Field, src_ip: u32, dst_ip: u32, src_port: u16, dst_port: u16) -> Option<Value<'static>> {
+    match field {
+        Field::Src => Some(Value::Addr(ipv4_addr(src_ip))),
+        Field::Dst => Some(Value::Addr(ipv4_addr(dst_ip))),
+        Field::Sport => Some(Value::Num(src_port.into())),
+        Field::Dport => Some(Value::Num(dst_port.into())),
+        _ => None,
+    }
+}
+
+fn family(name: &'static str) -> Option<Value<'static>> {
+    Some(Value::Text(Cow::Borrowed(name)))
+}
+
+impl Subject for RawEvent {
+    fn carries(&self, field: Field) -> bool {
+        use Field::*;
+        match self {
+            // kfree_skb reports the Ethernet protocol and owning socket, and
+            // the IP protocol when it could parse the packet's headers
+            RawEvent::Drop(e) => {
+                matches!(field, Src | Dst | Sport | Dport | Family | Reason) || (field == Proto && e.has_packet_tuple())
+            }
+            RawEvent::Netfilter(_) => field == Family,
+            RawEvent::Flow(_) => field != Reason,
+            RawEvent::Rst(_) => matches!(field, Src | Dst | Sport | Dport | Proto | Family),
+            RawEvent::Packet(_) => matches!(field, Src | Dst | Sport | Dport | Proto | Family),
+            RawEvent::Retransmit(_) => matches!(field, Src | Dst | Sport | Dport | Proto | Family),
+            RawEvent::Dns(_) => matches!(field, Src | Dst | Sport | Dport | Proto | Family),
+            RawEvent::Conntrack(_) => matches!(field, Src | Dst | Sport | Dport | Proto | Family),
+            RawEvent::Payload(_) => matches!(field, Src | Dst | Sport | Dport | Proto | Family),
+            RawEvent::Http(_) => matches!(field, Src | Dst | Sport | Dport | Proto | Family),
+        }
+    }
+
+    fn get(&self, field: Field) -> Option<Value<'_>> {
+        match (self, field) {
+            (RawEvent::Drop(e), Field::Reason) => Some(Value::Num(e.reason.into())),
+            (RawEvent::Drop(e), Field::Family) => match e.protocol {
+                0x0800 => family("ipv4"),
+                0x86DD => family("ipv6"),
+                _ => None,
+            },
+            (RawEvent::Drop(e), Field::Proto) if e.has_packet_tuple() => Some(Value::Num(e.ip_protocol.into())),
+            // The packet's own headers, else the owning socket's tuple
+            (RawEvent::Drop(e), Field::Src) if e.has_packet_tuple() => {
+                sennet_common::mapped_ipv4(&e.src_addr).map(|a| Value::Addr(a.into()))
+            }
+            (RawEvent::Drop(e), Field::Dst) if e.has_packet_tuple() => {
+                sennet_common::mapped_ipv4(&e.dst_addr).map(|a| Value::Addr(a.into()))
+            }
+            (RawEvent::Drop(e), Field::Sport) if e.has_packet_tuple() => Some(Value::Num(e.packet_src_port.into())),
+            (RawEvent::Drop(e), Field::Dport) if e.has_packet_tuple() => Some(Value::Num(e.packet_dst_port.into())),
+            (RawEvent::Drop(e), field) if e.has_tuple() => endpoint(field, e.src_ip, e.dst_ip, e.src_port, e.dst_port),
+            (RawEvent::Drop(_), _) => None,
+            (RawEvent::Netfilter(e), Field::Family) => match e.nf_family() {
+                Some(NfFamily::Ipv4) => family("ipv4"),
+                Some(NfFamily::Ipv6) => family("ipv6"),
+                _ => None,
+            },
+            (RawEvent::Netfilter(_), _) => None,
+            (RawEvent::Flow(e), Field::Proto) => Some(Value::Num(e.protocol.into())),
+            (RawEvent::Flow(_), Field::Family) => family("ipv4"),
+            (RawEvent::Flow(e), Field::Pid) => Some(Value::Num(e.pid.into())),
+            (RawEvent::Flow(e), Field::Comm) => Some(Value::Text(Cow::Owned(comm_to_string(&e.comm)))),
+            (RawEvent::Flow(e), field) => endpoint(field, e.src_ip, e.dst_ip, e.src_port, e.dst_port),
+            (RawEvent::Rst(_), Field::Proto) => Some(Value::Num(6)),
+            (RawEvent::Rst(_), Field::Family) => family("ipv4"),
+            (RawEvent::Rst(e), field) => endpoint(field, e.src_ip, e.dst_ip, e.src_port, e.dst_port),
+            (RawEvent::Packet(e), Field::Proto) => Some(Value::Num(e.protocol.into())),
+            (RawEvent::Packet(e), Field::Family) => match e.eth_proto {
+                0x0800 => family("ipv4"),
+                0x86DD => family("ipv6"),
+                _ => None,
+            },
+            // Address literals are IPv4, so IPv6 packets never match `src`/`dst`
+            (RawEvent::Packet(e), Field::Src) => sennet_common::mapped_ipv4(&e.src_addr).map(|a| Value::Addr(a.into())),
+            (RawEvent::Packet(e), Field::Dst) => sennet_common::mapped_ipv4(&e.dst_addr).map(|a| Value::Addr(a.into())),
+            (RawEvent::Packet(e), Field::Sport) => Some(Value::Num(e.src_port.into())),
+            (RawEvent::Packet(e), Field::Dport) => Some(Value::Num(e.dst_port.into())),
+            (RawEvent::Packet(_), _) => None,
+            (RawEvent::Retransmit(_), Field::Proto) => Some(Value::Num(6)),
+            (RawEvent::Retransmit(e), Field::Family) => match sennet_common::mapped_ipv4(&e.src_addr) {
+                Some(_) => family("ipv4"),
+                None => family("ipv6"),
+            },
+            (RawEvent::Retransmit(e), Field::Src) => sennet_common::mapped_ipv4(&e.src_addr).map(|a| Value::Addr(a.into())),
+            (RawEvent::Retransmit(e), Field::Dst) => sennet_common::mapped_ipv4(&e.dst_addr).map(|a| Value::Addr(a.into())),
+            (RawEvent::Retransmit(e), Field::Sport) => Some(Value::Num(e.src_port.into())),
+            (RawEvent::Retransmit(e), Field::Dport) => Some(Value::Num(e.dst_port.into())),
+            (RawEvent::Retransmit(_), _) => None,
+            // Queries go from the client to port 53, responses come back
+            (RawEvent::Dns(_), Field::Proto) => Some(Value::Num(17)),
+            (RawEvent::Dns(e), Field::Family) => match sennet_common::mapped_ipv4(&e.client_addr) {
+                Some(_) => family("ipv4"),
+                None => family("ipv6"),
+            },
+            (RawEvent::Dns(e), Field::Src) => {
+                let src = if e.is_response == 0 { &e.client_addr } else { &e.server_addr };
+                sennet_common::mapped_ipv4(src).map(|a| Value::Addr(a.into()))
+            }
+            (RawEvent::Dns(e), Field::Dst) => {
+                let dst = if e.is_response == 0 { &e.server_addr } else { &e.client_addr };
+                sennet_common::mapped_ipv4(dst).map(|a| Value::Addr(a.into()))
+            }
+            (RawEvent::Dns(e), Field::Sport) => Some(Value::Num(if e.is_response == 0 { e.client_port.into() } else { 53 })),
+            (RawEvent::Dns(e), Field::Dport) => Some(Value::Num(if e.is_response == 0 { 53 } else { e.client_port.into() })),
+            (RawEvent::Dns(_), _) => None,
+            // The original direction, before NAT
+            (RawEvent::Conntrack(e), Field::Proto) => Some(Value::Num(e.protocol.into())),
+            (RawEvent::Conntrack(e), Field::Family) => match sennet_common::mapped_ipv4(&e.src_addr) {
+                Some(_) => family("ipv4"),
+                None => family("ipv6"),
+            },
+            (RawEvent::Conntrack(e), Field::Src) => sennet_common::mapped_ipv4(&e.src_addr).map(|a| Value::Addr(a.into())),
+            (RawEvent::Conntrack(e), Field::Dst) => sennet_common::mapped_ipv4(&e.dst_addr).map(|a| Value::Addr(a.into())),
+            (RawEvent::Conntrack(e), Field::Sport) => Some(Value::Num(e.src_port.into())),
+            (RawEvent::Conntrack(e), Field::Dport) => Some(Value::Num(e.dst_port.into())),
+            (RawEvent::Conntrack(_), _) => None,
+            (RawEvent::Payload(e), Field::Proto) => Some(Value::Num(e.protocol.into())),
+            (RawEvent::Payload(e), Field::Family) => match sennet_common::mapped_ipv4(&e.src_addr) {
+                Some(_) => family("ipv4"),
+                None => family("ipv6"),
+            },
+            (RawEvent::Payload(e), Field::Src) => sennet_common::mapped_ipv4(&e.src_addr).map(|a| Value::Addr(a.into())),
+            (RawEvent::Payload(e), Field::Dst) => sennet_common::mapped_ipv4(&e.dst_addr).map(|a| Value::Addr(a.into())),
+            (RawEvent::Payload(e), Field::Sport) => Some(Value::Num(e.src_port.into())),
+            (RawEvent::Payload(e), Field::Dport) => Some(Value::Num(e.dst_port.into())),
+            (RawEvent::Payload(_), _) => None,
+            (RawEvent::Http(_), Field::Proto) => Some(Value::Num(6)),
+            (RawEvent::Http(e), Field::Family) => match sennet_common::mapped_ipv4(&e.src_addr) {
+                Some(_) => family("ipv4"),
+                None => family("ipv6"),
+            },
+            (RawEvent::Http(e), Field::Src) => sennet_common::mapped_ipv4(&e.src_addr).map(|a| Value::Addr(a.into())),
+            (RawEvent::Http(e), Field::Dst) => sennet_common::mapped_ipv4(&e.dst_addr).map(|a| Value::Addr(a.into())),
+            (RawEvent::Http(e), Field::Sport) => Some(Value::Num(e.src_port.into())),
+            (RawEvent::Http(e), Field::Dport) => Some(Value::Num(e.dst_port.into())),
+            (RawEvent::Http(_), _) => None,
+        }
+    }
+}
+
+/// An entry of the flow table
+impl Subject for (FlowKey, FlowInfo) {
+    fn carries(&self, field: Field) -> bool {
+        field != Field::Reason
+    }
+
+    fn get(&self, field: Field) -> Option<Value<'_>> {
+        let (key, info) = self;
+        match field {
+            Field::Proto => Some(Value::Num(key.protocol.into())),
+            Field::Family => family("ipv4"),
+            Field::Pid => Some(Value::Num(info.pid.into())),
+            Field::Comm => Some(Value::Text(Cow::Owned(comm_to_string(&info.comm)))),
+            field => endpoint(field, key.src_ip, key.dst_ip, key.src_port, key.dst_port),
+        }
+    }
+}
+
+/// A flow rollup records the remote end only: the destination of outbound
+/// flows, the source of inbound ones
+impl Subject for RollupKey {
+    fn carries(&self, field: Field) -> bool {
+        let outbound = self.direction == 1;
+        match field {
+            Field::Src | Field::Sport => !outbound,
+            Field::Dst | Field::Dport => outbound,
+            Field::Reason | Field::Pid => false,
+            _ => true,
+        }
+    }
+
+    fn get(&self, field: Field) -> Option<Value<'_>> {
+        match field {
+            Field::Src | Field::Dst => Some(Value::Addr(self.remote)),
+            Field::Sport | Field::Dport => Some(Value::Num(self.remote_port.into())),
+            Field::Proto => Some(Value::Num(self.protocol.into())),
+            Field::Family => family("ipv4"),
+            Field::Comm => Some(Value::Text(Cow::Borrowed(self.process.as_str()))),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{DropEvent, NetfilterEvent, PacketEvent};
+
+    fn drop(reason: u32, dst: [u8; 4], dst_port: u16) -> RawEvent {
+        RawEvent::Drop(DropEvent {
+            reason,
+            protocol: 0x0800,
+            src_ip: u32::from(Ipv4Addr::new(192, 168, 1, 20)),
+            dst_ip: u32::from(Ipv4Addr::from(dst)),
+            src_port: 50000,
+            dst_port,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_expression_matches_events() {
+        let filter = Filter::parse("dst == 10.0.0.0/24 && proto == tcp && port == 443 && reason != NOT_SPECIFIED").unwrap();
+        assert!(filter.matches(&drop(2, [10, 0, 0, 7], 443)));
+        assert!(!filter.matches(&drop(2, [10, 0, 1, 7], 443)));
+        assert!(!filter.matches(&drop(2, [10, 0, 0, 7], 80)));
+        assert!(!filter.matches(&drop(1, [10, 0, 0, 7], 443)));
+        // No owning socket: the address can't match
+        assert!(!filter.matches(&RawEvent::Drop(DropEvent { reason: 2, ..Default::default() })));
+        // ...unless the kernel parsed the packet's own headers
+        let parsed = DropEvent {
+            reason: 2,
+            ip_protocol: 6,
+            src_addr: sennet_common::ipv4_mapped([192, 0, 2, 1]),
+            dst_addr: sennet_common::ipv4_mapped([10, 0, 0, 7]),
+            packet_src_port: 40000,
+            packet_dst_port: 443,
+            ..Default::default()
+        };
+        assert!(filter.matches(&RawEvent::Drop(parsed)));
+        assert!(!filter.matches(&RawEvent::Drop(DropEvent { ip_protocol: 17, ..parsed })));
+        // Large packets carry the ports from their TCP/UDP header
+        let packet = PacketEvent {
+            eth_proto: 0x0800,
+            protocol: 6,
+            dst_addr: sennet_common::ipv4_mapped([10, 0, 0, 7]),
+            src_port: 40000,
+            dst_port: 443,
+            ..Default::default()
+        };
+        assert!(filter.matches(&RawEvent::Packet(packet)));
+        assert!(!filter.matches(&RawEvent::Packet(PacketEvent { dst_port: 80, ..packet })));
+        // Netfilter events record none of these fields
+        assert!(filter.matches(&RawEvent::Netfilter(NetfilterEvent { pf: 2, ..Default::default() })));
+
+        let filter = Filter::parse("not (reason == NO_SOCKET or reason == 7) and host != 192.168.1.20").unwrap();
+        assert!(!filter.matches(&drop(2, [10, 0, 0, 7], 443)));
+        assert!(!filter.matches(&drop(28, [10, 0, 0, 7], 443)));
+        assert!(Filter::parse("host = 192.168.1.20 && sport >= 49152").unwrap().matches(&drop(28, [8, 8, 8, 8], 53)));
+        assert!(Filter::parse("").unwrap().matches(&drop(2, [0; 4], 0)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |text: &str| format!("{:#}", Filter::parse(text).unwrap_err());
+        assert!(err("dest == 1.2.3.4").contains("unknown field `dest`"));
+        assert!(err("dst > 1.2.3.4").contains("only be compared with == or !="));
+        assert!(err("reason == NO_SUCH_THING").contains("unknown drop reason"));
+        assert!(err("port == 443 &&").contains("expected a field name"));
+        assert!(err("(port == 443").contains("missing `)`"));
+        assert!(err("port == 443 port == 80").contains("unexpected `port`"));
+        assert!(err("dst == 10.0.0.0/33").contains("invalid prefix length"));
+
+        let filter = Filter::parse("comm ~ nginx").unwrap();
+        assert!(filter.check_fields(&[Field::Pid, Field::Comm], "flows").is_ok());
+        let err = filter.check_fields(&[Field::Reason], "trace").unwrap_err().to_string();
+        assert!(err.contains("`comm` doesn't apply to trace"));
+    }
+
+    #[test]
+    fn test_drop_filter() {
+        let filter = Filter::parse("dst == 10.0.0.0/8 && dport == 443 && reason != TCP_OLD_DATA && (port == 1 || port == 2)").unwrap();
+        let kernel = filter.drop_filter();
+        assert_eq!((kernel.dst_addr, kernel.dst_mask), (0x0a00_0000, 0xff00_0000));
+        assert_eq!((kernel.src_mask, kernel.dst_port, kernel.src_port), (0, 443, 0));
+        assert_eq!(kernel.skip_reasons, [1 << 23, 0, 0, 0]);
+
+        // Only NO_SOCKET and NETFILTER_DROP get through
+        let kernel = Filter::parse("reason == NO_SOCKET || reason == 7").unwrap().drop_filter();
+        assert_eq!(kernel.skip_reasons[0], !((1 << 2) | (1 << 7)));
+        assert_eq!(kernel.skip_reasons[1..], [u64::MAX; 3]);
+
+        // A disjunction across fields can't be checked in the kernel
+        let kernel = Filter::parse("reason == NO_SOCKET || dst == 10.0.0.1").unwrap().drop_filter();
+        assert_eq!(kernel, DropFilter::default());
+    }
+
+    #[test]
+    fn test_trace_rule() {
+        let rule = Filter::parse("proto == udp && dst == 10.0.0.5").unwrap().trace_rule(99);
+        assert_eq!((rule.protocol, rule.expires_ns), (17, 99));
+        assert_eq!((rule.filter.dst_addr, rule.filter.dst_mask), (0x0a00_0005, u32::MAX));
+        assert_eq!(Filter::parse("proto == tcp || proto == udp").unwrap().trace_rule(1).protocol, 0);
+    }
+}
@@ -0,0 +1,734 @@
+//! Host Firewall Model
+//!
+//! Backend-neutral model of the live packet filter ruleset: tables, chains,
+//! rules and sets. The nftables backend fills it over netlink; hosts on
+//! iptables-legacy are read from `iptables-save` (both, if both are in use).
+//!
+//! Two features sit on top of it:
+//! - Diagnosis: `Ruleset::evaluate` walks the chains on a hook for a
+//!   hypothetical packet, so `sennet diagnose --port 443` can say
+//!   "port 443/tcp is rejected by inet filter input rule 12".
+//! - Drop correlation: rule counters are compared across NETFILTER_DROP
+//!   bursts to name the drop/reject rule that fired (`DropRuleCorrelator`).
+//!
+//! Matches the evaluator doesn't model (marks, rate limits, ...) make a rule
+//! "maybe": it is skipped, and reported if it would have dropped the packet.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+/// Table families (NFPROTO_*)
+pub mod family {
+    pub const INET: u8 = 1;
+    pub const IPV4: u8 = 2;
+    pub const ARP: u8 = 3;
+    pub const NETDEV: u8 = 5;
+    pub const BRIDGE: u8 = 7;
+    pub const IPV6: u8 = 10;
+}
+
+/// Family name as shown by `nft list ruleset`
+pub fn family_name(family: u8) -> &'static str {
+    match family {
+        family::INET => "inet",
+        family::IPV4 => "ip",
+        family::ARP => "arp",
+        family::NETDEV => "netdev",
+        family::BRIDGE => "bridge",
+        family::IPV6 => "ip6",
+        _ => "unknown",
+    }
+}
+
+/// ct state bit for a connection's first packet (IP_CT_NEW)
+const CT_STATE_NEW: u32 = 8;
+
+/// Interface names are compared as IFNAMSIZ bytes, NUL padded
+const IFNAMSIZ: usize = 16;
+
+/// Nested jumps followed before giving up (the kernel allows 16)
+const MAX_JUMP_DEPTH: usize = 16;
+
+/// Baseline older than this is re-read before it is compared
+pub const BASELINE_MAX_AGE: Duration = Duration::from_secs(1);
+
+/// Netfilter hook a base chain is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    Prerouting,
+    Input,
+    Forward,
+    Output,
+    Postrouting,
+}
+
+impl Hook {
+    /// From NF_INET_* hook number
+    pub fn from_num(num: u32) -> Option<Self> {
+        match num {
+            0 => Some(Hook::Prerouting),
+            1 => Some(Hook::Input),
+            2 => Some(Hook::Forward),
+            3 => Some(Hook::Output),
+            4 => Some(Hook::Postrouting),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hook::Prerouting => "prerouting",
+            Hook::Input => "input",
+            Hook::Forward => "forward",
+            Hook::Output => "output",
+            Hook::Postrouting => "postrouting",
+        }
+    }
+}
+
+/// What a rule (or chain policy) does with a packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Accept,
+    Drop,
+    Reject,
+    Jump(String),
+    Goto(String),
+    Return,
+    Continue,
+}
+
+impl Verdict {
+    /// Whether matching packets are discarded
+    pub fn discards(&self) -> bool {
+        matches!(self, Verdict::Drop | Verdict::Reject)
+    }
+
+    /// Past participle for diagnosis output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verdict::Accept => "accepted",
+            Verdict::Drop => "dropped",
+            Verdict::Reject => "rejected",
+            Verdict::Jump(_) | Verdict::Goto(_) | Verdict::Return | Verdict::Continue => "passed on",
+        }
+    }
+}
+
+/// Packet field a match inspects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    NfProto,
+    L4Proto,
+    SrcAddr,
+    DstAddr,
+    SrcPort,
+    DstPort,
+    InIface,
+    OutIface,
+    CtState,
+}
+
+/// Comparison operator (NFT_CMP_*)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Neq,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+/// One condition of a rule
+///
+/// Values are the field's bytes as the kernel compares them: network byte
+/// order for addresses and ports, NUL padded for interface names, host byte
+/// order for ct state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Match {
+    Cmp { field: Field, op: CmpOp, value: Vec<u8>, mask: Option<Vec<u8>> },
+    Range { field: Field, negate: bool, from: Vec<u8>, to: Vec<u8> },
+    InSet { field: Field, set: String, negate: bool },
+    /// Condition the evaluator doesn't model
+    Other(String),
+}
+
+/// Packet and byte counter of a rule
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counter {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Rule {
+    /// nftables handle, or position in the chain for iptables
+    pub handle: u64,
+    pub matches: Vec<Match>,
+    /// None if the rule has no terminal statement (log or counter only)
+    pub verdict: Option<Verdict>,
+    pub counter: Option<Counter>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Chain {
+    pub name: String,
+    /// None for regular (jump target) chains
+    pub hook: Option<Hook>,
+    pub priority: i32,
+    /// filter, nat or route
+    pub kind: String,
+    pub policy: Verdict,
+    pub rules: Vec<Rule>,
+}
+
+/// Set element; interval sets store each range as a start key and an
+/// (exclusive) end marker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetElement {
+    pub key: Vec<u8>,
+    pub interval_end: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Set {
+    pub name: String,
+    pub interval: bool,
+    pub elements: Vec<SetElement>,
+}
+
+impl Set {
+    pub fn contains(&self, value: &[u8]) -> bool {
+        if !self.interval {
+            return self.elements.iter().any(|e| value.starts_with(&e.key));
+        }
+        // The closest start or end at or below the value decides
+        self.elements
+            .iter()
+            .filter(|e| value.len() >= e.key.len() && value[..e.key.len()] >= e.key[..])
+            .max_by(|a, b| a.key.cmp(&b.key).then(b.interval_end.cmp(&a.interval_end)))
+            .is_some_and(|e| !e.interval_end)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub family: u8,
+    pub name: String,
+    pub chains: Vec<Chain>,
+    pub sets: Vec<Set>,
+}
+
+impl Table {
+    pub fn chain(&self, name: &str) -> Option<&Chain> {
+        self.chains.iter().find(|c| c.name == name)
+    }
+
+    pub fn set(&self, name: &str) -> Option<&Set> {
+        self.sets.iter().find(|s| s.name == name)
+    }
+
+    /// Whether packets of the probe's address family traverse this table
+    fn applies_to(&self, probe: &Probe) -> bool {
+        match self.family {
+            family::INET => true,
+            family::IPV4 => !probe.is_ipv6(),
+            family::IPV6 => probe.is_ipv6(),
+            _ => false,
+        }
+    }
+
+    /// Evaluate one match; None if it can't be decided for this probe
+    fn eval(&self, m: &Match, probe: &Probe) -> Option<bool> {
+        match m {
+            Match::Cmp { field, op, value, mask } => {
+                let mut actual = probe.value(*field)?;
+                if let Some(mask) = mask {
+                    actual.iter_mut().zip(mask).for_each(|(a, m)| *a &= m);
+                }
+                let actual = actual.get(..value.len())?;
+                let ord = actual.cmp(value);
+                Some(match op {
+                    CmpOp::Eq => ord.is_eq(),
+                    CmpOp::Neq => ord.is_ne(),
+                    CmpOp::Lt => ord.is_lt(),
+                    CmpOp::Lte => ord.is_le(),
+                    CmpOp::Gt => ord.is_gt(),
+                    CmpOp::Gte => ord.is_ge(),
+                })
+            }
+            Match::Range { field, negate, from, to } => {
+                let actual = probe.value(*field)?;
+                let actual = actual.get(..from.len())?;
+                Some((actual >= &from[..] && actual <= &to[..]) != *negate)
+            }
+            Match::InSet { field, set, negate } => {
+                let actual = probe.value(*field)?;
+                Some(self.set(set)?.contains(&actual) != *negate)
+            }
+            Match::Other(_) => None,
+        }
+    }
+
+    /// Run a chain; Some((verdict, rule)) if a rule ended evaluation
+    fn walk(&self, chain: &Chain, probe: &Probe, uncertain: &mut Vec<RuleRef>, depth: usize) -> Option<(Verdict, RuleRef)> {
+        if depth > MAX_JUMP_DEPTH {
+            return None;
+        }
+        for rule in &chain.rules {
+            let Some(verdict) = &rule.verdict else { continue };
+            let rule_ref = RuleRef {
+                family: self.family,
+                table: self.name.clone(),
+                chain: chain.name.clone(),
+                handle: rule.handle,
+            };
+            let matched = rule.matches.iter().try_fold(true, |all, m| match self.eval(m, probe) {
+                Some(false) => Err(()),
+                Some(true) => Ok(all),
+                None => Ok(false),
+            });
+            match matched {
+                Err(()) => continue,
+                Ok(false) => {
+                    if verdict.discards() {
+                        uncertain.push(rule_ref);
+                    }
+                    continue;
+                }
+                Ok(true) => {}
+            }
+            match verdict {
+                Verdict::Accept | Verdict::Drop | Verdict::Reject => return Some((verdict.clone(), rule_ref)),
+                Verdict::Jump(target) => {
+                    if let Some(found) = self.chain(target).and_then(|c| self.walk(c, probe, uncertain, depth + 1)) {
+                        return Some(found);
+                    }
+                }
+                // Simplification: the end of a goto target ends the calling chain too
+                Verdict::Goto(target) => return self.chain(target).and_then(|c| self.walk(c, probe, uncertain, depth + 1)),
+                Verdict::Return => return None,
+                Verdict::Continue => {}
+            }
+        }
+        None
+    }
+}
+
+/// Identifies a rule: family, table, chain and handle
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RuleRef {
+    pub family: u8,
+    pub table: String,
+    pub chain: String,
+    pub handle: u64,
+}
+
+impl fmt::Display for RuleRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} rule {}", family_name(self.family), self.table, self.chain, self.handle)
+    }
+}
+
+/// The live ruleset
+#[derive(Debug, Clone, Default)]
+pub struct Ruleset {
+    pub tables: Vec<Table>,
+}
+
+impl Ruleset {
+    /// Read the live ruleset (needs CAP_NET_ADMIN)
+    ///
+    /// nftables and legacy iptables tables both see every packet, so tables
+    /// from either backend are read.
+    pub fn read() -> Result<Self> {
+        match (crate::nftables::read_ruleset(), crate::iptables::read_legacy_tables()) {
+            (Ok(mut ruleset), Ok(legacy)) => {
+                ruleset.tables.extend(legacy);
+                Ok(ruleset)
+            }
+            (Ok(ruleset), Err(_)) => Ok(ruleset),
+            (Err(_), Ok(legacy)) if !legacy.is_empty() => Ok(Self { tables: legacy }),
+            (Err(e), _) => Err(e),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Every rule with its reference, in chain order
+    pub fn rules(&self) -> impl Iterator<Item = (RuleRef, &Rule)> {
+        self.tables.iter().flat_map(|table| {
+            table.chains.iter().flat_map(move |chain| {
+                chain.rules.iter().map(move |rule| {
+                    let rule_ref = RuleRef {
+                        family: table.family,
+                        table: table.name.clone(),
+                        chain: chain.name.clone(),
+                        handle: rule.handle,
+                    };
+                    (rule_ref, rule)
+                })
+            })
+        })
+    }
+
+    /// What the filter chains on the probe's hook do with it
+    ///
+    /// Base chains run in priority order; an accept only ends its own base
+    /// chain, a drop or reject is final.
+    pub fn evaluate(&self, probe: &Probe) -> Decision {
+        let mut bases: Vec<(&Table, &Chain)> = self
+            .tables
+            .iter()
+            .filter(|t| t.applies_to(probe))
+            .flat_map(|t| t.chains.iter().map(move |c| (t, c)))
+            .filter(|(_, c)| c.hook == Some(probe.hook) && c.kind == "filter")
+            .collect();
+        bases.sort_by_key(|(_, c)| c.priority);
+
+        let mut uncertain = Vec::new();
+        for (table, chain) in bases {
+            match table.walk(chain, probe, &mut uncertain, 0) {
+                Some((verdict, rule)) if verdict.discards() => {
+                    return Decision { verdict, rule: Some(rule), policy: None, uncertain };
+                }
+                Some(_) => {}
+                None if chain.policy.discards() => {
+                    let policy = format!("{} {} {}", family_name(table.family), table.name, chain.name);
+                    return Decision { verdict: chain.policy.clone(), rule: None, policy: Some(policy), uncertain };
+                }
+                None => {}
+            }
+        }
+        Decision { verdict: Verdict::Accept, rule: None, policy: None, uncertain }
+    }
+}
+
+/// A hypothetical new connection to check against the ruleset
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub hook: Hook,
+    /// IPPROTO_TCP, IPPROTO_UDP, ...
+    pub l4proto: u8,
+    pub src: Option<IpAddr>,
+    pub dst: Option<IpAddr>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    pub in_iface: Option<String>,
+    pub out_iface: Option<String>,
+}
+
+impl Probe {
+    /// Inbound connection to a local port
+    pub fn inbound(l4proto: u8, port: u16) -> Self {
+        Self {
+            hook: Hook::Input,
+            l4proto,
+            src: None,
+            dst: None,
+            src_port: None,
+            dst_port: Some(port),
+            in_iface: None,
+            out_iface: None,
+        }
+    }
+
+    fn is_ipv6(&self) -> bool {
+        matches!(self.src.or(self.dst), Some(IpAddr::V6(_)))
+    }
+
+    /// The field's bytes as the kernel compares them; None if unknown
+    fn value(&self, field: Field) -> Option<Vec<u8>> {
+        let addr = |ip: Option<IpAddr>| match ip? {
+            IpAddr::V4(ip) => Some(ip.octets().to_vec()),
+            IpAddr::V6(ip) => Some(ip.octets().to_vec()),
+        };
+        let iface = |name: &Option<String>| {
+            let mut bytes = name.as_ref()?.as_bytes().to_vec();
+            bytes.resize(IFNAMSIZ, 0);
+            Some(bytes)
+        };
+        match field {
+            Field::NfProto => Some(vec![if self.is_ipv6() { family::IPV6 } else { family::IPV4 }]),
+            Field::L4Proto => Some(vec![self.l4proto]),
+            Field::SrcAddr => addr(self.src),
+            Field::DstAddr => addr(self.dst),
+            Field::SrcPort => self.src_port.map(|p| p.to_be_bytes().to_vec()),
+            Field::DstPort => self.dst_port.map(|p| p.to_be_bytes().to_vec()),
+            Field::InIface => iface(&self.in_iface),
+            Field::OutIface => iface(&self.out_iface),
+            Field::CtState => Some(CT_STATE_NEW.to_ne_bytes().to_vec()),
+        }
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let proto = match self.l4proto {
+            6 => "tcp".to_string(),
+            17 => "udp".to_string(),
+            p => p.to_string(),
+        };
+        match self.dst_port {
+            Some(port) => write!(f, "port {}/{}", port, proto)?,
+            None => write!(f, "{}", proto)?,
+        }
+        if let Some(src) = self.src {
+            write!(f, " from {}", src)?;
+        }
+        if let Some(dst) = self.dst {
+            write!(f, " to {}", dst)?;
+        }
+        if self.hook != Hook::Input {
+            write!(f, " ({})", self.hook.name())?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of evaluating a probe
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub verdict: Verdict,
+    /// Rule that decided, if not a chain policy or the default
+    pub rule: Option<RuleRef>,
+    /// "family table chain" whose policy decided
+    pub policy: Option<String>,
+    /// Drop/reject rules with matches that couldn't be evaluated
+    pub uncertain: Vec<RuleRef>,
+}
+
+impl Decision {
+    /// "port 443/tcp is rejected by inet filter input rule 12"
+    pub fn describe(&self, probe: &Probe) -> String {
+        match (&self.rule, &self.policy) {
+            (Some(rule), _) => format!("{} is {} by {}", probe, self.verdict.as_str(), rule),
+            (None, Some(policy)) => format!("{} is {} by the {} policy", probe, self.verdict.as_str(), policy),
+            (None, None) => format!("{} is {}", probe, self.verdict.as_str()),
+        }
+    }
+}
+
+/// Packet counters of drop and reject rules at one point in time
+#[derive(Debug, Default)]
+pub struct CounterSnapshot {
+    packets: HashMap<RuleRef, u64>,
+}
+
+impl CounterSnapshot {
+    pub fn from_ruleset(ruleset: &Ruleset) -> Self {
+        let packets = ruleset
+            .rules()
+            .filter(|(_, rule)| rule.verdict.as_ref().is_some_and(Verdict::discards))
+            .filter_map(|(rule_ref, rule)| Some((rule_ref, rule.counter?.packets)))
+            .collect();
+        Self { packets }
+    }
+
+    pub fn read() -> Result<Self> {
+        Ok(Self::from_ruleset(&Ruleset::read()?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Rules whose counters moved since `earlier`, most packets first
+    pub fn dropped_since(&self, earlier: &CounterSnapshot) -> Vec<(RuleRef, u64)> {
+        let mut moved: Vec<(RuleRef, u64)> = self
+            .packets
+            .iter()
+            .filter_map(|(rule, &now)| {
+                // New rules (or a reset counter) count from zero
+                let before = earlier.packets.get(rule).copied().filter(|&b| b <= now).unwrap_or(0);
+                (now > before).then(|| (rule.clone(), now - before))
+            })
+            .collect();
+        moved.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.handle.cmp(&b.0.handle)));
+        moved
+    }
+}
+
+/// Finds the rules behind bursts of netfilter drops
+///
+/// Keeps a recent baseline of drop/reject counters; `sample` compares the
+/// counters now against it and moves the baseline forward.
+pub struct DropRuleCorrelator {
+    baseline: CounterSnapshot,
+    taken: Instant,
+}
+
+impl DropRuleCorrelator {
+    /// None if the ruleset can't be read (no CAP_NET_ADMIN, no firewall)
+    /// or has no drop/reject rules with counters
+    pub fn new() -> Option<Self> {
+        let baseline = CounterSnapshot::read().ok()?;
+        if baseline.is_empty() {
+            return None;
+        }
+        Some(Self { baseline, taken: Instant::now() })
+    }
+
+    /// Re-read the baseline if it is older than `BASELINE_MAX_AGE`, so
+    /// earlier unrelated drops don't show up in the next sample
+    pub fn refresh(&mut self) {
+        if self.taken.elapsed() >= BASELINE_MAX_AGE {
+            if let Ok(snapshot) = CounterSnapshot::read() {
+                self.baseline = snapshot;
+                self.taken = Instant::now();
+            }
+        }
+    }
+
+    /// Rules that dropped packets since the baseline, most first
+    pub fn sample(&mut self) -> Vec<(RuleRef, u64)> {
+        let Ok(now) = CounterSnapshot::read() else {
+            return Vec::new();
+        };
+        let moved = now.dropped_since(&self.baseline);
+        self.baseline = now;
+        self.taken = Instant::now();
+        moved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port_match(port: u16) -> Match {
+        Match::Cmp { field: Field::DstPort, op: CmpOp::Eq, value: port.to_be_bytes().to_vec(), mask: None }
+    }
+
+    fn tcp() -> Match {
+        Match::Cmp { field: Field::L4Proto, op: CmpOp::Eq, value: vec![6], mask: None }
+    }
+
+    fn rule(handle: u64, matches: Vec<Match>, verdict: Verdict) -> Rule {
+        Rule { handle, matches, verdict: Some(verdict), counter: None }
+    }
+
+    fn chain(name: &str, hook: Option<Hook>, policy: Verdict, rules: Vec<Rule>) -> Chain {
+        Chain { name: name.into(), hook, priority: 0, kind: "filter".into(), policy, rules }
+    }
+
+    fn ruleset(chains: Vec<Chain>, sets: Vec<Set>) -> Ruleset {
+        Ruleset { tables: vec![Table { family: family::INET, name: "filter".into(), chains, sets }] }
+    }
+
+    #[test]
+    fn test_evaluate_names_rejecting_rule() {
+        let rs = ruleset(
+            vec![chain(
+                "input",
+                Some(Hook::Input),
+                Verdict::Accept,
+                vec![
+                    rule(4, vec![tcp(), port_match(22)], Verdict::Accept),
+                    rule(12, vec![tcp(), port_match(443)], Verdict::Reject),
+                ],
+            )],
+            Vec::new(),
+        );
+
+        let probe = Probe::inbound(6, 443);
+        let decision = rs.evaluate(&probe);
+        assert_eq!(decision.verdict, Verdict::Reject);
+        assert_eq!(decision.describe(&probe), "port 443/tcp is rejected by inet filter input rule 12");
+
+        let decision = rs.evaluate(&Probe::inbound(6, 22));
+        assert_eq!(decision.verdict, Verdict::Accept);
+        // UDP doesn't match the tcp rules
+        assert_eq!(rs.evaluate(&Probe::inbound(17, 443)).verdict, Verdict::Accept);
+    }
+
+    #[test]
+    fn test_evaluate_follows_jumps_and_policy() {
+        let allowed = Set {
+            name: "allowed".into(),
+            interval: true,
+            elements: vec![
+                SetElement { key: 80u16.to_be_bytes().to_vec(), interval_end: false },
+                SetElement { key: 90u16.to_be_bytes().to_vec(), interval_end: true },
+            ],
+        };
+        let rs = ruleset(
+            vec![
+                chain(
+                    "input",
+                    Some(Hook::Input),
+                    Verdict::Drop,
+                    vec![rule(2, vec![tcp()], Verdict::Jump("tcp_in".into()))],
+                ),
+                chain(
+                    "tcp_in",
+                    None,
+                    Verdict::Accept,
+                    vec![rule(
+                        7,
+                        vec![Match::InSet { field: Field::DstPort, set: "allowed".into(), negate: false }],
+                        Verdict::Accept,
+                    )],
+                ),
+            ],
+            vec![allowed],
+        );
+
+        assert_eq!(rs.evaluate(&Probe::inbound(6, 85)).verdict, Verdict::Accept);
+        let probe = Probe::inbound(6, 90);
+        let decision = rs.evaluate(&probe);
+        assert_eq!(decision.verdict, Verdict::Drop);
+        assert_eq!(decision.describe(&probe), "port 90/tcp is dropped by the inet filter input policy");
+    }
+
+    #[test]
+    fn test_unknown_match_is_reported() {
+        let rs = ruleset(
+            vec![chain(
+                "input",
+                Some(Hook::Input),
+                Verdict::Accept,
+                vec![rule(5, vec![port_match(443), Match::Other("meta mark".into())], Verdict::Drop)],
+            )],
+            Vec::new(),
+        );
+        let decision = rs.evaluate(&Probe::inbound(6, 443));
+        assert_eq!(decision.verdict, Verdict::Accept);
+        assert_eq!(decision.uncertain.len(), 1);
+        assert_eq!(decision.uncertain[0].handle, 5);
+
+        // A definite mismatch isn't uncertain
+        assert!(rs.evaluate(&Probe::inbound(6, 80)).uncertain.is_empty());
+    }
+
+    #[test]
+    fn test_dropped_since_reports_moved_counters() {
+        let snapshot = |counts: &[(u64, u64)]| {
+            let rules = counts
+                .iter()
+                .map(|&(handle, packets)| Rule {
+                    handle,
+                    verdict: Some(Verdict::Drop),
+                    counter: Some(Counter { packets, bytes: packets * 60 }),
+                    ..Default::default()
+                })
+                .collect();
+            CounterSnapshot::from_ruleset(&ruleset(vec![chain("input", Some(Hook::Input), Verdict::Accept, rules)], Vec::new()))
+        };
+        let before = snapshot(&[(12, 100), (13, 7)]);
+        let after = snapshot(&[(12, 140), (13, 7), (20, 3)]);
+
+        let moved = after.dropped_since(&before);
+        assert_eq!(moved.len(), 2);
+        assert_eq!(moved[0].0.to_string(), "inet filter input rule 12");
+        assert_eq!(moved[0].1, 40);
+        assert_eq!((moved[1].0.handle, moved[1].1), (20, 3));
+    }
+}
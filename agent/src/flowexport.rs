@@ -0,0 +1,380 @@
+//! Cross-Node Flow Export
+//!
+//! East-west traffic in a cluster is seen twice: by the agent on the client's
+//! node (outbound) and by the agent on the server's node (inbound). When
+//! enabled, the daemon periodically sends each active flow's 5-tuple, the
+//! side it saw and its packet counters to the control plane, which joins the
+//! records across agents: "node A sent 12 packets, node B received none".
+//!
+//! Batches are compact, one array per flow in `FIELDS` order, and carry
+//! hints for matching: a per-agent sequence number to spot lost batches, the
+//! window they cover, and each flow's start time to tell apart connections
+//! that reuse a 5-tuple. `id` hashes the 5-tuple so both ends agree on it.
+//! Client-side HTTPS flows carry the server name from the TLS ClientHello,
+//! so encrypted connections can be labelled with the service they reach.
+//!
+//! Only flows with new packets since the last batch are sent. Flows that
+//! rollups delete from the kernel map in between are handed over through
+//! [`hand_off`].
+
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+
+use crate::client::SentinelClient;
+use crate::ebpf::{ipv4_addr, FlowInfo, FlowKey};
+
+/// Version of the batch layout
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Meaning of each flow array, in order
+pub const FIELDS: [&str; 13] = [
+    "id",
+    "client",
+    "clientPort",
+    "server",
+    "serverPort",
+    "protocol",
+    "side",
+    "startMs",
+    "txPackets",
+    "rxPackets",
+    "txBytes",
+    "rxBytes",
+    "serverName",
+];
+
+/// Flows sent per batch at most; the busiest are kept
+pub const MAX_FLOWS_PER_BATCH: usize = 8192;
+
+/// Deleted flows held for the next batch at most
+const MAX_HANDED_OFF: usize = 65536;
+
+/// Which end of the flow this agent is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Client,
+    Server,
+}
+
+/// One flow as this agent saw it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowRecord {
+    pub id: String,
+    pub client: Ipv4Addr,
+    pub client_port: u16,
+    pub server: Ipv4Addr,
+    pub server_port: u16,
+    pub protocol: u8,
+    pub side: Side,
+    /// Unix milliseconds the flow started
+    pub start_ms: u64,
+    pub tx_packets: u64,
+    pub rx_packets: u64,
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+    /// Server name from the TLS ClientHello (client side only)
+    pub server_name: Option<String>,
+}
+
+impl FlowRecord {
+    /// None for flows of unknown direction
+    pub fn from_flow(key: &FlowKey, info: &FlowInfo) -> Option<Self> {
+        // Flow keys put the initiator first in both directions
+        let side = match info.direction {
+            1 => Side::Client,
+            2 => Side::Server,
+            _ => return None,
+        };
+        let (client, server) = (ipv4_addr(key.src_ip), ipv4_addr(key.dst_ip));
+        let start = crate::clock::wall_time(info.start_time_ns);
+        Some(Self {
+            id: flow_id(client, key.src_port, server, key.dst_port, key.protocol),
+            client,
+            client_port: key.src_port,
+            server,
+            server_port: key.dst_port,
+            protocol: key.protocol,
+            side,
+            start_ms: unix_ms(start),
+            tx_packets: info.tx_packets.into(),
+            rx_packets: info.rx_packets.into(),
+            tx_bytes: info.tx_bytes,
+            rx_bytes: info.rx_bytes,
+            server_name: None,
+        })
+    }
+
+    fn packets(&self) -> u64 {
+        self.tx_packets + self.rx_packets
+    }
+}
+
+impl Serialize for FlowRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (
+            &self.id,
+            self.client,
+            self.client_port,
+            self.server,
+            self.server_port,
+            self.protocol,
+            self.side,
+            self.start_ms,
+            self.tx_packets,
+            self.rx_packets,
+            self.tx_bytes,
+            self.rx_bytes,
+            &self.server_name,
+        )
+            .serialize(serializer)
+    }
+}
+
+/// Identifier of a 5-tuple, the same on every agent that sees the flow
+pub fn flow_id(
+    client: Ipv4Addr,
+    client_port: u16,
+    server: Ipv4Addr,
+    server_port: u16,
+    protocol: u8,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(client.octets());
+    hasher.update(client_port.to_be_bytes());
+    hasher.update(server.octets());
+    hasher.update(server_port.to_be_bytes());
+    hasher.update([protocol]);
+    hex::encode(&hasher.finalize()[..8])
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// One export window
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowBatch {
+    pub version: u32,
+    pub agent_id: String,
+    /// Kubernetes node name, or the host name outside a cluster
+    pub node: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Increments by one per batch; a gap means a batch was lost
+    pub seq: u64,
+    pub window_start_ms: u64,
+    pub window_end_ms: u64,
+    pub fields: [&'static str; 13],
+    pub flows: Vec<FlowRecord>,
+    /// Active flows left out to stay within MAX_FLOWS_PER_BATCH
+    #[serde(skip_serializing_if = "is_zero")]
+    pub truncated: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static HANDED_OFF: Mutex<Vec<(FlowKey, FlowInfo)>> = Mutex::new(Vec::new());
+
+/// Keep flows deleted from the kernel map for the next batch (no-op unless
+/// the exporter runs)
+pub fn hand_off(flows: &[(FlowKey, FlowInfo)]) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut pending = HANDED_OFF.lock().unwrap_or_else(|e| e.into_inner());
+    let room = MAX_HANDED_OFF.saturating_sub(pending.len());
+    pending.extend(flows.iter().take(room).copied());
+}
+
+/// Builds batches from flow snapshots
+pub struct FlowExporter {
+    agent_id: String,
+    node: String,
+    seq: u64,
+    window_start: SystemTime,
+    /// Packets last sent per (id, side, start), to skip idle flows
+    sent: HashMap<(String, Side, u64), u64>,
+}
+
+impl FlowExporter {
+    pub fn new(agent_id: String, node: String) -> Self {
+        Self { agent_id, node, seq: 0, window_start: SystemTime::now(), sent: HashMap::new() }
+    }
+
+    /// Batch of the flows that moved since the last one, labelled with the
+    /// TLS server names in `names`
+    pub fn batch(
+        &mut self,
+        flows: &[(FlowKey, FlowInfo)],
+        names: &HashMap<FlowKey, String>,
+        now: SystemTime,
+    ) -> FlowBatch {
+        let mut sent = HashMap::with_capacity(self.sent.len());
+        let mut records: Vec<FlowRecord> = Vec::new();
+        for (key, info) in flows {
+            let Some(mut record) = FlowRecord::from_flow(key, info) else {
+                continue;
+            };
+            let flow = (record.id.clone(), record.side, record.start_ms);
+            let packets = record.packets();
+            let before = self.sent.get(&flow).copied();
+            // The same flow can be both deleted and still in the map (recreated)
+            if sent.insert(flow, packets).is_some() {
+                continue;
+            }
+            if before.is_none_or(|b| packets > b) {
+                if record.side == Side::Client {
+                    record.server_name = names.get(key).cloned();
+                }
+                records.push(record);
+            }
+        }
+        // Flows gone from the map are forgotten
+        self.sent = sent;
+
+        records.sort_by_key(|r| std::cmp::Reverse(r.packets()));
+        let truncated = records.len().saturating_sub(MAX_FLOWS_PER_BATCH) as u64;
+        records.truncate(MAX_FLOWS_PER_BATCH);
+
+        self.seq += 1;
+        let batch = FlowBatch {
+            version: FORMAT_VERSION,
+            agent_id: self.agent_id.clone(),
+            node: self.node.clone(),
+            labels: crate::labels::get().clone(),
+            seq: self.seq,
+            window_start_ms: unix_ms(self.window_start),
+            window_end_ms: unix_ms(now),
+            fields: FIELDS,
+            flows: records,
+            truncated,
+        };
+        self.window_start = now;
+        batch
+    }
+}
+
+/// Node name for batches: NODE_NAME in a DaemonSet, else the host name
+pub fn node_name(pod: Option<&crate::daemonset::PodIdentity>) -> String {
+    pod.map(|p| p.node_name.clone())
+        .filter(|n| !n.is_empty())
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok().map(|h| h.trim().to_string()))
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_default()
+}
+
+/// Send a batch of flow records to the control plane every `interval`
+pub async fn run(client: Arc<SentinelClient>, mut exporter: FlowExporter, interval: Duration) {
+    info!("Starting cross-node flow export (interval: {:?})", interval);
+    ENABLED.store(true, Ordering::Relaxed);
+
+    loop {
+        tokio::time::sleep(interval).await;
+        let mut flows = std::mem::take(&mut *HANDED_OFF.lock().unwrap_or_else(|e| e.into_inner()));
+        match tokio::task::spawn_blocking(crate::ebpf::read_pinned_flows).await {
+            Ok(Ok(active)) => flows.extend(active),
+            Ok(Err(e)) => debug!("Could not read flows: {}", e),
+            Err(e) => debug!("Flow read task panicked: {}", e),
+        }
+        let names: HashMap<FlowKey, String> = match tokio::task::spawn_blocking(crate::ebpf::read_pinned_tls_sni).await {
+            Ok(Ok(names)) => names.into_iter().collect(),
+            Ok(Err(e)) => {
+                debug!("Could not read TLS server names: {}", e);
+                HashMap::new()
+            }
+            Err(e) => {
+                debug!("TLS server name read task panicked: {}", e);
+                HashMap::new()
+            }
+        };
+
+        let batch = exporter.batch(&flows, &names, SystemTime::now());
+        let (seq, count) = (batch.seq, batch.flows.len());
+        let client = Arc::clone(&client);
+        match tokio::task::spawn_blocking(move || client.export_flows(&batch)).await {
+            Ok(Ok(())) => debug!(seq, flows = count, "Flow batch exported"),
+            Ok(Err(e)) => {
+                crate::selfmetrics::global().exporter_errors.fetch_add(1, Ordering::Relaxed);
+                debug!(seq, "Could not export flow batch: {}", e);
+            }
+            Err(e) => debug!("Flow export task panicked: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(client: [u8; 4], server: [u8; 4], direction: u8, packets: u32) -> (FlowKey, FlowInfo) {
+        let key = FlowKey {
+            src_ip: u32::from_be_bytes(client),
+            dst_ip: u32::from_be_bytes(server),
+            src_port: 40000,
+            dst_port: 443,
+            protocol: 6,
+            ..Default::default()
+        };
+        let info = FlowInfo { direction, tx_packets: packets, start_time_ns: 1_000, ..Default::default() };
+        (key, info)
+    }
+
+    #[test]
+    fn test_both_sides_share_an_id() {
+        let (key, info) = flow([10, 0, 0, 5], [10, 0, 1, 7], 1, 3);
+        let client = FlowRecord::from_flow(&key, &info).unwrap();
+        let (key, info) = flow([10, 0, 0, 5], [10, 0, 1, 7], 2, 0);
+        let server = FlowRecord::from_flow(&key, &info).unwrap();
+
+        assert_eq!((client.side, server.side), (Side::Client, Side::Server));
+        assert_eq!(client.id, server.id);
+        assert_eq!(client.id.len(), 16);
+        assert_eq!(server.client, Ipv4Addr::new(10, 0, 0, 5));
+
+        let json = serde_json::to_string(&client).unwrap();
+        assert!(json.starts_with(&format!(r#"["{}","10.0.0.5",40000,"10.0.1.7",443,6,"client","#, client.id)));
+
+        let (key, info) = flow([10, 0, 0, 5], [10, 0, 1, 7], 0, 1);
+        assert!(FlowRecord::from_flow(&key, &info).is_none());
+    }
+
+    #[test]
+    fn test_batches_skip_idle_flows() {
+        let mut exporter = FlowExporter::new("agent-1".into(), "worker-1".into());
+        let busy = flow([10, 0, 0, 5], [10, 0, 1, 7], 1, 5);
+        let idle = flow([10, 0, 0, 6], [10, 0, 1, 7], 1, 2);
+
+        let names = HashMap::from([(busy.0, "api.example.com".to_string())]);
+        let first = exporter.batch(&[busy, idle], &names, SystemTime::now());
+        assert_eq!((first.seq, first.flows.len()), (1, 2));
+        assert_eq!(first.flows[0].tx_packets, 5);
+        assert_eq!(first.flows[0].server_name.as_deref(), Some("api.example.com"));
+        assert_eq!(first.flows[1].server_name, None);
+
+        let mut busier = busy;
+        busier.1.tx_packets = 9;
+        // Drained and still mapped: sent once
+        let second = exporter.batch(&[busier, busier, idle], &HashMap::new(), SystemTime::now());
+        assert_eq!((second.seq, second.flows.len()), (2, 1));
+        assert_eq!(second.flows[0].tx_packets, 9);
+        assert_eq!(second.window_start_ms, first.window_end_ms);
+
+        let value = serde_json::to_value(&second).unwrap();
+        assert_eq!(value["node"], "worker-1");
+        assert_eq!(value["fields"][0], "id");
+        assert_eq!(value["fields"][12], "serverName");
+        assert_eq!(value["flows"][0][12], serde_json::Value::Null);
+        assert!(value.get("truncated").is_none());
+    }
+}
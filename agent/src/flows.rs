@@ -1,25 +1,37 @@
 //! Flow Tracking CLI Command (Phase 8)
 //!
 //! Displays active network flows with PID attribution.
-//! Usage: sennet flows [OPTIONS]
+//! Usage: sennet flows [OPTIONS] [EXPRESSION]
 
 use anyhow::Result;
 use colored::Colorize;
-use crate::ebpf::{EbpfManager, format_ip, comm_to_string, flow_direction_str};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use crate::asn::{self, AsnDb};
+use crate::conntrack::{NatTable, Tuple};
+use crate::ebpf::{EbpfManager, FlowKey, format_ip, ipv4_addr, comm_to_string, flow_direction_str};
+use crate::filter::{Field, Filter};
+use crate::rollup::RollupWindow;
 
 /// Print help for the flows command
 pub fn print_help() {
     println!("{}", "Sennet Flows - Active Network Flows with PID Attribution".bold());
-    println!("Show all active TCP connections with process information.");
+    println!("Show active TCP connections and outbound UDP flows with process information.");
     println!();
     println!("{}", "USAGE:".yellow());
-    println!("    sennet flows [OPTIONS]");
+    println!("    sennet flows [OPTIONS] [EXPRESSION]");
     println!();
     println!("{}", "OPTIONS:".yellow());
     println!("    --sort <FIELD>     Sort by: pid, bytes, packets (default: bytes)");
     println!("    --limit <N>        Show only top N flows (default: 50)");
+    println!("    -f, --filter <E>   Filter expression (also given as trailing words)");
     println!("    --pid <PID>        Filter by process ID");
     println!("    --comm <NAME>      Filter by process name (partial match)");
+    println!("    --nat              Show only flows translated by SNAT/DNAT");
+    println!("    --by-asn           Aggregate bandwidth by remote ASN");
+    println!("    --asn-db <PATH>    ASN database (iptoasn TSV, default: from config)");
+    println!("    --rollups          Show the daemon's last flow rollup window");
     println!("    -h, --help         Show this help message");
     println!();
     println!("{}", "EXAMPLES:".yellow());
@@ -27,18 +39,30 @@ pub fn print_help() {
     println!("    sennet flows --sort packets   # Sort by packet count");
     println!("    sennet flows --pid 1234       # Show flows for PID 1234");
     println!("    sennet flows --comm nginx     # Show flows for nginx");
+    println!("    sennet flows 'dst == 10.0.0.0/8 && port == 5432'");
+    println!("    sennet flows --by-asn         # Who is consuming egress bandwidth");
+    println!("    sennet flows --rollups        # Flows per process and destination");
     println!();
     println!("{}", "OUTPUT:".yellow());
     println!("    PID       Process name");
     println!("    DIR       Direction (IN=inbound, OUT=outbound)");
     println!("    LOCAL     Local IP:port");
     println!("    REMOTE    Remote IP:port");
-    println!("    RX        Bytes received");
-    println!("    TX        Bytes transmitted");
+    println!("    RX        Bytes the process read from the socket");
+    println!("    TX        Bytes the process wrote to the socket (UDP: bytes sent)");
+    println!("    NAT       Translated tuple as seen upstream (from conntrack)");
+    println!("    TLS       Server name the client sent in its TLS ClientHello (port 443)");
+    println!("    QUIC      UDP flow to port 443 carrying QUIC (HTTP/3)");
     println!();
     println!("{}", "NOTES:".yellow());
-    println!("    - Requires root privileges for eBPF access");
+    println!("    - Requires root or CAP_BPF to read the pinned eBPF maps");
     println!("    - Flow tracking must be enabled (kprobes attached)");
+    println!("    - Connections opened before the agent started are not listed");
+    println!("    - UDP flows are attributed to the process that created the socket and");
+    println!("      need cgroup v2; list them with 'proto == udp'");
+    println!("    - Expressions compare src, dst, host, sport, dport, port, proto,");
+    println!("      family, pid and comm; see `sennet trace --help` for the syntax");
+    println!("    - With --rollups only the remote end of a flow can be matched");
 }
 
 /// Sort field for flows
@@ -53,8 +77,12 @@ pub enum SortField {
 pub struct FlowsOptions {
     pub sort_by: SortField,
     pub limit: usize,
-    pub filter_pid: Option<u32>,
-    pub filter_comm: Option<String>,
+    /// Which flows to show (see [crate::filter])
+    pub filter: Filter,
+    pub nat_only: bool,
+    pub by_asn: bool,
+    pub asn_db: Option<PathBuf>,
+    pub rollups: bool,
 }
 
 impl Default for FlowsOptions {
@@ -62,15 +90,34 @@ impl Default for FlowsOptions {
         Self {
             sort_by: SortField::Bytes,
             limit: 50,
-            filter_pid: None,
-            filter_comm: None,
+            filter: Filter::default(),
+            nat_only: false,
+            by_asn: false,
+            asn_db: None,
+            rollups: false,
         }
     }
 }
 
+/// Fields a flows filter can use
+const FLOW_FIELDS: &[Field] = &[
+    Field::Src,
+    Field::Dst,
+    Field::Host,
+    Field::Sport,
+    Field::Dport,
+    Field::Port,
+    Field::Proto,
+    Field::Family,
+    Field::Pid,
+    Field::Comm,
+];
+
 /// Parse command line arguments for flows command
-pub fn parse_args(args: &[String]) -> FlowsOptions {
+pub fn parse_args(args: &[String]) -> Result<FlowsOptions> {
     let mut opts = FlowsOptions::default();
+    let mut exprs: Vec<String> = Vec::new();
+    let mut words: Vec<&str> = Vec::new();
     let mut i = 0;
     
     while i < args.len() {
@@ -91,28 +138,51 @@ pub fn parse_args(args: &[String]) -> FlowsOptions {
                     i += 1;
                 }
             }
+            "--filter" | "-f" => {
+                if i + 1 < args.len() {
+                    exprs.push(args[i + 1].clone());
+                    i += 1;
+                }
+            }
             "--pid" => {
                 if i + 1 < args.len() {
-                    opts.filter_pid = args[i + 1].parse().ok();
+                    exprs.push(format!("pid == {}", args[i + 1]));
                     i += 1;
                 }
             }
             "--comm" => {
                 if i + 1 < args.len() {
-                    opts.filter_comm = Some(args[i + 1].clone());
+                    exprs.push(format!("comm ~ {:?}", args[i + 1]));
                     i += 1;
                 }
             }
+            "--nat" => opts.nat_only = true,
+            "--by-asn" => opts.by_asn = true,
+            "--rollups" => opts.rollups = true,
+            "--asn-db" if i + 1 < args.len() => {
+                opts.asn_db = Some(PathBuf::from(&args[i + 1]));
+                opts.by_asn = true;
+                i += 1;
+            }
+            // Anything else not starting with a dash is part of an expression
+            word if !word.starts_with('-') => words.push(word),
             _ => {}
         }
         i += 1;
     }
+    if !words.is_empty() {
+        exprs.push(words.join(" "));
+    }
     
-    opts
+    for expr in &exprs {
+        opts.filter = std::mem::take(&mut opts.filter).and(Filter::parse(expr)?);
+    }
+    opts.filter.check_fields(FLOW_FIELDS, "flows")?;
+    Ok(opts)
 }
 
 /// Format bytes in human-readable form
-fn format_bytes(bytes: u64) -> String {
+pub(crate) fn format_bytes(bytes: u64) -> String {
     if bytes >= 1_000_000_000 {
         format!("{:.1}GB", bytes as f64 / 1_000_000_000.0)
     } else if bytes >= 1_000_000 {
@@ -124,41 +194,212 @@ fn format_bytes(bytes: u64) -> String {
     }
 }
 
+/// Print flow bandwidth aggregated by remote ASN
+fn print_by_asn(opts: &FlowsOptions, flows: &[(FlowKey, crate::ebpf::FlowInfo)]) -> Result<()> {
+    let db = match &opts.asn_db {
+        Some(path) => AsnDb::load(path)?,
+        None => {
+            let config = crate::config::Config::load().ok();
+            let state_dir = config
+                .as_ref()
+                .map(|c| c.state_dir.clone())
+                .unwrap_or_else(|| PathBuf::from("/var/lib/sennet"));
+            let configured = config.as_ref().and_then(|c| c.asn_db_path.clone());
+            let path = configured.unwrap_or_else(|| state_dir.join(asn::DEFAULT_DB_FILE));
+            AsnDb::load(&path).map_err(|e| {
+                anyhow::anyhow!("{}\nDownload one from https://iptoasn.com and pass --asn-db <PATH>", e)
+            })?
+        }
+    };
+    
+    let mut usage = asn::aggregate(&db, flows);
+    // SHARE is of all traffic, not just the ASNs that fit under --limit
+    let total: u64 = usage.iter().map(|u| u.total_bytes()).sum();
+    let asns = usage.len();
+    usage.truncate(opts.limit);
+    
+    println!();
+    println!("{}", "Sennet Bandwidth by ASN".bold());
+    println!("{}", "═".repeat(90));
+    println!(
+        "{:>8} {:<32} {:>3} {:>6} {:>10} {:>10} {:>6}",
+        "ASN".cyan(),
+        "NAME".cyan(),
+        "CC".cyan(),
+        "FLOWS".cyan(),
+        "RX".cyan(),
+        "TX".cyan(),
+        "SHARE".cyan()
+    );
+    println!("{}", "─".repeat(90));
+    
+    for u in &usage {
+        let asn = if u.asn == 0 { "-".to_string() } else { format!("AS{}", u.asn) };
+        let name: String = u.name.chars().take(32).collect();
+        let share = if total > 0 { u.total_bytes() as f64 * 100.0 / total as f64 } else { 0.0 };
+        println!(
+            "{:>8} {:<32} {:>3} {:>6} {:>10} {:>10} {:>5.1}%",
+            asn,
+            name,
+            u.country,
+            u.flows,
+            format_bytes(u.rx_bytes),
+            format_bytes(u.tx_bytes),
+            share,
+        );
+    }
+    
+    println!("{}", "─".repeat(90));
+    println!("Total: {} ASNs, {} flows", asns, flows.len());
+    println!();
+    
+    Ok(())
+}
+
+/// Print the last rollup window written by the daemon
+fn print_rollups(opts: &FlowsOptions) -> Result<()> {
+    let state_dir = crate::config::Config::load()
+        .map(|c| c.state_dir)
+        .unwrap_or_else(|_| PathBuf::from("/var/lib/sennet"));
+    let mut window = RollupWindow::load(&state_dir).map_err(|e| {
+        anyhow::anyhow!("{}\nSet flow_rollup_interval_secs in the agent config to enable rollups", e)
+    })?;
+
+    window.rollups.retain(|r| opts.filter.matches(&r.key));
+    let total_flows = window.total_flows();
+    window.rollups.truncate(opts.limit);
+
+    println!();
+    println!(
+        "{} ({}s window)",
+        "Sennet Flow Rollups".bold(),
+        window.window_end.saturating_sub(window.window_start)
+    );
+    println!("{}", "═".repeat(90));
+    println!(
+        "{:>16} {:>3} {:>21} {:>6} {:>8} {:>10} {:>10}",
+        "COMMAND".cyan(),
+        "DIR".cyan(),
+        "REMOTE".cyan(),
+        "PROTO".cyan(),
+        "FLOWS".cyan(),
+        "RX".cyan(),
+        "TX".cyan()
+    );
+    println!("{}", "─".repeat(90));
+
+    for r in &window.rollups {
+        let process: String = r.key.process.chars().take(16).collect();
+        let dir = if r.key.direction == 1 { "OUT".green() } else { "IN".blue() };
+        let proto = match r.key.protocol {
+            6 => "tcp".to_string(),
+            17 => "udp".to_string(),
+            p => p.to_string(),
+        };
+        println!(
+            "{:>16} {:>3} {:>21} {:>6} {:>8} {:>10} {:>10}",
+            process,
+            dir,
+            format!("{}:{}", r.key.remote, r.key.remote_port),
+            proto,
+            r.flows,
+            format_bytes(r.rx_bytes),
+            format_bytes(r.tx_bytes),
+        );
+    }
+
+    println!("{}", "─".repeat(90));
+    if window.overflow_flows > 0 {
+        println!(
+            "Total: {} flows ({} not attributed, rollup limit reached)",
+            total_flows, window.overflow_flows
+        );
+    } else {
+        println!("Total: {} flows", total_flows);
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Convert a flow key to a conntrack tuple (same byte order as format_ip)
+fn flow_tuple(key: &FlowKey) -> Tuple {
+    Tuple {
+        src: IpAddr::V4(ipv4_addr(key.src_ip)),
+        dst: IpAddr::V4(ipv4_addr(key.dst_ip)),
+        sport: key.src_port,
+        dport: key.dst_port,
+    }
+}
+
+/// Active flows from the daemon's control socket, if it is listening
+#[cfg(unix)]
+fn daemon_flows() -> Option<Vec<(FlowKey, crate::ebpf::FlowInfo)>> {
+    crate::control::Client::connect()?.flows().ok()
+}
+
+#[cfg(not(unix))]
+fn daemon_flows() -> Option<Vec<(FlowKey, crate::ebpf::FlowInfo)>> {
+    None
+}
+
+/// TLS server names by flow, from the daemon or else its pinned map (empty
+/// if neither is readable)
+fn server_names() -> HashMap<FlowKey, String> {
+    #[cfg(unix)]
+    if let Some(names) = crate::control::Client::connect().and_then(|mut c| c.tls_sni().ok()) {
+        return names.into_iter().collect();
+    }
+    crate::ebpf::read_pinned_tls_sni().unwrap_or_default().into_iter().collect()
+}
+
 /// Run the flows command
 pub fn run(args: &[String]) -> Result<()> {
-    let opts = parse_args(args);
-    
-    // Discover interface and load eBPF
-    let interface = crate::interface::discover_default_interface(None)?;
-    let manager = EbpfManager::load_and_attach(&interface)?;
+    let opts = parse_args(args)?;
     
-    if !manager.flow_tracing_enabled {
-        eprintln!("{} Flow tracing not enabled. kprobes may have failed to attach.", "Warning:".yellow());
-        eprintln!("This requires a recent kernel with kprobe support.");
+    if opts.rollups {
+        return print_rollups(&opts);
     }
     
-    // Read flows
-    let mut flows = manager.read_flows()?;
+    // Ask the running agent first: loading eBPF here needs root
+    let mut flows = match daemon_flows() {
+        Some(flows) => flows,
+        None => {
+            // Discover interface and load eBPF
+            let interface = crate::interface::discover_default_interface(None)?;
+            let manager = EbpfManager::load_and_attach(&interface)?;
+            
+            if !manager.flow_tracing_enabled {
+                eprintln!("{} Flow tracing not enabled. kprobes may have failed to attach.", "Warning:".yellow());
+                eprintln!("This requires a recent kernel with kprobe support.");
+            }
+            
+            manager.read_flows()?
+        }
+    };
     
     if flows.is_empty() {
         println!("{}", "No active flows found.".yellow());
         println!();
         println!("Possible reasons:");
-        println!("  - No active TCP connections");
+        println!("  - No active TCP connections or outbound UDP flows");
         println!("  - Flow tracking kprobes not attached");
         println!("  - Flows started before sennet was running");
         return Ok(());
     }
     
     // Apply filters
-    if let Some(pid) = opts.filter_pid {
-        flows.retain(|(_, info)| info.pid == pid);
+    flows.retain(|flow| opts.filter.matches(flow));
+    
+    if opts.by_asn {
+        return print_by_asn(&opts, &flows);
     }
-    if let Some(ref comm) = opts.filter_comm {
-        let comm_lower = comm.to_lowercase();
-        flows.retain(|(_, info)| {
-            comm_to_string(&info.comm).to_lowercase().contains(&comm_lower)
-        });
+    
+    // Resolve SNAT/DNAT translations from conntrack
+    let nat = NatTable::load();
+    let names = server_names();
+    if opts.nat_only {
+        flows.retain(|(key, _)| nat.lookup(key.protocol, &flow_tuple(key)).is_some());
     }
     
     // Sort flows
@@ -223,10 +464,33 @@ pub fn run(args: &[String]) -> Result<()> {
             format_bytes(info.rx_bytes),
             format_bytes(info.tx_bytes),
         );
+        
+        if let Some(entry) = nat.lookup(key.protocol, &flow_tuple(key)) {
+            if let Some(kind) = entry.nat_kind() {
+                println!(
+                    "{:>29} {} {}  {} {}",
+                    "↳".dimmed(),
+                    kind.as_str().magenta(),
+                    entry.original,
+                    "as".dimmed(),
+                    entry.translated(),
+                );
+            }
+        }
+        if let Some(name) = names.get(key) {
+            println!("{:>29} {} {}", "↳".dimmed(), "tls".magenta(), name);
+        }
+        if let Some(app) = sennet_common::app_proto_str(info.app_protocol) {
+            println!("{:>29} {}", "↳".dimmed(), app.magenta());
+        }
     }
     
     println!("{}", "─".repeat(100));
-    println!("Total: {} flows", flows.len());
+    if nat.is_empty() {
+        println!("Total: {} flows", flows.len());
+    } else {
+        println!("Total: {} flows ({} NAT'd connections tracked)", flows.len(), nat.len());
+    }
     println!();
     
     Ok(())
@@ -0,0 +1,401 @@
+//! Grafana Datasource Endpoints
+//!
+//! Under `/api/v1/grafana` the REST API speaks the protocol of Grafana's
+//! JSON datasource (`GET /`, `POST /search`, `POST /metrics`, `POST /query`)
+//! and serves plain JSON arrays for the Infinity datasource:
+//!
+//! - `GET /api/v1/grafana/series?target=<series>&from=<ms>&to=<ms>`: rows
+//!   of `{time, series, value}`
+//! - `GET /api/v1/grafana/table?target=<table>&from=<ms>&to=<ms>`: one
+//!   object per table row
+//!
+//! Timeseries are charted from the last hour of pipeline windows kept in
+//! memory ([`History`]), so they need `pipeline.enabled`. Every value is a
+//! per-second rate over one window; packet and byte rates come from the
+//! kernel counters read at the ends of consecutive windows.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::control::ControlState;
+use crate::ebpf::{drop_reason_str, nf_hook_str, PacketCounters};
+use crate::pipeline::Summary;
+
+/// How far back the history reaches
+pub const HISTORY_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Windows kept whatever the flush interval
+const MAX_POINTS: usize = 3600;
+
+/// Timeseries targets and what they chart
+pub const SERIES: &[(&str, &str)] = &[
+    ("packets.rx", "Received packets/s"),
+    ("packets.tx", "Sent packets/s"),
+    ("bytes.rx", "Received bytes/s"),
+    ("bytes.tx", "Sent bytes/s"),
+    ("drops.kernel", "Packets/s counted by the kernel drop counter"),
+    ("events", "Pipeline events/s"),
+    ("drops", "Drop events/s"),
+    ("drops.by_reason", "Drop events/s by drop reason"),
+    ("drops.by_interface", "Drop events/s by interface"),
+    ("netfilter.drops", "Netfilter DROP verdicts/s by hook"),
+    ("resets", "TCP resets/s by direction"),
+    ("flows.opened", "Connections opened/s"),
+    ("flows.closed", "Connections closed/s"),
+];
+
+/// Table targets and what they list
+pub const TABLES: &[(&str, &str)] = &[
+    ("flows", "Active flows"),
+    ("drop_reasons", "Drop events by reason over the range"),
+    ("interfaces", "Drop events by interface over the range"),
+];
+
+/// One flushed pipeline window
+#[derive(Debug, Clone)]
+pub struct WindowPoint {
+    pub start: SystemTime,
+    pub end: SystemTime,
+    /// The window's counters; notable events and labels are not kept
+    pub summary: Summary,
+    /// Kernel packet counters when the window was flushed
+    pub counters: Option<PacketCounters>,
+}
+
+/// Recent pipeline windows, oldest first
+#[derive(Debug, Default)]
+pub struct History {
+    points: Mutex<VecDeque<WindowPoint>>,
+}
+
+impl History {
+    pub fn record(&self, summary: &Summary, counters: Option<PacketCounters>) {
+        let end = summary.window_end.unwrap_or_else(SystemTime::now);
+        let point = WindowPoint {
+            start: summary.window_start.unwrap_or(end),
+            end,
+            summary: Summary {
+                notable: Vec::new(),
+                labels: BTreeMap::new(),
+                ..summary.clone()
+            },
+            counters,
+        };
+        let mut points = self.points.lock().unwrap();
+        points.push_back(point);
+        while points.len() > MAX_POINTS
+            || points.front().is_some_and(|p| end.duration_since(p.end).unwrap_or_default() > HISTORY_WINDOW)
+        {
+            points.pop_front();
+        }
+    }
+
+    /// Windows ending within `from..=to`, plus the one before for counter deltas
+    fn range(&self, from: SystemTime, to: SystemTime) -> Vec<WindowPoint> {
+        let points = self.points.lock().unwrap();
+        let first = points.partition_point(|p| p.end < from).saturating_sub(1);
+        points.iter().skip(first).take_while(|p| p.end <= to).cloned().collect()
+    }
+}
+
+/// Query parameters of the Infinity endpoints
+#[derive(Debug, Default)]
+pub struct Params {
+    pub target: Option<String>,
+    /// Unix milliseconds, as Grafana's `${__from}` and `${__to}`
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// `POST /query` body
+#[derive(Debug, Deserialize)]
+pub struct QueryRequest {
+    pub range: QueryRange,
+    #[serde(default)]
+    pub targets: Vec<QueryTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryRange {
+    pub from: chrono::DateTime<chrono::Utc>,
+    pub to: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryTarget {
+    #[serde(default)]
+    pub target: String,
+    #[serde(default)]
+    pub hide: bool,
+}
+
+/// Answer a request under `/api/v1/grafana`; Err holds the HTTP status and
+/// message. Blocking, as the flows table reads a pinned map.
+pub fn respond(method: &str, route: &str, params: &Params, body: &[u8], state: &ControlState) -> Result<Value, (&'static str, String)> {
+    let bad_request = |message: String| ("400 Bad Request", message);
+    match (method, route) {
+        // Datasource health check
+        ("GET", "" | "/") => Ok(json!({ "status": "ok" })),
+        ("POST", "/search") => Ok(SERIES.iter().chain(TABLES).map(|(name, _)| *name).collect()),
+        ("POST", "/metrics") => Ok(SERIES
+            .iter()
+            .chain(TABLES)
+            .map(|(name, help)| json!({ "label": format!("{} ({})", name, help), "value": name, "payloads": [] }))
+            .collect()),
+        ("POST", "/metric-payload-options") => Ok(json!([])),
+        ("POST", "/query") => {
+            let request: QueryRequest = serde_json::from_slice(body).map_err(|e| bad_request(format!("invalid query: {}", e)))?;
+            query(&request, state)
+        }
+        ("GET", "/series") => {
+            let (target, from, to) = infinity_params(params).map_err(bad_request)?;
+            let series = series(&target, &state.history().range(from, to), from).ok_or_else(|| unknown(&target))?;
+            Ok(series
+                .into_iter()
+                .flat_map(|(label, points)| {
+                    points
+                        .into_iter()
+                        .map(move |(time, value)| json!({ "time": time, "series": label, "value": value }))
+                })
+                .collect())
+        }
+        ("GET", "/table") => {
+            let (target, from, to) = infinity_params(params).map_err(bad_request)?;
+            Ok(Value::Array(table(&target, from, to, state)?))
+        }
+        _ => Err(("404 Not Found", "not found".to_string())),
+    }
+}
+
+fn unknown(target: &str) -> (&'static str, String) {
+    ("400 Bad Request", format!("unknown target: {}", target))
+}
+
+/// Target and range of an Infinity request; the range defaults to the last hour
+fn infinity_params(params: &Params) -> Result<(String, SystemTime, SystemTime), String> {
+    let target = params.target.clone().ok_or("missing target")?;
+    let time = |value: &Option<String>| -> Result<Option<SystemTime>, String> {
+        value
+            .as_deref()
+            .map(|ms| {
+                ms.parse::<u64>()
+                    .map(|ms| UNIX_EPOCH + Duration::from_millis(ms))
+                    .map_err(|_| format!("not a unix millisecond time: {}", ms))
+            })
+            .transpose()
+    };
+    let to = time(&params.to)?.unwrap_or_else(SystemTime::now);
+    let from = time(&params.from)?.unwrap_or_else(|| to - HISTORY_WINDOW);
+    Ok((target, from, to))
+}
+
+fn query(request: &QueryRequest, state: &ControlState) -> Result<Value, (&'static str, String)> {
+    let from = SystemTime::from(request.range.from);
+    let to = SystemTime::from(request.range.to);
+    let points = state.history().range(from, to);
+    let mut results = Vec::new();
+    for target in request.targets.iter().filter(|t| !t.hide && !t.target.is_empty()) {
+        if let Some(series) = series(&target.target, &points, from) {
+            for (label, points) in series {
+                let datapoints: Vec<Value> = points.into_iter().map(|(time, value)| json!([value, time])).collect();
+                results.push(json!({ "target": label, "datapoints": datapoints }));
+            }
+        } else {
+            let rows = table(&target.target, from, to, state)?;
+            results.push(grafana_table(&rows));
+        }
+    }
+    Ok(Value::Array(results))
+}
+
+/// Rows of objects as a JSON datasource table, columns taken from the first row
+fn grafana_table(rows: &[Value]) -> Value {
+    let names: Vec<&String> = rows.first().and_then(Value::as_object).map(|row| row.keys().collect()).unwrap_or_default();
+    let columns: Vec<Value> = names
+        .iter()
+        .map(|name| {
+            let kind = match rows[0][name.as_str()] {
+                Value::Number(_) => "number",
+                _ => "string",
+            };
+            json!({ "text": name, "type": kind })
+        })
+        .collect();
+    let rows: Vec<Value> = rows.iter().map(|row| names.iter().map(|name| row[name.as_str()].clone()).collect()).collect();
+    json!({ "type": "table", "columns": columns, "rows": rows })
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn secs_between(start: SystemTime, end: SystemTime) -> f64 {
+    end.duration_since(start).map(|d| d.as_secs_f64()).unwrap_or(0.0).max(0.001)
+}
+
+/// Series label → (unix ms, per-second value), None for an unknown target
+fn series(target: &str, points: &[WindowPoint], from: SystemTime) -> Option<BTreeMap<String, Vec<(u64, f64)>>> {
+    if !SERIES.iter().any(|(name, _)| *name == target) {
+        return None;
+    }
+    let mut out: BTreeMap<String, Vec<(u64, f64)>> = BTreeMap::new();
+
+    let counter: Option<fn(&PacketCounters) -> u64> = match target {
+        "packets.rx" => Some(|c| c.rx_packets),
+        "packets.tx" => Some(|c| c.tx_packets),
+        "bytes.rx" => Some(|c| c.rx_bytes),
+        "bytes.tx" => Some(|c| c.tx_bytes),
+        "drops.kernel" => Some(|c| c.drop_count),
+        _ => None,
+    };
+    if let Some(counter) = counter {
+        let values = out.entry(target.to_string()).or_default();
+        for pair in points.windows(2) {
+            let (Some(before), Some(after)) = (&pair[0].counters, &pair[1].counters) else {
+                continue;
+            };
+            // Counters restart from zero when the programs are reloaded
+            let delta = counter(after).saturating_sub(counter(before));
+            values.push((unix_ms(pair[1].end), delta as f64 / secs_between(pair[0].end, pair[1].end)));
+        }
+        return Some(out);
+    }
+
+    // A window ending before the range only anchors kernel counter deltas
+    let points = points.iter().filter(|p| p.end >= from);
+    let counts: Vec<(&WindowPoint, BTreeMap<String, u64>)> = points.map(|p| (p, window_counts(target, &p.summary))).collect();
+    // Zero-fill so a label missing from one window dips to 0 rather than leaving a gap
+    let labels: BTreeSet<&String> = counts.iter().flat_map(|(_, c)| c.keys()).collect();
+    for label in labels {
+        let values = counts
+            .iter()
+            .map(|(p, c)| (unix_ms(p.end), c.get(label).copied().unwrap_or(0) as f64 / secs_between(p.start, p.end)))
+            .collect();
+        out.insert(label.clone(), values);
+    }
+    Some(out)
+}
+
+/// Counts of one window for a pipeline target, by series label
+fn window_counts(target: &str, s: &Summary) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    let mut add = |label: &str, count: u64| *counts.entry(label.to_string()).or_insert(0) += count;
+    match target {
+        "events" => add(target, s.events),
+        "drops" => add(target, s.drops_by_reason.values().sum()),
+        "drops.by_reason" => s.drops_by_reason.iter().for_each(|(&r, &n)| add(drop_reason_str(r), n)),
+        "drops.by_interface" => s.drops_by_interface.iter().for_each(|(i, &n)| add(i, n)),
+        "netfilter.drops" => s.nf_drops_by_hook.iter().for_each(|(&h, &n)| add(nf_hook_str(h), n)),
+        "resets" => {
+            add("in", s.resets_in);
+            add("out", s.resets_out);
+        }
+        "flows.opened" => add(target, s.flows_opened),
+        "flows.closed" => add(target, s.flows_closed),
+        _ => {}
+    }
+    counts
+}
+
+/// Rows of a table target
+fn table(target: &str, from: SystemTime, to: SystemTime, state: &ControlState) -> Result<Vec<Value>, (&'static str, String)> {
+    let totals = |counts: fn(&Summary) -> Vec<(String, u64)>| {
+        let mut totals: BTreeMap<String, u64> = BTreeMap::new();
+        let points = state.history().range(from, to);
+        for point in points.iter().filter(|p| p.end >= from) {
+            for (label, count) in counts(&point.summary) {
+                *totals.entry(label).or_insert(0) += count;
+            }
+        }
+        totals
+    };
+    let range_secs = secs_between(from, to);
+    match target {
+        "flows" => {
+            let flows = crate::ebpf::read_pinned_flows().map_err(|e| ("503 Service Unavailable", format!("{:#}", e)))?;
+            Ok(flows
+                .iter()
+                .filter_map(|(k, i)| serde_json::to_value(crate::api::FlowRecord::new(k, i)).ok())
+                .collect())
+        }
+        "drop_reasons" => Ok(totals(|s| s.drops_by_reason.iter().map(|(&r, &n)| (drop_reason_str(r).to_string(), n)).collect())
+            .into_iter()
+            .map(|(reason, drops)| json!({ "reason": reason, "drops": drops, "perSecond": drops as f64 / range_secs }))
+            .collect()),
+        "interfaces" => Ok(totals(|s| s.drops_by_interface.iter().map(|(i, &n)| (i.clone(), n)).collect())
+            .into_iter()
+            .map(|(interface, drops)| json!({ "interface": interface, "drops": drops, "perSecond": drops as f64 / range_secs }))
+            .collect()),
+        _ => Err(unknown(target)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    /// Windows of 10s ending at 10, 20 and 30s
+    fn history() -> History {
+        let history = History::default();
+        for (i, reason) in [5u32, 2, 2].into_iter().enumerate() {
+            let start = 10 * i as u64;
+            let summary = Summary {
+                window_start: Some(at(start)),
+                window_end: Some(at(start + 10)),
+                events: 20,
+                drops_by_reason: [(reason, 20)].into(),
+                ..Default::default()
+            };
+            let counters = PacketCounters { rx_packets: 1000 * (i as u64 + 1), ..Default::default() };
+            history.record(&summary, Some(counters));
+        }
+        history
+    }
+
+    #[test]
+    fn test_series() {
+        let points = history().range(at(15), at(30));
+        assert_eq!(points.len(), 3, "the window before the range anchors the deltas");
+
+        let rx = series("packets.rx", &points, at(15)).unwrap();
+        assert_eq!(rx["packets.rx"], [(20_000, 100.0), (30_000, 100.0)]);
+
+        // Zero-filled where a reason is missing from a window
+        let reasons = series("drops.by_reason", &points, at(15)).unwrap();
+        assert_eq!(reasons.len(), 1);
+        assert_eq!(reasons["NO_SOCKET"], [(20_000, 2.0), (30_000, 2.0)]);
+        let reasons = series("drops.by_reason", &points, at(0)).unwrap();
+        assert_eq!(reasons["SOCKET_FILTER"], [(10_000, 2.0), (20_000, 0.0), (30_000, 0.0)]);
+        assert!(series("drops.by_pod", &points, at(0)).is_none());
+    }
+
+    #[test]
+    fn test_query_response() {
+        let state = ControlState::new(Default::default(), None, Default::default(), Default::default());
+        state.history().record(&Summary::default(), None);
+
+        let body = br#"{"range":{"from":"1970-01-01T00:00:00Z","to":"2100-01-01T00:00:00Z"},
+            "targets":[{"target":"events","refId":"A"},{"target":"drop_reasons","refId":"B"},{"target":"resets","hide":true}]}"#;
+        let reply = respond("POST", "/query", &Params::default(), body, &state).unwrap();
+        let results = reply.as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["target"], "events");
+        assert_eq!(results[0]["datapoints"][0][0], 0.0);
+        assert_eq!(results[1]["type"], "table");
+
+        let search = respond("POST", "/search", &Params::default(), b"", &state).unwrap();
+        assert!(search.as_array().unwrap().contains(&json!("drops.by_interface")));
+        assert_eq!(respond("GET", "/", &Params::default(), b"", &state).unwrap()["status"], "ok");
+
+        let params = Params { target: Some("nope".into()), ..Default::default() };
+        assert_eq!(respond("GET", "/series", &params, b"", &state).unwrap_err().0, "400 Bad Request");
+        assert_eq!(respond("POST", "/query", &params, b"{", &state).unwrap_err().0, "400 Bad Request");
+    }
+}
@@ -0,0 +1,421 @@
+//! Local gRPC API
+//!
+//! The control socket methods as a gRPC service, for programs on the node
+//! that would rather use generated clients than speak the socket's JSON
+//! lines: a custom autoscaler, a security daemon. The service is defined in
+//! `proto/local.proto` (package `sennet.local.v1`):
+//!
+//! ```text
+//! GetStatus    → Status
+//! GetCounters  → Counters
+//! ListFlows    → ListFlowsResponse
+//! GetDrops     → Drops
+//! Subscribe    → stream Event    (until the client cancels)
+//! ```
+//!
+//! It is off unless `grpc_listen` is set. The API has no authentication of
+//! its own, so it is served on a Unix socket that, like the control socket,
+//! has mode 0660 and belongs to `control_socket_group`.
+//!
+//! The messages and the service trait are generated from the proto by
+//! tonic-build (build.rs); tonic serves them.
+
+use anyhow::{Context, Result};
+use futures::stream::{self, Stream, StreamExt};
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::{Request, Response, Status};
+use tracing::warn;
+
+use crate::control::{ControlState, DropsReport, StreamRecord};
+use crate::ebpf::{comm_to_string, dns_name, format_addr, format_ip, FlowInfo, FlowKey, PacketCounters};
+use crate::pipeline::{RawEvent, RingKind};
+use proto::local_service_server::{LocalService, LocalServiceServer};
+
+/// Bind the gRPC socket, readable by `group` like the control socket
+pub fn bind(path: &Path, group: &str) -> Result<UnixListener> {
+    crate::control::bind(path, group).context("Failed to bind gRPC API")
+}
+
+/// Serve the API until the task is aborted
+pub async fn serve(listener: UnixListener, state: Arc<ControlState>) {
+    let listener = match listener
+        .set_nonblocking(true)
+        .and_then(|()| tokio::net::UnixListener::from_std(listener))
+    {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("gRPC API unavailable: {}", e);
+            return;
+        }
+    };
+    // Back off after a failed accept (out of file descriptors) rather than spin
+    let incoming = UnixListenerStream::new(listener).then(|socket| async move {
+        if let Err(e) = &socket {
+            warn!("gRPC accept failed: {}", e);
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        socket
+    });
+    let result = tonic::transport::Server::builder()
+        .add_service(LocalServiceServer::new(LocalApi { state }))
+        .serve_with_incoming(incoming)
+        .await;
+    if let Err(e) = result {
+        warn!("gRPC API stopped: {}", e);
+    }
+}
+
+/// `LocalService` over the daemon's control state
+struct LocalApi {
+    state: Arc<ControlState>,
+}
+
+#[tonic::async_trait]
+impl LocalService for LocalApi {
+    async fn get_status(&self, _: Request<proto::GetStatusRequest>) -> Result<Response<proto::Status>, Status> {
+        let state = self.state.clone();
+        blocking(move || Ok(proto::Status::from(state.status()))).await
+    }
+
+    async fn get_counters(&self, _: Request<proto::GetCountersRequest>) -> Result<Response<proto::Counters>, Status> {
+        blocking(|| Ok(proto::Counters::from(crate::ebpf::read_pinned_counters().map_err(unavailable)?))).await
+    }
+
+    async fn list_flows(
+        &self,
+        _: Request<proto::ListFlowsRequest>,
+    ) -> Result<Response<proto::ListFlowsResponse>, Status> {
+        blocking(|| {
+            let flows = crate::ebpf::read_pinned_flows().map_err(unavailable)?;
+            Ok(proto::ListFlowsResponse { flows: flows.iter().map(|(k, i)| proto::Flow::new(k, i)).collect() })
+        })
+        .await
+    }
+
+    async fn get_drops(&self, _: Request<proto::GetDropsRequest>) -> Result<Response<proto::Drops>, Status> {
+        let state = self.state.clone();
+        blocking(move || Ok(proto::Drops::from(state.drops()))).await
+    }
+
+    type SubscribeStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+    /// Stream events until the client cancels, which drops the stream, or
+    /// the event source goes away
+    async fn subscribe(
+        &self,
+        request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let kinds = parse_kinds(&request.into_inner().kinds)?;
+        let subscription = self.state.subscribe().only(kinds);
+        let events = stream::unfold(subscription, |mut subscription| async move {
+            let records = subscription.next().await?;
+            let events: Vec<_> = records.iter().map(|record| Ok(proto::Event::from(record))).collect();
+            Some((stream::iter(events), subscription))
+        })
+        .flatten();
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Answer a unary call off the runtime; the state and map reads block
+async fn blocking<T, F>(read: F) -> Result<Response<T>, Status>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Status> + Send + 'static,
+{
+    tokio::task::spawn_blocking(read)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map(Response::new)
+}
+
+fn unavailable(e: anyhow::Error) -> Status {
+    Status::unavailable(format!("{:#}", e))
+}
+
+/// Event kinds named in a SubscribeRequest
+fn parse_kinds(names: &[String]) -> Result<Vec<RingKind>, Status> {
+    names
+        .iter()
+        .map(|name| RingKind::from_name(name).ok_or_else(|| Status::invalid_argument(format!("unknown event kind: {}", name))))
+        .collect()
+}
+
+// ============================================================================
+// Messages
+// ============================================================================
+
+/// Messages and service of `proto/local.proto`, generated by tonic-build
+/// (build.rs)
+pub mod proto {
+    include!(concat!(env!("OUT_DIR"), "/sennet.local.v1.rs"));
+}
+
+impl From<crate::control::DaemonStatus> for proto::Status {
+    fn from(s: crate::control::DaemonStatus) -> Self {
+        Self {
+            version: s.version,
+            agent_id: s.agent_id,
+            pid: s.pid,
+            uptime_secs: s.uptime_secs,
+            interface: s.interface,
+            ebpf_attached: s.ebpf_attached,
+            offline: s.offline,
+            heartbeat_age_secs: s.heartbeat_age_secs,
+            pipeline: s.pipeline,
+            labels: s.labels.into_iter().collect(),
+        }
+    }
+}
+
+impl From<PacketCounters> for proto::Counters {
+    fn from(c: PacketCounters) -> Self {
+        Self {
+            rx_packets: c.rx_packets,
+            rx_bytes: c.rx_bytes,
+            tx_packets: c.tx_packets,
+            tx_bytes: c.tx_bytes,
+            drop_count: c.drop_count,
+        }
+    }
+}
+
+impl proto::Flow {
+    fn new(key: &FlowKey, info: &FlowInfo) -> Self {
+        Self {
+            src_ip: format_ip(key.src_ip),
+            dst_ip: format_ip(key.dst_ip),
+            src_port: key.src_port.into(),
+            dst_port: key.dst_port.into(),
+            protocol: key.protocol.into(),
+            pid: info.pid,
+            tgid: info.tgid,
+            comm: comm_to_string(&info.comm),
+            start_time_ns: info.start_time_ns,
+            rx_bytes: info.rx_bytes,
+            tx_bytes: info.tx_bytes,
+            rx_packets: info.rx_packets,
+            tx_packets: info.tx_packets,
+            state: info.state.into(),
+            direction: info.direction.into(),
+        }
+    }
+}
+
+impl From<DropsReport> for proto::Drops {
+    fn from(d: DropsReport) -> Self {
+        Self {
+            drop_count: d.drop_count,
+            events_lost: d.events_lost.into_iter().collect(),
+            last_window_json: d.last_window.map(|w| w.to_string()).unwrap_or_default(),
+            by_reason: d.by_reason.into_iter().collect(),
+        }
+    }
+}
+
+impl From<&StreamRecord> for proto::Event {
+    fn from(record: &StreamRecord) -> Self {
+        use proto::event::Event;
+        let (raw, count, interface, time) = match record {
+            StreamRecord::Event { raw, count, interface, time } => {
+                (&**raw, *count, interface.clone().unwrap_or_default(), time.clone().unwrap_or_default())
+            }
+            StreamRecord::Gap { ring, lost } => {
+                return Self {
+                    event: Some(Event::Gap(proto::Gap { ring: ring.clone(), lost: *lost })),
+                    ..Default::default()
+                };
+            }
+        };
+        let event = match raw {
+            RawEvent::Drop(e) => Event::Drop(proto::DropEvent {
+                reason: e.reason,
+                ifindex: e.ifindex,
+                protocol: e.protocol.into(),
+                src_ip: if e.has_tuple() { format_ip(e.src_ip) } else { String::new() },
+                dst_ip: if e.has_tuple() { format_ip(e.dst_ip) } else { String::new() },
+                src_port: e.src_port.into(),
+                dst_port: e.dst_port.into(),
+                ip_protocol: e.ip_protocol.into(),
+                packet_src_ip: if e.has_packet_tuple() { format_addr(&e.src_addr) } else { String::new() },
+                packet_dst_ip: if e.has_packet_tuple() { format_addr(&e.dst_addr) } else { String::new() },
+                packet_src_port: e.packet_src_port.into(),
+                packet_dst_port: e.packet_dst_port.into(),
+            }),
+            RawEvent::Netfilter(e) => Event::Netfilter(proto::NetfilterEvent {
+                hook: e.hook.into(),
+                pf: e.pf.into(),
+                verdict: e.verdict.into(),
+                ifindex_in: e.ifindex_in,
+                ifindex_out: e.ifindex_out,
+                in_iface: crate::ifnames::lookup(e.ifindex_in).map(|n| n.to_string()).unwrap_or_default(),
+                out_iface: crate::ifnames::lookup(e.ifindex_out).map(|n| n.to_string()).unwrap_or_default(),
+            }),
+            RawEvent::Flow(e) => Event::Flow(proto::FlowEvent {
+                event_type: e.event_type.into(),
+                direction: e.direction.into(),
+                protocol: e.protocol.into(),
+                pid: e.pid,
+                src_ip: format_ip(e.src_ip),
+                dst_ip: format_ip(e.dst_ip),
+                src_port: e.src_port.into(),
+                dst_port: e.dst_port.into(),
+                comm: comm_to_string(&e.comm),
+            }),
+            RawEvent::Rst(e) => Event::Rst(proto::RstEvent {
+                src_ip: format_ip(e.src_ip),
+                dst_ip: format_ip(e.dst_ip),
+                src_port: e.src_port.into(),
+                dst_port: e.dst_port.into(),
+                ifindex: e.ifindex,
+                direction: e.direction.into(),
+                tcp_flags: e.tcp_flags.into(),
+            }),
+            RawEvent::Packet(e) => {
+                let ip = e.eth_proto == 0x0800 || e.eth_proto == 0x86DD;
+                Event::Packet(proto::PacketEvent {
+                    event_type: e.event_type,
+                    size: e.size,
+                    src_ip: if ip { format_addr(&e.src_addr) } else { String::new() },
+                    dst_ip: if ip { format_addr(&e.dst_addr) } else { String::new() },
+                    eth_proto: e.eth_proto.into(),
+                    protocol: e.protocol.into(),
+                    ifindex: e.ifindex,
+                    direction: e.direction.into(),
+                    pps: e.pps,
+                    src_port: e.src_port.into(),
+                    dst_port: e.dst_port.into(),
+                })
+            }
+            RawEvent::Retransmit(e) => Event::Retransmit(proto::RetransmitEvent {
+                src_ip: format_addr(&e.src_addr),
+                dst_ip: format_addr(&e.dst_addr),
+                src_port: e.src_port.into(),
+                dst_port: e.dst_port.into(),
+                state: e.state.into(),
+            }),
+            RawEvent::Dns(e) => Event::Dns(proto::DnsEvent {
+                client_ip: format_addr(&e.client_addr),
+                server_ip: format_addr(&e.server_addr),
+                client_port: e.client_port.into(),
+                id: e.id.into(),
+                qname: dns_name(&e.qname),
+                qtype: e.qtype.into(),
+                response: e.is_response != 0,
+                rcode: e.rcode.into(),
+                latency_ns: e.latency_ns,
+            }),
+            RawEvent::Conntrack(e) => Event::Conntrack(proto::ConntrackEvent {
+                src_ip: format_addr(&e.src_addr),
+                dst_ip: format_addr(&e.dst_addr),
+                src_port: e.src_port.into(),
+                dst_port: e.dst_port.into(),
+                reply_src_ip: format_addr(&e.reply_src_addr),
+                reply_dst_ip: format_addr(&e.reply_dst_addr),
+                reply_src_port: e.reply_src_port.into(),
+                reply_dst_port: e.reply_dst_port.into(),
+                protocol: e.protocol.into(),
+                destroyed: e.event_type == sennet_common::ct_event::DESTROY,
+                status: e.status,
+            }),
+            RawEvent::Payload(e) => Event::Payload(proto::PayloadEvent {
+                src_ip: format_addr(&e.src_addr),
+                dst_ip: format_addr(&e.dst_addr),
+                src_port: e.src_port.into(),
+                dst_port: e.dst_port.into(),
+                protocol: e.protocol.into(),
+                len: e.len,
+                data: e.snippet().to_vec(),
+                ifindex: e.ifindex,
+                direction: e.direction.into(),
+            }),
+            RawEvent::Http(e) => Event::Http(proto::HttpEvent {
+                src_ip: format_addr(&e.src_addr),
+                dst_ip: format_addr(&e.dst_addr),
+                src_port: e.src_port.into(),
+                dst_port: e.dst_port.into(),
+                method: sennet_common::http_method_str(e.method).to_string(),
+                path: e.path(),
+                truncated: e.truncated != 0,
+                ifindex: e.ifindex,
+                direction: e.direction.into(),
+            }),
+        };
+        Self { timestamp_ns: raw.timestamp_ns(), count, interface, time, event: Some(event) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::DaemonStatus;
+    use crate::events::DropEvent;
+    use crate::pipeline::EnrichedEvent;
+    use proto::local_service_client::LocalServiceClient;
+    use tokio::sync::broadcast;
+    use tonic::transport::{Channel, Endpoint, Uri};
+
+    #[test]
+    fn test_parse_kinds() {
+        assert_eq!(parse_kinds(&["drop".into(), "rst".into()]).unwrap(), [RingKind::Drop, RingKind::Rst]);
+        assert_eq!(parse_kinds(&["drops".into()]).unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
+
+    /// Client of the API on the Unix socket at `path`
+    async fn connect(path: &Path) -> LocalServiceClient<Channel> {
+        let path = path.to_path_buf();
+        let channel = Endpoint::from_static("http://localhost")
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                let path = path.clone();
+                async move { Ok::<_, std::io::Error>(hyper_util::rt::TokioIo::new(tokio::net::UnixStream::connect(path).await?)) }
+            }))
+            .await
+            .unwrap();
+        LocalServiceClient::new(channel)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_serve_status_and_subscribe() {
+        let (tap, _) = broadcast::channel(16);
+        let status = DaemonStatus { version: "9.9.9".into(), pid: 7, ..Default::default() };
+        let state = ControlState::new(status, Some(tap.clone()), Default::default(), Default::default());
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("grpc.sock");
+        let listener = bind(&path, "sennet").unwrap();
+        // Only the agent's user and group can connect
+        let mode = std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&path).unwrap().permissions());
+        assert_eq!(mode & 0o777, 0o660);
+        let server = tokio::spawn(serve(listener, state));
+
+        let mut client = connect(&path).await;
+        let status = client.get_status(proto::GetStatusRequest {}).await.unwrap().into_inner();
+        assert_eq!((status.version.as_str(), status.pid), ("9.9.9", 7));
+
+        let bad_kind = proto::SubscribeRequest { kinds: vec!["bogus".into()] };
+        let error = client.subscribe(bad_kind).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+
+        // Publish until the subscription is in place and an event comes back
+        let publisher = tokio::spawn(async move {
+            let drop = DropEvent { timestamp_ns: 5, reason: 2, ifindex: 3, ..Default::default() };
+            loop {
+                let _ = tap.send(EnrichedEvent::new(RawEvent::Drop(drop), Some("eth0".into())));
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        let request = proto::SubscribeRequest { kinds: vec!["drop".into()] };
+        let mut events = client.subscribe(request).await.unwrap().into_inner();
+        let event = tokio::time::timeout(Duration::from_secs(5), events.message()).await.unwrap().unwrap().unwrap();
+        assert_eq!((event.timestamp_ns, event.interface.as_str()), (5, "eth0"));
+        assert!(matches!(event.event, Some(proto::event::Event::Drop(proto::DropEvent { reason: 2, ifindex: 3, .. }))));
+
+        publisher.abort();
+        server.abort();
+    }
+}
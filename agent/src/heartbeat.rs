@@ -4,37 +4,74 @@
 
 use anyhow::Result;
 use backoff::ExponentialBackoff;
+use std::sync::atomic::Ordering;
 use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 
+use crate::asn::{self, AsnDb};
+use crate::audit::{self, AuditLog, Outcome};
+use crate::budget::{self, MemoryBudget};
 use crate::client::{Command, HeartbeatRequest, MetricsSummary, SentinelClient};
 use crate::config::Config;
+use crate::conntrack;
+use crate::drops::SharedDrops;
 use crate::identity::IdentityManager;
+use crate::events::RingKind;
+use crate::latency::{self, SharedLatency};
+use crate::retransmits::SharedRetransmits;
+use crate::selfmetrics;
 use crate::upgrade::Updater;
 
 // Linux-only: imports for reading eBPF metrics from pinned maps
 #[cfg(target_os = "linux")]
 use crate::ebpf::PacketCounters;
-#[cfg(target_os = "linux")]
-use aya::maps::{Map, MapData, PerCpuArray};
-#[cfg(target_os = "linux")]
-use std::path::Path;
 
 /// Heartbeat loop that runs continuously
 pub struct HeartbeatLoop {
     config: Config,
     identity: IdentityManager,
     client: SentinelClient,
+    latency: SharedLatency,
+    retransmits: SharedRetransmits,
+    drops: SharedDrops,
+    asn_db: Option<AsnDb>,
+    memory_budget_bytes: u64,
+    audit: AuditLog,
     start_time: Instant,
 }
 
+/// Number of ASNs reported per heartbeat
+const TOP_ASNS: usize = 10;
+
+/// Number of retransmit destinations reported per heartbeat
+const TOP_RETRANSMITS: usize = 10;
+
 impl HeartbeatLoop {
     /// Create a new heartbeat loop
-    pub fn new(config: Config, identity: IdentityManager, client: SentinelClient) -> Self {
+    pub fn new(
+        config: Config,
+        identity: IdentityManager,
+        client: SentinelClient,
+        latency: SharedLatency,
+        retransmits: SharedRetransmits,
+        drops: SharedDrops,
+    ) -> Self {
+        let asn_db = AsnDb::load_default(config.asn_db_path.as_deref(), &config.state_dir);
+        if let Some(db) = &asn_db {
+            info!("ASN database loaded ({} ranges)", db.len());
+        }
+        let memory_budget_bytes = MemoryBudget::resolve(config.memory_budget_mb).total_bytes();
+        let audit = AuditLog::new(&config.state_dir);
         Self {
             config,
             identity,
             client,
+            latency,
+            retransmits,
+            drops,
+            asn_db,
+            memory_budget_bytes,
+            audit,
             start_time: Instant::now(),
         }
     }
@@ -48,10 +85,12 @@ impl HeartbeatLoop {
         loop {
             match self.send_heartbeat() {
                 Ok(response) => {
+                    selfmetrics::global().heartbeat_succeeded();
                     info!("Heartbeat successful, command: {:?}", response.command);
                     self.handle_command(&response.command, &response.latest_version);
                 }
                 Err(e) => {
+                    selfmetrics::global().heartbeats_failed.fetch_add(1, Ordering::Relaxed);
                     warn!("Heartbeat failed: {}", e);
                 }
             }
@@ -66,6 +105,8 @@ impl HeartbeatLoop {
             agent_id: self.identity.agent_id().to_string(),
             current_version: self.identity.version().to_string(),
             metrics: Some(self.collect_metrics()),
+            pod: self.identity.pod().cloned(),
+            labels: self.config.labels.clone(),
         };
 
         // Use exponential backoff for retries
@@ -77,101 +118,154 @@ impl HeartbeatLoop {
         };
 
         let client = &self.client;
-        backoff::retry(backoff_config, || {
-            match client.heartbeat(&request) {
-                Ok(resp) => Ok(resp),
+        let result = backoff::retry(backoff_config, || {
+            let start = Instant::now();
+            let result = client.heartbeat(&request);
+            let mut latency = self.latency.lock().unwrap_or_else(|e| e.into_inner());
+            match result {
+                Ok(resp) => {
+                    latency.record(latency::CONTROL_PLANE, start.elapsed());
+                    Ok(resp)
+                }
                 Err(e) => {
+                    latency.record_failure(latency::CONTROL_PLANE);
                     warn!("Heartbeat attempt failed, retrying: {}", e);
                     Err(backoff::Error::transient(e))
                 }
             }
         })
-        .map_err(|e| anyhow::anyhow!("Heartbeat failed after retries: {}", e))
+        .map_err(|e| anyhow::anyhow!("Heartbeat failed after retries: {}", e));
+
+        if let Err(e) = self.latency.lock().unwrap_or_else(|e| e.into_inner()).save() {
+            debug!("Could not persist latency history: {}", e);
+        }
+
+        result
     }
 
-    /// Collect current metrics from eBPF maps (Linux) or return zeros (other platforms)
+    /// Collect current metrics from eBPF maps (Linux), interface counters
+    /// (Windows, macOS) or return zeros (other platforms)
     fn collect_metrics(&self) -> MetricsSummary {
         let uptime = self.start_time.elapsed().as_secs();
         
+        // Fallback: zeros (no eBPF or interface counters available)
+        let mut metrics = MetricsSummary {
+            uptime_seconds: uptime,
+            memory_rss_bytes: budget::resident_bytes().unwrap_or(0),
+            memory_budget_bytes: self.memory_budget_bytes,
+            ..Default::default()
+        };
+        
         #[cfg(target_os = "linux")]
         {
             // Try to read from pinned eBPF maps
             match Self::read_ebpf_counters() {
                 Ok(counters) => {
-                    return MetricsSummary {
-                        rx_packets: counters.rx_packets,
-                        rx_bytes: counters.rx_bytes,
-                        tx_packets: counters.tx_packets,
-                        tx_bytes: counters.tx_bytes,
-                        drop_count: counters.drop_count,
-                        uptime_seconds: uptime,
-                    };
+                    metrics.rx_packets = counters.rx_packets;
+                    metrics.rx_bytes = counters.rx_bytes;
+                    metrics.tx_packets = counters.tx_packets;
+                    metrics.tx_bytes = counters.tx_bytes;
+                    metrics.drop_count = counters.drop_count;
                 }
                 Err(e) => {
                     debug!("Could not read eBPF counters: {}", e);
                 }
             }
+            // Absent on kernels without the inet_sock_set_state tracepoint
+            metrics.connection_churn = crate::ebpf::read_pinned_conn_churn().ok();
         }
         
-        // Fallback: return zeros (eBPF not available or not Linux)
-        MetricsSummary {
-            rx_packets: 0,
-            rx_bytes: 0,
-            tx_packets: 0,
-            tx_bytes: 0,
-            drop_count: 0,
-            uptime_seconds: uptime,
-        }
-    }
-    
-    /// Read packet counters from pinned eBPF maps (Linux only)
-    #[cfg(target_os = "linux")]
-    fn read_ebpf_counters() -> Result<PacketCounters> {
-        let pin_path = Path::new("/sys/fs/bpf/sennet/counters");
-        if !pin_path.exists() {
-            anyhow::bail!("Pinned map not found");
+        #[cfg(any(windows, target_os = "macos"))]
+        match crate::ifstats::read_counters(self.config.interface.as_deref()) {
+            Ok(counters) => {
+                metrics.rx_packets = counters.rx_packets;
+                metrics.rx_bytes = counters.rx_bytes;
+                metrics.tx_packets = counters.tx_packets;
+                metrics.tx_bytes = counters.tx_bytes;
+                metrics.drop_count = counters.drop_count;
+            }
+            Err(e) => {
+                debug!("Could not read interface counters: {}", e);
+            }
         }
         
-        let map_data = MapData::from_pin(pin_path)?;
-        let map = Map::PerCpuArray(map_data);
-        let counters: PerCpuArray<_, PacketCounters> = map.try_into()?;
-        
-        let mut total = PacketCounters::default();
+        if let Some(ct) = conntrack::read_stats() {
+            metrics.conntrack_count = ct.count;
+            metrics.conntrack_max = ct.max;
+            metrics.conntrack_drops = ct.exhaustion_drops();
+            metrics.conntrack_early_drops = ct.early_drop;
+        }
         
-        // Read ingress counters (index 0)
-        if let Ok(values) = counters.get(&0, 0) {
-            for cpu_val in values.iter() {
-                total.rx_packets += cpu_val.rx_packets;
-                total.rx_bytes += cpu_val.rx_bytes;
-                total.drop_count += cpu_val.drop_count;
+        {
+            let latency = self.latency.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(hist) = latency.get(latency::CONTROL_PLANE) {
+                metrics.heartbeat_rtt_p50_ms = hist.p50().unwrap_or(0.0);
+                metrics.heartbeat_rtt_p95_ms = hist.p95().unwrap_or(0.0);
             }
+            metrics.latency_targets = latency.target_summaries();
         }
         
-        // Read egress counters (index 1)
-        if let Ok(values) = counters.get(&1, 0) {
-            for cpu_val in values.iter() {
-                total.tx_packets += cpu_val.tx_packets;
-                total.tx_bytes += cpu_val.tx_bytes;
+        if let Some(db) = &self.asn_db {
+            match crate::ebpf::read_pinned_flows() {
+                Ok(flows) => {
+                    let mut usage = asn::aggregate(db, &flows);
+                    usage.truncate(TOP_ASNS);
+                    metrics.top_asns = usage;
+                }
+                Err(e) => debug!("Could not read flows for ASN attribution: {}", e),
             }
         }
         
-        Ok(total)
+        metrics.top_retransmits = self.retransmits.top(TOP_RETRANSMITS);
+        metrics.drops_by_reason = self
+            .drops
+            .take_recent()
+            .top(usize::MAX)
+            .into_iter()
+            .map(|(reason, count)| (reason.to_string(), count))
+            .collect();
+
+        let lost = selfmetrics::global().events_lost();
+        metrics.events_lost = RingKind::ALL
+            .iter()
+            .filter(|kind| lost[kind.index()] > 0)
+            .map(|kind| (kind.name().to_string(), lost[kind.index()]))
+            .collect();
+        
+        metrics
+    }
+    
+    /// Read packet counters from pinned eBPF maps (Linux only)
+    #[cfg(target_os = "linux")]
+    fn read_ebpf_counters() -> Result<PacketCounters> {
+        crate::ebpf::read_pinned_counters()
     }
 
-    /// Handle commands from the server
+    /// Handle commands from the server, if `allowed_commands` permits
     fn handle_command(&self, command: &Command, latest_version: &str) {
+        if *command == Command::CommandNoop {
+            debug!("No action required");
+            return;
+        }
+        let name = command.name();
+        self.audit.record(name, Outcome::Received, None);
+        if !audit::is_allowed(&self.config.allowed_commands, name) {
+            warn!("Ignoring {} command from the control plane: not in allowed_commands", name);
+            self.audit.record(name, Outcome::Denied, Some("not in allowed_commands"));
+            return;
+        }
+
         match command {
-            Command::CommandNoop => {
-                debug!("No action required");
-            }
             Command::CommandUpgrade => {
                 info!("Upgrade available: {} -> {}", self.identity.version(), latest_version);
+                let target = format!("{} -> {}", self.identity.version(), latest_version);
                 // Perform self-update
                 match Updater::new() {
                     Ok(updater) => {
                         match updater.upgrade() {
                             Ok(()) => {
                                 info!("Upgrade successful! Restarting...");
+                                self.audit.record(name, Outcome::Succeeded, Some(&target));
                                 // Exec into new binary to restart
                                 #[cfg(unix)]
                                 {
@@ -187,11 +281,13 @@ impl HeartbeatLoop {
                             }
                             Err(e) => {
                                 error!("Upgrade failed: {}", e);
+                                self.audit.record(name, Outcome::Failed, Some(&format!("{}: {}", target, e)));
                             }
                         }
                     }
                     Err(e) => {
                         error!("Failed to initialize updater: {}", e);
+                        self.audit.record(name, Outcome::Failed, Some(&e.to_string()));
                     }
                 }
             }
@@ -199,10 +295,9 @@ impl HeartbeatLoop {
                 info!("Reconfiguration requested");
                 // TODO: Implement config reload
                 warn!("Config reload not yet implemented");
+                self.audit.record(name, Outcome::Failed, Some("config reload not implemented"));
             }
-            Command::CommandUnspecified => {
-                warn!("Received unspecified command");
-            }
+            Command::CommandNoop | Command::CommandUnspecified => {}
         }
     }
 }
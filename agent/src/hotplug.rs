@@ -0,0 +1,174 @@
+//! Interface Hotplug
+//!
+//! Attaches the classifiers to interfaces that appear while the agent runs,
+//! such as a VM's NIC after migration, a VPN tunnel coming up or container
+//! veths, so they are counted without a restart. Only interfaces the agent
+//! would have attached to at start are picked up: every up, non-loopback
+//! interface with `all_interfaces`, or those listed in `interfaces`.
+//! Link notifications come from an rtnetlink (RTMGRP_LINK) subscription.
+
+use crate::ifnames::{IFINFOMSG_LEN, IFLA_IFNAME, RTM_DELLINK, RTM_NEWLINK};
+use crate::nftables::{attr_str, split_messages, Attrs};
+
+// net_device flags (ifi_flags)
+const IFF_UP: u32 = 0x1;
+const IFF_LOOPBACK: u32 = 0x8;
+
+/// A link notification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkEvent {
+    /// Created, renamed, or its flags changed
+    Changed { index: u32, name: String, up: bool, loopback: bool },
+    Removed { index: u32, name: String },
+}
+
+/// Link notifications in an rtnetlink receive buffer
+pub fn parse_link_events(buf: &[u8]) -> Vec<LinkEvent> {
+    let Ok((messages, _)) = split_messages(buf) else {
+        return Vec::new();
+    };
+    messages
+        .into_iter()
+        .filter_map(|(kind, body)| {
+            // ifi_index and ifi_flags follow family, padding and type
+            let index = u32::from_ne_bytes(body.get(4..8)?.try_into().ok()?);
+            let flags = u32::from_ne_bytes(body.get(8..12)?.try_into().ok()?);
+            let name = || Attrs::get(body.get(IFINFOMSG_LEN..)?, IFLA_IFNAME).map(attr_str);
+            match kind {
+                RTM_NEWLINK => Some(LinkEvent::Changed {
+                    index,
+                    name: name()?,
+                    up: flags & IFF_UP != 0,
+                    loopback: flags & IFF_LOOPBACK != 0,
+                }),
+                RTM_DELLINK => Some(LinkEvent::Removed { index, name: name()? }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Whether the agent attaches to a link reported by `event`, given the
+/// `interfaces` and `all_interfaces` settings
+pub fn wanted(event: &LinkEvent, interfaces: &[String], all: bool) -> bool {
+    match event {
+        LinkEvent::Changed { name, up, loopback, .. } => {
+            *up && if interfaces.is_empty() { all && !loopback } else { interfaces.contains(name) }
+        }
+        LinkEvent::Removed { .. } => false,
+    }
+}
+
+/// Forward link notifications from a background thread until the receiver
+/// is dropped
+#[cfg(target_os = "linux")]
+pub fn watch() -> std::io::Result<tokio::sync::mpsc::Receiver<LinkEvent>> {
+    use std::os::fd::AsRawFd;
+
+    let socket = crate::ifnames::subscribe()?;
+    let (tx, rx) = tokio::sync::mpsc::channel(64);
+    std::thread::Builder::new().name("sennet-hotplug".into()).spawn(move || {
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            // SAFETY: buf is valid for writes of buf.len() bytes
+            let n = unsafe { libc::recv(socket.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if n < 0 {
+                let err = std::io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    // The socket overflowed; re-list so no new interface is missed
+                    Some(libc::ENOBUFS) => {
+                        let listed = crate::interface::list_interfaces().unwrap_or_default();
+                        for info in listed {
+                            let event = LinkEvent::Changed {
+                                index: info.index,
+                                name: info.name,
+                                up: info.is_up,
+                                loopback: info.is_loopback,
+                            };
+                            if tx.blocking_send(event).is_err() {
+                                return;
+                            }
+                        }
+                        continue;
+                    }
+                    _ => {
+                        tracing::warn!("Interface hotplug stopped: {}", err);
+                        return;
+                    }
+                }
+            }
+            for event in parse_link_events(&buf[..n as usize]) {
+                if tx.blocking_send(event).is_err() {
+                    return;
+                }
+            }
+        }
+    })?;
+    Ok(rx)
+}
+
+/// Attach to the link `event` reports if wanted, or forget a removed one
+#[cfg(target_os = "linux")]
+pub fn apply(manager: &mut crate::ebpf::EbpfManager, event: LinkEvent, interfaces: &[String], all: bool) {
+    match &event {
+        LinkEvent::Changed { name, .. } if wanted(&event, interfaces, all) => match manager.attach_interface(name) {
+            Ok(true) => tracing::info!("Attached to new interface {}", name),
+            Ok(false) => {}
+            Err(e) => tracing::warn!("Failed to attach to new interface {}: {:#}", name, e),
+        },
+        LinkEvent::Changed { .. } => {}
+        LinkEvent::Removed { index, name } => {
+            if manager.forget_interface(*index, name) {
+                tracing::info!("Attached interface {} was removed", name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Netlink message with an ifinfomsg for `index` and optional IFLA_IFNAME
+    fn link_message(kind: u16, index: u32, flags: u32, name: Option<&str>) -> Vec<u8> {
+        let mut body = vec![0u8; IFINFOMSG_LEN];
+        body[4..8].copy_from_slice(&index.to_ne_bytes());
+        body[8..12].copy_from_slice(&flags.to_ne_bytes());
+        if let Some(name) = name {
+            body.extend_from_slice(&((name.len() + 5) as u16).to_ne_bytes());
+            body.extend_from_slice(&IFLA_IFNAME.to_ne_bytes());
+            body.extend_from_slice(name.as_bytes());
+            body.push(0);
+            body.resize((body.len() + 3) & !3, 0);
+        }
+        let mut msg = Vec::new();
+        msg.extend_from_slice(&((body.len() + 16) as u32).to_ne_bytes());
+        msg.extend_from_slice(&kind.to_ne_bytes());
+        msg.extend_from_slice(&[0u8; 10]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    #[test]
+    fn test_parse_and_filter_link_events() {
+        let buf = [
+            link_message(RTM_NEWLINK, 7, IFF_UP, Some("wg0")),
+            link_message(RTM_NEWLINK, 8, 0, Some("veth1a2b")),
+            link_message(RTM_NEWLINK, 1, IFF_UP | IFF_LOOPBACK, Some("lo")),
+            link_message(RTM_DELLINK, 5, 0, Some("tap0")),
+        ]
+        .concat();
+        let events = parse_link_events(&buf);
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[3], LinkEvent::Removed { index: 5, name: "tap0".to_string() });
+
+        let all: Vec<bool> = events.iter().map(|e| wanted(e, &[], true)).collect();
+        assert_eq!(all, vec![true, false, false, false]);
+        // Listed interfaces only, whatever all_interfaces says
+        let listed = ["veth1a2b".to_string(), "lo".to_string()];
+        let listed: Vec<bool> = events.iter().map(|e| wanted(e, &listed, true)).collect();
+        assert_eq!(listed, vec![false, false, true, false]);
+        assert!(!wanted(&events[0], &[], false));
+    }
+}
@@ -0,0 +1,259 @@
+//! Local HTTP Endpoint
+//!
+//! Opt-in listener (`http_listen`) for things that poll the agent:
+//!
+//! - `GET /metrics`: self-metrics in Prometheus format, plus event counters
+//!   when the `prometheus` pipeline sink is configured
+//! - `GET /healthz`: liveness, 200 while the process is serving requests
+//! - `GET /readyz`: readiness, 200 once eBPF programs are attached and the
+//!   control plane answered a heartbeat within `ready_heartbeat_window_secs`
+//!   (in offline mode only the eBPF check applies)
+//!
+//! A minimal HTTP/1.0 responder: one request per connection, no keep-alive,
+//! which is all scrapers and kubelet probes need.
+
+use serde::Serialize;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, info, warn};
+
+use crate::selfmetrics::{self, SelfMetrics};
+
+/// Readiness verdict, returned as the `/readyz` body
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Readiness {
+    pub ready: bool,
+    pub ebpf_attached: bool,
+    /// Seconds since the last successful heartbeat (None = none yet)
+    pub heartbeat_age_secs: Option<u64>,
+}
+
+impl Readiness {
+    /// `window` is None in offline mode, where no heartbeat is expected
+    pub fn check(metrics: &SelfMetrics, window: Option<Duration>) -> Self {
+        let ebpf_attached = metrics.ebpf_attached();
+        let heartbeat_age_secs = metrics.heartbeat_age_secs();
+        let reachable = match window {
+            Some(window) => heartbeat_age_secs.is_some_and(|age| age <= window.as_secs()),
+            None => true,
+        };
+        Self {
+            ready: ebpf_attached && reachable,
+            ebpf_attached,
+            heartbeat_age_secs,
+        }
+    }
+}
+
+/// Serve the endpoints on `addr` until the task is aborted
+pub async fn serve(addr: String, ready_window: Option<Duration>) {
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to bind HTTP listener on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("Serving /metrics, /healthz and /readyz on http://{}", addr);
+
+    loop {
+        let (mut stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                debug!("HTTP listener accept failed: {}", e);
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            let mut request = [0u8; 1024];
+            let read = match tokio::time::timeout(Duration::from_secs(5), stream.read(&mut request)).await {
+                Ok(Ok(n)) => n,
+                _ => return,
+            };
+            let response = respond(request_path(&request[..read]), ready_window).await;
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+async fn respond(path: Option<&str>, ready_window: Option<Duration>) -> String {
+    match path {
+        Some("/metrics") => {
+            let body = tokio::task::spawn_blocking(|| {
+                let mut text = selfmetrics::global().snapshot().to_prometheus();
+                text.extend(crate::sinks::prometheus_text());
+                text
+            })
+            .await
+            .unwrap_or_default();
+            http_response("200 OK", "text/plain; version=0.0.4", &body)
+        }
+        Some("/healthz") => http_response("200 OK", "text/plain", "ok\n"),
+        Some("/readyz") => {
+            let readiness = Readiness::check(selfmetrics::global(), ready_window);
+            let status = if readiness.ready { "200 OK" } else { "503 Service Unavailable" };
+            let body = serde_json::to_string(&readiness).unwrap_or_default();
+            http_response(status, "application/json", &body)
+        }
+        Some(_) => http_response("404 Not Found", "text/plain", "not found\n"),
+        None => http_response("400 Bad Request", "text/plain", "bad request\n"),
+    }
+}
+
+/// Path of a `GET` request, without query string
+fn request_path(request: &[u8]) -> Option<&str> {
+    Request::parse(request).filter(|r| r.method == "GET").map(|r| r.path)
+}
+
+/// A parsed `GET` or `POST` request head
+#[derive(Debug)]
+#[cfg_attr(not(unix), allow(dead_code))] // Query and headers are read by the REST API
+pub struct Request<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    query: &'a str,
+    headers: &'a str,
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+impl<'a> Request<'a> {
+    /// Parse the request line and headers; None unless it is a `GET` or `POST`
+    ///
+    /// `request` may run past the head into the body.
+    pub fn parse(request: &'a [u8]) -> Option<Self> {
+        let end = request.windows(4).position(|w| w == b"\r\n\r\n").unwrap_or(request.len());
+        let text = std::str::from_utf8(&request[..end]).ok()?;
+        let (line, headers) = text.split_once("\r\n").unwrap_or((text, ""));
+        let mut parts = line.split_whitespace();
+        let method = parts.next()?;
+        if method != "GET" && method != "POST" {
+            return None;
+        }
+        let target = parts.next()?;
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        Some(Self { method, path, query, headers })
+    }
+
+    /// Declared body length: 0 without a `Content-Length` header, None if
+    /// it doesn't parse
+    pub fn content_length(&self) -> Option<usize> {
+        match self.header("Content-Length") {
+            Some(value) => value.parse().ok(),
+            None => Some(0),
+        }
+    }
+
+    /// Value of a header, matching the name case-insensitively
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    /// Percent-decoded value of a query parameter
+    pub fn param(&self, name: &str) -> Option<String> {
+        self.query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key == name).then(|| percent_decode(value))
+        })
+    }
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+pub fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.0 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_path() {
+        assert_eq!(request_path(b"GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n"), Some("/metrics"));
+        assert_eq!(request_path(b"GET /readyz?verbose=1 HTTP/1.1\r\n"), Some("/readyz"));
+        assert_eq!(request_path(b"POST /metrics HTTP/1.1\r\n"), None);
+        assert_eq!(request_path(b""), None);
+    }
+
+    #[test]
+    fn test_post_request() {
+        let raw = b"POST /api/v1/grafana/query HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}";
+        let request = Request::parse(raw).unwrap();
+        assert_eq!((request.method, request.path), ("POST", "/api/v1/grafana/query"));
+        assert_eq!(request.content_length(), Some(2));
+        assert_eq!(request.header("Host"), None);
+        assert_eq!(Request::parse(b"GET / HTTP/1.1\r\n\r\n").unwrap().content_length(), Some(0));
+        assert!(Request::parse(b"DELETE / HTTP/1.1\r\n\r\n").is_none());
+    }
+
+    #[test]
+    fn test_request_query_and_headers() {
+        let raw = b"GET /api/v1/events?follow=1&token=a%2Fb+c HTTP/1.1\r\nHost: x\r\nauthorization: Bearer s3cret\r\n\r\n";
+        let request = Request::parse(raw).unwrap();
+        assert_eq!(request.path, "/api/v1/events");
+        assert_eq!(request.param("follow").as_deref(), Some("1"));
+        assert_eq!(request.param("token").as_deref(), Some("a/b c"));
+        assert_eq!(request.param("kinds"), None);
+        assert_eq!(request.header("Authorization"), Some("Bearer s3cret"));
+        assert_eq!(request.header("Cookie"), None);
+    }
+
+    #[test]
+    fn test_readiness_needs_ebpf_and_recent_heartbeat() {
+        let window = Some(Duration::from_secs(300));
+        let metrics = SelfMetrics::default();
+        assert!(!Readiness::check(&metrics, window).ready);
+
+        metrics.set_ebpf_attached(true);
+        let readiness = Readiness::check(&metrics, window);
+        assert!(!readiness.ready);
+        assert_eq!(readiness.heartbeat_age_secs, None);
+
+        // Offline: no heartbeat expected
+        assert!(Readiness::check(&metrics, None).ready);
+
+        metrics.heartbeat_succeeded();
+        assert!(Readiness::check(&metrics, window).ready);
+    }
+
+    #[tokio::test]
+    async fn test_healthz_always_ok() {
+        let response = respond(Some("/healthz"), Some(Duration::from_secs(300))).await;
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nok\n"));
+    }
+}
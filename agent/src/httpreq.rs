@@ -0,0 +1,245 @@
+//! HTTP Request CLI Command
+//!
+//! Watches the HTTP/1.x request lines the classifier samples on the ports
+//! listed in `http_ports` and summarizes them: request rate, methods, the
+//! busiest paths and servers. Only the request line of a segment is read,
+//! so TLS, HTTP/2 and requests split across segments aren't seen.
+//! Usage: sennet http [OPTIONS]
+
+use anyhow::Result;
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+
+use crate::ebpf::{describe_http, ip_addr, HttpEvent};
+
+/// Options for the http command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpOptions {
+    pub timeout_secs: u64,
+    pub limit: usize,
+    /// Print every request as it is seen
+    pub follow: bool,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 10,
+            limit: 10,
+            follow: false,
+        }
+    }
+}
+
+/// Parse command line arguments for the http command
+pub fn parse_args(args: &[String]) -> HttpOptions {
+    let mut opts = HttpOptions::default();
+    let mut i = 0;
+
+    while i < args.len() {
+        match args[i].as_str() {
+            "--timeout" | "-t" if i + 1 < args.len() => {
+                opts.timeout_secs = args[i + 1].parse().unwrap_or(10);
+                i += 1;
+            }
+            "--limit" if i + 1 < args.len() => {
+                opts.limit = args[i + 1].parse().unwrap_or(10);
+                i += 1;
+            }
+            "--follow" | "-f" => opts.follow = true,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    opts
+}
+
+/// Print help for the http command
+pub fn print_help() {
+    println!("{}", "Sennet HTTP - Sampled HTTP/1.x Requests".bold());
+    println!("Capture request lines on the configured ports and report methods, paths and servers.");
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet http [OPTIONS]");
+    println!();
+    println!("{}", "OPTIONS:".yellow());
+    println!("    -t, --timeout <SECS>   Capture duration (default: 10)");
+    println!("    --limit <N>            Paths and servers to list (default: 10)");
+    println!("    -f, --follow           Print each request as it is seen");
+    println!("    -h, --help             Show this help message");
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sudo sennet http           # Ten seconds of requests");
+    println!("    sudo sennet http -f -t 60  # Follow requests for a minute");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    - Requires the agent to be running with http_ports set (reads pinned eBPF maps)");
+    println!("    - Requires root: paths can carry tokens, so only this command reads them");
+    println!("    - Plain HTTP/1.x only; TLS and HTTP/2 requests are not seen");
+    println!("    - Paths are listed without their query string");
+    println!("    - Counts honor kernel sampling and rate limits (rate_limits.http)");
+}
+
+/// Aggregated report of observed requests
+#[derive(Debug, Default)]
+pub struct HttpReport {
+    pub requests: u64,
+    /// Method → requests
+    pub methods: BTreeMap<&'static str, u64>,
+    /// "METHOD /path" (query string dropped) → requests
+    pub paths: BTreeMap<String, u64>,
+    /// Server address and port → requests
+    pub servers: BTreeMap<SocketAddr, u64>,
+}
+
+impl HttpReport {
+    /// Count one ring event, weighted by its kernel sample rate
+    pub fn add(&mut self, event: &HttpEvent) {
+        let weight = u64::from(event.sample_rate.max(1));
+        let method = sennet_common::http_method_str(event.method);
+        let path = event.path();
+        let path = path.split('?').next().unwrap_or_default();
+
+        self.requests += weight;
+        *self.methods.entry(method).or_insert(0) += weight;
+        *self.paths.entry(format!("{} {}", method, path)).or_insert(0) += weight;
+        let server = SocketAddr::new(ip_addr(&event.dst_addr), event.dst_port);
+        *self.servers.entry(server).or_insert(0) += weight;
+    }
+
+    pub fn print(&self, elapsed_secs: f64, limit: usize) {
+        println!();
+        println!("{}", "HTTP Summary".bold());
+        println!("{}", "─".repeat(70));
+        if self.requests == 0 {
+            println!("{}", "No HTTP requests observed.".green());
+            return;
+        }
+
+        println!("  Requests     {:>8}  ({:.1}/s)", self.requests, self.requests as f64 / elapsed_secs.max(1.0));
+        let methods: Vec<String> = self.methods.iter().map(|(method, n)| format!("{} {}", method, n)).collect();
+        println!("  Methods      {}", methods.join(", "));
+
+        println!();
+        println!("{}", "Top paths:".bold());
+        for (path, count) in top(&self.paths, limit) {
+            println!("  {:>8}  {}", count, path);
+        }
+
+        println!();
+        println!("{}", "Top servers:".bold());
+        for (server, count) in top(&self.servers, limit) {
+            println!("  {:>8}  {}", count, server);
+        }
+    }
+}
+
+/// Busiest `limit` entries of `counts`
+fn top<K>(counts: &BTreeMap<K, u64>, limit: usize) -> Vec<(&K, u64)> {
+    let mut entries: Vec<_> = counts.iter().map(|(key, &count)| (key, count)).collect();
+    entries.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    entries.truncate(limit);
+    entries
+}
+
+/// Run the http command
+pub fn run(args: &[String]) -> Result<()> {
+    let opts = parse_args(args);
+
+    println!("{}", "Sennet HTTP".bold());
+    println!("Capturing HTTP requests for {}s...", opts.timeout_secs.to_string().yellow());
+    println!("{}", "─".repeat(70));
+
+    #[cfg(target_os = "linux")]
+    {
+        run_linux(&opts)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        println!("{}: HTTP capture requires Linux with eBPF support", "Error".red());
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run_linux(opts: &HttpOptions) -> Result<()> {
+    use crate::ebpf::open_pinned_ringbuf;
+    use crate::events::RingKind;
+    use std::time::{Duration, Instant};
+
+    let mut http_rb = open_pinned_ringbuf(RingKind::Http.pin_name()).map_err(|e| {
+        anyhow::anyhow!("{}\nIs the agent running with an eBPF build that supports HTTP sampling?", e)
+    })?;
+
+    let mut report = HttpReport::default();
+    let start = Instant::now();
+    let timeout = Duration::from_secs(opts.timeout_secs);
+    let mut losses = crate::ebpf::LossTracker::new();
+    let mut total_lost = 0;
+
+    while start.elapsed() < timeout {
+        while let Some(item) = http_rb.next() {
+            if let Some(event) = crate::events::view_event::<HttpEvent>(&item) {
+                if opts.follow {
+                    println!("{}", describe_http(event));
+                }
+                report.add(event);
+            }
+        }
+        total_lost += losses.poll()[RingKind::Http.index()];
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    report.print(start.elapsed().as_secs_f64(), opts.limit);
+    if report.requests == 0 {
+        println!("Requests are only sampled on the ports in http_ports; see `sennet http --help`.");
+    }
+    if total_lost > 0 {
+        println!("{}: {} HTTP events were lost during capture; counts above are incomplete", "Warning".yellow(), total_lost);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sennet_common::http_method;
+
+    fn event(method: u8, path: &str, server: [u8; 4], port: u16) -> HttpEvent {
+        let mut e = HttpEvent {
+            method,
+            dst_addr: sennet_common::ipv4_mapped(server),
+            dst_port: port,
+            path_len: path.len() as u16,
+            ..Default::default()
+        };
+        e.path[..path.len()].copy_from_slice(path.as_bytes());
+        e
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = HttpReport::default();
+        report.add(&event(http_method::GET, "/api/users?id=1", [10, 0, 0, 5], 8080));
+        report.add(&event(http_method::GET, "/api/users?id=2", [10, 0, 0, 5], 8080));
+        report.add(&HttpEvent { sample_rate: 4, ..event(http_method::POST, "/login", [10, 0, 0, 6], 80) });
+
+        assert_eq!(report.requests, 6);
+        assert_eq!(report.methods.get("GET"), Some(&2));
+        assert_eq!(report.methods.get("POST"), Some(&4));
+        // Query strings are dropped
+        assert_eq!(report.paths.get("GET /api/users"), Some(&2));
+        let top_servers = top(&report.servers, 1);
+        assert_eq!(top_servers, vec![(&"10.0.0.6:80".parse::<SocketAddr>().unwrap(), 4)]);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args: Vec<String> = ["-t", "30", "--limit", "5", "-f"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(parse_args(&args), HttpOptions { timeout_secs: 30, limit: 5, follow: true });
+        assert_eq!(parse_args(&[]), HttpOptions::default());
+    }
+}
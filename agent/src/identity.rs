@@ -9,6 +9,7 @@ use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 use crate::config::Config;
+use crate::daemonset::PodIdentity;
 
 /// Agent identity state
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,6 +27,8 @@ pub struct IdentityState {
 /// Manages agent identity persistence
 pub struct IdentityManager {
     state: IdentityState,
+    /// Node and pod names when running as a DaemonSet (not persisted)
+    pod: Option<PodIdentity>,
     #[allow(dead_code)]
     state_path: PathBuf,
 }
@@ -50,7 +53,7 @@ impl IdentityManager {
             state
         };
 
-        Ok(Self { state, state_path })
+        Ok(Self { state, pod: PodIdentity::from_env(), state_path })
     }
 
     /// Get the agent ID
@@ -63,6 +66,11 @@ impl IdentityManager {
         &self.state.version
     }
 
+    /// Get the node and pod this agent runs as, if in a DaemonSet
+    pub fn pod(&self) -> Option<&PodIdentity> {
+        self.pod.as_ref()
+    }
+
     /// Load state from file
     fn load_state(path: &Path) -> Result<IdentityState> {
         let content = fs::read_to_string(path)
@@ -111,10 +119,49 @@ mod tests {
         Config {
             api_key: "sk_test123".to_string(),
             server_url: "https://test.example.com".to_string(),
+            offline: false,
             log_level: "info".to_string(),
+            log_format: Default::default(),
+            log_journald: true,
+            log_file: None,
+            log_file_max_size_mb: 10,
+            log_file_max_files: 5,
             interface: None,
+            interfaces: Vec::new(),
+            all_interfaces: false,
+            attach_mode: Default::default(),
+            packet_sample_one_in: 0,
+            large_packet_threshold: 9000,
+            source_pps_threshold: 0,
+            http_ports: Vec::new(),
+            enforcement: false,
             heartbeat_interval_secs: 30,
             state_dir,
+            conntrack_alert_pct: 90,
+            latency_targets: Vec::new(),
+            latency_probe_interval_secs: 60,
+            asn_db_path: None,
+            pipeline: Default::default(),
+            flow_rollup_interval_secs: 0,
+            flow_export_interval_secs: 0,
+            blackbox_minutes: 0,
+            rate_limits: Default::default(),
+            memory_budget_mb: 0,
+            http_listen: None,
+            ready_heartbeat_window_secs: 300,
+            otlp_endpoint: None,
+            crash_report_upload: false,
+            drop_privileges: true,
+            keep_pinned_maps: false,
+            pin_namespace: None,
+            disabled_probes: Vec::new(),
+            control_socket: None,
+            control_socket_group: "sennet".to_string(),
+            grpc_listen: None,
+            api_listen: None,
+            api_token: None,
+            labels: Default::default(),
+            allowed_commands: vec!["upgrade".to_string(), "reconfigure".to_string()],
             config_path: PathBuf::new(),
         }
     }
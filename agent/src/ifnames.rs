@@ -0,0 +1,272 @@
+//! Interface Name Cache
+//!
+//! Drop, netfilter and reset events carry raw ifindex values. This is the
+//! process-wide ifindex→name table used wherever events are displayed or
+//! exported. On Linux a background thread keeps it current from rtnetlink
+//! link notifications (RTMGRP_LINK), so interfaces that come and go, like
+//! container veths, resolve without re-listing /sys/class/net. Elsewhere, or
+//! if the subscription fails, a miss re-lists the interfaces at most every
+//! `REFRESH_INTERVAL`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Once, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::nftables::{attr_str, split_messages, Attrs};
+
+/// Minimum time between full re-listings on a miss
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+// rtnetlink link messages
+pub(crate) const RTM_NEWLINK: u16 = 16;
+pub(crate) const RTM_DELLINK: u16 = 17;
+pub(crate) const IFLA_IFNAME: u16 = 3;
+/// struct ifinfomsg, which precedes the link attributes
+pub(crate) const IFINFOMSG_LEN: usize = 16;
+
+/// A link notification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkChange {
+    /// Created or renamed
+    Added(u32, String),
+    Removed(u32),
+}
+
+/// Link changes in an rtnetlink receive buffer
+pub fn parse_link_changes(buf: &[u8]) -> Vec<LinkChange> {
+    let Ok((messages, _)) = split_messages(buf) else {
+        return Vec::new();
+    };
+    messages
+        .into_iter()
+        .filter_map(|(kind, body)| {
+            // ifi_index follows family, padding and type
+            let index = u32::from_ne_bytes(body.get(4..8)?.try_into().ok()?);
+            match kind {
+                RTM_NEWLINK => {
+                    let name = Attrs::get(body.get(IFINFOMSG_LEN..)?, IFLA_IFNAME).map(attr_str)?;
+                    Some(LinkChange::Added(index, name))
+                }
+                RTM_DELLINK => Some(LinkChange::Removed(index)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// ifindex→name table
+pub struct IfNames {
+    names: RwLock<HashMap<u32, Arc<str>>>,
+    last_refresh: Mutex<Option<Instant>>,
+}
+
+impl IfNames {
+    fn new() -> Self {
+        Self { names: RwLock::new(HashMap::new()), last_refresh: Mutex::new(None) }
+    }
+
+    /// Name of `ifindex` if it is in the table, without refreshing
+    pub fn cached(&self, ifindex: u32) -> Option<Arc<str>> {
+        self.names.read().unwrap_or_else(|e| e.into_inner()).get(&ifindex).cloned()
+    }
+
+    /// Name of `ifindex`; None for 0 or an interface that doesn't exist
+    pub fn get(&self, ifindex: u32) -> Option<Arc<str>> {
+        if ifindex == 0 {
+            return None;
+        }
+        if let Some(name) = self.cached(ifindex) {
+            return Some(name);
+        }
+        // Not watching, or the event beat its link notification
+        if self.refresh_due() {
+            self.reload();
+        }
+        self.cached(ifindex)
+    }
+
+    fn refresh_due(&self) -> bool {
+        let mut last = self.last_refresh.lock().unwrap_or_else(|e| e.into_inner());
+        if last.is_some_and(|t| t.elapsed() < REFRESH_INTERVAL) {
+            return false;
+        }
+        *last = Some(Instant::now());
+        true
+    }
+
+    /// Replace the table with a fresh interface listing
+    fn reload(&self) {
+        if let Ok(ifaces) = crate::interface::list_interfaces() {
+            let names = ifaces.into_iter().map(|i| (i.index, Arc::from(i.name))).collect();
+            *self.names.write().unwrap_or_else(|e| e.into_inner()) = names;
+        }
+    }
+
+    fn apply(&self, changes: Vec<LinkChange>) {
+        let mut names = self.names.write().unwrap_or_else(|e| e.into_inner());
+        for change in changes {
+            match change {
+                LinkChange::Added(index, name) => {
+                    names.insert(index, Arc::from(name));
+                }
+                LinkChange::Removed(index) => {
+                    names.remove(&index);
+                }
+            }
+        }
+    }
+}
+
+/// The process-wide cache; the first call loads it and starts watching
+pub fn cache() -> &'static IfNames {
+    static CACHE: OnceLock<IfNames> = OnceLock::new();
+    static WATCH: Once = Once::new();
+
+    let cache = CACHE.get_or_init(|| {
+        let names = IfNames::new();
+        names.reload();
+        names
+    });
+    WATCH.call_once(|| watch(cache));
+    cache
+}
+
+/// Name of `ifindex` from the process-wide cache
+pub fn lookup(ifindex: u32) -> Option<Arc<str>> {
+    cache().get(ifindex)
+}
+
+/// Name for output: "eth0", "if7" if unknown, "-" for none (0)
+pub fn display(ifindex: u32) -> String {
+    match (ifindex, lookup(ifindex)) {
+        (0, _) => "-".to_string(),
+        (_, Some(name)) => name.to_string(),
+        (index, None) => format!("if{}", index),
+    }
+}
+
+/// Apply link notifications to `cache` on a background thread
+#[cfg(target_os = "linux")]
+fn watch(cache: &'static IfNames) {
+    use std::os::fd::AsRawFd;
+
+    let socket = match subscribe() {
+        Ok(socket) => socket,
+        Err(e) => {
+            tracing::debug!("Not watching link changes, interface names refresh on misses: {}", e);
+            return;
+        }
+    };
+    // Links created between the first listing and the subscription
+    cache.reload();
+
+    let spawned = std::thread::Builder::new()
+        .name("sennet-ifnames".into())
+        .spawn(move || {
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                // SAFETY: buf is valid for writes of buf.len() bytes
+                let n = unsafe { libc::recv(socket.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+                if n < 0 {
+                    let err = std::io::Error::last_os_error();
+                    match err.raw_os_error() {
+                        Some(libc::EINTR) => continue,
+                        // The socket overflowed and notifications were lost
+                        Some(libc::ENOBUFS) => cache.reload(),
+                        _ => {
+                            tracing::debug!("Link notifications stopped: {}", err);
+                            return;
+                        }
+                    }
+                    continue;
+                }
+                cache.apply(parse_link_changes(&buf[..n as usize]));
+            }
+        });
+    if let Err(e) = spawned {
+        tracing::debug!("Failed to start link watcher: {}", e);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch(_cache: &'static IfNames) {}
+
+/// rtnetlink socket subscribed to link notifications
+#[cfg(target_os = "linux")]
+pub(crate) fn subscribe() -> std::io::Result<std::os::fd::OwnedFd> {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    // SAFETY: plain socket(2) call; the fd is owned below
+    let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_ROUTE) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: fd is a fresh socket nothing else owns
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // SAFETY: sockaddr_nl is plain data; all-zero is a valid value
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = libc::RTMGRP_LINK as u32;
+    // SAFETY: addr is a valid sockaddr_nl of the given size
+    let rc = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            (&addr as *const libc::sockaddr_nl).cast(),
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if rc < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Netlink message with an ifinfomsg for `index` and optional IFLA_IFNAME
+    fn link_message(kind: u16, index: u32, name: Option<&str>) -> Vec<u8> {
+        let mut body = vec![0u8; IFINFOMSG_LEN];
+        body[4..8].copy_from_slice(&index.to_ne_bytes());
+        if let Some(name) = name {
+            body.extend_from_slice(&((name.len() + 5) as u16).to_ne_bytes());
+            body.extend_from_slice(&IFLA_IFNAME.to_ne_bytes());
+            body.extend_from_slice(name.as_bytes());
+            body.push(0);
+            body.resize((body.len() + 3) & !3, 0);
+        }
+        let mut msg = Vec::new();
+        msg.extend_from_slice(&((body.len() + 16) as u32).to_ne_bytes());
+        msg.extend_from_slice(&kind.to_ne_bytes());
+        msg.extend_from_slice(&[0u8; 10]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    #[test]
+    fn test_parse_link_changes() {
+        let buf = [
+            link_message(RTM_NEWLINK, 7, Some("veth1a2b")),
+            link_message(RTM_DELLINK, 5, None),
+            // Other rtnetlink messages are ignored
+            link_message(20, 9, None),
+        ]
+        .concat();
+        assert_eq!(
+            parse_link_changes(&buf),
+            vec![LinkChange::Added(7, "veth1a2b".into()), LinkChange::Removed(5)]
+        );
+    }
+
+    #[test]
+    fn test_apply_tracks_renames_and_removals() {
+        let names = IfNames::new();
+        names.apply(vec![LinkChange::Added(2, "eth0".into()), LinkChange::Added(7, "veth1".into())]);
+        names.apply(vec![LinkChange::Added(2, "lan0".into()), LinkChange::Removed(7)]);
+
+        assert_eq!(names.cached(2).as_deref(), Some("lan0"));
+        assert_eq!(names.cached(7), None);
+    }
+}
@@ -0,0 +1,224 @@
+//! Interface Counters (Windows, macOS)
+//!
+//! Without eBPF, `sennet top`, `status` and heartbeat metrics read the
+//! counters the OS keeps per interface: packets and bytes in each
+//! direction, plus packets the stack discarded. Windows provides them
+//! through `GetIfTable2`, macOS through the `NET_RT_IFLIST2` sysctl that
+//! `netstat -i` uses (64-bit counters). By default the totals cover every
+//! interface that is up, leaving out loopback and tunnels; with `interface`
+//! set in the config only that interface is counted.
+
+use anyhow::Result;
+
+use crate::ebpf::PacketCounters;
+
+/// Counters for one interface
+#[derive(Debug, Clone, Default)]
+pub struct InterfaceStats {
+    /// "Ethernet" (Windows alias, as in `Get-NetAdapter`) or "en0" (macOS)
+    pub alias: String,
+    pub index: u32,
+    pub up: bool,
+    /// Loopback and tunnel pseudo-interfaces
+    pub virtual_if: bool,
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    pub rx_discards: u64,
+    pub rx_errors: u64,
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+    pub tx_discards: u64,
+    pub tx_errors: u64,
+}
+
+impl InterfaceStats {
+    /// As the eBPF counters: discards count as drops
+    pub fn counters(&self) -> PacketCounters {
+        PacketCounters {
+            rx_packets: self.rx_packets,
+            rx_bytes: self.rx_bytes,
+            tx_packets: self.tx_packets,
+            tx_bytes: self.tx_bytes,
+            drop_count: self.rx_discards + self.tx_discards,
+            // The OS doesn't break counters down by protocol
+            ..Default::default()
+        }
+    }
+}
+
+/// Interfaces counted for `interface` (None = all up, non-virtual ones)
+pub fn select<'a>(interfaces: &'a [InterfaceStats], interface: Option<&str>) -> Vec<&'a InterfaceStats> {
+    interfaces
+        .iter()
+        .filter(|i| match interface {
+            Some(name) => i.alias.eq_ignore_ascii_case(name),
+            None => i.up && !i.virtual_if,
+        })
+        .collect()
+}
+
+/// Summed counters of the selected interfaces
+pub fn total(interfaces: &[&InterfaceStats]) -> PacketCounters {
+    interfaces.iter().fold(PacketCounters::default(), |mut sum, i| {
+        let c = i.counters();
+        sum.rx_packets += c.rx_packets;
+        sum.rx_bytes += c.rx_bytes;
+        sum.tx_packets += c.tx_packets;
+        sum.tx_bytes += c.tx_bytes;
+        sum.drop_count += c.drop_count;
+        sum
+    })
+}
+
+/// Totals for `interface` (see [`select`])
+pub fn read_counters(interface: Option<&str>) -> Result<PacketCounters> {
+    let interfaces = read_interfaces()?;
+    let selected = select(&interfaces, interface);
+    if let (Some(name), true) = (interface, selected.is_empty()) {
+        anyhow::bail!("Interface '{}' not found", name);
+    }
+    Ok(total(&selected))
+}
+
+#[cfg(any(windows, test))]
+fn wide_to_string(wide: &[u16]) -> String {
+    let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..len])
+}
+
+#[cfg(windows)]
+pub fn read_interfaces() -> Result<Vec<InterfaceStats>> {
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        FreeMibTable, GetIfTable2, IF_TYPE_SOFTWARE_LOOPBACK, IF_TYPE_TUNNEL, MIB_IF_TABLE2,
+    };
+    use windows_sys::Win32::NetworkManagement::Ndis::IfOperStatusUp;
+
+    let mut table: *mut MIB_IF_TABLE2 = std::ptr::null_mut();
+    // SAFETY: GetIfTable2 allocates the table and stores it in `table`
+    let status = unsafe { GetIfTable2(&mut table) };
+    if status != 0 {
+        anyhow::bail!("GetIfTable2 failed: {}", std::io::Error::from_raw_os_error(status as i32));
+    }
+
+    // SAFETY: on success `table` points to NumEntries rows
+    let rows = unsafe {
+        std::slice::from_raw_parts((*table).Table.as_ptr(), (*table).NumEntries as usize)
+    };
+    let interfaces = rows
+        .iter()
+        .map(|row| InterfaceStats {
+            alias: wide_to_string(&row.Alias),
+            index: row.InterfaceIndex,
+            up: row.OperStatus == IfOperStatusUp,
+            virtual_if: row.Type == IF_TYPE_SOFTWARE_LOOPBACK || row.Type == IF_TYPE_TUNNEL,
+            rx_packets: row.InUcastPkts + row.InNUcastPkts,
+            rx_bytes: row.InOctets,
+            rx_discards: row.InDiscards,
+            rx_errors: row.InErrors,
+            tx_packets: row.OutUcastPkts + row.OutNUcastPkts,
+            tx_bytes: row.OutOctets,
+            tx_discards: row.OutDiscards,
+            tx_errors: row.OutErrors,
+        })
+        .collect();
+
+    // SAFETY: `table` came from GetIfTable2 and is not used afterwards
+    unsafe { FreeMibTable(table as *const _) };
+    Ok(interfaces)
+}
+
+#[cfg(target_os = "macos")]
+pub fn read_interfaces() -> Result<Vec<InterfaceStats>> {
+    let mut mib = [libc::CTL_NET, libc::PF_ROUTE, 0, 0, libc::NET_RT_IFLIST2, 0];
+    let mut len: libc::size_t = 0;
+    // SAFETY: a null buffer asks sysctl for the size needed
+    if unsafe { libc::sysctl(mib.as_mut_ptr(), 6, std::ptr::null_mut(), &mut len, std::ptr::null_mut(), 0) } != 0 {
+        anyhow::bail!("sysctl NET_RT_IFLIST2 failed: {}", std::io::Error::last_os_error());
+    }
+    let mut buf = vec![0u8; len];
+    // SAFETY: buf holds len bytes
+    if unsafe { libc::sysctl(mib.as_mut_ptr(), 6, buf.as_mut_ptr().cast(), &mut len, std::ptr::null_mut(), 0) } != 0 {
+        anyhow::bail!("sysctl NET_RT_IFLIST2 failed: {}", std::io::Error::last_os_error());
+    }
+    buf.truncate(len);
+
+    let mut interfaces = Vec::new();
+    let mut offset = 0;
+    while offset + std::mem::size_of::<libc::if_msghdr2>() <= buf.len() {
+        // SAFETY: in bounds (checked above); the struct is packed, so read unaligned
+        let msg: libc::if_msghdr2 = unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr().cast()) };
+        let msglen = msg.ifm_msglen as usize;
+        if msglen == 0 {
+            break;
+        }
+        offset += msglen;
+        if msg.ifm_type as libc::c_int != libc::RTM_IFINFO2 {
+            continue;
+        }
+
+        let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+        // SAFETY: name has IF_NAMESIZE bytes, as if_indextoname requires
+        if unsafe { libc::if_indextoname(msg.ifm_index as libc::c_uint, name.as_mut_ptr()) }.is_null() {
+            continue;
+        }
+        // SAFETY: if_indextoname NUL-terminates on success
+        let alias = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned();
+        let data = msg.ifm_data;
+        let flags = msg.ifm_flags;
+        interfaces.push(InterfaceStats {
+            alias,
+            index: msg.ifm_index as u32,
+            up: flags & libc::IFF_UP != 0,
+            virtual_if: flags & (libc::IFF_LOOPBACK | libc::IFF_POINTOPOINT) != 0,
+            rx_packets: data.ifi_ipackets,
+            rx_bytes: data.ifi_ibytes,
+            rx_discards: data.ifi_iqdrops,
+            rx_errors: data.ifi_ierrors,
+            tx_packets: data.ifi_opackets,
+            tx_bytes: data.ifi_obytes,
+            tx_discards: msg.ifm_snd_drops.max(0) as u64,
+            tx_errors: data.ifi_oerrors,
+        });
+    }
+    Ok(interfaces)
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn read_interfaces() -> Result<Vec<InterfaceStats>> {
+    anyhow::bail!("Interface counters are only read this way on Windows and macOS")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_and_total() {
+        let ethernet = InterfaceStats {
+            alias: "Ethernet".to_string(),
+            up: true,
+            rx_packets: 10,
+            rx_bytes: 1000,
+            rx_discards: 2,
+            tx_packets: 5,
+            tx_discards: 1,
+            ..Default::default()
+        };
+        let loopback = InterfaceStats {
+            alias: "Loopback Pseudo-Interface 1".to_string(),
+            up: true,
+            virtual_if: true,
+            rx_packets: 99,
+            ..Default::default()
+        };
+        let wifi = InterfaceStats { alias: "Wi-Fi".to_string(), rx_packets: 7, ..Default::default() };
+        let all = vec![ethernet, loopback, wifi];
+
+        let counters = total(&select(&all, None));
+        assert_eq!((counters.rx_packets, counters.rx_bytes, counters.drop_count), (10, 1000, 3));
+        // Named interfaces are counted even when down
+        assert_eq!(total(&select(&all, Some("wi-fi"))).rx_packets, 7);
+        assert!(select(&all, Some("eth1")).is_empty());
+        assert_eq!(wide_to_string(&[0x45, 0x74, 0x68, 0, 0x78]), "Eth");
+    }
+}
@@ -81,7 +81,7 @@ pub fn run() -> Result<()> {
     println!("{}", "═══════════════════════════════════════".green());
     println!();
     println!("Next steps:");
-    println!("  1. Start the agent:  {}", "sudo systemctl start sennet".cyan());
+    println!("  1. Start the agent:  {}", crate::service::InitSystem::detect().hint("start").cyan());
     println!("  2. Check status:     {}", "sennet status".cyan());
     println!("  3. Monitor traffic:  {}", "sennet top".cyan());
     println!();
@@ -0,0 +1,537 @@
+//! Install and Uninstall
+//!
+//! `sennet install` copies the running binary to /usr/local/bin, creates the
+//! config and state directories, grants the binary the capabilities eBPF
+//! needs, writes a service definition for the detected init system and
+//! enables it. `sennet uninstall` stops the service and reverses each step.
+//!
+//! Both commands build a list of [`Step`]s first, so `--dry-run` prints
+//! exactly what would be done.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+
+use crate::service::{InitSystem, SERVICE_NAME};
+
+const BINARY_PATH: &str = "/usr/local/bin/sennet";
+const CONFIG_DIR: &str = "/etc/sennet";
+const STATE_DIR: &str = "/var/lib/sennet";
+
+/// Group allowed to use the control socket (`control_socket_group`)
+const CONTROL_GROUP: &str = "sennet";
+
+/// Capabilities set on the binary, so the agent runs without full root
+const FILE_CAPS: &str = "cap_bpf,cap_perfmon,cap_net_admin+ep";
+
+/// Options shared by install and uninstall
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub dry_run: bool,
+    /// Run the service as this user instead of root (install only)
+    pub user: Option<String>,
+    /// Keep /etc/sennet and /var/lib/sennet (uninstall only)
+    pub keep_data: bool,
+    /// Leave the binary where it is (used by install.sh, which places it)
+    pub skip_binary: bool,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--dry-run" | "-n" => options.dry_run = true,
+                "--user" | "-u" => options.user = Some(args.next().context("--user needs a value")?.clone()),
+                "--keep-data" => options.keep_data = true,
+                "--skip-binary" => options.skip_binary = true,
+                other => anyhow::bail!("Unknown option: {}", other),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// One change to the system
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    CopyBinary { from: PathBuf, to: PathBuf },
+    /// Create a directory with `mode`, owned by `owner` (root if unset)
+    CreateDir { path: PathBuf, mode: u32, owner: Option<String> },
+    WriteFile { path: PathBuf, contents: String, mode: u32 },
+    Symlink { target: PathBuf, link: PathBuf },
+    /// Run a command; failures are reported but not fatal
+    Run(Vec<String>),
+    Remove(PathBuf),
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::CopyBinary { from, to } => write!(f, "copy {} → {}", from.display(), to.display()),
+            Step::CreateDir { path, mode, owner } => write!(
+                f,
+                "create {} (mode {:o}, owner {})",
+                path.display(),
+                mode,
+                owner.as_deref().unwrap_or("root")
+            ),
+            Step::WriteFile { path, mode, .. } => write!(f, "write {} (mode {:o})", path.display(), mode),
+            Step::Symlink { target, link } => write!(f, "link {} → {}", link.display(), target.display()),
+            Step::Run(args) => write!(f, "run `{}`", args.join(" ")),
+            Step::Remove(path) => write!(f, "remove {}", path.display()),
+        }
+    }
+}
+
+fn run_step(args: &[&str]) -> Step {
+    Step::Run(args.iter().map(|a| a.to_string()).collect())
+}
+
+/// Files making up the service definition under `init`
+fn service_files(init: InitSystem, user: Option<&str>) -> Vec<(PathBuf, String, u32)> {
+    match init {
+        InitSystem::Systemd => vec![(
+            PathBuf::from(format!("/etc/systemd/system/{}.service", SERVICE_NAME)),
+            systemd_unit(user),
+            0o644,
+        )],
+        InitSystem::OpenRc => vec![(
+            PathBuf::from(format!("/etc/init.d/{}", SERVICE_NAME)),
+            openrc_script(user),
+            0o755,
+        )],
+        InitSystem::Runit => {
+            let dir = runit_dir();
+            vec![
+                (dir.join("run"), runit_run_script(user), 0o755),
+                (dir.join("log/run"), format!("#!/bin/sh\nexec svlogd -tt /var/log/{}\n", SERVICE_NAME), 0o755),
+            ]
+        }
+        InitSystem::SysVinit => vec![(
+            PathBuf::from(format!("/etc/init.d/{}", SERVICE_NAME)),
+            sysvinit_script(user),
+            0o755,
+        )],
+        InitSystem::Unknown => Vec::new(),
+    }
+}
+
+fn systemd_unit(user: Option<&str>) -> String {
+    let user_line = user.map(|u| format!("User={}\n", u)).unwrap_or_default();
+    format!(
+        "[Unit]
+Description=Sennet Network Observability Agent
+Documentation=https://github.com/MannanSaood/Sennet
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+Type=simple
+ExecStart={bin}
+{user_line}Restart=always
+RestartSec=10
+Environment=RUST_LOG=info
+
+# Security hardening
+NoNewPrivileges=false
+ProtectSystem=strict
+ProtectHome=true
+ReadWritePaths={state}
+ReadOnlyPaths={config}
+
+# eBPF requires these capabilities
+AmbientCapabilities=CAP_BPF CAP_PERFMON CAP_NET_ADMIN CAP_SYS_ADMIN CAP_SYS_RESOURCE
+CapabilityBoundingSet=CAP_BPF CAP_PERFMON CAP_NET_ADMIN CAP_SYS_ADMIN CAP_SYS_RESOURCE
+
+[Install]
+WantedBy=multi-user.target
+",
+        bin = BINARY_PATH,
+        state = STATE_DIR,
+        config = CONFIG_DIR,
+    )
+}
+
+fn openrc_script(user: Option<&str>) -> String {
+    let user_line = user.map(|u| format!("command_user=\"{}\"\n", u)).unwrap_or_default();
+    format!(
+        "#!/sbin/openrc-run
+
+description=\"Sennet Network Observability Agent\"
+command=\"{bin}\"
+command_background=true
+{user_line}pidfile=\"{pid}\"
+output_log=\"{log}\"
+error_log=\"{log}\"
+export RUST_LOG=info
+
+# Restart the agent if it dies, like Restart=always under systemd
+supervisor=supervise-daemon
+respawn_delay=10
+
+depend() {{
+    need net
+    after firewall
+}}
+",
+        bin = BINARY_PATH,
+        pid = crate::service::PID_FILE,
+        log = InitSystem::OpenRc.log_path().unwrap_or_default(),
+    )
+}
+
+fn runit_run_script(user: Option<&str>) -> String {
+    let exec = match user {
+        Some(user) => format!("chpst -u {} {}", user, BINARY_PATH),
+        None => BINARY_PATH.to_string(),
+    };
+    format!("#!/bin/sh\nexport RUST_LOG=info\nexec {} 2>&1\n", exec)
+}
+
+fn sysvinit_script(user: Option<&str>) -> String {
+    // setpriv execs the agent, so $! is the agent's own PID
+    let daemon = match user {
+        Some(user) => format!("setpriv --reuid={user} --regid=$(id -g {user}) --init-groups \"$DAEMON\"", user = user),
+        None => "\"$DAEMON\"".to_string(),
+    };
+    format!(
+        "#!/bin/sh
+### BEGIN INIT INFO
+# Provides:          {name}
+# Required-Start:    $network $remote_fs
+# Required-Stop:     $network $remote_fs
+# Default-Start:     2 3 4 5
+# Default-Stop:      0 1 6
+# Short-Description: Sennet Network Observability Agent
+### END INIT INFO
+
+DAEMON=\"{bin}\"
+PIDFILE=\"{pid}\"
+LOGFILE=\"{log}\"
+
+running() {{
+    [ -f \"$PIDFILE\" ] && kill -0 \"$(cat \"$PIDFILE\")\" 2>/dev/null
+}}
+
+case \"$1\" in
+    start)
+        running && exit 0
+        RUST_LOG=info nohup {daemon} >> \"$LOGFILE\" 2>&1 &
+        echo $! > \"$PIDFILE\"
+        ;;
+    stop)
+        running && kill \"$(cat \"$PIDFILE\")\"
+        rm -f \"$PIDFILE\"
+        ;;
+    restart)
+        \"$0\" stop
+        sleep 1
+        \"$0\" start
+        ;;
+    status)
+        # LSB exit codes: 0 running, 1 dead with pid file, 3 stopped
+        if running; then
+            echo \"{name} is running\"; exit 0
+        elif [ -f \"$PIDFILE\" ]; then
+            echo \"{name} is dead\"; exit 1
+        fi
+        echo \"{name} is stopped\"; exit 3
+        ;;
+    *)
+        echo \"Usage: $0 {{start|stop|restart|status}}\"; exit 2
+        ;;
+esac
+",
+        name = SERVICE_NAME,
+        bin = BINARY_PATH,
+        pid = crate::service::PID_FILE,
+        log = InitSystem::SysVinit.log_path().unwrap_or_default(),
+    )
+}
+
+/// Void keeps service definitions in /etc/sv and links them into /var/service
+fn runit_dir() -> PathBuf {
+    PathBuf::from(format!("/etc/sv/{}", SERVICE_NAME))
+}
+
+fn runsvdir() -> PathBuf {
+    if Path::new("/var/service").is_dir() {
+        PathBuf::from("/var/service")
+    } else {
+        PathBuf::from("/etc/service")
+    }
+}
+
+/// Commands that register the service to start at boot
+fn enable_steps(init: InitSystem) -> Vec<Step> {
+    match init {
+        InitSystem::Systemd => vec![
+            run_step(&["systemctl", "daemon-reload"]),
+            run_step(&["systemctl", "enable", SERVICE_NAME]),
+        ],
+        InitSystem::OpenRc => vec![run_step(&["rc-update", "add", SERVICE_NAME, "default"])],
+        InitSystem::Runit => vec![
+            // Keeps runsv from starting the agent before it is configured
+            Step::WriteFile { path: runit_dir().join("down"), contents: String::new(), mode: 0o644 },
+            Step::Symlink { target: runit_dir(), link: runsvdir().join(SERVICE_NAME) },
+        ],
+        InitSystem::SysVinit => vec![run_step(&["update-rc.d", SERVICE_NAME, "defaults"])],
+        InitSystem::Unknown => Vec::new(),
+    }
+}
+
+/// Steps for `sennet install`
+pub fn plan_install(init: InitSystem, options: &Options, current_exe: &Path) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let binary = PathBuf::from(BINARY_PATH);
+    if !options.skip_binary && current_exe != binary {
+        steps.push(Step::CopyBinary { from: current_exe.to_path_buf(), to: binary.clone() });
+    }
+
+    // Config holds the API key: readable by the service user only
+    steps.push(Step::CreateDir { path: CONFIG_DIR.into(), mode: 0o750, owner: options.user.clone() });
+    steps.push(Step::CreateDir { path: STATE_DIR.into(), mode: 0o700, owner: options.user.clone() });
+    if let Some(log_dir) = init.log_path().and_then(|p| Path::new(p).parent()).filter(|d| *d != Path::new("/var/log")) {
+        steps.push(Step::CreateDir { path: log_dir.to_path_buf(), mode: 0o750, owner: options.user.clone() });
+    }
+
+    steps.push(run_step(&["setcap", FILE_CAPS, BINARY_PATH]));
+
+    // Members of the group can run the CLI against the daemon's control socket
+    steps.push(run_step(&["groupadd", "-f", "--system", CONTROL_GROUP]));
+    if let Some(user) = &options.user {
+        steps.push(run_step(&["usermod", "-a", "-G", CONTROL_GROUP, user]));
+    }
+
+    for (path, contents, mode) in service_files(init, options.user.as_deref()) {
+        steps.push(Step::WriteFile { path, contents, mode });
+    }
+    steps.extend(enable_steps(init));
+    steps
+}
+
+/// Steps for `sennet uninstall`, reversing [`plan_install`]
+pub fn plan_uninstall(init: InitSystem, options: &Options) -> Vec<Step> {
+    let mut steps = Vec::new();
+    if let Some(stop) = init.command_line("stop") {
+        steps.push(Step::Run(stop));
+    }
+    match init {
+        InitSystem::Systemd => steps.push(run_step(&["systemctl", "disable", SERVICE_NAME])),
+        InitSystem::OpenRc => steps.push(run_step(&["rc-update", "del", SERVICE_NAME, "default"])),
+        InitSystem::Runit => steps.push(Step::Remove(runsvdir().join(SERVICE_NAME))),
+        InitSystem::SysVinit => steps.push(run_step(&["update-rc.d", "-f", SERVICE_NAME, "remove"])),
+        InitSystem::Unknown => {}
+    }
+
+    match init {
+        InitSystem::Runit => steps.push(Step::Remove(runit_dir())),
+        _ => {
+            for (path, _, _) in service_files(init, None) {
+                steps.push(Step::Remove(path));
+            }
+        }
+    }
+    if init == InitSystem::Systemd {
+        steps.push(run_step(&["systemctl", "daemon-reload"]));
+    }
+
+    if !options.skip_binary {
+        steps.push(Step::Remove(BINARY_PATH.into()));
+    }
+    if !options.keep_data {
+        steps.push(Step::Remove(CONFIG_DIR.into()));
+        steps.push(Step::Remove(STATE_DIR.into()));
+        if let Some(log_path) = init.log_path() {
+            let log_path = Path::new(log_path);
+            // runit logs to a directory of its own, the others to a file
+            let log_dir = log_path.parent().filter(|d| *d != Path::new("/var/log"));
+            steps.push(Step::Remove(log_dir.unwrap_or(log_path).to_path_buf()));
+        }
+    }
+    steps
+}
+
+#[cfg(unix)]
+fn chown(path: &Path, user: &str) -> Result<()> {
+    let name = std::ffi::CString::new(user)?;
+    // SAFETY: getpwnam returns null or a pointer to a static passwd entry
+    let pw = unsafe { libc::getpwnam(name.as_ptr()) };
+    if pw.is_null() {
+        anyhow::bail!("No such user: {}", user);
+    }
+    // SAFETY: checked non-null above
+    let (uid, gid) = unsafe { ((*pw).pw_uid, (*pw).pw_gid) };
+    std::os::unix::fs::chown(path, Some(uid), Some(gid))
+        .with_context(|| format!("Failed to chown {}", path.display()))
+}
+
+#[cfg(unix)]
+fn apply(step: &Step) -> Result<()> {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    match step {
+        Step::CopyBinary { from, to } => {
+            // Copy beside the target and rename, so a running binary is replaced atomically
+            let tmp = to.with_extension("new");
+            fs::copy(from, &tmp).with_context(|| format!("Failed to copy {}", from.display()))?;
+            fs::set_permissions(&tmp, fs::Permissions::from_mode(0o755))?;
+            fs::rename(&tmp, to).with_context(|| format!("Failed to install {}", to.display()))?;
+        }
+        Step::CreateDir { path, mode, owner } => {
+            fs::create_dir_all(path).with_context(|| format!("Failed to create {}", path.display()))?;
+            fs::set_permissions(path, fs::Permissions::from_mode(*mode))?;
+            if let Some(user) = owner {
+                chown(path, user)?;
+            }
+        }
+        Step::WriteFile { path, contents, mode } => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+            fs::set_permissions(path, fs::Permissions::from_mode(*mode))?;
+        }
+        Step::Symlink { target, link } => {
+            if let Some(parent) = link.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let _ = fs::remove_file(link);
+            std::os::unix::fs::symlink(target, link)
+                .with_context(|| format!("Failed to link {}", link.display()))?;
+        }
+        Step::Run(args) => {
+            let output = Command::new(&args[0])
+                .args(&args[1..])
+                .output()
+                .with_context(|| format!("Failed to run {}", args[0]))?;
+            if !output.status.success() {
+                anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+            }
+        }
+        Step::Remove(path) => {
+            let meta = match fs::symlink_metadata(path) {
+                Ok(meta) => meta,
+                Err(_) => return Ok(()),
+            };
+            if meta.is_dir() {
+                fs::remove_dir_all(path)
+            } else {
+                fs::remove_file(path)
+            }
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn apply(_step: &Step) -> Result<()> {
+    anyhow::bail!("install/uninstall is only supported on Linux")
+}
+
+/// Print or apply steps; failed commands are warnings, failed file
+/// operations abort
+fn execute(steps: &[Step], dry_run: bool) -> Result<()> {
+    for step in steps {
+        if dry_run {
+            println!("  {} {}", "[DRY-RUN]".yellow(), step);
+            continue;
+        }
+        match (apply(step), step) {
+            (Ok(()), _) => println!("  {} {}", "✓".green(), step),
+            (Err(e), Step::Run(_)) => println!("  {} {}: {:#}", "⚠".yellow(), step, e),
+            (Err(e), _) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+pub fn install(args: &[String]) -> Result<()> {
+    let options = Options::parse(args)?;
+    let init = InitSystem::detect();
+    if init == InitSystem::Unknown {
+        println!("{}", "⚠ No supported init system detected; the agent will not start at boot".yellow());
+    }
+    let current_exe = std::env::current_exe().context("Failed to locate the running binary")?;
+
+    println!("Installing Sennet ({} service)", init.name());
+    execute(&plan_install(init, &options, &current_exe), options.dry_run)?;
+
+    println!();
+    if !Path::new(CONFIG_DIR).join("config.yaml").exists() {
+        println!("  Configure the agent:  {}", "sudo sennet init".cyan());
+    }
+    println!("  Start the agent:      {}", init.hint("start").cyan());
+    Ok(())
+}
+
+pub fn uninstall(args: &[String]) -> Result<()> {
+    let options = Options::parse(args)?;
+    let init = InitSystem::detect();
+
+    println!("Uninstalling Sennet ({} service)", init.name());
+    execute(&plan_uninstall(init, &options), options.dry_run)?;
+    if options.keep_data {
+        println!("Kept {} and {}", CONFIG_DIR, STATE_DIR);
+    }
+    Ok(())
+}
+
+pub fn print_help() {
+    println!("{}", "sennet install / uninstall".bold());
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sudo sennet install [OPTIONS]");
+    println!("    sudo sennet uninstall [OPTIONS]");
+    println!();
+    println!("{}", "OPTIONS:".yellow());
+    println!("    -n, --dry-run        Show the steps without making changes");
+    println!("    -u, --user <NAME>    Run the service as NAME instead of root (install)");
+    println!("        --keep-data      Keep {} and {} (uninstall)", CONFIG_DIR, STATE_DIR);
+    println!("        --skip-binary    Don't copy or remove {}", BINARY_PATH);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_plan() {
+        let options = Options::parse(&["--user".to_string(), "sennet".to_string()]).unwrap();
+        let steps = plan_install(InitSystem::OpenRc, &options, Path::new("/tmp/sennet"));
+
+        assert_eq!(steps[0], Step::CopyBinary { from: "/tmp/sennet".into(), to: BINARY_PATH.into() });
+        assert!(steps.contains(&Step::CreateDir { path: STATE_DIR.into(), mode: 0o700, owner: Some("sennet".into()) }));
+        assert!(steps.contains(&run_step(&["setcap", FILE_CAPS, BINARY_PATH])));
+        assert!(steps.contains(&run_step(&["usermod", "-a", "-G", "sennet", "sennet"])));
+        let script = steps.iter().find_map(|s| match s {
+            Step::WriteFile { path, contents, .. } if path == Path::new("/etc/init.d/sennet") => Some(contents),
+            _ => None,
+        });
+        assert!(script.unwrap().contains("command_user=\"sennet\""));
+        assert_eq!(steps.last(), Some(&run_step(&["rc-update", "add", "sennet", "default"])));
+
+        // Already installed: nothing to copy
+        let steps = plan_install(InitSystem::Systemd, &Options::default(), Path::new(BINARY_PATH));
+        assert!(!steps.iter().any(|s| matches!(s, Step::CopyBinary { .. })));
+    }
+
+    #[test]
+    fn test_uninstall_reverses_install() {
+        let steps = plan_uninstall(InitSystem::Systemd, &Options::default());
+        assert_eq!(steps[0], run_step(&["systemctl", "stop", "sennet"]));
+        assert!(steps.contains(&Step::Remove("/etc/systemd/system/sennet.service".into())));
+        assert!(steps.contains(&Step::Remove(BINARY_PATH.into())));
+        assert!(steps.contains(&Step::Remove(STATE_DIR.into())));
+
+        let keep = Options::parse(&["--keep-data".to_string()]).unwrap();
+        let steps = plan_uninstall(InitSystem::Systemd, &keep);
+        assert!(!steps.contains(&Step::Remove(CONFIG_DIR.into())));
+        assert!(Options::parse(&["--bogus".to_string()]).is_err());
+    }
+}
@@ -59,6 +59,43 @@ pub fn discover_default_interface(config_override: Option<&str>) -> Result<Strin
     anyhow::bail!("No suitable network interface found")
 }
 
+/// Discover the interfaces to attach to
+///
+/// The `interfaces` that exist if any are listed, else every up,
+/// non-loopback interface when `all` is set, else the single default
+/// interface (see [`discover_default_interface`]).
+pub fn discover_interfaces(config_override: Option<&str>, interfaces: &[String], all: bool) -> Result<Vec<String>> {
+    if !interfaces.is_empty() {
+        // Missing ones are attached if they appear later (see hotplug)
+        let (present, missing): (Vec<String>, Vec<String>) =
+            interfaces.iter().cloned().partition(|iface| interface_exists(iface));
+        if present.is_empty() {
+            anyhow::bail!("None of the configured interfaces exist: {}", missing.join(", "));
+        }
+        if !missing.is_empty() {
+            tracing::warn!("Configured interfaces not present yet: {}", missing.join(", "));
+        }
+        return Ok(present);
+    }
+    if all {
+        let up = attachable(&list_interfaces()?);
+        if up.is_empty() {
+            anyhow::bail!("No interface is up besides loopback");
+        }
+        return Ok(up);
+    }
+    discover_default_interface(config_override).map(|iface| vec![iface])
+}
+
+/// Names of the up, non-loopback interfaces in `interfaces`
+fn attachable(interfaces: &[InterfaceInfo]) -> Vec<String> {
+    interfaces
+        .iter()
+        .filter(|iface| iface.is_up && !iface.is_loopback)
+        .map(|iface| iface.name.clone())
+        .collect()
+}
+
 /// Check if an interface exists
 pub fn interface_exists(name: &str) -> bool {
     Path::new(&format!("/sys/class/net/{}", name)).exists()
@@ -120,11 +157,9 @@ pub fn list_interfaces() -> Result<Vec<InterfaceInfo>> {
 
         // Read flags to check if up
         let flags_path = entry.path().join("flags");
-        let flags: u32 = fs::read_to_string(&flags_path)
-            .unwrap_or_else(|_| "0x0".to_string())
-            .trim()
-            .trim_start_matches("0x")
-            .parse()
+        let flags = fs::read_to_string(&flags_path)
+            .ok()
+            .and_then(|flags| u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).ok())
             .unwrap_or(0);
         
         // IFF_UP = 0x1, IFF_LOOPBACK = 0x8
@@ -193,6 +228,21 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_attachable() {
+        let info = |name: &str, is_up, is_loopback| InterfaceInfo {
+            name: name.to_string(),
+            index: 0,
+            is_up,
+            is_loopback,
+            ipv4_addrs: vec![],
+        };
+        let interfaces = [info("lo", true, true), info("eth0", true, false), info("eth1", false, false), info("wg0", true, false)];
+        assert_eq!(attachable(&interfaces), vec!["eth0", "wg0"]);
+
+        assert!(discover_interfaces(None, &["nonexistent_12345".to_string()], false).is_err());
+    }
+
     #[test]
     #[cfg(target_os = "linux")]
     fn test_list_interfaces() {
@@ -0,0 +1,452 @@
+//! Legacy iptables Ruleset
+//!
+//! Hosts on iptables-legacy keep their rules in x_tables, which nftables
+//! netlink doesn't see. This backend parses `iptables-save -c` (and
+//! `ip6tables-save -c`) output into the same `firewall` model, so drop
+//! correlation and host firewall diagnosis work on either backend.
+//!
+//! Rules have no handles; a rule's number is its 1-based position in the
+//! chain, as shown by `iptables -L --line-numbers`.
+//!
+//! iptables-nft rules already live in nftables and are read from there.
+
+use std::sync::OnceLock;
+
+use anyhow::Result;
+
+use crate::firewall::{family, Chain, CmpOp, Counter, Field, Hook, Match, Rule, Set, SetElement, Table, Verdict};
+
+/// ct state bits (IP_CT_*), as `-m conntrack --ctstate` names them
+fn ct_state_bit(name: &str) -> Option<u32> {
+    match name {
+        "INVALID" => Some(1),
+        "ESTABLISHED" => Some(2),
+        "RELATED" => Some(4),
+        "NEW" => Some(8),
+        "UNTRACKED" => Some(64),
+        _ => None,
+    }
+}
+
+/// Hook priority of each table's base chains (NF_IP_PRI_*)
+fn table_priority(table: &str) -> i32 {
+    match table {
+        "raw" => -300,
+        "mangle" => -150,
+        "nat" => -100,
+        "security" => 50,
+        _ => 0,
+    }
+}
+
+fn builtin_hook(chain: &str) -> Option<Hook> {
+    match chain {
+        "PREROUTING" => Some(Hook::Prerouting),
+        "INPUT" => Some(Hook::Input),
+        "FORWARD" => Some(Hook::Forward),
+        "OUTPUT" => Some(Hook::Output),
+        "POSTROUTING" => Some(Hook::Postrouting),
+        _ => None,
+    }
+}
+
+/// Split a rule line into words, honouring double quotes (comments)
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => word.extend(chars.next()),
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn protocol_number(proto: &str) -> Option<u8> {
+    match proto {
+        "tcp" => Some(6),
+        "udp" => Some(17),
+        "icmp" => Some(1),
+        "icmpv6" | "ipv6-icmp" => Some(58),
+        "sctp" => Some(132),
+        p => p.parse().ok(),
+    }
+}
+
+/// Address match for `-s`/`-d`: network bytes with a prefix mask
+fn addr_match(field: Field, negate: bool, value: &str) -> Match {
+    let (addr, prefix) = value.split_once('/').unwrap_or((value, ""));
+    let Ok(addr) = addr.parse::<std::net::IpAddr>() else {
+        return Match::Other(format!("address {}", value));
+    };
+    let bytes = match addr {
+        std::net::IpAddr::V4(a) => a.octets().to_vec(),
+        std::net::IpAddr::V6(a) => a.octets().to_vec(),
+    };
+    let bits = bytes.len() * 8;
+    let prefix: usize = match prefix {
+        "" => bits,
+        p => match p.parse::<std::net::Ipv4Addr>() {
+            // Dotted masks (255.255.0.0) as saved by older versions
+            Ok(mask) => u32::from(mask).leading_ones() as usize,
+            Err(_) => p.parse().unwrap_or(bits),
+        },
+    };
+    let mask: Vec<u8> = (0..bytes.len())
+        .map(|i| {
+            let ones = prefix.saturating_sub(i * 8).min(8);
+            (0xffu16 << (8 - ones)) as u8
+        })
+        .collect();
+    let value = bytes.iter().zip(&mask).map(|(b, m)| b & m).collect();
+    let op = if negate { CmpOp::Neq } else { CmpOp::Eq };
+    Match::Cmp { field, op, value, mask: Some(mask) }
+}
+
+/// Interface match for `-i`/`-o`; a trailing `+` is a prefix wildcard
+fn iface_match(field: Field, negate: bool, name: &str) -> Match {
+    let value = match name.strip_suffix('+') {
+        Some(prefix) => prefix.as_bytes().to_vec(),
+        // Include the NUL so "eth0" doesn't match "eth01"
+        None => [name.as_bytes(), &[0]].concat(),
+    };
+    let op = if negate { CmpOp::Neq } else { CmpOp::Eq };
+    Match::Cmp { field, op, value, mask: None }
+}
+
+/// Port match for `--dport 443` or `--dport 1000:2000`
+fn port_match(field: Field, negate: bool, value: &str) -> Match {
+    let port = |p: &str, default: u16| if p.is_empty() { Some(default) } else { p.parse::<u16>().ok() };
+    match value.split_once(':') {
+        None => match value.parse::<u16>() {
+            Ok(p) => {
+                let op = if negate { CmpOp::Neq } else { CmpOp::Eq };
+                Match::Cmp { field, op, value: p.to_be_bytes().to_vec(), mask: None }
+            }
+            Err(_) => Match::Other(format!("port {}", value)),
+        },
+        Some((from, to)) => match (port(from, 0), port(to, u16::MAX)) {
+            (Some(from), Some(to)) => Match::Range {
+                field,
+                negate,
+                from: from.to_be_bytes().to_vec(),
+                to: to.to_be_bytes().to_vec(),
+            },
+            _ => Match::Other(format!("port {}", value)),
+        },
+    }
+}
+
+/// Interval set for a `-m multiport` port list ("80,443,1000:2000")
+fn multiport_set(name: String, list: &str) -> Option<Set> {
+    let mut elements = Vec::new();
+    for item in list.split(',') {
+        let (from, to) = item.split_once(':').unwrap_or((item, item));
+        let (from, to): (u16, u16) = (from.parse().ok()?, to.parse().ok()?);
+        elements.push(SetElement { key: from.to_be_bytes().to_vec(), interval_end: false });
+        if let Some(end) = to.checked_add(1) {
+            elements.push(SetElement { key: end.to_be_bytes().to_vec(), interval_end: true });
+        }
+    }
+    Some(Set { name, interval: true, elements })
+}
+
+/// A rule's options: (negated, option, values)
+fn option_groups(words: &[String]) -> Vec<(bool, &str, Vec<&str>)> {
+    let mut groups: Vec<(bool, &str, Vec<&str>)> = Vec::new();
+    let mut negate = false;
+    for word in words {
+        if word == "!" {
+            negate = true;
+        } else if word.starts_with('-') && word.len() > 1 && word.parse::<i64>().is_err() {
+            groups.push((std::mem::take(&mut negate), word, Vec::new()));
+        } else if let Some(group) = groups.last_mut() {
+            group.2.push(word);
+        }
+    }
+    groups
+}
+
+/// Parse one `-A CHAIN ...` line (counters already stripped) of `table`
+///
+/// Returns the chain name, the rule, and any sets the rule needs.
+fn parse_rule(words: &[String], counter: Option<Counter>, table: &Table) -> Option<(String, Rule, Vec<Set>)> {
+    let mut chain = None;
+    let mut rule = Rule { counter, ..Default::default() };
+    let mut sets = Vec::new();
+
+    for (negate, option, values) in option_groups(words) {
+        let value = values.first().copied().unwrap_or_default();
+        match option {
+            "-A" | "--append" => chain = Some(value.to_string()),
+            "-p" | "--protocol" => rule.matches.push(match protocol_number(value) {
+                Some(proto) => {
+                    let op = if negate { CmpOp::Neq } else { CmpOp::Eq };
+                    Match::Cmp { field: Field::L4Proto, op, value: vec![proto], mask: None }
+                }
+                None => Match::Other(format!("protocol {}", value)),
+            }),
+            "-s" | "--source" => rule.matches.push(addr_match(Field::SrcAddr, negate, value)),
+            "-d" | "--destination" => rule.matches.push(addr_match(Field::DstAddr, negate, value)),
+            "-i" | "--in-interface" => rule.matches.push(iface_match(Field::InIface, negate, value)),
+            "-o" | "--out-interface" => rule.matches.push(iface_match(Field::OutIface, negate, value)),
+            "--dport" | "--destination-port" => rule.matches.push(port_match(Field::DstPort, negate, value)),
+            "--sport" | "--source-port" => rule.matches.push(port_match(Field::SrcPort, negate, value)),
+            "--dports" | "--destination-ports" | "--sports" | "--source-ports" => {
+                let field = match option {
+                    "--dports" | "--destination-ports" => Field::DstPort,
+                    _ => Field::SrcPort,
+                };
+                let name = format!("__multiport{}", table.sets.len() + sets.len());
+                match multiport_set(name, value) {
+                    Some(set) => {
+                        rule.matches.push(Match::InSet { field, set: set.name.clone(), negate });
+                        sets.push(set);
+                    }
+                    None => rule.matches.push(Match::Other(format!("{} {}", option, value))),
+                }
+            }
+            "--ctstate" | "--state" => {
+                let bits = value.split(',').map(ct_state_bit).try_fold(0u32, |acc, b| Some(acc | b?));
+                rule.matches.push(match bits {
+                    // Same encoding nftables uses: (state & bits) != 0
+                    Some(bits) => Match::Cmp {
+                        field: Field::CtState,
+                        op: if negate { CmpOp::Eq } else { CmpOp::Neq },
+                        value: 0u32.to_ne_bytes().to_vec(),
+                        mask: Some(bits.to_ne_bytes().to_vec()),
+                    },
+                    None => Match::Other(format!("ctstate {}", value)),
+                });
+            }
+            "-j" | "--jump" => {
+                rule.verdict = match value {
+                    "ACCEPT" => Some(Verdict::Accept),
+                    "DROP" => Some(Verdict::Drop),
+                    "REJECT" => Some(Verdict::Reject),
+                    "RETURN" => Some(Verdict::Return),
+                    chain if table.chain(chain).is_some() => Some(Verdict::Jump(chain.to_string())),
+                    // LOG, MARK, DNAT, ... don't end filter evaluation
+                    _ => None,
+                };
+            }
+            "-g" | "--goto" => rule.verdict = Some(Verdict::Goto(value.to_string())),
+            // Module loads, comments and target options don't match anything
+            "-m" | "--match" | "--comment" | "--reject-with" | "--log-prefix" | "--log-level" => {}
+            other => rule.matches.push(Match::Other(format!("{} {}", other, values.join(" ")).trim().to_string())),
+        }
+    }
+
+    Some((chain?, rule, sets))
+}
+
+/// Parse `[packets:bytes]` counters
+fn parse_counter(text: &str) -> Option<Counter> {
+    let (packets, bytes) = text.strip_prefix('[')?.strip_suffix(']')?.split_once(':')?;
+    Some(Counter { packets: packets.parse().ok()?, bytes: bytes.parse().ok()? })
+}
+
+/// Parse `iptables-save` (or `ip6tables-save`) output into tables
+///
+/// `family` is `family::IPV4` or `family::IPV6`.
+pub fn parse_save(output: &str, family: u8) -> Vec<Table> {
+    let mut tables: Vec<Table> = Vec::new();
+
+    for line in output.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('*') {
+            tables.push(Table { family, name: name.to_string(), chains: Vec::new(), sets: Vec::new() });
+            continue;
+        }
+        let Some(table) = tables.last_mut() else { continue };
+
+        if let Some(decl) = line.strip_prefix(':') {
+            // :INPUT ACCEPT [0:0]
+            let mut parts = decl.split_whitespace();
+            let Some(name) = parts.next() else { continue };
+            let hook = builtin_hook(name);
+            table.chains.push(Chain {
+                name: name.to_string(),
+                hook,
+                priority: table_priority(&table.name),
+                kind: table.name.clone(),
+                policy: match parts.next() {
+                    Some("DROP") => Verdict::Drop,
+                    _ => Verdict::Accept,
+                },
+                rules: Vec::new(),
+            });
+        } else if line.starts_with("-A") || line.starts_with('[') {
+            let mut words = split_words(line);
+            let mut counter = None;
+            if words.first().is_some_and(|w| w.starts_with('[')) {
+                counter = parse_counter(&words.remove(0));
+            }
+            // `-c packets bytes` form
+            if let Some(i) = words.iter().position(|w| w == "-c") {
+                if let (Some(p), Some(b)) = (words.get(i + 1), words.get(i + 2)) {
+                    counter = p.parse().ok().zip(b.parse().ok()).map(|(packets, bytes)| Counter { packets, bytes });
+                }
+                words.drain(i..(i + 3).min(words.len()));
+            }
+            let Some((chain_name, mut rule, sets)) = parse_rule(&words, counter, table) else { continue };
+            table.sets.extend(sets);
+            if let Some(chain) = table.chains.iter_mut().find(|c| c.name == chain_name) {
+                rule.handle = chain.rules.len() as u64 + 1;
+                chain.rules.push(rule);
+            }
+        }
+    }
+
+    tables
+}
+
+/// `*-save` commands for the legacy backend, per family; None if the host
+/// has no iptables-legacy
+fn legacy_commands() -> Option<&'static [(u8, &'static str); 2]> {
+    static COMMANDS: OnceLock<Option<[(u8, &'static str); 2]>> = OnceLock::new();
+    COMMANDS
+        .get_or_init(|| {
+            let version = |cmd: &str| {
+                std::process::Command::new(cmd)
+                    .arg("--version")
+                    .output()
+                    .ok()
+                    .filter(|o| o.status.success())
+                    .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
+            };
+            if version("iptables-legacy-save").is_some() {
+                return Some([(family::IPV4, "iptables-legacy-save"), (family::IPV6, "ip6tables-legacy-save")]);
+            }
+            // Before 1.8 there was only the legacy backend, without a suffix
+            match version("iptables-save") {
+                Some(v) if !v.contains("nf_tables") => Some([(family::IPV4, "iptables-save"), (family::IPV6, "ip6tables-save")]),
+                _ => None,
+            }
+        })
+        .as_ref()
+}
+
+/// Read the legacy iptables tables (needs root or CAP_NET_ADMIN)
+///
+/// Empty if the host doesn't use iptables-legacy.
+pub fn read_legacy_tables() -> Result<Vec<Table>> {
+    let Some(commands) = legacy_commands() else {
+        return Ok(Vec::new());
+    };
+    let mut tables = Vec::new();
+    for &(nfproto, cmd) in commands {
+        let output = match std::process::Command::new(cmd).arg("-c").output() {
+            Ok(output) => output,
+            // ip6tables may be missing on IPv4-only hosts
+            Err(_) if nfproto == family::IPV6 => continue,
+            Err(e) => anyhow::bail!("Failed to run {}: {}", cmd, e),
+        };
+        if !output.status.success() {
+            anyhow::bail!("{} failed: {}", cmd, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        tables.extend(parse_save(&String::from_utf8_lossy(&output.stdout), nfproto));
+    }
+    // Legacy tables exist once the module is loaded, even with no rules
+    tables.retain(|t| t.chains.iter().any(|c| !c.rules.is_empty() || c.policy.discards()));
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::firewall::{Probe, Ruleset};
+
+    const SAVE: &str = r#"# Generated by iptables-save v1.6.1 on Mon Jan  1 00:00:00 2024
+*nat
+:PREROUTING ACCEPT [0:0]
+:POSTROUTING ACCEPT [0:0]
+[3:180] -A POSTROUTING -o eth0 -j MASQUERADE
+COMMIT
+*filter
+:INPUT DROP [10:600]
+:FORWARD ACCEPT [0:0]
+:OUTPUT ACCEPT [0:0]
+:SSH - [0:0]
+[0:0] -A INPUT -i lo -j ACCEPT
+[500:30000] -A INPUT -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+[7:420] -A INPUT -p tcp -m tcp --dport 22 -j SSH
+[40:2400] -A INPUT -p tcp -m tcp --dport 443 -m comment --comment "block \"public\" https" -j REJECT --reject-with icmp-port-unreachable
+[9:540] -A INPUT -s 10.0.0.0/8 -p tcp -m multiport --dports 80,8000:8080 -j ACCEPT
+[0:0] -A SSH ! -s 192.168.0.0/16 -j DROP
+[0:0] -A SSH -j ACCEPT
+COMMIT
+"#;
+
+    #[test]
+    fn test_parse_save() {
+        let tables = parse_save(SAVE, family::IPV4);
+        assert_eq!(tables.len(), 2);
+        let filter = &tables[1];
+        assert_eq!(filter.name, "filter");
+
+        let input = filter.chain("INPUT").unwrap();
+        assert_eq!(input.hook, Some(Hook::Input));
+        assert_eq!(input.policy, Verdict::Drop);
+        assert_eq!(input.rules.len(), 5);
+        let reject = &input.rules[3];
+        assert_eq!(reject.handle, 4);
+        assert_eq!(reject.verdict, Some(Verdict::Reject));
+        assert_eq!(reject.counter, Some(Counter { packets: 40, bytes: 2400 }));
+        assert_eq!(reject.matches.len(), 2);
+        assert_eq!(input.rules[2].verdict, Some(Verdict::Jump("SSH".into())));
+
+        let ssh = filter.chain("SSH").unwrap();
+        assert_eq!(ssh.hook, None);
+        assert!(matches!(ssh.rules[0].matches[0], Match::Cmp { op: CmpOp::Neq, .. }));
+
+        // MASQUERADE is not a filter verdict
+        assert_eq!(tables[0].chain("POSTROUTING").unwrap().rules[0].verdict, None);
+    }
+
+    #[test]
+    fn test_evaluate_legacy_ruleset() {
+        let ruleset = Ruleset { tables: parse_save(SAVE, family::IPV4) };
+
+        let probe = Probe::inbound(6, 443);
+        assert_eq!(ruleset.evaluate(&probe).describe(&probe), "port 443/tcp is rejected by ip filter INPUT rule 4");
+
+        // multiport with a source restriction
+        let mut probe = Probe::inbound(6, 8080);
+        probe.src = Some("10.1.2.3".parse().unwrap());
+        assert_eq!(ruleset.evaluate(&probe).verdict, Verdict::Accept);
+        probe.src = Some("172.16.0.1".parse().unwrap());
+        let decision = ruleset.evaluate(&probe);
+        assert_eq!(decision.describe(&probe), "port 8080/tcp from 172.16.0.1 is dropped by the ip filter INPUT policy");
+
+        // SSH only from the LAN
+        let mut probe = Probe::inbound(6, 22);
+        probe.src = Some("192.168.1.10".parse().unwrap());
+        assert_eq!(ruleset.evaluate(&probe).verdict, Verdict::Accept);
+        probe.src = Some("8.8.8.8".parse().unwrap());
+        assert_eq!(ruleset.evaluate(&probe).rule.unwrap().to_string(), "ip filter SSH rule 1");
+    }
+
+    #[test]
+    fn test_split_words_and_masks() {
+        assert_eq!(split_words(r#"-A INPUT -m comment --comment "a b" -j ACCEPT"#)[5], "a b");
+        match addr_match(Field::SrcAddr, false, "10.1.0.0/255.255.0.0") {
+            Match::Cmp { value, mask, .. } => {
+                assert_eq!(value, vec![10, 1, 0, 0]);
+                assert_eq!(mask, Some(vec![255, 255, 0, 0]));
+            }
+            m => panic!("unexpected {:?}", m),
+        }
+    }
+}
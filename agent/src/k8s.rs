@@ -11,7 +11,9 @@ use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, warn, Instrument};
+
+use crate::daemonset::PodIdentity;
 
 #[cfg(target_os = "linux")]
 use std::fs;
@@ -129,6 +131,8 @@ pub struct K8sManager {
     cni_type: CniType,
     /// Whether we're running inside a Kubernetes cluster
     in_cluster: bool,
+    /// Node and pod names from the downward API (DaemonSet deployments)
+    pod_identity: Option<PodIdentity>,
 }
 
 impl K8sManager {
@@ -140,10 +144,14 @@ impl K8sManager {
         let in_cluster = Self::detect_in_cluster();
         let has_kubeconfig = Self::detect_kubeconfig();
         let cni_type = Self::detect_cni();
+        let pod_identity = PodIdentity::from_env();
         
         info!(
-            "K8s Manager initialized: in_cluster={}, kubeconfig={}, cni={}",
-            in_cluster, has_kubeconfig, cni_type
+            "K8s Manager initialized: in_cluster={}, kubeconfig={}, cni={}, node={}",
+            in_cluster,
+            has_kubeconfig,
+            cni_type,
+            pod_identity.as_ref().map_or("-", |p| p.node_name.as_str())
         );
         
         Ok(Self {
@@ -151,6 +159,7 @@ impl K8sManager {
             policy_index: Arc::new(RwLock::new(HashMap::new())),
             cni_type,
             in_cluster: in_cluster || has_kubeconfig, // Consider "in cluster" if we have any K8s access
+            pod_identity,
         })
     }
     
@@ -272,6 +281,29 @@ impl K8sManager {
         cache.values().find(|p| p.ip.as_deref() == Some(ip)).cloned()
     }
     
+    /// Look up pod info by container ID without waiting; None while the
+    /// watcher holds the cache for writing
+    pub fn cached_pod(&self, container_id: &str) -> Option<PodInfo> {
+        self.container_cache.try_read().ok()?.get(container_id).cloned()
+    }
+
+    /// Fetch one pod from the API server (in-cluster or kubeconfig)
+    pub async fn fetch_pod(namespace: &str, name: &str) -> Result<PodInfo> {
+        use k8s_openapi::api::core::v1::Pod;
+        use kube::{Api, Client};
+        
+        let client = Client::try_default().await.context("No Kubernetes API access (in-cluster or kubeconfig)")?;
+        let pods: Api<Pod> = Api::namespaced(client, namespace);
+        let pod = pods.get(name).await.with_context(|| format!("Pod '{}' not found in namespace '{}'", name, namespace))?;
+        Self::pod_to_info(&pod).with_context(|| format!("Pod '{}' has not been scheduled yet", name))
+    }
+    
+    /// Insert pod info for a container ID (the watcher fills the cache directly)
+    #[allow(dead_code)] // Used by benches
+    pub async fn cache_pod(&self, container_id: &str, info: PodInfo) {
+        self.container_cache.write().await.insert(container_id.to_string(), info);
+    }
+    
     /// Get all NetworkPolicies affecting a pod
     pub async fn get_policies_for_pod(&self, namespace: &str, labels: &HashMap<String, String>) -> Vec<NetworkPolicyInfo> {
         let index = self.policy_index.read().await;
@@ -313,10 +345,11 @@ impl K8sManager {
         
         let container_cache = Arc::clone(&self.container_cache);
         let policy_index = Arc::clone(&self.policy_index);
+        let node_selector = self.pod_identity.as_ref().and_then(PodIdentity::node_selector);
         
         // Spawn background task for syncing
         tokio::spawn(async move {
-            if let Err(e) = Self::sync_loop(container_cache, policy_index).await {
+            if let Err(e) = Self::sync_loop(container_cache, policy_index, node_selector).await {
                 warn!("K8s sync loop error: {}", e);
             }
         });
@@ -328,25 +361,34 @@ impl K8sManager {
     async fn sync_loop(
         container_cache: Arc<RwLock<HashMap<String, PodInfo>>>,
         policy_index: Arc<RwLock<HashMap<String, Vec<NetworkPolicyInfo>>>>,
+        node_selector: Option<String>,
     ) -> Result<()> {
         use futures::StreamExt;
         use k8s_openapi::api::core::v1::Pod;
         use k8s_openapi::api::networking::v1::NetworkPolicy;
         use kube::{Api, Client, runtime::watcher, runtime::watcher::Event};
         
-        let client = Client::try_default().await
+        let client = Client::try_default()
+            .instrument(tracing::info_span!("k8s.connect"))
+            .await
             .context("Failed to create Kubernetes client")?;
         
         info!("Connected to Kubernetes API, starting watchers");
         
-        // Watch pods across all namespaces
+        // Watch pods across all namespaces; a DaemonSet pod only needs the
+        // pods on its own node, which keeps the cache small on large clusters
         let pods: Api<Pod> = Api::all(client.clone());
+        let mut pod_config = watcher::Config::default();
+        if let Some(selector) = &node_selector {
+            info!("Scoping pod watch to {}", selector);
+            pod_config = pod_config.fields(selector);
+        }
         let policies: Api<NetworkPolicy> = Api::all(client.clone());
         
         // Spawn pod watcher
         let cache_clone = Arc::clone(&container_cache);
         let pod_watcher = tokio::spawn(async move {
-            let mut stream = watcher(pods, watcher::Config::default()).boxed();
+            let mut stream = watcher(pods, pod_config).boxed();
             
             while let Some(event) = stream.next().await {
                 match event {
@@ -370,14 +412,19 @@ impl K8sManager {
                     }
                     Ok(Event::Restarted(pods)) => {
                         // Initial list on (re)start - cache all pods
-                        let mut cache = cache_clone.write().await;
-                        for pod in pods {
-                            if let Some(info) = Self::pod_to_info(&pod) {
-                                for cid in &info.container_ids {
-                                    cache.insert(cid.clone(), info.clone());
+                        let span = tracing::info_span!("k8s.sync", resource = "pods", objects = pods.len());
+                        async {
+                            let mut cache = cache_clone.write().await;
+                            for pod in pods {
+                                if let Some(info) = Self::pod_to_info(&pod) {
+                                    for cid in &info.container_ids {
+                                        cache.insert(cid.clone(), info.clone());
+                                    }
                                 }
                             }
                         }
+                        .instrument(span)
+                        .await;
                         debug!("Pod cache initialized/restarted");
                     }
                     Err(e) => {
@@ -417,14 +464,19 @@ impl K8sManager {
                     }
                     Ok(Event::Restarted(policies)) => {
                         // Initial list on (re)start - index all policies
-                        let mut index = index_clone.write().await;
-                        for policy in policies {
-                            if let Some(info) = Self::policy_to_info(&policy) {
-                                let ns_policies = index.entry(info.namespace.clone()).or_default();
-                                ns_policies.retain(|p| p.name != info.name);
-                                ns_policies.push(info);
+                        let span = tracing::info_span!("k8s.sync", resource = "networkpolicies", objects = policies.len());
+                        async {
+                            let mut index = index_clone.write().await;
+                            for policy in policies {
+                                if let Some(info) = Self::policy_to_info(&policy) {
+                                    let ns_policies = index.entry(info.namespace.clone()).or_default();
+                                    ns_policies.retain(|p| p.name != info.name);
+                                    ns_policies.push(info);
+                                }
                             }
                         }
+                        .instrument(span)
+                        .await;
                         debug!("NetworkPolicy index initialized/restarted");
                     }
                     Err(e) => {
@@ -574,6 +626,38 @@ impl K8sManager {
     }
 }
 
+/// The daemon's K8s manager, connected and synced in the background and
+/// shared by everything that looks pods up; empty until it is ready
+#[derive(Clone, Default)]
+pub struct SharedK8s(Arc<std::sync::OnceLock<K8sManager>>);
+
+impl SharedK8s {
+    /// Connect to the API server and start the pod watcher in the background
+    ///
+    /// Needs a running tokio runtime.
+    pub fn start() -> Self {
+        let shared = Self::default();
+        let slot = shared.0.clone();
+        tokio::spawn(async move {
+            match K8sManager::new().await {
+                Ok(k8s) => {
+                    if let Err(e) = k8s.start_sync().await {
+                        warn!("k8s sync: {:#}", e);
+                    }
+                    let _ = slot.set(k8s);
+                }
+                Err(e) => warn!("k8s enrichment disabled: {:#}", e),
+            }
+        });
+        shared
+    }
+
+    /// The manager, once connected
+    pub fn get(&self) -> Option<&K8sManager> {
+        self.0.get()
+    }
+}
+
 // =============================================================================
 // Container ID Lookup from cgroup (7.1)
 // =============================================================================
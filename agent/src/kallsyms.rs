@@ -0,0 +1,111 @@
+//! Kernel Symbol Resolution
+//!
+//! Turns kernel instruction addresses, such as the frames of the drop
+//! stacks `kfree_skb` records, into `function+offset` using
+//! `/proc/kallsyms`. With `kptr_restrict` set, unprivileged readers see
+//! every address as zero; [`KernelSymbols::load`] reports that instead of
+//! resolving everything to the first symbol.
+
+use anyhow::Result;
+
+/// Path of the kernel symbol table
+const KALLSYMS_PATH: &str = "/proc/kallsyms";
+
+/// One text symbol
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Symbol {
+    addr: u64,
+    name: String,
+    /// Module the symbol belongs to (None = core kernel)
+    module: Option<String>,
+}
+
+/// Kernel text symbols sorted by address
+#[derive(Debug, Default)]
+pub struct KernelSymbols {
+    symbols: Vec<Symbol>,
+}
+
+impl KernelSymbols {
+    /// Read the running kernel's symbols
+    pub fn load() -> Result<Self> {
+        let text = std::fs::read_to_string(KALLSYMS_PATH)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", KALLSYMS_PATH, e))?;
+        let symbols = Self::parse(&text);
+        if symbols.symbols.is_empty() {
+            anyhow::bail!("{} shows no addresses; run as root or lower kernel.kptr_restrict", KALLSYMS_PATH);
+        }
+        Ok(symbols)
+    }
+
+    /// Parse kallsyms lines (`ffffffff81000000 T _stext [module]`), keeping
+    /// text symbols with a nonzero address
+    pub fn parse(text: &str) -> Self {
+        let mut symbols: Vec<Symbol> = text
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let addr = u64::from_str_radix(parts.next()?, 16).ok()?;
+                let kind = parts.next()?;
+                let name = parts.next()?;
+                let module = parts.next().map(|m| m.trim_matches(|c| c == '[' || c == ']').to_string());
+                let text = matches!(kind, "t" | "T" | "w" | "W");
+                (addr != 0 && text).then(|| Symbol { addr, name: name.to_string(), module })
+            })
+            .collect();
+        symbols.sort_by_key(|s| s.addr);
+        Self { symbols }
+    }
+
+    /// Symbol containing `addr` and the offset into it
+    pub fn resolve(&self, addr: u64) -> Option<(&str, u64)> {
+        let symbol = self.lookup(addr)?;
+        Some((&symbol.name, addr - symbol.addr))
+    }
+
+    /// `function+0xoff [module]`, or the bare address when no symbol covers it
+    pub fn format(&self, addr: u64) -> String {
+        match self.lookup(addr) {
+            Some(Symbol { addr: start, name, module: Some(module) }) => {
+                format!("{}+{:#x} [{}]", name, addr - start, module)
+            }
+            Some(Symbol { addr: start, name, module: None }) => format!("{}+{:#x}", name, addr - start),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    fn lookup(&self, addr: u64) -> Option<&Symbol> {
+        let i = self.symbols.partition_point(|s| s.addr <= addr);
+        self.symbols.get(i.checked_sub(1)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+ffffffff81000000 T _stext
+ffffffff81a2b3c0 T kfree_skb_reason
+ffffffff81a2b500 t skb_release_data
+ffffffff82600000 D init_task
+ffffffffc0a01000 t nft_do_chain\t[nf_tables]
+";
+
+    #[test]
+    fn test_resolve() {
+        let symbols = KernelSymbols::parse(SAMPLE);
+        assert_eq!(symbols.resolve(0xffffffff81a2b3c0), Some(("kfree_skb_reason", 0)));
+        assert_eq!(symbols.resolve(0xffffffff81a2b3fe), Some(("kfree_skb_reason", 0x3e)));
+        assert_eq!(symbols.format(0xffffffff81a2b510), "skb_release_data+0x10");
+        assert_eq!(symbols.format(0xffffffffc0a01042), "nft_do_chain+0x42 [nf_tables]");
+        // Below the first symbol
+        assert_eq!(symbols.format(0x1000), "0x1000");
+    }
+
+    #[test]
+    fn test_restricted_addresses_are_skipped() {
+        let symbols = KernelSymbols::parse("0000000000000000 T _stext\n0000000000000000 T kfree_skb_reason\n");
+        assert!(symbols.resolve(0xffffffff81a2b3c0).is_none());
+    }
+}
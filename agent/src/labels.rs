@@ -0,0 +1,67 @@
+//! Deployment Labels
+//!
+//! The `labels:` map from the config (env, team, region, ...) is set once
+//! at startup and attached to what the agent exports: heartbeats, /metrics
+//! samples, JSON log records, OTLP resource attributes, pipeline summaries,
+//! flow rollup windows, cross-node flow batches and the daemon status served
+//! to local clients.
+//!
+//! Names follow Prometheus label rules so they can be used as-is there.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+static LABELS: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+/// Attach `labels` to exported data from now on; later calls are ignored
+pub fn set(labels: &BTreeMap<String, String>) {
+    let _ = LABELS.set(labels.clone());
+}
+
+/// Labels set at startup (empty before [`set`])
+pub fn get() -> &'static BTreeMap<String, String> {
+    static EMPTY: BTreeMap<String, String> = BTreeMap::new();
+    LABELS.get().unwrap_or(&EMPTY)
+}
+
+/// Prometheus label name: `[a-zA-Z_][a-zA-Z0-9_]*`, not starting with `__`
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__")
+}
+
+/// Parse `key=value,key=value` (SENNET_LABELS)
+pub fn parse_list(list: &str) -> Result<BTreeMap<String, String>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => Ok((key.trim().to_string(), value.trim().to_string())),
+            None => anyhow::bail!("label '{}' must be key=value", pair),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_and_parse() {
+        assert!(is_valid_name("env"));
+        assert!(is_valid_name("_team2"));
+        assert!(!is_valid_name("2fast"));
+        assert!(!is_valid_name("team-name"));
+        assert!(!is_valid_name("__name__"));
+        assert!(!is_valid_name(""));
+
+        let labels = parse_list("env=prod, region = eu-west-1,").unwrap();
+        assert_eq!(labels.get("env").map(String::as_str), Some("prod"));
+        assert_eq!(labels.get("region").map(String::as_str), Some("eu-west-1"));
+        assert!(parse_list("env").is_err());
+        assert!(parse_list("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+//! Latency Probing
+//!
+//! Tracks round-trip times to the control plane (measured on every
+//! heartbeat) and to optional user-defined HTTP(S) SLO targets.
+//! A short history is kept per target and persisted to the state
+//! directory so `sennet status` can report p50/p95 without talking
+//! to the running daemon.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Number of samples kept per target
+pub const HISTORY_LEN: usize = 120;
+
+/// Target name used for heartbeat round-trips
+pub const CONTROL_PLANE: &str = "control-plane";
+
+/// File name of the persisted history inside the state directory
+const STATE_FILE: &str = "latency.json";
+
+/// Per-request timeout for SLO probes
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Rolling latency history for a single target
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LatencyHistory {
+    /// Most recent round-trip times in milliseconds (oldest first)
+    samples: VecDeque<f64>,
+    /// Failed probes since the agent started
+    #[serde(default)]
+    pub failures: u64,
+}
+
+impl LatencyHistory {
+    /// Record a successful round-trip
+    pub fn record(&mut self, rtt: Duration) {
+        if self.samples.len() == HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rtt.as_secs_f64() * 1000.0);
+    }
+
+    /// Record a failed probe (timeout, connection error, non-2xx)
+    pub fn record_failure(&mut self) {
+        self.failures += 1;
+    }
+
+    /// Nearest-rank percentile in milliseconds (p in 0.0-100.0)
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.samples.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+
+    pub fn p50(&self) -> Option<f64> {
+        self.percentile(50.0)
+    }
+
+    pub fn p95(&self) -> Option<f64> {
+        self.percentile(95.0)
+    }
+
+    /// Number of samples currently in the window
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+/// p50/p95 summary for one target, sent with heartbeats
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetLatency {
+    pub target: String,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub failures: u64,
+}
+
+/// Latency histories for all targets
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LatencyStore {
+    targets: BTreeMap<String, LatencyHistory>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// Latency store shared between the heartbeat loop and the prober
+pub type SharedLatency = Arc<Mutex<LatencyStore>>;
+
+impl LatencyStore {
+    /// Load persisted history from the state directory (empty if missing)
+    pub fn load(state_dir: &Path) -> Self {
+        let path = state_dir.join(STATE_FILE);
+        let mut store: LatencyStore = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        store.path = Some(path);
+        store
+    }
+
+    /// Load persisted history and wrap it for sharing between tasks
+    pub fn shared(state_dir: &Path) -> SharedLatency {
+        Arc::new(Mutex::new(Self::load(state_dir)))
+    }
+
+    /// Persist history to the state directory
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let content = serde_json::to_string(self).context("Failed to serialize latency history")?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write latency history: {}", path.display()))
+    }
+
+    pub fn record(&mut self, target: &str, rtt: Duration) {
+        self.targets.entry(target.to_string()).or_default().record(rtt);
+    }
+
+    pub fn record_failure(&mut self, target: &str) {
+        self.targets.entry(target.to_string()).or_default().record_failure();
+    }
+
+    pub fn get(&self, target: &str) -> Option<&LatencyHistory> {
+        self.targets.get(target)
+    }
+
+    /// Iterate over all targets and their histories
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &LatencyHistory)> {
+        self.targets.iter()
+    }
+
+    /// p50/p95 for every target that has samples, excluding the control plane
+    pub fn target_summaries(&self) -> Vec<TargetLatency> {
+        self.targets
+            .iter()
+            .filter(|(name, _)| name.as_str() != CONTROL_PLANE)
+            .filter_map(|(name, hist)| {
+                Some(TargetLatency {
+                    target: name.clone(),
+                    p50_ms: hist.p50()?,
+                    p95_ms: hist.p95()?,
+                    failures: hist.failures,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Measure a single HTTP(S) GET round-trip to `url`
+///
+/// Any HTTP response (including 4xx/5xx) counts as reachable; only
+/// transport errors and timeouts are failures.
+pub fn probe_once(url: &str) -> Result<Duration> {
+    let start = Instant::now();
+    match ureq::get(url).timeout(PROBE_TIMEOUT).call() {
+        Ok(_) | Err(ureq::Error::Status(_, _)) => Ok(start.elapsed()),
+        Err(e) => Err(anyhow::anyhow!("{}", e)),
+    }
+}
+
+/// Periodically probe the configured SLO targets
+pub async fn run_prober(store: SharedLatency, targets: Vec<String>, interval: Duration) {
+    if targets.is_empty() {
+        return;
+    }
+
+    info!("Starting latency prober ({} targets, interval: {:?})", targets.len(), interval);
+
+    loop {
+        for target in &targets {
+            let url = target.clone();
+            let result = tokio::task::spawn_blocking(move || probe_once(&url)).await;
+
+            let mut store = store.lock().unwrap_or_else(|e| e.into_inner());
+            match result {
+                Ok(Ok(rtt)) => {
+                    debug!("Probe {} took {:?}", target, rtt);
+                    store.record(target, rtt);
+                }
+                Ok(Err(e)) => {
+                    warn!("Latency probe to {} failed: {}", target, e);
+                    store.record_failure(target);
+                }
+                Err(e) => {
+                    warn!("Latency probe task panicked: {}", e);
+                    store.record_failure(target);
+                }
+            }
+        }
+
+        if let Err(e) = store.lock().unwrap_or_else(|e| e.into_inner()).save() {
+            debug!("Could not persist latency history: {}", e);
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_percentiles() {
+        let mut hist = LatencyHistory::default();
+        assert!(hist.p50().is_none());
+
+        for ms in 1..=100 {
+            hist.record(Duration::from_millis(ms));
+        }
+        assert_eq!(hist.p50(), Some(50.0));
+        assert_eq!(hist.p95(), Some(95.0));
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut hist = LatencyHistory::default();
+        for ms in 0..(HISTORY_LEN as u64 + 10) {
+            hist.record(Duration::from_millis(ms));
+        }
+        assert_eq!(hist.len(), HISTORY_LEN);
+        // Oldest samples were evicted
+        assert_eq!(hist.percentile(0.0), Some(10.0));
+    }
+
+    #[test]
+    fn test_store_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let mut store = LatencyStore::load(dir.path());
+        store.record(CONTROL_PLANE, Duration::from_millis(20));
+        store.record("https://api.example.com", Duration::from_millis(40));
+        store.record_failure("https://api.example.com");
+        store.save().unwrap();
+
+        let loaded = LatencyStore::load(dir.path());
+        assert_eq!(loaded.get(CONTROL_PLANE).unwrap().p50(), Some(20.0));
+
+        // Control plane is reported separately from SLO targets
+        let summaries = loaded.target_summaries();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].failures, 1);
+    }
+}
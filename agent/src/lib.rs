@@ -0,0 +1,209 @@
+//! Sennet Collection Engine
+//!
+//! The `sennet` binary is a thin CLI over this library. Other Rust daemons
+//! can embed the same engine instead of shelling out to it: load the eBPF
+//! programs with [`ebpf::EbpfManager`], run the event pipeline
+//! ([`pipeline::spawn`]) with their own sinks, and feed it from the kernel
+//! ring buffers ([`pipeline::spawn_reader`]).
+//!
+//! ```no_run
+//! use sennet_agent::ebpf::EbpfManager;
+//! use sennet_agent::enrich::DeepEnricher;
+//! use sennet_agent::pipeline::{self, PipelineConfig, SinkFn, Summary};
+//!
+//! #[tokio::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     let config = PipelineConfig { enabled: true, ..Default::default() };
+//!     let sink: SinkFn = Box::new(|summary: &Summary| {
+//!         println!("{} events, drops by reason: {:?}", summary.events, summary.drops_by_reason);
+//!     });
+//!     let (handle, tasks) = pipeline::spawn(&config, vec![sink], DeepEnricher::default());
+//!
+//!     // Needs root (or CAP_BPF and CAP_NET_ADMIN)
+//!     let mut manager = EbpfManager::load_and_attach("eth0")?;
+//!     #[cfg(target_os = "linux")]
+//!     let _reader = pipeline::spawn_reader(&mut manager, &config, handle);
+//!     # #[cfg(not(target_os = "linux"))]
+//!     # drop((handle, &mut manager));
+//!
+//!     for task in tasks {
+//!         task.await?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Custom enrichment and export plug in through [`enrich::EventEnricher`]
+//! and [`pipeline::EventSink`], either directly or by name through a
+//! [`plugins::Registry`].
+//!
+//! The modules below are the supported API. The rest are public for the
+//! `sennet` binary and hidden from the docs; they may change without notice.
+
+// -----------------------------------------------------------------------------
+// Embedding API
+
+/// Agent configuration (`config.yaml`), including the pipeline section
+pub mod config;
+/// eBPF program loading, pinned maps and kernel-side tunables
+pub mod ebpf;
+/// Raw kernel event records and ring buffer decoding
+pub mod events;
+/// Daemon event pipeline: reader, enrichment, aggregation and sinks
+pub mod pipeline;
+/// Enrichment of notable events (ASN, country, container, pod)
+pub mod enrich;
+/// Enricher and sink registry, configured from `pipeline.enrichers` and `pipeline.sinks`
+pub mod plugins;
+/// Built-in summary sinks: Prometheus, OTLP, webhook, syslog, exec
+pub mod sinks;
+/// Merging of identical drops within a time window
+pub mod coalesce;
+/// Per-reason and per-key rate limiting
+pub mod ratelimit;
+/// Filter expression language over events and flows
+pub mod filter;
+/// Flow table rollups
+pub mod rollup;
+/// Flow export to files and collectors
+pub mod flowexport;
+/// Rolling on-disk recording of pipeline summaries
+pub mod blackbox;
+/// Agent self-metrics (Prometheus text and JSON)
+pub mod selfmetrics;
+/// Static labels attached to exported data
+pub mod labels;
+/// Kubernetes pod and NetworkPolicy context
+pub mod k8s;
+/// ASN lookup of remote addresses
+pub mod asn;
+/// Interface index to name resolution
+pub mod ifnames;
+/// Drop attribution to owning processes
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub mod attribution;
+
+// -----------------------------------------------------------------------------
+// Used by the `sennet` binary
+
+#[doc(hidden)]
+pub mod identity;
+#[doc(hidden)]
+pub mod heartbeat;
+#[doc(hidden)]
+pub mod client;
+#[doc(hidden)]
+pub mod interface;
+#[doc(hidden)]
+pub mod hotplug;
+#[doc(hidden)]
+pub mod upgrade;
+#[doc(hidden)]
+pub mod status;
+#[doc(hidden)]
+pub mod tui;
+#[doc(hidden)]
+pub mod init;
+#[doc(hidden)]
+pub mod trace;
+#[doc(hidden)]
+pub mod flows;
+#[doc(hidden)]
+pub mod talkers;
+#[doc(hidden)]
+pub mod block;
+#[doc(hidden)]
+pub mod rtt;
+#[doc(hidden)]
+pub mod crypto;
+#[doc(hidden)]
+pub mod btf;
+#[doc(hidden)]
+pub mod kallsyms;
+#[doc(hidden)]
+pub mod tracefmt;
+#[doc(hidden)]
+pub mod clock;
+#[doc(hidden)]
+pub mod docker;
+#[doc(hidden)]
+pub mod conntrack;
+#[doc(hidden)]
+pub mod latency;
+#[doc(hidden)]
+pub mod audit;
+#[doc(hidden)]
+pub mod resets;
+#[doc(hidden)]
+pub mod dns;
+#[doc(hidden)]
+pub mod httpreq;
+#[doc(hidden)]
+pub mod retransmits;
+#[doc(hidden)]
+pub mod drops;
+#[doc(hidden)]
+pub mod budget;
+#[doc(hidden)]
+pub mod logging;
+#[doc(hidden)]
+pub mod http;
+#[doc(hidden)]
+pub mod crash;
+#[doc(hidden)]
+pub mod telemetry;
+#[doc(hidden)]
+pub mod service;
+#[doc(hidden)]
+pub mod install;
+#[doc(hidden)]
+pub mod privileges;
+#[doc(hidden)]
+pub mod daemonset;
+#[doc(hidden)]
+pub mod doctor;
+#[doc(hidden)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub mod firewall;
+#[doc(hidden)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub mod iptables;
+#[doc(hidden)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub mod nftables;
+#[doc(hidden)]
+#[cfg(unix)]
+pub mod control;
+#[doc(hidden)]
+#[cfg(unix)]
+pub mod grpc;
+#[doc(hidden)]
+#[cfg(unix)]
+pub mod api;
+#[doc(hidden)]
+#[cfg(unix)]
+pub mod grafana;
+#[doc(hidden)]
+#[cfg(unix)]
+pub mod remote;
+#[doc(hidden)]
+#[cfg(unix)]
+pub mod watch;
+#[doc(hidden)]
+#[cfg(unix)]
+pub mod probe;
+#[doc(hidden)]
+#[cfg(unix)]
+pub mod probes;
+#[doc(hidden)]
+#[cfg(unix)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub mod replay;
+#[doc(hidden)]
+#[cfg(any(windows, target_os = "macos", test))]
+#[cfg_attr(not(any(windows, target_os = "macos")), allow(dead_code))]
+pub mod ifstats;
+#[doc(hidden)]
+#[cfg(any(target_os = "macos", test))]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub mod capture;
@@ -0,0 +1,460 @@
+//! Logging Setup
+//!
+//! `log_format: text` (the default) prints human-readable lines. `json`
+//! prints one object per line with a fixed set of keys, so Loki or
+//! Elastic can ingest agent logs without regex parsing:
+//!
+//! ```text
+//! {"timestamp":"2026-01-01T00:00:00.000Z","level":"INFO","agent_id":"…","module":"sennet::heartbeat","event":"Heartbeat successful"}
+//! ```
+//!
+//! Structured fields of the log call follow as top-level keys.
+//!
+//! Started by systemd with `log_format: text`, the agent writes to the
+//! journal directly instead: levels map to journal priorities, so
+//! `journalctl -u sennet -p warning` works, and structured fields become
+//! journal fields (`F_FLOWS=3`).
+//!
+//! With `log_file` set, logs go to that file instead, rotated by size. A
+//! background thread does the writing; if it falls behind, lines are
+//! dropped rather than blocking the thread that logged them.
+//!
+//! Whatever the output, the last few hundred lines are also kept in memory
+//! for crash reports. With `otlp_endpoint` set, spans are also exported
+//! over OTLP (see `telemetry`).
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_appender::non_blocking::{NonBlockingBuilder, WorkerGuard};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::config::Config;
+
+/// Output format of agent logs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => anyhow::bail!("Unknown log format '{}' (expected text or json)", other),
+        }
+    }
+}
+
+static AGENT_ID: OnceLock<String> = OnceLock::new();
+
+/// Include the agent ID in every JSON log record from now on
+pub fn set_agent_id(agent_id: &str) {
+    let _ = AGENT_ID.set(agent_id.to_string());
+}
+
+/// Keeps buffered output alive; hold until exit
+///
+/// Dropping it flushes the log file writer and any spans not yet exported.
+#[derive(Default)]
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+    otlp: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.otlp.take() {
+            let _ = provider.shutdown();
+        }
+    }
+}
+
+/// Install the global subscriber; level comes from RUST_LOG (default info)
+///
+/// `config` is None for CLI commands run without a configuration.
+pub fn init(config: Option<&Config>) -> LogGuard {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+    let format = config.map(|c| c.log_format).unwrap_or_default();
+    let journald = config.is_none_or(|c| c.log_journald);
+
+    let mut guard = LogGuard::default();
+    let otlp = config.and_then(|c| c.otlp_endpoint.as_deref()).and_then(|endpoint| {
+        match crate::telemetry::tracer(endpoint) {
+            Ok((tracer, provider)) => {
+                guard.otlp = Some(provider);
+                Some(tracing_opentelemetry::layer().with_tracer(tracer))
+            }
+            Err(e) => {
+                eprintln!("Could not set up OTLP export to {}: {:#}", endpoint, e);
+                None
+            }
+        }
+    });
+
+    let registry = tracing_subscriber::registry().with(filter).with(RecentLines).with(otlp);
+    if let Some((path, config)) = config.and_then(|c| Some((c.log_file.as_deref()?, c))) {
+        let max_bytes = config.log_file_max_size_mb.max(1) << 20;
+        match RotatingFile::open(path, max_bytes, config.log_file_max_files) {
+            Ok(file) => {
+                let (writer, file_guard) = NonBlockingBuilder::default()
+                    .lossy(true)
+                    .thread_name("sennet-log")
+                    .finish(file);
+                let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(false);
+                match format {
+                    LogFormat::Text => registry.with(layer).init(),
+                    LogFormat::Json => registry.with(layer.event_format(JsonFormat)).init(),
+                }
+                guard._file = Some(file_guard);
+                return guard;
+            }
+            Err(e) => eprintln!("Could not open log file {}, logging to stdout: {}", path.display(), e),
+        }
+    }
+    if format == LogFormat::Text && journald && under_systemd() {
+        match journald_layer() {
+            Ok(layer) => {
+                registry.with(layer).init();
+                return guard;
+            }
+            // Fall through to stdout, which systemd still captures
+            Err(e) => eprintln!("Could not connect to journald, logging to stdout: {}", e),
+        }
+    }
+    match format {
+        LogFormat::Text => registry.with(tracing_subscriber::fmt::layer()).init(),
+        LogFormat::Json => registry.with(tracing_subscriber::fmt::layer().event_format(JsonFormat)).init(),
+    }
+    guard
+}
+
+/// Lines kept for [`recent_lines`]
+const RECENT_LINES: usize = 200;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// The most recent log lines, oldest first
+///
+/// Called from the panic hook, so it never waits on the lock: if a panic
+/// interrupted a thread holding it, the tail is reported as empty.
+pub fn recent_lines() -> Vec<String> {
+    match RECENT.try_lock() {
+        Ok(lines) => lines.iter().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Layer that copies each event into the in-memory tail
+struct RecentLines;
+
+impl<S: Subscriber> Layer<S> for RecentLines {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let mut line = format!(
+            "{} {:>5} {}: {}",
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            meta.level(),
+            meta.target(),
+            visitor.message
+        );
+        for (key, value) in &visitor.fields {
+            line.push_str(&format!(" {}={}", key, value));
+        }
+        if let Ok(mut lines) = RECENT.lock() {
+            if lines.len() == RECENT_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+    }
+}
+
+/// Log file that is rotated once it reaches a size limit
+///
+/// On rotation `sennet.log` becomes `sennet.log.1`, `.1` becomes `.2` and
+/// so on; files beyond `max_files` are deleted.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            max_files,
+            file,
+            written,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            self.file.set_len(0)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.max_files));
+            for n in (1..self.max_files).rev() {
+                let _ = fs::rename(self.rotated_path(n), self.rotated_path(n + 1));
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Each call is one formatted line, so lines never straddle files
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Whether stdout/stderr are connected to the journal (set by systemd)
+fn under_systemd() -> bool {
+    std::env::var_os("JOURNAL_STREAM").is_some()
+}
+
+#[cfg(target_os = "linux")]
+fn journald_layer() -> std::io::Result<tracing_journald::Layer> {
+    Ok(tracing_journald::layer()?
+        .with_syslog_identifier("sennet".to_string())
+        .with_priority_mappings(journal_priorities()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn journald_layer() -> std::io::Result<tracing_subscriber::layer::Identity> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "journald is only available on Linux"))
+}
+
+/// Syslog meanings rather than the crate default, which logs INFO as NOTICE
+/// and would make `-p notice` show every routine message
+#[cfg(target_os = "linux")]
+fn journal_priorities() -> tracing_journald::PriorityMappings {
+    use tracing_journald::Priority;
+
+    tracing_journald::PriorityMappings {
+        error: Priority::Error,
+        warn: Priority::Warning,
+        info: Priority::Informational,
+        debug: Priority::Debug,
+        trace: Priority::Debug,
+    }
+}
+
+/// One JSON object per event with consistent top-level keys
+pub struct JsonFormat;
+
+#[derive(Serialize)]
+struct Record<'a> {
+    timestamp: String,
+    level: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agent_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    labels: &'a BTreeMap<String, String>,
+    module: &'a str,
+    event: String,
+    #[serde(flatten)]
+    fields: BTreeMap<&'static str, serde_json::Value>,
+}
+
+/// Keys fields may not overwrite
+const RESERVED: [&str; 6] = ["timestamp", "level", "agent_id", "labels", "module", "event"];
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, _ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let meta = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let record = Record {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            level: meta.level().as_str(),
+            agent_id: AGENT_ID.get().map(String::as_str),
+            labels: crate::labels::get(),
+            module: meta.module_path().unwrap_or_else(|| meta.target()),
+            event: visitor.message,
+            fields: visitor.fields,
+        };
+        let line = serde_json::to_string(&record).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{}", line)
+    }
+}
+
+/// Collects an event's message and fields as JSON values
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: BTreeMap<&'static str, serde_json::Value>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: serde_json::Value) {
+        if field.name() == "message" {
+            self.message = match value {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+        } else if !RESERVED.contains(&field.name()) {
+            self.fields.insert(field.name(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_record_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .event_format(JsonFormat)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(flows = 3, interface = "eth0", level = "spoofed", "Flow rollup window flushed");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let record: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(record["level"], "WARN");
+        assert_eq!(record["module"], module_path!());
+        assert_eq!(record["event"], "Flow rollup window flushed");
+        assert_eq!(record["flows"], 3);
+        assert_eq!(record["interface"], "eth0");
+        assert!(record["timestamp"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_journal_priorities() {
+        use tracing_journald::Priority;
+
+        let priorities = journal_priorities();
+        assert_eq!(priorities.warn, Priority::Warning);
+        assert_eq!(priorities.info, Priority::Informational);
+        assert_eq!(priorities.debug, Priority::Debug);
+    }
+
+    #[test]
+    fn test_rotating_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("logs/sennet.log");
+        let mut file = RotatingFile::open(&path, 100, 2).unwrap();
+        let line = [b'x'; 60];
+        for _ in 0..5 {
+            file.write_all(&line).unwrap();
+        }
+        file.flush().unwrap();
+
+        // One line per file: current, .1 and .2; the oldest two were deleted
+        assert_eq!(fs::metadata(&path).unwrap().len(), 60);
+        assert_eq!(fs::metadata(dir.path().join("logs/sennet.log.2")).unwrap().len(), 60);
+        assert!(!dir.path().join("logs/sennet.log.3").exists());
+
+        // Reopening continues the current file's size
+        let reopened = RotatingFile::open(&path, 100, 2).unwrap();
+        assert_eq!(reopened.written, 60);
+    }
+
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+}
@@ -1,29 +1,24 @@
 //! Sennet Agent - Network Observability Agent
 //!
 //! This agent connects to the Sennet control plane, sends heartbeats,
-//! and runs eBPF programs for packet analysis.
-
-mod config;
-mod identity;
-mod heartbeat;
-mod client;
-mod interface;
-mod ebpf;
-mod upgrade;
-mod status;
-mod tui;
-mod init;
-mod trace;
-mod k8s;
-mod flows;
-mod crypto;
-mod btf;
-mod docker;
+//! and runs eBPF programs for packet analysis. The engine itself lives in
+//! the `sennet_agent` library; this binary is the CLI and daemon around it.
+
+use sennet_agent::{
+    blackbox, block, budget, client, config, conntrack, crash, daemonset, dns, doctor, drops, ebpf, firewall,
+    flowexport, flows, heartbeat, http, httpreq, identity, init, install, interface, k8s, labels, latency,
+    logging, pipeline, plugins, resets, retransmits, rollup, rtt, selfmetrics, status, talkers,
+    trace, tui, upgrade,
+};
+#[cfg(unix)]
+use sennet_agent::{api, control, grpc, probe, probes, replay, watch};
+#[cfg(target_os = "linux")]
+use sennet_agent::{hotplug, privileges};
 
 use anyhow::Result;
 use tracing::{info, error, warn};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 use tokio::signal;
+use std::time::Duration;
 use colored::Colorize;
 
 use crate::config::Config;
@@ -42,6 +37,15 @@ async fn main() -> Result<()> {
                 // Init doesn't need tracing - it's interactive
                 return init::run();
             }
+            "install" | "uninstall" => {
+                // Prints its own step-by-step progress
+                let rest = &args[2..];
+                if rest.iter().any(|a| a == "--help" || a == "-h") {
+                    install::print_help();
+                    return Ok(());
+                }
+                return if args[1] == "install" { install::install(rest) } else { install::uninstall(rest) };
+            }
             "help" | "--help" | "-h" => {
                 print_help();
                 return Ok(());
@@ -56,12 +60,19 @@ async fn main() -> Result<()> {
     }
 
     // Initialize tracing for remaining commands
-    init_tracing();
+    let log_config = Config::load().ok();
+    if let Some(config) = &log_config {
+        labels::set(&config.labels);
+    }
+    let _log_guard = logging::init(log_config.as_ref());
 
     // Handle remaining commands
     if args.len() > 1 {
         match args[1].as_str() {
             "upgrade" => {
+                if Config::load().is_ok_and(|c| c.offline) {
+                    anyhow::bail!("Upgrades are disabled in offline mode; replace the binary manually");
+                }
                 info!("Checking for updates...");
                 let updater = Updater::new()?;
                 
@@ -79,11 +90,17 @@ async fn main() -> Result<()> {
                 return Ok(());
             }
             "status" => {
-                status::run()?;
+                let verbose = args[2..].iter().any(|a| a == "--verbose" || a == "-v");
+                status::run(verbose)?;
                 return Ok(());
             }
             "top" => {
-                tui::run()?;
+                let top_args: Vec<String> = args[2..].to_vec();
+                if top_args.iter().any(|a| a == "--help" || a == "-h") {
+                    tui::print_help();
+                } else {
+                    tui::run(&top_args)?;
+                }
                 return Ok(());
             }
             "trace" => {
@@ -96,6 +113,48 @@ async fn main() -> Result<()> {
                 }
                 return Ok(());
             }
+            #[cfg(unix)]
+            "record" | "replay" => {
+                let replay_args: Vec<String> = args[2..].to_vec();
+                if replay_args.iter().any(|a| a == "--help" || a == "-h") {
+                    replay::print_help();
+                } else if args[1] == "record" {
+                    replay::record(&replay_args)?;
+                } else {
+                    replay::replay(&replay_args).await?;
+                }
+                return Ok(());
+            }
+            "export" => {
+                let export_args: Vec<String> = args[2..].to_vec();
+                if export_args.iter().any(|a| a == "--help" || a == "-h") {
+                    blackbox::print_help();
+                } else {
+                    blackbox::export(&export_args)?;
+                }
+                return Ok(());
+            }
+            #[cfg(unix)]
+            "probes" => {
+                // Attach or detach tracing probes of the running agent
+                let probe_args: Vec<String> = args[2..].to_vec();
+                if probe_args.iter().any(|a| a == "--help" || a == "-h") {
+                    probes::print_help();
+                } else {
+                    probes::run(&probe_args)?;
+                }
+                return Ok(());
+            }
+            #[cfg(unix)]
+            "watch" => {
+                let watch_args: Vec<String> = args[2..].to_vec();
+                if watch_args.iter().any(|a| a == "--help" || a == "-h") {
+                    watch::print_help();
+                } else {
+                    watch::run(&watch_args).await?;
+                }
+                return Ok(());
+            }
             "diagnose" => {
                 // Kubernetes connectivity diagnosis (Phase 7.4)
                 let diag_args: Vec<String> = args[2..].to_vec();
@@ -106,6 +165,14 @@ async fn main() -> Result<()> {
                 }
                 return Ok(());
             }
+            "doctor" => {
+                if args[2..].iter().any(|a| a == "--help" || a == "-h") {
+                    doctor::print_help();
+                } else {
+                    doctor::run()?;
+                }
+                return Ok(());
+            }
             "flows" => {
                 // Network flow tracking with PID attribution (Phase 8)
                 let flow_args: Vec<String> = args[2..].to_vec();
@@ -116,6 +183,78 @@ async fn main() -> Result<()> {
                 }
                 return Ok(());
             }
+            "top-talkers" => {
+                // Busiest source/destination pairs
+                let talker_args: Vec<String> = args[2..].to_vec();
+                if talker_args.iter().any(|a| a == "--help" || a == "-h") {
+                    talkers::print_help();
+                } else {
+                    talkers::run(&talker_args)?;
+                }
+                return Ok(());
+            }
+            "block" => {
+                // Blocklist enforced by the TC/XDP programs
+                let block_args: Vec<String> = args[2..].to_vec();
+                if block_args.iter().any(|a| a == "--help" || a == "-h") {
+                    block::print_help();
+                } else {
+                    block::run(&block_args)?;
+                }
+                return Ok(());
+            }
+            "latency" => {
+                // TCP RTT percentiles per remote
+                let rtt_args: Vec<String> = args[2..].to_vec();
+                if rtt_args.iter().any(|a| a == "--help" || a == "-h") {
+                    rtt::print_help();
+                } else {
+                    rtt::run(&rtt_args)?;
+                }
+                return Ok(());
+            }
+            "resets" => {
+                // TCP RST cause analysis
+                let reset_args: Vec<String> = args[2..].to_vec();
+                if reset_args.iter().any(|a| a == "--help" || a == "-h") {
+                    resets::print_help();
+                } else {
+                    resets::run(&reset_args)?;
+                }
+                return Ok(());
+            }
+            "dns" => {
+                // DNS query rates and resolver latency
+                let dns_args: Vec<String> = args[2..].to_vec();
+                if dns_args.iter().any(|a| a == "--help" || a == "-h") {
+                    dns::print_help();
+                } else {
+                    dns::run(&dns_args)?;
+                }
+                return Ok(());
+            }
+            "http" => {
+                // Sampled HTTP/1.x request lines
+                let http_args: Vec<String> = args[2..].to_vec();
+                if http_args.iter().any(|a| a == "--help" || a == "-h") {
+                    httpreq::print_help();
+                } else {
+                    httpreq::run(&http_args)?;
+                }
+                return Ok(());
+            }
+            "conntrack" => {
+                // Tracked connections and their lifecycle
+                let ct_args: Vec<String> = args[2..].to_vec();
+                if ct_args.iter().any(|a| a == "--help" || a == "-h") {
+                    conntrack::print_help();
+                } else {
+                    conntrack::run(&ct_args)?;
+                }
+                return Ok(());
+            }
+            // Daemon flag, applied once the config is loaded
+            "--all-interfaces" => {}
             cmd => {
                 eprintln!("{} Unknown command: '{}'", "Error:".red(), cmd);
                 eprintln!();
@@ -128,7 +267,7 @@ async fn main() -> Result<()> {
     info!("Sennet Agent starting...");
 
     // Load configuration
-    let config = match Config::load() {
+    let mut config = match Config::load() {
         Ok(cfg) => {
             info!("Configuration loaded from {}", cfg.config_path().display());
             cfg
@@ -139,9 +278,42 @@ async fn main() -> Result<()> {
         }
     };
 
+    // Size kernel maps, ring buffers and pipeline queues from the memory budget
+    let memory_budget = budget::MemoryBudget::resolve(config.memory_budget_mb);
+    memory_budget.apply(&mut config.pipeline);
+    info!(
+        "Memory budget: {}MB{} (flow map: {} entries, drop ring: {}KB)",
+        memory_budget.budget_mb,
+        if memory_budget.automatic { " (auto)" } else { "" },
+        memory_budget.maps.flow_entries,
+        memory_budget.maps.drop_ring_bytes / 1024
+    );
+
+    // Running as a DaemonSet pod: keep state on a mounted volume
+    if let Some(pod) = daemonset::PodIdentity::from_env() {
+        info!("Running as {}", pod);
+        if config.state_dir == config::state_dir_for(config.pin_namespace.as_deref()) {
+            match daemonset::select_state_dir(&config.state_dir) {
+                Some(mount) => {
+                    info!("State directory: {} ({})", mount.path.display(), mount.kind.name());
+                    if mount.kind == daemonset::VolumeKind::EmptyDir {
+                        warn!("State is on an emptyDir volume; the agent ID changes when the pod is replaced");
+                    }
+                    config.state_dir = mount.path;
+                }
+                None => warn!(
+                    "No volume mounted for {}; mount a hostPath there to keep the agent ID across pod restarts",
+                    config.state_dir.display()
+                ),
+            }
+        }
+    }
+
     // Load or create agent identity
     let identity = match IdentityManager::load_or_create(&config) {
         Ok(id) => {
+            logging::set_agent_id(id.agent_id());
+            crash::install_hook(&config, id.agent_id());
             info!("Agent ID: {}", id.agent_id());
             id
         }
@@ -151,35 +323,137 @@ async fn main() -> Result<()> {
         }
     };
 
-    // Discover network interface (used by eBPF on Linux)
+    // Discover network interfaces (used by eBPF on Linux)
+    if args.iter().skip(1).any(|a| a == "--all-interfaces") {
+        config.all_interfaces = true;
+    }
     #[allow(unused_variables)] // Used only on Linux for eBPF attachment
-    let interface = match interface::discover_default_interface(config.interface.as_deref()) {
-        Ok(iface) => {
-            info!("Network interface: {}", iface);
-            iface
-        }
-        Err(e) => {
-            warn!("Interface discovery failed: {}. eBPF will be disabled.", e);
-            String::new()
-        }
-    };
+    let interfaces =
+        match interface::discover_interfaces(config.interface.as_deref(), &config.interfaces, config.all_interfaces) {
+            Ok(interfaces) => {
+                info!("Network interface: {}", interfaces.join(","));
+                interfaces
+            }
+            Err(e) => {
+                warn!("Interface discovery failed: {}. eBPF will be disabled.", e);
+                Vec::new()
+            }
+        };
 
     // Load and attach eBPF programs (Linux only)
     #[cfg(target_os = "linux")]
-    let _ebpf_manager = if !interface.is_empty() {
-        match ebpf::EbpfManager::load_and_attach(&interface) {
-            Ok(mgr) => {
+    ebpf::set_pin_namespace(config.pin_namespace.as_deref());
+    #[cfg(target_os = "linux")]
+    let mut ebpf_manager = if !interfaces.is_empty() {
+        let missing = privileges::check();
+        if !missing.is_empty() {
+            warn!(
+                "Missing {} for eBPF; grant them with `sennet install` (file capabilities) or AmbientCapabilities",
+                missing.join(", ")
+            );
+        }
+        let loaded = tracing::info_span!("ebpf.load", interface = %interfaces.join(","))
+            .in_scope(|| ebpf::EbpfManager::load_and_attach_all(&interfaces, &memory_budget.maps, config.attach_mode));
+        match loaded {
+            Ok(mut mgr) => {
                 info!("eBPF programs loaded successfully");
+                if mgr.attach_mode == ebpf::AttachMode::Xdp {
+                    info!("Ingress counting: XDP");
+                }
+                selfmetrics::global().set_ebpf_attached(true);
+                selfmetrics::global().set_flow_map_capacity(memory_budget.maps.flow_entries);
                 if mgr.drop_tracing_enabled {
                     info!("Drop tracing: enabled (kfree_skb tracepoint attached)");
                 }
                 if mgr.nf_tracing_enabled {
-                    info!("Netfilter tracing: enabled (nf_hook_slow tracepoint attached)");
+                    info!("Netfilter tracing: enabled (nf_hook_slow fexit attached)");
+                }
+                if mgr.sock_state_tracing_enabled {
+                    info!("Connection lifecycle: enabled (inet_sock_set_state tracepoint attached)");
+                }
+                if mgr.flow_tracing_enabled {
+                    match mgr.attach_flow_attribution(ebpf::CGROUP_ROOT) {
+                        Ok(()) => info!("Flow attribution: enabled (cgroup/sock_create and egress programs attached)"),
+                        Err(e) => warn!("Flow attribution unavailable, UDP flows are not tracked: {}", e),
+                    }
+                }
+                if mgr.retransmit_tracing_enabled {
+                    info!("Retransmit tracing: enabled (tcp_retransmit_skb tracepoint attached)");
+                }
+                if mgr.rtt_tracing_enabled {
+                    info!("RTT measurement: enabled (tcp_probe tracepoint attached)");
+                }
+                if mgr.conntrack_tracing_enabled {
+                    info!("Conntrack tracing: enabled (__nf_conntrack_confirm/nf_ct_delete kprobes attached)");
+                }
+                for name in &config.disabled_probes {
+                    let Ok(probe) = name.parse::<ebpf::Probe>() else { continue };
+                    match mgr.set_probe(probe, false) {
+                        Ok(_) => info!("Probe {} detached (disabled_probes)", probe.name()),
+                        Err(e) => warn!("Failed to detach probe {}: {:#}", probe.name(), e),
+                    }
+                }
+                if config.rate_limits.is_enabled() {
+                    let ncpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+                    match mgr.set_tunables(&config.rate_limits.to_tunables(ncpus)) {
+                        Ok(()) => info!("Kernel event rate limits applied"),
+                        Err(e) => warn!("Failed to apply kernel rate limits: {}", e),
+                    }
+                }
+                if config.packet_sample_one_in > 1 {
+                    match mgr.set_packet_sampling(config.packet_sample_one_in) {
+                        Ok(()) => info!("Packet event sampling: 1 in {}", config.packet_sample_one_in.min(255)),
+                        Err(e) => warn!("Failed to apply packet sampling: {}", e),
+                    }
+                }
+                if config.large_packet_threshold != sennet_common::DEFAULT_LARGE_PACKET_THRESHOLD {
+                    match mgr.set_large_packet_threshold(config.large_packet_threshold) {
+                        Ok(()) => info!("Large packet threshold: {} bytes", config.large_packet_threshold),
+                        Err(e) => warn!("Failed to apply large packet threshold: {}", e),
+                    }
+                }
+                if config.source_pps_threshold > 0 {
+                    match mgr.set_source_pps_threshold(config.source_pps_threshold) {
+                        Ok(()) => info!("Rate anomalies: sources above {} pps", config.source_pps_threshold),
+                        Err(e) => warn!("Failed to apply the source rate threshold: {}", e),
+                    }
+                }
+                if !config.http_ports.is_empty() {
+                    match mgr.set_http_ports(&config.http_ports) {
+                        Ok(()) => info!("HTTP request sampling on ports {:?}", config.http_ports),
+                        Err(e) => warn!("Failed to apply HTTP ports: {}", e),
+                    }
+                }
+                if config.enforcement {
+                    let blocklist = block::load(&config.state_dir).unwrap_or_else(|e| {
+                        warn!("Failed to load the blocklist: {:#}", e);
+                        Vec::new()
+                    });
+                    match mgr.enable_enforcement(&blocklist) {
+                        Ok(()) => info!("Enforcement: enabled ({} blocklist entries)", blocklist.len()),
+                        Err(e) => warn!("Failed to enable enforcement: {}", e),
+                    }
+                }
+                let pipeline = &config.pipeline;
+                if pipeline.enabled && (pipeline.filter.is_some() || !pipeline.filters.ignore_reasons.is_empty()) {
+                    let mut kernel = pipeline.filter.as_ref().map(|f| f.drop_filter()).unwrap_or_default();
+                    pipeline.filters.apply_to(&mut kernel);
+                    match mgr.set_drop_filter(&kernel) {
+                        Ok(()) => {
+                            if let Some(filter) = &pipeline.filter {
+                                info!("Event filter: {}", filter);
+                            }
+                        }
+                        Err(e) => warn!("Failed to apply the event filter in the kernel: {}", e),
+                    }
                 }
                 Some(mgr)
             }
             Err(e) => {
-                warn!("Failed to load eBPF programs: {}. Continuing without packet analysis.", e);
+                warn!(
+                    "Failed to load eBPF programs: {}. Continuing without packet analysis; run `sennet doctor` for details.",
+                    e
+                );
                 None
             }
         }
@@ -187,39 +461,311 @@ async fn main() -> Result<()> {
         None
     };
 
+    // Control socket for the CLI; bound before privileges are dropped so it
+    // can be handed to the sennet group
+    #[cfg(unix)]
+    let control_listener = config.control_socket.as_deref().and_then(|path| {
+        match control::bind(path, &config.control_socket_group) {
+            Ok(listener) => {
+                info!("Control socket: {}", path.display());
+                Some(listener)
+            }
+            Err(e) => {
+                warn!("Control socket disabled: {:#}", e);
+                None
+            }
+        }
+    });
+
+    // Local gRPC API for other programs on the node; like the control
+    // socket, bound before privileges are dropped
+    #[cfg(unix)]
+    let grpc_listener = match &config.grpc_listen {
+        Some(path) => match grpc::bind(path, &config.control_socket_group) {
+            Ok(listener) => {
+                info!("gRPC API: {}", path.display());
+                Some(listener)
+            }
+            Err(e) => {
+                warn!("gRPC API disabled: {:#}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    // Programs are attached and maps pinned: give up what the daemon no longer needs
+    #[cfg(target_os = "linux")]
+    if config.drop_privileges && ebpf_manager.is_some() {
+        match privileges::drop_after_load() {
+            Ok(kept) => info!("Dropped privileges, keeping {}", kept.join(", ")),
+            Err(e) => warn!("Failed to drop privileges: {:#}", e),
+        }
+    }
+
+    if config.blackbox_minutes > 0 && !config.pipeline.enabled {
+        warn!("blackbox_minutes is set but the event pipeline is disabled; nothing will be recorded");
+    }
+
+    // Start the event pipeline (reader → enrich → aggregate → sinks)
+    let mut pipeline_tasks = Vec::new();
+    // Retransmits per destination over about one heartbeat interval
+    let retransmit_window = config.heartbeat_interval_secs.max(config.pipeline.flush_interval_secs);
+    let retransmit_rates = retransmits::SharedRetransmits::new(Duration::from_secs(retransmit_window));
+    let drop_summary = drops::SharedDrops::default();
+    #[cfg(unix)]
+    let last_window = control::LastWindow::default();
+    #[cfg(unix)]
+    let mut event_tap = None;
+    if config.pipeline.enabled {
+        let registry = plugins::Registry::builtin();
+        let mut plugin_context = plugins::PluginContext::new(&config);
+        // One Kubernetes client for the daemon, started only when the k8s
+        // enricher is configured and shared with it
+        if config.pipeline.enrichers.iter().any(|spec| spec.kind == "k8s") {
+            plugin_context = plugin_context.with_k8s(k8s::SharedK8s::start());
+        }
+        let enricher = registry.build_enricher(&config.pipeline.enrichers, &plugin_context)?;
+        info!("Enrichers: {}", enricher.names().join(", "));
+        let mut sinks = vec![pipeline::log_sink(), retransmit_rates.sink(), drop_summary.sink()];
+        #[cfg(unix)]
+        sinks.push(control::summary_sink(last_window.clone()));
+        if config.blackbox_minutes > 0 {
+            sinks.push(blackbox::sink(&config.state_dir, config.blackbox_minutes));
+        }
+        sinks.extend(registry.build_sinks(&config.pipeline.sinks, &plugin_context)?);
+        let (handle, tasks) = pipeline::spawn(&config.pipeline, sinks, enricher);
+        selfmetrics::global().attach_pipeline(handle.clone());
+        #[cfg(unix)]
+        {
+            event_tap = Some(handle.tap());
+        }
+        pipeline_tasks = tasks;
+        #[cfg(target_os = "linux")]
+        if let Some(mgr) = ebpf_manager.as_mut() {
+            let readers = if config.pipeline.pinned_readers {
+                pipeline::spawn_pinned_readers(mgr, &config.pipeline, &handle)
+            } else {
+                pipeline::spawn_reader(mgr, &config.pipeline, handle)
+            };
+            if readers.is_empty() {
+                warn!("Event pipeline enabled but no ring buffers are available");
+            }
+            pipeline_tasks.extend(readers);
+        }
+        #[cfg(not(target_os = "linux"))]
+        drop(handle);
+    }
+
     // Create client
     let client = SentinelClient::new(&config)?;
+    crash::process_pending(&config, &client);
+
+    // Latency history shared by heartbeat RTT tracking and the SLO prober
+    let latency = latency::LatencyStore::shared(&config.state_dir);
+
+    #[cfg(unix)]
+    let agent_id = identity.agent_id().to_string();
+
+    // Cross-node flow export needs the control plane
+    let flow_exporter = (config.flow_export_interval_secs > 0 && !config.offline).then(|| {
+        flowexport::FlowExporter::new(
+            identity.agent_id().to_string(),
+            flowexport::node_name(identity.pod()),
+        )
+    });
+
+    // Start heartbeat loop (offline: there is no control plane to talk to)
+    let heartbeat_handle = if config.offline {
+        info!("Offline mode: heartbeats, upgrade checks and crash uploads disabled");
+        None
+    } else {
+        let heartbeat = HeartbeatLoop::new(
+            config.clone(),
+            identity,
+            client,
+            latency.clone(),
+            retransmit_rates.clone(),
+            drop_summary.clone(),
+        );
+        Some(tokio::spawn(async move {
+            if let Err(e) = heartbeat.run().await {
+                error!("Heartbeat loop failed: {}", e);
+            }
+        }))
+    };
 
-    // Start heartbeat loop
-    let heartbeat = HeartbeatLoop::new(config.clone(), identity, client);
-    let heartbeat_handle = tokio::spawn(async move {
-        if let Err(e) = heartbeat.run().await {
-            error!("Heartbeat loop failed: {}", e);
+    // Start conntrack utilization monitor
+    let conntrack_monitor = conntrack::ConntrackMonitor::new(config.conntrack_alert_pct);
+    let conntrack_interval = Duration::from_secs(config.heartbeat_interval_secs.clamp(1, 10));
+    let conntrack_handle = tokio::spawn(conntrack_monitor.run(conntrack_interval));
+
+    // Start latency prober for configured SLO targets
+    let prober_handle = tokio::spawn(latency::run_prober(
+        latency,
+        config.latency_targets.clone(),
+        Duration::from_secs(config.latency_probe_interval_secs.max(1)),
+    ));
+
+    // Start flow rollups (reads the kernel flow map, deleting closed and idle flows)
+    let rollup_handle = (config.flow_rollup_interval_secs > 0).then(|| {
+        tokio::spawn(rollup::run(
+            config.state_dir.clone(),
+            Duration::from_secs(config.flow_rollup_interval_secs),
+        ))
+    });
+
+    // Start cross-node flow export
+    let export_handle = match flow_exporter {
+        Some(exporter) => Some(tokio::spawn(flowexport::run(
+            std::sync::Arc::new(SentinelClient::new(&config)?),
+            exporter,
+            Duration::from_secs(config.flow_export_interval_secs),
+        ))),
+        None => None,
+    };
+
+    // Self-metrics snapshot for `sennet status --verbose`
+    let selfmetrics_handle = tokio::spawn(selfmetrics::run(
+        config.state_dir.clone(),
+        Duration::from_secs(config.heartbeat_interval_secs.clamp(1, 10)),
+    ));
+
+    // Local /metrics, /healthz and /readyz endpoints
+    let http_handle = config.http_listen.clone().map(|addr| {
+        let ready_window = (!config.offline).then(|| Duration::from_secs(config.ready_heartbeat_window_secs));
+        tokio::spawn(http::serve(addr, ready_window))
+    });
+
+    // Local control socket for CLI commands, the gRPC API and the REST API
+    #[cfg(target_os = "linux")]
+    let (probe_commands_tx, mut probe_commands) = tokio::sync::mpsc::channel::<control::ProbeCommand>(4);
+    #[cfg(unix)]
+    let serves_state = control_listener.is_some() || grpc_listener.is_some() || config.api_listen.is_some();
+    #[cfg(unix)]
+    let control_state = serves_state.then(|| {
+        let status = control::DaemonStatus {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            agent_id,
+            pid: std::process::id(),
+            interface: interfaces.join(", "),
+            offline: config.offline,
+            pipeline: config.pipeline.enabled,
+            labels: config.labels.clone(),
+            ..Default::default()
+        };
+        let state = control::ControlState::new(status, event_tap, last_window, drop_summary.clone());
+        #[cfg(target_os = "linux")]
+        if ebpf_manager.is_some() {
+            state.set_probe_commands(probe_commands_tx.clone());
         }
+        state
     });
+    #[cfg(unix)]
+    let control_handle = control_listener
+        .zip(control_state.clone())
+        .map(|(listener, state)| tokio::spawn(control::serve(listener, state)));
+    #[cfg(unix)]
+    let grpc_handle = grpc_listener
+        .zip(control_state.clone())
+        .map(|(listener, state)| tokio::spawn(grpc::serve(listener, state)));
+    #[cfg(unix)]
+    let api_handle = config
+        .api_listen
+        .clone()
+        .zip(control_state)
+        .map(|(addr, state)| tokio::spawn(api::serve(addr, config.api_token.clone(), state)));
+    #[cfg(not(unix))]
+    let (control_handle, grpc_handle, api_handle): (
+        Option<tokio::task::JoinHandle<()>>,
+        Option<tokio::task::JoinHandle<()>>,
+        Option<tokio::task::JoinHandle<()>>,
+    ) = (None, None, None);
+
+    // Attach to wanted interfaces that come up later
+    #[cfg(target_os = "linux")]
+    let mut hotplug_events = match &ebpf_manager {
+        Some(_) if config.all_interfaces || !config.interfaces.is_empty() => match hotplug::watch() {
+            Ok(events) => Some(events),
+            Err(e) => {
+                warn!("Interfaces that come up later will not be attached: {}", e);
+                None
+            }
+        },
+        _ => None,
+    };
+
+    // Probe switches from the control socket, handled by the owner of the manager
+    #[cfg(target_os = "linux")]
+    drop(probe_commands_tx);
 
     // Wait for shutdown signal
     info!("Agent running. Press Ctrl+C to stop.");
+    #[cfg(target_os = "linux")]
+    {
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+        // Without the pipeline nothing else reads DROP_EVENTS
+        let mut drain_drops = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = drain_drops.tick(), if !config.pipeline.enabled && ebpf_manager.is_some() => {
+                    if let Some(mgr) = ebpf_manager.as_mut() {
+                        match mgr.read_drop_summary() {
+                            Ok(summary) => drop_summary.add(&summary),
+                            Err(e) => tracing::debug!("Could not drain drop events: {:#}", e),
+                        }
+                    }
+                }
+                Some(event) = async { hotplug_events.as_mut()?.recv().await } => {
+                    if let Some(mgr) = ebpf_manager.as_mut() {
+                        hotplug::apply(mgr, event, &config.interfaces, config.all_interfaces);
+                    }
+                }
+                Some(command) = probe_commands.recv() => {
+                    if let Some(mgr) = ebpf_manager.as_mut() {
+                        command.run(mgr);
+                    }
+                }
+            }
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
     shutdown_signal().await;
 
     // Graceful shutdown
     warn!("Shutdown signal received, stopping...");
-    heartbeat_handle.abort();
+    conntrack_handle.abort();
+    prober_handle.abort();
+    selfmetrics_handle.abort();
+    #[cfg(unix)]
+    let control_bound = control_handle.is_some();
+    #[cfg(unix)]
+    let grpc_bound = grpc_handle.is_some();
+    for handle in [heartbeat_handle, rollup_handle, export_handle, http_handle, control_handle, grpc_handle, api_handle].into_iter().flatten() {
+        handle.abort();
+    }
+    #[cfg(unix)]
+    if let (true, Some(path)) = (control_bound, &config.control_socket) {
+        control::remove(path);
+    }
+    #[cfg(unix)]
+    if let (true, Some(path)) = (grpc_bound, &config.grpc_listen) {
+        control::remove(path);
+    }
+    for task in pipeline_tasks {
+        task.abort();
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(mgr) = ebpf_manager {
+        mgr.shutdown(config.keep_pinned_maps);
+    }
     
     info!("Agent stopped");
     Ok(())
 }
 
-fn init_tracing() {
-    let filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new("info"));
-
-    tracing_subscriber::registry()
-        .with(filter)
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-}
-
 fn print_help() {
     println!("{}", "Sennet Agent - Network Observability".bold());
     println!("High-performance network monitoring with eBPF");
@@ -229,23 +775,44 @@ fn print_help() {
     println!();
     println!("{}", "COMMANDS:".yellow());
     println!("    {}       Run the agent daemon", "(none)".cyan());
+    println!("    {} Run the daemon on every up, non-loopback interface", "--all-interfaces".cyan());
     println!("    {}        Initialize configuration interactively", "init".cyan());
     println!("    {}      Display agent status and connection info", "status".cyan());
     println!("    {}         Live traffic monitoring dashboard", "top".cyan());
     println!("    {}       One-shot packet tracing", "trace".cyan());
     println!("    {}       Active flows with PID attribution", "flows".cyan());
+    println!("    {} Busiest source/destination pairs", "top-talkers".cyan());
+    println!("    {}     TCP round-trip times per remote", "latency".cyan());
+    println!("    {}       Drop traffic to or from addresses and ports", "block".cyan());
+    println!("    {}      Attach or detach tracing probes at runtime", "probes".cyan());
+    println!("    {}      Save the agent's event stream to a file", "record".cyan());
+    println!("    {}      Replay a recording through trace, top or the pipeline", "replay".cyan());
+    println!("    {}      Export the last minutes of the black-box recording", "export".cyan());
+    println!("    {}      TCP reset cause analysis", "resets".cyan());
+    println!("    {}         DNS query rate, NXDOMAIN rate and slow resolvers", "dns".cyan());
+    println!("    {}        Sampled HTTP requests: methods, paths and servers", "http".cyan());
+    println!("    {}   Tracked connections, or new and destroyed entries live", "conntrack".cyan());
+    println!("    {}       Follow one pod, container or process", "watch".cyan());
     println!("    {}    K8s pod connectivity diagnosis", "diagnose".cyan());
+    println!("    {}      Check kernel, capabilities and security modules", "doctor".cyan());
     println!("    {}     Check for and install updates", "upgrade".cyan());
+    println!("    {}     Install the binary and service", "install".cyan());
+    println!("    {}   Remove the service, binary and data", "uninstall".cyan());
     println!("    {}     Print version information", "version".cyan());
     println!("    {}        Show this help message", "help".cyan());
     println!();
     println!("{}", "EXAMPLES:".yellow());
+    println!("    sudo sennet install      # Install and enable the service");
     println!("    sennet init              # Configure the agent");
     println!("    sudo sennet              # Run as daemon");
     println!("    sennet status            # Check agent status");
+    println!("    sennet status --verbose  # Include agent self-metrics");
+    println!("    sennet doctor            # Find out why eBPF fails to load");
     println!("    sennet top               # Monitor traffic live");
+    println!("    sennet top --remote node-3:9465   # Monitor another agent");
     println!("    sennet trace --dst 10.0.0.5  # Trace drops to IP");
     println!("    sennet flows --pid 1234  # Show flows for process");
+    println!("    sennet watch prod/api-7d9f8  # Follow a pod's networking");
     println!();
     println!("{}", "CONFIGURATION:".yellow());
     println!("    Config file: /etc/sennet/config.yaml");
@@ -289,26 +856,85 @@ fn print_diagnose_help() {
     println!();
     println!("{}", "USAGE:".yellow());
     println!("    sennet diagnose <SOURCE_POD> <TARGET_POD> [OPTIONS]");
+    println!("    sennet diagnose --port <PORT>[/udp] [--from <IP>] [--outbound]");
     println!();
     println!("{}", "OPTIONS:".yellow());
     println!("    -n, --namespace <NS>   Namespace (default: default)");
+    println!("    --port <PORT>[/PROTO]  Check this host's firewall ruleset for a new connection");
+    println!("    --from <IP>            Source address of the connection (with --port)");
+    println!("    --outbound             Check an outgoing connection to PORT instead");
+    println!("    --probe <PORT|icmp>    Connect from inside the source pod and watch for drops (root)");
     println!("    -h, --help             Show this help message");
     println!();
     println!("{}", "EXAMPLES:".yellow());
     println!("    sennet diagnose frontend backend");
     println!("    sennet diagnose frontend backend -n production");
     println!("    sennet diagnose web-abc123 api-def456 --namespace staging");
+    println!("    sennet diagnose frontend backend --probe 8080");
+    println!("    sennet diagnose --port 443       # Is this host's firewall blocking 443?");
     println!();
     println!("{}", "OUTPUT:".yellow());
     println!("    - Source and target pod details");
     println!("    - NetworkPolicies affecting each pod");
     println!("    - Connectivity status (ALLOWED / BLOCKED / UNKNOWN)");
+    println!("    - With --probe: the real outcome, drops it caused, and whether it confirms the status");
+    println!("    - nftables/iptables drop/reject rules dropping packets on this host");
     println!("    - Recommendations for troubleshooting");
     println!();
     println!("{}", "NOTES:".yellow());
     println!("    - Must be run from within a Kubernetes cluster");
     println!("    - Requires RBAC permissions to list pods and NetworkPolicies");
     println!("    - Works with standard K8s NetworkPolicy, Calico, and Cilium");
+    println!("    - --probe must run on the source pod's node; drops are read from the running agent");
+}
+
+/// How long diagnose watches firewall counters
+const FIREWALL_SAMPLE: Duration = Duration::from_secs(2);
+
+/// Report host firewall drop/reject rules whose counters move during a short sample
+async fn print_firewall_drops() {
+    let Ok(before) = firewall::CounterSnapshot::read() else {
+        return;
+    };
+    if before.is_empty() {
+        return;
+    }
+    tokio::time::sleep(FIREWALL_SAMPLE).await;
+    let Ok(after) = firewall::CounterSnapshot::read() else {
+        return;
+    };
+    
+    println!("{} ({}s sample)", "Host Firewall".bold(), FIREWALL_SAMPLE.as_secs());
+    let moved = after.dropped_since(&before);
+    if moved.is_empty() {
+        println!("  No firewall drop/reject rule matched packets");
+    }
+    for (rule, packets) in moved {
+        println!("  {} dropped {} packets", rule.to_string().red(), packets);
+    }
+}
+
+/// Walk this host's ruleset for `probe` and print what happens to it
+fn print_host_diagnosis(probe: &firewall::Probe) -> Result<()> {
+    let ruleset = firewall::Ruleset::read()
+        .map_err(|e| anyhow::anyhow!("Failed to read the firewall ruleset (needs root or CAP_NET_ADMIN): {}", e))?;
+    
+    println!("{}", "Host Firewall".bold());
+    if ruleset.is_empty() {
+        println!("  No firewall tables loaded: {} is not filtered", probe);
+        return Ok(());
+    }
+    let decision = ruleset.evaluate(probe);
+    let line = decision.describe(probe);
+    if decision.verdict.discards() {
+        println!("  {} {}", "✗".red(), line.red());
+    } else {
+        println!("  {} {}", "✓".green(), line);
+    }
+    for rule in &decision.uncertain {
+        println!("  {} {} may also drop it (has matches sennet can't evaluate)", "?".yellow(), rule);
+    }
+    Ok(())
 }
 
 async fn run_diagnose(args: &[String]) -> Result<()> {
@@ -316,6 +942,10 @@ async fn run_diagnose(args: &[String]) -> Result<()> {
     let mut source_pod: Option<String> = None;
     let mut target_pod: Option<String> = None;
     let mut namespace: Option<String> = None;
+    let mut host_port: Option<(u16, u8)> = None;
+    let mut from: Option<std::net::IpAddr> = None;
+    let mut outbound = false;
+    let mut probe_with: Option<String> = None;
     
     let mut i = 0;
     while i < args.len() {
@@ -330,6 +960,30 @@ async fn run_diagnose(args: &[String]) -> Result<()> {
                     std::process::exit(1);
                 }
             }
+            "--port" if i + 1 < args.len() => {
+                let (port, proto) = args[i + 1].split_once('/').unwrap_or((&args[i + 1], "tcp"));
+                let proto = match proto {
+                    "udp" => 17,
+                    _ => 6,
+                };
+                match port.parse() {
+                    Ok(port) => host_port = Some((port, proto)),
+                    Err(_) => {
+                        eprintln!("{} Invalid port: {}", "Error:".red(), args[i + 1]);
+                        std::process::exit(1);
+                    }
+                }
+                i += 1;
+            }
+            "--from" if i + 1 < args.len() => {
+                from = args[i + 1].parse().ok();
+                i += 1;
+            }
+            "--outbound" => outbound = true,
+            "--probe" if i + 1 < args.len() => {
+                probe_with = Some(args[i + 1].clone());
+                i += 1;
+            }
             _ if !arg.starts_with('-') => {
                 if source_pod.is_none() {
                     source_pod = Some(arg.clone());
@@ -345,6 +999,20 @@ async fn run_diagnose(args: &[String]) -> Result<()> {
         i += 1;
     }
     
+    // Host firewall check: no Kubernetes needed
+    if let Some((port, proto)) = host_port {
+        let mut probe = firewall::Probe::inbound(proto, port);
+        probe.src = from;
+        if outbound {
+            probe.hook = firewall::Hook::Output;
+        }
+        print_host_diagnosis(&probe)?;
+        if source_pod.is_none() {
+            return Ok(());
+        }
+        println!();
+    }
+    
     // Validate required arguments
     let source = match source_pod {
         Some(s) => s,
@@ -364,6 +1032,20 @@ async fn run_diagnose(args: &[String]) -> Result<()> {
         }
     };
     
+    #[cfg(unix)]
+    let probe_method = match probe_with.as_deref().map(probe::Method::parse).transpose() {
+        Ok(method) => method,
+        Err(e) => {
+            eprintln!("{} {}", "Error:".red(), e);
+            std::process::exit(1);
+        }
+    };
+    #[cfg(not(unix))]
+    if probe_with.is_some() {
+        eprintln!("{} --probe needs Linux network namespaces", "Error:".red());
+        std::process::exit(1);
+    }
+    
     info!("Diagnosing connectivity: {} -> {}", source, target);
     
     // Initialize K8s manager
@@ -397,6 +1079,18 @@ async fn run_diagnose(args: &[String]) -> Result<()> {
     match k8s_manager.diagnose_connectivity(&source, &target, namespace.as_deref()).await {
         Ok(result) => {
             println!("{}", result.format_output());
+            #[cfg(unix)]
+            if let Some(method) = probe_method {
+                match (&result.source_pod, &result.target_pod) {
+                    (Some(source), Some(target)) => {
+                        if let Err(e) = probe::run(source, target, method, &result.connectivity_status) {
+                            eprintln!("{} Probe failed: {:#}", "Error:".red(), e);
+                        }
+                    }
+                    _ => eprintln!("{} Both pods must exist to probe", "Error:".red()),
+                }
+            }
+            print_firewall_drops().await;
         }
         Err(e) => {
             eprintln!("{} Diagnosis failed: {}", "Error:".red(), e);
@@ -0,0 +1,733 @@
+//! nftables Ruleset Introspection
+//!
+//! Reads the live nftables ruleset over NETLINK_NETFILTER (tables, chains,
+//! rules, sets and set elements) into the `firewall` model. Rule expressions
+//! are decoded by tracking what each register holds, so the bytecode for
+//! `tcp dport 443` (payload load, cmp) becomes a `DstPort == 443` match.
+//!
+//! Only rules with a `counter` statement can be correlated with drops.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::firewall::{Chain, CmpOp, Counter, Field, Hook, Match, Rule, Ruleset, Set, SetElement, Verdict};
+
+/// nfnetlink subsystem for nf_tables
+const NFNL_SUBSYS_NFTABLES: u16 = 10;
+
+// Dump requests (replies are the matching NFT_MSG_NEW* messages)
+const NFT_MSG_GETTABLE: u16 = 1;
+const NFT_MSG_GETCHAIN: u16 = 4;
+const NFT_MSG_GETRULE: u16 = 7;
+const NFT_MSG_GETSET: u16 = 10;
+const NFT_MSG_GETSETELEM: u16 = 13;
+
+/// Netlink attribute type bits (NLA_F_NESTED, NLA_F_NET_BYTEORDER) to ignore
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+// Table, chain and set attributes
+const NFTA_TABLE_NAME: u16 = 1;
+const NFTA_CHAIN_TABLE: u16 = 1;
+const NFTA_CHAIN_NAME: u16 = 3;
+const NFTA_CHAIN_HOOK: u16 = 4;
+const NFTA_CHAIN_POLICY: u16 = 5;
+const NFTA_CHAIN_TYPE: u16 = 7;
+const NFTA_HOOK_HOOKNUM: u16 = 1;
+const NFTA_HOOK_PRIORITY: u16 = 2;
+const NFTA_SET_TABLE: u16 = 1;
+const NFTA_SET_NAME: u16 = 2;
+const NFTA_SET_FLAGS: u16 = 3;
+const NFTA_SET_ELEM_LIST_TABLE: u16 = 1;
+const NFTA_SET_ELEM_LIST_SET: u16 = 2;
+const NFTA_SET_ELEM_LIST_ELEMENTS: u16 = 3;
+const NFTA_SET_ELEM_KEY: u16 = 1;
+const NFTA_SET_ELEM_FLAGS: u16 = 3;
+const NFT_SET_INTERVAL: u32 = 0x4;
+const NFT_SET_ELEM_INTERVAL_END: u32 = 0x1;
+
+// Rule attributes
+const NFTA_RULE_TABLE: u16 = 1;
+const NFTA_RULE_CHAIN: u16 = 2;
+const NFTA_RULE_HANDLE: u16 = 3;
+const NFTA_RULE_EXPRESSIONS: u16 = 4;
+const NFTA_LIST_ELEM: u16 = 1;
+const NFTA_EXPR_NAME: u16 = 1;
+const NFTA_EXPR_DATA: u16 = 2;
+const NFTA_DATA_VALUE: u16 = 1;
+const NFTA_DATA_VERDICT: u16 = 2;
+const NFTA_VERDICT_CODE: u16 = 1;
+const NFTA_VERDICT_CHAIN: u16 = 2;
+
+// Expression attributes; the source/destination register is attribute 1 or
+// 2 depending on the expression
+const NFTA_COUNTER_BYTES: u16 = 1;
+const NFTA_COUNTER_PACKETS: u16 = 2;
+const NFTA_IMMEDIATE_DREG: u16 = 1;
+const NFTA_IMMEDIATE_DATA: u16 = 2;
+const NFTA_META_DREG: u16 = 1;
+const NFTA_META_KEY: u16 = 2;
+const NFTA_PAYLOAD_DREG: u16 = 1;
+const NFTA_PAYLOAD_BASE: u16 = 2;
+const NFTA_PAYLOAD_OFFSET: u16 = 3;
+const NFTA_PAYLOAD_LEN: u16 = 4;
+const NFTA_CT_DREG: u16 = 1;
+const NFTA_CT_KEY: u16 = 2;
+const NFTA_BITWISE_SREG: u16 = 1;
+const NFTA_BITWISE_DREG: u16 = 2;
+const NFTA_BITWISE_MASK: u16 = 4;
+const NFTA_CMP_SREG: u16 = 1;
+const NFTA_CMP_OP: u16 = 2;
+const NFTA_CMP_DATA: u16 = 3;
+const NFTA_RANGE_SREG: u16 = 1;
+const NFTA_RANGE_OP: u16 = 2;
+const NFTA_RANGE_FROM_DATA: u16 = 3;
+const NFTA_RANGE_TO_DATA: u16 = 4;
+const NFTA_LOOKUP_SET: u16 = 1;
+const NFTA_LOOKUP_SREG: u16 = 2;
+const NFTA_LOOKUP_DREG: u16 = 3;
+const NFTA_LOOKUP_FLAGS: u16 = 5;
+const NFT_LOOKUP_F_INV: u32 = 0x1;
+// x_tables compat expressions, used by iptables-nft for extensions
+const NFTA_MATCH_NAME: u16 = 1;
+const NFTA_TARGET_NAME: u16 = 1;
+
+// meta keys (enum nft_meta_keys) and payload bases
+const NFT_META_IIFNAME: u32 = 6;
+const NFT_META_OIFNAME: u32 = 7;
+const NFT_META_NFPROTO: u32 = 15;
+const NFT_META_L4PROTO: u32 = 16;
+const NFT_CT_STATE: u32 = 0;
+const NFT_PAYLOAD_NETWORK_HEADER: u32 = 1;
+const NFT_PAYLOAD_TRANSPORT_HEADER: u32 = 2;
+
+/// Verdict codes: NF_DROP, NF_ACCEPT and the nf_tables chain verdicts
+const NF_DROP: i32 = 0;
+const NF_ACCEPT: i32 = 1;
+const NFT_CONTINUE: i32 = -1;
+const NFT_BREAK: i32 = -2;
+const NFT_JUMP: i32 = -3;
+const NFT_GOTO: i32 = -4;
+const NFT_RETURN: i32 = -5;
+
+/// Expressions that act on the packet without affecting whether a rule matches
+const STATEMENTS: &[&str] = &["counter", "log", "notrack", "nat", "masq", "redir", "dup", "fwd", "objref"];
+
+/// Iterates the netlink attributes in a buffer as (type, payload)
+pub(crate) struct Attrs<'a>(&'a [u8]);
+
+impl<'a> Attrs<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self(buf)
+    }
+
+    /// Payload of the first attribute of `kind`
+    pub(crate) fn get(buf: &'a [u8], kind: u16) -> Option<&'a [u8]> {
+        Attrs::new(buf).find(|&(k, _)| k == kind).map(|(_, data)| data)
+    }
+}
+
+impl<'a> Iterator for Attrs<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.len() < 4 {
+            return None;
+        }
+        let len = u16::from_ne_bytes([self.0[0], self.0[1]]) as usize;
+        let kind = u16::from_ne_bytes([self.0[2], self.0[3]]) & NLA_TYPE_MASK;
+        if len < 4 || len > self.0.len() {
+            return None;
+        }
+        let payload = &self.0[4..len];
+        self.0 = &self.0[align4(len).min(self.0.len())..];
+        Some((kind, payload))
+    }
+}
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Big-endian u64 attribute
+pub(crate) fn attr_u64(data: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(..8)?.try_into().ok()?))
+}
+
+/// Big-endian u32 attribute
+pub(crate) fn attr_u32(data: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(..4)?.try_into().ok()?))
+}
+
+/// NUL-terminated string attribute
+pub(crate) fn attr_str(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// Value of a nested NFTA_DATA_VALUE
+fn data_value(data: &[u8]) -> Option<Vec<u8>> {
+    Attrs::get(data, NFTA_DATA_VALUE).map(<[u8]>::to_vec)
+}
+
+/// Verdict in a nested NFTA_DATA_VERDICT (immediate data, chain policy)
+fn data_verdict(data: &[u8]) -> Option<Verdict> {
+    let verdict = Attrs::get(data, NFTA_DATA_VERDICT)?;
+    let chain = || Attrs::get(verdict, NFTA_VERDICT_CHAIN).map(attr_str);
+    Some(match attr_u32(Attrs::get(verdict, NFTA_VERDICT_CODE)?)? as i32 {
+        NF_DROP => Verdict::Drop,
+        NF_ACCEPT => Verdict::Accept,
+        NFT_CONTINUE | NFT_BREAK => Verdict::Continue,
+        NFT_JUMP => Verdict::Jump(chain()?),
+        NFT_GOTO => Verdict::Goto(chain()?),
+        NFT_RETURN => Verdict::Return,
+        _ => return None,
+    })
+}
+
+/// What a register holds while decoding a rule
+#[derive(Debug, Clone)]
+enum Reg {
+    Field(Field, Option<Vec<u8>>),
+    Unknown(String),
+}
+
+/// Packet field loaded by a payload expression
+fn payload_field(base: u32, offset: u32, len: u32, ipv6: bool) -> Option<Field> {
+    match (base, offset, len, ipv6) {
+        (NFT_PAYLOAD_NETWORK_HEADER, 9, 1, false) => Some(Field::L4Proto),
+        (NFT_PAYLOAD_NETWORK_HEADER, 12, 4, false) => Some(Field::SrcAddr),
+        (NFT_PAYLOAD_NETWORK_HEADER, 16, 4, false) => Some(Field::DstAddr),
+        (NFT_PAYLOAD_NETWORK_HEADER, 6, 1, true) => Some(Field::L4Proto),
+        (NFT_PAYLOAD_NETWORK_HEADER, 8, 16, true) => Some(Field::SrcAddr),
+        (NFT_PAYLOAD_NETWORK_HEADER, 24, 16, true) => Some(Field::DstAddr),
+        (NFT_PAYLOAD_TRANSPORT_HEADER, 0, 2, _) => Some(Field::SrcPort),
+        (NFT_PAYLOAD_TRANSPORT_HEADER, 2, 2, _) => Some(Field::DstPort),
+        _ => None,
+    }
+}
+
+fn cmp_op(op: u32) -> Option<CmpOp> {
+    Some(match op {
+        0 => CmpOp::Eq,
+        1 => CmpOp::Neq,
+        2 => CmpOp::Lt,
+        3 => CmpOp::Lte,
+        4 => CmpOp::Gt,
+        5 => CmpOp::Gte,
+        _ => return None,
+    })
+}
+
+/// Decode an NFT_MSG_NEWRULE payload into (table, chain, rule)
+pub fn parse_rule(family: u8, payload: &[u8]) -> Option<(String, String, Rule)> {
+    let table = attr_str(Attrs::get(payload, NFTA_RULE_TABLE)?);
+    let chain = attr_str(Attrs::get(payload, NFTA_RULE_CHAIN)?);
+    let mut rule = Rule { handle: attr_u64(Attrs::get(payload, NFTA_RULE_HANDLE)?)?, ..Default::default() };
+
+    let mut regs: HashMap<u32, Reg> = HashMap::new();
+    let mut ipv6 = family == crate::firewall::family::IPV6;
+    let exprs = Attrs::get(payload, NFTA_RULE_EXPRESSIONS).unwrap_or_default();
+    for (_, expr) in Attrs::new(exprs).filter(|&(kind, _)| kind == NFTA_LIST_ELEM) {
+        let name = Attrs::get(expr, NFTA_EXPR_NAME).map(attr_str).unwrap_or_default();
+        let data = Attrs::get(expr, NFTA_EXPR_DATA).unwrap_or_default();
+        let u32_attr = |kind| Attrs::get(data, kind).and_then(attr_u32);
+        let reg = |kind| u32_attr(kind).and_then(|r| regs.get(&r).cloned());
+
+        match name.as_str() {
+            "meta" => {
+                let Some(dreg) = u32_attr(NFTA_META_DREG) else { continue };
+                let key = u32_attr(NFTA_META_KEY).unwrap_or(u32::MAX);
+                let loaded = match key {
+                    NFT_META_IIFNAME => Reg::Field(Field::InIface, None),
+                    NFT_META_OIFNAME => Reg::Field(Field::OutIface, None),
+                    NFT_META_NFPROTO => Reg::Field(Field::NfProto, None),
+                    NFT_META_L4PROTO => Reg::Field(Field::L4Proto, None),
+                    _ => Reg::Unknown(format!("meta key {}", key)),
+                };
+                regs.insert(dreg, loaded);
+            }
+            "payload" => {
+                let Some(dreg) = u32_attr(NFTA_PAYLOAD_DREG) else { continue };
+                let (base, offset, len) = (
+                    u32_attr(NFTA_PAYLOAD_BASE).unwrap_or(0),
+                    u32_attr(NFTA_PAYLOAD_OFFSET).unwrap_or(0),
+                    u32_attr(NFTA_PAYLOAD_LEN).unwrap_or(0),
+                );
+                let loaded = match payload_field(base, offset, len, ipv6) {
+                    Some(field) => Reg::Field(field, None),
+                    None => Reg::Unknown(format!("payload base {} offset {} len {}", base, offset, len)),
+                };
+                regs.insert(dreg, loaded);
+            }
+            "ct" => {
+                let Some(dreg) = u32_attr(NFTA_CT_DREG) else { continue };
+                let loaded = match u32_attr(NFTA_CT_KEY) {
+                    Some(NFT_CT_STATE) => Reg::Field(Field::CtState, None),
+                    key => Reg::Unknown(format!("ct key {}", key.unwrap_or(u32::MAX))),
+                };
+                regs.insert(dreg, loaded);
+            }
+            "bitwise" => {
+                let (Some(src), Some(dreg)) = (reg(NFTA_BITWISE_SREG), u32_attr(NFTA_BITWISE_DREG)) else { continue };
+                let mask = Attrs::get(data, NFTA_BITWISE_MASK).and_then(data_value);
+                let masked = match src {
+                    Reg::Field(field, None) => Reg::Field(field, mask),
+                    _ => Reg::Unknown("bitwise".into()),
+                };
+                regs.insert(dreg, masked);
+            }
+            "cmp" => {
+                let value = Attrs::get(data, NFTA_CMP_DATA).and_then(data_value).unwrap_or_default();
+                let op = u32_attr(NFTA_CMP_OP).and_then(cmp_op);
+                rule.matches.push(match (reg(NFTA_CMP_SREG), op) {
+                    (Some(Reg::Field(field, mask)), Some(op)) => {
+                        // Later payload offsets depend on the address family
+                        if field == Field::NfProto && op == CmpOp::Eq {
+                            ipv6 = value.first() == Some(&crate::firewall::family::IPV6);
+                        }
+                        Match::Cmp { field, op, value, mask }
+                    }
+                    (Some(Reg::Unknown(what)), _) => Match::Other(what),
+                    _ => Match::Other("cmp".into()),
+                });
+            }
+            "range" => {
+                let from = Attrs::get(data, NFTA_RANGE_FROM_DATA).and_then(data_value).unwrap_or_default();
+                let to = Attrs::get(data, NFTA_RANGE_TO_DATA).and_then(data_value).unwrap_or_default();
+                let negate = u32_attr(NFTA_RANGE_OP) == Some(1);
+                rule.matches.push(match reg(NFTA_RANGE_SREG) {
+                    Some(Reg::Field(field, None)) => Match::Range { field, negate, from, to },
+                    _ => Match::Other("range".into()),
+                });
+            }
+            "lookup" => {
+                let set = Attrs::get(data, NFTA_LOOKUP_SET).map(attr_str).unwrap_or_default();
+                let negate = u32_attr(NFTA_LOOKUP_FLAGS).is_some_and(|f| f & NFT_LOOKUP_F_INV != 0);
+                // Map lookups (with a destination register) aren't modelled
+                let is_map = u32_attr(NFTA_LOOKUP_DREG).is_some();
+                rule.matches.push(match reg(NFTA_LOOKUP_SREG) {
+                    Some(Reg::Field(field, None)) if !is_map => Match::InSet { field, set, negate },
+                    _ => Match::Other(format!("lookup @{}", set)),
+                });
+            }
+            "immediate" => {
+                // Only verdicts; data loads into registers feed unmodelled statements
+                if u32_attr(NFTA_IMMEDIATE_DREG) == Some(0) {
+                    if let Some(verdict) = Attrs::get(data, NFTA_IMMEDIATE_DATA).and_then(data_verdict) {
+                        rule.verdict = Some(verdict);
+                    }
+                }
+            }
+            "reject" => rule.verdict = Some(Verdict::Reject),
+            "target" => {
+                // LOG, MARK, ... are statements
+                if Attrs::get(data, NFTA_TARGET_NAME).map(attr_str).as_deref() == Some("REJECT") {
+                    rule.verdict = Some(Verdict::Reject);
+                }
+            }
+            "match" => {
+                let name = Attrs::get(data, NFTA_MATCH_NAME).map(attr_str).unwrap_or_default();
+                if name != "comment" {
+                    rule.matches.push(Match::Other(format!("xt match {}", name)));
+                }
+            }
+            "counter" => {
+                rule.counter = Some(Counter {
+                    packets: Attrs::get(data, NFTA_COUNTER_PACKETS).and_then(attr_u64).unwrap_or(0),
+                    bytes: Attrs::get(data, NFTA_COUNTER_BYTES).and_then(attr_u64).unwrap_or(0),
+                });
+            }
+            name if STATEMENTS.contains(&name) => {}
+            // limit, quota, fib, socket, ...
+            name => rule.matches.push(Match::Other(name.to_string())),
+        }
+    }
+
+    Some((table, chain, rule))
+}
+
+/// Decode an NFT_MSG_NEWCHAIN payload into (table, chain)
+pub fn parse_chain(payload: &[u8]) -> Option<(String, Chain)> {
+    let table = attr_str(Attrs::get(payload, NFTA_CHAIN_TABLE)?);
+    let hook = Attrs::get(payload, NFTA_CHAIN_HOOK);
+    let chain = Chain {
+        name: attr_str(Attrs::get(payload, NFTA_CHAIN_NAME)?),
+        hook: hook
+            .and_then(|h| Attrs::get(h, NFTA_HOOK_HOOKNUM))
+            .and_then(attr_u32)
+            .and_then(Hook::from_num),
+        priority: hook
+            .and_then(|h| Attrs::get(h, NFTA_HOOK_PRIORITY))
+            .and_then(attr_u32)
+            .map_or(0, |p| p as i32),
+        kind: Attrs::get(payload, NFTA_CHAIN_TYPE).map(attr_str).unwrap_or_else(|| "filter".into()),
+        policy: match Attrs::get(payload, NFTA_CHAIN_POLICY).and_then(attr_u32) {
+            Some(p) if p as i32 == NF_DROP => Verdict::Drop,
+            _ => Verdict::Accept,
+        },
+        rules: Vec::new(),
+    };
+    Some((table, chain))
+}
+
+/// Decode an NFT_MSG_NEWSET payload into (table, set without elements)
+pub fn parse_set(payload: &[u8]) -> Option<(String, Set)> {
+    let table = attr_str(Attrs::get(payload, NFTA_SET_TABLE)?);
+    let flags = Attrs::get(payload, NFTA_SET_FLAGS).and_then(attr_u32).unwrap_or(0);
+    let set = Set {
+        name: attr_str(Attrs::get(payload, NFTA_SET_NAME)?),
+        interval: flags & NFT_SET_INTERVAL != 0,
+        elements: Vec::new(),
+    };
+    Some((table, set))
+}
+
+/// Decode an NFT_MSG_NEWSETELEM payload
+pub fn parse_set_elements(payload: &[u8]) -> Vec<SetElement> {
+    let Some(elements) = Attrs::get(payload, NFTA_SET_ELEM_LIST_ELEMENTS) else {
+        return Vec::new();
+    };
+    Attrs::new(elements)
+        .filter(|&(kind, _)| kind == NFTA_LIST_ELEM)
+        .filter_map(|(_, elem)| {
+            let flags = Attrs::get(elem, NFTA_SET_ELEM_FLAGS).and_then(attr_u32).unwrap_or(0);
+            Some(SetElement {
+                key: Attrs::get(elem, NFTA_SET_ELEM_KEY).and_then(data_value)?,
+                interval_end: flags & NFT_SET_ELEM_INTERVAL_END != 0,
+            })
+        })
+        .collect()
+}
+
+/// (message type, body) pairs of a netlink receive buffer
+pub(crate) type Messages<'a> = Vec<(u16, &'a [u8])>;
+
+/// Split a netlink receive buffer into (message type, body)
+///
+/// Returns the messages and whether the dump is complete (NLMSG_DONE seen).
+/// A netlink error reply is returned as an error.
+pub(crate) fn split_messages(buf: &[u8]) -> Result<(Messages<'_>, bool)> {
+    const NLMSG_HDRLEN: usize = 16;
+    const NLMSG_ERROR: u16 = 2;
+    const NLMSG_DONE: u16 = 3;
+
+    let mut messages = Vec::new();
+    let mut rest = buf;
+    while rest.len() >= NLMSG_HDRLEN {
+        let len = u32::from_ne_bytes(rest[0..4].try_into()?) as usize;
+        let kind = u16::from_ne_bytes(rest[4..6].try_into()?);
+        if len < NLMSG_HDRLEN || len > rest.len() {
+            anyhow::bail!("Truncated netlink message ({} bytes, {} left)", len, rest.len());
+        }
+        let body = &rest[NLMSG_HDRLEN..len];
+        match kind {
+            NLMSG_DONE => return Ok((messages, true)),
+            NLMSG_ERROR => {
+                let errno = body.get(..4).map(|b| i32::from_ne_bytes(b.try_into().unwrap())).unwrap_or(0);
+                if errno != 0 {
+                    return Err(std::io::Error::from_raw_os_error(-errno).into());
+                }
+            }
+            _ => messages.push((kind, body)),
+        }
+        rest = &rest[align4(len).min(rest.len())..];
+    }
+    Ok((messages, false))
+}
+
+/// Encode a NUL-terminated string attribute for a request
+fn str_attr(kind: u16, value: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&((value.len() + 5) as u16).to_ne_bytes());
+    out.extend_from_slice(&kind.to_ne_bytes());
+    out.extend_from_slice(value.as_bytes());
+    out.push(0);
+    out.resize(align4(out.len()), 0);
+    out
+}
+
+/// Dump one nf_tables object type; returns (family, attributes) per object
+///
+/// `family` and `attrs` narrow the request (set elements need both).
+#[cfg(target_os = "linux")]
+pub(crate) fn dump(msg_type: u16, family: u8, attrs: &[u8]) -> Result<Vec<(u8, Vec<u8>)>> {
+    use anyhow::Context;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    const NETLINK_NETFILTER: libc::c_int = 12;
+    const NLM_F_REQUEST: u16 = 0x1;
+    const NLM_F_DUMP: u16 = 0x300;
+
+    let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, NETLINK_NETFILTER) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to open netfilter netlink socket");
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // Don't hang on a kernel that never answers
+    let timeout = libc::timeval { tv_sec: 2, tv_usec: 0 };
+    unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            (&timeout as *const libc::timeval).cast(),
+            std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+        )
+    };
+
+    // nlmsghdr, nfgenmsg, then request attributes
+    let len = 20 + attrs.len();
+    let mut req = Vec::with_capacity(len);
+    req.extend_from_slice(&(len as u32).to_ne_bytes());
+    req.extend_from_slice(&((NFNL_SUBSYS_NFTABLES << 8) | msg_type).to_ne_bytes());
+    req.extend_from_slice(&(NLM_F_REQUEST | NLM_F_DUMP).to_ne_bytes());
+    req.extend_from_slice(&1u32.to_ne_bytes());
+    req.extend_from_slice(&0u32.to_ne_bytes());
+    req.extend_from_slice(&[family, 0, 0, 0]);
+    req.extend_from_slice(attrs);
+
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    let sent = unsafe {
+        libc::sendto(
+            fd.as_raw_fd(),
+            req.as_ptr().cast(),
+            req.len(),
+            0,
+            (&addr as *const libc::sockaddr_nl).cast(),
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to send nf_tables dump request");
+    }
+
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut objects = Vec::new();
+    loop {
+        let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to read nf_tables dump");
+        }
+        let (messages, done) = split_messages(&buf[..n as usize]).context("nf_tables dump failed")?;
+        for (_, body) in messages {
+            // nfgenmsg: family, version, res_id
+            if body.len() >= 4 {
+                objects.push((body[0], body[4..].to_vec()));
+            }
+        }
+        if done || n == 0 {
+            return Ok(objects);
+        }
+    }
+}
+
+/// Read the live ruleset (needs CAP_NET_ADMIN)
+#[cfg(target_os = "linux")]
+pub fn read_ruleset() -> Result<Ruleset> {
+    const UNSPEC: u8 = libc::AF_UNSPEC as u8;
+
+    let mut ruleset = Ruleset::default();
+    for (family, attrs) in dump(NFT_MSG_GETTABLE, UNSPEC, &[])? {
+        if let Some(name) = Attrs::get(&attrs, NFTA_TABLE_NAME).map(attr_str) {
+            ruleset.tables.push(crate::firewall::Table { family, name, chains: Vec::new(), sets: Vec::new() });
+        }
+    }
+
+    let table = |ruleset: &Ruleset, family: u8, name: &str| -> Option<usize> {
+        ruleset.tables.iter().position(|t| t.family == family && t.name == name)
+    };
+
+    for (family, attrs) in dump(NFT_MSG_GETCHAIN, UNSPEC, &[])? {
+        let Some((name, chain)) = parse_chain(&attrs) else { continue };
+        if let Some(i) = table(&ruleset, family, &name) {
+            ruleset.tables[i].chains.push(chain);
+        }
+    }
+
+    for (family, attrs) in dump(NFT_MSG_GETSET, UNSPEC, &[])? {
+        let Some((name, mut set)) = parse_set(&attrs) else { continue };
+        let Some(i) = table(&ruleset, family, &name) else { continue };
+        let mut request = str_attr(NFTA_SET_ELEM_LIST_TABLE, &name);
+        request.extend(str_attr(NFTA_SET_ELEM_LIST_SET, &set.name));
+        // Sets can be large; a failure only leaves this one empty
+        if let Ok(replies) = dump(NFT_MSG_GETSETELEM, family, &request) {
+            set.elements = replies.iter().flat_map(|(_, attrs)| parse_set_elements(attrs)).collect();
+        }
+        ruleset.tables[i].sets.push(set);
+    }
+
+    // Rules arrive in chain order
+    for (family, attrs) in dump(NFT_MSG_GETRULE, UNSPEC, &[])? {
+        let Some((table_name, chain_name, rule)) = parse_rule(family, &attrs) else { continue };
+        let Some(i) = table(&ruleset, family, &table_name) else { continue };
+        if let Some(chain) = ruleset.tables[i].chains.iter_mut().find(|c| c.name == chain_name) {
+            chain.rules.push(rule);
+        }
+    }
+
+    Ok(ruleset)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_ruleset() -> Result<Ruleset> {
+    anyhow::bail!("nftables is only available on Linux")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a netlink attribute (payload padded to 4 bytes)
+    fn attr(kind: u16, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&((payload.len() + 4) as u16).to_ne_bytes());
+        out.extend_from_slice(&kind.to_ne_bytes());
+        out.extend_from_slice(payload);
+        out.resize(align4(out.len()), 0);
+        out
+    }
+
+    fn u32_attr(kind: u16, value: u32) -> Vec<u8> {
+        attr(kind, &value.to_be_bytes())
+    }
+
+    fn expr(name: &str, attrs: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = str_attr(NFTA_EXPR_NAME, name);
+        body.extend(attr(NFTA_EXPR_DATA, &attrs.concat()));
+        attr(NFTA_LIST_ELEM, &body)
+    }
+
+    fn data(kind: u16, value: &[u8]) -> Vec<u8> {
+        attr(kind, &attr(NFTA_DATA_VALUE, value))
+    }
+
+    fn verdict(code: i32) -> Vec<u8> {
+        let verdict = attr(NFTA_DATA_VERDICT, &u32_attr(NFTA_VERDICT_CODE, code as u32));
+        expr("immediate", &[u32_attr(NFTA_IMMEDIATE_DREG, 0), attr(NFTA_IMMEDIATE_DATA, &verdict)])
+    }
+
+    fn rule(handle: u64, exprs: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = str_attr(NFTA_RULE_TABLE, "filter");
+        out.extend(str_attr(NFTA_RULE_CHAIN, "input"));
+        out.extend(attr(NFTA_RULE_HANDLE, &handle.to_be_bytes()));
+        out.extend(attr(NFTA_RULE_EXPRESSIONS, &exprs.concat()));
+        out
+    }
+
+    /// `tcp dport 443 counter reject`
+    fn tcp_dport_reject(port: u16) -> Vec<u8> {
+        rule(
+            12,
+            &[
+                expr("meta", &[u32_attr(NFTA_META_KEY, NFT_META_L4PROTO), u32_attr(NFTA_META_DREG, 1)]),
+                expr("cmp", &[u32_attr(NFTA_CMP_SREG, 1), u32_attr(NFTA_CMP_OP, 0), data(NFTA_CMP_DATA, &[6])]),
+                expr(
+                    "payload",
+                    &[
+                        u32_attr(NFTA_PAYLOAD_DREG, 1),
+                        u32_attr(NFTA_PAYLOAD_BASE, NFT_PAYLOAD_TRANSPORT_HEADER),
+                        u32_attr(NFTA_PAYLOAD_OFFSET, 2),
+                        u32_attr(NFTA_PAYLOAD_LEN, 2),
+                    ],
+                ),
+                expr("cmp", &[u32_attr(NFTA_CMP_SREG, 1), u32_attr(NFTA_CMP_OP, 0), data(NFTA_CMP_DATA, &port.to_be_bytes())]),
+                expr("counter", &[attr(NFTA_COUNTER_BYTES, &600u64.to_be_bytes()), attr(NFTA_COUNTER_PACKETS, &10u64.to_be_bytes())]),
+                expr("reject", &[]),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_parse_rule_decodes_matches() {
+        let (table, chain, parsed) = parse_rule(1, &tcp_dport_reject(443)).unwrap();
+        assert_eq!((table.as_str(), chain.as_str(), parsed.handle), ("filter", "input", 12));
+        assert_eq!(parsed.verdict, Some(Verdict::Reject));
+        assert_eq!(parsed.counter, Some(Counter { packets: 10, bytes: 600 }));
+        assert_eq!(
+            parsed.matches,
+            vec![
+                Match::Cmp { field: Field::L4Proto, op: CmpOp::Eq, value: vec![6], mask: None },
+                Match::Cmp { field: Field::DstPort, op: CmpOp::Eq, value: vec![1, 187], mask: None },
+            ]
+        );
+
+        // Missing handle
+        assert!(parse_rule(1, &str_attr(NFTA_RULE_TABLE, "filter")).is_none());
+    }
+
+    #[test]
+    fn test_parse_rule_verdicts_and_unknown_matches() {
+        let (_, _, accept) = parse_rule(2, &rule(3, &[verdict(NF_ACCEPT)])).unwrap();
+        assert_eq!(accept.verdict, Some(Verdict::Accept));
+
+        let jump = attr(
+            NFTA_DATA_VERDICT,
+            &[u32_attr(NFTA_VERDICT_CODE, NFT_JUMP as u32), str_attr(NFTA_VERDICT_CHAIN, "tcp_in")].concat(),
+        );
+        let (_, _, jumped) = parse_rule(
+            2,
+            &rule(4, &[expr("immediate", &[u32_attr(NFTA_IMMEDIATE_DREG, 0), attr(NFTA_IMMEDIATE_DATA, &jump)])]),
+        )
+        .unwrap();
+        assert_eq!(jumped.verdict, Some(Verdict::Jump("tcp_in".into())));
+
+        // `limit rate 10/second drop`: the limit can't be evaluated
+        let (_, _, limited) = parse_rule(2, &rule(5, &[expr("limit", &[]), expr("log", &[]), verdict(NF_DROP)])).unwrap();
+        assert_eq!(limited.matches, vec![Match::Other("limit".into())]);
+        assert_eq!(limited.verdict, Some(Verdict::Drop));
+    }
+
+    #[test]
+    fn test_parse_chain_and_set() {
+        let hook = attr(NFTA_CHAIN_HOOK, &[u32_attr(NFTA_HOOK_HOOKNUM, 1), u32_attr(NFTA_HOOK_PRIORITY, (-10i32) as u32)].concat());
+        let payload = [
+            str_attr(NFTA_CHAIN_TABLE, "filter"),
+            str_attr(NFTA_CHAIN_NAME, "input"),
+            hook,
+            u32_attr(NFTA_CHAIN_POLICY, 0),
+            str_attr(NFTA_CHAIN_TYPE, "filter"),
+        ]
+        .concat();
+        let (table, chain) = parse_chain(&payload).unwrap();
+        assert_eq!(table, "filter");
+        assert_eq!(chain.hook, Some(Hook::Input));
+        assert_eq!(chain.priority, -10);
+        assert_eq!(chain.policy, Verdict::Drop);
+
+        let set = [str_attr(NFTA_SET_TABLE, "filter"), str_attr(NFTA_SET_NAME, "web"), u32_attr(NFTA_SET_FLAGS, NFT_SET_INTERVAL)].concat();
+        let (_, set) = parse_set(&set).unwrap();
+        assert!(set.interval);
+
+        let elem = |key: u16, flags: u32| attr(NFTA_LIST_ELEM, &[data(NFTA_SET_ELEM_KEY, &key.to_be_bytes()), u32_attr(NFTA_SET_ELEM_FLAGS, flags)].concat());
+        let list = attr(NFTA_SET_ELEM_LIST_ELEMENTS, &[elem(80, 0), elem(90, NFT_SET_ELEM_INTERVAL_END)].concat());
+        let elements = parse_set_elements(&list);
+        assert_eq!(elements.len(), 2);
+        assert!(elements[1].interval_end);
+    }
+
+    #[test]
+    fn test_split_messages() {
+        let mut buf = Vec::new();
+        for (kind, body) in [(0x0a06u16, vec![1u8, 0, 0, 0, 9, 9, 9, 9]), (3, vec![0; 4])] {
+            buf.extend_from_slice(&((16 + body.len()) as u32).to_ne_bytes());
+            buf.extend_from_slice(&kind.to_ne_bytes());
+            buf.extend_from_slice(&[0; 10]);
+            buf.extend_from_slice(&body);
+        }
+        let (messages, done) = split_messages(&buf).unwrap();
+        assert!(done);
+        assert_eq!(messages, vec![(0x0a06, &[1u8, 0, 0, 0, 9, 9, 9, 9][..])]);
+
+        // EPERM reply
+        let mut err = Vec::new();
+        err.extend_from_slice(&20u32.to_ne_bytes());
+        err.extend_from_slice(&2u16.to_ne_bytes());
+        err.extend_from_slice(&[0; 10]);
+        err.extend_from_slice(&(-1i32).to_ne_bytes());
+        assert!(split_messages(&err).is_err());
+    }
+}
@@ -0,0 +1,1559 @@
+//! Daemon Event Pipeline
+//!
+//! Kernel events flow through tokio tasks connected by bounded channels:
+//!
+//! ```text
+//! reader ──▶ enrich ──▶ aggregate ──▶ sink (one channel per sink)
+//! ```
+//!
+//! Every hand-off uses `try_send`: when a downstream stage is full the event
+//! is dropped and counted instead of blocking. A slow sink therefore loses
+//! summaries rather than growing memory or stalling ring buffer draining.
+//!
+//! The aggregate stage also copies events to a broadcast tap while anyone is
+//! subscribed ([`PipelineHandle::tap`]); the control socket streams
+//! from it. Subscribers that fall behind lose the oldest events.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+use crate::coalesce::{CoalescedDrop, DropCoalescer};
+use crate::ebpf::{drop_reason_str, DropFilter};
+use crate::enrich::{DeepEnricher, EnrichGate, NotableEvent, Severity};
+use crate::filter::Filter;
+use crate::plugins::PluginSpec;
+use crate::ratelimit::ReasonLimiter;
+pub use crate::events::{RawEvent, RingKind};
+
+/// Pipeline configuration (`pipeline:` section of config.yaml)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineConfig {
+    /// Consume kernel ring buffers in the daemon
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Capacity of the reader → enrich channel (events)
+    #[serde(default = "default_reader_capacity")]
+    pub reader_capacity: usize,
+
+    /// Capacity of the enrich → aggregate channel (events)
+    #[serde(default = "default_enrich_capacity")]
+    pub enrich_capacity: usize,
+
+    /// Capacity of each aggregate → sink channel (summaries)
+    #[serde(default = "default_sink_capacity")]
+    pub sink_capacity: usize,
+
+    /// How often aggregated summaries are flushed to sinks
+    #[serde(default = "default_flush_interval")]
+    pub flush_interval_secs: u64,
+
+    /// Records read from a ring buffer before handing them on as one batch
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+
+    /// Upper bound on records read from one ring buffer per wakeup, so a
+    /// busy ring can't starve the others
+    #[serde(default = "default_max_events_per_tick")]
+    pub max_events_per_tick: usize,
+
+    /// Read each ring buffer on its own CPU-pinned thread with a dedicated
+    /// queue and enrich task, instead of one shared reader
+    #[serde(default)]
+    pub pinned_readers: bool,
+
+    /// CPUs to pin reader threads to, assigned round-robin per ring buffer
+    /// (empty = ring N on CPU N)
+    #[serde(default)]
+    pub reader_cpus: Vec<usize>,
+
+    /// Merge identical drops seen within this window (0 = disabled)
+    #[serde(default = "default_coalesce_window")]
+    pub coalesce_window_ms: u64,
+
+    /// Only events at or above this severity get expensive enrichment
+    #[serde(default = "default_enrich_min_severity")]
+    pub enrich_min_severity: Severity,
+
+    /// Expensive enrichments per distinct cause per flush window
+    #[serde(default = "default_enrich_per_key")]
+    pub enrich_per_key: u32,
+
+    /// Only events matching this expression enter the pipeline; its drop
+    /// reason and socket address terms are also checked in the kernel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+
+    /// Drop records per second let through for each drop reason (0 = unlimited)
+    #[serde(default)]
+    pub max_per_reason: u32,
+
+    /// Noise to discard before coalescing: drop reasons, interfaces, small packets
+    #[serde(default)]
+    pub filters: EventFilters,
+
+    /// Enrichers run on events past the severity gate, in order
+    #[serde(default = "default_enrichers")]
+    pub enrichers: Vec<PluginSpec>,
+
+    /// Sinks summaries are exported to, besides the log and control socket
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sinks: Vec<PluginSpec>,
+}
+
+fn default_reader_capacity() -> usize {
+    8192
+}
+
+fn default_enrich_capacity() -> usize {
+    4096
+}
+
+fn default_sink_capacity() -> usize {
+    64
+}
+
+fn default_flush_interval() -> u64 {
+    10
+}
+
+fn default_batch_size() -> usize {
+    256
+}
+
+fn default_max_events_per_tick() -> usize {
+    65536
+}
+
+fn default_coalesce_window() -> u64 {
+    100
+}
+
+fn default_enrich_min_severity() -> Severity {
+    Severity::Medium
+}
+
+fn default_enrich_per_key() -> u32 {
+    5
+}
+
+fn default_enrichers() -> Vec<PluginSpec> {
+    vec![PluginSpec::named("asn"), PluginSpec::named("container")]
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            reader_capacity: default_reader_capacity(),
+            enrich_capacity: default_enrich_capacity(),
+            sink_capacity: default_sink_capacity(),
+            flush_interval_secs: default_flush_interval(),
+            batch_size: default_batch_size(),
+            max_events_per_tick: default_max_events_per_tick(),
+            pinned_readers: false,
+            reader_cpus: Vec::new(),
+            coalesce_window_ms: default_coalesce_window(),
+            enrich_min_severity: default_enrich_min_severity(),
+            enrich_per_key: default_enrich_per_key(),
+            filter: None,
+            max_per_reason: 0,
+            filters: EventFilters::default(),
+            enrichers: default_enrichers(),
+            sinks: Vec::new(),
+        }
+    }
+}
+
+/// A drop reason in config, by name (as `sennet trace` prints it) or number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DropReason(pub u32);
+
+impl Serialize for DropReason {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match drop_reason_str(self.0) {
+            "UNKNOWN" => serializer.serialize_u32(self.0),
+            name => serializer.serialize_str(name),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DropReason {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Given {
+            Code(u32),
+            Name(String),
+        }
+        match Given::deserialize(deserializer)? {
+            Given::Code(code) => Ok(Self(code)),
+            Given::Name(name) => crate::filter::reason_code(&name)
+                .ok()
+                .and_then(|code| u32::try_from(code).ok())
+                .map(Self)
+                .ok_or_else(|| serde::de::Error::custom(format!("unknown drop reason `{}`", name))),
+        }
+    }
+}
+
+/// Noise filters (`pipeline.filters:`), for tuning what reaches summaries
+/// without writing a filter expression
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EventFilters {
+    /// Drop reasons to discard; also skipped in the kernel
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore_reasons: Vec<DropReason>,
+
+    /// Interfaces whose events are discarded; a trailing `*` matches a prefix
+    ///
+    /// Events the kernel couldn't tie to an interface (flows, retransmits,
+    /// conntrack, skbs with neither `dev` nor `skb_iif`) are kept.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_interfaces: Vec<String>,
+
+    /// Drops of packets shorter than this many bytes are discarded (0 = none)
+    #[serde(default)]
+    pub min_packet_bytes: u32,
+}
+
+impl EventFilters {
+    /// Whether `raw` is noise; resolves its interface only when excluding some
+    pub fn discards(&self, raw: &RawEvent, names: &mut InterfaceNames) -> bool {
+        if let RawEvent::Drop(e) = raw {
+            if self.ignore_reasons.contains(&DropReason(e.reason)) {
+                return true;
+            }
+            // Unknown length (older eBPF object, unreadable skb) passes
+            if e.len != 0 && e.len < self.min_packet_bytes {
+                return true;
+            }
+        }
+        if self.exclude_interfaces.is_empty() {
+            return false;
+        }
+        raw.ifindex()
+            .and_then(|idx| names.lookup(idx))
+            .is_some_and(|name| self.exclude_interfaces.iter().any(|pattern| interface_matches(pattern, &name)))
+    }
+
+    /// Add the ignored reasons to the kernel drop filter
+    pub fn apply_to(&self, kernel: &mut DropFilter) {
+        for DropReason(reason) in &self.ignore_reasons {
+            if let Some(word) = kernel.skip_reasons.get_mut((reason / 64) as usize) {
+                *word |= 1 << (reason % 64);
+            }
+        }
+    }
+}
+
+fn interface_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+/// Event with userspace context attached
+#[derive(Debug, Clone)]
+pub struct EnrichedEvent {
+    /// The event, or the first of a coalesced burst
+    pub raw: RawEvent,
+    /// Interface name resolved from ifindex
+    pub ifname: Option<Arc<str>>,
+    /// Number of identical kernel events this record stands for
+    pub count: u64,
+    /// Timestamp of the last event in the burst (== raw timestamp if count is 1)
+    pub last_timestamp_ns: u64,
+    /// Wall-clock time of the event (the first of a burst)
+    pub time: SystemTime,
+}
+
+impl EnrichedEvent {
+    pub fn new(raw: RawEvent, ifname: Option<Arc<str>>) -> Self {
+        Self {
+            raw,
+            ifname,
+            count: raw.sample_weight(),
+            last_timestamp_ns: raw.timestamp_ns(),
+            time: crate::clock::wall_time(raw.timestamp_ns()),
+        }
+    }
+
+    fn from_coalesced(drop: CoalescedDrop, ifname: Option<Arc<str>>) -> Self {
+        Self {
+            raw: RawEvent::Drop(drop.event),
+            ifname,
+            count: drop.count,
+            last_timestamp_ns: drop.last_timestamp_ns,
+            time: crate::clock::wall_time(drop.event.timestamp_ns),
+        }
+    }
+}
+
+/// Counters for one pipeline stage
+#[derive(Debug, Default)]
+pub struct StageStats {
+    /// Events handed to this stage
+    pub processed: AtomicU64,
+    /// Events dropped because this stage's input channel was full
+    pub dropped: AtomicU64,
+}
+
+impl StageStats {
+    fn snapshot(&self) -> (u64, u64) {
+        (self.processed.load(Ordering::Relaxed), self.dropped.load(Ordering::Relaxed))
+    }
+}
+
+/// Per-stage counters shared by all pipeline tasks
+#[derive(Debug, Default)]
+pub struct PipelineStats {
+    pub enrich: StageStats,
+    pub aggregate: StageStats,
+    pub sink: StageStats,
+    /// Drops merged into an earlier record by the coalescer
+    pub coalesced: AtomicU64,
+    /// Drops held back because their reason was over `max_per_reason`
+    pub reason_limited: AtomicU64,
+    /// Events discarded by `filter` or `filters`
+    pub filtered: AtomicU64,
+    /// Records that received expensive enrichment
+    pub deep_enriched: AtomicU64,
+    /// Records at or above the severity threshold whose key was over budget
+    pub deep_skipped: AtomicU64,
+    /// Records read from each ring buffer, indexed like `RingKind::ALL`
+    pub ring_events: [AtomicU64; RingKind::ALL.len()],
+    /// Records from each ring buffer dropped because the reader queue was full
+    pub ring_lost: [AtomicU64; RingKind::ALL.len()],
+    /// Records skipped because their header names a kind or layout version
+    /// this build can't read (eBPF object and agent from different builds)
+    pub undecodable: AtomicU64,
+    /// Interface name lookups answered from the cache
+    pub ifname_hits: AtomicU64,
+    /// Interface name lookups that needed (or were refused) a table refresh
+    pub ifname_misses: AtomicU64,
+    /// Fullest sink queue at the last flush (summaries)
+    pub sink_queue_depth: AtomicU64,
+}
+
+impl PipelineStats {
+    /// Total events dropped across all stages
+    pub fn total_dropped(&self) -> u64 {
+        [&self.enrich, &self.aggregate, &self.sink]
+            .iter()
+            .map(|stage| stage.snapshot().1)
+            .sum()
+    }
+}
+
+/// Aggregated view of one flush window
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    #[serde(skip)]
+    pub window_start: Option<SystemTime>,
+    #[serde(skip)]
+    pub window_end: Option<SystemTime>,
+    pub events: u64,
+    /// Kernel timestamps of the first and last event in the window
+    pub first_timestamp_ns: u64,
+    pub last_timestamp_ns: u64,
+    /// Kernel drop reason → count
+    pub drops_by_reason: BTreeMap<u32, u64>,
+    /// Interface name → drop count
+    pub drops_by_interface: BTreeMap<String, u64>,
+    /// Netfilter hook → DROP verdict count
+    pub nf_drops_by_hook: BTreeMap<u8, u64>,
+    pub flows_opened: u64,
+    pub flows_closed: u64,
+    pub resets_in: u64,
+    pub resets_out: u64,
+    /// Remote address → TCP segments retransmitted to it
+    pub retransmits_by_dst: BTreeMap<IpAddr, u64>,
+    pub dns_queries: u64,
+    /// DNS response code → responses (3 = NXDOMAIN)
+    pub dns_responses_by_rcode: BTreeMap<u8, u64>,
+    pub conntrack_new: u64,
+    pub conntrack_destroyed: u64,
+    /// Enriched records of events that passed the severity gate
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notable: Vec<NotableEvent>,
+    /// Deployment labels, set when the window is flushed
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Summary {
+    /// Fold one event into the summary
+    pub fn add(&mut self, event: &EnrichedEvent) {
+        self.events += event.count;
+        let first = event.raw.timestamp_ns();
+        if self.first_timestamp_ns == 0 || first < self.first_timestamp_ns {
+            self.first_timestamp_ns = first;
+        }
+        self.last_timestamp_ns = self.last_timestamp_ns.max(event.last_timestamp_ns);
+        match &event.raw {
+            RawEvent::Drop(e) => {
+                *self.drops_by_reason.entry(e.reason).or_insert(0) += event.count;
+                if let Some(name) = &event.ifname {
+                    // Look up before inserting so known interfaces don't allocate a key
+                    match self.drops_by_interface.get_mut(name.as_ref()) {
+                        Some(count) => *count += event.count,
+                        None => {
+                            self.drops_by_interface.insert(name.to_string(), event.count);
+                        }
+                    }
+                }
+            }
+            RawEvent::Netfilter(e) => {
+                if e.is_drop() {
+                    *self.nf_drops_by_hook.entry(e.hook).or_insert(0) += event.count;
+                }
+            }
+            RawEvent::Flow(e) => match e.event_type {
+                1 => self.flows_opened += event.count,
+                3 => self.flows_closed += event.count,
+                _ => {}
+            },
+            RawEvent::Rst(e) => {
+                if e.direction == 0 {
+                    self.resets_in += event.count;
+                } else {
+                    self.resets_out += event.count;
+                }
+            }
+            RawEvent::Packet(_) | RawEvent::Payload(_) | RawEvent::Http(_) => {}
+            RawEvent::Retransmit(e) => {
+                *self.retransmits_by_dst.entry(crate::ebpf::ip_addr(&e.dst_addr)).or_insert(0) += event.count;
+            }
+            RawEvent::Dns(e) if e.is_response == 0 => self.dns_queries += event.count,
+            RawEvent::Dns(e) => *self.dns_responses_by_rcode.entry(e.rcode).or_insert(0) += event.count,
+            RawEvent::Conntrack(e) if e.event_type == sennet_common::ct_event::NEW => self.conntrack_new += event.count,
+            RawEvent::Conntrack(_) => self.conntrack_destroyed += event.count,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events == 0
+    }
+
+    /// Notable events kept per window
+    pub const MAX_NOTABLE: usize = 256;
+}
+
+/// Consumer of aggregated summaries
+///
+/// Each sink runs on its own blocking thread behind a bounded channel, so
+/// `export` may do slow I/O; while it is busy, that sink alone loses
+/// windows. Closures taking `&Summary` are sinks too.
+pub trait EventSink: Send {
+    /// Name used in config.yaml, logs and spans
+    fn name(&self) -> &str {
+        "custom"
+    }
+
+    fn export(&mut self, summary: &Summary);
+}
+
+impl<F: FnMut(&Summary) + Send> EventSink for F {
+    fn export(&mut self, summary: &Summary) {
+        self(summary)
+    }
+}
+
+/// A boxed sink, as passed to [`spawn`]
+pub type SinkFn = Box<dyn EventSink>;
+
+/// Sink that logs each non-empty summary
+pub fn log_sink() -> SinkFn {
+    Box::new(|summary: &Summary| {
+        if summary.is_empty() {
+            return;
+        }
+        let drops: u64 = summary.drops_by_reason.values().sum();
+        let nf_drops: u64 = summary.nf_drops_by_hook.values().sum();
+        let window_secs = match (summary.window_start, summary.window_end) {
+            (Some(start), Some(end)) => end.duration_since(start).map(|d| d.as_secs()).unwrap_or(0),
+            _ => 0,
+        };
+        info!(
+            window_secs,
+            events = summary.events,
+            drops,
+            nf_drops,
+            flows_opened = summary.flows_opened,
+            flows_closed = summary.flows_closed,
+            resets = summary.resets_in + summary.resets_out,
+            retransmits = summary.retransmits_by_dst.values().sum::<u64>(),
+            dns_queries = summary.dns_queries,
+            conntrack_new = summary.conntrack_new,
+            "event summary"
+        );
+    })
+}
+
+/// Entry point of a running pipeline
+#[derive(Clone)]
+pub struct PipelineHandle {
+    tx: mpsc::Sender<RawEvent>,
+    enriched_tx: mpsc::Sender<EnrichedEvent>,
+    tap: broadcast::Sender<EnrichedEvent>,
+    coalesce_window: Duration,
+    filter: Option<Filter>,
+    filters: EventFilters,
+    max_per_reason: u32,
+    pub stats: Arc<PipelineStats>,
+}
+
+/// Events buffered per tap subscriber before the oldest are dropped
+pub const TAP_CAPACITY: usize = 4096;
+
+impl PipelineHandle {
+    /// Submit an event without blocking; returns false if it was dropped
+    pub fn submit(&self, event: RawEvent) -> bool {
+        let kind = event.kind();
+        let accepted = try_forward(&self.tx, event, &self.stats.enrich);
+        if !accepted && !self.tx.is_closed() {
+            self.stats.ring_lost[kind.index()].fetch_add(1, Ordering::Relaxed);
+        }
+        accepted
+    }
+
+    /// Submit an event, waiting for room instead of dropping it; for sources
+    /// that can wait, like replays. Returns false once the pipeline is gone
+    ///
+    /// Must not be called from an async task.
+    pub fn submit_wait(&self, event: RawEvent) -> bool {
+        let sent = self.tx.blocking_send(event).is_ok();
+        if sent {
+            self.stats.enrich.processed.fetch_add(1, Ordering::Relaxed);
+        }
+        sent
+    }
+
+    /// Submit a batch of events, reserving channel capacity once for the
+    /// whole batch; returns how many were accepted
+    ///
+    /// The batch is left empty. If the channel can't take all of it, as many
+    /// events as fit are accepted and the rest are counted as dropped.
+    pub fn submit_batch(&self, batch: &mut Vec<RawEvent>) -> usize {
+        let total = batch.len();
+        if total == 0 {
+            return 0;
+        }
+        match self.tx.try_reserve_many(total) {
+            Ok(permits) => {
+                for (permit, event) in permits.zip(batch.drain(..)) {
+                    permit.send(event);
+                }
+                self.stats.enrich.processed.fetch_add(total as u64, Ordering::Relaxed);
+                total
+            }
+            // Not enough room for all of it: fall back to per-event sends
+            Err(TrySendError::Full(())) => batch.drain(..).filter(|e| self.submit(*e)).count(),
+            Err(TrySendError::Closed(())) => {
+                batch.clear();
+                0
+            }
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Resolves once the pipeline has shut down
+    #[cfg(target_os = "linux")]
+    async fn closed(&self) {
+        self.tx.closed().await
+    }
+
+    /// Event tap: every receiver subscribed to it gets a copy of each event
+    /// reaching the aggregate stage
+    pub fn tap(&self) -> broadcast::Sender<EnrichedEvent> {
+        self.tap.clone()
+    }
+
+    /// Current (depth, capacity) of the reader and enrich queues
+    pub fn queue_depths(&self) -> [(&'static str, usize, usize); 2] {
+        let depth = |max: usize, free: usize| max.saturating_sub(free);
+        [
+            ("reader", depth(self.tx.max_capacity(), self.tx.capacity()), self.tx.max_capacity()),
+            ("enrich", depth(self.enriched_tx.max_capacity(), self.enriched_tx.capacity()), self.enriched_tx.max_capacity()),
+        ]
+    }
+
+    /// Add a lane: a separate input queue with its own enrich task, feeding
+    /// the same aggregate stage
+    ///
+    /// Each lane has a single producer, so readers on different lanes never
+    /// contend on a shared queue.
+    pub fn add_lane(&self, capacity: usize) -> (PipelineHandle, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        let task = spawn_enrich(
+            rx,
+            self.enriched_tx.clone(),
+            self.stats.clone(),
+            self.coalesce_window,
+            self.filter.clone(),
+            self.filters.clone(),
+            self.max_per_reason,
+        );
+        let lane = PipelineHandle {
+            tx,
+            enriched_tx: self.enriched_tx.clone(),
+            tap: self.tap.clone(),
+            coalesce_window: self.coalesce_window,
+            filter: self.filter.clone(),
+            filters: self.filters.clone(),
+            max_per_reason: self.max_per_reason,
+            stats: self.stats.clone(),
+        };
+        (lane, task)
+    }
+}
+
+/// Reads records in fixed-size batches and submits each batch at once
+pub struct BatchDrainer {
+    batch: Vec<RawEvent>,
+    batch_size: usize,
+    max_events_per_tick: usize,
+}
+
+impl BatchDrainer {
+    pub fn new(config: &PipelineConfig) -> Self {
+        let batch_size = config.batch_size.max(1);
+        Self {
+            batch: Vec::with_capacity(batch_size),
+            batch_size,
+            max_events_per_tick: config.max_events_per_tick.max(1),
+        }
+    }
+
+    /// Read from `next` until it runs dry or the per-tick budget is spent
+    ///
+    /// Returns the number of events read. A return value equal to the
+    /// budget means more records are likely waiting.
+    pub fn drain(&mut self, mut next: impl FnMut() -> Option<RawEvent>, handle: &PipelineHandle) -> usize {
+        let mut read = 0;
+        while read < self.max_events_per_tick {
+            let want = self.batch_size.min(self.max_events_per_tick - read);
+            while self.batch.len() < want {
+                match next() {
+                    Some(event) => self.batch.push(event),
+                    None => break,
+                }
+            }
+            let got = self.batch.len();
+            if got == 0 {
+                break;
+            }
+            read += got;
+            handle.submit_batch(&mut self.batch);
+            if got < want {
+                break;
+            }
+        }
+        read
+    }
+
+    pub fn max_events_per_tick(&self) -> usize {
+        self.max_events_per_tick
+    }
+}
+
+/// Forward to the next stage, counting the event as processed or dropped
+fn try_forward<T>(tx: &mpsc::Sender<T>, item: T, stage: &StageStats) -> bool {
+    match tx.try_send(item) {
+        Ok(()) => {
+            stage.processed.fetch_add(1, Ordering::Relaxed);
+            true
+        }
+        Err(TrySendError::Full(_)) => {
+            stage.dropped.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+        Err(TrySendError::Closed(_)) => false,
+    }
+}
+
+/// Resolves interface indexes to names through the shared cache
+/// (`crate::ifnames`), counting hits and misses
+#[derive(Default)]
+pub struct InterfaceNames {
+    stats: Option<Arc<PipelineStats>>,
+}
+
+impl InterfaceNames {
+    pub fn new() -> Self {
+        Self { stats: None }
+    }
+
+    /// Count cache hits and misses in `stats`
+    pub fn with_stats(stats: Arc<PipelineStats>) -> Self {
+        Self { stats: Some(stats) }
+    }
+
+    pub fn lookup(&mut self, ifindex: u32) -> Option<Arc<str>> {
+        let cache = crate::ifnames::cache();
+        if let Some(name) = cache.cached(ifindex) {
+            if let Some(stats) = &self.stats {
+                stats.ifname_hits.fetch_add(1, Ordering::Relaxed);
+            }
+            return Some(name);
+        }
+        if let Some(stats) = &self.stats {
+            stats.ifname_misses.fetch_add(1, Ordering::Relaxed);
+        }
+        cache.get(ifindex)
+    }
+}
+
+/// Spawn the enrich, aggregate and sink stages
+///
+/// Returns a handle for the reader to submit events into, plus the join
+/// handles of all spawned tasks. `enricher` performs the expensive lookups
+/// for events that pass the severity gate.
+pub fn spawn(
+    config: &PipelineConfig,
+    sinks: Vec<SinkFn>,
+    enricher: DeepEnricher,
+) -> (PipelineHandle, Vec<JoinHandle<()>>) {
+    let stats = Arc::new(PipelineStats::default());
+    let (raw_tx, raw_rx) = mpsc::channel::<RawEvent>(config.reader_capacity.max(1));
+    let (enriched_tx, mut enriched_rx) = mpsc::channel::<EnrichedEvent>(config.enrich_capacity.max(1));
+    let (tap, _) = broadcast::channel::<EnrichedEvent>(TAP_CAPACITY);
+    let mut tasks = Vec::new();
+
+    // Sinks: one bounded channel and blocking thread each, so one slow sink
+    // can't hold up the others or the async runtime
+    let mut sink_txs = Vec::with_capacity(sinks.len());
+    for mut sink in sinks {
+        let (tx, mut rx) = mpsc::channel::<Arc<Summary>>(config.sink_capacity.max(1));
+        sink_txs.push(tx);
+        tasks.push(tokio::task::spawn_blocking(move || {
+            while let Some(summary) = rx.blocking_recv() {
+                let _span = tracing::info_span!("pipeline.export", sink = sink.name(), events = summary.events).entered();
+                sink.export(&summary);
+            }
+        }));
+    }
+
+    // Enrich stage
+    let coalesce_window = Duration::from_millis(config.coalesce_window_ms);
+    let filter = config.filter.clone();
+    let filters = config.filters.clone();
+    let max_per_reason = config.max_per_reason;
+    tasks.push(spawn_enrich(
+        raw_rx,
+        enriched_tx.clone(),
+        stats.clone(),
+        coalesce_window,
+        filter.clone(),
+        filters.clone(),
+        max_per_reason,
+    ));
+
+    // Aggregate stage
+    let agg_stats = stats.clone();
+    let flush_interval = Duration::from_secs(config.flush_interval_secs.max(1));
+    let min_severity = config.enrich_min_severity;
+    let mut gate = EnrichGate::new(min_severity, config.enrich_per_key);
+    let agg_tap = tap.clone();
+    tasks.push(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(flush_interval);
+        ticker.tick().await;
+        let mut summary = Summary {
+            window_start: Some(SystemTime::now()),
+            ..Default::default()
+        };
+        loop {
+            tokio::select! {
+                event = enriched_rx.recv() => match event {
+                    Some(event) => {
+                        if agg_tap.receiver_count() > 0 {
+                            let _ = agg_tap.send(event.clone());
+                        }
+                        // Counters for everything; lookups only past the gate
+                        summary.add(&event);
+                        if summary.notable.len() < Summary::MAX_NOTABLE && gate.admit(&event.raw) {
+                            agg_stats.deep_enriched.fetch_add(1, Ordering::Relaxed);
+                            let context = enricher.enrich(&event.raw);
+                            summary.notable.push(NotableEvent::new(&event.raw, event.count, event.ifname.as_deref(), event.time, context));
+                        } else if Severity::of(&event.raw) >= min_severity {
+                            agg_stats.deep_skipped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    None => {
+                        // Input closed: export what the last window has
+                        if !summary.is_empty() {
+                            summary.window_end = Some(SystemTime::now());
+                            summary.labels = crate::labels::get().clone();
+                            let flushed = Arc::new(std::mem::take(&mut summary));
+                            for tx in &sink_txs {
+                                try_forward(tx, flushed.clone(), &agg_stats.sink);
+                            }
+                        }
+                        break;
+                    }
+                },
+                _ = ticker.tick() => {
+                    gate.reset();
+                    let now = SystemTime::now();
+                    summary.window_end = Some(now);
+                    summary.labels = crate::labels::get().clone();
+                    let flushed = Arc::new(std::mem::replace(&mut summary, Summary {
+                        window_start: Some(now),
+                        ..Default::default()
+                    }));
+                    let mut sink_depth = 0;
+                    for tx in &sink_txs {
+                        try_forward(tx, flushed.clone(), &agg_stats.sink);
+                        sink_depth = sink_depth.max(tx.max_capacity() - tx.capacity());
+                    }
+                    agg_stats.sink_queue_depth.store(sink_depth as u64, Ordering::Relaxed);
+                    debug!(
+                        events = flushed.events,
+                        dropped = agg_stats.total_dropped(),
+                        coalesced = agg_stats.coalesced.load(Ordering::Relaxed),
+                        notable = flushed.notable.len(),
+                        deep_skipped = agg_stats.deep_skipped.load(Ordering::Relaxed),
+                        "pipeline window flushed"
+                    );
+                }
+            }
+        }
+    }));
+
+    (PipelineHandle { tx: raw_tx, enriched_tx, tap, coalesce_window, filter, filters, max_per_reason, stats }, tasks)
+}
+
+/// Drop filtered and noise events, coalesce drops, hold back drops of
+/// reasons over `max_per_reason`, resolve interface names and forward to the
+/// aggregate stage
+fn spawn_enrich(
+    mut raw_rx: mpsc::Receiver<RawEvent>,
+    enriched_tx: mpsc::Sender<EnrichedEvent>,
+    stats: Arc<PipelineStats>,
+    coalesce_window: Duration,
+    filter: Option<Filter>,
+    filters: EventFilters,
+    max_per_reason: u32,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // tokio's clock, so coalescing windows follow paused time in tests
+        let now = || tokio::time::Instant::now().into_std();
+        let start = now();
+        let mut names = InterfaceNames::with_stats(stats.clone());
+        let mut coalescer = DropCoalescer::new(coalesce_window);
+        let mut limiter = ReasonLimiter::new(max_per_reason.into());
+        let forward = |event: EnrichedEvent| try_forward(&enriched_tx, event, &stats.aggregate);
+        let mut emit = |drop: CoalescedDrop, names: &mut InterfaceNames| {
+            stats.coalesced.fetch_add(drop.count - 1, Ordering::Relaxed);
+            if !limiter.admit(drop.event.reason, drop.count, now().duration_since(start)) {
+                stats.reason_limited.fetch_add(drop.count, Ordering::Relaxed);
+                return;
+            }
+            let ifname = names.lookup(drop.event.ifindex);
+            forward(EnrichedEvent::from_coalesced(drop, ifname));
+        };
+        // Closes bursts that see no further drops; idles when coalescing is off
+        let mut ticker = tokio::time::interval(coalescer.window().max(Duration::from_secs(1)));
+
+        loop {
+            tokio::select! {
+                raw = raw_rx.recv() => match raw {
+                    Some(raw) if filter.as_ref().is_some_and(|f| !f.matches(&raw)) || filters.discards(&raw, &mut names) => {
+                        stats.filtered.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Some(RawEvent::Drop(event)) => {
+                        if let Some(drop) = coalescer.push(event, now()) {
+                            emit(drop, &mut names);
+                        }
+                    }
+                    Some(raw) => {
+                        let ifname = raw.ifindex().and_then(|idx| names.lookup(idx));
+                        forward(EnrichedEvent::new(raw, ifname));
+                    }
+                    None => break,
+                },
+                _ = ticker.tick() => {
+                    for drop in coalescer.flush_expired(now()) {
+                        emit(drop, &mut names);
+                    }
+                }
+            }
+        }
+
+        for drop in coalescer.flush_all() {
+            emit(drop, &mut names);
+        }
+    })
+}
+
+/// How long the reader waits for ring buffer readiness before re-checking
+/// whether the pipeline has shut down
+#[cfg(target_os = "linux")]
+const READER_POLL_TIMEOUT_MS: i32 = 100;
+
+/// Drain the kernel ring buffers into the pipeline
+///
+/// Each ring buffer gets a tokio task that waits for epoll readiness on its
+/// fd (`AsyncFd`) and drains it in batches (see [`BatchDrainer`]) until the
+/// pipeline closes. Returns the reader tasks, none without ring buffers.
+#[cfg(target_os = "linux")]
+pub fn spawn_reader(
+    manager: &mut crate::ebpf::EbpfManager,
+    config: &PipelineConfig,
+    handle: PipelineHandle,
+) -> Vec<JoinHandle<()>> {
+    let rings = manager.take_ring_buffers();
+    if !rings.is_empty() {
+        info!(
+            "Event pipeline reading {} ring buffers (batch: {}, max per tick: {})",
+            rings.len(),
+            config.batch_size,
+            config.max_events_per_tick
+        );
+    }
+    rings
+        .into_iter()
+        .map(|(kind, rb)| tokio::spawn(read_ring(kind, rb, BatchDrainer::new(config), handle.clone())))
+        .collect()
+}
+
+/// Read each ring buffer on its own CPU-pinned thread
+///
+/// Every reader gets its own lane (see [`PipelineHandle::add_lane`]), so the
+/// hot path is a plain OS thread pushing into a single-producer queue with
+/// no tokio scheduling involved. Returns the lanes' enrich tasks.
+#[cfg(target_os = "linux")]
+pub fn spawn_pinned_readers(
+    manager: &mut crate::ebpf::EbpfManager,
+    config: &PipelineConfig,
+    handle: &PipelineHandle,
+) -> Vec<JoinHandle<()>> {
+    let ncpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut tasks = Vec::new();
+
+    for (index, (kind, rb)) in manager.take_ring_buffers().into_iter().enumerate() {
+        let cpu = reader_cpu(&config.reader_cpus, index, ncpus);
+        let (lane, task) = handle.add_lane(config.reader_capacity);
+        let drainer = BatchDrainer::new(config);
+        let spawned = std::thread::Builder::new()
+            .name(format!("sennet-rd-{}", index))
+            .spawn(move || {
+                if let Err(e) = pin_current_thread(cpu) {
+                    tracing::warn!("Could not pin {:?} reader to CPU {}: {}", kind, cpu, e);
+                }
+                read_loop(vec![(kind, rb)], drainer, lane)
+            });
+        match spawned {
+            Ok(_) => {
+                info!("Pinned {:?} ring buffer reader to CPU {}", kind, cpu);
+                tasks.push(task);
+            }
+            Err(e) => {
+                tracing::warn!("Failed to spawn {:?} reader thread: {}", kind, e);
+                task.abort();
+            }
+        }
+    }
+    tasks
+}
+
+/// CPU for the reader of the `index`-th ring buffer
+fn reader_cpu(cpus: &[usize], index: usize, ncpus: usize) -> usize {
+    if cpus.is_empty() {
+        index % ncpus.max(1)
+    } else {
+        cpus[index % cpus.len()]
+    }
+}
+
+/// CPUs a `cpu_set_t` can name; `CPU_SET` panics beyond this
+#[cfg(target_os = "linux")]
+const CPU_SETSIZE: usize = libc::CPU_SETSIZE as usize;
+
+/// Check that `reader_cpus` only names CPUs a reader thread can be pinned
+/// to: below CPU_SETSIZE and online
+#[cfg(target_os = "linux")]
+pub fn check_reader_cpus(cpus: &[usize]) -> anyhow::Result<()> {
+    if let Some(cpu) = cpus.iter().find(|&&cpu| cpu >= CPU_SETSIZE) {
+        anyhow::bail!("pipeline.reader_cpus: CPU {} is out of range (at most {})", cpu, CPU_SETSIZE - 1);
+    }
+    let Ok(online) = std::fs::read_to_string("/sys/devices/system/cpu/online") else {
+        return Ok(());
+    };
+    if let Some(set) = parse_cpu_list(&online) {
+        if let Some(cpu) = cpus.iter().find(|cpu| !set.contains(cpu)) {
+            anyhow::bail!("pipeline.reader_cpus: CPU {} is not online (online: {})", cpu, online.trim());
+        }
+    }
+    Ok(())
+}
+
+/// Parse a kernel CPU list like `0-3,8,10-11`
+#[allow(dead_code)] // Used on Linux
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// Restrict the calling thread to a single CPU
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpu: usize) -> std::io::Result<()> {
+    if cpu >= CPU_SETSIZE {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "CPU beyond CPU_SETSIZE"));
+    }
+    // SAFETY: cpu_set_t is plain data, and cpu is below CPU_SETSIZE, which
+    // CPU_SET would otherwise panic on
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Drain one ring buffer whenever epoll reports it readable, until the
+/// pipeline closes
+#[cfg(target_os = "linux")]
+async fn read_ring(
+    kind: RingKind,
+    rb: aya::maps::RingBuf<aya::maps::MapData>,
+    mut drainer: BatchDrainer,
+    handle: PipelineHandle,
+) {
+    let mut ring = match tokio::io::unix::AsyncFd::new(rb) {
+        Ok(ring) => ring,
+        Err(e) => {
+            tracing::warn!("Failed to register {} ring buffer with epoll: {}", kind.name(), e);
+            return;
+        }
+    };
+    let mut reported: Vec<crate::events::Undecodable> = Vec::new();
+    loop {
+        let mut guard = tokio::select! {
+            guard = ring.readable_mut() => match guard {
+                Ok(guard) => guard,
+                Err(e) => {
+                    tracing::warn!("Waiting on {} ring buffer failed: {}", kind.name(), e);
+                    return;
+                }
+            },
+            _ = handle.closed() => return,
+        };
+        let read = drain_ring(kind, guard.get_inner_mut(), &mut drainer, &handle, &mut reported);
+        if read < drainer.max_events_per_tick() {
+            // Drained; readiness the kernel signalled since is kept
+            guard.clear_ready();
+        } else {
+            // Budget spent with records left: let the other readers run first
+            drop(guard);
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+/// Wait for readiness on `rings` and drain them until the pipeline closes
+#[cfg(target_os = "linux")]
+fn read_loop(
+    mut rings: Vec<(RingKind, aya::maps::RingBuf<aya::maps::MapData>)>,
+    mut drainer: BatchDrainer,
+    handle: PipelineHandle,
+) {
+    use std::os::fd::AsRawFd;
+
+    let mut fds: Vec<libc::pollfd> = rings
+        .iter()
+        .map(|(_, rb)| libc::pollfd { fd: rb.as_raw_fd(), events: libc::POLLIN, revents: 0 })
+        .collect();
+    let mut backlog = false;
+    // Mismatches already logged; one warning each is enough to explain the
+    // counter
+    let mut reported: Vec<crate::events::Undecodable> = Vec::new();
+    while !handle.is_closed() {
+        // Don't block if the previous pass stopped at the per-tick budget
+        let timeout = if backlog { 0 } else { READER_POLL_TIMEOUT_MS };
+        // SAFETY: fds points to fds.len() initialized pollfd structs
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        if ready < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            tracing::warn!("Ring buffer poll failed: {}", err);
+            break;
+        }
+
+        let drain_all = backlog;
+        backlog = false;
+        for ((kind, rb), pfd) in rings.iter_mut().zip(fds.iter_mut()) {
+            if pfd.revents == 0 && !drain_all {
+                continue;
+            }
+            pfd.revents = 0;
+            let read = drain_ring(*kind, rb, &mut drainer, &handle, &mut reported);
+            if read >= drainer.max_events_per_tick() {
+                backlog = true;
+            }
+        }
+    }
+}
+
+/// Read `rb` through `drainer` and count what was read, skipping records
+/// that don't decode (each kind of mismatch is logged once)
+#[cfg(target_os = "linux")]
+fn drain_ring(
+    kind: RingKind,
+    rb: &mut aya::maps::RingBuf<aya::maps::MapData>,
+    drainer: &mut BatchDrainer,
+    handle: &PipelineHandle,
+    reported: &mut Vec<crate::events::Undecodable>,
+) -> usize {
+    let next = || loop {
+        let item = rb.next()?;
+        match crate::events::try_decode(&item) {
+            Ok(event) => return Some(event),
+            Err(e) => {
+                handle.stats.undecodable.fetch_add(1, Ordering::Relaxed);
+                if !reported.contains(&e) {
+                    tracing::warn!("Skipping {} records: {}", kind.name(), e);
+                    reported.push(e);
+                }
+            }
+        }
+    };
+    let read = drainer.drain(next, handle);
+    handle.stats.ring_events[kind.index()].fetch_add(read as u64, Ordering::Relaxed);
+    read
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ebpf::{ConntrackEvent, DnsEvent, DropEvent, RetransmitEvent, RstEvent};
+    use std::time::Instant;
+
+    fn drop_event(reason: u32) -> RawEvent {
+        RawEvent::Drop(DropEvent { reason, ..Default::default() })
+    }
+
+    /// Handle whose input is returned instead of feeding an enrich stage
+    fn test_handle(capacity: usize) -> (PipelineHandle, mpsc::Receiver<RawEvent>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let (enriched_tx, _) = mpsc::channel(1);
+        let handle = PipelineHandle {
+            tx,
+            enriched_tx,
+            tap: broadcast::channel(1).0,
+            coalesce_window: Duration::ZERO,
+            filter: None,
+            filters: EventFilters::default(),
+            max_per_reason: 0,
+            stats: Arc::default(),
+        };
+        (handle, rx)
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), Some(vec![0, 1, 2, 3, 8, 10, 11]));
+        assert_eq!(parse_cpu_list("0"), Some(vec![0]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+
+    #[test]
+    fn test_reader_cpu_assignment() {
+        assert_eq!(reader_cpu(&[], 0, 4), 0);
+        assert_eq!(reader_cpu(&[], 5, 4), 1);
+        assert_eq!(reader_cpu(&[2, 3], 0, 8), 2);
+        assert_eq!(reader_cpu(&[2, 3], 3, 8), 3);
+    }
+
+    #[tokio::test]
+    async fn test_lanes_feed_shared_aggregate() {
+        let config = PipelineConfig { coalesce_window_ms: 0, ..Default::default() };
+        let (handle, tasks) = spawn(&config, Vec::new(), DeepEnricher::default());
+        let (lane_a, task_a) = handle.add_lane(16);
+        let (lane_b, task_b) = handle.add_lane(16);
+        let mut tap = handle.tap().subscribe();
+
+        assert!(lane_a.submit(drop_event(1)));
+        assert!(lane_b.submit(drop_event(2)));
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(handle.stats.enrich.processed.load(Ordering::Relaxed), 2);
+        assert_eq!(handle.stats.aggregate.processed.load(Ordering::Relaxed), 2);
+        // Both lanes' events reach tap subscribers once aggregated
+        for _ in 0..2 {
+            let received = tokio::time::timeout(Duration::from_secs(1), tap.recv()).await;
+            assert!(matches!(received, Ok(Ok(_))));
+        }
+
+        for task in tasks.into_iter().chain([task_a, task_b]) {
+            task.abort();
+        }
+    }
+
+    #[test]
+    fn test_summary_add() {
+        let mut summary = Summary::default();
+        summary.add(&EnrichedEvent::new(drop_event(7), Some(Arc::from("eth0"))));
+        summary.add(&EnrichedEvent::new(drop_event(7), None));
+        summary.add(&EnrichedEvent::new(RawEvent::Rst(RstEvent { direction: 1, ..Default::default() }), None));
+        assert_eq!(summary.events, 3);
+        assert_eq!(summary.drops_by_reason.get(&7), Some(&2));
+        assert_eq!(summary.drops_by_interface.get("eth0"), Some(&1));
+        assert_eq!(summary.resets_out, 1);
+
+        let retransmit = RetransmitEvent { dst_addr: sennet_common::ipv4_mapped([10, 0, 0, 9]), ..Default::default() };
+        summary.add(&EnrichedEvent::new(RawEvent::Retransmit(retransmit), None));
+        assert_eq!(summary.retransmits_by_dst.get(&"10.0.0.9".parse::<IpAddr>().unwrap()), Some(&1));
+        assert_eq!(summary.events, 4);
+
+        summary.add(&EnrichedEvent::new(RawEvent::Dns(DnsEvent::default()), None));
+        summary.add(&EnrichedEvent::new(RawEvent::Dns(DnsEvent { is_response: 1, rcode: 3, ..Default::default() }), None));
+        assert_eq!(summary.dns_queries, 1);
+        assert_eq!(summary.dns_responses_by_rcode.get(&3), Some(&1));
+        assert_eq!(summary.events, 6);
+
+        let destroyed = ConntrackEvent { event_type: sennet_common::ct_event::DESTROY, ..Default::default() };
+        summary.add(&EnrichedEvent::new(RawEvent::Conntrack(destroyed), None));
+        assert_eq!((summary.conntrack_new, summary.conntrack_destroyed), (0, 1));
+        assert_eq!(summary.events, 7);
+
+        // Kernel-sampled events count for their weight
+        let sampled = RawEvent::Drop(DropEvent { reason: 2, sample_rate: 50, ..Default::default() });
+        summary.add(&EnrichedEvent::new(sampled, None));
+        assert_eq!(summary.drops_by_reason.get(&2), Some(&50));
+        assert_eq!(summary.events, 57);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drop_storm_is_coalesced() {
+        let (handle, tasks) = spawn(&PipelineConfig::default(), Vec::new(), DeepEnricher::default());
+
+        for _ in 0..100 {
+            assert!(handle.submit(drop_event(7)));
+        }
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        // Nothing reaches aggregation until the burst's window closes
+        assert_eq!(handle.stats.aggregate.processed.load(Ordering::Relaxed), 0);
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(handle.stats.aggregate.processed.load(Ordering::Relaxed), 1);
+        assert_eq!(handle.stats.coalesced.load(Ordering::Relaxed), 99);
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_noisy_reason_is_limited() {
+        let config = PipelineConfig { coalesce_window_ms: 0, max_per_reason: 5, ..Default::default() };
+        let (handle, tasks) = spawn(&config, Vec::new(), DeepEnricher::default());
+
+        // TCP_OLD_DATA floods, one NETFILTER_DROP among it
+        for i in 0..100 {
+            assert!(handle.submit(drop_event(if i == 50 { 7 } else { 74 })));
+            tokio::task::yield_now().await;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(handle.stats.aggregate.processed.load(Ordering::Relaxed), 6);
+        assert_eq!(handle.stats.reason_limited.load(Ordering::Relaxed), 94);
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_noise_filters() {
+        let filters: EventFilters = serde_yaml::from_str("ignore_reasons: [TCP_OLD_DATA, 2]\nmin_packet_bytes: 64\n").unwrap();
+        assert_eq!(filters.ignore_reasons, [DropReason(23), DropReason(2)]);
+        assert!(serde_yaml::from_str::<EventFilters>("ignore_reasons: [NO_SUCH_REASON]").is_err());
+
+        let mut kernel = DropFilter::default();
+        filters.apply_to(&mut kernel);
+        assert_eq!(kernel.skip_reasons, [(1 << 23) | (1 << 2), 0, 0, 0]);
+
+        let config = PipelineConfig { coalesce_window_ms: 0, filters, ..Default::default() };
+        let (handle, tasks) = spawn(&config, Vec::new(), DeepEnricher::default());
+        let sized = |reason, len| RawEvent::Drop(DropEvent { reason, len, ..Default::default() });
+        for raw in [sized(23, 1500), sized(2, 1500), sized(7, 40), sized(7, 1500), sized(7, 0)] {
+            assert!(handle.submit(raw));
+            tokio::task::yield_now().await;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(handle.stats.filtered.load(Ordering::Relaxed), 3);
+        assert_eq!(handle.stats.aggregate.processed.load(Ordering::Relaxed), 2);
+
+        assert!(interface_matches("veth*", "veth3a9f"));
+        assert!(interface_matches("lo", "lo"));
+        assert!(!interface_matches("lo", "lo2"));
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    /// Ring records as the kernel writes them, with the given interface
+    /// index at `offset` in the event
+    #[cfg(target_os = "linux")]
+    fn ring_record<T: crate::events::RingEvent>(offset: usize, ifindex: u32) -> RawEvent {
+        use crate::events::{EventHeader, EVENT_HEADER_LEN};
+
+        #[repr(C, align(8))]
+        struct Aligned([u8; 256]);
+        let mut buf = Aligned([0u8; 256]);
+        buf.0[..EVENT_HEADER_LEN].copy_from_slice(&EventHeader::of::<T>().to_bytes());
+        let at = EVENT_HEADER_LEN + offset;
+        buf.0[at..at + 4].copy_from_slice(&ifindex.to_ne_bytes());
+        crate::events::decode(&buf.0).unwrap()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_kernel_ifindex_names_and_filters_events() {
+        use crate::ebpf::NetfilterEvent;
+        use std::mem::offset_of;
+
+        // Loopback is interface 1; drops name it from skb->dev, netfilter
+        // events from nf_hook_state->in, falling back to ->out
+        let dropped = ring_record::<DropEvent>(offset_of!(DropEvent, ifindex), 1);
+        let hooked_in = ring_record::<NetfilterEvent>(offset_of!(NetfilterEvent, ifindex_in), 1);
+        let hooked_out = ring_record::<NetfilterEvent>(offset_of!(NetfilterEvent, ifindex_out), 1);
+        let unknown = ring_record::<DropEvent>(offset_of!(DropEvent, ifindex), 0);
+        assert_eq!(dropped.ifindex(), Some(1));
+        assert_eq!(hooked_out.ifindex(), Some(1));
+        assert_eq!(unknown.ifindex(), None);
+
+        let config = PipelineConfig { coalesce_window_ms: 0, ..Default::default() };
+        let (handle, tasks) = spawn(&config, Vec::new(), DeepEnricher::default());
+        let mut tap = handle.tap().subscribe();
+        for raw in [dropped, hooked_in, hooked_out] {
+            assert!(handle.submit(raw));
+            let received = tokio::time::timeout(Duration::from_secs(1), tap.recv()).await.unwrap().unwrap();
+            assert_eq!(received.ifname.as_deref(), Some("lo"));
+        }
+        for task in tasks {
+            task.abort();
+        }
+
+        let filters: EventFilters = serde_yaml::from_str("exclude_interfaces: [lo]").unwrap();
+        let config = PipelineConfig { coalesce_window_ms: 0, filters, ..Default::default() };
+        let (handle, tasks) = spawn(&config, Vec::new(), DeepEnricher::default());
+        for raw in [dropped, hooked_in, hooked_out, unknown] {
+            assert!(handle.submit(raw));
+            tokio::task::yield_now().await;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(handle.stats.filtered.load(Ordering::Relaxed), 3);
+        assert_eq!(handle.stats.aggregate.processed.load(Ordering::Relaxed), 1);
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_storm_is_enriched_lazily() {
+        let config = PipelineConfig { coalesce_window_ms: 0, enrich_per_key: 3, ..Default::default() };
+        let (handle, tasks) = spawn(&config, Vec::new(), DeepEnricher::default());
+
+        // NETFILTER_DROP storm plus low-severity noise
+        for i in 0..1000 {
+            assert!(handle.submit(drop_event(if i % 2 == 0 { 7 } else { 16 })));
+            tokio::task::yield_now().await;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // Every event is counted, only the first few of the storm are enriched
+        assert_eq!(handle.stats.aggregate.processed.load(Ordering::Relaxed), 1000);
+        assert_eq!(handle.stats.deep_enriched.load(Ordering::Relaxed), 3);
+        assert_eq!(handle.stats.deep_skipped.load(Ordering::Relaxed), 497);
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drainer_batches_and_budget() {
+        let config = PipelineConfig {
+            batch_size: 4,
+            max_events_per_tick: 10,
+            ..Default::default()
+        };
+        let (handle, tasks) = spawn(&config, Vec::new(), DeepEnricher::default());
+        let mut drainer = BatchDrainer::new(&config);
+
+        let mut source = (0..25).map(drop_event);
+        // Budget stops the first pass at 10 events even though more are waiting
+        assert_eq!(drainer.drain(|| source.next(), &handle), 10);
+        assert_eq!(drainer.drain(|| source.next(), &handle), 10);
+        assert_eq!(drainer.drain(|| source.next(), &handle), 5);
+        assert_eq!(drainer.drain(|| source.next(), &handle), 0);
+        assert_eq!(handle.stats.enrich.processed.load(Ordering::Relaxed), 25);
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submit_batch_partial_capacity() {
+        let config = PipelineConfig {
+            reader_capacity: 3,
+            ..Default::default()
+        };
+        // Keep the receiver idle so the channel fills up
+        let (handle, _rx) = test_handle(config.reader_capacity);
+
+        let mut batch: Vec<RawEvent> = (0..5).map(drop_event).collect();
+        assert_eq!(handle.submit_batch(&mut batch), 3);
+        assert!(batch.is_empty());
+        let (processed, dropped) = handle.stats.enrich.snapshot();
+        assert_eq!((processed, dropped), (3, 2));
+    }
+
+    /// Delivered events/sec for different batch sizes
+    ///
+    /// Run with `cargo test --release bench_drain_throughput -- --ignored --nocapture`
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[ignore]
+    async fn bench_drain_throughput() {
+        const EVENTS: usize = 2_000_000;
+
+        for batch_size in [1, 16, 256] {
+            let config = PipelineConfig { batch_size, ..Default::default() };
+            // Room for every event so the numbers measure delivery, not drops
+            let (handle, mut rx) = test_handle(EVENTS);
+            let consumer = tokio::spawn(async move {
+                let mut received = 0;
+                while received < EVENTS && rx.recv().await.is_some() {
+                    received += 1;
+                }
+            });
+
+            let start = Instant::now();
+            tokio::task::spawn_blocking(move || {
+                let mut drainer = BatchDrainer::new(&config);
+                let mut source = (0..EVENTS as u32).map(drop_event);
+                while drainer.drain(|| source.next(), &handle) > 0 {}
+            })
+            .await
+            .unwrap();
+            consumer.await.unwrap();
+            let elapsed = start.elapsed();
+
+            println!(
+                "batch_size={:>4}: {:>12.0} events/sec",
+                batch_size,
+                EVENTS as f64 / elapsed.as_secs_f64()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_drops_instead_of_blocking() {
+        let config = PipelineConfig {
+            reader_capacity: 4,
+            enrich_capacity: 4,
+            ..Default::default()
+        };
+        let (handle, tasks) = spawn(&config, Vec::new(), DeepEnricher::default());
+
+        // Submitting far more than capacity must never block
+        for _ in 0..10_000 {
+            handle.submit(drop_event(2));
+        }
+        let processed = handle.stats.enrich.processed.load(Ordering::Relaxed);
+        let dropped = handle.stats.enrich.dropped.load(Ordering::Relaxed);
+        assert_eq!(processed + dropped, 10_000);
+        assert!(dropped > 0);
+        // Losses are attributed to the ring the events came from
+        assert_eq!(handle.stats.ring_lost[RingKind::Drop.index()].load(Ordering::Relaxed), dropped);
+        assert_eq!(handle.stats.ring_lost[RingKind::Flow.index()].load(Ordering::Relaxed), 0);
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_sink_drops_summaries() {
+        let config = PipelineConfig {
+            sink_capacity: 1,
+            flush_interval_secs: 1,
+            ..Default::default()
+        };
+        // A sink that stalls until the gate is dropped at the end of the test
+        let (gate_tx, gate_rx) = std::sync::mpsc::channel::<()>();
+        let stalled: SinkFn = Box::new(move |_: &Summary| {
+            let _ = gate_rx.recv();
+        });
+        let (handle, tasks) = spawn(&config, vec![stalled], DeepEnricher::default());
+
+        for _ in 0..5 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        }
+        // Flushes keep happening; at most one summary is in the sink and one
+        // queued, the rest are dropped instead of piling up
+        let (processed, dropped) = handle.stats.sink.snapshot();
+        assert!(processed + dropped >= 3);
+        assert!(processed <= 2);
+        assert!(dropped >= 1);
+
+        drop(gate_tx);
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_closing_input_flushes_last_window() {
+        let windows = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = windows.clone();
+        let sink: SinkFn = Box::new(move |summary: &Summary| seen.lock().unwrap().push(summary.events));
+        let config = PipelineConfig { flush_interval_secs: 3600, ..Default::default() };
+        let (handle, tasks) = spawn(&config, vec![sink], DeepEnricher::default());
+
+        let rst = RawEvent::Rst(RstEvent { direction: 1, ..Default::default() });
+        let submitted = tokio::task::spawn_blocking(move || handle.submit_wait(rst) && handle.submit_wait(rst));
+        assert!(submitted.await.unwrap());
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*windows.lock().unwrap(), vec![2]);
+    }
+}
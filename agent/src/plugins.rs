@@ -0,0 +1,259 @@
+//! Enricher and Sink Registry
+//!
+//! Which enrichers run on notable events and which sinks summaries are
+//! exported to is chosen by name in config.yaml:
+//!
+//! ```yaml
+//! pipeline:
+//!   enrichers: [asn, geoip, container, k8s]
+//!   sinks:
+//!     - prometheus
+//!     - type: webhook
+//!       url: https://hooks.example.com/sennet
+//! ```
+//!
+//! Each name maps to a factory in a [`Registry`]. Adding an integration is a
+//! module implementing [`EventEnricher`] or [`EventSink`] plus one
+//! `register_*` call in [`Registry::builtin`]; programs embedding the
+//! library can register their own factories before building.
+//!
+//! [`EventSink`]: crate::pipeline::EventSink
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+use tracing::info;
+
+use crate::asn::AsnDb;
+use crate::config::Config;
+use crate::k8s::SharedK8s;
+use crate::enrich::{
+    AsnEnricher, ContainerEnricher, DeepEnricher, EventEnricher, GeoIpEnricher, K8sEnricher,
+};
+use crate::pipeline::SinkFn;
+use crate::sinks;
+
+/// One `enrichers` or `sinks` entry: a type name plus its options
+///
+/// Written as just the name when there are no options.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginSpec {
+    pub kind: String,
+    pub options: serde_yaml::Mapping,
+}
+
+impl PluginSpec {
+    pub fn named(kind: &str) -> Self {
+        Self {
+            kind: kind.to_string(),
+            options: serde_yaml::Mapping::new(),
+        }
+    }
+
+    /// Options parsed into the plugin's own settings type
+    pub fn options<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_yaml::from_value(serde_yaml::Value::Mapping(self.options.clone()))
+            .with_context(|| format!("Invalid options for '{}'", self.kind))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SpecRepr {
+    Name(String),
+    Full {
+        #[serde(rename = "type")]
+        kind: String,
+        #[serde(flatten)]
+        options: serde_yaml::Mapping,
+    },
+}
+
+impl Serialize for PluginSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.options.is_empty() {
+            SpecRepr::Name(self.kind.clone()).serialize(serializer)
+        } else {
+            SpecRepr::Full { kind: self.kind.clone(), options: self.options.clone() }.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PluginSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match SpecRepr::deserialize(deserializer)? {
+            SpecRepr::Name(kind) => Self::named(&kind),
+            SpecRepr::Full { kind, options } => Self { kind, options },
+        })
+    }
+}
+
+/// What factories may draw on while building
+pub struct PluginContext<'a> {
+    pub config: &'a Config,
+    asn_db: OnceLock<Option<Arc<AsnDb>>>,
+    k8s: Option<SharedK8s>,
+}
+
+impl<'a> PluginContext<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self { config, asn_db: OnceLock::new(), k8s: None }
+    }
+
+    /// Share the daemon's Kubernetes manager with plugins
+    pub fn with_k8s(mut self, k8s: SharedK8s) -> Self {
+        self.k8s = Some(k8s);
+        self
+    }
+
+    /// The daemon's Kubernetes manager, if it runs one
+    pub fn k8s(&self) -> Option<&SharedK8s> {
+        self.k8s.as_ref()
+    }
+
+    /// The ASN database, loaded on first use and shared between plugins
+    pub fn asn_db(&self) -> Option<Arc<AsnDb>> {
+        self.asn_db
+            .get_or_init(|| {
+                AsnDb::load_default(self.config.asn_db_path.as_deref(), &self.config.state_dir).map(Arc::new)
+            })
+            .clone()
+    }
+}
+
+/// Builds an enricher; `None` when it has nothing to work with (e.g. no
+/// ASN database), which is not an error
+pub type EnricherFactory =
+    Box<dyn Fn(&PluginSpec, &PluginContext) -> Result<Option<Box<dyn EventEnricher>>> + Send + Sync>;
+
+/// Builds a sink
+pub type SinkFactory = Box<dyn Fn(&PluginSpec, &PluginContext) -> Result<SinkFn> + Send + Sync>;
+
+/// Enricher and sink factories by type name
+#[derive(Default)]
+pub struct Registry {
+    enrichers: BTreeMap<String, EnricherFactory>,
+    sinks: BTreeMap<String, SinkFactory>,
+}
+
+impl Registry {
+    /// Everything this crate ships
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register_enricher("asn", |_, ctx| {
+            Ok(ctx.asn_db().map(|db| Box::new(AsnEnricher(db)) as Box<dyn EventEnricher>))
+        });
+        registry.register_enricher("geoip", |_, ctx| {
+            Ok(ctx.asn_db().map(|db| Box::new(GeoIpEnricher(db)) as Box<dyn EventEnricher>))
+        });
+        registry.register_enricher("container", |_, _| Ok(Some(Box::new(ContainerEnricher))));
+        registry.register_enricher("k8s", |_, ctx| {
+            Ok(ctx.k8s().map(|k8s| Box::new(K8sEnricher::new(k8s.clone())) as Box<dyn EventEnricher>))
+        });
+        registry.register_sink("prometheus", |_, _| Ok(Box::new(sinks::PrometheusSink::new())));
+        registry.register_sink("otlp", |spec, ctx| {
+            Ok(Box::new(sinks::OtlpSink::new(spec.options()?, ctx.config.otlp_endpoint.as_deref())?))
+        });
+        registry.register_sink("webhook", |spec, _| Ok(Box::new(sinks::WebhookSink::new(spec.options()?)?)));
+        #[cfg(unix)]
+        registry.register_sink("syslog", |spec, _| Ok(Box::new(sinks::SyslogSink::new(spec.options()?)?)));
+        registry.register_sink("exec", |spec, _| Ok(Box::new(sinks::ExecSink::new(spec.options()?)?)));
+        registry
+    }
+
+    /// Add or replace the enricher factory for `kind`
+    pub fn register_enricher(
+        &mut self,
+        kind: &str,
+        factory: impl Fn(&PluginSpec, &PluginContext) -> Result<Option<Box<dyn EventEnricher>>> + Send + Sync + 'static,
+    ) {
+        self.enrichers.insert(kind.to_string(), Box::new(factory));
+    }
+
+    /// Add or replace the sink factory for `kind`
+    pub fn register_sink(
+        &mut self,
+        kind: &str,
+        factory: impl Fn(&PluginSpec, &PluginContext) -> Result<SinkFn> + Send + Sync + 'static,
+    ) {
+        self.sinks.insert(kind.to_string(), Box::new(factory));
+    }
+
+    /// Build the configured enrichers, in order
+    pub fn build_enricher(&self, specs: &[PluginSpec], ctx: &PluginContext) -> Result<DeepEnricher> {
+        let mut enrichers = Vec::with_capacity(specs.len());
+        for spec in specs {
+            let factory = self
+                .enrichers
+                .get(&spec.kind)
+                .with_context(|| unknown("enricher", &spec.kind, self.enrichers.keys()))?;
+            match factory(spec, ctx).with_context(|| format!("Failed to set up enricher '{}'", spec.kind))? {
+                Some(enricher) => enrichers.push(enricher),
+                None => info!("Enricher '{}' has nothing to look up and is skipped", spec.kind),
+            }
+        }
+        Ok(DeepEnricher::with_enrichers(enrichers))
+    }
+
+    /// Build the configured sinks
+    pub fn build_sinks(&self, specs: &[PluginSpec], ctx: &PluginContext) -> Result<Vec<SinkFn>> {
+        specs
+            .iter()
+            .map(|spec| {
+                let factory = self
+                    .sinks
+                    .get(&spec.kind)
+                    .with_context(|| unknown("sink", &spec.kind, self.sinks.keys()))?;
+                factory(spec, ctx).with_context(|| format!("Failed to set up sink '{}'", spec.kind))
+            })
+            .collect()
+    }
+}
+
+fn unknown<'a>(what: &str, kind: &str, known: impl Iterator<Item = &'a String>) -> String {
+    let known: Vec<&str> = known.map(String::as_str).collect();
+    format!("Unknown {} '{}' (available: {})", what, kind, known.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_forms() {
+        let specs: Vec<PluginSpec> =
+            serde_yaml::from_str("- geoip\n- type: webhook\n  url: http://127.0.0.1:9/hook\n  timeout_secs: 2\n").unwrap();
+        assert_eq!(specs[0], PluginSpec::named("geoip"));
+        assert_eq!(specs[1].kind, "webhook");
+        assert_eq!(specs[1].options.len(), 2);
+
+        // Round trips, keeping the short form where there are no options
+        let yaml = serde_yaml::to_string(&specs).unwrap();
+        assert!(yaml.starts_with("- geoip\n"), "{}", yaml);
+        assert_eq!(serde_yaml::from_str::<Vec<PluginSpec>>(&yaml).unwrap(), specs);
+    }
+
+    #[test]
+    fn test_registry_builds_by_name() {
+        let config: Config = serde_yaml::from_str("offline: true\n").unwrap();
+        let ctx = PluginContext::new(&config);
+        let registry = Registry::builtin();
+
+        // Without the daemon's Kubernetes manager, k8s has nothing to look up
+        let enricher = registry
+            .build_enricher(&[PluginSpec::named("container"), PluginSpec::named("k8s")], &ctx)
+            .unwrap();
+        assert_eq!(enricher.names(), ["container"]);
+
+        let specs: Vec<PluginSpec> = serde_yaml::from_str("- prometheus\n- type: webhook\n  url: http://127.0.0.1:9/hook\n").unwrap();
+        let sinks = registry.build_sinks(&specs, &ctx).unwrap();
+        assert_eq!(sinks.iter().map(|s| s.name()).collect::<Vec<_>>(), ["prometheus", "webhook"]);
+
+        let err = registry.build_sinks(&[PluginSpec::named("kafka")], &ctx).err().unwrap();
+        assert!(format!("{:#}", err).contains("Unknown sink 'kafka' (available: exec, otlp, prometheus"), "{:#}", err);
+        let err = registry.build_sinks(&[PluginSpec::named("webhook")], &ctx).err().unwrap();
+        assert!(format!("{:#}", err).contains("Invalid options for 'webhook'"), "{:#}", err);
+    }
+}
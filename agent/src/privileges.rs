@@ -0,0 +1,265 @@
+//! Privileges
+//!
+//! The agent does not need full root. Loading and attaching the eBPF
+//! programs needs:
+//!
+//! - `CAP_BPF`: create maps and load programs (`CAP_SYS_ADMIN` on kernels
+//!   before 5.8, which lack `CAP_BPF`)
+//! - `CAP_PERFMON`: attach the kfree_skb tracepoint and nf_hook_slow fexit
+//! - `CAP_NET_ADMIN`: attach the TC classifier
+//!
+//! granted through file capabilities (`sennet install`) or systemd's
+//! `AmbientCapabilities`. [`check`] reports which are missing before the
+//! load is attempted.
+//!
+//! Once the programs are attached and maps pinned, [`drop_after_load`]
+//! lowers every thread's capabilities to what the running daemon still
+//! uses: `CAP_BPF` for map reads (every bpf() call needs it when
+//! unprivileged BPF is disabled), `CAP_PERFMON` to attach the tracing
+//! probes again after `sennet probes detach`, and `CAP_NET_ADMIN` to
+//! detach the classifier on shutdown. Capabilities are per thread and tokio's workers
+//! already exist at that point, so each thread is signalled to apply the
+//! new set itself, as libpsx does.
+
+use anyhow::Result;
+use std::fs;
+
+pub const CAP_NET_ADMIN: u32 = 12;
+pub const CAP_SYS_ADMIN: u32 = 21;
+pub const CAP_PERFMON: u32 = 38;
+pub const CAP_BPF: u32 = 39;
+
+/// Capability name for messages
+pub fn cap_name(cap: u32) -> &'static str {
+    match cap {
+        CAP_NET_ADMIN => "CAP_NET_ADMIN",
+        CAP_SYS_ADMIN => "CAP_SYS_ADMIN",
+        CAP_PERFMON => "CAP_PERFMON",
+        CAP_BPF => "CAP_BPF",
+        _ => "CAP_?",
+    }
+}
+
+fn bit(cap: u32) -> u64 {
+    1 << cap
+}
+
+fn names(mask: u64) -> Vec<&'static str> {
+    (0..64).filter(|cap| mask & bit(*cap) != 0).map(cap_name).collect()
+}
+
+/// Whether the kernel knows CAP_BPF and CAP_PERFMON (5.8+)
+fn kernel_has_cap_bpf() -> bool {
+    fs::read_to_string("/proc/sys/kernel/cap_last_cap")
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+        .is_some_and(|last| last >= CAP_BPF)
+}
+
+/// A `Cap*` mask from a /proc status file
+fn parse_cap_mask(status: &str, field: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+}
+
+fn effective_caps() -> Option<u64> {
+    parse_cap_mask(&fs::read_to_string("/proc/self/status").ok()?, "CapEff")
+}
+
+/// Capabilities needed to load and attach the programs, given the
+/// effective set; CAP_SYS_ADMIN stands in for CAP_BPF and CAP_PERFMON
+fn missing_for_load(effective: u64, has_cap_bpf: bool) -> Vec<u32> {
+    let admin = effective & bit(CAP_SYS_ADMIN) != 0;
+    let mut required = vec![CAP_NET_ADMIN];
+    if has_cap_bpf {
+        required.extend([CAP_BPF, CAP_PERFMON]);
+    } else {
+        required.push(CAP_SYS_ADMIN);
+    }
+    required
+        .into_iter()
+        .filter(|&cap| effective & bit(cap) == 0)
+        .filter(|&cap| !(admin && (cap == CAP_BPF || cap == CAP_PERFMON)))
+        .collect()
+}
+
+/// Names of capabilities missing for loading the eBPF programs
+pub fn check() -> Vec<&'static str> {
+    match effective_caps() {
+        Some(effective) => missing_for_load(effective, kernel_has_cap_bpf())
+            .into_iter()
+            .map(cap_name)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Set kept after loading; CAP_SYS_ADMIN covers CAP_PERFMON before 5.8
+fn runtime_mask(has_cap_bpf: bool) -> u64 {
+    if has_cap_bpf {
+        bit(CAP_BPF) | bit(CAP_PERFMON) | bit(CAP_NET_ADMIN)
+    } else {
+        bit(CAP_SYS_ADMIN) | bit(CAP_NET_ADMIN)
+    }
+}
+
+/// Lower all threads to the runtime set; returns the names kept
+#[cfg(target_os = "linux")]
+pub fn drop_after_load() -> Result<Vec<&'static str>> {
+    let current = effective_caps().unwrap_or(0);
+    let keep = runtime_mask(kernel_has_cap_bpf()) & current;
+    broadcast::apply_to_all_threads(keep)?;
+    Ok(names(keep))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn drop_after_load() -> Result<Vec<&'static str>> {
+    Ok(Vec::new())
+}
+
+#[cfg(target_os = "linux")]
+mod broadcast {
+    use anyhow::{Context, Result};
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+    #[repr(C)]
+    struct CapHeader {
+        version: u32,
+        pid: i32,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct CapData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    static KEEP: AtomicU64 = AtomicU64::new(0);
+    static APPLIED: AtomicUsize = AtomicUsize::new(0);
+    static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+    /// Set the calling thread's capabilities to KEEP (async-signal-safe)
+    fn apply_to_self() -> bool {
+        let keep = KEEP.load(Ordering::SeqCst);
+        let low = keep as u32;
+        let high = (keep >> 32) as u32;
+        let header = CapHeader { version: LINUX_CAPABILITY_VERSION_3, pid: 0 };
+        let data = [
+            CapData { effective: low, permitted: low, inheritable: 0 },
+            CapData { effective: high, permitted: high, inheritable: 0 },
+        ];
+        // SAFETY: header and data follow the capset(2) v3 layout
+        let ok = unsafe { libc::syscall(libc::SYS_capset, &header, data.as_ptr()) } == 0;
+        // Children (systemctl, setcap) should not inherit anything either
+        // SAFETY: prctl with integer arguments only
+        unsafe { libc::prctl(libc::PR_CAP_AMBIENT, libc::PR_CAP_AMBIENT_CLEAR_ALL, 0, 0, 0) };
+        ok
+    }
+
+    extern "C" fn handler(_signal: libc::c_int) {
+        if apply_to_self() {
+            APPLIED.fetch_add(1, Ordering::SeqCst);
+        } else {
+            FAILED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn thread_ids() -> Result<Vec<i32>> {
+        Ok(std::fs::read_dir("/proc/self/task")
+            .context("Failed to list threads")?
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+            .collect())
+    }
+
+    pub fn apply_to_all_threads(keep: u64) -> Result<()> {
+        KEEP.store(keep, Ordering::SeqCst);
+        let signal = libc::SIGRTMAX() - 1;
+        // SAFETY: installs a handler that only makes raw syscalls and
+        // touches atomics
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as extern "C" fn(libc::c_int) as usize;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                anyhow::bail!("Failed to install signal handler: {}", std::io::Error::last_os_error());
+            }
+        }
+
+        // SAFETY: plain syscalls without pointers
+        let (pid, own) = unsafe { (libc::getpid(), libc::syscall(libc::SYS_gettid) as i32) };
+        let mut done: HashSet<i32> = HashSet::from([own]);
+        let mut signalled = 0;
+        if !apply_to_self() {
+            anyhow::bail!("capset failed: {}", std::io::Error::last_os_error());
+        }
+
+        // Threads may start while we signal; repeat until no new ones appear
+        loop {
+            let new: Vec<i32> = thread_ids()?.into_iter().filter(|tid| !done.contains(tid)).collect();
+            if new.is_empty() {
+                break;
+            }
+            for tid in new {
+                done.insert(tid);
+                // SAFETY: tgkill to a thread of our own process
+                if unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, signal) } == 0 {
+                    signalled += 1;
+                }
+            }
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while APPLIED.load(Ordering::SeqCst) + FAILED.load(Ordering::SeqCst) < signalled {
+                if Instant::now() > deadline {
+                    anyhow::bail!(
+                        "{} of {} threads did not drop privileges",
+                        signalled - APPLIED.load(Ordering::SeqCst),
+                        signalled
+                    );
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        let failed = FAILED.load(Ordering::SeqCst);
+        if failed > 0 {
+            anyhow::bail!("capset failed on {} of {} threads", failed, signalled);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_capabilities() {
+        let status = "Name:\tsennet\nCapInh:\t0000000000000000\nCapEff:\t000000c000001000\n";
+        let effective = parse_cap_mask(status, "CapEff").unwrap();
+        assert_eq!(effective, bit(CAP_BPF) | bit(CAP_PERFMON) | bit(CAP_NET_ADMIN));
+        assert!(missing_for_load(effective, true).is_empty());
+
+        // Pre-5.8 kernels need CAP_SYS_ADMIN instead
+        assert_eq!(missing_for_load(effective, false), vec![CAP_SYS_ADMIN]);
+        // CAP_SYS_ADMIN covers CAP_BPF and CAP_PERFMON
+        assert_eq!(missing_for_load(bit(CAP_SYS_ADMIN), true), vec![CAP_NET_ADMIN]);
+    }
+
+    #[test]
+    fn test_runtime_mask_can_reattach_probes() {
+        // `sennet probes attach` attaches tracepoints, kprobes and fexit
+        // programs after the drop, which needs CAP_PERFMON
+        assert_eq!(names(runtime_mask(true)), vec!["CAP_NET_ADMIN", "CAP_PERFMON", "CAP_BPF"]);
+        assert!(missing_for_load(runtime_mask(true), true).is_empty());
+        assert_eq!(names(runtime_mask(false)), vec!["CAP_NET_ADMIN", "CAP_SYS_ADMIN"]);
+    }
+}
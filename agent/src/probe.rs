@@ -0,0 +1,443 @@
+//! Active Connectivity Probe
+//!
+//! `sennet diagnose <SOURCE> <TARGET> --probe` goes past reading
+//! NetworkPolicies: it enters the source pod's network namespace (through
+//! `/proc/<pid>/ns/net` of one of its processes) and really connects to the
+//! target, with TCP to a port or an ICMP echo, while following the agent's
+//! event stream for the drops and resets it causes. The outcome confirms or
+//! refutes the policy verdict, and the drops say which kernel path ate the
+//! packets.
+//!
+//! Only the probing thread switches namespace, so the rest of the process
+//! keeps the host's network. Needs root (CAP_SYS_ADMIN for setns, CAP_NET_RAW
+//! for ICMP) and a process of the source pod on this node.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use crate::control::StreamRecord;
+use crate::ebpf::{drop_reason_str, ipv4_addr};
+use crate::events::RawEvent;
+use crate::k8s::{ConnectivityStatus, PodInfo};
+
+/// How long a connect or echo may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long to keep collecting events after the probe, for drops that the
+/// pipeline is still coalescing
+const SETTLE: Duration = Duration::from_millis(1500);
+
+/// How to probe the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Tcp(u16),
+    Icmp,
+}
+
+impl Method {
+    /// A port for a TCP connect, or `icmp`
+    pub fn parse(value: &str) -> Result<Self> {
+        if value.eq_ignore_ascii_case("icmp") {
+            return Ok(Method::Icmp);
+        }
+        let port = value.strip_suffix("/tcp").unwrap_or(value);
+        Ok(Method::Tcp(port.parse().with_context(|| format!("Invalid probe: {} (a TCP port or icmp)", value))?))
+    }
+}
+
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Method::Tcp(port) => write!(f, "TCP connect to port {}", port),
+            Method::Icmp => f.write_str("ICMP echo"),
+        }
+    }
+}
+
+/// What the probe got back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Connected, or got an echo reply
+    Answered(Duration),
+    /// The target host answered with a reset (port closed, or a REJECT rule)
+    Refused,
+    /// No answer at all
+    TimedOut,
+    /// An ICMP error or a local routing failure
+    Unreachable(String),
+}
+
+impl Outcome {
+    fn reached(&self) -> bool {
+        matches!(self, Outcome::Answered(_) | Outcome::Refused)
+    }
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Answered(rtt) => write!(f, "answered in {:.1}ms", rtt.as_secs_f64() * 1000.0),
+            Outcome::Refused => f.write_str("refused (reset)"),
+            Outcome::TimedOut => write!(f, "no answer within {}s", PROBE_TIMEOUT.as_secs()),
+            Outcome::Unreachable(why) => write!(f, "unreachable: {}", why),
+        }
+    }
+}
+
+/// Drop and reset events caused by the probe
+#[derive(Debug, Default)]
+pub struct Observed {
+    /// Drops of the probe's packets by reason
+    pub drops: BTreeMap<&'static str, u64>,
+    pub resets: u64,
+    /// Netfilter drops during the probe; they carry no addresses, so may be
+    /// someone else's
+    pub netfilter_drops: u64,
+}
+
+impl Observed {
+    /// Count an event if it involves the probe's addresses (and port)
+    fn add(&mut self, raw: &RawEvent, count: u64, source: Option<Ipv4Addr>, target: Ipv4Addr, method: Method) {
+        let involved = |src: u32, dst: u32, sport: u16, dport: u16| {
+            let (src, dst) = (ipv4_addr(src), ipv4_addr(dst));
+            let addrs = (dst == target && source.is_none_or(|s| s == src)) || (src == target && source.is_none_or(|s| s == dst));
+            let port = match method {
+                Method::Tcp(port) => sport == port || dport == port,
+                Method::Icmp => true,
+            };
+            addrs && port
+        };
+        match raw {
+            RawEvent::Drop(e) if e.has_tuple() && involved(e.src_ip, e.dst_ip, e.src_port, e.dst_port) => {
+                *self.drops.entry(drop_reason_str(e.reason)).or_default() += count;
+            }
+            RawEvent::Rst(e) if involved(e.src_ip, e.dst_ip, e.src_port, e.dst_port) => self.resets += count,
+            RawEvent::Netfilter(e) if e.is_drop() => self.netfilter_drops += count,
+            _ => {}
+        }
+    }
+}
+
+/// Whether the probe agrees with the policy verdict, and why
+fn assess(verdict: &ConnectivityStatus, outcome: &Outcome, observed: &Observed) -> (Option<bool>, String) {
+    let dropped = !observed.drops.is_empty() || observed.netfilter_drops > 0;
+    match (verdict, outcome.reached()) {
+        (ConnectivityStatus::Allowed, true) => (Some(true), "Packets reach the target, as the policies allow".to_string()),
+        (ConnectivityStatus::Allowed, false) if dropped => (
+            Some(false),
+            "No policy blocks this traffic, yet the kernel dropped the probe; see the drops above".to_string(),
+        ),
+        (ConnectivityStatus::Allowed, false) => (
+            Some(false),
+            "No policy blocks this traffic, yet the probe got no answer; check the target's listener, routes, or drops on the target's node".to_string(),
+        ),
+        (ConnectivityStatus::Blocked, false) => (Some(true), "The probe was stopped, as the policies predict".to_string()),
+        (ConnectivityStatus::Blocked, true) => (
+            Some(false),
+            "The policies should block this traffic but it got through; the CNI may not enforce NetworkPolicies".to_string(),
+        ),
+        (ConnectivityStatus::Unknown, reached) => (
+            None,
+            if reached { "The target is reachable" } else { "The target is not reachable" }.to_string(),
+        ),
+    }
+}
+
+/// Enter the network namespace of `pid`, for the calling thread only
+#[cfg(target_os = "linux")]
+fn enter_netns(pid: u32) -> Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let path = format!("/proc/{}/ns/net", pid);
+    let ns = std::fs::File::open(&path).with_context(|| format!("Failed to open {}", path))?;
+    // SAFETY: setns(2) on a namespace fd we hold open; only this thread moves
+    if unsafe { libc::setns(ns.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .context("Failed to enter the source pod's network namespace (needs root)");
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enter_netns(_pid: u32) -> Result<()> {
+    anyhow::bail!("Probing from a pod needs Linux network namespaces")
+}
+
+fn connect(target: Ipv4Addr, port: u16) -> Outcome {
+    let start = Instant::now();
+    match TcpStream::connect_timeout(&SocketAddr::from((target, port)), PROBE_TIMEOUT) {
+        Ok(_) => Outcome::Answered(start.elapsed()),
+        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => Outcome::Refused,
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock) => Outcome::TimedOut,
+        Err(e) => Outcome::Unreachable(e.to_string()),
+    }
+}
+
+/// ICMP echo request with a valid checksum
+fn echo_request(id: u16, seq: u16) -> Vec<u8> {
+    let mut packet = vec![8, 0, 0, 0];
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(b"sennet-probe");
+    let sum = checksum(&packet);
+    packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    packet
+}
+
+/// Internet checksum (RFC 1071)
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// What an IPv4 packet read from a raw ICMP socket says about our echo
+fn parse_icmp(packet: &[u8], target: Ipv4Addr, id: u16) -> Option<Outcome> {
+    let header_len = usize::from(packet.first()? & 0x0f) * 4;
+    let src = Ipv4Addr::from(<[u8; 4]>::try_from(packet.get(12..16)?).ok()?);
+    let icmp = packet.get(header_len..)?;
+    match icmp.first()? {
+        // Echo reply from the target to our id
+        0 if src == target && icmp.get(4..6)? == id.to_be_bytes() => Some(Outcome::Answered(Duration::ZERO)),
+        // Destination unreachable, quoting our echo to the target
+        3 => {
+            let quoted = icmp.get(8..)?;
+            let quoted_dst = Ipv4Addr::from(<[u8; 4]>::try_from(quoted.get(16..20)?).ok()?);
+            let quoted_icmp = quoted.get(usize::from(quoted.first()? & 0x0f) * 4..)?;
+            (quoted_dst == target && quoted_icmp.get(4..6)? == id.to_be_bytes())
+                .then(|| Outcome::Unreachable(format!("ICMP unreachable (code {}) from {}", icmp[1], src)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn ping(target: Ipv4Addr) -> Result<Outcome> {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    // SAFETY: plain socket(2) call; the fd is owned below
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::IPPROTO_ICMP) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to open an ICMP socket (needs CAP_NET_RAW)");
+    }
+    // SAFETY: fd is a fresh socket nothing else owns
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let id = std::process::id() as u16;
+    let packet = echo_request(id, 1);
+    // SAFETY: sockaddr_in is plain data; all-zero is a valid value
+    let mut addr: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+    addr.sin_family = libc::AF_INET as libc::sa_family_t;
+    addr.sin_addr.s_addr = u32::from(target).to_be();
+    let start = Instant::now();
+    // SAFETY: packet and addr are valid for the lengths given
+    let sent = unsafe {
+        libc::sendto(
+            fd.as_raw_fd(),
+            packet.as_ptr().cast(),
+            packet.len(),
+            0,
+            (&addr as *const libc::sockaddr_in).cast(),
+            std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+        )
+    };
+    if sent < 0 {
+        return Ok(Outcome::Unreachable(std::io::Error::last_os_error().to_string()));
+    }
+
+    // The raw socket sees every ICMP packet in the namespace; wait for ours
+    let mut buf = [0u8; 1500];
+    while let Some(left) = PROBE_TIMEOUT.checked_sub(start.elapsed()) {
+        let timeout = libc::timeval {
+            tv_sec: left.as_secs() as libc::time_t,
+            tv_usec: left.subsec_micros().max(1) as libc::suseconds_t,
+        };
+        // SAFETY: timeout is a valid timeval of the given size
+        unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                (&timeout as *const libc::timeval).cast(),
+                std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+            );
+        }
+        // SAFETY: buf is valid for writes of its length
+        let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+        if n < 0 {
+            match std::io::Error::last_os_error().raw_os_error() {
+                Some(libc::EINTR) => continue,
+                _ => break,
+            }
+        }
+        match parse_icmp(&buf[..n as usize], target, id) {
+            Some(Outcome::Answered(_)) => return Ok(Outcome::Answered(start.elapsed())),
+            Some(outcome) => return Ok(outcome),
+            None => {}
+        }
+    }
+    Ok(Outcome::TimedOut)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn ping(_target: Ipv4Addr) -> Result<Outcome> {
+    anyhow::bail!("ICMP probes need Linux")
+}
+
+/// Probe `target` from inside `source` and compare with the policy verdict
+pub fn run(source: &PodInfo, target: &PodInfo, method: Method, verdict: &ConnectivityStatus) -> Result<()> {
+    let target_ip: Ipv4Addr = target
+        .ip
+        .as_deref()
+        .and_then(|ip| ip.parse().ok())
+        .with_context(|| format!("Target pod '{}' has no IPv4 address", target.name))?;
+    let source_ip: Option<Ipv4Addr> = source.ip.as_deref().and_then(|ip| ip.parse().ok());
+    let pid = crate::watch::container_pids(&source.container_ids)
+        .into_iter()
+        .find(|&pid| crate::watch::has_own_netns(pid))
+        .with_context(|| {
+            format!(
+                "No process of pod '{}' in its own network namespace on this node; run the probe on node {}",
+                source.name,
+                if source.node_name.is_empty() { "?" } else { &source.node_name }
+            )
+        })?;
+
+    println!();
+    println!("{}", "Active Probe".bold());
+    println!("{}", "─".repeat(60));
+    println!("  {} from {} (pid {}) to {} ({})", method, source.name, pid, target.name, target_ip);
+
+    // Subscribe before sending anything, so no drop is missed
+    let mut events = crate::control::Client::connect().and_then(|client| client.events().ok());
+    if events.is_none() {
+        println!("  {}", "The agent is not running here: drops caused by the probe are not shown".dimmed());
+    }
+
+    let outcome = std::thread::Builder::new()
+        .name("sennet-probe".into())
+        .spawn(move || -> Result<Outcome> {
+            enter_netns(pid)?;
+            match method {
+                Method::Tcp(port) => Ok(connect(target_ip, port)),
+                Method::Icmp => ping(target_ip),
+            }
+        })
+        .context("Failed to start the probe")?
+        .join()
+        .map_err(|_| anyhow::anyhow!("The probe thread panicked"))??;
+
+    let mut observed = Observed::default();
+    if let Some(stream) = events.as_mut() {
+        let settle = Instant::now();
+        while settle.elapsed() < SETTLE {
+            let Ok(records) = stream.poll(Duration::from_millis(250)) else { break };
+            for record in records {
+                if let StreamRecord::Event { raw, count, .. } = record {
+                    observed.add(&raw, count, source_ip, target_ip, method);
+                }
+            }
+        }
+    }
+
+    let colored_outcome = if outcome.reached() { outcome.to_string().green() } else { outcome.to_string().red() };
+    println!("  Result: {}", colored_outcome);
+    for (reason, count) in &observed.drops {
+        println!("  Dropped: {} ×{}", reason.red(), count);
+    }
+    if observed.resets > 0 {
+        println!("  Resets: {}", observed.resets);
+    }
+    if observed.netfilter_drops > 0 {
+        println!("  Netfilter drops on this node meanwhile: {}", observed.netfilter_drops);
+    }
+
+    let (agrees, why) = assess(verdict, &outcome, &observed);
+    let label = match agrees {
+        Some(true) => "CONFIRMED".green().bold(),
+        Some(false) => "CONTRADICTED".red().bold(),
+        None => "PROBED".cyan().bold(),
+    };
+    println!("  {}: {}", label, why);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{DropEvent, NetfilterEvent};
+
+    #[test]
+    fn test_echo_request_checksum() {
+        let packet = echo_request(0x1234, 1);
+        assert_eq!(packet[0], 8);
+        // Summing a packet with its checksum gives zero
+        assert_eq!(checksum(&packet), 0);
+    }
+
+    #[test]
+    fn test_parse_icmp() {
+        let target = Ipv4Addr::new(10, 244, 2, 9);
+        let ip_header = |src: [u8; 4], dst: [u8; 4]| {
+            let mut header = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, 1, 0, 0];
+            header.extend(src);
+            header.extend(dst);
+            header
+        };
+
+        let mut reply = ip_header([10, 244, 2, 9], [10, 244, 1, 5]);
+        reply.extend([0, 0, 0, 0, 0x12, 0x34, 0, 1]);
+        assert_eq!(parse_icmp(&reply, target, 0x1234), Some(Outcome::Answered(Duration::ZERO)));
+        assert_eq!(parse_icmp(&reply, target, 0x9999), None);
+
+        // Host unreachable from a router, quoting our echo
+        let mut unreachable = ip_header([10, 244, 0, 1], [10, 244, 1, 5]);
+        unreachable.extend([3, 1, 0, 0, 0, 0, 0, 0]);
+        unreachable.extend(ip_header([10, 244, 1, 5], [10, 244, 2, 9]));
+        unreachable.extend(echo_request(0x1234, 1));
+        assert!(matches!(parse_icmp(&unreachable, target, 0x1234), Some(Outcome::Unreachable(why)) if why.contains("code 1")));
+    }
+
+    #[test]
+    fn test_assess_against_policy_verdict() {
+        let source = Ipv4Addr::new(10, 244, 1, 5);
+        let target = Ipv4Addr::new(10, 244, 2, 9);
+        let mut observed = Observed::default();
+        let drop = |src: Ipv4Addr, dport| {
+            RawEvent::Drop(DropEvent {
+                reason: 7,
+                src_ip: u32::from(src),
+                dst_ip: u32::from(target),
+                src_port: 40000,
+                dst_port: dport,
+                ..Default::default()
+            })
+        };
+        observed.add(&drop(source, 8080), 3, Some(source), target, Method::Tcp(8080));
+        observed.add(&drop(source, 5432), 1, Some(source), target, Method::Tcp(8080));
+        observed.add(&drop(Ipv4Addr::new(10, 0, 0, 1), 8080), 1, Some(source), target, Method::Tcp(8080));
+        observed.add(&RawEvent::Netfilter(NetfilterEvent::default()), 1, Some(source), target, Method::Tcp(8080));
+        assert_eq!(observed.drops.get("NETFILTER_DROP"), Some(&3));
+        assert_eq!(observed.drops.len(), 1);
+        assert_eq!(observed.netfilter_drops, 1);
+
+        let (agrees, why) = assess(&ConnectivityStatus::Allowed, &Outcome::TimedOut, &observed);
+        assert_eq!(agrees, Some(false));
+        assert!(why.contains("dropped"));
+        assert_eq!(assess(&ConnectivityStatus::Blocked, &Outcome::TimedOut, &observed).0, Some(true));
+        assert_eq!(assess(&ConnectivityStatus::Blocked, &Outcome::Refused, &Observed::default()).0, Some(false));
+        assert_eq!(assess(&ConnectivityStatus::Unknown, &Outcome::TimedOut, &observed).0, None);
+
+        assert_eq!(Method::parse("8080").unwrap(), Method::Tcp(8080));
+        assert_eq!(Method::parse("ICMP").unwrap(), Method::Icmp);
+        assert!(Method::parse("http").is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! Probes CLI Command
+//!
+//! Lists the tracing probes of the running agent and attaches or detaches
+//! them through the control socket, without restarting the agent. Detached
+//! probes stay loaded, so attaching them again is cheap; `disabled_probes`
+//! in the config picks the ones detached at startup.
+//! Usage: sennet probes [list|attach|detach] [PROBE]...
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+
+use crate::control::Client;
+use crate::ebpf::{Probe, ProbeState};
+
+/// Print help for the probes command
+pub fn print_help() {
+    println!("{}", "Sennet Probes - Switch Tracing Probes at Runtime".bold());
+    println!("Attach or detach the running agent's tracing programs.");
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet probes [list] [--json]      Show each probe and whether it is attached");
+    println!("    sennet probes attach <PROBE>...    Attach probes");
+    println!("    sennet probes detach <PROBE>...    Detach probes");
+    println!();
+    println!("{}", "PROBES:".yellow());
+    println!("    drops       kfree_skb tracepoint (drop events and reasons)");
+    println!("    netfilter   nf_hook_slow fexit (netfilter verdicts)");
+    println!("    flows       Connection lifecycle and flow byte kprobes");
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sudo sennet probes detach flows    # Stop flow tracking on a busy host");
+    println!("    sudo sennet probes attach flows");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    - Talks to the running agent over the control socket");
+    println!("    - Attaching and detaching requires root or the agent's user");
+    println!("    - Changes last until the agent restarts; see disabled_probes in the config");
+    println!("    - Probes the kernel could not attach at startup cannot be attached later");
+}
+
+/// Run the probes command
+pub fn run(args: &[String]) -> Result<()> {
+    let mut client = Client::connect()
+        .context("The agent is not running, or its control socket is disabled (control_socket in the config)")?;
+
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => ("list", args),
+    };
+    let states = match command {
+        "list" | "--json" => {
+            let states = client.probes()?;
+            if args.iter().any(|a| a == "--json") {
+                println!("{}", serde_json::to_string_pretty(&states)?);
+                return Ok(());
+            }
+            states
+        }
+        "attach" | "detach" => {
+            if rest.is_empty() {
+                anyhow::bail!("sennet probes {} needs at least one probe; see `sennet probes --help`", command);
+            }
+            let probes = rest.iter().map(|name| name.parse::<Probe>()).collect::<Result<Vec<_>>>()?;
+            let mut states = Vec::new();
+            for probe in probes {
+                states = client.set_probe(probe, command == "attach")?;
+            }
+            states
+        }
+        other => anyhow::bail!("Unknown probes command '{}'; see `sennet probes --help`", other),
+    };
+    print_states(&states);
+    Ok(())
+}
+
+fn print_states(states: &[ProbeState]) {
+    println!("{:<12} {}", "PROBE".cyan(), "STATE".cyan());
+    for state in states {
+        let label = match (state.available, state.attached) {
+            (false, _) => "unavailable".dimmed(),
+            (true, true) => "attached".green(),
+            (true, false) => "detached".yellow(),
+        };
+        println!("{:<12} {}", state.probe.name(), label);
+    }
+}
@@ -0,0 +1,218 @@
+//! Kernel-side Rate Limiting
+//!
+//! The eBPF programs run a per-CPU token bucket for each event kind before
+//! reserving ring buffer space. Events over the rate are either dropped in
+//! the kernel or sampled 1 in N and marked with `sample_rate = N`, so
+//! userspace can scale counts back up. Limits are configured here and
+//! written to the TUNABLES map at startup.
+//!
+//! Kernel limits apply per event kind, so a flood of one drop reason still
+//! uses up the budget of all the others. [`ReasonLimiter`] limits records
+//! per drop reason in userspace, for `sennet trace --max-per-reason` and
+//! `pipeline.max_per_reason`, so the rare interesting drop stays visible.
+
+use anyhow::{Context, Result};
+use sennet_common::event_kind;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::Duration;
+
+use crate::ebpf::Tunables;
+
+/// Limits for one event kind
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EventRateLimit {
+    /// Events per second let through unsampled, across all CPUs (0 = unlimited)
+    #[serde(default)]
+    pub per_sec: u32,
+
+    /// Burst size in events (0 = one second's worth)
+    #[serde(default)]
+    pub burst: u32,
+
+    /// Over the rate, emit 1 in N events (0 = drop the excess, max 255)
+    #[serde(default)]
+    pub sample_one_in: u32,
+}
+
+/// `rate_limits:` section of config.yaml
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub drops: EventRateLimit,
+    #[serde(default)]
+    pub netfilter: EventRateLimit,
+    #[serde(default)]
+    pub flows: EventRateLimit,
+    #[serde(default)]
+    pub resets: EventRateLimit,
+    #[serde(default)]
+    pub packets: EventRateLimit,
+    #[serde(default)]
+    pub retransmits: EventRateLimit,
+    #[serde(default)]
+    pub dns: EventRateLimit,
+    #[serde(default)]
+    pub conntrack: EventRateLimit,
+    #[serde(default)]
+    pub payloads: EventRateLimit,
+    #[serde(default)]
+    pub http: EventRateLimit,
+}
+
+impl RateLimitConfig {
+    /// True if any event kind is limited
+    pub fn is_enabled(&self) -> bool {
+        [
+            &self.drops, &self.netfilter, &self.flows, &self.resets, &self.packets, &self.retransmits, &self.dns,
+            &self.conntrack, &self.payloads, &self.http,
+        ]
+        .iter()
+        .any(|l| l.per_sec > 0)
+    }
+
+    /// Convert to the kernel layout
+    ///
+    /// Buckets are per CPU, so host-wide rates are split across `ncpus`
+    /// (rounding up, so a configured limit never becomes 0 = unlimited).
+    pub fn to_tunables(&self, ncpus: usize) -> Tunables {
+        let ncpus = ncpus.max(1) as u32;
+        let per_cpu = |v: u32| if v == 0 { 0 } else { v.div_ceil(ncpus) };
+
+        let mut tunables = Tunables::default();
+        for (i, limit) in [
+            (event_kind::DROP, &self.drops),
+            (event_kind::NETFILTER, &self.netfilter),
+            (event_kind::FLOW, &self.flows),
+            (event_kind::RST, &self.resets),
+            (event_kind::PACKET, &self.packets),
+            (event_kind::RETRANSMIT, &self.retransmits),
+            (event_kind::DNS, &self.dns),
+            (event_kind::CONNTRACK, &self.conntrack),
+            (event_kind::PAYLOAD, &self.payloads),
+            (event_kind::HTTP, &self.http),
+        ] {
+            tunables.rate_per_sec[i] = per_cpu(limit.per_sec);
+            tunables.burst[i] = per_cpu(limit.burst);
+            tunables.sample_one_in[i] = limit.sample_one_in.min(255);
+        }
+        tunables
+    }
+}
+
+/// Token bucket per key (drop reason), allowing `per_sec` records per
+/// second and bursts of one second's worth
+#[derive(Debug, Default)]
+pub struct ReasonLimiter<K = u32> {
+    per_sec: f64,
+    /// Tokens left and when they were last refilled
+    buckets: HashMap<K, (f64, Duration)>,
+    /// Events held back per key, weighted by the count of each record
+    suppressed: BTreeMap<K, u64>,
+}
+
+impl<K: Clone + Hash + Ord> ReasonLimiter<K> {
+    /// `per_sec` of 0 lets everything through
+    pub fn new(per_sec: f64) -> Self {
+        Self { per_sec, buckets: HashMap::new(), suppressed: BTreeMap::new() }
+    }
+
+    /// Whether a record for `key` standing for `count` events may pass at
+    /// `now` (any monotonic offset)
+    pub fn admit(&mut self, key: K, count: u64, now: Duration) -> bool {
+        if self.per_sec <= 0.0 {
+            return true;
+        }
+        let burst = self.per_sec.max(1.0);
+        let (tokens, last) = self.buckets.entry(key.clone()).or_insert((burst, now));
+        *tokens = (*tokens + now.saturating_sub(*last).as_secs_f64() * self.per_sec).min(burst);
+        *last = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            *self.suppressed.entry(key).or_default() += count;
+            false
+        }
+    }
+
+    /// Events held back so far, by key
+    pub fn suppressed(&self) -> &BTreeMap<K, u64> {
+        &self.suppressed
+    }
+}
+
+/// Parse a rate like "20/s", "100/m" or "500/h" into events per second; a
+/// bare number is per second
+pub fn parse_rate(s: &str) -> Result<f64> {
+    let (number, unit) = s.trim().split_once('/').unwrap_or((s.trim(), "s"));
+    let n: f64 = number
+        .parse()
+        .ok()
+        .filter(|n: &f64| n.is_finite() && *n >= 0.0)
+        .with_context(|| format!("Invalid rate: {}", s))?;
+    match unit {
+        "s" => Ok(n),
+        "m" => Ok(n / 60.0),
+        "h" => Ok(n / 3600.0),
+        _ => anyhow::bail!("Invalid rate: {} (use N/s, N/m or N/h)", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_tunables_splits_across_cpus() {
+        let config = RateLimitConfig {
+            drops: EventRateLimit { per_sec: 1000, burst: 0, sample_one_in: 100 },
+            resets: EventRateLimit { per_sec: 3, burst: 10, sample_one_in: 1000 },
+            ..Default::default()
+        };
+        assert!(config.is_enabled());
+
+        let t = config.to_tunables(4);
+        assert_eq!(t.rate_per_sec[event_kind::DROP], 250);
+        assert_eq!(t.sample_one_in[event_kind::DROP], 100);
+        // Rounds up rather than disabling the limit
+        assert_eq!(t.rate_per_sec[event_kind::RST], 1);
+        assert_eq!(t.burst[event_kind::RST], 3);
+        assert_eq!(t.sample_one_in[event_kind::RST], 255);
+        // Unconfigured kinds stay unlimited
+        assert_eq!(t.rate_per_sec[event_kind::FLOW], 0);
+    }
+
+    #[test]
+    fn test_reason_limiter() {
+        let mut limiter = ReasonLimiter::new(2.0);
+        let t = Duration::from_secs(100);
+        // A burst of one second's worth, per reason
+        assert!(limiter.admit(1, 1, t));
+        assert!(limiter.admit(1, 1, t));
+        assert!(!limiter.admit(1, 5, t));
+        assert!(limiter.admit(74, 1, t));
+        // Refills at the rate
+        assert!(limiter.admit(1, 1, t + Duration::from_millis(500)));
+        assert!(!limiter.admit(1, 1, t + Duration::from_millis(600)));
+        assert_eq!(limiter.suppressed().get(&1), Some(&6));
+        assert_eq!(limiter.suppressed().get(&74), None);
+
+        let mut unlimited = ReasonLimiter::new(0.0);
+        assert!((0..1000).all(|_| unlimited.admit(1, 1, t)));
+
+        assert_eq!(parse_rate("20/s").unwrap(), 20.0);
+        assert_eq!(parse_rate("5").unwrap(), 5.0);
+        assert_eq!(parse_rate("120/m").unwrap(), 2.0);
+        assert!(parse_rate("20/d").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let config = RateLimitConfig::default();
+        assert!(!config.is_enabled());
+        assert_eq!(config.to_tunables(8), Tunables::default());
+    }
+}
@@ -0,0 +1,188 @@
+//! Remote Agent Client
+//!
+//! Reads another agent's REST API (`api_listen`, see [`crate::api`]) for
+//! `sennet top --remote`, so an operator can watch a headless node from a
+//! workstation without SSH or root there. The token is the remote agent's
+//! `api_token`, given with `--token` or SENNET_API_TOKEN.
+
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use std::io::{BufRead, BufReader};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+use crate::control::{DaemonStatus, StreamRecord};
+use crate::ebpf::PacketCounters;
+
+/// Timeout of a single API request (not the event stream)
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Stream records buffered before the reader stops reading from the socket
+const EVENT_BUFFER: usize = 4096;
+
+/// Another agent's REST API
+pub struct RemoteAgent {
+    base: String,
+    token: Option<String>,
+}
+
+impl RemoteAgent {
+    /// `target` is host:port or an http(s):// URL
+    pub fn new(target: &str, token: Option<String>) -> Self {
+        let target = target.trim_end_matches('/');
+        let base = if target.contains("://") {
+            target.to_string()
+        } else {
+            format!("http://{}", target)
+        };
+        Self { base, token }
+    }
+
+    fn request(&self, path: &str) -> ureq::Request {
+        let request = ureq::get(&format!("{}{}", self.base, path));
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let response = self.request(path).timeout(REQUEST_TIMEOUT).call().map_err(describe)?;
+        response
+            .into_json()
+            .with_context(|| format!("Invalid response from {}{}", self.base, path))
+    }
+
+    pub fn status(&self) -> Result<DaemonStatus> {
+        self.get("/api/v1/status")
+    }
+
+    pub fn counters(&self) -> Result<PacketCounters> {
+        self.get("/api/v1/counters")
+    }
+
+    /// Follow the event stream (`kinds` by short name, empty = all) on a
+    /// background thread
+    pub fn events(&self, kinds: &[&str]) -> Result<RemoteEvents> {
+        let path = format!("/api/v1/events?follow=1&kinds={}", kinds.join(","));
+        let response = self.request(&path).call().map_err(describe)?;
+        let (tx, rx) = mpsc::sync_channel(EVENT_BUFFER);
+        std::thread::Builder::new()
+            .name("sennet-remote".into())
+            .spawn(move || {
+                // Server-Sent Events: a `data:` line per record, and comments
+                for line in BufReader::new(response.into_reader()).lines() {
+                    let Ok(line) = line else { break };
+                    let Some(data) = line.strip_prefix("data: ") else { continue };
+                    if let Ok(record) = serde_json::from_str::<StreamRecord>(data) {
+                        if tx.send(record).is_err() {
+                            break;
+                        }
+                    }
+                }
+            })
+            .context("Failed to start the event stream reader")?;
+        Ok(RemoteEvents { rx })
+    }
+}
+
+/// Explain the failures an operator can do something about
+fn describe(e: ureq::Error) -> anyhow::Error {
+    match e {
+        ureq::Error::Status(401, _) => {
+            anyhow!("The remote agent rejected the token (pass its api_token with --token or SENNET_API_TOKEN)")
+        }
+        ureq::Error::Status(code, response) => {
+            let body = response.into_string().unwrap_or_default();
+            anyhow!("The remote agent answered {}: {}", code, body.trim())
+        }
+        e => anyhow::Error::new(e).context("Failed to reach the remote agent (is api_listen set there?)"),
+    }
+}
+
+/// Records streamed by a remote agent
+pub struct RemoteEvents {
+    rx: Receiver<StreamRecord>,
+}
+
+impl RemoteEvents {
+    /// Records received since the last poll; fails once the stream has ended
+    pub fn poll(&mut self) -> Result<Vec<StreamRecord>> {
+        let mut records = Vec::new();
+        loop {
+            match self.rx.try_recv() {
+                Ok(record) => records.push(record),
+                Err(TryRecvError::Empty) => return Ok(records),
+                Err(TryRecvError::Disconnected) if records.is_empty() => {
+                    anyhow::bail!("The remote agent closed the event stream")
+                }
+                Err(TryRecvError::Disconnected) => return Ok(records),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::RawEvent;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    /// Answer each connection like the agent's API, requiring token s3cret
+    fn serve(listener: TcpListener) {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { break };
+            let mut head = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                head.extend_from_slice(&buf[..n]);
+            }
+            let head = String::from_utf8_lossy(&head).to_string();
+            let (status, kind, body) = if !head.contains("Authorization: Bearer s3cret") {
+                ("401 Unauthorized", "application/json", r#"{"error":"missing or invalid token"}"#.to_string())
+            } else if head.starts_with("GET /api/v1/status ") {
+                ("200 OK", "application/json", r#"{"version":"9.9.9","agentId":"edge-7","pid":1,"uptimeSecs":5,"interface":"eth0","ebpfAttached":true,"offline":true,"heartbeatAgeSecs":null,"pipeline":true}"#.to_string())
+            } else if head.starts_with("GET /api/v1/events?follow=1&kinds=drop ") {
+                let event = r#"{"type":"event","raw":{"kind":"drop","timestampNs":5,"reason":2,"ifindex":3,"protocol":2048,"sampleRate":0,"srcIp":0,"dstIp":0,"srcPort":0,"dstPort":0},"count":1,"interface":"eth1"}"#;
+                ("200 OK", "text/event-stream", format!(": keepalive\n\ndata: {}\n\n", event))
+            } else {
+                ("404 Not Found", "application/json", r#"{"error":"not found"}"#.to_string())
+            };
+            let response = format!("HTTP/1.0 {}\r\nContent-Type: {}\r\n\r\n{}", status, kind, body);
+            let _ = stream.write_all(response.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_remote_agent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener));
+
+        let agent = RemoteAgent::new(&addr.to_string(), Some("s3cret".into()));
+        assert_eq!(agent.status().unwrap().agent_id, "edge-7");
+
+        let mut events = agent.events(&["drop"]).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut records = Vec::new();
+        while records.is_empty() && Instant::now() < deadline {
+            records = events.poll().unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(matches!(
+            &records[0],
+            StreamRecord::Event { raw, interface: Some(name), .. }
+                if matches!(**raw, RawEvent::Drop(e) if e.reason == 2) && name == "eth1"
+        ));
+
+        let unauthorized = RemoteAgent::new(&format!("http://{}/", addr), Some("wrong".into()));
+        let err = unauthorized.status().unwrap_err();
+        assert!(err.to_string().contains("rejected the token"), "{}", err);
+    }
+}
@@ -0,0 +1,443 @@
+//! Event Recording and Replay
+//!
+//! `sennet record` writes the running agent's event stream to a file and
+//! `sennet replay` feeds it back through `trace`, `top` or the event
+//! pipeline, to reproduce an incident, give a demo or test enrichment
+//! without a live kernel.
+//!
+//! A recording is JSON lines: a [`Header`] carrying the schema version, then
+//! one [`Entry`] per record of the control socket's event stream, stamped
+//! with its offset from the start of the recording. Replays pace entries by
+//! those offsets, scaled by `--speed`. Lines that don't parse, like a last
+//! line cut short when recording was interrupted, are skipped.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::control::StreamRecord;
+
+/// Identifies recording files
+pub const FORMAT: &str = "sennet-events";
+
+/// Version of the recording layout; replays refuse newer ones
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// First line of a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Header {
+    pub format: String,
+    pub version: u32,
+    /// Version of the agent that streamed the events
+    #[serde(default)]
+    pub agent_version: String,
+    #[serde(default)]
+    pub agent_id: String,
+    /// RFC 3339 time the recording started
+    pub started: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// One recorded stream record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Milliseconds since the recording started
+    pub at: u64,
+    pub record: StreamRecord,
+}
+
+/// Write `value` as one JSON line
+fn write_line<W: Write, T: Serialize>(out: &mut W, value: &T) -> Result<()> {
+    serde_json::to_writer(&mut *out, value)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+/// A replayed record and when it happened
+#[derive(Debug, Clone)]
+pub struct Replayed {
+    pub record: StreamRecord,
+    /// Offset from the start of the recording
+    pub offset: Duration,
+    /// Wall-clock time it was recorded at
+    pub time: SystemTime,
+}
+
+/// Reads a recording back, paced like the original stream
+pub struct Replay<R> {
+    lines: Lines<R>,
+    pub header: Header,
+    started: SystemTime,
+    /// Playback speed factor; 0 = as fast as possible
+    speed: f64,
+    clock: Instant,
+    next: Option<Entry>,
+    exhausted: bool,
+}
+
+/// Replay of a recording file
+pub type FileReplay = Replay<BufReader<File>>;
+
+impl FileReplay {
+    pub fn open(path: &Path, speed: f64) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        Replay::new(BufReader::new(file), speed).with_context(|| format!("Failed to read {}", path.display()))
+    }
+}
+
+impl<R: BufRead> Replay<R> {
+    /// Check the header; playback starts now
+    pub fn new(reader: R, speed: f64) -> Result<Self> {
+        let mut lines = reader.lines();
+        let first = lines.next().transpose()?.context("The recording is empty")?;
+        let header: Header = serde_json::from_str(&first)
+            .ok()
+            .filter(|header: &Header| header.format == FORMAT)
+            .context("Not a sennet event recording")?;
+        if header.version > SCHEMA_VERSION {
+            anyhow::bail!(
+                "The recording uses schema v{}, this sennet reads up to v{}; upgrade sennet to replay it",
+                header.version,
+                SCHEMA_VERSION
+            );
+        }
+        let started = chrono::DateTime::parse_from_rfc3339(&header.started)
+            .map(SystemTime::from)
+            .unwrap_or(UNIX_EPOCH);
+        Ok(Self { lines, header, started, speed, clock: Instant::now(), next: None, exhausted: false })
+    }
+
+    /// The next entry, reading past lines that don't parse
+    fn peek(&mut self) -> Option<&Entry> {
+        while self.next.is_none() && !self.exhausted {
+            match self.lines.next() {
+                Some(Ok(line)) => self.next = serde_json::from_str(&line).ok(),
+                Some(Err(_)) | None => self.exhausted = true,
+            }
+        }
+        self.next.as_ref()
+    }
+
+    /// When the entry at `at` ms is due
+    fn due(&self, at: u64) -> Instant {
+        if self.speed > 0.0 {
+            self.clock + Duration::from_secs_f64(at as f64 / 1000.0 / self.speed)
+        } else {
+            self.clock
+        }
+    }
+
+    /// Records that are due, waiting up to `timeout` for the first one;
+    /// empty once the recording is over
+    pub fn poll(&mut self, timeout: Duration) -> Vec<Replayed> {
+        let deadline = Instant::now() + timeout;
+        let mut due = Vec::new();
+        while let Some(at) = self.peek().map(|entry| entry.at) {
+            let wait = self.due(at).saturating_duration_since(Instant::now());
+            if !wait.is_zero() {
+                if !due.is_empty() {
+                    break;
+                }
+                let left = deadline.saturating_duration_since(Instant::now());
+                std::thread::sleep(wait.min(left));
+                if wait > left {
+                    break;
+                }
+            }
+            if let Some(entry) = self.next.take() {
+                due.push(self.replayed(entry));
+            }
+        }
+        due
+    }
+
+    /// Whether every record has been replayed
+    pub fn finished(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn replayed(&self, entry: Entry) -> Replayed {
+        let offset = Duration::from_millis(entry.at);
+        let recorded = match &entry.record {
+            StreamRecord::Event { time: Some(time), .. } => chrono::DateTime::parse_from_rfc3339(time).ok(),
+            _ => None,
+        };
+        Replayed {
+            time: recorded.map(SystemTime::from).unwrap_or(self.started + offset),
+            record: entry.record,
+            offset,
+        }
+    }
+}
+
+/// Options for `sennet record`
+#[derive(Debug, Default)]
+struct RecordOptions {
+    out: PathBuf,
+    duration: Option<Duration>,
+    count: Option<u64>,
+}
+
+impl RecordOptions {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut out = None;
+        let mut options = RecordOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().with_context(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--out" | "-o" => out = Some(PathBuf::from(value()?)),
+                "--duration" | "-d" => {
+                    let secs: u64 = value()?.parse().context("--duration takes seconds")?;
+                    options.duration = Some(Duration::from_secs(secs));
+                }
+                "--count" | "-c" => options.count = Some(value()?.parse().context("--count takes a number")?),
+                other => anyhow::bail!("Unknown option: {}", other),
+            }
+        }
+        options.out = out.context("--out <FILE> is required")?;
+        Ok(options)
+    }
+}
+
+/// Run `sennet record`: save the running agent's event stream
+pub fn record(args: &[String]) -> Result<()> {
+    let options = RecordOptions::parse(args)?;
+    let mut client = crate::control::Client::connect()
+        .context("Agent control socket not available; is the agent running?")?;
+    let status = client.status()?;
+    let mut events = client.events()?;
+
+    let file = File::create(&options.out).with_context(|| format!("Failed to create {}", options.out.display()))?;
+    let mut out = BufWriter::new(file);
+    let header = Header {
+        format: FORMAT.to_string(),
+        version: SCHEMA_VERSION,
+        agent_version: status.version,
+        agent_id: status.agent_id,
+        started: crate::clock::rfc3339(SystemTime::now()),
+        labels: status.labels,
+    };
+    write_line(&mut out, &header)?;
+    out.flush()?;
+
+    println!("Recording events to {}", options.out.display().to_string().cyan());
+    println!("Press {} to stop.", "Ctrl+C".bold());
+
+    let start = Instant::now();
+    let (mut recorded, mut lost) = (0u64, 0u64);
+    'record: while options.duration.is_none_or(|d| start.elapsed() < d) {
+        let records = match events.poll(Duration::from_millis(100)) {
+            Ok(records) => records,
+            Err(e) => {
+                println!("{}: {}", "Stopped".yellow(), e);
+                break;
+            }
+        };
+        let at = start.elapsed().as_millis() as u64;
+        for record in records {
+            if options.count.is_some_and(|n| recorded >= n) {
+                break 'record;
+            }
+            match &record {
+                StreamRecord::Event { .. } => recorded += 1,
+                StreamRecord::Gap { lost: n, .. } => lost += n,
+            }
+            write_line(&mut out, &Entry { at, record })?;
+        }
+        // Interrupting loses at most the last poll
+        out.flush()?;
+        if options.count.is_some_and(|n| recorded >= n) {
+            break;
+        }
+    }
+    out.flush()?;
+
+    println!("Recorded {} events in {:.1}s", recorded, start.elapsed().as_secs_f64());
+    if lost > 0 {
+        println!("{}: {} events were lost while recording", "Warning".yellow(), lost);
+    }
+    Ok(())
+}
+
+/// Where `sennet replay` sends the recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Trace,
+    Top,
+    Summary,
+}
+
+/// Run `sennet replay`
+pub async fn replay(args: &[String]) -> Result<()> {
+    let mut path = None;
+    let mut speed = 1.0;
+    let mut view = View::Trace;
+    // Everything else is for trace (filters, --count, -T)
+    let mut trace_args = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => {
+                speed = args.next().and_then(|s| s.parse().ok()).context("--speed takes a factor, like 2 or 0.5")?;
+            }
+            "--fast" => speed = 0.0,
+            "--top" => view = View::Top,
+            "--summary" => view = View::Summary,
+            file if path.is_none() && !file.starts_with('-') => path = Some(PathBuf::from(file)),
+            _ => trace_args.push(arg.clone()),
+        }
+    }
+    let path = path.context("Usage: sennet replay <FILE> [OPTIONS]")?;
+    let replay = FileReplay::open(&path, speed)?;
+
+    match view {
+        #[cfg(target_os = "linux")]
+        View::Trace => crate::trace::replay(&trace_args, replay),
+        #[cfg(target_os = "linux")]
+        View::Top => crate::tui::replay(replay),
+        #[cfg(not(target_os = "linux"))]
+        View::Trace | View::Top => anyhow::bail!("Replaying into trace and top needs Linux; use --summary"),
+        View::Summary => replay_summary(replay).await,
+    }
+}
+
+/// Feed the recorded events through the event pipeline and print each
+/// window's summary as JSON, as the daemon exports it
+async fn replay_summary(mut replay: FileReplay) -> Result<()> {
+    let config = crate::config::Config::load().ok();
+    let pipeline = config.as_ref().map(|c| c.pipeline.clone()).unwrap_or_default();
+    let asn_db = config
+        .as_ref()
+        .and_then(|c| crate::asn::AsnDb::load_default(c.asn_db_path.as_deref(), &c.state_dir));
+    let enricher = crate::enrich::DeepEnricher::new(asn_db.map(std::sync::Arc::new));
+
+    let print: crate::pipeline::SinkFn = Box::new(|summary: &crate::pipeline::Summary| {
+        if !summary.is_empty() {
+            if let Ok(line) = serde_json::to_string(summary) {
+                println!("{}", line);
+            }
+        }
+    });
+    let (handle, tasks) = crate::pipeline::spawn(&pipeline, vec![print], enricher);
+
+    let feed = tokio::task::spawn_blocking(move || {
+        while !replay.finished() {
+            for replayed in replay.poll(Duration::from_millis(100)) {
+                let StreamRecord::Event { raw, count, .. } = replayed.record else {
+                    continue;
+                };
+                // Undo coalescing: the pipeline sees the events the kernel sent
+                for _ in 0..(count / raw.sample_weight()).max(1) {
+                    if !handle.submit_wait(*raw) {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    feed.await?;
+    // Closing the input flushes the last window
+    for task in tasks {
+        task.await?;
+    }
+    Ok(())
+}
+
+/// Print record and replay help
+pub fn print_help() {
+    println!("{}", "sennet record / replay - Save and replay the event stream".bold());
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet record --out <FILE> [OPTIONS]");
+    println!("    sennet replay <FILE> [OPTIONS]");
+    println!();
+    println!("{}", "RECORD OPTIONS:".yellow());
+    println!("    {}    File to write", "-o, --out <FILE>".cyan());
+    println!("    {}  Stop after S seconds (default: until Ctrl+C)", "-d, --duration <S>".cyan());
+    println!("    {}   Stop after N events", "-c, --count <N>".cyan());
+    println!();
+    println!("{}", "REPLAY OPTIONS:".yellow());
+    println!("    {}     Playback speed factor (default: 1)", "--speed <X>".cyan());
+    println!("    {}          Replay without pauses", "--fast".cyan());
+    println!("    {}           Show the events in the top dashboard", "--top".cyan());
+    println!("    {}       Run the events through the pipeline; print summaries", "--summary".cyan());
+    println!("    Other options are passed to trace (--dst, --src, --proto, --count, -T)");
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sennet record -o incident.sennet -d 300  # Record 5 minutes");
+    println!("    sennet replay incident.sennet -T         # Trace it with wall-clock times");
+    println!("    sennet replay incident.sennet --fast --summary");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    Recording reads the running agent's event stream (sennet group or root).");
+    println!("    Interface names are as the recording agent resolved them; process and");
+    println!("    firewall rule attribution needs live state and is skipped in replays.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{DropEvent, RawEvent};
+
+    fn recording(entries: &[(u64, u32)]) -> Vec<u8> {
+        let header = Header {
+            format: FORMAT.to_string(),
+            version: SCHEMA_VERSION,
+            agent_version: "1.0.0".into(),
+            agent_id: "agent-1".into(),
+            started: "2026-01-01T00:00:00Z".into(),
+            labels: BTreeMap::new(),
+        };
+        let mut out = Vec::new();
+        write_line(&mut out, &header).unwrap();
+        for &(at, reason) in entries {
+            let raw = RawEvent::Drop(DropEvent { reason, ..Default::default() });
+            let record = StreamRecord::Event { raw: Box::new(raw), count: 1, interface: None, time: None };
+            write_line(&mut out, &Entry { at, record }).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_replay_round_trip() {
+        let mut data = recording(&[(0, 2), (1500, 7)]);
+        // Cut short by Ctrl+C
+        data.extend_from_slice(br#"{"at":1600,"record":{"ty"#);
+
+        let mut replay = Replay::new(data.as_slice(), 0.0).unwrap();
+        assert_eq!(replay.header.agent_id, "agent-1");
+        let replayed = replay.poll(Duration::ZERO);
+        assert_eq!(replayed.len(), 2);
+        assert!(matches!(&replayed[1].record, StreamRecord::Event { raw, .. } if matches!(**raw, RawEvent::Drop(e) if e.reason == 7)));
+        assert_eq!(replayed[1].offset, Duration::from_millis(1500));
+        assert_eq!(crate::clock::rfc3339(replayed[1].time), "2026-01-01T00:00:01.500000000Z");
+        assert!(replay.finished());
+    }
+
+    #[test]
+    fn test_replay_paces_entries() {
+        let data = recording(&[(0, 2), (60_000, 7)]);
+        let mut replay = Replay::new(data.as_slice(), 1.0).unwrap();
+        assert_eq!(replay.poll(Duration::from_millis(10)).len(), 1);
+        // A minute away at normal speed
+        assert!(replay.poll(Duration::from_millis(10)).is_empty());
+        assert!(!replay.finished());
+    }
+
+    #[test]
+    fn test_replay_rejects_other_files() {
+        let newer = br#"{"format":"sennet-events","version":99,"started":"2026-01-01T00:00:00Z"}"#;
+        let err = Replay::new(&newer[..], 1.0).err().unwrap();
+        assert!(err.to_string().contains("schema v99"));
+        assert!(Replay::new(&b"{}\n"[..], 1.0).is_err());
+        assert!(Replay::new(&b""[..], 1.0).is_err());
+    }
+}
@@ -0,0 +1,550 @@
+//! TCP RST Cause Analysis
+//!
+//! Watches TCP RST segments captured at the TC hook and correlates them
+//! with kernel drop reasons and listening-socket state to explain why a
+//! connection was reset.
+//! Usage: sennet resets [OPTIONS]
+//!
+//! Classification:
+//! - Outbound RST next to a NETFILTER_DROP: a REJECT rule answered with a reset
+//! - Outbound RST next to a NO_SOCKET drop: nothing listening on the port
+//! - Outbound RST otherwise: a local application aborted the connection
+//! - Inbound RST next to a NO_SOCKET drop (and no TCP_RESET): the peer reset
+//!   a connection the local stack had already closed
+//! - Inbound RST otherwise: the peer (or a middlebox on the path) aborted the connection
+
+use anyhow::Result;
+use colored::Colorize;
+use sennet_common::{drop_reason, tcp_flag};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use crate::ebpf::{format_ip, RstEvent};
+#[cfg(target_os = "linux")]
+use crate::control::StreamRecord;
+#[cfg(target_os = "linux")]
+use crate::ebpf::DropEvent;
+#[cfg(target_os = "linux")]
+use crate::events::{RawEvent, RingKind};
+
+/// Maximum distance between an RST and a correlated drop.
+/// REJECT and no-socket resets are generated synchronously in the same
+/// softirq as the drop, so the window can be tight.
+const CORRELATION_WINDOW_NS: u64 = 10_000_000;
+
+/// How long drop events are kept for correlation
+const DROP_HISTORY_NS: u64 = 2_000_000_000;
+
+/// Why a connection was reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RstCause {
+    /// Local stack answered a segment for a port with no socket
+    NoListener,
+    /// A netfilter REJECT rule sent the reset
+    FirewallReset,
+    /// The remote side (or a middlebox) reset the connection
+    PeerAborted,
+    /// A local application aborted the connection (SO_LINGER 0, unread data)
+    LocalAbort,
+    /// The peer reset a connection the local stack had already closed
+    Stale,
+}
+
+impl RstCause {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RstCause::NoListener => "no listener",
+            RstCause::FirewallReset => "firewall reset",
+            RstCause::PeerAborted => "peer aborted",
+            RstCause::LocalAbort => "local abort",
+            RstCause::Stale => "stale reset",
+        }
+    }
+}
+
+/// Correlates RST segments with recent kernel drops
+#[derive(Debug, Default)]
+pub struct RstCorrelator {
+    /// (timestamp_ns, drop reason), oldest first
+    drops: VecDeque<(u64, u32)>,
+    /// Local TCP ports in LISTEN state
+    listening: HashSet<u16>,
+}
+
+impl RstCorrelator {
+    pub fn new(listening: HashSet<u16>) -> Self {
+        Self {
+            drops: VecDeque::new(),
+            listening,
+        }
+    }
+
+    /// Replace the set of listening ports
+    pub fn set_listening(&mut self, listening: HashSet<u16>) {
+        self.listening = listening;
+    }
+
+    /// Record a kernel drop; only reasons relevant to resets are kept
+    pub fn add_drop(&mut self, timestamp_ns: u64, reason: u32) {
+        if !matches!(reason, drop_reason::NO_SOCKET | drop_reason::NETFILTER_DROP | drop_reason::TCP_RESET) {
+            return;
+        }
+        self.drops.push_back((timestamp_ns, reason));
+        while let Some(&(ts, _)) = self.drops.front() {
+            if timestamp_ns.saturating_sub(ts) > DROP_HISTORY_NS {
+                self.drops.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn drop_near(&self, timestamp_ns: u64, reason: u32) -> bool {
+        self.drops
+            .iter()
+            .any(|&(ts, r)| r == reason && ts.abs_diff(timestamp_ns) <= CORRELATION_WINDOW_NS)
+    }
+
+    /// Classify an RST segment
+    pub fn classify(&self, event: &RstEvent) -> RstCause {
+        let outbound = event.direction != 0;
+        let near = |reason| self.drop_near(event.timestamp_ns, reason);
+        let (netfilter, no_socket, tcp_reset) =
+            (near(drop_reason::NETFILTER_DROP), near(drop_reason::NO_SOCKET), near(drop_reason::TCP_RESET));
+        match (outbound, netfilter, no_socket, tcp_reset) {
+            // The kernel logs TCP_RESET when a socket takes an inbound reset,
+            // NO_SOCKET when there was none left to take it
+            (false, _, _, true) => RstCause::PeerAborted,
+            (false, _, true, false) => RstCause::Stale,
+            (false, _, false, false) => RstCause::PeerAborted,
+            (true, true, _, _) => RstCause::FirewallReset,
+            (true, false, true, _) => RstCause::NoListener,
+            (true, false, false, _) if self.unlistened(event) => RstCause::NoListener,
+            (true, false, false, _) => RstCause::LocalAbort,
+        }
+    }
+
+    /// RST+ACK from a port nothing listens on: the stack's answer to a SYN
+    fn unlistened(&self, event: &RstEvent) -> bool {
+        // The RST's source port is the local port the peer tried to reach
+        !self.listening.is_empty() && !self.listening.contains(&event.src_port) && event.tcp_flags & tcp_flag::ACK != 0
+    }
+}
+
+/// Parse listening ports from /proc/net/tcp or /proc/net/tcp6 content
+pub fn parse_listening_ports(content: &str) -> HashSet<u16> {
+    const TCP_LISTEN: &str = "0A";
+
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut cols = line.split_whitespace();
+            let local = cols.nth(1)?;
+            let state = cols.nth(1)?;
+            if state != TCP_LISTEN {
+                return None;
+            }
+            let (_, port) = local.rsplit_once(':')?;
+            u16::from_str_radix(port, 16).ok()
+        })
+        .collect()
+}
+
+/// Read all local TCP ports in LISTEN state
+pub fn read_listening_ports() -> HashSet<u16> {
+    let mut ports = HashSet::new();
+    for path in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        if let Ok(content) = std::fs::read_to_string(path) {
+            ports.extend(parse_listening_ports(&content));
+        }
+    }
+    ports
+}
+
+/// Options for the resets command
+pub struct ResetsOptions {
+    pub timeout_secs: u64,
+    pub count: usize,
+    pub port: Option<u16>,
+}
+
+impl Default for ResetsOptions {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            count: 1000,
+            port: None,
+        }
+    }
+}
+
+/// Parse command line arguments for resets command
+pub fn parse_args(args: &[String]) -> ResetsOptions {
+    let mut opts = ResetsOptions::default();
+    let mut i = 0;
+
+    while i < args.len() {
+        match args[i].as_str() {
+            "--timeout" | "-t" if i + 1 < args.len() => {
+                opts.timeout_secs = args[i + 1].parse().unwrap_or(30);
+                i += 1;
+            }
+            "--count" | "-c" if i + 1 < args.len() => {
+                opts.count = args[i + 1].parse().unwrap_or(1000);
+                i += 1;
+            }
+            "--port" | "-p" if i + 1 < args.len() => {
+                opts.port = args[i + 1].parse().ok();
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    opts
+}
+
+/// Print help for the resets command
+pub fn print_help() {
+    println!("{}", "Sennet Resets - TCP RST Cause Analysis".bold());
+    println!("Capture TCP resets and explain why each connection was reset.");
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet resets [OPTIONS]");
+    println!();
+    println!("{}", "OPTIONS:".yellow());
+    println!("    -t, --timeout <SECS>   Capture duration (default: 30)");
+    println!("    -c, --count <N>        Stop after N resets (default: 1000)");
+    println!("    -p, --port <PORT>      Only resets involving this port");
+    println!("    -h, --help             Show this help message");
+    println!();
+    println!("{}", "CAUSES:".yellow());
+    println!("    no listener      Local port closed; the kernel answered with RST");
+    println!("    firewall reset   A netfilter REJECT --reject-with tcp-reset rule");
+    println!("    peer aborted     The remote side (or a middlebox) reset the connection");
+    println!("    local abort      A local application aborted an open connection");
+    println!("    stale reset      The peer reset a connection already closed locally");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    - Requires the agent to be running (streams its events, or reads");
+    println!("      the pinned eBPF maps as root if its pipeline is disabled)");
+    println!("    - IPv4 only");
+}
+
+/// Aggregated report of observed resets
+#[derive(Debug, Default)]
+pub struct ResetReport {
+    pub by_cause: BTreeMap<RstCause, u64>,
+    /// (cause, local port) -> count
+    pub by_port: BTreeMap<(RstCause, u16), u64>,
+}
+
+impl ResetReport {
+    pub fn add(&mut self, cause: RstCause, event: &RstEvent) {
+        *self.by_cause.entry(cause).or_insert(0) += 1;
+        let local_port = if event.direction == 0 { event.dst_port } else { event.src_port };
+        *self.by_port.entry((cause, local_port)).or_insert(0) += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.by_cause.values().sum()
+    }
+
+    pub fn print(&self) {
+        println!();
+        println!("{}", "Reset Summary".bold());
+        println!("{}", "─".repeat(60));
+        if self.total() == 0 {
+            println!("{}", "No TCP resets observed.".green());
+            return;
+        }
+        for (cause, count) in &self.by_cause {
+            println!("  {:<16} {:>8}", cause.as_str(), count);
+        }
+        println!();
+        println!("{}", "Top local ports:".bold());
+        let mut ports: Vec<_> = self.by_port.iter().collect();
+        ports.sort_by(|a, b| b.1.cmp(a.1));
+        for ((cause, port), count) in ports.into_iter().take(10) {
+            println!("  {:>5}  {:<16} {:>8}", port, cause.as_str(), count);
+        }
+    }
+}
+
+fn cause_colored(cause: RstCause) -> colored::ColoredString {
+    match cause {
+        RstCause::NoListener => cause.as_str().yellow(),
+        RstCause::FirewallReset => cause.as_str().red(),
+        RstCause::PeerAborted => cause.as_str().magenta(),
+        RstCause::LocalAbort => cause.as_str().white(),
+        RstCause::Stale => cause.as_str().dimmed(),
+    }
+}
+
+/// Run the resets command
+pub fn run(args: &[String]) -> Result<()> {
+    let opts = parse_args(args);
+
+    println!("{}", "Sennet Reset Analysis".bold());
+    println!("Capturing TCP resets for {}s...", opts.timeout_secs.to_string().yellow());
+    println!("{}", "─".repeat(80));
+
+    #[cfg(target_os = "linux")]
+    {
+        run_linux(&opts)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        println!("{}: TCP reset capture requires Linux with eBPF support", "Error".red());
+        Ok(())
+    }
+}
+
+/// Where the resets command reads events from
+#[cfg(target_os = "linux")]
+enum ResetSource {
+    /// The running agent's event stream
+    Daemon(crate::control::EventStream),
+    /// The pinned ring buffers, read directly (needs root)
+    Pinned {
+        rst_rb: aya::maps::RingBuf<aya::maps::MapData>,
+        drop_rb: Option<aya::maps::RingBuf<aya::maps::MapData>>,
+        losses: crate::ebpf::LossTracker,
+    },
+}
+
+#[cfg(target_os = "linux")]
+impl ResetSource {
+    /// The running agent's event stream, or else the pinned ring buffers
+    fn open() -> Result<Self> {
+        use crate::ebpf::open_pinned_ringbuf;
+
+        // Prefer the agent's event stream: reading the rings directly takes
+        // the events away from the agent's own pipeline
+        if let Some(stream) = crate::control::Client::connect().and_then(|client| client.events().ok()) {
+            println!("{}", "Streaming events from the running agent".dimmed());
+            return Ok(ResetSource::Daemon(stream));
+        }
+
+        let rst_rb = open_pinned_ringbuf(RingKind::Rst.pin_name()).map_err(|e| {
+            anyhow::anyhow!("{}\nIs the agent running with an eBPF build that supports RST capture?", e)
+        })?;
+        let drop_rb = match open_pinned_ringbuf(RingKind::Drop.pin_name()) {
+            Ok(rb) => Some(rb),
+            Err(e) => {
+                eprintln!("{}: {} (reset causes will be less precise)", "Warning".yellow(), e);
+                None
+            }
+        };
+        Ok(ResetSource::Pinned { rst_rb, drop_rb, losses: crate::ebpf::LossTracker::new() })
+    }
+
+    /// Drops and resets since the last poll, and the resets lost meanwhile
+    fn poll(&mut self) -> Result<(Vec<DropEvent>, Vec<RstEvent>, u64)> {
+        let (mut drops, mut resets, mut lost) = (Vec::new(), Vec::new(), 0);
+        match self {
+            ResetSource::Daemon(stream) => {
+                for record in stream.poll(Duration::from_millis(50))? {
+                    match record {
+                        StreamRecord::Event { raw, .. } => match *raw {
+                            RawEvent::Drop(event) => drops.push(event),
+                            RawEvent::Rst(event) => resets.push(event),
+                            _ => {}
+                        },
+                        StreamRecord::Gap { ring, lost: n } => {
+                            if ring == RingKind::Rst.name() || ring == crate::control::STREAM_GAP {
+                                lost += n;
+                            }
+                        }
+                    }
+                }
+            }
+            ResetSource::Pinned { rst_rb, drop_rb, losses } => {
+                if let Some(rb) = drop_rb {
+                    while let Some(item) = rb.next() {
+                        drops.extend(crate::events::read_event::<DropEvent>(&item));
+                    }
+                }
+                while let Some(item) = rst_rb.next() {
+                    resets.extend(crate::events::read_event::<RstEvent>(&item));
+                }
+                lost = losses.poll()[RingKind::Rst.index()];
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+        Ok((drops, resets, lost))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run_linux(opts: &ResetsOptions) -> Result<()> {
+    use std::time::Instant;
+
+    let mut source = ResetSource::open()?;
+
+    // RSTs are held briefly so drops logged just after them can still correlate
+    const SETTLE: Duration = Duration::from_millis(200);
+
+    let mut correlator = RstCorrelator::new(read_listening_ports());
+    let mut pending: VecDeque<(Instant, RstEvent)> = VecDeque::new();
+    let mut report = ResetReport::default();
+    let mut last_listen_refresh = Instant::now();
+    let start = Instant::now();
+    let timeout = Duration::from_secs(opts.timeout_secs);
+    let mut total_lost = 0;
+
+    println!("{:>8}  {:<16}  {:>21}  {:>21}  DIR", "TIME", "CAUSE", "LOCAL", "REMOTE");
+
+    loop {
+        let mut done = start.elapsed() > timeout || report.total() as usize >= opts.count;
+
+        match source.poll() {
+            Ok((drops, resets, lost)) => {
+                for event in drops {
+                    correlator.add_drop(event.timestamp_ns, event.reason);
+                }
+                for event in resets {
+                    if let Some(port) = opts.port {
+                        if event.src_port != port && event.dst_port != port {
+                            continue;
+                        }
+                    }
+                    pending.push_back((Instant::now(), event));
+                }
+                if lost > 0 {
+                    total_lost += lost;
+                    println!(
+                        "{:>7.2}s  {}",
+                        start.elapsed().as_secs_f64(),
+                        format!("··· gap: {} resets lost ···", lost).yellow()
+                    );
+                }
+            }
+            Err(e) => {
+                println!("{}: {}", "Stopped".yellow(), e);
+                done = true;
+            }
+        }
+
+        if last_listen_refresh.elapsed() > Duration::from_secs(5) {
+            correlator.set_listening(read_listening_ports());
+            last_listen_refresh = Instant::now();
+        }
+
+        while let Some((seen, event)) = pending.front().copied() {
+            if !done && seen.elapsed() < SETTLE {
+                break;
+            }
+            pending.pop_front();
+            let cause = correlator.classify(&event);
+            report.add(cause, &event);
+
+            let (local, remote, dir) = if event.direction == 0 {
+                (
+                    format!("{}:{}", format_ip(event.dst_ip), event.dst_port),
+                    format!("{}:{}", format_ip(event.src_ip), event.src_port),
+                    "IN".blue(),
+                )
+            } else {
+                (
+                    format!("{}:{}", format_ip(event.src_ip), event.src_port),
+                    format!("{}:{}", format_ip(event.dst_ip), event.dst_port),
+                    "OUT".green(),
+                )
+            };
+            println!(
+                "{:>7.2}s  {:<16}  {:>21}  {:>21}  {}",
+                start.elapsed().as_secs_f64(),
+                cause_colored(cause),
+                local,
+                remote,
+                dir
+            );
+        }
+
+        if done {
+            break;
+        }
+    }
+
+    report.print();
+    if total_lost > 0 {
+        println!("{}: {} resets were lost during capture; counts above are incomplete", "Warning".yellow(), total_lost);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rst(direction: u8, src_port: u16, flags: u8, ts: u64) -> RstEvent {
+        RstEvent {
+            timestamp_ns: ts,
+            src_port,
+            dst_port: 50000,
+            direction,
+            tcp_flags: flags,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_inbound_is_peer_aborted() {
+        let c = RstCorrelator::default();
+        assert_eq!(c.classify(&rst(0, 443, 0x04, 1)), RstCause::PeerAborted);
+    }
+
+    #[test]
+    fn test_firewall_reset_correlates_with_netfilter_drop() {
+        let mut c = RstCorrelator::default();
+        c.add_drop(1_000_000_000, drop_reason::NETFILTER_DROP);
+        assert_eq!(c.classify(&rst(1, 22, 0x14, 1_002_000_000)), RstCause::FirewallReset);
+        // Outside the window the drop no longer explains the reset
+        assert_eq!(c.classify(&rst(1, 22, 0x04, 1_500_000_000)), RstCause::LocalAbort);
+    }
+
+    #[test]
+    fn test_no_listener() {
+        let mut c = RstCorrelator::new([22u16, 443].into_iter().collect());
+        // RST+ACK from a closed port
+        assert_eq!(c.classify(&rst(1, 8080, 0x14, 5)), RstCause::NoListener);
+        // NO_SOCKET drop alone is enough
+        c.set_listening(HashSet::new());
+        c.add_drop(100, drop_reason::NO_SOCKET);
+        assert_eq!(c.classify(&rst(1, 8080, 0x14, 120)), RstCause::NoListener);
+    }
+
+    #[test]
+    fn test_inbound_reset_taken_or_stale() {
+        let mut c = RstCorrelator::default();
+        // Nothing left locally to take the reset
+        c.add_drop(100, drop_reason::NO_SOCKET);
+        assert_eq!(c.classify(&rst(0, 443, 0x04, 120)), RstCause::Stale);
+        // A socket took it: TCP_RESET outweighs another connection's NO_SOCKET
+        c.add_drop(110, drop_reason::TCP_RESET);
+        assert_eq!(c.classify(&rst(0, 443, 0x04, 120)), RstCause::PeerAborted);
+        // Outbound resets aren't explained by it
+        assert_eq!(c.classify(&rst(1, 22, 0x04, 120)), RstCause::NoListener);
+    }
+
+    #[test]
+    fn test_irrelevant_drops_ignored() {
+        let mut c = RstCorrelator::default();
+        c.add_drop(100, 4); // TCP_CSUM
+        assert!(c.drops.is_empty());
+    }
+
+    #[test]
+    fn test_parse_listening_ports() {
+        let content = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+   0: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1234 1\n\
+   1: 0100007F:1F90 0100007F:C350 01 00000000:00000000 00:00000000 00000000     0        0 5678 1\n";
+        let ports = parse_listening_ports(content);
+        assert!(ports.contains(&22));
+        assert!(!ports.contains(&8080));
+    }
+}
@@ -0,0 +1,136 @@
+//! TCP Retransmission Rates
+//!
+//! The tcp_retransmit_skb tracepoint reports every segment the local stack
+//! sends again, which is what packet loss on a path looks like from this
+//! host. [`RetransmitRates`] keeps per-destination counts over a sliding
+//! window so `sennet top` and heartbeats can show which remote hosts are
+//! affected, as retransmits per second. The daemon folds pipeline windows
+//! in through [`SharedRetransmits::sink`], so heartbeats only carry rates
+//! while the pipeline is enabled.
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::pipeline::{SinkFn, Summary};
+
+/// Retransmits to one destination over the window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetransmitRate {
+    pub dst: IpAddr,
+    pub count: u64,
+    pub per_sec: f64,
+}
+
+/// Per-destination retransmit counts over a sliding window, kept in
+/// one-second buckets
+#[derive(Debug)]
+pub struct RetransmitRates {
+    window_secs: u64,
+    /// (second, destination → count), oldest first
+    buckets: VecDeque<(u64, HashMap<IpAddr, u64>)>,
+}
+
+impl RetransmitRates {
+    pub fn new(window: Duration) -> Self {
+        Self { window_secs: window.as_secs().max(1), buckets: VecDeque::new() }
+    }
+
+    /// Count `count` retransmits to `dst` at `now` (any monotonic offset)
+    pub fn add(&mut self, dst: IpAddr, count: u64, now: Duration) {
+        let second = now.as_secs();
+        self.expire(second);
+        match self.buckets.back_mut() {
+            Some((last, counts)) if *last >= second => *counts.entry(dst).or_insert(0) += count,
+            _ => self.buckets.push_back((second, HashMap::from([(dst, count)]))),
+        }
+    }
+
+    /// Destinations with the most retransmits in the window ending at `now`
+    pub fn top(&mut self, limit: usize, now: Duration) -> Vec<RetransmitRate> {
+        self.expire(now.as_secs());
+        let mut totals: HashMap<IpAddr, u64> = HashMap::new();
+        for (_, counts) in &self.buckets {
+            for (&dst, &count) in counts {
+                *totals.entry(dst).or_insert(0) += count;
+            }
+        }
+        let mut rates: Vec<RetransmitRate> = totals
+            .into_iter()
+            .map(|(dst, count)| RetransmitRate { dst, count, per_sec: count as f64 / self.window_secs as f64 })
+            .collect();
+        rates.sort_by(|a, b| b.count.cmp(&a.count).then(a.dst.cmp(&b.dst)));
+        rates.truncate(limit);
+        rates
+    }
+
+    fn expire(&mut self, second: u64) {
+        while self.buckets.front().is_some_and(|(first, _)| first + self.window_secs <= second) {
+            self.buckets.pop_front();
+        }
+    }
+}
+
+/// Rates shared between the pipeline sink that fills them and the
+/// heartbeat loop that reports them
+#[derive(Clone)]
+pub struct SharedRetransmits {
+    start: Instant,
+    rates: Arc<Mutex<RetransmitRates>>,
+}
+
+impl SharedRetransmits {
+    pub fn new(window: Duration) -> Self {
+        Self { start: Instant::now(), rates: Arc::new(Mutex::new(RetransmitRates::new(window))) }
+    }
+
+    /// Sink adding each window's retransmits
+    pub fn sink(&self) -> SinkFn {
+        let shared = self.clone();
+        Box::new(move |summary: &Summary| {
+            if summary.retransmits_by_dst.is_empty() {
+                return;
+            }
+            let now = shared.start.elapsed();
+            let mut rates = shared.rates.lock().unwrap_or_else(|e| e.into_inner());
+            for (&dst, &count) in &summary.retransmits_by_dst {
+                rates.add(dst, count, now);
+            }
+        })
+    }
+
+    /// Destinations with the most retransmits right now
+    pub fn top(&self, limit: usize) -> Vec<RetransmitRate> {
+        let now = self.start.elapsed();
+        self.rates.lock().unwrap_or_else(|e| e.into_inner()).top(limit, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_over_window() {
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "2001:db8::1".parse().unwrap();
+        let mut rates = RetransmitRates::new(Duration::from_secs(10));
+
+        rates.add(a, 5, Duration::from_secs(100));
+        rates.add(b, 20, Duration::from_millis(100_500));
+        rates.add(a, 5, Duration::from_secs(105));
+
+        let top = rates.top(10, Duration::from_secs(106));
+        assert_eq!(top[0], RetransmitRate { dst: b, count: 20, per_sec: 2.0 });
+        assert_eq!(top[1], RetransmitRate { dst: a, count: 10, per_sec: 1.0 });
+        assert_eq!(rates.top(1, Duration::from_secs(106)).len(), 1);
+
+        // The first second falls out of the window
+        let top = rates.top(10, Duration::from_secs(110));
+        assert_eq!(top, [RetransmitRate { dst: a, count: 5, per_sec: 0.5 }]);
+        assert!(rates.top(10, Duration::from_secs(200)).is_empty());
+    }
+}
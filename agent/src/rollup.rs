@@ -0,0 +1,409 @@
+//! Flow Rollups
+//!
+//! The kernel FLOWS map keeps one entry per 5-tuple until the LRU evicts
+//! it. On busy servers that keeps the map permanently full. When enabled,
+//! the daemon periodically reads the map, folds the traffic each flow saw
+//! since the last read into a rollup keyed by process and remote endpoint,
+//! and deletes the kernel entries of flows that closed or went idle, so map
+//! pressure stays bounded. Open flows stay in the map: the kernel records
+//! their process only when they are created.
+//!
+//! The most recent window is written to the state directory for
+//! `sennet flows --rollups`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, Instrument};
+
+use crate::ebpf::{comm_to_string, ipv4_addr, FlowInfo, FlowKey};
+use sennet_common::flow_state;
+
+/// File name of the last rollup window inside the state directory
+pub const STATE_FILE: &str = "flow_rollups.json";
+
+/// Distinct rollups kept per window; further flows are only counted
+pub const MAX_ROLLUPS: usize = 4096;
+
+/// Open flows without traffic for this long are deleted from the kernel map
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Process / remote endpoint a flow is attributed to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RollupKey {
+    pub process: String,
+    pub remote: Ipv4Addr,
+    pub remote_port: u16,
+    pub protocol: u8,
+    /// 1 = outbound, 2 = inbound
+    pub direction: u8,
+}
+
+impl RollupKey {
+    pub fn from_flow(key: &FlowKey, info: &FlowInfo) -> Self {
+        // Outbound: remote is the destination; inbound: remote is the source
+        let (remote, remote_port) = if info.direction == 1 {
+            (key.dst_ip, key.dst_port)
+        } else {
+            (key.src_ip, key.src_port)
+        };
+        Self {
+            process: comm_to_string(&info.comm),
+            remote: ipv4_addr(remote),
+            remote_port,
+            protocol: key.protocol,
+            direction: info.direction,
+        }
+    }
+}
+
+/// Flows and traffic aggregated under one key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rollup {
+    #[serde(flatten)]
+    pub key: RollupKey,
+    pub flows: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+}
+
+impl Rollup {
+    pub fn total_bytes(&self) -> u64 {
+        self.rx_bytes + self.tx_bytes
+    }
+}
+
+/// Rollups for one flush window
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RollupWindow {
+    /// Unix seconds
+    pub window_start: u64,
+    pub window_end: u64,
+    /// Sorted by flow count, then bytes, largest first
+    pub rollups: Vec<Rollup>,
+    /// Flows that didn't fit within MAX_ROLLUPS
+    pub overflow_flows: u64,
+    /// Deployment labels of the agent that wrote the window
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl RollupWindow {
+    pub fn total_flows(&self) -> u64 {
+        self.rollups.iter().map(|r| r.flows).sum::<u64>() + self.overflow_flows
+    }
+
+    /// Write the window to the state directory
+    pub fn save(&self, state_dir: &Path) -> Result<()> {
+        let path = state_dir.join(STATE_FILE);
+        let content = serde_json::to_string(self).context("Failed to serialize flow rollups")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write flow rollups: {}", path.display()))
+    }
+
+    /// Read the last window written by the daemon
+    pub fn load(state_dir: &Path) -> Result<Self> {
+        let path: PathBuf = state_dir.join(STATE_FILE);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("No flow rollups at {}", path.display()))?;
+        serde_json::from_str(&content).context("Failed to parse flow rollups")
+    }
+}
+
+/// Accumulates flow traffic until the next flush
+pub struct FlowAggregator {
+    rollups: HashMap<RollupKey, Rollup>,
+    overflow_flows: u64,
+    window_start: SystemTime,
+}
+
+impl Default for FlowAggregator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlowAggregator {
+    pub fn new() -> Self {
+        Self {
+            rollups: HashMap::new(),
+            overflow_flows: 0,
+            window_start: SystemTime::now(),
+        }
+    }
+
+    /// Fold one flow into its rollup
+    pub fn add(&mut self, key: &FlowKey, info: &FlowInfo) {
+        self.add_delta(key, info, true);
+    }
+
+    /// Fold traffic of a flow into its rollup; `new_flow` counts the flow
+    /// itself, which only its first delta should do
+    pub fn add_delta(&mut self, key: &FlowKey, info: &FlowInfo, new_flow: bool) {
+        let rollup_key = RollupKey::from_flow(key, info);
+        let at_capacity = self.rollups.len() >= MAX_ROLLUPS;
+        let rollup = match self.rollups.get_mut(&rollup_key) {
+            Some(rollup) => rollup,
+            None if at_capacity => {
+                self.overflow_flows += 1;
+                return;
+            }
+            None => self.rollups.entry(rollup_key.clone()).or_insert(Rollup {
+                key: rollup_key,
+                flows: 0,
+                rx_bytes: 0,
+                tx_bytes: 0,
+                rx_packets: 0,
+                tx_packets: 0,
+            }),
+        };
+        rollup.flows += u64::from(new_flow);
+        rollup.rx_bytes += info.rx_bytes;
+        rollup.tx_bytes += info.tx_bytes;
+        rollup.rx_packets += info.rx_packets as u64;
+        rollup.tx_packets += info.tx_packets as u64;
+    }
+
+    /// Close the current window and start a new one
+    pub fn flush(&mut self) -> RollupWindow {
+        let now = SystemTime::now();
+        let unix = |t: SystemTime| t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+        let mut rollups: Vec<Rollup> = self.rollups.drain().map(|(_, r)| r).collect();
+        rollups.sort_by(|a, b| {
+            b.flows
+                .cmp(&a.flows)
+                .then(b.total_bytes().cmp(&a.total_bytes()))
+                .then(a.key.process.cmp(&b.key.process))
+        });
+
+        let window = RollupWindow {
+            window_start: unix(self.window_start),
+            window_end: unix(now),
+            rollups,
+            overflow_flows: std::mem::take(&mut self.overflow_flows),
+            labels: crate::labels::get().clone(),
+        };
+        self.window_start = now;
+        window
+    }
+}
+
+/// Counters of a flow at the last read
+struct Seen {
+    info: FlowInfo,
+    /// When its counters last moved
+    changed: Instant,
+}
+
+/// Turns snapshots of the kernel flow map into per-read deltas, and picks
+/// the entries to delete
+#[derive(Default)]
+pub struct FlowTracker {
+    seen: HashMap<FlowKey, Seen>,
+}
+
+impl FlowTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold the traffic since the last read into `aggregator`; returns the
+    /// flows that closed or were idle for `IDLE_TIMEOUT`, to delete
+    pub fn update(
+        &mut self,
+        flows: &[(FlowKey, FlowInfo)],
+        aggregator: &mut FlowAggregator,
+        now: Instant,
+    ) -> Vec<(FlowKey, FlowInfo)> {
+        let mut seen = HashMap::with_capacity(flows.len());
+        let mut expired = Vec::new();
+        for (key, info) in flows {
+            // A new flow, or a new connection reusing the 5-tuple
+            let last = self.seen.remove(key).filter(|last| last.info.start_time_ns == info.start_time_ns);
+            let delta = FlowInfo {
+                rx_bytes: info.rx_bytes.saturating_sub(last.as_ref().map_or(0, |l| l.info.rx_bytes)),
+                tx_bytes: info.tx_bytes.saturating_sub(last.as_ref().map_or(0, |l| l.info.tx_bytes)),
+                rx_packets: info.rx_packets.saturating_sub(last.as_ref().map_or(0, |l| l.info.rx_packets)),
+                tx_packets: info.tx_packets.saturating_sub(last.as_ref().map_or(0, |l| l.info.tx_packets)),
+                ..*info
+            };
+            let moved = delta.rx_packets > 0 || delta.tx_packets > 0 || delta.rx_bytes > 0 || delta.tx_bytes > 0;
+            if last.is_none() || moved {
+                aggregator.add_delta(key, &delta, last.is_none());
+            }
+
+            let changed = match last {
+                Some(last) if !moved => last.changed,
+                _ => now,
+            };
+            if info.state == flow_state::CLOSED || now.duration_since(changed) >= IDLE_TIMEOUT {
+                expired.push((*key, *info));
+            }
+            seen.insert(*key, Seen { info: *info, changed });
+        }
+        // Flows missing from this read were evicted by the kernel LRU
+        self.seen = seen;
+        expired
+    }
+
+    /// Forget deleted flows, so a new connection on the same 5-tuple
+    /// counts from zero
+    pub fn forget(&mut self, removed: &[(FlowKey, FlowInfo)]) {
+        for (key, _) in removed {
+            self.seen.remove(key);
+        }
+    }
+}
+
+/// Periodically fold the kernel flow map into rollups
+pub async fn run(state_dir: PathBuf, interval: Duration) {
+    info!("Starting flow rollups (interval: {:?})", interval);
+    let mut aggregator = FlowAggregator::new();
+    let mut tracker = FlowTracker::new();
+
+    loop {
+        tokio::time::sleep(interval).await;
+        flush(&mut aggregator, &mut tracker, &state_dir)
+            .instrument(tracing::info_span!("rollup.flush"))
+            .await;
+    }
+}
+
+/// Fold the kernel flow map into the aggregator, delete the flows that
+/// closed or went idle, and save the window
+async fn flush(aggregator: &mut FlowAggregator, tracker: &mut FlowTracker, state_dir: &Path) {
+    match tokio::task::spawn_blocking(crate::ebpf::read_pinned_flows).await {
+        Ok(Ok(flows)) => {
+            let expired = tracker.update(&flows, aggregator, Instant::now());
+            match tokio::task::spawn_blocking(move || crate::ebpf::remove_pinned_flows(&expired)).await {
+                Ok(Ok(removed)) => {
+                    crate::flowexport::hand_off(&removed);
+                    tracker.forget(&removed);
+                }
+                Ok(Err(e)) => debug!("Could not delete expired flows: {}", e),
+                Err(e) => debug!("Flow delete task panicked: {}", e),
+            }
+        }
+        Ok(Err(e)) => debug!("Could not read flows: {}", e),
+        Err(e) => debug!("Flow read task panicked: {}", e),
+    }
+
+    let window = aggregator.flush();
+    info!(
+        flows = window.total_flows(),
+        rollups = window.rollups.len(),
+        overflow = window.overflow_flows,
+        "Flow rollup window flushed"
+    );
+    if let Err(e) = window.save(state_dir) {
+        crate::selfmetrics::global().exporter_errors.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        debug!("Could not persist flow rollups: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn flow(comm: &str, remote: [u8; 4], port: u16, direction: u8, tx: u64) -> (FlowKey, FlowInfo) {
+        let local = u32::from_be_bytes([10, 0, 0, 1]);
+        let remote = u32::from_be_bytes(remote);
+        let mut info = FlowInfo { direction, tx_bytes: tx, ..Default::default() };
+        info.comm[..comm.len()].copy_from_slice(comm.as_bytes());
+        let key = if direction == 1 {
+            FlowKey { src_ip: local, dst_ip: remote, src_port: 40000, dst_port: port, protocol: 6, ..Default::default() }
+        } else {
+            FlowKey { src_ip: remote, dst_ip: local, src_port: port, dst_port: 443, protocol: 6, ..Default::default() }
+        };
+        (key, info)
+    }
+
+    #[test]
+    fn test_rollup_by_process_and_destination() {
+        let mut agg = FlowAggregator::new();
+        for (key, info) in [
+            flow("curl", [1, 1, 1, 1], 443, 1, 100),
+            flow("curl", [1, 1, 1, 1], 443, 1, 200),
+            flow("nginx", [1, 1, 1, 1], 443, 1, 50),
+            flow("curl", [8, 8, 8, 8], 53, 1, 10),
+        ] {
+            agg.add(&key, &info);
+        }
+
+        let window = agg.flush();
+        assert_eq!(window.rollups.len(), 3);
+        assert_eq!(window.total_flows(), 4);
+        let top = &window.rollups[0];
+        assert_eq!(top.key.process, "curl");
+        assert_eq!(top.flows, 2);
+        assert_eq!(top.tx_bytes, 300);
+
+        // Flushing starts a fresh window
+        assert_eq!(agg.flush().total_flows(), 0);
+    }
+
+    #[test]
+    fn test_tracker_exports_deltas_and_expires_closed_or_idle() {
+        let mut agg = FlowAggregator::new();
+        let mut tracker = FlowTracker::new();
+        let start = Instant::now();
+        let (key, mut info) = flow("curl", [1, 1, 1, 1], 443, 1, 100);
+        info.start_time_ns = 1;
+        info.state = flow_state::ACTIVE;
+
+        // An open flow is counted once, then only its new bytes
+        assert!(tracker.update(&[(key, info)], &mut agg, start).is_empty());
+        info.tx_bytes = 250;
+        assert!(tracker.update(&[(key, info)], &mut agg, start + Duration::from_secs(60)).is_empty());
+        let window = agg.flush();
+        assert_eq!((window.rollups[0].flows, window.rollups[0].tx_bytes), (1, 250));
+
+        // Idle past the timeout, with nothing new to export
+        let idle = start + Duration::from_secs(60) + IDLE_TIMEOUT;
+        assert_eq!(tracker.update(&[(key, info)], &mut agg, idle), vec![(key, info)]);
+        assert_eq!(agg.flush().total_flows(), 0);
+
+        // Closed flows expire at once; a reused 5-tuple counts from zero
+        let closed = FlowInfo { state: flow_state::CLOSED, start_time_ns: 2, tx_bytes: 10, ..info };
+        assert_eq!(tracker.update(&[(key, closed)], &mut agg, idle).len(), 1);
+        let window = agg.flush();
+        assert_eq!((window.rollups[0].flows, window.rollups[0].tx_bytes), (1, 10));
+    }
+
+    #[test]
+    fn test_rollups_are_bounded() {
+        let mut agg = FlowAggregator::new();
+        for port in 0..(MAX_ROLLUPS as u16 + 10) {
+            let (key, info) = flow("worker", [10, 1, 2, 3], port, 1, 1);
+            agg.add(&key, &info);
+        }
+        let window = agg.flush();
+        assert_eq!(window.rollups.len(), MAX_ROLLUPS);
+        assert_eq!(window.overflow_flows, 10);
+        assert_eq!(window.total_flows(), MAX_ROLLUPS as u64 + 10);
+    }
+
+    #[test]
+    fn test_window_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let mut agg = FlowAggregator::new();
+        let (key, info) = flow("sshd", [192, 168, 1, 5], 51000, 2, 0);
+        agg.add(&key, &info);
+        agg.flush().save(dir.path()).unwrap();
+
+        let loaded = RollupWindow::load(dir.path()).unwrap();
+        assert_eq!(loaded.rollups[0].key.remote, Ipv4Addr::new(192, 168, 1, 5));
+        assert_eq!(loaded.rollups[0].key.remote_port, 51000);
+    }
+}
@@ -0,0 +1,210 @@
+//! Connection RTT CLI Command
+//!
+//! Shows round-trip time percentiles per remote address, from the
+//! RTT_HISTOGRAMS map the tcp_probe tracepoint fills with each TCP
+//! connection's smoothed RTT whenever a segment arrives. Remotes with many
+//! connections or busy ones weigh in with more samples. The map is an LRU,
+//! so remotes idle long enough to be evicted by busier ones drop out.
+//!
+//! With `--icmp` it shows ping round trips instead: the classifiers time
+//! each ICMP echo request this host sends against its reply (ICMP_RTT), so
+//! whatever already pings (health checks, monitoring) is measured
+//! passively.
+//! Usage: sennet latency [OPTIONS]
+
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::ebpf::{EbpfManager, RemoteRtt};
+
+/// Sort field for the RTT table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Samples,
+    P50,
+    P99,
+}
+
+/// Options for the latency command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RttOptions {
+    pub sort_by: SortField,
+    pub limit: usize,
+    pub json: bool,
+    /// ICMP echo round trips instead of TCP smoothed RTT
+    pub icmp: bool,
+}
+
+impl Default for RttOptions {
+    fn default() -> Self {
+        Self {
+            sort_by: SortField::Samples,
+            limit: 20,
+            json: false,
+            icmp: false,
+        }
+    }
+}
+
+/// Print help for the latency command
+pub fn print_help() {
+    println!("{}", "Sennet Latency - TCP Round-Trip Times per Remote".bold());
+    println!("Show smoothed RTT percentiles of the TCP connections to each remote address.");
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet latency [OPTIONS]");
+    println!();
+    println!("{}", "OPTIONS:".yellow());
+    println!("    --sort <FIELD>     Sort by: samples, p50, p99 (default: samples)");
+    println!("    --limit <N>        Show only the top N remotes (default: 20)");
+    println!("    --json             Print JSON instead of a table");
+    println!("    --icmp             Ping round trips of echo requests this host sends");
+    println!("    -h, --help         Show this help message");
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sennet latency                # RTT to the busiest remotes");
+    println!("    sennet latency --sort p99     # Slowest tails first");
+    println!("    sennet latency --icmp         # Latency seen by existing pings");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    - Samples are taken since the agent loaded its eBPF programs");
+    println!("    - Percentiles are estimated from power-of-two buckets");
+    println!("    - --icmp only sees echoes on the monitored interface; nothing is sent");
+    println!("    - Requires the agent's control socket, or root to read the pinned maps");
+}
+
+/// Parse command line arguments for the latency command
+pub fn parse_args(args: &[String]) -> RttOptions {
+    let mut opts = RttOptions::default();
+    let mut i = 0;
+
+    while i < args.len() {
+        match args[i].as_str() {
+            "--sort" if i + 1 < args.len() => {
+                opts.sort_by = match args[i + 1].as_str() {
+                    "p50" => SortField::P50,
+                    "p99" => SortField::P99,
+                    _ => SortField::Samples,
+                };
+                i += 1;
+            }
+            "--limit" if i + 1 < args.len() => {
+                opts.limit = args[i + 1].parse().unwrap_or(20);
+                i += 1;
+            }
+            "--json" => opts.json = true,
+            "--icmp" => opts.icmp = true,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    opts
+}
+
+#[cfg(unix)]
+fn daemon_rtt(icmp: bool) -> Option<Vec<RemoteRtt>> {
+    let mut client = crate::control::Client::connect()?;
+    let rtts = if icmp { client.icmp_rtt() } else { client.rtt() };
+    rtts.ok()
+}
+
+#[cfg(not(unix))]
+fn daemon_rtt(_icmp: bool) -> Option<Vec<RemoteRtt>> {
+    None
+}
+
+/// Run the latency command
+pub fn run(args: &[String]) -> Result<()> {
+    let opts = parse_args(args);
+
+    // Ask the running agent first: loading eBPF here needs root
+    let pinned = if opts.icmp { crate::ebpf::read_pinned_icmp_rtt() } else { crate::ebpf::read_pinned_rtt() };
+    let mut rtts = match daemon_rtt(opts.icmp) {
+        Some(rtts) => rtts,
+        None => match pinned {
+            Ok(rtts) => rtts,
+            Err(_) => {
+                let interface = crate::interface::discover_default_interface(None)?;
+                let manager = EbpfManager::load_and_attach(&interface)?;
+                if opts.icmp { manager.read_icmp_rtt()? } else { manager.read_rtt()? }
+            }
+        },
+    };
+
+    match opts.sort_by {
+        SortField::Samples => {}
+        SortField::P50 => rtts.sort_by(|a, b| b.p50_ms.total_cmp(&a.p50_ms)),
+        SortField::P99 => rtts.sort_by(|a, b| b.p99_ms.total_cmp(&a.p99_ms)),
+    }
+    rtts.truncate(opts.limit);
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&rtts)?);
+        return Ok(());
+    }
+
+    if rtts.is_empty() {
+        let what = if opts.icmp { "No ICMP echo replies timed yet." } else { "No RTT samples recorded yet." };
+        println!("{}", what.yellow());
+        return Ok(());
+    }
+
+    let title = if opts.icmp { "Sennet Latency (ICMP echo)" } else { "Sennet Latency" };
+    println!("{}", title.bold());
+    println!("{}", "═".repeat(90));
+    println!(
+        "{:<39} {:>10} {:>9} {:>9} {:>9} {:>9}",
+        "REMOTE".cyan(),
+        "SAMPLES".cyan(),
+        "MEAN".cyan(),
+        "P50".cyan(),
+        "P90".cyan(),
+        "P99".cyan()
+    );
+    println!("{}", "─".repeat(90));
+
+    for rtt in &rtts {
+        println!(
+            "{:<39} {:>10} {:>9} {:>9} {:>9} {:>9}",
+            rtt.remote.to_string(),
+            rtt.samples,
+            format_ms(rtt.mean_ms),
+            format_ms(rtt.p50_ms),
+            format_ms(rtt.p90_ms),
+            format_ms(rtt.p99_ms),
+        );
+    }
+    Ok(())
+}
+
+/// Milliseconds with precision suited to their size
+fn format_ms(ms: f64) -> String {
+    if ms < 10.0 {
+        format!("{:.2}ms", ms)
+    } else if ms < 1000.0 {
+        format!("{:.1}ms", ms)
+    } else {
+        format!("{:.2}s", ms / 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args: Vec<String> = ["--sort", "p99", "--limit", "5", "--json"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(parse_args(&args), RttOptions { sort_by: SortField::P99, limit: 5, json: true, icmp: false });
+        assert!(parse_args(&["--icmp".to_string()]).icmp);
+        assert_eq!(parse_args(&[]), RttOptions::default());
+    }
+
+    #[test]
+    fn test_format_ms() {
+        assert_eq!(format_ms(0.256), "0.26ms");
+        assert_eq!(format_ms(42.04), "42.0ms");
+        assert_eq!(format_ms(1500.0), "1.50s");
+    }
+}
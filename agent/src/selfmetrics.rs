@@ -0,0 +1,446 @@
+//! Agent Self-Metrics
+//!
+//! Counters describing the agent itself rather than the network: events
+//! consumed and lost per ring buffer, pipeline queue depths, enrichment
+//! cache hit rates, heartbeat and exporter outcomes, and kernel map
+//! occupancy.
+//!
+//! The daemon serves them in Prometheus text format on `http_listen`
+//! and periodically writes a snapshot to the state directory for
+//! `sennet status --verbose`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+use crate::pipeline::{PipelineHandle, RingKind};
+
+/// File name of the last snapshot inside the state directory
+pub const STATE_FILE: &str = "self_metrics.json";
+
+/// Process-wide counters, updated from wherever the event happens
+pub struct SelfMetrics {
+    pub heartbeats_ok: AtomicU64,
+    pub heartbeats_failed: AtomicU64,
+    /// Unix seconds of the last successful heartbeat (0 = none yet)
+    last_heartbeat_ok: AtomicU64,
+    ebpf_attached: AtomicBool,
+    /// Failed writes of exported data (rollups, snapshots)
+    pub exporter_errors: AtomicU64,
+    flow_map_capacity: AtomicU64,
+    pipeline: OnceLock<PipelineHandle>,
+}
+
+static METRICS: SelfMetrics = SelfMetrics::new();
+
+/// The agent's metrics registry
+pub fn global() -> &'static SelfMetrics {
+    &METRICS
+}
+
+impl Default for SelfMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SelfMetrics {
+    const fn new() -> Self {
+        Self {
+            heartbeats_ok: AtomicU64::new(0),
+            heartbeats_failed: AtomicU64::new(0),
+            last_heartbeat_ok: AtomicU64::new(0),
+            ebpf_attached: AtomicBool::new(false),
+            exporter_errors: AtomicU64::new(0),
+            flow_map_capacity: AtomicU64::new(0),
+            pipeline: OnceLock::new(),
+        }
+    }
+
+    /// Report stats and queue depths of the running pipeline
+    pub fn attach_pipeline(&self, handle: PipelineHandle) {
+        let _ = self.pipeline.set(handle);
+    }
+
+    pub fn heartbeat_succeeded(&self) {
+        self.heartbeats_ok.fetch_add(1, Ordering::Relaxed);
+        self.last_heartbeat_ok.store(unix_now(), Ordering::Relaxed);
+    }
+
+    /// Seconds since the last successful heartbeat
+    pub fn heartbeat_age_secs(&self) -> Option<u64> {
+        match self.last_heartbeat_ok.load(Ordering::Relaxed) {
+            0 => None,
+            at => Some(unix_now().saturating_sub(at)),
+        }
+    }
+
+    pub fn set_ebpf_attached(&self, attached: bool) {
+        self.ebpf_attached.store(attached, Ordering::Relaxed);
+    }
+
+    pub fn ebpf_attached(&self) -> bool {
+        self.ebpf_attached.load(Ordering::Relaxed)
+    }
+
+    /// Capacity of the kernel FLOWS map as loaded
+    pub fn set_flow_map_capacity(&self, entries: u32) {
+        self.flow_map_capacity.store(entries as u64, Ordering::Relaxed);
+    }
+
+    /// Events lost so far per ring buffer, in the kernel (ring full) and
+    /// in userspace (reader queue full), indexed like `RingKind::ALL`
+    pub fn events_lost(&self) -> [u64; RingKind::ALL.len()] {
+        let mut lost = crate::ebpf::read_pinned_reserve_failures().unwrap_or_default();
+        if let Some(handle) = self.pipeline.get() {
+            for (lost, queue_full) in lost.iter_mut().zip(&handle.stats.ring_lost) {
+                *lost += queue_full.load(Ordering::Relaxed);
+            }
+        }
+        lost
+    }
+
+    /// Collect current values; reads kernel maps, so may block briefly
+    pub fn snapshot(&self) -> Snapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let reserve_failures = crate::ebpf::read_pinned_reserve_failures().ok();
+        let mut snapshot = Snapshot {
+            timestamp: unix_now(),
+            heartbeats_ok: load(&self.heartbeats_ok),
+            heartbeats_failed: load(&self.heartbeats_failed),
+            exporter_errors: load(&self.exporter_errors),
+            flow_map_entries: crate::ebpf::count_pinned_flows().ok(),
+            flow_map_capacity: load(&self.flow_map_capacity),
+            memory_rss_bytes: crate::budget::resident_bytes().unwrap_or(0),
+            ..Default::default()
+        };
+
+        snapshot.rings = RingKind::ALL
+            .iter()
+            .map(|kind| RingMetrics {
+                ring: kind.name().to_string(),
+                consumed: self.pipeline.get().map_or(0, |h| load(&h.stats.ring_events[kind.index()])),
+                reserve_failures: reserve_failures.map(|f| f[kind.index()]),
+                queue_full: self.pipeline.get().map_or(0, |h| load(&h.stats.ring_lost[kind.index()])),
+            })
+            .collect();
+
+        if let Some(handle) = self.pipeline.get() {
+            let stats = &handle.stats;
+            snapshot.stages = [("enrich", &stats.enrich), ("aggregate", &stats.aggregate), ("sink", &stats.sink)]
+                .into_iter()
+                .map(|(stage, s)| StageMetrics {
+                    stage: stage.to_string(),
+                    processed: load(&s.processed),
+                    dropped: load(&s.dropped),
+                })
+                .collect();
+            snapshot.queues = handle
+                .queue_depths()
+                .into_iter()
+                .map(|(queue, depth, capacity)| QueueMetrics { queue: queue.to_string(), depth: depth as u64, capacity: capacity as u64 })
+                .collect();
+            snapshot.queues.push(QueueMetrics {
+                queue: "sink".to_string(),
+                depth: load(&stats.sink_queue_depth),
+                capacity: 0,
+            });
+            snapshot.coalesced = load(&stats.coalesced);
+            snapshot.reason_limited = load(&stats.reason_limited);
+            snapshot.filtered = load(&stats.filtered);
+            snapshot.undecodable = load(&stats.undecodable);
+            snapshot.deep_enriched = load(&stats.deep_enriched);
+            snapshot.deep_skipped = load(&stats.deep_skipped);
+            snapshot.ifname_cache_hits = load(&stats.ifname_hits);
+            snapshot.ifname_cache_misses = load(&stats.ifname_misses);
+        }
+        snapshot
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RingMetrics {
+    pub ring: String,
+    pub consumed: u64,
+    /// None if the loaded eBPF object doesn't count them
+    pub reserve_failures: Option<u64>,
+    /// Records dropped because the reader queue was full
+    #[serde(default)]
+    pub queue_full: u64,
+}
+
+impl RingMetrics {
+    /// Events that never reached the pipeline, for either reason
+    pub fn lost(&self) -> u64 {
+        self.reserve_failures.unwrap_or(0) + self.queue_full
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageMetrics {
+    pub stage: String,
+    pub processed: u64,
+    pub dropped: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueMetrics {
+    pub queue: String,
+    pub depth: u64,
+    /// 0 if not fixed (the sink depth is the fullest of several queues)
+    pub capacity: u64,
+}
+
+/// Point-in-time copy of all self-metrics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    /// Unix seconds
+    pub timestamp: u64,
+    pub rings: Vec<RingMetrics>,
+    pub stages: Vec<StageMetrics>,
+    pub queues: Vec<QueueMetrics>,
+    pub coalesced: u64,
+    #[serde(default)]
+    pub reason_limited: u64,
+    #[serde(default)]
+    pub filtered: u64,
+    /// Records skipped because the eBPF object wrote a layout this build
+    /// can't read
+    #[serde(default)]
+    pub undecodable: u64,
+    pub deep_enriched: u64,
+    pub deep_skipped: u64,
+    pub ifname_cache_hits: u64,
+    pub ifname_cache_misses: u64,
+    pub heartbeats_ok: u64,
+    pub heartbeats_failed: u64,
+    pub exporter_errors: u64,
+    /// None if the flow map isn't pinned
+    pub flow_map_entries: Option<u64>,
+    pub flow_map_capacity: u64,
+    pub memory_rss_bytes: u64,
+}
+
+impl Snapshot {
+    /// Fraction of interface lookups served from cache
+    pub fn ifname_hit_ratio(&self) -> Option<f64> {
+        let total = self.ifname_cache_hits + self.ifname_cache_misses;
+        (total > 0).then(|| self.ifname_cache_hits as f64 / total as f64)
+    }
+
+    /// Events lost across all ring buffers
+    pub fn events_lost(&self) -> u64 {
+        self.rings.iter().map(RingMetrics::lost).sum()
+    }
+
+    /// Write the snapshot to the state directory
+    pub fn save(&self, state_dir: &Path) -> Result<()> {
+        let path = state_dir.join(STATE_FILE);
+        let content = serde_json::to_string(self).context("Failed to serialize self-metrics")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write self-metrics: {}", path.display()))
+    }
+
+    /// Read the last snapshot written by the daemon
+    pub fn load(state_dir: &Path) -> Result<Self> {
+        let path = state_dir.join(STATE_FILE);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("No self-metrics at {}", path.display()))?;
+        serde_json::from_str(&content).context("Failed to parse self-metrics")
+    }
+
+    /// Render in the Prometheus text exposition format, with the
+    /// deployment labels on every sample
+    pub fn to_prometheus(&self) -> String {
+        self.render_prometheus(crate::labels::get())
+    }
+
+    fn render_prometheus(&self, labels: &BTreeMap<String, String>) -> String {
+        let mut out = Exposition::new("sennet_agent", labels);
+
+        out.family("ring_events_total", "counter", "Records consumed from each kernel ring buffer");
+        for r in &self.rings {
+            out.sample(&[("ring", &r.ring)], r.consumed);
+        }
+        out.family("ring_reserve_failures_total", "counter", "Events lost in the kernel because a ring buffer was full");
+        for r in &self.rings {
+            if let Some(failures) = r.reserve_failures {
+                out.sample(&[("ring", &r.ring)], failures);
+            }
+        }
+        out.family("events_lost_total", "counter", "Events lost before reaching the pipeline, by ring buffer and cause");
+        for r in &self.rings {
+            if let Some(failures) = r.reserve_failures {
+                out.sample(&[("ring", &r.ring), ("cause", "ring_full")], failures);
+            }
+            out.sample(&[("ring", &r.ring), ("cause", "queue_full")], r.queue_full);
+        }
+        out.family("pipeline_processed_total", "counter", "Events handed to each pipeline stage");
+        for s in &self.stages {
+            out.sample(&[("stage", &s.stage)], s.processed);
+        }
+        out.family("pipeline_dropped_total", "counter", "Events dropped because a stage's input queue was full");
+        for s in &self.stages {
+            out.sample(&[("stage", &s.stage)], s.dropped);
+        }
+        out.family("queue_depth", "gauge", "Items waiting in each pipeline queue");
+        for q in &self.queues {
+            out.sample(&[("queue", &q.queue)], q.depth);
+        }
+        out.family("queue_capacity", "gauge", "Capacity of each pipeline queue");
+        for q in self.queues.iter().filter(|q| q.capacity > 0) {
+            out.sample(&[("queue", &q.queue)], q.capacity);
+        }
+        out.family("coalesced_drops_total", "counter", "Drops merged into an earlier record");
+        out.sample(&[], self.coalesced);
+        out.family("reason_limited_drops_total", "counter", "Drops held back by pipeline.max_per_reason");
+        out.sample(&[], self.reason_limited);
+        out.family("filtered_events_total", "counter", "Events discarded by pipeline.filter or pipeline.filters");
+        out.sample(&[], self.filtered);
+        out.family("undecodable_events_total", "counter", "Records skipped because their kind or layout version is unknown to this build");
+        out.sample(&[], self.undecodable);
+        out.family("deep_enrichments_total", "counter", "Events at or above the enrichment severity, by outcome");
+        out.sample(&[("result", "enriched")], self.deep_enriched);
+        out.sample(&[("result", "skipped")], self.deep_skipped);
+        out.family("ifname_cache_lookups_total", "counter", "Interface name lookups, by cache result");
+        out.sample(&[("result", "hit")], self.ifname_cache_hits);
+        out.sample(&[("result", "miss")], self.ifname_cache_misses);
+        out.family("heartbeats_total", "counter", "Heartbeats sent to the control plane, by outcome");
+        out.sample(&[("result", "ok")], self.heartbeats_ok);
+        out.sample(&[("result", "failed")], self.heartbeats_failed);
+        out.family("exporter_errors_total", "counter", "Failed writes of exported data");
+        out.sample(&[], self.exporter_errors);
+        if let Some(entries) = self.flow_map_entries {
+            out.family("flow_map_entries", "gauge", "Entries in the kernel flow map");
+            out.sample(&[], entries);
+        }
+        out.family("flow_map_capacity", "gauge", "Maximum entries of the kernel flow map");
+        out.sample(&[], self.flow_map_capacity);
+        out.family("resident_memory_bytes", "gauge", "Resident memory of the agent process");
+        out.sample(&[], self.memory_rss_bytes);
+        out.finish()
+    }
+}
+
+/// Prometheus text writer; samples belong to the last declared family
+pub struct Exposition<'a> {
+    text: String,
+    prefix: &'static str,
+    name: &'static str,
+    /// Appended to every sample's own labels
+    labels: &'a BTreeMap<String, String>,
+}
+
+impl<'a> Exposition<'a> {
+    /// Metric names are `<prefix>_<family>`
+    pub fn new(prefix: &'static str, labels: &'a BTreeMap<String, String>) -> Self {
+        Self { text: String::new(), prefix, name: "", labels }
+    }
+
+    pub fn family(&mut self, name: &'static str, kind: &str, help: &str) {
+        self.name = name;
+        let _ = writeln!(self.text, "# HELP {}_{} {}", self.prefix, name, help);
+        let _ = writeln!(self.text, "# TYPE {}_{} {}", self.prefix, name, kind);
+    }
+
+    pub fn sample(&mut self, labels: &[(&str, &str)], value: u64) {
+        let _ = write!(self.text, "{}_{}", self.prefix, self.name);
+        let common = self.labels.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        for (i, (key, val)) in labels.iter().copied().chain(common).enumerate() {
+            let sep = if i == 0 { '{' } else { ',' };
+            let val = val.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            let _ = write!(self.text, "{}{}=\"{}\"", sep, key, val);
+        }
+        if !labels.is_empty() || !self.labels.is_empty() {
+            self.text.push('}');
+        }
+        let _ = writeln!(self.text, " {}", value);
+    }
+
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Periodically write a snapshot for `sennet status --verbose`
+pub async fn run(state_dir: std::path::PathBuf, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let state_dir = state_dir.clone();
+        let saved = tokio::task::spawn_blocking(move || global().snapshot().save(&state_dir)).await;
+        if let Ok(Err(e)) = saved {
+            global().exporter_errors.fetch_add(1, Ordering::Relaxed);
+            debug!("Could not persist self-metrics: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            rings: vec![
+                RingMetrics { ring: "drop".to_string(), consumed: 42, reserve_failures: Some(3), queue_full: 5 },
+                RingMetrics { ring: "rst".to_string(), consumed: 7, reserve_failures: None, queue_full: 0 },
+            ],
+            queues: vec![QueueMetrics { queue: "reader".to_string(), depth: 5, capacity: 8192 }],
+            ifname_cache_hits: 9,
+            ifname_cache_misses: 1,
+            heartbeats_ok: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_prometheus_format() {
+        let text = snapshot().to_prometheus();
+        assert!(text.contains("# TYPE sennet_agent_ring_events_total counter\n"));
+        assert!(text.contains("sennet_agent_ring_events_total{ring=\"drop\"} 42\n"));
+        assert!(text.contains("sennet_agent_ring_reserve_failures_total{ring=\"drop\"} 3\n"));
+        assert!(!text.contains("sennet_agent_ring_reserve_failures_total{ring=\"rst\"}"));
+        assert!(text.contains("sennet_agent_events_lost_total{ring=\"drop\",cause=\"ring_full\"} 3\n"));
+        assert!(text.contains("sennet_agent_events_lost_total{ring=\"drop\",cause=\"queue_full\"} 5\n"));
+        assert!(text.contains("sennet_agent_queue_depth{queue=\"reader\"} 5\n"));
+        assert!(text.contains("sennet_agent_heartbeats_total{result=\"ok\"} 2\n"));
+        assert!(text.contains("sennet_agent_exporter_errors_total 0\n"));
+        assert!(text.contains("sennet_agent_undecodable_events_total 0\n"));
+        // Unpinned flow map: no occupancy sample rather than a misleading 0
+        assert!(!text.contains("sennet_agent_flow_map_entries"));
+    }
+
+    #[test]
+    fn test_prometheus_deployment_labels() {
+        let labels = BTreeMap::from([("env".to_string(), "prod".to_string()), ("team".to_string(), "a\"b".to_string())]);
+        let text = snapshot().render_prometheus(&labels);
+        assert!(text.contains("sennet_agent_ring_events_total{ring=\"drop\",env=\"prod\",team=\"a\\\"b\"} 42\n"));
+        assert!(text.contains("sennet_agent_exporter_errors_total{env=\"prod\",team=\"a\\\"b\"} 0\n"));
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        snapshot().save(dir.path()).unwrap();
+        let loaded = Snapshot::load(dir.path()).unwrap();
+        assert_eq!(loaded.rings[0].consumed, 42);
+        assert_eq!(loaded.events_lost(), 8);
+        assert_eq!(loaded.ifname_hit_ratio(), Some(0.9));
+    }
+}
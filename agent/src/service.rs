@@ -0,0 +1,280 @@
+//! Service Management
+//!
+//! Detects which init system runs the host (systemd, OpenRC, runit or
+//! sysvinit) and drives the `sennet` service through it, so `status` and
+//! upgrade restarts work on Alpine, Void or Devuan as well as on systemd
+//! distributions.
+//!
+//! Outside systemd the service scripts written by the installer log to a
+//! file; [`InitSystem::recent_logs`] reads that file instead of the journal.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Name of the service under every init system
+pub const SERVICE_NAME: &str = "sennet";
+
+/// PID file written by the OpenRC and sysvinit scripts
+pub const PID_FILE: &str = "/run/sennet.pid";
+
+/// Init system managing services on this host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitSystem {
+    Systemd,
+    OpenRc,
+    Runit,
+    SysVinit,
+    Unknown,
+}
+
+/// State of the service as reported by the init system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceState {
+    Active,
+    Inactive,
+    Failed,
+    /// Anything else, as printed by the init system
+    Unknown(String),
+}
+
+impl InitSystem {
+    pub fn detect() -> Self {
+        Self::detect_in(Path::new("/"))
+    }
+
+    /// Detect from marker files below `root`
+    fn detect_in(root: &Path) -> Self {
+        let exists = |path: &str| root.join(path).exists();
+        if exists("run/systemd/system") {
+            // The check sd_booted(3) uses
+            InitSystem::Systemd
+        } else if exists("run/openrc") || exists("sbin/openrc-run") {
+            InitSystem::OpenRc
+        } else if exists("run/runit") || exists("etc/runit") {
+            InitSystem::Runit
+        } else if exists("etc/init.d") {
+            InitSystem::SysVinit
+        } else {
+            InitSystem::Unknown
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InitSystem::Systemd => "systemd",
+            InitSystem::OpenRc => "OpenRC",
+            InitSystem::Runit => "runit",
+            InitSystem::SysVinit => "sysvinit",
+            InitSystem::Unknown => "unknown",
+        }
+    }
+
+    /// Command line that performs `action` (start, stop, restart, status)
+    pub fn command_line(&self, action: &str) -> Option<Vec<String>> {
+        let args: Vec<&str> = match self {
+            InitSystem::Systemd => {
+                let action = if action == "status" { "is-active" } else { action };
+                vec!["systemctl", action, SERVICE_NAME]
+            }
+            InitSystem::OpenRc => vec!["rc-service", SERVICE_NAME, action],
+            InitSystem::Runit => vec!["sv", action, SERVICE_NAME],
+            InitSystem::SysVinit => vec!["/etc/init.d/sennet", action],
+            InitSystem::Unknown => return None,
+        };
+        Some(args.into_iter().map(String::from).collect())
+    }
+
+    /// Command a user would type to perform `action`, for hints
+    pub fn hint(&self, action: &str) -> String {
+        match self.command_line(action) {
+            Some(args) => format!("sudo {}", args.join(" ")),
+            None => format!("{} the sennet process manually", action),
+        }
+    }
+
+    fn run(&self, action: &str) -> Result<std::process::Output> {
+        let args = self
+            .command_line(action)
+            .context("No supported init system detected")?;
+        Command::new(&args[0])
+            .args(&args[1..])
+            .output()
+            .with_context(|| format!("Failed to run {}", args[0]))
+    }
+
+    pub fn state(&self) -> ServiceState {
+        let output = match self.run("status") {
+            Ok(output) => output,
+            Err(_) => return ServiceState::Unknown("unknown".to_string()),
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match self {
+            InitSystem::Systemd => parse_systemd_state(&stdout),
+            InitSystem::OpenRc => parse_openrc_state(&stdout),
+            InitSystem::Runit => parse_runit_state(&stdout),
+            _ => parse_lsb_status(output.status.code()),
+        }
+    }
+
+    pub fn restart(&self) -> Result<()> {
+        let output = self.run("restart")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} exited with {}: {}",
+                self.hint("restart"),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// PID of the running agent
+    pub fn main_pid(&self) -> Option<u32> {
+        match self {
+            InitSystem::Systemd => {
+                let output = Command::new("systemctl")
+                    .args(["show", SERVICE_NAME, "--property=MainPID", "--value"])
+                    .output()
+                    .ok()?;
+                String::from_utf8_lossy(&output.stdout).trim().parse().ok().filter(|&pid| pid != 0)
+            }
+            InitSystem::Runit => {
+                let output = self.run("status").ok()?;
+                parse_runit_pid(&String::from_utf8_lossy(&output.stdout))
+            }
+            _ => fs::read_to_string(PID_FILE).ok()?.trim().parse().ok(),
+        }
+    }
+
+    /// Time the agent has been running, as printed by the init system or ps
+    pub fn uptime(&self) -> Option<String> {
+        if *self == InitSystem::Systemd {
+            let output = Command::new("systemctl")
+                .args(["show", SERVICE_NAME, "--property=ActiveEnterTimestamp", "--value"])
+                .output()
+                .ok()?;
+            let since = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            return (!since.is_empty()).then(|| format!("since {}", since));
+        }
+        let pid = self.main_pid()?;
+        let output = Command::new("ps").args(["-o", "etime=", "-p", &pid.to_string()]).output().ok()?;
+        let elapsed = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (!elapsed.is_empty()).then_some(elapsed)
+    }
+
+    /// Where the service's output goes when it isn't the journal
+    pub fn log_path(&self) -> Option<&'static str> {
+        match self {
+            InitSystem::Systemd | InitSystem::Unknown => None,
+            InitSystem::Runit => Some("/var/log/sennet/current"),
+            InitSystem::OpenRc | InitSystem::SysVinit => Some("/var/log/sennet.log"),
+        }
+    }
+
+    /// Last `lines` lines of service output
+    ///
+    /// `since_secs` limits journal output to recent entries; log files are
+    /// not filtered by time.
+    pub fn recent_logs(&self, lines: usize, since_secs: Option<u64>) -> Option<String> {
+        if *self == InitSystem::Systemd {
+            let mut cmd = Command::new("journalctl");
+            cmd.args(["-u", SERVICE_NAME, "--no-pager", "-n", &lines.to_string()]);
+            if let Some(secs) = since_secs {
+                cmd.args(["--since", &format!("{} seconds ago", secs)]);
+            }
+            let output = cmd.output().ok()?;
+            return Some(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+        tail_file(Path::new(self.log_path()?), lines)
+    }
+}
+
+/// Last `lines` lines of a text file
+pub fn tail_file(path: &Path, lines: usize) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let all: Vec<&str> = content.lines().collect();
+    Some(all[all.len().saturating_sub(lines)..].join("\n"))
+}
+
+/// `systemctl is-active` output
+fn parse_systemd_state(stdout: &str) -> ServiceState {
+    match stdout.trim() {
+        "active" => ServiceState::Active,
+        "inactive" => ServiceState::Inactive,
+        "failed" => ServiceState::Failed,
+        other => ServiceState::Unknown(other.to_string()),
+    }
+}
+
+/// `rc-service <name> status` output, e.g. ` * status: started`
+fn parse_openrc_state(stdout: &str) -> ServiceState {
+    let status = stdout.rsplit(':').next().unwrap_or("").trim();
+    match status {
+        "started" => ServiceState::Active,
+        "stopped" => ServiceState::Inactive,
+        "crashed" => ServiceState::Failed,
+        other => ServiceState::Unknown(other.to_string()),
+    }
+}
+
+/// `sv status <name>` output, e.g. `run: sennet: (pid 123) 45s`
+fn parse_runit_state(stdout: &str) -> ServiceState {
+    match stdout.split(':').next().unwrap_or("").trim() {
+        "run" => ServiceState::Active,
+        "down" => ServiceState::Inactive,
+        "fail" => ServiceState::Failed,
+        other => ServiceState::Unknown(other.to_string()),
+    }
+}
+
+fn parse_runit_pid(stdout: &str) -> Option<u32> {
+    let rest = stdout.split("(pid ").nth(1)?;
+    rest.split(')').next()?.trim().parse().ok()
+}
+
+/// Exit code of an LSB init script's `status` action
+fn parse_lsb_status(code: Option<i32>) -> ServiceState {
+    match code {
+        Some(0) => ServiceState::Active,
+        Some(3) => ServiceState::Inactive,
+        // 1 and 2: dead, but the pid or lock file remains
+        Some(1) | Some(2) => ServiceState::Failed,
+        Some(code) => ServiceState::Unknown(format!("exit code {}", code)),
+        None => ServiceState::Unknown("killed".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_init_system() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(InitSystem::detect_in(dir.path()), InitSystem::Unknown);
+        fs::create_dir_all(dir.path().join("etc/init.d")).unwrap();
+        assert_eq!(InitSystem::detect_in(dir.path()), InitSystem::SysVinit);
+        // Alpine ships /etc/init.d too; OpenRC wins
+        fs::create_dir_all(dir.path().join("run/openrc")).unwrap();
+        assert_eq!(InitSystem::detect_in(dir.path()), InitSystem::OpenRc);
+        fs::create_dir_all(dir.path().join("run/systemd/system")).unwrap();
+        assert_eq!(InitSystem::detect_in(dir.path()), InitSystem::Systemd);
+    }
+
+    #[test]
+    fn test_parse_states() {
+        assert_eq!(parse_systemd_state("active\n"), ServiceState::Active);
+        assert_eq!(parse_openrc_state(" * status: started\n"), ServiceState::Active);
+        assert_eq!(parse_openrc_state(" * status: crashed\n"), ServiceState::Failed);
+        assert_eq!(parse_runit_state("down: sennet: 3s, normally up\n"), ServiceState::Inactive);
+        assert_eq!(parse_runit_pid("run: sennet: (pid 4242) 45s\n"), Some(4242));
+        assert_eq!(parse_lsb_status(Some(3)), ServiceState::Inactive);
+        assert_eq!(
+            InitSystem::OpenRc.command_line("restart").unwrap(),
+            vec!["rc-service", "sennet", "restart"]
+        );
+    }
+}
@@ -0,0 +1,785 @@
+//! Built-in Summary Sinks
+//!
+//! Sinks that can be listed in `pipeline.sinks` (see `plugins`):
+//!
+//! - `prometheus`: running totals of the summary counters, served next to
+//!   the self-metrics on `http_listen`'s `/metrics`
+//! - `otlp`: each window's counters as OTLP/HTTP JSON delta sums, posted to
+//!   `<endpoint>/v1/metrics`
+//! - `webhook`: POSTs the summary JSON when it holds a notable event at or
+//!   above `min_severity`
+//! - `syslog` (Unix): one RFC 5424 message per such event to the local
+//!   syslog socket, with the event's fields as structured data
+//! - `exec`: runs a program with those events as JSON on stdin, for sites
+//!   that can reach neither a webhook nor the control plane
+//!
+//! Every sink runs on its own blocking thread, so the HTTP calls here are
+//! synchronous. A failed export is logged and counted in `exporter_errors`;
+//! the window is not retried.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::ebpf::{drop_reason_str, nf_hook_str};
+use crate::enrich::{NotableEvent, Severity};
+use crate::pipeline::{EventSink, Summary};
+use crate::selfmetrics::Exposition;
+
+fn default_timeout() -> u64 {
+    5
+}
+
+/// Log and count a failed export
+fn report(sink: &str, result: Result<()>) {
+    if let Err(e) = result {
+        crate::selfmetrics::global().exporter_errors.fetch_add(1, Ordering::Relaxed);
+        warn!("{} sink: {:#}", sink, e);
+    }
+}
+
+fn post_json(url: &str, headers: &BTreeMap<String, String>, timeout: Duration, body: &Value) -> Result<()> {
+    let mut request = ureq::post(url).timeout(timeout);
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    request.send_json(body).with_context(|| format!("POST {} failed", url))?;
+    Ok(())
+}
+
+fn check_url(url: &str) -> Result<()> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        anyhow::bail!("'{}' is not an http:// or https:// URL", url);
+    }
+    Ok(())
+}
+
+// ============================================================================
+// prometheus
+// ============================================================================
+
+/// Totals of every summary the `prometheus` sink has seen (None = no such sink)
+static TOTALS: Mutex<Option<EventTotals>> = Mutex::new(None);
+
+/// Counters summed across windows, keyed by their label values
+#[derive(Debug, Default)]
+struct EventTotals {
+    events: u64,
+    drops_by_reason: BTreeMap<&'static str, u64>,
+    drops_by_interface: BTreeMap<String, u64>,
+    nf_drops_by_hook: BTreeMap<&'static str, u64>,
+    flows_opened: u64,
+    flows_closed: u64,
+    resets_in: u64,
+    resets_out: u64,
+    retransmits: u64,
+}
+
+impl EventTotals {
+    fn add(&mut self, summary: &Summary) {
+        self.events += summary.events;
+        for (&reason, count) in &summary.drops_by_reason {
+            *self.drops_by_reason.entry(drop_reason_str(reason)).or_insert(0) += count;
+        }
+        for (interface, count) in &summary.drops_by_interface {
+            *self.drops_by_interface.entry(interface.clone()).or_insert(0) += count;
+        }
+        for (&hook, count) in &summary.nf_drops_by_hook {
+            *self.nf_drops_by_hook.entry(nf_hook_str(hook)).or_insert(0) += count;
+        }
+        self.flows_opened += summary.flows_opened;
+        self.flows_closed += summary.flows_closed;
+        self.resets_in += summary.resets_in;
+        self.resets_out += summary.resets_out;
+        self.retransmits += summary.retransmits_by_dst.values().sum::<u64>();
+    }
+
+    fn render(&self, labels: &BTreeMap<String, String>) -> String {
+        let mut out = Exposition::new("sennet", labels);
+        out.family("events_total", "counter", "Kernel events counted by the pipeline");
+        out.sample(&[], self.events);
+        out.family("drops_total", "counter", "Packet drops by kernel drop reason");
+        for (&reason, &count) in &self.drops_by_reason {
+            out.sample(&[("reason", reason)], count);
+        }
+        out.family("interface_drops_total", "counter", "Packet drops by interface");
+        for (interface, &count) in &self.drops_by_interface {
+            out.sample(&[("interface", interface.as_str())], count);
+        }
+        out.family("netfilter_drops_total", "counter", "Netfilter DROP verdicts by hook");
+        for (&hook, &count) in &self.nf_drops_by_hook {
+            out.sample(&[("hook", hook)], count);
+        }
+        out.family("flows_opened_total", "counter", "Connections opened");
+        out.sample(&[], self.flows_opened);
+        out.family("flows_closed_total", "counter", "Connections closed");
+        out.sample(&[], self.flows_closed);
+        out.family("resets_total", "counter", "TCP resets by direction");
+        out.sample(&[("direction", "in")], self.resets_in);
+        out.sample(&[("direction", "out")], self.resets_out);
+        out.family("retransmits_total", "counter", "TCP segments retransmitted");
+        out.sample(&[], self.retransmits);
+        out.finish()
+    }
+}
+
+/// Event counters in Prometheus text format, when a `prometheus` sink exists
+pub fn prometheus_text() -> Option<String> {
+    let totals = TOTALS.lock().unwrap();
+    totals.as_ref().map(|t| t.render(crate::labels::get()))
+}
+
+/// Adds each window to the totals served on `/metrics`
+pub struct PrometheusSink;
+
+impl Default for PrometheusSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrometheusSink {
+    /// Creating one turns the totals on
+    pub fn new() -> Self {
+        TOTALS.lock().unwrap().get_or_insert_with(EventTotals::default);
+        Self
+    }
+}
+
+impl EventSink for PrometheusSink {
+    fn name(&self) -> &str {
+        "prometheus"
+    }
+
+    fn export(&mut self, summary: &Summary) {
+        if let Some(totals) = TOTALS.lock().unwrap().as_mut() {
+            totals.add(summary);
+        }
+    }
+}
+
+// ============================================================================
+// otlp
+// ============================================================================
+
+/// `otlp` sink options
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OtlpOptions {
+    /// Collector base URL, e.g. `http://collector:4318`; defaults to `otlp_endpoint`
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// Extra request headers, e.g. for collector authentication
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+/// Posts each window's counters as OTLP metrics
+pub struct OtlpSink {
+    url: String,
+    headers: BTreeMap<String, String>,
+    timeout: Duration,
+}
+
+impl OtlpSink {
+    /// `fallback` is the agent's `otlp_endpoint`, used without an `endpoint` option
+    pub fn new(options: OtlpOptions, fallback: Option<&str>) -> Result<Self> {
+        let endpoint = options
+            .endpoint
+            .as_deref()
+            .or(fallback)
+            .context("Needs an endpoint option or otlp_endpoint")?;
+        check_url(endpoint)?;
+        Ok(Self {
+            url: metrics_url(endpoint),
+            headers: options.headers,
+            timeout: Duration::from_secs(options.timeout_secs.max(1)),
+        })
+    }
+}
+
+impl EventSink for OtlpSink {
+    fn name(&self) -> &str {
+        "otlp"
+    }
+
+    fn export(&mut self, summary: &Summary) {
+        if summary.is_empty() {
+            return;
+        }
+        report("otlp", post_json(&self.url, &self.headers, self.timeout, &otlp_metrics(summary)));
+    }
+}
+
+/// OTLP/HTTP metrics URL for a collector base URL
+fn metrics_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/metrics") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/metrics", endpoint)
+    }
+}
+
+fn unix_nanos(time: Option<SystemTime>) -> String {
+    time.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+        .to_string()
+}
+
+fn otlp_attributes<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> Value {
+    pairs
+        .into_iter()
+        .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+        .collect()
+}
+
+/// One window as an OTLP `ExportMetricsServiceRequest` in JSON encoding
+fn otlp_metrics(summary: &Summary) -> Value {
+    let start = unix_nanos(summary.window_start);
+    let end = unix_nanos(summary.window_end);
+    // One monotonic delta sum per counter, one data point per label value
+    let sum = |name: &str, unit: &str, points: Vec<(Vec<(&str, &str)>, u64)>| {
+        let points: Vec<Value> = points
+            .into_iter()
+            .map(|(attributes, value)| {
+                json!({
+                    "attributes": otlp_attributes(attributes),
+                    "startTimeUnixNano": start,
+                    "timeUnixNano": end,
+                    "asInt": value.to_string(),
+                })
+            })
+            .collect();
+        json!({
+            "name": name,
+            "unit": unit,
+            "sum": { "aggregationTemporality": 1, "isMonotonic": true, "dataPoints": points },
+        })
+    };
+
+    let metrics = vec![
+        sum("sennet.events", "{event}", vec![(vec![], summary.events)]),
+        sum(
+            "sennet.drops",
+            "{packet}",
+            summary
+                .drops_by_reason
+                .iter()
+                .map(|(&reason, &count)| (vec![("reason", drop_reason_str(reason))], count))
+                .collect(),
+        ),
+        sum(
+            "sennet.interface.drops",
+            "{packet}",
+            summary
+                .drops_by_interface
+                .iter()
+                .map(|(interface, &count)| (vec![("interface", interface.as_str())], count))
+                .collect(),
+        ),
+        sum(
+            "sennet.netfilter.drops",
+            "{packet}",
+            summary
+                .nf_drops_by_hook
+                .iter()
+                .map(|(&hook, &count)| (vec![("hook", nf_hook_str(hook))], count))
+                .collect(),
+        ),
+        sum("sennet.flows.opened", "{connection}", vec![(vec![], summary.flows_opened)]),
+        sum("sennet.flows.closed", "{connection}", vec![(vec![], summary.flows_closed)]),
+        sum(
+            "sennet.resets",
+            "{segment}",
+            vec![
+                (vec![("direction", "in")], summary.resets_in),
+                (vec![("direction", "out")], summary.resets_out),
+            ],
+        ),
+        sum(
+            "sennet.tcp.retransmits",
+            "{segment}",
+            vec![(vec![], summary.retransmits_by_dst.values().sum())],
+        ),
+    ];
+
+    let resource = [("service.name", "sennet-agent"), ("service.version", crate::upgrade::CURRENT_VERSION)]
+        .into_iter()
+        .chain(summary.labels.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    json!({
+        "resourceMetrics": [{
+            "resource": { "attributes": otlp_attributes(resource) },
+            "scopeMetrics": [{
+                "scope": { "name": "sennet", "version": crate::upgrade::CURRENT_VERSION },
+                "metrics": metrics,
+            }],
+        }],
+    })
+}
+
+// ============================================================================
+// webhook
+// ============================================================================
+
+/// `webhook` sink options
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookOptions {
+    pub url: String,
+    /// Post a window only if one of its notable events is at least this severe
+    #[serde(default = "default_alert_severity")]
+    pub min_severity: Severity,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+/// Posts summaries with notable events to a URL
+pub struct WebhookSink {
+    options: WebhookOptions,
+}
+
+impl WebhookSink {
+    pub fn new(options: WebhookOptions) -> Result<Self> {
+        check_url(&options.url)?;
+        Ok(Self { options })
+    }
+}
+
+impl EventSink for WebhookSink {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn export(&mut self, summary: &Summary) {
+        if !summary.notable.iter().any(|n| n.severity >= self.options.min_severity) {
+            return;
+        }
+        let body = match serde_json::to_value(summary) {
+            Ok(body) => body,
+            Err(e) => return report("webhook", Err(e.into())),
+        };
+        let timeout = Duration::from_secs(self.options.timeout_secs.max(1));
+        report("webhook", post_json(&self.options.url, &self.options.headers, timeout, &body));
+    }
+}
+
+// ============================================================================
+// Alerts (syslog, exec)
+// ============================================================================
+
+/// Notable events of one window that reach a severity
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_end: Option<String>,
+    pub labels: &'a BTreeMap<String, String>,
+    pub events: Vec<&'a NotableEvent>,
+}
+
+impl<'a> Alert<'a> {
+    /// None when no notable event is at least `min_severity`
+    pub fn from_summary(summary: &'a Summary, min_severity: Severity) -> Option<Self> {
+        let events: Vec<&NotableEvent> = summary.notable.iter().filter(|n| n.severity >= min_severity).collect();
+        if events.is_empty() {
+            return None;
+        }
+        Some(Self {
+            window_start: summary.window_start.map(crate::clock::rfc3339),
+            window_end: summary.window_end.map(crate::clock::rfc3339),
+            labels: &summary.labels,
+            events,
+        })
+    }
+}
+
+fn default_alert_severity() -> Severity {
+    Severity::High
+}
+
+// ============================================================================
+// syslog
+// ============================================================================
+
+/// Structured data ID of the event fields (32473 is the documentation
+/// enterprise number, as used by RFC 5424's own examples)
+#[cfg(unix)]
+const SD_EVENT: &str = "sennet@32473";
+/// Structured data ID of the deployment labels
+#[cfg(unix)]
+const SD_LABELS: &str = "labels@32473";
+
+#[cfg(unix)]
+fn default_syslog_socket() -> std::path::PathBuf {
+    if cfg!(target_os = "macos") { "/var/run/syslog" } else { "/dev/log" }.into()
+}
+
+#[cfg(unix)]
+fn default_facility() -> String {
+    "daemon".to_string()
+}
+
+/// `syslog` sink options
+#[cfg(unix)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyslogOptions {
+    /// Local syslog datagram socket
+    #[serde(default = "default_syslog_socket")]
+    pub socket: std::path::PathBuf,
+    /// user, daemon, auth or local0 to local7
+    #[serde(default = "default_facility")]
+    pub facility: String,
+    #[serde(default = "default_alert_severity")]
+    pub min_severity: Severity,
+}
+
+/// Writes one RFC 5424 message per alerting event to the local syslog
+#[cfg(unix)]
+pub struct SyslogSink {
+    socket: std::os::unix::net::UnixDatagram,
+    path: std::path::PathBuf,
+    facility: u8,
+    min_severity: Severity,
+    hostname: String,
+}
+
+#[cfg(unix)]
+impl SyslogSink {
+    pub fn new(options: SyslogOptions) -> Result<Self> {
+        let facility = match options.facility.as_str() {
+            "user" => 1,
+            "daemon" => 3,
+            "auth" => 4,
+            local => match local.strip_prefix("local").and_then(|n| n.parse::<u8>().ok()) {
+                Some(n) if n <= 7 => 16 + n,
+                _ => anyhow::bail!("Unknown syslog facility '{}'", options.facility),
+            },
+        };
+        let hostname = crate::flowexport::node_name(None);
+        Ok(Self {
+            socket: std::os::unix::net::UnixDatagram::unbound().context("Failed to create syslog socket")?,
+            path: options.socket,
+            facility,
+            min_severity: options.min_severity,
+            hostname: if hostname.is_empty() { "-".to_string() } else { hostname },
+        })
+    }
+
+    /// One event as an RFC 5424 message
+    fn format(&self, event: &NotableEvent, labels: &BTreeMap<String, String>) -> String {
+        // err, warning, notice
+        let severity = match event.severity {
+            Severity::High => 3,
+            Severity::Medium => 4,
+            Severity::Low => 5,
+        };
+        // RFC 5424 allows at most microseconds
+        let timestamp = chrono::DateTime::parse_from_rfc3339(&event.time)
+            .map(|t| t.with_timezone(&chrono::Utc).to_rfc3339_opts(chrono::SecondsFormat::Micros, true))
+            .unwrap_or_else(|_| "-".to_string());
+
+        let mut data = format!("[{}", SD_EVENT);
+        if let Ok(Value::Object(fields)) = serde_json::to_value(event) {
+            for (name, value) in &fields {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    Value::Number(_) | Value::Bool(_) => value.to_string(),
+                    _ => continue,
+                };
+                if name != "time" {
+                    sd_param(&mut data, name, &value);
+                }
+            }
+        }
+        data.push(']');
+        if !labels.is_empty() {
+            data.push_str(&format!("[{}", SD_LABELS));
+            for (name, value) in labels {
+                sd_param(&mut data, name, value);
+            }
+            data.push(']');
+        }
+
+        let mut message = format!("{} {} x{}", event.kind, event.detail, event.count);
+        if let Some(interface) = &event.interface {
+            message.push_str(&format!(" on {}", interface));
+        }
+        if let Some(remote) = event.context.remote {
+            message.push_str(&format!(" remote {}", remote));
+        }
+        format!(
+            "<{}>1 {} {} sennet {} {} {} {}",
+            self.facility * 8 + severity,
+            timestamp,
+            self.hostname,
+            std::process::id(),
+            event.kind,
+            data,
+            message
+        )
+    }
+}
+
+/// Append `name="value"`, escaped as RFC 5424 structured data requires
+#[cfg(unix)]
+fn sd_param(data: &mut String, name: &str, value: &str) {
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+        .take(32)
+        .collect();
+    if name.is_empty() {
+        return;
+    }
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]");
+    data.push_str(&format!(" {}=\"{}\"", name, value));
+}
+
+#[cfg(unix)]
+impl EventSink for SyslogSink {
+    fn name(&self) -> &str {
+        "syslog"
+    }
+
+    fn export(&mut self, summary: &Summary) {
+        let Some(alert) = Alert::from_summary(summary, self.min_severity) else {
+            return;
+        };
+        for event in alert.events {
+            let message = self.format(event, alert.labels);
+            let sent = self.socket.send_to(message.as_bytes(), &self.path);
+            report("syslog", sent.map(drop).with_context(|| format!("Failed to write to {}", self.path.display())));
+        }
+    }
+}
+
+// ============================================================================
+// exec
+// ============================================================================
+
+fn default_exec_timeout() -> u64 {
+    10
+}
+
+/// `exec` sink options
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExecOptions {
+    /// Absolute path of the program to run
+    pub command: std::path::PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default = "default_alert_severity")]
+    pub min_severity: Severity,
+    /// The program is killed after this long
+    #[serde(default = "default_exec_timeout")]
+    pub timeout_secs: u64,
+}
+
+/// Runs a program once per alerting window, with the alert JSON on stdin
+pub struct ExecSink {
+    options: ExecOptions,
+}
+
+impl ExecSink {
+    pub fn new(options: ExecOptions) -> Result<Self> {
+        // The daemon's PATH is whatever the service manager gave it
+        if !options.command.is_absolute() {
+            anyhow::bail!("command must be an absolute path, not '{}'", options.command.display());
+        }
+        std::fs::metadata(&options.command)
+            .with_context(|| format!("Cannot run {}", options.command.display()))?;
+        Ok(Self { options })
+    }
+
+    fn run(&self, input: Vec<u8>) -> Result<()> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let command = self.options.command.display();
+        let mut child = Command::new(&self.options.command)
+            .args(&self.options.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", command))?;
+        // Written from another thread so a program that never reads stdin
+        // still runs into the timeout instead of blocking the sink
+        if let Some(mut stdin) = child.stdin.take() {
+            std::thread::spawn(move || {
+                let _ = stdin.write_all(&input);
+            });
+        }
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(self.options.timeout_secs.max(1));
+        loop {
+            if let Some(status) = child.try_wait()? {
+                if status.success() {
+                    return Ok(());
+                }
+                anyhow::bail!("{} exited with {}", command, status);
+            }
+            if std::time::Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                anyhow::bail!("{} killed after {}s", command, self.options.timeout_secs.max(1));
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl EventSink for ExecSink {
+    fn name(&self) -> &str {
+        "exec"
+    }
+
+    fn export(&mut self, summary: &Summary) {
+        let Some(alert) = Alert::from_summary(summary, self.options.min_severity) else {
+            return;
+        };
+        match serde_json::to_vec(&alert) {
+            Ok(input) => report("exec", self.run(input)),
+            Err(e) => report("exec", Err(e.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> Summary {
+        Summary {
+            window_start: Some(UNIX_EPOCH + Duration::from_secs(100)),
+            window_end: Some(UNIX_EPOCH + Duration::from_secs(110)),
+            events: 12,
+            drops_by_reason: [(2, 5), (7, 3), (9999, 1)].into(),
+            drops_by_interface: [("eth0".to_string(), 8)].into(),
+            nf_drops_by_hook: [(1, 2)].into(),
+            resets_in: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_prometheus_totals() {
+        let mut totals = EventTotals::default();
+        totals.add(&summary());
+        totals.add(&summary());
+        let labels = [("node".to_string(), "n1".to_string())].into();
+        let text = totals.render(&labels);
+        assert!(text.contains("# TYPE sennet_drops_total counter\n"));
+        assert!(text.contains("sennet_drops_total{reason=\"NO_SOCKET\",node=\"n1\"} 10\n"), "{}", text);
+        assert!(text.contains("sennet_drops_total{reason=\"UNKNOWN\",node=\"n1\"} 2\n"));
+        assert!(text.contains("sennet_interface_drops_total{interface=\"eth0\",node=\"n1\"} 16\n"));
+        assert!(text.contains("sennet_netfilter_drops_total{hook=\"INPUT\",node=\"n1\"} 4\n"));
+        assert!(text.contains("sennet_resets_total{direction=\"in\",node=\"n1\"} 2\n"));
+    }
+
+    #[test]
+    fn test_otlp_metrics() {
+        assert_eq!(metrics_url("http://collector:4318/"), "http://collector:4318/v1/metrics");
+        assert_eq!(metrics_url("https://otel.example.com/v1/metrics"), "https://otel.example.com/v1/metrics");
+
+        let mut summary = summary();
+        summary.labels = [("cluster".to_string(), "prod".to_string())].into();
+        let body = otlp_metrics(&summary);
+        let resource = &body["resourceMetrics"][0];
+        assert_eq!(resource["resource"]["attributes"][2]["key"], "cluster");
+        let metrics = resource["scopeMetrics"][0]["metrics"].as_array().unwrap();
+        let drops = metrics.iter().find(|m| m["name"] == "sennet.drops").unwrap();
+        assert_eq!(drops["sum"]["aggregationTemporality"], 1);
+        let point = &drops["sum"]["dataPoints"][0];
+        assert_eq!(point["attributes"][0]["value"]["stringValue"], "NO_SOCKET");
+        assert_eq!(point["asInt"], "5");
+        assert_eq!(point["startTimeUnixNano"], "100000000000");
+        assert_eq!(point["timeUnixNano"], "110000000000");
+
+        assert!(OtlpSink::new(OtlpOptions { endpoint: None, headers: BTreeMap::new(), timeout_secs: 5 }, None).is_err());
+    }
+
+    #[cfg(unix)]
+    fn notable(severity: Severity) -> NotableEvent {
+        NotableEvent {
+            severity,
+            kind: "drop",
+            detail: "NETFILTER_DROP",
+            count: 3,
+            timestamp_ns: 0,
+            time: "2024-05-01T12:00:00.123456789Z".to_string(),
+            interface: Some("eth0".to_string()),
+            context: crate::enrich::DeepContext {
+                attributes: [("note".to_string(), "a \"quoted\" ]".to_string())].into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_syslog_format() {
+        let options: SyslogOptions = serde_yaml::from_str("facility: local3\n").unwrap();
+        let sink = SyslogSink::new(options).unwrap();
+        let labels = [("cluster".to_string(), "prod".to_string())].into();
+        let line = sink.format(&notable(Severity::High), &labels);
+        // local3 (19) * 8 + err (3)
+        assert!(line.starts_with("<155>1 2024-05-01T12:00:00.123456Z "), "{}", line);
+        assert!(line.contains(" sennet "));
+        assert!(line.contains(" drop [sennet@32473 "));
+        assert!(line.contains(" detail=\"NETFILTER_DROP\""));
+        assert!(line.contains(" note=\"a \\\"quoted\\\" \\]\""), "{}", line);
+        assert!(!line.contains(" time="));
+        assert!(line.contains("][labels@32473 cluster=\"prod\"] drop NETFILTER_DROP x3 on eth0"), "{}", line);
+
+        let bad: SyslogOptions = serde_yaml::from_str("facility: local9\n").unwrap();
+        assert!(SyslogSink::new(bad).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exec_gets_alert_on_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("alert.json");
+        let options = ExecOptions {
+            command: "/bin/sh".into(),
+            args: vec!["-c".to_string(), format!("cat > {}", out.display())],
+            min_severity: Severity::High,
+            timeout_secs: 10,
+        };
+        let mut sink = ExecSink::new(options).unwrap();
+
+        // Nothing severe enough: not run
+        let mut summary = summary();
+        summary.notable = vec![notable(Severity::Medium)];
+        sink.export(&summary);
+        assert!(!out.exists());
+
+        summary.notable.push(notable(Severity::High));
+        sink.export(&summary);
+        let alert: Value = serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(alert["events"].as_array().unwrap().len(), 1);
+        assert_eq!(alert["events"][0]["detail"], "NETFILTER_DROP");
+        assert_eq!(alert["windowStart"], "1970-01-01T00:01:40.000000000Z");
+
+        let failing = ExecSink {
+            options: ExecOptions { command: "/bin/sh".into(), args: vec!["-c".to_string(), "exit 3".to_string()], min_severity: Severity::Low, timeout_secs: 10 },
+        };
+        assert!(failing.run(Vec::new()).unwrap_err().to_string().contains("exited with"));
+        assert!(ExecSink::new(ExecOptions { command: "alert.sh".into(), args: Vec::new(), min_severity: Severity::Low, timeout_secs: 1 }).is_err());
+    }
+}
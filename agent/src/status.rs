@@ -1,49 +1,124 @@
 use anyhow::Result;
-use std::process::Command;
 use std::path::Path;
 use colored::*;
 
-pub fn run() -> Result<()> {
+use crate::service::{InitSystem, ServiceState};
+
+pub fn run(verbose: bool) -> Result<()> {
     println!("{}", "Sennet Agent Status".bold().cyan());
     println!("{}", "===================".bold().cyan());
 
     // 1. Service Status
-    let service_status = check_service_status();
-    match service_status.as_str() {
-        "active" => println!("Status:       {}", "Active (Running)".green().bold()),
-        "inactive" => println!("Status:       {}", "Inactive".yellow()),
-        "failed" => println!("Status:       {}", "Failed".red().bold()),
-        _ => println!("Status:       {}", service_status),
+    let init = InitSystem::detect();
+    let service_status = init.state();
+    match &service_status {
+        ServiceState::Active => println!("Status:       {}", "Active (Running)".green().bold()),
+        ServiceState::Inactive => println!("Status:       {}", "Inactive".yellow()),
+        ServiceState::Failed => println!("Status:       {}", "Failed".red().bold()),
+        ServiceState::Unknown(state) => println!("Status:       {}", state),
     }
-
-    if service_status != "active" {
-        return Ok(());
+    if verbose {
+        println!("Init System:  {}", init.name());
     }
 
-    // 2. Uptime & PID
-    if let Ok((uptime, pid)) = get_service_details() {
-        println!("PID:          {}", pid);
-        println!("Uptime:       {}", uptime);
-    }
+    // No eBPF on Windows or macOS: show the interface counters heartbeats report
+    #[cfg(any(windows, target_os = "macos"))]
+    print_interface_counters();
 
-    // 3. Interface (from config)
-    if let Ok(interface) = get_interface_from_logs() {
-        println!("Interface:    {}", interface);
-    } else {
-        println!("Interface:    {}", "Unknown".dimmed());
+    // The daemon answers for itself over the control socket, which also
+    // works without an init system (e.g. in a container)
+    #[cfg(unix)]
+    let daemon = crate::control::Client::connect().and_then(|mut client| client.status().ok());
+    #[cfg(unix)]
+    let reported = daemon.is_some();
+    #[cfg(not(unix))]
+    let reported = false;
+
+    if service_status != ServiceState::Active && !reported {
+        if init == InitSystem::Unknown {
+            println!("              {}", "No supported init system (systemd, OpenRC, runit, sysvinit) detected".dimmed());
+        }
+        return Ok(());
     }
 
-    // 4. Backend Connection (from logs)
-    if check_backend_connection() {
-        println!("Backend:      {}", "Connected".green());
-    } else {
-        println!("Backend:      {}", "Disconnected / Error".red());
+    // 2-4. PID, uptime, interface and backend connection
+    let config = crate::config::Config::load().ok();
+    #[cfg(unix)]
+    if let Some(daemon) = &daemon {
+        print_daemon_status(daemon);
+    }
+    if !reported {
+        print_service_details(&init, config.as_ref());
     }
 
     // 5. eBPF Mode
     println!("eBPF Mode:    {}", "TC (Traffic Control)".cyan());
+    #[cfg(target_os = "linux")]
+    print_ebpf_interface_counters();
+    #[cfg(target_os = "linux")]
+    print_drop_reasons();
     
-    // 6. Kubernetes Context (Phase 7)
+    // 6. Conntrack table utilization
+    if let Some(ct) = crate::conntrack::read_stats() {
+        let pct = ct.utilization_pct();
+        let alert_pct = config
+            .as_ref()
+            .map_or_else(crate::config::default_conntrack_alert_pct, |c| c.conntrack_alert_pct);
+        let usage = format!("{}/{} ({:.1}%)", ct.count, ct.max, pct);
+        let usage = if ct.count >= ct.max {
+            usage.red().bold()
+        } else if pct >= f64::from(alert_pct) {
+            usage.yellow()
+        } else {
+            usage.green()
+        };
+        println!("Conntrack:    {}", usage);
+        if ct.exhaustion_drops() > 0 {
+            println!("              {} drops, {} early evictions since boot", ct.exhaustion_drops().to_string().red(), ct.early_drop);
+        }
+    }
+
+    // 7. Events lost to full ring buffers or pipeline queues (from the
+    //    daemon's self-metrics in the state directory)
+    let state_dir = crate::config::Config::load()
+        .map(|c| c.state_dir)
+        .unwrap_or_else(|_| std::path::PathBuf::from("/var/lib/sennet"));
+    if let Ok(snapshot) = crate::selfmetrics::Snapshot::load(&state_dir) {
+        if snapshot.events_lost() > 0 {
+            let by_ring: Vec<String> = snapshot
+                .rings
+                .iter()
+                .filter(|r| r.lost() > 0)
+                .map(|r| format!("{} {}", r.ring, r.lost()))
+                .collect();
+            println!("Events lost:  {} ({})", snapshot.events_lost().to_string().red(), by_ring.join(", "));
+            println!("              {}", "drop, flow and reset data is incomplete".yellow());
+        }
+    }
+
+    // 8. Latency (persisted by the daemon in the state directory)
+    let latency = crate::latency::LatencyStore::load(&state_dir);
+    let mut latency_rows = latency.iter().filter(|(_, h)| !h.is_empty() || h.failures > 0).peekable();
+    if latency_rows.peek().is_some() {
+        println!();
+        println!("{}", "Latency (p50 / p95):".bold());
+        for (target, hist) in latency_rows {
+            let fmt_ms = |v: Option<f64>| v.map(|ms| format!("{:.1}ms", ms)).unwrap_or_else(|| "-".to_string());
+            let failures = if hist.failures > 0 {
+                format!("  {} failed", hist.failures).red().to_string()
+            } else {
+                String::new()
+            };
+            println!("  {:<30} {:>9} / {:<9} ({} samples){}",
+                     target, fmt_ms(hist.p50()), fmt_ms(hist.p95()), hist.len(), failures);
+        }
+    }
+
+    if verbose {
+        print_self_metrics(&state_dir);
+    }
+
+    // 9. Kubernetes Context (Phase 7)
     let k8s_info = check_kubernetes_context();
     println!();
     println!("{}", "Kubernetes:".bold());
@@ -53,6 +128,130 @@ pub fn run() -> Result<()> {
     Ok(())
 }
 
+/// PID, uptime, interface and backend as reported by the running daemon
+#[cfg(unix)]
+fn print_daemon_status(daemon: &crate::control::DaemonStatus) {
+    println!("PID:          {}", daemon.pid);
+    println!("Uptime:       {}", format_uptime(daemon.uptime_secs));
+    println!("Version:      {}", daemon.version);
+    if daemon.interface.is_empty() {
+        println!("Interface:    {}", "None (eBPF disabled)".dimmed());
+    } else {
+        println!("Interface:    {}", daemon.interface);
+    }
+    if daemon.offline {
+        println!("Backend:      {}", "Offline mode".dimmed());
+    } else {
+        match daemon.heartbeat_age_secs {
+            Some(age) => println!("Backend:      {} (last heartbeat {}s ago)", "Connected".green(), age),
+            None => println!("Backend:      {}", "No heartbeat yet".yellow()),
+        }
+    }
+    if !daemon.ebpf_attached {
+        println!("eBPF:         {}", "Not attached".red());
+    }
+    if !daemon.labels.is_empty() {
+        let labels: Vec<String> = daemon.labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        println!("Labels:       {}", labels.join(", "));
+    }
+}
+
+#[cfg(unix)]
+fn format_uptime(secs: u64) -> String {
+    let (days, hours, mins) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m {}s", mins, secs % 60),
+        (0, _) => format!("{}h {}m", hours, mins),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+/// PID, uptime, interface and backend pieced together from the init
+/// system and the agent's logs, for daemons without a control socket
+fn print_service_details(init: &InitSystem, config: Option<&crate::config::Config>) {
+    if let Some(pid) = init.main_pid() {
+        println!("PID:          {}", pid);
+    }
+    if let Some(uptime) = init.uptime() {
+        println!("Uptime:       {}", uptime);
+    }
+
+    // Interface from the agent's own log file when configured, otherwise
+    // wherever the init system sends its output
+    let log_file = config.and_then(|c| c.log_file.clone());
+    let recent_logs = |lines, since_secs| match &log_file {
+        Some(path) => crate::service::tail_file(path, lines),
+        None => init.recent_logs(lines, since_secs),
+    };
+    match get_interface_from_logs(recent_logs(50, None)) {
+        Some(interface) => println!("Interface:    {}", interface),
+        None => println!("Interface:    {}", "Unknown".dimmed()),
+    }
+
+    if config.is_some_and(|c| c.offline) {
+        println!("Backend:      {}", "Offline mode".dimmed());
+    } else if check_backend_connection(recent_logs(20, Some(120))) {
+        println!("Backend:      {}", "Connected".green());
+    } else {
+        println!("Backend:      {}", "Disconnected / Error".red());
+    }
+}
+
+/// Agent internals from the snapshot the daemon writes to the state directory
+fn print_self_metrics(state_dir: &Path) {
+    println!();
+    println!("{}", "Agent Metrics:".bold());
+    let snapshot = match crate::selfmetrics::Snapshot::load(state_dir) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            println!("  {}", e.to_string().dimmed());
+            return;
+        }
+    };
+
+    for ring in &snapshot.rings {
+        let lost = match ring.reserve_failures {
+            Some(0) => "0 lost".green(),
+            Some(n) => format!("{} lost", n).red(),
+            None => "lost: n/a".dimmed(),
+        };
+        let queue_full = if ring.queue_full > 0 {
+            format!(", {} dropped (queue full)", ring.queue_full).red().to_string()
+        } else {
+            String::new()
+        };
+        println!("  Ring {:<11} {:>12} consumed, {}{}", ring.ring, ring.consumed, lost, queue_full);
+    }
+    for stage in &snapshot.stages {
+        let dropped = if stage.dropped > 0 { stage.dropped.to_string().red() } else { "0".green() };
+        println!("  Stage {:<10} {:>12} processed, {} dropped", stage.stage, stage.processed, dropped);
+    }
+    for queue in &snapshot.queues {
+        if queue.capacity > 0 {
+            println!("  Queue {:<10} {:>12} / {}", queue.queue, queue.depth, queue.capacity);
+        } else {
+            println!("  Queue {:<10} {:>12}", queue.queue, queue.depth);
+        }
+    }
+    if let Some(ratio) = snapshot.ifname_hit_ratio() {
+        println!("  Ifname cache:     {:.1}% hits", ratio * 100.0);
+    }
+    println!("  Deep enrichment:  {} enriched, {} skipped", snapshot.deep_enriched, snapshot.deep_skipped);
+    if snapshot.undecodable > 0 {
+        println!(
+            "  Undecodable:      {} (eBPF object and agent are from different builds)",
+            snapshot.undecodable.to_string().red()
+        );
+    }
+    let failed = if snapshot.heartbeats_failed > 0 { snapshot.heartbeats_failed.to_string().red() } else { "0".green() };
+    println!("  Heartbeats:       {} ok, {} failed", snapshot.heartbeats_ok, failed);
+    println!("  Exporter errors:  {}", snapshot.exporter_errors);
+    if let Some(entries) = snapshot.flow_map_entries {
+        println!("  Flow map:         {}/{}", entries, snapshot.flow_map_capacity);
+    }
+    println!("  Memory (RSS):     {:.1}MB", snapshot.memory_rss_bytes as f64 / (1024.0 * 1024.0));
+}
+
 struct K8sInfo {
     in_cluster: bool,
     cni_type: String,
@@ -104,59 +303,83 @@ fn detect_cni_type() -> String {
     "Generic".to_string()
 }
 
-fn check_service_status() -> String {
-    let output = Command::new("systemctl")
-        .arg("is-active")
-        .arg("sennet")
-        .output();
+fn get_interface_from_logs(logs: Option<String>) -> Option<String> {
+    logs?
+        .lines()
+        .rev()
+        .find(|line| line.contains("Network interface:"))
+        .and_then(|line| line.split_whitespace().last())
+        .map(String::from)
+}
 
-    match output {
-        Ok(out) => String::from_utf8_lossy(&out.stdout).trim().to_string(),
-        Err(_) => "unknown".to_string(),
-    }
+fn check_backend_connection(logs: Option<String>) -> bool {
+    // Check for recent heartbeat success
+    logs.is_some_and(|logs| logs.lines().any(|line| line.contains("Heartbeat successful") || line.contains("heartbeat")))
 }
 
-fn get_service_details() -> Result<(String, String)> {
-    let output = Command::new("systemctl")
-        .arg("show")
-        .arg("sennet")
-        .arg("--property=ActiveEnterTimestamp,MainPID")
-        .output()?;
-
-    let out_str = String::from_utf8_lossy(&output.stdout);
-    let mut pid = String::new();
-    let mut uptime = String::new();
-
-    for line in out_str.lines() {
-        if line.starts_with("MainPID=") {
-            pid = line.replace("MainPID=", "");
-        } else if line.starts_with("ActiveEnterTimestamp=") {
-            uptime = line.replace("ActiveEnterTimestamp=", "");
-        }
+/// Counters of each interface the classifiers are attached to, from the
+/// daemon or the pinned maps; nothing if neither can be read
+#[cfg(target_os = "linux")]
+fn print_ebpf_interface_counters() {
+    let counters = crate::control::Client::connect()
+        .and_then(|mut client| client.interface_counters().ok())
+        .or_else(|| crate::ebpf::read_pinned_interface_counters().ok());
+    let Some(counters) = counters.filter(|counters| !counters.is_empty()) else {
+        return;
+    };
+    println!("Interfaces:");
+    for (ifindex, c) in &counters {
+        let name = crate::ifnames::cache().get(*ifindex).map(|n| n.to_string()).unwrap_or_else(|| format!("if{}", ifindex));
+        println!(
+            "  {:<20} rx {} pkts / {} B, tx {} pkts / {} B",
+            name, c.rx_packets, c.rx_bytes, c.tx_packets, c.tx_bytes
+        );
     }
-
-    Ok((uptime, pid))
 }
 
-fn get_interface_from_logs() -> Result<String> {
-    // Grep logs for "Network interface: "
-    let output = Command::new("bash")
-        .arg("-c")
-        .arg("journalctl -u sennet -n 50 | grep 'Network interface:' | tail -n 1 | awk '{print $NF}'")
-        .output()?;
-        
-    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+/// Drops since the agent started, by reason, from the daemon
+#[cfg(target_os = "linux")]
+fn print_drop_reasons() {
+    let Some(report) = crate::control::Client::connect().and_then(|mut client| client.drops().ok()) else {
+        return;
+    };
+    if report.by_reason.is_empty() {
+        return;
+    }
+    let mut reasons: Vec<_> = report.by_reason.into_iter().collect();
+    reasons.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    let top: Vec<String> = reasons.iter().take(5).map(|(reason, count)| format!("{} {}", reason, count)).collect();
+    println!("Drops:        {}", top.join(", "));
 }
 
-fn check_backend_connection() -> bool {
-    // Check for recent heartbeat success
-    let output = Command::new("bash")
-        .arg("-c")
-        .arg("journalctl -u sennet -n 20 --since '2 minutes ago' | grep -E 'Heartbeat successful|heartbeat'")
-        .output();
-
-    match output {
-        Ok(out) => !out.stdout.is_empty(),
-        Err(_) => false,
+#[cfg(any(windows, target_os = "macos"))]
+fn print_interface_counters() {
+    let interfaces = match crate::ifstats::read_interfaces() {
+        Ok(interfaces) => interfaces,
+        Err(e) => {
+            println!("Interfaces:   {} ({})", "Unavailable".red(), e);
+            return;
+        }
+    };
+    let configured = crate::config::Config::load().ok().and_then(|c| c.interface);
+    let selected = crate::ifstats::select(&interfaces, configured.as_deref());
+    if selected.is_empty() {
+        println!("Interfaces:   {}", "None up".yellow());
+        return;
+    }
+    println!("Interfaces:");
+    for iface in selected {
+        let discards = iface.rx_discards + iface.tx_discards;
+        let discards = if discards > 0 { discards.to_string().yellow() } else { discards.to_string().normal() };
+        println!(
+            "  {:<20} rx {} pkts / {} B, tx {} pkts / {} B, discarded {}, errors {}",
+            iface.alias,
+            iface.rx_packets,
+            iface.rx_bytes,
+            iface.tx_packets,
+            iface.tx_bytes,
+            discards,
+            iface.rx_errors + iface.tx_errors
+        );
     }
 }
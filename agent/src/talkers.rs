@@ -0,0 +1,176 @@
+//! Top Talkers CLI Command
+//!
+//! Shows the source/destination pairs moving the most bytes, from the
+//! TOP_TALKERS map the TC/XDP programs fill for every IPv4 and IPv6 packet.
+//! The map is an LRU, so pairs idle long enough to be evicted by busier
+//! ones drop out.
+//! Usage: sennet top-talkers [OPTIONS]
+
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::ebpf::{EbpfManager, TopTalker};
+use crate::flows::format_bytes;
+
+/// Sort field for top talkers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Bytes,
+    Packets,
+}
+
+/// Options for the top-talkers command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TalkersOptions {
+    pub sort_by: SortField,
+    pub limit: usize,
+    pub json: bool,
+}
+
+impl Default for TalkersOptions {
+    fn default() -> Self {
+        Self {
+            sort_by: SortField::Bytes,
+            limit: 20,
+            json: false,
+        }
+    }
+}
+
+/// Print help for the top-talkers command
+pub fn print_help() {
+    println!("{}", "Sennet Top Talkers - Busiest Source/Destination Pairs".bold());
+    println!("Show which address pairs account for the most traffic on the interface.");
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet top-talkers [OPTIONS]");
+    println!();
+    println!("{}", "OPTIONS:".yellow());
+    println!("    --sort <FIELD>     Sort by: bytes, packets (default: bytes)");
+    println!("    --limit <N>        Show only the top N pairs (default: 20)");
+    println!("    --json             Print JSON instead of a table");
+    println!("    -h, --help         Show this help message");
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sennet top-talkers                 # Who is using the bandwidth");
+    println!("    sennet top-talkers --sort packets  # Packet floods with small packets");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    - Counts are totals since the agent loaded its eBPF programs");
+    println!("    - Each direction of a conversation is its own pair");
+    println!("    - Requires the agent's control socket, or root to read the pinned maps");
+}
+
+/// Parse command line arguments for the top-talkers command
+pub fn parse_args(args: &[String]) -> TalkersOptions {
+    let mut opts = TalkersOptions::default();
+    let mut i = 0;
+
+    while i < args.len() {
+        match args[i].as_str() {
+            "--sort" if i + 1 < args.len() => {
+                opts.sort_by = match args[i + 1].as_str() {
+                    "packets" => SortField::Packets,
+                    _ => SortField::Bytes,
+                };
+                i += 1;
+            }
+            "--limit" if i + 1 < args.len() => {
+                opts.limit = args[i + 1].parse().unwrap_or(20);
+                i += 1;
+            }
+            "--json" => opts.json = true,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    opts
+}
+
+#[cfg(unix)]
+fn daemon_top_talkers() -> Option<Vec<TopTalker>> {
+    crate::control::Client::connect()?.top_talkers().ok()
+}
+
+#[cfg(not(unix))]
+fn daemon_top_talkers() -> Option<Vec<TopTalker>> {
+    None
+}
+
+/// Run the top-talkers command
+pub fn run(args: &[String]) -> Result<()> {
+    let opts = parse_args(args);
+
+    // Ask the running agent first: loading eBPF here needs root
+    let mut talkers = match daemon_top_talkers() {
+        Some(talkers) => talkers,
+        None => match crate::ebpf::read_pinned_top_talkers() {
+            Ok(talkers) => talkers,
+            Err(_) => {
+                let interface = crate::interface::discover_default_interface(None)?;
+                EbpfManager::load_and_attach(&interface)?.read_top_talkers()?
+            }
+        },
+    };
+
+    if opts.sort_by == SortField::Packets {
+        talkers.sort_by(|a, b| b.packets.cmp(&a.packets).then(b.bytes.cmp(&a.bytes)));
+    }
+    let total_bytes: u64 = talkers.iter().map(|t| t.bytes).sum();
+    talkers.truncate(opts.limit);
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&talkers)?);
+        return Ok(());
+    }
+
+    if talkers.is_empty() {
+        println!("{}", "No traffic recorded yet.".yellow());
+        return Ok(());
+    }
+
+    println!("{}", "Sennet Top Talkers".bold());
+    println!("{}", "═".repeat(100));
+    println!(
+        "{:<39} {:<39} {:>10} {:>10} {:>6}",
+        "SOURCE".cyan(),
+        "DESTINATION".cyan(),
+        "PACKETS".cyan(),
+        "BYTES".cyan(),
+        "SHARE".cyan()
+    );
+    println!("{}", "─".repeat(100));
+
+    for talker in &talkers {
+        let share = if total_bytes > 0 { talker.bytes as f64 * 100.0 / total_bytes as f64 } else { 0.0 };
+        println!(
+            "{:<39} {:<39} {:>10} {:>10} {:>5.1}%",
+            talker.src.to_string(),
+            talker.dst.to_string(),
+            talker.packets,
+            format_bytes(talker.bytes),
+            share,
+        );
+    }
+
+    println!("{}", "─".repeat(100));
+    println!("Total: {} across all pairs", format_bytes(total_bytes));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args: Vec<String> = ["--sort", "packets", "--limit", "5", "--json"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            parse_args(&args),
+            TalkersOptions { sort_by: SortField::Packets, limit: 5, json: true }
+        );
+        assert_eq!(parse_args(&[]), TalkersOptions::default());
+        assert_eq!(parse_args(&["--limit".to_string(), "x".to_string()]).limit, 20);
+    }
+}
@@ -0,0 +1,69 @@
+//! OpenTelemetry Export
+//!
+//! With `otlp_endpoint` set, tracing spans around the agent's key
+//! operations are exported over OTLP/HTTP to a collector:
+//!
+//! - `ebpf.load`: loading and attaching the eBPF programs
+//! - `heartbeat.rpc`: one heartbeat round trip to the control plane
+//! - `pipeline.export`: handing a flushed event summary to a sink
+//! - `rollup.flush`: draining the kernel flow map and saving a rollup window
+//! - `k8s.connect` and `k8s.sync`: reaching the API server and (re)listing
+//!   pods and network policies
+//!
+//! Spans are batched and sent from a background task. If the collector is
+//! unreachable they are dropped; logging and the agent carry on.
+
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::runtime;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::Resource;
+
+/// Build a tracer that exports to `endpoint` (e.g. `http://collector:4318`)
+///
+/// Needs a running tokio runtime. The provider must be shut down on exit to
+/// flush spans still in the batch.
+pub fn tracer(endpoint: &str) -> Result<(Tracer, TracerProvider)> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(traces_url(endpoint))
+        .build()
+        .context("Failed to create OTLP exporter")?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(
+            [
+                KeyValue::new("service.name", "sennet-agent"),
+                KeyValue::new("service.version", crate::upgrade::CURRENT_VERSION),
+            ]
+            .into_iter()
+            .chain(crate::labels::get().iter().map(|(k, v)| KeyValue::new(k.clone(), v.clone()))),
+        ))
+        .build();
+    let tracer = provider.tracer("sennet");
+    Ok((tracer, provider))
+}
+
+/// OTLP/HTTP traces URL for a collector base URL
+fn traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traces_url() {
+        assert_eq!(traces_url("http://collector:4318"), "http://collector:4318/v1/traces");
+        assert_eq!(traces_url("http://collector:4318/"), "http://collector:4318/v1/traces");
+        assert_eq!(traces_url("https://otel.example.com/v1/traces"), "https://otel.example.com/v1/traces");
+    }
+}
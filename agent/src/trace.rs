@@ -1,29 +1,98 @@
 //! Packet Trace Command (Phase 6.4)
 //!
 //! One-shot packet tracing for debugging.
-//! Usage: sennet trace [OPTIONS]
+//! Usage: sennet trace [OPTIONS] [EXPRESSION]
 //!
 //! Options:
+//!   -f, --filter <EXPR>  Filter expression (see [crate::filter])
 //!   --dst <IP[:PORT]>    Filter by destination
 //!   --src <IP[:PORT]>    Filter by source
 //!   --proto <tcp|udp|icmp>  Filter by protocol
 //!   --count <N>          Stop after N events (default: 20)
 //!   --timeout <SECS>     Stop after seconds (default: 30)
+//!   -T, --wall-clock     Print event times as wall-clock time
+//!   --max-per-reason <N/s>  Show at most N drops per second of each reason
+//!   --stacks             Show the kernel stack that freed each dropped packet
+//!   --payload [N]        Hex dump the first N bytes of matching packets
+//!
+//! Reading the pinned rings directly, the filter's reason, address, port
+//! and protocol terms are also loaded into the kernel (TRACE_RULE), so
+//! non-matching events never reach the rings. With `--payload` the same terms
+//! go into PAYLOAD_RULE, which has the classifiers copy the head of each
+//! matching packet into PAYLOAD_EVENTS, a ring only the trace reads; it is
+//! read directly even while the other events come from the agent's stream.
+//!
+//! A live trace looks dropped packets up in conntrack, and prints the
+//! SNAT/DNAT translated tuple under drops of translated connections, as
+//! `sennet flows` does for flows.
 
 use anyhow::Result;
 use colored::Colorize;
 use std::time::{Duration, Instant};
+use crate::filter::{Field, Filter};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::ratelimit::ReasonLimiter;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::collections::BTreeMap;
+#[cfg(target_os = "linux")]
+use std::time::SystemTime;
+#[cfg(target_os = "linux")]
+use crate::control::StreamRecord;
+#[cfg(target_os = "linux")]
+use crate::events::{RawEvent, RingKind};
+#[cfg(target_os = "linux")]
+use crate::ebpf::PayloadRule;
+#[cfg(target_os = "linux")]
+use crate::attribution::{DropTally, FlowOwners};
+#[cfg(target_os = "linux")]
+use crate::firewall::RuleRef;
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+
+/// How often `trace` re-reads the flow table used to attribute drops
+#[cfg(target_os = "linux")]
+const FLOW_REFRESH: Duration = Duration::from_secs(2);
+
+/// Bytes `--payload` captures without a count: Ethernet, IP and TCP headers
+/// with some room for options
+const DEFAULT_PAYLOAD: usize = 64;
 
 /// Filter configuration for tracing
 #[derive(Default, Debug)]
 pub struct TraceFilter {
-    pub dst_ip: Option<String>,
-    pub dst_port: Option<u16>,
-    pub src_ip: Option<String>,
-    pub src_port: Option<u16>,
-    pub protocol: Option<String>,
+    /// Which events to show (see [crate::filter])
+    pub filter: Filter,
     pub count: usize,
     pub timeout_secs: u64,
+    /// Print the events' wall-clock time instead of seconds since start
+    pub wall_clock: bool,
+    /// Drops shown per second for each reason (0 = unlimited)
+    pub max_per_reason: f64,
+    /// Print the kernel stack under each drop
+    pub stacks: bool,
+    /// Leading bytes of matching packets to capture (0 = none)
+    pub payload: usize,
+}
+
+/// Fields a trace filter can use
+const TRACE_FIELDS: &[Field] = &[
+    Field::Src,
+    Field::Dst,
+    Field::Host,
+    Field::Sport,
+    Field::Dport,
+    Field::Port,
+    Field::Proto,
+    Field::Family,
+    Field::Reason,
+];
+
+/// Expression for the legacy `--dst IP[:PORT]` and `--src IP[:PORT]` flags
+pub fn endpoint_expr(addr_field: &str, port_field: &str, value: &str) -> String {
+    match value.split_once(':') {
+        Some((ip, port)) => format!("{} == {} && {} == {}", addr_field, ip, port_field, port),
+        None => format!("{} == {}", addr_field, value),
+    }
 }
 
 impl TraceFilter {
@@ -33,37 +102,35 @@ impl TraceFilter {
             timeout_secs: 30,
             ..Default::default()
         };
+        let mut exprs: Vec<String> = Vec::new();
+        let mut words: Vec<&str> = Vec::new();
         
         let mut i = 0;
         while i < args.len() {
             match args[i].as_str() {
+                "--filter" | "-f" => {
+                    if i + 1 < args.len() {
+                        exprs.push(args[i + 1].clone());
+                        i += 1;
+                    }
+                }
                 "--dst" => {
                     if i + 1 < args.len() {
-                        let dst = &args[i + 1];
-                        if let Some((ip, port)) = dst.split_once(':') {
-                            filter.dst_ip = Some(ip.to_string());
-                            filter.dst_port = port.parse().ok();
-                        } else {
-                            filter.dst_ip = Some(dst.clone());
-                        }
+                        exprs.push(endpoint_expr("dst", "dport", &args[i + 1]));
                         i += 1;
                     }
                 }
                 "--src" => {
                     if i + 1 < args.len() {
-                        let src = &args[i + 1];
-                        if let Some((ip, port)) = src.split_once(':') {
-                            filter.src_ip = Some(ip.to_string());
-                            filter.src_port = port.parse().ok();
-                        } else {
-                            filter.src_ip = Some(src.clone());
-                        }
+                        exprs.push(endpoint_expr("src", "sport", &args[i + 1]));
                         i += 1;
                     }
                 }
                 "--proto" => {
                     if i + 1 < args.len() {
-                        filter.protocol = Some(args[i + 1].to_lowercase());
+                        let proto = args[i + 1].to_lowercase();
+                        let field = if proto.starts_with("ipv") { "family" } else { "proto" };
+                        exprs.push(format!("{} == {}", field, proto));
                         i += 1;
                     }
                 }
@@ -79,13 +146,47 @@ impl TraceFilter {
                         i += 1;
                     }
                 }
+                "--wall-clock" | "-T" => filter.wall_clock = true,
+                "--stacks" => filter.stacks = true,
+                "--payload" => {
+                    filter.payload = DEFAULT_PAYLOAD;
+                    if let Some(Ok(n)) = args.get(i + 1).map(|a| a.parse::<usize>()) {
+                        filter.payload = n;
+                        i += 1;
+                    }
+                    let max = crate::events::PAYLOAD_SNIPPET_MAX;
+                    if !(1..=max).contains(&filter.payload) {
+                        anyhow::bail!("--payload takes 1 to {} bytes", max);
+                    }
+                }
+                "--max-per-reason" => {
+                    if i + 1 < args.len() {
+                        filter.max_per_reason = crate::ratelimit::parse_rate(&args[i + 1])?;
+                        i += 1;
+                    }
+                }
+                // Anything else not starting with a dash is part of an expression
+                word if !word.starts_with('-') => words.push(word),
                 _ => {}
             }
             i += 1;
         }
+        if !words.is_empty() {
+            exprs.push(words.join(" "));
+        }
         
+        for expr in &exprs {
+            filter.filter = std::mem::take(&mut filter.filter).and(Filter::parse(expr)?);
+        }
+        filter.filter.check_fields(TRACE_FIELDS, "trace")?;
         Ok(filter)
     }
+
+    /// Whether a flow passes the filter
+    #[cfg(any(target_os = "macos", test))]
+    pub fn matches_flow(&self, signal: &crate::capture::Signal) -> bool {
+        self.filter.matches(signal)
+    }
 }
 
 /// Run the trace command
@@ -95,29 +196,7 @@ pub fn run(args: &[String]) -> Result<()> {
     println!("{}", "Sennet Packet Trace".bold());
     println!("Watching for packet drops and netfilter events...");
     println!();
-    
-    // Print active filters
-    if filter.dst_ip.is_some() || filter.src_ip.is_some() || filter.protocol.is_some() {
-        print!("Filters: ");
-        if let Some(ref dst) = filter.dst_ip {
-            print!("dst={}", dst.cyan());
-            if let Some(port) = filter.dst_port {
-                print!(":{}", port.to_string().cyan());
-            }
-            print!(" ");
-        }
-        if let Some(ref src) = filter.src_ip {
-            print!("src={}", src.cyan());
-            if let Some(port) = filter.src_port {
-                print!(":{}", port.to_string().cyan());
-            }
-            print!(" ");
-        }
-        if let Some(ref proto) = filter.protocol {
-            print!("proto={}", proto.cyan());
-        }
-        println!();
-    }
+    print_filters(&filter);
     
     println!("Limit: {} events, {}s timeout", 
              filter.count.to_string().yellow(),
@@ -128,10 +207,17 @@ pub fn run(args: &[String]) -> Result<()> {
     // Try to read from pinned maps
     #[cfg(target_os = "linux")]
     {
-        run_linux_trace(&filter)?;
+        if let Some(source) = TraceSource::open(&filter) {
+            run_linux_trace(&filter, source)?;
+        }
     }
     
-    #[cfg(not(target_os = "linux"))]
+    #[cfg(target_os = "macos")]
+    {
+        run_pcap_trace(&filter)?;
+    }
+    
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
     {
         run_mock_trace(&filter)?;
     }
@@ -139,77 +225,463 @@ pub fn run(args: &[String]) -> Result<()> {
     Ok(())
 }
 
+/// Show a recording (`sennet replay`) the way trace shows live events
 #[cfg(target_os = "linux")]
-fn run_linux_trace(filter: &TraceFilter) -> Result<()> {
-    use std::path::Path;
-    use aya::maps::{Map, MapData, RingBuf};
-    use crate::ebpf::{DropEvent, NetfilterEvent, drop_reason_str, eth_proto_str, nf_hook_str, nf_verdict_str};
-    
-    let drop_path = Path::new("/sys/fs/bpf/sennet/drop_events");
-    let nf_path = Path::new("/sys/fs/bpf/sennet/nf_events");
-    
-    if !drop_path.exists() && !nf_path.exists() {
-        println!("{}: Pinned maps not found. Is the agent running?", "Warning".yellow());
-        println!("Run '{}' first, then use trace.", "sudo sennet".cyan());
-        return Ok(());
-    }
-    
-    // Open DROP_EVENTS RingBuf (Phase 6.1)
-    let mut drop_rb: Option<RingBuf<MapData>> = if drop_path.exists() {
-        match MapData::from_pin(drop_path) {
-            Ok(data) => {
-                let map = Map::RingBuf(data);
-                match map.try_into() {
-                    Ok(rb) => Some(rb),
-                    Err(e) => {
-                        eprintln!("{}: Failed to convert drop_events to RingBuf: {:?}", "Debug".blue(), e);
-                        None
-                    }
-                }
+pub fn replay(args: &[String], replay: crate::replay::FileReplay) -> Result<()> {
+    let mut filter = TraceFilter::parse(args)?;
+    // The whole recording, unless limited
+    if !args.iter().any(|a| a == "--count" || a == "-c") {
+        filter.count = usize::MAX;
+    }
+    if !args.iter().any(|a| a == "--timeout" || a == "-t") {
+        filter.timeout_secs = u64::MAX;
+    }
+    
+    let header = &replay.header;
+    println!("{}", "Sennet Packet Trace (replay)".bold());
+    println!("Recorded {} by agent {} (v{})", header.started, header.agent_id, header.agent_version);
+    println!();
+    print_filters(&filter);
+    println!("{}", "─".repeat(60));
+    
+    run_linux_trace(&filter, TraceSource::Replay(Box::new(replay)))
+}
+
+/// Print the active filter and limit, if any
+fn print_filters(filter: &TraceFilter) {
+    if !filter.filter.is_empty() {
+        println!("Filter: {}", filter.filter.to_string().cyan());
+    }
+    if filter.max_per_reason > 0.0 {
+        println!("Showing at most {}/s drops per reason", filter.max_per_reason.to_string().cyan());
+    }
+}
+
+/// Print how many events of each reason `--max-per-reason` held back
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn print_suppressed<K: std::fmt::Display>(suppressed: &BTreeMap<K, u64>) {
+    if suppressed.is_empty() {
+        return;
+    }
+    let mut reasons: Vec<_> = suppressed.iter().collect();
+    reasons.sort_by_key(|(_, n)| std::cmp::Reverse(**n));
+    let reasons: Vec<String> = reasons.iter().map(|(reason, n)| format!("{} ×{}", reason, n)).collect();
+    println!("{}: {}", "Held back by --max-per-reason".dimmed(), reasons.join(", "));
+}
+
+/// Where `trace` gets events from
+#[cfg(target_os = "linux")]
+enum TraceSource {
+    /// The running agent's control socket, and packet heads with --payload
+    Daemon(crate::control::EventStream, Option<PayloadRing>),
+    /// The pinned ring buffers, read directly (needs root)
+    Pinned(Box<PinnedRings>),
+    /// A recording (`sennet replay`)
+    Replay(Box<crate::replay::FileReplay>),
+}
+
+#[cfg(target_os = "linux")]
+struct PinnedRings {
+    drop_rb: Option<aya::maps::RingBuf<aya::maps::MapData>>,
+    nf_rb: Option<aya::maps::RingBuf<aya::maps::MapData>>,
+    /// Packet heads, with --payload
+    payloads: Option<PayloadRing>,
+    losses: crate::ebpf::LossTracker,
+    /// Whether the trace filter was pushed into the kernel (cleared on drop)
+    rule_set: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for PinnedRings {
+    fn drop(&mut self) {
+        if self.rule_set {
+            let _ = crate::ebpf::set_pinned_trace_rule(&Default::default());
+        }
+    }
+}
+
+/// PAYLOAD_EVENTS, read directly while the trace keeps PAYLOAD_RULE set
+///
+/// Packet contents never go through the agent: its stream is open to
+/// unprivileged clients.
+#[cfg(target_os = "linux")]
+struct PayloadRing {
+    rb: aya::maps::RingBuf<aya::maps::MapData>,
+    losses: crate::ebpf::LossTracker,
+}
+
+#[cfg(target_os = "linux")]
+impl PayloadRing {
+    /// Open the ring and ask for the heads of the packets matching the
+    /// filter; None (with a warning) if the agent can't provide them
+    fn open(filter: &TraceFilter) -> Option<Self> {
+        let payloads = crate::ebpf::open_pinned_ringbuf(RingKind::Payload.pin_name()).and_then(|rb| {
+            let rule = PayloadRule { rule: kernel_rule(filter), len: filter.payload as u8, ..Default::default() };
+            crate::ebpf::set_pinned_payload_rule(&rule)?;
+            Ok(Self { rb, losses: crate::ebpf::LossTracker::new() })
+        });
+        match payloads {
+            Ok(payloads) => {
+                println!("{}", "Reading packet heads from the pinned ring (needs root)".dimmed());
+                Some(payloads)
             }
             Err(e) => {
-                eprintln!("{}: Failed to open drop_events from pin: {:?}", "Debug".blue(), e);
+                println!("{}: packet payloads unavailable: {}", "Warning".yellow(), e);
                 None
             }
         }
-    } else {
-        eprintln!("{}: drop_events path does not exist", "Debug".blue());
-        None
-    };
+    }
+
+    /// Packet heads since the last poll, and how many were lost meanwhile
+    fn poll(&mut self, debug: bool) -> Result<(Vec<RawEvent>, u64)> {
+        let lost = self.losses.poll()[RingKind::Payload.index()];
+        Ok((drain_ring(&mut self.rb, debug)?, lost))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for PayloadRing {
+    fn drop(&mut self) {
+        let _ = crate::ebpf::set_pinned_payload_rule(&Default::default());
+    }
+}
+
+/// How long a kernel trace filter outlives the trace's own timeout, in
+/// case the trace is killed before it can clear it
+#[cfg(target_os = "linux")]
+const KERNEL_FILTER_GRACE: Duration = Duration::from_secs(5);
+
+/// Kernel-checkable part of the trace filter, lapsing shortly after the
+/// trace's own timeout
+#[cfg(target_os = "linux")]
+fn kernel_rule(filter: &TraceFilter) -> crate::ebpf::TraceRule {
+    let lifetime = Duration::from_secs(filter.timeout_secs) + KERNEL_FILTER_GRACE;
+    let now = crate::clock::Clocks::read().monotonic as u64;
+    filter.filter.trace_rule(now + lifetime.as_nanos() as u64)
+}
+
+/// Push the kernel-checkable part of the trace filter into the pinned
+/// TRACE_RULE map; false (with a note) if the agent can't take it
+#[cfg(target_os = "linux")]
+fn set_kernel_filter(filter: &TraceFilter) -> bool {
+    match crate::ebpf::set_pinned_trace_rule(&kernel_rule(filter)) {
+        Ok(()) => {
+            println!("{}", "Filtering in the kernel".dimmed());
+            true
+        }
+        Err(e) => {
+            println!("{}: {} (filtering in userspace only)", "Note".dimmed(), e);
+            false
+        }
+    }
+}
+
+/// When a polled event happened
+#[cfg(target_os = "linux")]
+struct Stamp {
+    /// Since the trace (or the recording) started
+    elapsed: Duration,
+    wall: SystemTime,
+}
+
+/// Events with the kernel events each stands for, and (source, count) of
+/// events lost
+#[cfg(target_os = "linux")]
+type Polled = (Vec<(RawEvent, u64, Stamp)>, Vec<(String, u64, Stamp)>);
+
+#[cfg(target_os = "linux")]
+impl TraceSource {
+    /// The running agent's event stream, or else the pinned ring buffers
+    fn open(filter: &TraceFilter) -> Option<Self> {
+        // Prefer the agent's event stream: it needs no privileges, and reading
+        // the rings directly competes with the agent's own pipeline for events
+        match crate::control::Client::connect().and_then(|client| client.events().ok()) {
+            Some(stream) => {
+                println!("{}", "Streaming events from the running agent".dimmed());
+                let payloads = if filter.payload > 0 { PayloadRing::open(filter) } else { None };
+                Some(TraceSource::Daemon(stream, payloads))
+            }
+            None => Self::open_pinned(filter),
+        }
+    }
+    
+    /// Whether events come from the live kernel rather than a recording
+    fn is_live(&self) -> bool {
+        !matches!(self, TraceSource::Replay(_))
+    }
     
-    // Open NF_EVENTS RingBuf (Phase 6.2)
-    let mut nf_rb: Option<RingBuf<MapData>> = if nf_path.exists() {
-        match MapData::from_pin(nf_path) {
-            Ok(data) => {
-                let map = Map::RingBuf(data);
-                match map.try_into() {
-                    Ok(rb) => Some(rb),
-                    Err(e) => {
-                        eprintln!("{}: Failed to convert nf_events to RingBuf: {:?}", "Debug".blue(), e);
-                        None
+    /// Open the pinned ring buffers; None if the agent never pinned them
+    ///
+    /// Reading the rings directly, the trace is their consumer, so it also
+    /// pushes its filter into the kernel for as long as it runs. The agent's
+    /// stream is left alone: the pipeline needs every event.
+    fn open_pinned(filter: &TraceFilter) -> Option<Self> {
+        use aya::maps::{Map, MapData, RingBuf};
+        
+        let drop_path = crate::ebpf::pin_dir().join("drop_events");
+        let nf_path = crate::ebpf::pin_dir().join("nf_events");
+        
+        if !drop_path.exists() && !nf_path.exists() {
+            println!(
+                "{}: Pinned maps not found. Is the agent running?{}",
+                "Warning".yellow(),
+                crate::ebpf::pin_namespace_hint()
+            );
+            println!("Run '{}' first, then use trace.", "sudo sennet".cyan());
+            return None;
+        }
+        
+        // Open DROP_EVENTS RingBuf (Phase 6.1)
+        let drop_rb: Option<RingBuf<MapData>> = if drop_path.exists() {
+            match MapData::from_pin(&drop_path) {
+                Ok(data) => {
+                    let map = Map::RingBuf(data);
+                    match map.try_into() {
+                        Ok(rb) => Some(rb),
+                        Err(e) => {
+                            eprintln!("{}: Failed to convert drop_events to RingBuf: {:?}", "Debug".blue(), e);
+                            None
+                        }
                     }
                 }
+                Err(e) => {
+                    eprintln!("{}: Failed to open drop_events from pin: {:?}", "Debug".blue(), e);
+                    None
+                }
             }
-            Err(e) => {
-                eprintln!("{}: Failed to open nf_events from pin: {:?}", "Debug".blue(), e);
+        } else {
+            eprintln!("{}: drop_events path does not exist", "Debug".blue());
+            None
+        };
+        
+        // Open NF_EVENTS RingBuf (Phase 6.2)
+        let nf_rb: Option<RingBuf<MapData>> = if nf_path.exists() {
+            match MapData::from_pin(&nf_path) {
+                Ok(data) => {
+                    let map = Map::RingBuf(data);
+                    match map.try_into() {
+                        Ok(rb) => Some(rb),
+                        Err(e) => {
+                            eprintln!("{}: Failed to convert nf_events to RingBuf: {:?}", "Debug".blue(), e);
+                            None
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}: Failed to open nf_events from pin: {:?}", "Debug".blue(), e);
+                    None
+                }
+            }
+        } else {
+            eprintln!("{}: nf_events path does not exist", "Debug".blue());
+            None
+        };
+        
+        if drop_rb.is_none() && nf_rb.is_none() {
+            println!("{}: Could not open any event maps (see debug messages above)", "Warning".yellow());
+        }
+        
+        let payloads = if filter.payload > 0 { PayloadRing::open(filter) } else { None };
+        
+        // Events the kernel couldn't queue because a ring buffer was full
+        let losses = crate::ebpf::LossTracker::new();
+        let rule_set = !filter.filter.is_empty() && set_kernel_filter(filter);
+        Some(TraceSource::Pinned(Box::new(PinnedRings { drop_rb, nf_rb, payloads, losses, rule_set })))
+    }
+    
+    /// Events since the last poll, and events lost meanwhile
+    fn poll(&mut self, debug: bool, start: Instant) -> Result<Polled> {
+        let mut events = Vec::new();
+        let mut gaps = Vec::new();
+        let live = |timestamp_ns: Option<u64>| Stamp {
+            elapsed: start.elapsed(),
+            wall: timestamp_ns.map_or_else(SystemTime::now, crate::clock::wall_time),
+        };
+        let payloads = match self {
+            TraceSource::Daemon(stream, payloads) => {
+                for record in stream.poll(Duration::from_millis(50))? {
+                    match record {
+                        StreamRecord::Event { raw, count, .. } => {
+                            events.push((*raw, count, live(Some(raw.timestamp_ns()))))
+                        }
+                        StreamRecord::Gap { ring, lost } => gaps.push((ring, lost, live(None))),
+                    }
+                }
+                payloads.as_mut()
+            }
+            TraceSource::Replay(replay) => {
+                let replayed = replay.poll(Duration::from_millis(50));
+                if replayed.is_empty() && replay.finished() {
+                    anyhow::bail!("End of recording");
+                }
+                for entry in replayed {
+                    let stamp = Stamp { elapsed: entry.offset, wall: entry.time };
+                    match entry.record {
+                        StreamRecord::Event { raw, count, .. } => events.push((*raw, count, stamp)),
+                        StreamRecord::Gap { ring, lost } => gaps.push((ring, lost, stamp)),
+                    }
+                }
                 None
             }
+            TraceSource::Pinned(rings) => {
+                let PinnedRings { drop_rb, nf_rb, payloads, losses, .. } = rings.as_mut();
+                let lost = losses.poll();
+                for kind in [RingKind::Drop, RingKind::Netfilter] {
+                    if lost[kind.index()] > 0 {
+                        gaps.push((kind.name().to_string(), lost[kind.index()], live(None)));
+                    }
+                }
+                for rb in [drop_rb, nf_rb].into_iter().flatten() {
+                    for raw in drain_ring(rb, debug)? {
+                        events.push((raw, raw.sample_weight(), live(Some(raw.timestamp_ns()))));
+                    }
+                }
+                // Small sleep to avoid busy loop
+                std::thread::sleep(Duration::from_millis(50));
+                payloads.as_mut()
+            }
+        };
+        if let Some(payloads) = payloads {
+            let (heads, lost) = payloads.poll(debug)?;
+            if lost > 0 {
+                gaps.push((RingKind::Payload.name().to_string(), lost, live(None)));
+            }
+            for raw in heads {
+                events.push((raw, raw.sample_weight(), live(Some(raw.timestamp_ns()))));
+            }
         }
+        Ok((events, gaps))
+    }
+}
+
+/// Decode the records waiting in a pinned ring buffer
+#[cfg(target_os = "linux")]
+fn drain_ring(rb: &mut aya::maps::RingBuf<aya::maps::MapData>, debug: bool) -> Result<Vec<RawEvent>> {
+    let mut events = Vec::new();
+    while let Some(item) = rb.next() {
+        // Debug: show raw event data
+        if debug {
+            eprintln!("Raw event bytes (len={}): {:02x?}", item.len(), &item[..item.len().min(24)]);
+        }
+        match crate::events::try_decode(&item) {
+            Ok(raw) => events.push(raw),
+            Err(crate::events::Undecodable::Truncated) => {}
+            // Every later record would be skipped too
+            Err(e) => anyhow::bail!("{}", e),
+        }
+    }
+    Ok(events)
+}
+
+/// Resolves the kernel stacks drops refer to into symbol names
+#[cfg(target_os = "linux")]
+struct DropStacks {
+    map: aya::maps::StackTraceMap<aya::maps::MapData>,
+    symbols: crate::kallsyms::KernelSymbols,
+}
+
+#[cfg(target_os = "linux")]
+impl DropStacks {
+    fn open() -> Result<Self> {
+        Ok(Self {
+            map: crate::ebpf::open_pinned_drop_stacks()?,
+            symbols: crate::kallsyms::KernelSymbols::load()?,
+        })
+    }
+
+    /// Frames of the stack, innermost first; empty once the kernel has
+    /// reused the slot for another stack
+    fn frames(&self, stack_id: u32) -> Vec<String> {
+        match self.map.get(&stack_id, 0) {
+            Ok(stack) => stack.frames().iter().map(|frame| self.symbols.format(frame.ip)).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Conntrack tuple of a dropped packet, for finding its SNAT/DNAT
+/// translation; None without addresses or ports
+#[cfg(target_os = "linux")]
+fn drop_tuple(event: &crate::ebpf::DropEvent) -> Option<crate::conntrack::Tuple> {
+    use crate::ebpf::ip_addr;
+
+    if !event.has_packet_tuple() || (event.packet_src_port == 0 && event.packet_dst_port == 0) {
+        return None;
+    }
+    Some(crate::conntrack::Tuple {
+        src: ip_addr(&event.src_addr),
+        dst: ip_addr(&event.dst_addr),
+        sport: event.packet_src_port,
+        dport: event.packet_dst_port,
+    })
+}
+
+/// TIME column: seconds since the trace started, or the event's wall-clock
+/// time with --wall-clock (when polled, for lines that aren't events)
+#[cfg(target_os = "linux")]
+fn time_column(filter: &TraceFilter, stamp: &Stamp) -> String {
+    if filter.wall_clock {
+        let time: chrono::DateTime<chrono::Local> = stamp.wall.into();
+        time.format("%H:%M:%S%.3f").to_string()
     } else {
-        eprintln!("{}: nf_events path does not exist", "Debug".blue());
-        None
-    };
-    
-    if drop_rb.is_none() && nf_rb.is_none() {
-        println!("{}: Could not open any event maps (see debug messages above)", "Warning".yellow());
+        format!("{:>7.2}s", stamp.elapsed.as_secs_f64())
     }
+}
+
+#[cfg(target_os = "linux")]
+fn run_linux_trace(filter: &TraceFilter, mut source: TraceSource) -> Result<()> {
+    use crate::ebpf::{describe_drop_packet, describe_packet, describe_payload, drop_reason_str, eth_proto_str, hex_lines, NfFamily};
+    use sennet_common::drop_reason;
+    
+    // Process, conntrack and firewall state describe this host now, which
+    // says nothing about a recording
+    let live = source.is_live();
     
     let start = Instant::now();
     let timeout = Duration::from_secs(filter.timeout_secs);
     let mut event_count = 0;
     
+    // Conntrack drop counter, used to flag NF drops caused by table exhaustion
+    let read_ct_drops = || live.then(crate::conntrack::read_stats).flatten().map(|s| s.exhaustion_drops());
+    let mut ct_drops = read_ct_drops();
+    let debug = std::env::var("SENNET_DEBUG").is_ok();
+    let mut total_lost = 0;
+    
+    // Flow table for naming the process behind a drop, and the NAT table
+    // for its translated tuple, refreshed as flows churn
+    let mut owners = if live { FlowOwners::load() } else { FlowOwners::default() };
+    let mut nat = if live { crate::conntrack::NatTable::load() } else { Default::default() };
+    let mut owners_loaded = Instant::now();
+    let mut affected = DropTally::default();
+    let mut limiter = ReasonLimiter::new(filter.max_per_reason);
+    
+    // Firewall drop/reject counters, to name the rule behind netfilter drops
+    let mut nft = live.then(crate::firewall::DropRuleCorrelator::new).flatten();
+    let mut rule_drops: HashMap<RuleRef, u64> = HashMap::new();
+    
+    if filter.payload > 0 && !live {
+        println!("{}: recordings carry no packet payloads; ignoring --payload", "Note".yellow());
+    }
+    
+    // Stacks live in the agent's pinned map, so only a live trace has them
+    let stacks = match (filter.stacks, live) {
+        (false, _) => None,
+        (true, false) => {
+            println!("{}: recordings carry no kernel stacks; ignoring --stacks", "Note".yellow());
+            None
+        }
+        (true, true) => match DropStacks::open() {
+            Ok(stacks) => Some(stacks),
+            Err(e) => {
+                println!("{}: kernel stacks unavailable: {}", "Warning".yellow(), e);
+                None
+            }
+        },
+    };
+    
     println!();
-    println!("{:>8}  {:15}  {:10}  {}", "TIME", "REASON", "HOOK", "DETAILS");
+    let time_width = if filter.wall_clock { 12 } else { 8 };
+    println!("{:>width$}  {:15}  {:10}  DETAILS", "TIME", "REASON", "HOOK", width = time_width);
     println!("{}", "─".repeat(60));
     
     loop {
@@ -225,123 +697,355 @@ fn run_linux_trace(filter: &TraceFilter) -> Result<()> {
             break;
         }
         
-        // Poll DROP_EVENTS (Phase 6.1)
-        if let Some(ref mut rb) = drop_rb {
-            while let Some(item) = rb.next() {
-                // Debug: show raw event data
-                if std::env::var("SENNET_DEBUG").is_ok() {
-                    eprintln!("Raw event bytes (len={}): {:02x?}", item.len(), &item[..item.len().min(24)]);
+        let (events, gaps) = match source.poll(debug, start) {
+            Ok(polled) => polled,
+            Err(e) => {
+                println!();
+                println!("{}: {}", "Stopped".yellow(), e);
+                break;
+            }
+        };
+        
+        if live && owners_loaded.elapsed() >= FLOW_REFRESH {
+            owners = FlowOwners::load();
+            nat = crate::conntrack::NatTable::load();
+            owners_loaded = Instant::now();
+        }
+        
+        // Did the conntrack table drop packets since the last poll?
+        let ct_now = read_ct_drops();
+        let conntrack_full = matches!((ct_drops, ct_now), (Some(prev), Some(now)) if now > prev);
+        ct_drops = ct_now;
+        let ct_hint = if conntrack_full {
+            format!("  {}", "(conntrack table full)".yellow())
+        } else {
+            String::new()
+        };
+        
+        // Which drop/reject rules' counters moved alongside this batch?
+        let netfilter_drops = events.iter().any(|(event, _, _)| match event {
+            RawEvent::Drop(e) => e.reason == drop_reason::NETFILTER_DROP,
+            RawEvent::Netfilter(e) => e.is_drop(),
+            _ => false,
+        });
+        let rule_hint = match nft.as_mut() {
+            Some(nft) if netfilter_drops => {
+                let moved = nft.sample();
+                for (rule, packets) in &moved {
+                    *rule_drops.entry(rule.clone()).or_default() += packets;
                 }
-                
-                if item.len() >= std::mem::size_of::<DropEvent>() {
-                    let event: DropEvent = unsafe {
-                        std::ptr::read_unaligned(item.as_ptr() as *const DropEvent)
-                    };
-                    
+                match moved.first() {
+                    Some((rule, _)) => format!("  rule: {}", rule.to_string().magenta()),
+                    None => String::new(),
+                }
+            }
+            Some(nft) => {
+                nft.refresh();
+                String::new()
+            }
+            None => String::new(),
+        };
+        
+        // Mark gaps where events were lost since the last poll
+        for (ring, n, stamp) in gaps {
+            let why = if ring == crate::control::STREAM_GAP {
+                format!("··· gap: {} events lost (trace fell behind the agent) ···", n)
+            } else if [RingKind::Drop, RingKind::Netfilter, RingKind::Payload].iter().any(|kind| ring == kind.name()) {
+                format!("··· gap: {} {} events lost (ring buffer full) ···", n, ring)
+            } else {
+                continue;
+            };
+            total_lost += n;
+            println!("{}  {}", time_column(filter, &stamp), why.yellow());
+        }
+        
+        for (event, count, stamp) in events {
+            if event_count >= filter.count {
+                break;
+            }
+            if !filter.filter.matches(&event) {
+                continue;
+            }
+            if let RawEvent::Drop(drop) = &event {
+                if !limiter.admit(drop.reason, count, stamp.elapsed) {
+                    continue;
+                }
+            }
+            // Bursts of identical drops the agent coalesced
+            let repeats = Repeats(count / event.sample_weight());
+            match event {
+                // Poll DROP_EVENTS (Phase 6.1)
+                RawEvent::Drop(event) => {
                     // Debug: show parsed values
-                    if std::env::var("SENNET_DEBUG").is_ok() {
-                        eprintln!("Parsed: ts={}, reason={}, ifindex={}, proto={}, pad={}",
-                            event.timestamp_ns, event.reason, event.ifindex, event.protocol, event._pad);
-                    }
-                    
-                    // Apply protocol filter (Phase 6.4)
-                    // Note: kfree_skb protocol is ETH_P_* (Ethernet), not IP protocol
-                    // For now, filter by IP version: "ipv4", "ipv6", or skip filter for TCP/UDP/ICMP
-                    if let Some(ref proto_filter) = filter.protocol {
-                        let matches = match proto_filter.as_str() {
-                            "ipv4" => event.protocol == 0x0800,
-                            "ipv6" => event.protocol == 0x86DD,
-                            // TCP/UDP/ICMP filters don't apply to kfree_skb (no L4 info)
-                            _ => true, // Show all for unsupported filters
-                        };
-                        if !matches {
-                            continue; // Skip non-matching events
-                        }
+                    if debug {
+                        eprintln!("Parsed: ts={}, reason={}, ifindex={}, proto={}, sample_rate={}",
+                            event.timestamp_ns, event.reason, event.ifindex, event.protocol, event.sample_rate);
                     }
                     
                     let reason = drop_reason_str(event.reason);
-                    let elapsed = start.elapsed().as_secs_f64();
+                    let time = time_column(filter, &stamp);
                     
                     // Color by severity
                     let reason_colored = match event.reason {
-                        7 | 5 => reason.red(),      // NETFILTER_DROP, SOCKET_FILTER
-                        2 | 37 => reason.yellow(),  // NO_SOCKET, IP_OUTNOROUTES
+                        drop_reason::NETFILTER_DROP | drop_reason::SOCKET_FILTER => reason.red(),
+                        drop_reason::NO_SOCKET | drop_reason::IP_OUTNOROUTES => reason.yellow(),
                         _ => reason.white(),
                     };
                     
                     // Protocol from kfree_skb is Ethernet protocol (ETH_P_*)
                     let proto = eth_proto_str(event.protocol);
+                    // Addresses and ports from the packet's own headers
+                    let tuple = describe_drop_packet(&event).map(|t| format!(" {}", t)).unwrap_or_default();
                     
                     // Skip events with no valid data (stale/uninitialized)
                     if event.timestamp_ns == 0 && event.reason == 0 && event.protocol == 0 {
                         continue; // Skip empty/stale events
                     }
                     
-                    println!("{:>7.2}s  {:15}  {:10}  eth={}",
-                             elapsed,
+                    // NETFILTER_DROP while conntrack is dropping: likely table exhaustion
+                    let hint = if event.reason == drop_reason::NETFILTER_DROP { format!("{}{}", rule_hint, ct_hint) } else { String::new() };
+                    
+                    // Process whose connection the packet belonged to
+                    let owner = match owners.lookup(&event) {
+                        Some(owner) => {
+                            affected.add(owner, event.reason, count);
+                            format!("  → {}", owner.to_string().cyan())
+                        }
+                        None => String::new(),
+                    };
+                    
+                    // Device the packet was on, when the kernel recorded one
+                    let dev = match event.ifindex {
+                        0 => String::new(),
+                        ifindex => format!(" dev={}", crate::ifnames::display(ifindex)),
+                    };
+                    
+                    println!("{}  {:15}  {:10}  eth={}{}{}{}{}{}{}",
+                             time,
                              reason_colored,
                              "-".white(),
-                             proto);
+                             proto,
+                             tuple,
+                             dev,
+                             owner,
+                             hint,
+                             repeats,
+                             SampleMarker(event.sample_rate));
                     
-                    event_count += 1;
-                    if event_count >= filter.count {
-                        break;
+                    // SNAT/DNAT: the tuple as seen on the other side of the translation
+                    if let Some(entry) = drop_tuple(&event).and_then(|t| nat.lookup(event.ip_protocol, &t)) {
+                        if let Some(kind) = entry.nat_kind() {
+                            println!("{:>width$}  {} {} {}  {} {}",
+                                     "",
+                                     "↳".dimmed(),
+                                     kind.as_str().magenta(),
+                                     entry.original,
+                                     "as".dimmed(),
+                                     entry.translated(),
+                                     width = time_width);
+                        }
                     }
-                }
-            }
-        }
-        
-        // Poll NF_EVENTS (Phase 6.2)
-        if let Some(ref mut rb) = nf_rb {
-            while let Some(item) = rb.next() {
-                if item.len() >= std::mem::size_of::<NetfilterEvent>() {
-                    let event: NetfilterEvent = unsafe {
-                        std::ptr::read_unaligned(item.as_ptr() as *const NetfilterEvent)
-                    };
                     
+                    if let Some(stacks) = &stacks {
+                        let frames = event.kernel_stack_id().map(|id| stacks.frames(id)).unwrap_or_default();
+                        if frames.is_empty() {
+                            println!("{:>width$}  {}", "", "(no stack captured)".dimmed(), width = time_width);
+                        }
+                        for frame in frames {
+                            println!("{:>width$}  {}", "", frame.dimmed(), width = time_width);
+                        }
+                    }
+                    
+                    event_count += 1;
+                }
+                
+                // Poll NF_EVENTS (Phase 6.2)
+                RawEvent::Netfilter(event) => {
                     // Only show DROP verdicts by default
-                    if event.verdict != 0 {
+                    if !event.is_drop() {
                         continue;
                     }
                     
-                    let elapsed = start.elapsed().as_secs_f64();
-                    let hook_name = nf_hook_str(event.hook);
-                    let verdict_name = nf_verdict_str(event.verdict);
+                    let time = time_column(filter, &stamp);
+                    let pf = event.nf_family().map_or("?", NfFamily::name);
                     
-                    let reason = format!("NF_{}", verdict_name);
-                    let pf = match event.pf {
-                        2 => "IPv4",
-                        10 => "IPv6",
-                        _ => "?",
-                    };
+                    // Devices, when the kernel recorded them
+                    let mut devs = String::new();
+                    for (label, ifindex) in [("ifin", event.ifindex_in), ("ifout", event.ifindex_out)] {
+                        if ifindex != 0 {
+                            devs.push_str(&format!(" {}={}", label, crate::ifnames::display(ifindex)));
+                        }
+                    }
                     
-                    println!("{:>7.2}s  {:15}  {:10}  pf={} ifin={} ifout={}",
-                             elapsed,
-                             reason.red(),
-                             hook_name.cyan(),
+                    println!("{}  {:15}  {:10}  pf={}{}{}{}{}{}",
+                             time,
+                             "NETFILTER".red(),
+                             event.hook_verdict().cyan(),
                              pf,
-                             event.ifindex_in,
-                             event.ifindex_out);
+                             devs,
+                             rule_hint,
+                             ct_hint,
+                             repeats,
+                             SampleMarker(event.sample_rate));
                     
                     event_count += 1;
-                    if event_count >= filter.count {
-                        break;
+                }
+                
+                // Large packets (IPv4 or IPv6) and rate anomalies from the
+                // TC/XDP hook
+                RawEvent::Packet(event) => {
+                    let dev = match event.ifindex {
+                        0 => String::new(),
+                        ifindex => format!(" dev={}", crate::ifnames::display(ifindex)),
+                    };
+                    println!("{}  {:15}  {:10}  {}{}{}{}",
+                             time_column(filter, &stamp),
+                             if event.is_anomaly() { "RATE_ANOMALY".red() } else { "LARGE_PACKET".blue() },
+                             if event.direction == 0 { "ingress" } else { "egress" },
+                             describe_packet(&event),
+                             dev,
+                             repeats,
+                             SampleMarker(event.sample_rate));
+
+                    event_count += 1;
+                }
+
+                // Packet heads from the TC/XDP hook, with --payload
+                RawEvent::Payload(event) => {
+                    let dev = match event.ifindex {
+                        0 => String::new(),
+                        ifindex => format!(" dev={}", crate::ifnames::display(ifindex)),
+                    };
+                    println!("{}  {:15}  {:10}  {}{}{}{}",
+                             time_column(filter, &stamp),
+                             "PAYLOAD".blue(),
+                             if event.direction == 0 { "ingress" } else { "egress" },
+                             describe_payload(&event),
+                             dev,
+                             repeats,
+                             SampleMarker(event.sample_rate));
+                    for line in hex_lines(event.snippet()) {
+                        println!("{:>width$}  {}", "", line.dimmed(), width = time_width);
                     }
+
+                    event_count += 1;
                 }
+
+                // Flows, resets, DNS, conntrack and HTTP have their own
+                // commands; retransmits are summarized by `sennet top`
+                RawEvent::Flow(_)
+                | RawEvent::Rst(_)
+                | RawEvent::Retransmit(_)
+                | RawEvent::Dns(_)
+                | RawEvent::Conntrack(_)
+                | RawEvent::Http(_) => {}
             }
         }
-        
-        // Small sleep to avoid busy loop
-        std::thread::sleep(Duration::from_millis(50));
     }
     
     println!();
     println!("Captured {} events in {:.1}s", event_count, start.elapsed().as_secs_f64());
+    print_suppressed(&limiter.suppressed().iter().map(|(&reason, &n)| (drop_reason_str(reason), n)).collect::<BTreeMap<_, _>>());
+    if !affected.is_empty() {
+        println!();
+        println!("{}", "Drops affecting processes:".bold());
+        for (owner, total, reasons) in affected.summary() {
+            let reasons: Vec<String> = reasons
+                .iter()
+                .map(|&(reason, n)| format!("{} ×{}", drop_reason_str(reason), n))
+                .collect();
+            println!("  {:<28} {:>6}  {}", owner.to_string(), total, reasons.join(", "));
+        }
+    } else if live && owners.is_empty() {
+        println!("{}", "No flow table available: drops were not attributed to processes".dimmed());
+    }
+    if !rule_drops.is_empty() {
+        let mut rules: Vec<_> = rule_drops.into_iter().collect();
+        rules.sort_by_key(|(_, packets)| std::cmp::Reverse(*packets));
+        println!();
+        println!("{}", "Firewall rules dropping packets:".bold());
+        for (rule, packets) in rules {
+            println!("  {:<40} {:>8} packets", rule.to_string(), packets);
+        }
+    }
+    if total_lost > 0 {
+        println!("{}: {} events were lost while tracing; the output above is incomplete",
+                 "Warning".yellow(), total_lost);
+    }
+    
+    Ok(())
+}
+
+/// Suffix for a burst of identical drops the agent coalesced into one record
+#[cfg(target_os = "linux")]
+struct Repeats(u64);
+
+#[cfg(target_os = "linux")]
+impl std::fmt::Display for Repeats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0 > 1 {
+            write!(f, "  ×{}", self.0)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Suffix for events the kernel rate limiter sampled
+#[cfg(target_os = "linux")]
+struct SampleMarker(u8);
+
+#[cfg(target_os = "linux")]
+impl std::fmt::Display for SampleMarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0 > 1 {
+            write!(f, "  (sampled 1/{})", self.0)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Resets and ICMP unreachables seen on the wire (no eBPF on macOS)
+#[cfg(target_os = "macos")]
+fn run_pcap_trace(filter: &TraceFilter) -> Result<()> {
+    let interface = crate::config::Config::load().ok().and_then(|c| c.interface);
+    let mut capture = crate::capture::open(interface.as_deref())?;
+    
+    let start = Instant::now();
+    let timeout = Duration::from_secs(filter.timeout_secs);
+    let mut event_count = 0;
+    
+    let mut limiter = ReasonLimiter::new(filter.max_per_reason);
+    
+    println!("{}", "No eBPF on macOS: showing TCP resets and ICMP unreachables from a packet capture".dimmed());
+    println!();
+    println!("{:>8}  {:22}  {}", "TIME", "REASON", "FLOW");
+    println!("{}", "─".repeat(60));
+    
+    while event_count < filter.count && start.elapsed() < timeout {
+        let Some(signal) = crate::capture::next_signal(&mut capture)? else {
+            continue;
+        };
+        if !filter.matches_flow(&signal) {
+            continue;
+        }
+        if !limiter.admit(signal.reason.clone(), 1, start.elapsed()) {
+            continue;
+        }
+        let reason = if signal.reason == "TCP_RESET" { signal.reason.yellow() } else { signal.reason.red() };
+        println!("{:>7.2}s  {:22}  {}", start.elapsed().as_secs_f64(), reason, signal);
+        event_count += 1;
+    }
+    
+    println!();
+    println!("Captured {} events in {:.1}s", event_count, start.elapsed().as_secs_f64());
+    print_suppressed(limiter.suppressed());
     
     Ok(())
 }
 
-#[cfg(not(target_os = "linux"))]
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
 fn run_mock_trace(filter: &TraceFilter) -> Result<()> {
     use std::thread;
     
@@ -401,17 +1105,42 @@ pub fn print_help() {
     println!("{}", "sennet trace - One-shot packet tracing".bold());
     println!();
     println!("{}", "USAGE:".yellow());
-    println!("    sennet trace [OPTIONS]");
+    println!("    sennet trace [OPTIONS] [EXPRESSION]");
     println!();
     println!("{}", "OPTIONS:".yellow());
+    println!("    {}  Filter expression (also given as trailing words)", "-f, --filter <E>".cyan());
     println!("    {}        Filter by destination IP[:PORT]", "--dst <IP>".cyan());
     println!("    {}        Filter by source IP[:PORT]", "--src <IP>".cyan());
-    println!("    {}   Filter by protocol (tcp, udp, icmp)", "--proto <P>".cyan());
+    println!("    {}   Filter by protocol (tcp, udp, icmp, ipv4, ipv6)", "--proto <P>".cyan());
     println!("    {}      Stop after N events (default: 20)", "--count <N>".cyan());
     println!("    {}   Stop after S seconds (default: 30)", "--timeout <S>".cyan());
+    println!("    {}  Show event times as wall-clock time", "-T, --wall-clock".cyan());
+    println!("    {}  Show at most N drops per second of each reason", "--max-per-reason <N/s>".cyan());
+    println!("    {}         Show the kernel stack that freed each dropped packet", "--stacks".cyan());
+    println!("    {}  Hex dump the first N bytes of matching packets (default: 64, max: 128)", "--payload [N]".cyan());
     println!();
     println!("{}", "EXAMPLES:".yellow());
     println!("    sennet trace                     # Trace all drops");
     println!("    sennet trace --dst 10.0.0.5:443  # Filter by destination");
     println!("    sennet trace --proto icmp -c 10  # Trace 10 ICMP drops");
+    println!("    sennet trace --max-per-reason 2/s -c 200  # Keep noisy reasons from crowding out rare ones");
+    println!("    sennet trace --stacks -c 5       # Which kernel function dropped the packet");
+    println!("    sennet trace --payload 96 'dport == 53'  # Headers of DNS queries, no tcpdump needed");
+    println!("    sennet trace 'dst == 10.0.0.0/24 && port == 443 && reason != NOT_SPECIFIED'");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    Expressions compare src, dst, host, sport, dport, port, proto, family");
+    println!("    and reason with == != < <= > >= ~, joined by && || ! and parentheses.");
+    println!("    Addresses take a /prefix. Fields an event type doesn't record (proto");
+    println!("    on a kfree_skb drop) are ignored for it.");
+    println!("    Drops of packets owned by a tracked connection name the process");
+    println!("    affected (→ nginx (pid 1234)) and are summarized per process.");
+    println!("    Netfilter drops name the nftables or iptables drop/reject rule whose");
+    println!("    counter moved at the same time (needs CAP_NET_ADMIN; nftables rules");
+    println!("    need a `counter` statement).");
+    println!("    --stacks reads the agent's pinned stack map and /proc/kallsyms, so it");
+    println!("    needs root; stacks are kept for the 1024 most recent drop paths.");
+    println!("    --payload reads packet heads from a pinned ring, so it needs root. Bytes are");
+    println!("    copied at the TC/XDP hook from the Ethernet header on, for IP packets");
+    println!("    passing the filter's address, port and protocol terms.");
 }
@@ -0,0 +1,249 @@
+//! Tracepoint Record Formats
+//!
+//! The eBPF tracepoint programs read fields out of the raw tracepoint
+//! record, whose layout depends on the kernel build. Each tracepoint's
+//! `format` file under tracefs lists its fields with their offsets; this
+//! module parses it so the agent can hand the kernel programs the offsets
+//! of the running kernel instead of guessing. The offsets go into one
+//! single-entry map per tracepoint (KFREE_SKB_LAYOUT,
+//! TCP_RETRANSMIT_SKB_LAYOUT, TCP_PROBE_LAYOUT, INET_SOCK_SET_STATE_LAYOUT),
+//! written before the programs are attached.
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::ebpf::{InetSockSetStateLayout, KfreeSkbLayout, TcpProbeLayout, TcpRetransmitSkbLayout};
+
+/// tracefs mount points, newest first
+const TRACEFS_ROOTS: &[&str] = &["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+/// One field of a tracepoint record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatField {
+    pub name: String,
+    /// Bytes from the start of the record
+    pub offset: u16,
+    pub size: u16,
+}
+
+/// Fields of one tracepoint's record, from its `format` file
+#[derive(Debug, Default, Clone)]
+pub struct TracepointFormat {
+    pub fields: Vec<FormatField>,
+}
+
+impl TracepointFormat {
+    /// Read `events/<system>/<event>/format` from tracefs
+    pub fn read(system: &str, event: &str) -> Result<Self> {
+        for root in TRACEFS_ROOTS {
+            let path = Path::new(root).join("events").join(system).join(event).join("format");
+            if let Ok(text) = std::fs::read_to_string(&path) {
+                return Ok(Self::parse(&text));
+            }
+        }
+        anyhow::bail!("No format file for tracepoint {}/{} (is tracefs mounted?)", system, event)
+    }
+
+    /// Parse the `field:<decl>; offset:<n>; size:<n>; signed:<n>;` lines
+    pub fn parse(text: &str) -> Self {
+        let fields = text
+            .lines()
+            .filter_map(|line| {
+                let mut decl = None;
+                let mut offset = None;
+                let mut size = None;
+                for part in line.split(';') {
+                    match part.trim().split_once(':') {
+                        Some(("field", d)) => decl = Some(d),
+                        Some(("offset", n)) => offset = n.trim().parse().ok(),
+                        Some(("size", n)) => size = n.trim().parse().ok(),
+                        _ => {}
+                    }
+                }
+                // The name is the declaration's last word, less any array
+                // suffix (which may hold spaces: `[sizeof(struct sockaddr_in6)]`)
+                let name = decl?.split('[').next()?.split_whitespace().last()?;
+                let name = name.trim_start_matches('*');
+                Some(FormatField { name: name.to_string(), offset: offset?, size: size? })
+            })
+            .collect();
+        Self { fields }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FormatField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Offset of `name` if it is present with the expected size
+    pub fn offset(&self, name: &str, size: u16) -> Option<u16> {
+        self.field(name).filter(|f| f.size == size).map(|f| f.offset)
+    }
+}
+
+/// Offsets for the kfree_skb program, None if the record lacks the skb
+/// pointer or protocol
+pub fn kfree_skb_layout(format: &TracepointFormat) -> Option<KfreeSkbLayout> {
+    Some(KfreeSkbLayout {
+        skbaddr: format.offset("skbaddr", 8)?,
+        protocol: format.offset("protocol", 2)?,
+        // Missing before 5.17
+        reason: format.offset("reason", 4).unwrap_or(0),
+        _pad: 0,
+    })
+}
+
+/// Offsets for the tcp_retransmit_skb program, None if the record lacks a
+/// field it reads
+pub fn tcp_retransmit_skb_layout(format: &TracepointFormat) -> Option<TcpRetransmitSkbLayout> {
+    Some(TcpRetransmitSkbLayout {
+        state: format.offset("state", 4)?,
+        sport: format.offset("sport", 2)?,
+        dport: format.offset("dport", 2)?,
+        saddr_v6: format.offset("saddr_v6", 16)?,
+        daddr_v6: format.offset("daddr_v6", 16)?,
+        _pad: 0,
+    })
+}
+
+/// Offsets for the tcp_probe program, None if the record lacks the remote
+/// address or the smoothed RTT
+pub fn tcp_probe_layout(format: &TracepointFormat) -> Option<TcpProbeLayout> {
+    Some(TcpProbeLayout {
+        // Room for a sockaddr_in6
+        daddr: format.offset("daddr", 28)?,
+        srtt: format.offset("srtt", 4)?,
+    })
+}
+
+/// Offsets for the inet_sock_set_state program, None if the record lacks a
+/// field it reads
+pub fn inet_sock_set_state_layout(format: &TracepointFormat) -> Option<InetSockSetStateLayout> {
+    Some(InetSockSetStateLayout {
+        oldstate: format.offset("oldstate", 4)?,
+        newstate: format.offset("newstate", 4)?,
+        sport: format.offset("sport", 2)?,
+        dport: format.offset("dport", 2)?,
+        family: format.offset("family", 2)?,
+        protocol: format.offset("protocol", 2)?,
+        saddr: format.offset("saddr", 4)?,
+        daddr: format.offset("daddr", 4)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// skb/kfree_skb on 6.12, with `rx_sk`
+    const KFREE_SKB: &str = "\
+name: kfree_skb
+ID: 1631
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:void * skbaddr;\toffset:8;\tsize:8;\tsigned:0;
+\tfield:void * location;\toffset:16;\tsize:8;\tsigned:0;
+\tfield:void * rx_sk;\toffset:24;\tsize:8;\tsigned:0;
+\tfield:unsigned short protocol;\toffset:32;\tsize:2;\tsigned:0;
+\tfield:enum skb_drop_reason reason;\toffset:36;\tsize:4;\tsigned:0;
+
+print fmt: \"skbaddr=%p protocol=%u location=%pS reason: %s\"
+";
+
+    /// tcp/tcp_retransmit_skb on 6.13, with `err`
+    const TCP_RETRANSMIT_SKB: &str = "\
+\tfield:const void * skbaddr;\toffset:8;\tsize:8;\tsigned:0;
+\tfield:const void * skaddr;\toffset:16;\tsize:8;\tsigned:0;
+\tfield:int state;\toffset:24;\tsize:4;\tsigned:1;
+\tfield:__u16 sport;\toffset:28;\tsize:2;\tsigned:0;
+\tfield:__u16 dport;\toffset:30;\tsize:2;\tsigned:0;
+\tfield:__u16 family;\toffset:32;\tsize:2;\tsigned:0;
+\tfield:__u8 saddr[4];\toffset:34;\tsize:4;\tsigned:0;
+\tfield:__u8 daddr[4];\toffset:38;\tsize:4;\tsigned:0;
+\tfield:__u8 saddr_v6[16];\toffset:42;\tsize:16;\tsigned:0;
+\tfield:__u8 daddr_v6[16];\toffset:58;\tsize:16;\tsigned:0;
+\tfield:enum sk_rst_reason err;\toffset:76;\tsize:4;\tsigned:0;
+";
+
+    /// tcp/tcp_probe on 6.10, with `skbaddr` and `skaddr`
+    const TCP_PROBE: &str = "\
+\tfield:__u8 saddr[sizeof(struct sockaddr_in6)];\toffset:8;\tsize:28;\tsigned:0;
+\tfield:__u8 daddr[sizeof(struct sockaddr_in6)];\toffset:36;\tsize:28;\tsigned:0;
+\tfield:__u16 sport;\toffset:64;\tsize:2;\tsigned:0;
+\tfield:__u16 dport;\toffset:66;\tsize:2;\tsigned:0;
+\tfield:__u16 family;\toffset:68;\tsize:2;\tsigned:0;
+\tfield:__u32 mark;\toffset:72;\tsize:4;\tsigned:0;
+\tfield:__u16 data_len;\toffset:76;\tsize:2;\tsigned:0;
+\tfield:__u32 snd_nxt;\toffset:80;\tsize:4;\tsigned:0;
+\tfield:__u32 snd_una;\toffset:84;\tsize:4;\tsigned:0;
+\tfield:__u32 snd_cwnd;\toffset:88;\tsize:4;\tsigned:0;
+\tfield:__u32 ssthresh;\toffset:92;\tsize:4;\tsigned:0;
+\tfield:__u32 snd_wnd;\toffset:96;\tsize:4;\tsigned:0;
+\tfield:__u32 srtt;\toffset:100;\tsize:4;\tsigned:0;
+\tfield:__u32 rcv_wnd;\toffset:104;\tsize:4;\tsigned:0;
+\tfield:__u64 sock_cookie;\toffset:112;\tsize:8;\tsigned:0;
+\tfield:const void * skbaddr;\toffset:120;\tsize:8;\tsigned:0;
+\tfield:const void * skaddr;\toffset:128;\tsize:8;\tsigned:0;
+";
+
+    /// sock/inet_sock_set_state on 6.x
+    const INET_SOCK_SET_STATE: &str = "\
+\tfield:const void * skaddr;\toffset:8;\tsize:8;\tsigned:0;
+\tfield:int oldstate;\toffset:16;\tsize:4;\tsigned:1;
+\tfield:int newstate;\toffset:20;\tsize:4;\tsigned:1;
+\tfield:__u16 sport;\toffset:24;\tsize:2;\tsigned:0;
+\tfield:__u16 dport;\toffset:26;\tsize:2;\tsigned:0;
+\tfield:__u16 family;\toffset:28;\tsize:2;\tsigned:0;
+\tfield:__u16 protocol;\toffset:30;\tsize:2;\tsigned:0;
+\tfield:__u8 saddr[4];\toffset:32;\tsize:4;\tsigned:0;
+\tfield:__u8 daddr[4];\toffset:36;\tsize:4;\tsigned:0;
+\tfield:__u8 saddr_v6[16];\toffset:40;\tsize:16;\tsigned:0;
+\tfield:__u8 daddr_v6[16];\toffset:56;\tsize:16;\tsigned:0;
+";
+
+    #[test]
+    fn test_parse_format() {
+        let format = TracepointFormat::parse(KFREE_SKB);
+        assert_eq!(format.fields.len(), 9);
+        assert_eq!(format.field("rx_sk"), Some(&FormatField { name: "rx_sk".into(), offset: 24, size: 8 }));
+        assert_eq!(format.offset("protocol", 2), Some(32));
+        // Wrong size: not the field the program expects
+        assert_eq!(format.offset("protocol", 4), None);
+
+        let arrays = TracepointFormat::parse("\tfield:__u8 saddr[4];\toffset:28;\tsize:4;\tsigned:0;\n");
+        assert_eq!(arrays.offset("saddr", 4), Some(28));
+        let sized = TracepointFormat::parse(TCP_PROBE);
+        assert_eq!(sized.offset("daddr", 28), Some(36));
+    }
+
+    #[test]
+    fn test_kfree_skb_layout() {
+        let layout = kfree_skb_layout(&TracepointFormat::parse(KFREE_SKB)).unwrap();
+        assert_eq!((layout.skbaddr, layout.protocol, layout.reason), (8, 32, 36));
+
+        // Before 5.17: no reason field
+        let old = KFREE_SKB.replace("\tfield:enum skb_drop_reason reason;\toffset:36;\tsize:4;\tsigned:0;\n", "");
+        assert_eq!(kfree_skb_layout(&TracepointFormat::parse(&old)).unwrap().reason, 0);
+
+        assert!(kfree_skb_layout(&TracepointFormat::default()).is_none());
+    }
+
+    #[test]
+    fn test_socket_tracepoint_layouts() {
+        // The built-in offsets match what these kernels report
+        let retransmit = tcp_retransmit_skb_layout(&TracepointFormat::parse(TCP_RETRANSMIT_SKB));
+        assert_eq!(retransmit, Some(TcpRetransmitSkbLayout::DEFAULT));
+        assert_eq!(tcp_probe_layout(&TracepointFormat::parse(TCP_PROBE)), Some(TcpProbeLayout::DEFAULT));
+        let set_state = inet_sock_set_state_layout(&TracepointFormat::parse(INET_SOCK_SET_STATE));
+        assert_eq!(set_state, Some(InetSockSetStateLayout::DEFAULT));
+
+        // A record without a field the program reads is refused, not guessed at
+        let old = TCP_PROBE.replace("\tfield:__u32 srtt;\toffset:100;\tsize:4;\tsigned:0;\n", "");
+        assert!(tcp_probe_layout(&TracepointFormat::parse(&old)).is_none());
+        assert!(tcp_retransmit_skb_layout(&TracepointFormat::default()).is_none());
+        assert!(inet_sock_set_state_layout(&TracepointFormat::default()).is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
     execute,
@@ -14,14 +14,60 @@ use ratatui::{
 };
 use std::{io, time::{Duration, Instant}};
 
+use crate::ebpf::TcpFlagCounters;
+use crate::retransmits::{RetransmitRate, RetransmitRates};
+
+/// Window over which `top` shows retransmits per destination
+const RETRANSMIT_WINDOW: Duration = Duration::from_secs(10);
+
 // Data structures for UI
 struct AppState {
     rx_packets: u64,
     rx_bytes: u64,
     tx_packets: u64,
     tx_bytes: u64,
+    /// Per-protocol breakdown; zero where only interface counters exist
+    rx_proto: crate::ebpf::ProtoCounters,
+    tx_proto: crate::ebpf::ProtoCounters,
+    /// SYN/FIN/RST rates, to spot connection storms and reset spikes
+    tcp_flags: TcpFlagRates,
+    /// Events lost to full ring buffers since the TUI started
+    events_lost: u64,
     events: Vec<String>,
     drop_events: Vec<DropEventDisplay>,  // Phase 6.3: Drop events panel
+    /// kfree_skb drops since the TUI started, by reason
+    drop_summary: crate::drops::DropSummary,
+    /// Retransmits per destination, and the busiest ones at the last update
+    retransmits: RetransmitRates,
+    top_retransmits: Vec<RetransmitRate>,
+    /// What is being shown when it isn't this host, for the header
+    source: Option<String>,
+}
+
+/// TCP control segments per second between the last two counter updates
+#[derive(Default)]
+struct TcpFlagRates {
+    last: Option<(Instant, TcpFlagCounters, TcpFlagCounters)>,
+    /// In and out; None until two updates have been seen
+    per_sec: Option<(FlagRates, FlagRates)>,
+}
+
+/// Per-second rate of each counted flag
+type FlagRates = [(&'static str, f64); 4];
+
+impl TcpFlagRates {
+    fn update(&mut self, rx: TcpFlagCounters, tx: TcpFlagCounters, now: Instant) {
+        if let Some((at, last_rx, last_tx)) = self.last {
+            let secs = now.duration_since(at).as_secs_f64();
+            if secs > 0.0 {
+                let rates = |current: TcpFlagCounters, last: TcpFlagCounters| {
+                    current.since(&last).rows().map(|(name, count)| (name, count as f64 / secs))
+                };
+                self.per_sec = Some((rates(rx, last_rx), rates(tx, last_tx)));
+            }
+        }
+        self.last = Some((now, rx, tx));
+    }
 }
 
 /// Display-ready drop event
@@ -29,7 +75,7 @@ struct AppState {
 struct DropEventDisplay {
     timestamp_secs: u64,
     reason: String,
-    hook: Option<String>,  // From netfilter if available
+    hook: Option<String>,  // Interface, if available
     severity: DropSeverity,
 }
 
@@ -50,38 +96,48 @@ trait DataProvider {
 use aya::maps::{Map, MapData, PerCpuArray, RingBuf};
 
 #[cfg(target_os = "linux")]
-use crate::ebpf::{PacketCounters, DropEvent, NetfilterEvent, drop_reason_str, nf_hook_str, nf_verdict_str};
+use crate::ebpf::{KernelCounters, PacketCounters};
+#[cfg(unix)]
+use crate::ebpf::{DropEvent, NetfilterEvent, PacketEvent, RetransmitEvent, describe_packet, drop_reason_str};
+#[cfg(target_os = "linux")]
+use crate::events::RingKind;
 
 #[cfg(target_os = "linux")]
 struct RealDataProvider {
-    counters: PerCpuArray<MapData, PacketCounters>,
+    counters: PerCpuArray<MapData, KernelCounters>,
     drop_events_rb: Option<RingBuf<MapData>>,
     nf_events_rb: Option<RingBuf<MapData>>,  // Phase 6.2: Netfilter events
+    retransmit_events_rb: Option<RingBuf<MapData>>,
+    tcp_flags: Option<PerCpuArray<MapData, TcpFlagCounters>>,
     // Track last values to show delta/rates
     last_counters: PacketCounters,
+    losses: crate::ebpf::LossTracker,
     start_time: Instant,
 }
 
 #[cfg(target_os = "linux")]
 impl RealDataProvider {
     fn new() -> Result<Self> {
-        use std::path::Path;
-        
-        let pin_path = Path::new("/sys/fs/bpf/sennet/counters");
+        let pin_dir = crate::ebpf::pin_dir();
+        let pin_path = pin_dir.join("counters");
         if !pin_path.exists() {
-            anyhow::bail!("Pinned map not found at {:?}. Is the agent running?", pin_path);
+            anyhow::bail!("Pinned map not found at {:?}. Is the agent running?{}", pin_path, crate::ebpf::pin_namespace_hint());
         }
         
         // In aya 0.12: MapData::from_pin -> Map::PerCpuArray -> PerCpuArray::try_from(Map)
-        let map_data = MapData::from_pin(pin_path)?;
+        let map_data = MapData::from_pin(&pin_path)?;
         let map = Map::PerCpuArray(map_data);
-        let counters: PerCpuArray<_, PacketCounters> = map.try_into()?;
+        let counters: PerCpuArray<_, KernelCounters> = map.try_into()?;
+        // Missing when the agent predates the flag counters
+        let tcp_flags = MapData::from_pin(pin_dir.join("tcp_flag_counters"))
+            .ok()
+            .and_then(|data| Map::PerCpuArray(data).try_into().ok());
         
         // Try to open DROP_EVENTS RingBuf (Phase 6.1)
         let drop_events_rb = {
-            let drop_path = Path::new("/sys/fs/bpf/sennet/drop_events");
+            let drop_path = pin_dir.join("drop_events");
             if drop_path.exists() {
-                match MapData::from_pin(drop_path) {
+                match MapData::from_pin(&drop_path) {
                     Ok(data) => {
                         let map = Map::RingBuf(data);
                         match map.try_into() {
@@ -98,9 +154,9 @@ impl RealDataProvider {
         
         // Try to open NF_EVENTS RingBuf (Phase 6.2)
         let nf_events_rb = {
-            let nf_path = Path::new("/sys/fs/bpf/sennet/nf_events");
+            let nf_path = pin_dir.join("nf_events");
             if nf_path.exists() {
-                match MapData::from_pin(nf_path) {
+                match MapData::from_pin(&nf_path) {
                     Ok(data) => {
                         let map = Map::RingBuf(data);
                         match map.try_into() {
@@ -117,9 +173,12 @@ impl RealDataProvider {
         
         Ok(Self { 
             counters,
+            tcp_flags,
             drop_events_rb,
             nf_events_rb,
+            retransmit_events_rb: crate::ebpf::open_pinned_ringbuf("retransmit_events").ok(),
             last_counters: PacketCounters::default(),
+            losses: crate::ebpf::LossTracker::new(),
             start_time: Instant::now(),
         })
     }
@@ -127,20 +186,13 @@ impl RealDataProvider {
     fn read_totals(&self) -> Result<PacketCounters> {
         let mut total = PacketCounters::default();
         
-        // Read ingress counters (index 0)
-        if let Ok(values) = self.counters.get(&0, 0) {
-            for cpu_val in values.iter() {
-                total.rx_packets += cpu_val.rx_packets;
-                total.rx_bytes += cpu_val.rx_bytes;
-                total.drop_count += cpu_val.drop_count;
+        // Index 0 is ingress, index 1 egress
+        for direction in 0..2 {
+            if let Ok(values) = self.counters.get(&direction, 0) {
+                values.iter().for_each(|cpu_val| total.add_kernel(direction, cpu_val));
             }
-        }
-        
-        // Read egress counters (index 1)
-        if let Ok(values) = self.counters.get(&1, 0) {
-            for cpu_val in values.iter() {
-                total.tx_packets += cpu_val.tx_packets;
-                total.tx_bytes += cpu_val.tx_bytes;
+            if let Some(Ok(values)) = self.tcp_flags.as_ref().map(|map| map.get(&direction, 0)) {
+                values.iter().for_each(|cpu_val| total.add_tcp_flags(direction, cpu_val));
             }
         }
         
@@ -151,33 +203,9 @@ impl RealDataProvider {
         // Poll kfree_skb drop events (Phase 6.1)
         if let Some(ref mut rb) = self.drop_events_rb {
             while let Some(item) = rb.next() {
-                if item.len() >= std::mem::size_of::<DropEvent>() {
-                    let event: DropEvent = unsafe {
-                        std::ptr::read_unaligned(item.as_ptr() as *const DropEvent)
-                    };
-                    
-                    let elapsed_secs = self.start_time.elapsed().as_secs();
-                    let reason_str = drop_reason_str(event.reason);
-                    
-                    let severity = match event.reason {
-                        7 => DropSeverity::Security,   // NETFILTER_DROP
-                        5 => DropSeverity::Security,   // SOCKET_FILTER
-                        2 => DropSeverity::Config,     // NO_SOCKET
-                        37 => DropSeverity::Config,    // IP_OUTNOROUTES
-                        _ => DropSeverity::Normal,
-                    };
-                    
-                    let display = DropEventDisplay {
-                        timestamp_secs: elapsed_secs,
-                        reason: reason_str.to_string(),
-                        hook: None,
-                        severity,
-                    };
-                    
-                    state.drop_events.insert(0, display);
-                    if state.drop_events.len() > 20 {
-                        state.drop_events.pop();
-                    }
+                if let Some(event) = crate::events::view_event::<DropEvent>(&item) {
+                    state.drop_summary.add_event(event);
+                    push_drop(state, drop_display(event, self.start_time.elapsed().as_secs(), crate::ifnames::display));
                 }
             }
         }
@@ -185,35 +213,96 @@ impl RealDataProvider {
         // Poll netfilter events (Phase 6.2)
         if let Some(ref mut rb) = self.nf_events_rb {
             while let Some(item) = rb.next() {
-                if item.len() >= std::mem::size_of::<NetfilterEvent>() {
-                    let event: NetfilterEvent = unsafe {
-                        std::ptr::read_unaligned(item.as_ptr() as *const NetfilterEvent)
-                    };
-                    
-                    // Only show DROP verdicts (verdict == 0)
-                    if event.verdict == 0 {
-                        let elapsed_secs = self.start_time.elapsed().as_secs();
-                        let hook_name = nf_hook_str(event.hook);
-                        let verdict_name = nf_verdict_str(event.verdict);
-                        
-                        let display = DropEventDisplay {
-                            timestamp_secs: elapsed_secs,
-                            reason: format!("NF_{}", verdict_name),
-                            hook: Some(hook_name.to_string()),
-                            severity: DropSeverity::Security, // Netfilter drops are security-relevant
-                        };
-                        
-                        state.drop_events.insert(0, display);
-                        if state.drop_events.len() > 20 {
-                            state.drop_events.pop();
-                        }
-                    }
+                if let Some(display) = crate::events::view_event::<NetfilterEvent>(&item)
+                    .and_then(|event| nf_display(event, self.start_time.elapsed().as_secs(), crate::ifnames::display))
+                {
+                    push_drop(state, display);
+                }
+            }
+        }
+
+        if let Some(ref mut rb) = self.retransmit_events_rb {
+            let now = self.start_time.elapsed();
+            while let Some(item) = rb.next() {
+                if let Some(event) = crate::events::view_event::<RetransmitEvent>(&item) {
+                    push_retransmit(state, event, u64::from(event.sample_rate.max(1)), now);
                 }
             }
         }
     }
 }
 
+/// Row for a kfree_skb drop; `ifname` names an interface index
+#[cfg(unix)]
+fn drop_display(event: &DropEvent, elapsed_secs: u64, ifname: impl Fn(u32) -> String) -> DropEventDisplay {
+    let severity = match event.reason {
+        7 => DropSeverity::Security,   // NETFILTER_DROP
+        5 => DropSeverity::Security,   // SOCKET_FILTER
+        2 => DropSeverity::Config,     // NO_SOCKET
+        37 => DropSeverity::Config,    // IP_OUTNOROUTES
+        _ => DropSeverity::Normal,
+    };
+    DropEventDisplay {
+        timestamp_secs: elapsed_secs,
+        reason: drop_reason_str(event.reason).to_string(),
+        hook: (event.ifindex != 0).then(|| ifname(event.ifindex)),
+        severity,
+    }
+}
+
+/// Row for a netfilter verdict; only DROP verdicts are shown
+#[cfg(unix)]
+fn nf_display(event: &NetfilterEvent, elapsed_secs: u64, ifname: impl Fn(u32) -> String) -> Option<DropEventDisplay> {
+    event.is_drop().then(|| DropEventDisplay {
+        timestamp_secs: elapsed_secs,
+        reason: event.hook_verdict(),
+        hook: Some(match event.ifindex_in {
+            0 => format!("out={}", ifname(event.ifindex_out)),
+            ifindex => format!("in={}", ifname(ifindex)),
+        }),
+        severity: DropSeverity::Security, // Netfilter drops are security-relevant
+    })
+}
+
+/// Row marking events that never reached the TUI
+#[cfg(unix)]
+fn gap_display(lost: u64, what: &str, elapsed_secs: u64) -> DropEventDisplay {
+    DropEventDisplay {
+        timestamp_secs: elapsed_secs,
+        reason: format!("··· {} {} ···", lost, what),
+        hook: None,
+        severity: DropSeverity::Config,
+    }
+}
+
+/// Add a row to the top of the drop list, keeping the newest 20
+#[cfg(unix)]
+fn push_drop(state: &mut AppState, display: DropEventDisplay) {
+    state.drop_events.insert(0, display);
+    state.drop_events.truncate(20);
+}
+
+/// Count a retransmission standing for `count` segments
+#[cfg(unix)]
+fn push_retransmit(state: &mut AppState, event: &RetransmitEvent, count: u64, now: Duration) {
+    state.retransmits.add(crate::ebpf::ip_addr(&event.dst_addr), count, now);
+}
+
+/// Recompute the busiest retransmit destinations as of `now`
+#[cfg(unix)]
+fn refresh_retransmits(state: &mut AppState, now: Duration) {
+    state.top_retransmits = state.retransmits.top(8, now);
+}
+
+/// Add a large packet (IPv4 or IPv6) or a rate anomaly to the top of the
+/// event list
+#[cfg(unix)]
+fn push_packet(state: &mut AppState, event: &PacketEvent, elapsed_secs: u64) {
+    let what = if event.is_anomaly() { "Rate Anomaly" } else { "Large Packet" };
+    state.events.insert(0, format!("[{}s] {}: {}", elapsed_secs, what, describe_packet(event)));
+    state.events.truncate(20);
+}
+
 #[cfg(target_os = "linux")]
 impl DataProvider for RealDataProvider {
     fn update(&mut self, state: &mut AppState) -> Result<()> {
@@ -224,6 +313,9 @@ impl DataProvider for RealDataProvider {
         state.rx_bytes = current.rx_bytes;
         state.tx_packets = current.tx_packets;
         state.tx_bytes = current.tx_bytes;
+        state.rx_proto = current.rx_proto;
+        state.tx_proto = current.tx_proto;
+        state.tcp_flags.update(current.rx_tcp_flags, current.tx_tcp_flags, Instant::now());
         
         // Add event if significant traffic delta detected
         let delta_rx = current.rx_packets.saturating_sub(self.last_counters.rx_packets);
@@ -231,8 +323,20 @@ impl DataProvider for RealDataProvider {
             state.events.insert(0, format!("High RX rate: {} pkts/250ms", delta_rx));
         }
         
+        // Mark gaps in the drop list where the kernel lost events
+        let lost = self.losses.poll();
+        state.events_lost += lost.iter().sum::<u64>();
+        for kind in [RingKind::Drop, RingKind::Netfilter] {
+            let n = lost[kind.index()];
+            if n > 0 {
+                let what = format!("{} events lost (ring buffer full)", kind.name());
+                push_drop(state, gap_display(n, &what, self.start_time.elapsed().as_secs()));
+            }
+        }
+        
         // Poll drop events from RingBuf
         self.poll_drop_events(state);
+        refresh_retransmits(state, self.start_time.elapsed());
         
         self.last_counters = current;
         Ok(())
@@ -240,7 +344,322 @@ impl DataProvider for RealDataProvider {
 }
 
 // -----------------------------------------------------------------------------
-// Mock Data Provider (Windows / Dev)
+// Daemon Data Provider (Linux) - Asks the agent over its control socket, so
+// members of the sennet group can run `top` without access to pinned maps
+#[cfg(target_os = "linux")]
+struct DaemonDataProvider {
+    client: crate::control::Client,
+    events: crate::control::EventStream,
+    last_counters: PacketCounters,
+    start_time: Instant,
+}
+
+#[cfg(target_os = "linux")]
+impl DaemonDataProvider {
+    fn new() -> Result<Self> {
+        let connect = || crate::control::Client::connect().ok_or_else(|| anyhow::anyhow!("Agent control socket not available"));
+        let mut client = connect()?;
+        // Fail early (and fall back to the pinned maps) without counters
+        client.counters()?;
+        Ok(Self {
+            client,
+            events: connect()?.events()?,
+            last_counters: PacketCounters::default(),
+            start_time: Instant::now(),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl DataProvider for DaemonDataProvider {
+    fn update(&mut self, state: &mut AppState) -> Result<()> {
+        use crate::control::{StreamRecord, STREAM_GAP};
+        use crate::events::RawEvent;
+
+        let current = self.client.counters()?;
+        state.rx_packets = current.rx_packets;
+        state.rx_bytes = current.rx_bytes;
+        state.tx_packets = current.tx_packets;
+        state.tx_bytes = current.tx_bytes;
+        state.rx_proto = current.rx_proto;
+        state.tx_proto = current.tx_proto;
+        state.tcp_flags.update(current.rx_tcp_flags, current.tx_tcp_flags, Instant::now());
+
+        let delta_rx = current.rx_packets.saturating_sub(self.last_counters.rx_packets);
+        if delta_rx > 1000 && state.events.len() < 20 {
+            state.events.insert(0, format!("High RX rate: {} pkts/250ms", delta_rx));
+        }
+        self.last_counters = current;
+
+        let elapsed_secs = self.start_time.elapsed().as_secs();
+        for record in self.events.poll(Duration::from_millis(1))? {
+            match record {
+                StreamRecord::Event { raw, count, .. } => match *raw {
+                    RawEvent::Drop(event) => {
+                        push_drop(state, drop_display(&event, elapsed_secs, crate::ifnames::display));
+                    }
+                    RawEvent::Netfilter(event) => {
+                        if let Some(display) = nf_display(&event, elapsed_secs, crate::ifnames::display) {
+                            push_drop(state, display);
+                        }
+                    }
+                    RawEvent::Packet(event) => push_packet(state, &event, elapsed_secs),
+                    RawEvent::Retransmit(event) => {
+                        push_retransmit(state, &event, count, self.start_time.elapsed());
+                    }
+                    _ => {}
+                },
+                StreamRecord::Gap { ring, lost } => {
+                    state.events_lost += lost;
+                    let what = if ring == STREAM_GAP {
+                        "events lost (top fell behind the agent)".to_string()
+                    } else {
+                        format!("{} events lost (ring buffer full)", ring)
+                    };
+                    push_drop(state, gap_display(lost, &what, elapsed_secs));
+                }
+            }
+        }
+        refresh_retransmits(state, self.start_time.elapsed());
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Replay Data Provider (Linux) - Shows a recording (`sennet replay --top`);
+// recordings hold events but no packet counters
+#[cfg(target_os = "linux")]
+struct ReplayDataProvider {
+    replay: crate::replay::FileReplay,
+    finished: bool,
+    /// Offset of the last replayed record, the clock for retransmit rates
+    position: Duration,
+}
+
+#[cfg(target_os = "linux")]
+impl DataProvider for ReplayDataProvider {
+    fn update(&mut self, state: &mut AppState) -> Result<()> {
+        use crate::control::{StreamRecord, STREAM_GAP};
+        use crate::events::RawEvent;
+
+        for replayed in self.replay.poll(Duration::from_millis(1)) {
+            let elapsed_secs = replayed.offset.as_secs();
+            self.position = replayed.offset;
+            match replayed.record {
+                StreamRecord::Event { raw, count, .. } => match *raw {
+                    RawEvent::Drop(event) => {
+                        push_drop(state, drop_display(&event, elapsed_secs, crate::ifnames::display));
+                    }
+                    RawEvent::Netfilter(event) => {
+                        if let Some(display) = nf_display(&event, elapsed_secs, crate::ifnames::display) {
+                            push_drop(state, display);
+                        }
+                    }
+                    RawEvent::Packet(event) => push_packet(state, &event, elapsed_secs),
+                    RawEvent::Retransmit(event) => {
+                        push_retransmit(state, &event, count, replayed.offset);
+                    }
+                    _ => {}
+                },
+                StreamRecord::Gap { ring, lost } => {
+                    state.events_lost += lost;
+                    let what = if ring == STREAM_GAP {
+                        "events lost (recording fell behind the agent)".to_string()
+                    } else {
+                        format!("{} events lost (ring buffer full)", ring)
+                    };
+                    push_drop(state, gap_display(lost, &what, elapsed_secs));
+                }
+            }
+        }
+        refresh_retransmits(state, self.position);
+        if !self.finished && self.replay.finished() {
+            self.finished = true;
+            state.events.insert(0, "End of recording (press q to quit)".to_string());
+        }
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Remote Data Provider (Linux / macOS) - Another agent's REST API
+// (`sennet top --remote`); interface names come from the remote agent
+#[cfg(unix)]
+struct RemoteDataProvider {
+    agent: crate::remote::RemoteAgent,
+    events: crate::remote::RemoteEvents,
+    last_rx_packets: Option<u64>,
+    start_time: Instant,
+}
+
+#[cfg(unix)]
+impl RemoteDataProvider {
+    /// Connect, returning the header line for the remote agent
+    fn new(target: &str, token: Option<String>) -> Result<(Self, String)> {
+        let agent = crate::remote::RemoteAgent::new(target, token);
+        let status = agent.status()?;
+        let events = agent.events(&["drop", "netfilter", "packet", "retransmit"])?;
+        let source = format!("{} (agent {}, v{})", target, status.agent_id, status.version);
+        Ok((Self { agent, events, last_rx_packets: None, start_time: Instant::now() }, source))
+    }
+}
+
+#[cfg(unix)]
+impl DataProvider for RemoteDataProvider {
+    fn update(&mut self, state: &mut AppState) -> Result<()> {
+        use crate::control::{StreamRecord, STREAM_GAP};
+        use crate::events::RawEvent;
+
+        // Agents without eBPF have no counters but may still stream events
+        if let Ok(current) = self.agent.counters() {
+            state.rx_packets = current.rx_packets;
+            state.rx_bytes = current.rx_bytes;
+            state.tx_packets = current.tx_packets;
+            state.tx_bytes = current.tx_bytes;
+            state.rx_proto = current.rx_proto;
+            state.tx_proto = current.tx_proto;
+            state.tcp_flags.update(current.rx_tcp_flags, current.tx_tcp_flags, Instant::now());
+
+            let delta_rx = current.rx_packets.saturating_sub(self.last_rx_packets.unwrap_or(current.rx_packets));
+            if delta_rx > 1000 && state.events.len() < 20 {
+                state.events.insert(0, format!("High RX rate: {} pkts/250ms", delta_rx));
+            }
+            self.last_rx_packets = Some(current.rx_packets);
+        }
+
+        let elapsed_secs = self.start_time.elapsed().as_secs();
+        for record in self.events.poll()? {
+            match record {
+                StreamRecord::Event { raw, count, interface, .. } => match *raw {
+                    RawEvent::Drop(event) => {
+                        let ifname = |index| interface.clone().unwrap_or_else(|| format!("if{}", index));
+                        push_drop(state, drop_display(&event, elapsed_secs, ifname));
+                    }
+                    RawEvent::Netfilter(event) => {
+                        let ifname = |index| interface.clone().unwrap_or_else(|| format!("if{}", index));
+                        if let Some(display) = nf_display(&event, elapsed_secs, ifname) {
+                            push_drop(state, display);
+                        }
+                    }
+                    RawEvent::Packet(event) => push_packet(state, &event, elapsed_secs),
+                    RawEvent::Retransmit(event) => {
+                        push_retransmit(state, &event, count, self.start_time.elapsed());
+                    }
+                    _ => {}
+                },
+                StreamRecord::Gap { ring, lost } => {
+                    state.events_lost += lost;
+                    let what = if ring == STREAM_GAP {
+                        "events lost (top fell behind the remote agent)".to_string()
+                    } else {
+                        format!("{} events lost (ring buffer full)", ring)
+                    };
+                    push_drop(state, gap_display(lost, &what, elapsed_secs));
+                }
+            }
+        }
+        refresh_retransmits(state, self.start_time.elapsed());
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Counter Data Provider (Windows / macOS) - OS interface counters, plus
+// resets and unreachables from a packet capture on macOS
+#[cfg(any(windows, target_os = "macos"))]
+struct CounterDataProvider {
+    interface: Option<String>,
+    /// Discards per interface at the last update, to report new ones
+    last_discards: std::collections::HashMap<u32, (u64, u64)>,
+    #[cfg(target_os = "macos")]
+    signals: Option<std::sync::mpsc::Receiver<crate::capture::Signal>>,
+    start_time: Instant,
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+impl CounterDataProvider {
+    fn new() -> Result<Self> {
+        let interface = crate::config::Config::load().ok().and_then(|c| c.interface);
+        // Fail early (and fall back to mock data) if counters are unavailable
+        crate::ifstats::read_interfaces()?;
+        Ok(Self {
+            #[cfg(target_os = "macos")]
+            signals: Self::spawn_capture(interface.clone()),
+            interface,
+            last_discards: std::collections::HashMap::new(),
+            start_time: Instant::now(),
+        })
+    }
+
+    /// Capture on a background thread; None without capture permission
+    #[cfg(target_os = "macos")]
+    fn spawn_capture(interface: Option<String>) -> Option<std::sync::mpsc::Receiver<crate::capture::Signal>> {
+        let mut capture = crate::capture::open(interface.as_deref()).ok()?;
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok(signal) = crate::capture::next_signal(&mut capture) {
+                if let Some(signal) = signal {
+                    if tx.send(signal).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Some(rx)
+    }
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+impl DataProvider for CounterDataProvider {
+    fn update(&mut self, state: &mut AppState) -> Result<()> {
+        let interfaces = crate::ifstats::read_interfaces()?;
+        let selected = crate::ifstats::select(&interfaces, self.interface.as_deref());
+        let current = crate::ifstats::total(&selected);
+
+        state.rx_packets = current.rx_packets;
+        state.rx_bytes = current.rx_bytes;
+        state.tx_packets = current.tx_packets;
+        state.tx_bytes = current.tx_bytes;
+
+        // The OS only counts discards, without a reason; show them per
+        // interface and direction
+        for iface in selected {
+            let now = (iface.rx_discards, iface.tx_discards);
+            let Some(last) = self.last_discards.insert(iface.index, now) else {
+                continue;
+            };
+            for (delta, direction) in [(now.0.saturating_sub(last.0), "in"), (now.1.saturating_sub(last.1), "out")] {
+                if delta > 0 {
+                    state.drop_events.insert(0, DropEventDisplay {
+                        timestamp_secs: self.start_time.elapsed().as_secs(),
+                        reason: format!("{} DISCARDED ({} packets)", iface.alias, delta),
+                        hook: Some(direction.to_uppercase()),
+                        severity: DropSeverity::Normal,
+                    });
+                    state.drop_events.truncate(20);
+                }
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        if let Some(signals) = &self.signals {
+            for signal in signals.try_iter() {
+                let severity = if signal.reason == "TCP_RESET" { DropSeverity::Normal } else { DropSeverity::Config };
+                state.drop_events.insert(0, DropEventDisplay {
+                    timestamp_secs: self.start_time.elapsed().as_secs(),
+                    reason: format!("{} {}", signal.reason, signal),
+                    hook: None,
+                    severity,
+                });
+                state.drop_events.truncate(20);
+            }
+        }
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Mock Data Provider (Dev)
 struct MockDataProvider {
     start_time: Instant,
 }
@@ -291,7 +710,80 @@ impl DataProvider for MockDataProvider {
 // -----------------------------------------------------------------------------
 // Main Run Function
 
-pub fn run() -> Result<()> {
+pub fn run(args: &[String]) -> Result<()> {
+    let mut remote: Option<String> = None;
+    let mut token = std::env::var("SENNET_API_TOKEN").ok();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--remote" | "-r" => remote = Some(args.next().context("--remote needs host:port")?.clone()),
+            "--token" => token = Some(args.next().context("--token needs a value")?.clone()),
+            other => anyhow::bail!("Unknown option: {}", other),
+        }
+    }
+
+    if let Some(target) = remote {
+        #[cfg(unix)]
+        {
+            let (provider, source) = RemoteDataProvider::new(&target, token)?;
+            return run_provider(Box::new(provider), Some(source));
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (target, token);
+            anyhow::bail!("--remote is not supported on this platform");
+        }
+    }
+
+    // Choose Provider
+    #[cfg(target_os = "linux")]
+    let provider: Box<dyn DataProvider> = match DaemonDataProvider::new() {
+        Ok(daemon) => Box::new(daemon),
+        Err(_) => match RealDataProvider::new() {
+            Ok(real) => Box::new(real),
+            Err(_) => Box::new(MockDataProvider::new()), // Fallback to mock if real fails
+        },
+    };
+
+    #[cfg(any(windows, target_os = "macos"))]
+    let provider: Box<dyn DataProvider> = match CounterDataProvider::new() {
+        Ok(provider) => Box::new(provider),
+        Err(_) => Box::new(MockDataProvider::new()),
+    };
+
+    #[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+    let provider: Box<dyn DataProvider> = Box::new(MockDataProvider::new());
+
+    run_provider(provider, None)
+}
+
+/// Print top command help
+pub fn print_help() {
+    use colored::Colorize;
+
+    println!("{}", "sennet top - Live traffic monitoring dashboard".bold());
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet top [OPTIONS]");
+    println!();
+    println!("{}", "OPTIONS:".yellow());
+    println!("    --remote <HOST:PORT>  Show another agent through its API (api_listen)");
+    println!("    --token <TOKEN>       The remote agent's api_token (or SENNET_API_TOKEN)");
+    println!("    -h, --help            Show this help message");
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sennet top                                  # This host");
+    println!("    SENNET_API_TOKEN=... sennet top --remote node-3:9465");
+}
+
+/// Show a recording (`sennet replay --top`)
+#[cfg(target_os = "linux")]
+pub fn replay(replay: crate::replay::FileReplay) -> Result<()> {
+    let source = format!("replay of a recording started {}", replay.header.started);
+    run_provider(Box::new(ReplayDataProvider { replay, finished: false, position: Duration::ZERO }), Some(source))
+}
+
+fn run_provider(mut provider: Box<dyn DataProvider>, source: Option<String>) -> Result<()> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -305,20 +797,18 @@ pub fn run() -> Result<()> {
         rx_bytes: 0,
         tx_packets: 0,
         tx_bytes: 0,
+        rx_proto: Default::default(),
+        tx_proto: Default::default(),
+        tcp_flags: TcpFlagRates::default(),
+        events_lost: 0,
         events: Vec::new(),
         drop_events: Vec::new(),
+        drop_summary: Default::default(),
+        retransmits: RetransmitRates::new(RETRANSMIT_WINDOW),
+        top_retransmits: Vec::new(),
+        source,
     };
 
-    // Choose Provider
-    #[cfg(target_os = "linux")]
-    let mut provider: Box<dyn DataProvider> = match RealDataProvider::new() {
-        Ok(real) => Box::new(real),
-        Err(_) => Box::new(MockDataProvider::new()), // Fallback to mock if real fails
-    };
-
-    #[cfg(not(target_os = "linux"))]
-    let mut provider: Box<dyn DataProvider> = Box::new(MockDataProvider::new());
-
     // Run Loop
     let res = run_app(&mut terminal, &mut *provider, &mut app_state);
 
@@ -368,6 +858,31 @@ fn run_app<B: Backend>(
     }
 }
 
+/// Stats line with packets and bytes per protocol
+fn proto_line(label: &'static str, counters: &crate::ebpf::ProtoCounters, color: Color) -> Line<'static> {
+    let mut spans = vec![Span::raw(label)];
+    for (name, packets, bytes) in counters.rows() {
+        spans.push(Span::raw(format!("{} ", name)));
+        spans.push(Span::styled(format!("{}/{}B  ", packets, bytes), Style::default().fg(color)));
+    }
+    Line::from(spans)
+}
+
+/// Stats line with TCP control segments per second; resets in red
+fn tcp_flags_line(label: &'static str, rates: Option<&FlagRates>, color: Color) -> Line<'static> {
+    let mut spans = vec![Span::raw(label)];
+    let Some(rates) = rates else {
+        spans.push(Span::styled("-", Style::default().fg(Color::DarkGray)));
+        return Line::from(spans);
+    };
+    for &(name, per_sec) in rates {
+        let color = if name == "RST" && per_sec > 0.0 { Color::Red } else { color };
+        spans.push(Span::raw(format!("{} ", name)));
+        spans.push(Span::styled(format!("{:.1}  ", per_sec), Style::default().fg(color)));
+    }
+    Line::from(spans)
+}
+
 fn ui(f: &mut ratatui::Frame, state: &AppState) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -375,7 +890,7 @@ fn ui(f: &mut ratatui::Frame, state: &AppState) {
         .constraints(
             [
                 Constraint::Length(3),  // Header
-                Constraint::Length(8),  // Stats
+                Constraint::Length(11), // Stats
                 Constraint::Length(10), // Drops (Phase 6.3)
                 Constraint::Min(0),     // Events
             ]
@@ -384,8 +899,12 @@ fn ui(f: &mut ratatui::Frame, state: &AppState) {
         .split(f.area());
 
     // 1. Header
+    let title = match &state.source {
+        Some(source) => format!("Sennet Network Monitor: {} (Press 'q' to quit)", source),
+        None => "Sennet Network Monitor (Press 'q' to quit)".to_string(),
+    };
     let title = Paragraph::new(Span::styled(
-        "Sennet Network Monitor (Press 'q' to quit)",
+        title,
         Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
     ))
     .block(Block::default().borders(Borders::ALL));
@@ -409,6 +928,17 @@ fn ui(f: &mut ratatui::Frame, state: &AppState) {
             Span::raw("TX Bytes:   "),
             Span::styled(format!("{}", state.tx_bytes), Style::default().fg(Color::Blue)),
         ]),
+        proto_line("RX by proto: ", &state.rx_proto, Color::Green),
+        proto_line("TX by proto: ", &state.tx_proto, Color::Blue),
+        tcp_flags_line("TCP in/s:    ", state.tcp_flags.per_sec.as_ref().map(|(rx, _)| rx), Color::Green),
+        tcp_flags_line("TCP out/s:   ", state.tcp_flags.per_sec.as_ref().map(|(_, tx)| tx), Color::Blue),
+        Line::from(vec![
+            Span::raw("Lost Events: "),
+            Span::styled(
+                format!("{}", state.events_lost),
+                Style::default().fg(if state.events_lost > 0 { Color::Red } else { Color::Green }),
+            ),
+        ]),
     ];
     let stats = Paragraph::new(stats_text)
         .block(Block::default().title("Traffic Stats").borders(Borders::ALL));
@@ -429,11 +959,21 @@ fn ui(f: &mut ratatui::Frame, state: &AppState) {
             ListItem::new(Span::styled(text, Style::default().fg(color)))
         })
         .collect();
-    let drops_list = List::new(drop_items)
-        .block(Block::default().title("Recent Drops (Phase 6)").borders(Borders::ALL));
+    let top_reasons: Vec<String> =
+        state.drop_summary.top(3).iter().map(|(reason, count)| format!("{} {}", reason, count)).collect();
+    let drops_title = if top_reasons.is_empty() {
+        "Recent Drops".to_string()
+    } else {
+        format!("Recent Drops ({})", top_reasons.join(", "))
+    };
+    let drops_list = List::new(drop_items).block(Block::default().title(drops_title).borders(Borders::ALL));
     f.render_widget(drops_list, chunks[2]);
 
-    // 4. Events
+    // 4. Events, with retransmits per destination alongside
+    let bottom = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(chunks[3]);
     let events: Vec<ListItem> = state
         .events
         .iter()
@@ -441,6 +981,21 @@ fn ui(f: &mut ratatui::Frame, state: &AppState) {
         .collect();
     let events_list = List::new(events)
         .block(Block::default().title("Recent Events").borders(Borders::ALL));
-    f.render_widget(events_list, chunks[3]);
+    f.render_widget(events_list, bottom[0]);
+
+    let retransmit_items: Vec<ListItem> = state
+        .top_retransmits
+        .iter()
+        .map(|r| {
+            let text = format!("{:<39} {:>7.1}/s {:>6}", r.dst.to_string(), r.per_sec, r.count);
+            ListItem::new(Span::styled(text, Style::default().fg(Color::Yellow)))
+        })
+        .collect();
+    let retransmits_list = List::new(retransmit_items).block(
+        Block::default()
+            .title(format!("Retransmits ({}s)", RETRANSMIT_WINDOW.as_secs()))
+            .borders(Borders::ALL),
+    );
+    f.render_widget(retransmits_list, bottom[1]);
 }
 
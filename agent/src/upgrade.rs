@@ -7,7 +7,6 @@ use sha2::{Sha256, Digest};
 use std::fs;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
 
 /// GitHub repository for releases
 const GITHUB_REPO: &str = "MannanSaood/Sennet";
@@ -192,27 +191,19 @@ impl Updater {
         Ok(())
     }
 
-    /// Trigger systemd restart
+    /// Restart the service through whichever init system manages it
     fn trigger_restart(&self) -> Result<()> {
-        tracing::info!("Triggering service restart...");
-        
-        // Use systemctl to restart
-        let status = Command::new("systemctl")
-            .args(["restart", "sennet"])
-            .status();
+        let init = crate::service::InitSystem::detect();
+        tracing::info!("Triggering service restart via {}...", init.name());
 
-        match status {
-            Ok(s) if s.success() => {
+        match init.restart() {
+            Ok(()) => {
                 tracing::info!("Service restart triggered");
                 Ok(())
             }
-            Ok(s) => {
-                tracing::warn!("systemctl restart returned: {}", s);
-                Ok(()) // Non-fatal
-            }
             Err(e) => {
-                tracing::warn!("Failed to trigger restart: {}", e);
-                tracing::info!("Please restart manually: sudo systemctl restart sennet");
+                tracing::warn!("Failed to trigger restart: {:#}", e);
+                tracing::info!("Please restart manually: {}", init.hint("restart"));
                 Ok(()) // Non-fatal
             }
         }
@@ -0,0 +1,553 @@
+//! Workload Watch
+//!
+//! `sennet watch <workload>` follows one pod, container or process and
+//! prints a single timeline of its networking: flows opening and closing,
+//! drops and resets on its connections, netfilter verdicts on its
+//! interfaces, TCP retransmits in its network namespace, its DNS lookups
+//! and the conntrack entries of its connections. It is `tail -f` for one
+//! workload.
+//!
+//! Events come from the running agent's event stream. The workload is
+//! resolved to its processes (the PID and its children, or every process
+//! whose cgroup names the container), the addresses of its network
+//! namespace and the host side of its veth interfaces. Resolution is redone
+//! every few seconds as processes come and go. A drop or reset belongs to the
+//! workload if its tuple is one of the workload's flows, or one of its
+//! addresses is involved.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::collections::HashSet;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::control::StreamRecord;
+use crate::ebpf::{comm_to_string, describe_conntrack, describe_dns, describe_drop_packet, describe_http, describe_packet, describe_payload, describe_retransmit, drop_reason_str, flow_event_type_str, ipv4_addr};
+use crate::events::RawEvent;
+
+/// How often processes, addresses and interfaces are resolved again
+const REFRESH: Duration = Duration::from_secs(3);
+
+/// (src_ip, dst_ip, src_port, dst_port) in the eBPF encoding
+type Tuple = (u32, u32, u16, u16);
+
+/// What to watch, as given on the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Pid(u32),
+    /// Full container ID or a prefix of at least 12 characters
+    Container(String),
+    Pod { namespace: String, name: String },
+}
+
+impl Target {
+    /// `1234` or `pid:1234`, `container:<id>`, or `pod:<name>`,
+    /// `<namespace>/<name>` or a bare pod name
+    pub fn parse(spec: &str, namespace: Option<&str>) -> Result<Self> {
+        let pod = |name: &str, ns: Option<&str>| Target::Pod {
+            namespace: ns.or(namespace).unwrap_or("default").to_string(),
+            name: name.to_string(),
+        };
+        if let Some(pid) = spec.strip_prefix("pid:") {
+            return Ok(Target::Pid(pid.parse().with_context(|| format!("Invalid PID: {}", pid))?));
+        }
+        if let Some(id) = spec.strip_prefix("container:") {
+            anyhow::ensure!(id.len() >= 12, "Container ID '{}' is too short (give at least 12 characters)", id);
+            return Ok(Target::Container(id.to_lowercase()));
+        }
+        let spec = spec.strip_prefix("pod:").unwrap_or(spec);
+        if let Ok(pid) = spec.parse() {
+            return Ok(Target::Pid(pid));
+        }
+        match spec.split_once('/') {
+            Some((ns, name)) => Ok(pod(name, Some(ns))),
+            None => Ok(pod(spec, None)),
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Pid(pid) => write!(f, "pid {}", pid),
+            Target::Container(id) => write!(f, "container {}", &id[..id.len().min(12)]),
+            Target::Pod { namespace, name } => write!(f, "pod {}/{}", namespace, name),
+        }
+    }
+}
+
+/// A resolved workload and the flows seen for it
+#[derive(Debug, Default)]
+pub struct Workload {
+    pids: HashSet<u32>,
+    /// Addresses of its network namespace (none when it shares the host's)
+    addrs: HashSet<Ipv4Addr>,
+    /// Host-side interfaces of its veth pairs
+    ifindexes: HashSet<u32>,
+    tuples: HashSet<Tuple>,
+    /// A process whose network namespace is the workload's own
+    netns_pid: Option<u32>,
+}
+
+impl Workload {
+    /// Add a flow of the workload
+    fn track(&mut self, tuple: Tuple) {
+        self.tuples.insert(tuple);
+    }
+
+    fn owns_tuple(&self, (src, dst, sport, dport): Tuple) -> bool {
+        self.tuples.contains(&(src, dst, sport, dport)) || self.tuples.contains(&(dst, src, dport, sport))
+    }
+
+    fn owns_addr(&self, ip: u32) -> bool {
+        ip != 0 && self.addrs.contains(&ipv4_addr(ip))
+    }
+
+    fn owns_ifindex(&self, ifindex: u32) -> bool {
+        ifindex != 0 && self.ifindexes.contains(&ifindex)
+    }
+
+    /// Whether an event is the workload's; flow events of its processes
+    /// also start tracking their tuple
+    pub fn claims(&mut self, raw: &RawEvent) -> bool {
+        match raw {
+            RawEvent::Flow(e) => {
+                let tuple = (e.src_ip, e.dst_ip, e.src_port, e.dst_port);
+                if !self.pids.contains(&e.pid) && !self.owns_tuple(tuple) {
+                    return false;
+                }
+                // Closed flows stay tracked: their last drops and resets
+                // arrive after the close
+                self.track(tuple);
+                true
+            }
+            RawEvent::Drop(e) => {
+                (e.has_tuple() && self.owns_tuple((e.src_ip, e.dst_ip, e.src_port, e.dst_port)))
+                    || (e.has_tuple() && (self.owns_addr(e.src_ip) || self.owns_addr(e.dst_ip)))
+                    || [&e.src_addr, &e.dst_addr]
+                        .into_iter()
+                        .filter_map(sennet_common::mapped_ipv4)
+                        .any(|addr| self.addrs.contains(&Ipv4Addr::from(addr)))
+                    || self.owns_ifindex(e.ifindex)
+            }
+            RawEvent::Rst(e) => {
+                self.owns_tuple((e.src_ip, e.dst_ip, e.src_port, e.dst_port))
+                    || self.owns_addr(e.src_ip)
+                    || self.owns_addr(e.dst_ip)
+                    || self.owns_ifindex(e.ifindex)
+            }
+            RawEvent::Netfilter(e) => self.owns_ifindex(e.ifindex_in) || self.owns_ifindex(e.ifindex_out),
+            RawEvent::Packet(e) => {
+                [&e.src_addr, &e.dst_addr]
+                    .into_iter()
+                    .filter_map(sennet_common::mapped_ipv4)
+                    .any(|addr| self.addrs.contains(&Ipv4Addr::from(addr)))
+                    || self.owns_ifindex(e.ifindex)
+            }
+            RawEvent::Payload(e) => {
+                [&e.src_addr, &e.dst_addr]
+                    .into_iter()
+                    .filter_map(sennet_common::mapped_ipv4)
+                    .any(|addr| self.addrs.contains(&Ipv4Addr::from(addr)))
+                    || self.owns_ifindex(e.ifindex)
+            }
+            RawEvent::Http(e) => {
+                [&e.src_addr, &e.dst_addr]
+                    .into_iter()
+                    .filter_map(sennet_common::mapped_ipv4)
+                    .any(|addr| self.addrs.contains(&Ipv4Addr::from(addr)))
+                    || self.owns_ifindex(e.ifindex)
+            }
+            RawEvent::Retransmit(e) => [&e.src_addr, &e.dst_addr]
+                .into_iter()
+                .filter_map(sennet_common::mapped_ipv4)
+                .any(|addr| self.addrs.contains(&Ipv4Addr::from(addr))),
+            RawEvent::Dns(e) => {
+                sennet_common::mapped_ipv4(&e.client_addr).is_some_and(|addr| self.addrs.contains(&Ipv4Addr::from(addr)))
+                    || self.owns_ifindex(e.ifindex)
+            }
+            RawEvent::Conntrack(e) => [&e.src_addr, &e.dst_addr, &e.reply_src_addr, &e.reply_dst_addr]
+                .into_iter()
+                .filter_map(sennet_common::mapped_ipv4)
+                .any(|addr| self.addrs.contains(&Ipv4Addr::from(addr))),
+        }
+    }
+}
+
+/// Resolves a target to a [`Workload`], again and again
+struct Resolver {
+    target: Target,
+    /// Container IDs of a pod, or the one given
+    container_ids: Vec<String>,
+    /// A pod's IP from the API server
+    pod_ip: Option<Ipv4Addr>,
+}
+
+impl Resolver {
+    async fn new(target: Target) -> Result<Self> {
+        let (container_ids, pod_ip) = match &target {
+            Target::Pid(_) => (Vec::new(), None),
+            Target::Container(id) => (vec![id.clone()], None),
+            Target::Pod { namespace, name } => {
+                let pod = crate::k8s::K8sManager::fetch_pod(namespace, name).await?;
+                anyhow::ensure!(!pod.container_ids.is_empty(), "Pod {}/{} has no running containers", namespace, name);
+                if !pod.node_name.is_empty() {
+                    println!("{}", format!("Pod {}/{} runs on node {}", namespace, name, pod.node_name).dimmed());
+                }
+                (pod.container_ids, pod.ip.and_then(|ip| ip.parse().ok()))
+            }
+        };
+        Ok(Self { target, container_ids, pod_ip })
+    }
+
+    fn resolve(&self, workload: &mut Workload) {
+        workload.pids = match &self.target {
+            Target::Pid(pid) => with_descendants(*pid),
+            _ => container_pids(&self.container_ids),
+        };
+        workload.netns_pid = workload.pids.iter().copied().find(|&pid| has_own_netns(pid));
+        workload.addrs = workload.netns_pid.map(netns_addrs).unwrap_or_default();
+        // A hostNetwork pod's IP is the node's
+        if workload.netns_pid.is_some() || workload.pids.is_empty() {
+            workload.addrs.extend(self.pod_ip);
+        }
+        workload.ifindexes = workload.netns_pid.map(host_veth_ifindexes).unwrap_or_default();
+    }
+}
+
+/// Processes in /proc with their parent PIDs
+fn processes() -> Vec<(u32, u32)> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            let stat = fs::read_to_string(entry.path().join("stat")).ok()?;
+            Some((pid, parse_ppid(&stat)?))
+        })
+        .collect()
+}
+
+/// Parent PID from /proc/<pid>/stat (the command name may contain spaces)
+fn parse_ppid(stat: &str) -> Option<u32> {
+    stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// `root` and every process started under it
+fn with_descendants(root: u32) -> HashSet<u32> {
+    let processes = processes();
+    let mut pids = HashSet::from([root]);
+    loop {
+        let before = pids.len();
+        pids.extend(processes.iter().filter(|(_, ppid)| pids.contains(ppid)).map(|(pid, _)| *pid).collect::<Vec<_>>());
+        if pids.len() == before {
+            return pids;
+        }
+    }
+}
+
+/// Processes whose cgroup names one of the containers
+pub fn container_pids(container_ids: &[String]) -> HashSet<u32> {
+    processes()
+        .into_iter()
+        .map(|(pid, _)| pid)
+        .filter(|&pid| {
+            let id = crate::docker::get_container_id_from_pid(pid).or_else(|| crate::k8s::container_id_from_pid(pid));
+            id.is_some_and(|id| container_ids.iter().any(|want| id.starts_with(want.as_str())))
+        })
+        .collect()
+}
+
+pub fn has_own_netns(pid: u32) -> bool {
+    let ns = |path: &str| fs::read_link(path).ok();
+    ns(&format!("/proc/{}/ns/net", pid)).is_some_and(|ns_pid| Some(ns_pid) != ns("/proc/self/ns/net"))
+}
+
+/// Local IPv4 addresses of a process's network namespace, loopback aside
+fn netns_addrs(pid: u32) -> HashSet<Ipv4Addr> {
+    fs::read_to_string(format!("/proc/{}/net/fib_trie", pid))
+        .map(|trie| parse_fib_trie_local(&trie))
+        .unwrap_or_default()
+}
+
+/// Addresses listed as `/32 host LOCAL` in /proc/net/fib_trie
+fn parse_fib_trie_local(trie: &str) -> HashSet<Ipv4Addr> {
+    let mut addrs = HashSet::new();
+    let mut last: Option<Ipv4Addr> = None;
+    for line in trie.lines().map(str::trim) {
+        if let Some(addr) = line.strip_prefix("|-- ") {
+            last = addr.parse().ok();
+        } else if line == "/32 host LOCAL" {
+            addrs.extend(last.filter(|addr| !addr.is_loopback()));
+        }
+    }
+    addrs
+}
+
+/// Host ends of the veth pairs in a process's network namespace, read
+/// through its root directory (a container's sysfs shows its own netns)
+fn host_veth_ifindexes(pid: u32) -> HashSet<u32> {
+    let dir = format!("/proc/{}/root/sys/class/net", pid);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return HashSet::new();
+    };
+    let read = |path: &Path| fs::read_to_string(path).ok().and_then(|s| s.trim().parse::<u32>().ok());
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let ifindex = read(&entry.path().join("ifindex"))?;
+            let iflink = read(&entry.path().join("iflink"))?;
+            (iflink != ifindex).then_some(iflink)
+        })
+        .collect()
+}
+
+/// Segments retransmitted in a process's network namespace
+fn retrans_segs(pid: u32) -> Option<u64> {
+    parse_retrans_segs(&fs::read_to_string(format!("/proc/{}/net/snmp", pid)).ok()?)
+}
+
+/// `RetransSegs` from the two `Tcp:` lines of /proc/net/snmp
+fn parse_retrans_segs(snmp: &str) -> Option<u64> {
+    let mut tcp = snmp.lines().filter(|line| line.starts_with("Tcp:"));
+    let (names, values) = (tcp.next()?, tcp.next()?);
+    let column = names.split_whitespace().position(|name| name == "RetransSegs")?;
+    values.split_whitespace().nth(column)?.parse().ok()
+}
+
+fn endpoints(src: u32, sport: u16, dst: u32, dport: u16) -> String {
+    format!("{}:{} → {}:{}", ipv4_addr(src), sport, ipv4_addr(dst), dport)
+}
+
+/// Timeline line for an event
+fn describe(raw: &RawEvent, count: u64, interface: Option<&str>) -> (colored::ColoredString, String) {
+    let on = interface.map(|name| format!(" on {}", name)).unwrap_or_default();
+    let repeats = if count > 1 { format!("  ×{}", count) } else { String::new() };
+    match raw {
+        RawEvent::Flow(e) => (
+            "FLOW".green(),
+            format!(
+                "{} {} (pid {}) {}",
+                flow_event_type_str(e.event_type),
+                comm_to_string(&e.comm),
+                e.pid,
+                endpoints(e.src_ip, e.src_port, e.dst_ip, e.dst_port)
+            ),
+        ),
+        RawEvent::Drop(e) => {
+            let tuple = if let Some(packet) = describe_drop_packet(e) {
+                format!(" {}", packet)
+            } else if e.has_tuple() {
+                format!(" {}", endpoints(e.src_ip, e.src_port, e.dst_ip, e.dst_port))
+            } else {
+                String::new()
+            };
+            ("DROP".red(), format!("{}{}{}{}", drop_reason_str(e.reason), tuple, on, repeats))
+        }
+        RawEvent::Rst(e) => {
+            let dir = if e.direction == 1 { "sent" } else { "received" };
+            ("RESET".yellow(), format!("{} {}{}{}", dir, endpoints(e.src_ip, e.src_port, e.dst_ip, e.dst_port), on, repeats))
+        }
+        RawEvent::Netfilter(e) => (
+            "POLICY".magenta(),
+            format!(
+                "{} in={} out={}{}",
+                e.hook_verdict(),
+                crate::ifnames::display(e.ifindex_in),
+                crate::ifnames::display(e.ifindex_out),
+                repeats
+            ),
+        ),
+        RawEvent::Packet(e) if e.is_anomaly() => ("RATE".red(), format!("{}{}{}", describe_packet(e), on, repeats)),
+        RawEvent::Packet(e) => ("LARGE".blue(), format!("{}{}{}", describe_packet(e), on, repeats)),
+        RawEvent::Retransmit(e) => ("RETRAN".yellow(), format!("{}{}", describe_retransmit(e), repeats)),
+        RawEvent::Dns(e) => ("DNS".cyan(), format!("{}{}{}", describe_dns(e), on, repeats)),
+        RawEvent::Conntrack(e) => ("CT".blue(), format!("{}{}", describe_conntrack(e), repeats)),
+        RawEvent::Payload(e) => ("PAYLOAD".blue(), format!("{}{}{}", describe_payload(e), on, repeats)),
+        RawEvent::Http(e) => ("HTTP".cyan(), format!("{}{}{}", describe_http(e), on, repeats)),
+    }
+}
+
+fn time_column(time: Option<&str>) -> String {
+    let time: chrono::DateTime<chrono::Local> = time
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .map(Into::into)
+        .unwrap_or_else(|| SystemTime::now().into());
+    time.format("%H:%M:%S%.3f").to_string()
+}
+
+/// Follow the daemon's event stream for the workload until interrupted
+fn follow(resolver: Resolver, mut stream: crate::control::EventStream) -> Result<()> {
+    let mut workload = Workload::default();
+    let mut resolved = Instant::now() - REFRESH;
+    let mut retrans: Option<u64> = None;
+    let mut warned_empty = false;
+
+    println!("{:>12}  {:6}  DETAILS", "TIME", "EVENT");
+    println!("{}", "─".repeat(72));
+    loop {
+        if resolved.elapsed() >= REFRESH {
+            resolver.resolve(&mut workload);
+            resolved = Instant::now();
+            if workload.pids.is_empty() && workload.addrs.is_empty() && !warned_empty {
+                println!("{}", format!("No processes of {} on this host yet; waiting", resolver.target).dimmed());
+                warned_empty = true;
+            }
+            // Flows opened before the watch started
+            if let Some(flows) = crate::control::Client::connect().and_then(|mut c| c.flows().ok()) {
+                let opened: Vec<_> = flows
+                    .iter()
+                    .filter(|(_, info)| workload.pids.contains(&info.pid))
+                    .map(|(key, _)| (key.src_ip, key.dst_ip, key.src_port, key.dst_port))
+                    .collect();
+                for flow in opened {
+                    workload.track(flow);
+                }
+            }
+            // Retransmits are per network namespace; a host process's would
+            // be everyone's
+            let now = workload.netns_pid.and_then(retrans_segs);
+            if let (Some(before), Some(now)) = (retrans, now) {
+                if now > before {
+                    println!("{}  {:6}  +{} TCP segments", time_column(None), "RETRAN".yellow(), now - before);
+                }
+            }
+            retrans = now;
+        }
+
+        for record in stream.poll(Duration::from_millis(500))? {
+            match record {
+                StreamRecord::Event { raw, count, interface, time } => {
+                    if workload.claims(&raw) {
+                        let (kind, details) = describe(&raw, count, interface.as_deref());
+                        println!("{}  {:6}  {}", time_column(time.as_deref()), kind, details);
+                    }
+                }
+                StreamRecord::Gap { ring, lost } => {
+                    println!("{}  {}", time_column(None), format!("··· {} {} events lost ···", lost, ring).yellow());
+                }
+            }
+        }
+    }
+}
+
+/// Run `sennet watch`
+pub async fn run(args: &[String]) -> Result<()> {
+    let mut spec: Option<&str> = None;
+    let mut namespace: Option<&str> = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" | "--namespace" => namespace = Some(args.next().context("--namespace needs a value")?),
+            other if other.starts_with('-') => anyhow::bail!("Unknown option: {}", other),
+            other => spec = Some(other),
+        }
+    }
+    let target = Target::parse(spec.context("Name a pod, container or PID to watch (see sennet watch --help)")?, namespace)?;
+
+    let stream = crate::control::Client::connect()
+        .context("sennet watch follows the running agent's event stream; start the agent with pipeline.enabled")?
+        .events()?;
+    let resolver = Resolver::new(target).await?;
+    println!("Watching {} (Ctrl-C to stop)", resolver.target.to_string().cyan());
+    println!();
+    tokio::task::spawn_blocking(move || follow(resolver, stream)).await?
+}
+
+/// Print watch command help
+pub fn print_help() {
+    println!("{}", "sennet watch - Follow one workload's networking".bold());
+    println!();
+    println!("{}", "USAGE:".yellow());
+    println!("    sennet watch <WORKLOAD> [OPTIONS]");
+    println!();
+    println!("{}", "WORKLOAD:".yellow());
+    println!("    {}            A process and its children", "<PID>, pid:<PID>".cyan());
+    println!("    {}      Processes of a container (12+ characters of its ID)", "container:<ID>".cyan());
+    println!("    {}  A Kubernetes pod", "<NAME>, <NS>/<NAME>".cyan());
+    println!();
+    println!("{}", "OPTIONS:".yellow());
+    println!("    {}  Namespace of the pod (default: default)", "-n, --namespace <NS>".cyan());
+    println!();
+    println!("{}", "EXAMPLES:".yellow());
+    println!("    sennet watch 1234");
+    println!("    sennet watch container:4f1c2d3e5a6b");
+    println!("    sennet watch production/checkout-7d9f8");
+    println!();
+    println!("{}", "NOTES:".yellow());
+    println!("    Shows one timeline of the workload's flows (FLOW), drops and resets on");
+    println!("    its connections (DROP, RESET), netfilter verdicts on its interfaces");
+    println!("    (POLICY), TCP retransmits in its network namespace (RETRAN), its DNS");
+    println!("    lookups (DNS) and conntrack entries of its connections (CT).");
+    println!("    Needs the running agent with pipeline.enabled, on the workload's node.");
+    println!("    Retransmits are only shown for workloads with their own network namespace.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{DropEvent, FlowEvent, NetfilterEvent};
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(Target::parse("1234", None).unwrap(), Target::Pid(1234));
+        assert_eq!(Target::parse("pid:99", None).unwrap(), Target::Pid(99));
+        assert_eq!(
+            Target::parse("container:4F1C2D3E5A6B7C", None).unwrap(),
+            Target::Container("4f1c2d3e5a6b7c".into())
+        );
+        assert!(Target::parse("container:4f1c", None).is_err());
+        assert_eq!(
+            Target::parse("checkout", Some("prod")).unwrap(),
+            Target::Pod { namespace: "prod".into(), name: "checkout".into() }
+        );
+        assert_eq!(
+            Target::parse("staging/api", Some("prod")).unwrap(),
+            Target::Pod { namespace: "staging".into(), name: "api".into() }
+        );
+    }
+
+    #[test]
+    fn test_claims_events_of_workload_flows() {
+        let ip = |a: [u8; 4]| u32::from(Ipv4Addr::from(a));
+        let (pod, db) = (ip([10, 244, 1, 5]), ip([10, 96, 0, 10]));
+        let mut workload = Workload { pids: HashSet::from([42]), ifindexes: HashSet::from([17]), ..Default::default() };
+
+        let other = FlowEvent { event_type: 1, pid: 7, src_ip: ip([10, 0, 0, 1]), dst_ip: db, src_port: 1000, dst_port: 5432, ..Default::default() };
+        assert!(!workload.claims(&RawEvent::Flow(other)));
+
+        let flow = FlowEvent { event_type: 1, pid: 42, src_ip: pod, dst_ip: db, src_port: 43210, dst_port: 5432, ..Default::default() };
+        assert!(workload.claims(&RawEvent::Flow(flow)));
+
+        // Drops on that flow, in either direction
+        let drop = |src, dst, sport, dport| RawEvent::Drop(DropEvent { reason: 7, src_ip: src, dst_ip: dst, src_port: sport, dst_port: dport, ..Default::default() });
+        assert!(workload.claims(&drop(pod, db, 43210, 5432)));
+        assert!(workload.claims(&drop(db, pod, 5432, 43210)));
+        assert!(!workload.claims(&drop(pod, db, 43211, 5432)));
+        assert!(!workload.claims(&drop(0, 0, 0, 0)));
+
+        // Drops to the pod's address without a flow yet, once it is known
+        workload.addrs.insert(Ipv4Addr::from([10, 244, 1, 5]));
+        assert!(workload.claims(&drop(ip([10, 0, 0, 9]), pod, 50000, 8080)));
+
+        let nf = |ifindex_in| RawEvent::Netfilter(NetfilterEvent { ifindex_in, ..Default::default() });
+        assert!(workload.claims(&nf(17)));
+        assert!(!workload.claims(&nf(3)));
+    }
+
+    #[test]
+    fn test_parse_proc_files() {
+        assert_eq!(parse_ppid("1234 (my (odd) proc) S 77 1234 1234 0"), Some(77));
+
+        let trie = "Main:\n  +-- 0.0.0.0/0 3 0 5\n     |-- 0.0.0.0\n        /0 universe UNICAST\n     +-- 10.244.1.0/24 2 0 2\n        |-- 10.244.1.5\n           /32 host LOCAL\n     |-- 127.0.0.1\n        /32 host LOCAL\nLocal:\n     |-- 10.244.1.5\n           /32 host LOCAL\n";
+        assert_eq!(parse_fib_trie_local(trie), HashSet::from([Ipv4Addr::new(10, 244, 1, 5)]));
+
+        let snmp = "Ip: Forwarding DefaultTTL\nIp: 1 64\nTcp: RtoAlgorithm RtoMin RetransSegs InErrs\nTcp: 1 200 37 0\n";
+        assert_eq!(parse_retrans_segs(snmp), Some(37));
+        assert_eq!(parse_retrans_segs("Ip: 1\n"), None);
+    }
+}
@@ -0,0 +1,58 @@
+//! Allocation guard for the event hot path
+//!
+//! Decoding a ring buffer record and adding it to a summary must not
+//! allocate once the summary has seen the key. Counting allocations needs a
+//! `#[global_allocator]`, which would apply to every unit test in the
+//! library, so this lives in its own test binary.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::Arc;
+
+use sennet_agent::events::{self, DropEvent, EventHeader, RawEvent, EVENT_HEADER_LEN};
+use sennet_agent::pipeline::{EnrichedEvent, Summary};
+
+// Counts allocations made by the current thread (other test threads don't
+// interfere)
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+fn allocations() -> u64 {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn test_hot_path_does_not_allocate() {
+    let mut bytes = [0u8; EVENT_HEADER_LEN + std::mem::size_of::<DropEvent>()];
+    bytes[..8].copy_from_slice(&EventHeader::of::<DropEvent>().to_bytes());
+    bytes[16..20].copy_from_slice(&7u32.to_ne_bytes()); // reason
+    let ifname: Arc<str> = Arc::from("eth0");
+    let mut summary = Summary::default();
+    // First event for a key inserts into the maps
+    summary.add(&EnrichedEvent::new(events::read_event(&bytes).map(RawEvent::Drop).unwrap(), Some(ifname.clone())));
+
+    let before = allocations();
+    for _ in 0..1000 {
+        let raw = events::decode(&bytes).unwrap();
+        summary.add(&EnrichedEvent::new(raw, Some(ifname.clone())));
+    }
+    assert_eq!(allocations() - before, 0);
+    assert_eq!(summary.drops_by_interface.get("eth0"), Some(&1001));
+}
@@ -0,0 +1,74 @@
+package handler
+
+import (
+	"encoding/json"
+	"fmt"
+	"io"
+	"log"
+	"net/http"
+	"os"
+	"path/filepath"
+	"regexp"
+	"time"
+)
+
+// maxCrashReportSize bounds the request body (backtraces and log tails included)
+const maxCrashReportSize = 1 << 20
+
+var unsafeIDChars = regexp.MustCompile(`[^A-Za-z0-9_-]`)
+
+// CrashHandler stores crash reports uploaded by agents
+type CrashHandler struct {
+	dir string
+}
+
+func NewCrashHandler(dir string) *CrashHandler {
+	return &CrashHandler{
+		dir: dir,
+	}
+}
+
+// HandleCrashReport saves the report as <dir>/<agentId>-<timestamp>.json
+func (h *CrashHandler) HandleCrashReport(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodPost {
+		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	body, err := io.ReadAll(io.LimitReader(r.Body, maxCrashReportSize+1))
+	if err != nil || len(body) > maxCrashReportSize {
+		http.Error(w, "Invalid request body", http.StatusBadRequest)
+		return
+	}
+
+	var report struct {
+		AgentID   string `json:"agentId"`
+		Version   string `json:"version"`
+		Timestamp int64  `json:"timestamp"`
+		Message   string `json:"message"`
+		Location  string `json:"location"`
+	}
+	if err := json.Unmarshal(body, &report); err != nil {
+		http.Error(w, "Invalid request body", http.StatusBadRequest)
+		return
+	}
+	if report.AgentID == "" {
+		http.Error(w, "agentId is required", http.StatusBadRequest)
+		return
+	}
+
+	if err := os.MkdirAll(h.dir, 0o750); err != nil {
+		http.Error(w, "Failed to store crash report", http.StatusInternalServerError)
+		return
+	}
+	name := fmt.Sprintf("%s-%d.json", unsafeIDChars.ReplaceAllString(report.AgentID, "_"), report.Timestamp)
+	if err := os.WriteFile(filepath.Join(h.dir, name), body, 0o640); err != nil {
+		http.Error(w, "Failed to store crash report", http.StatusInternalServerError)
+		return
+	}
+
+	log.Printf("Crash report from agent %s (v%s, %s): %s at %s",
+		report.AgentID, report.Version, time.Unix(report.Timestamp, 0).UTC().Format(time.RFC3339),
+		report.Message, report.Location)
+	w.WriteHeader(http.StatusAccepted)
+}
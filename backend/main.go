@@ -193,6 +193,15 @@ func runServer(port, dbPath, latestVersion string) {
 	mux.Handle("/api/sync-costs", authWrapper(http.HandlerFunc(costHandler.HandleSyncCosts)))
 	log.Printf("  Cost API endpoints: /api/costs, /api/clouds, /api/recommendations")
 
+	// Agent crash reports (with auth)
+	crashDir := os.Getenv("CRASH_REPORT_DIR")
+	if crashDir == "" {
+		crashDir = "crash-reports"
+	}
+	crashHandler := handler.NewCrashHandler(crashDir)
+	mux.Handle("/api/agent/crash", authWrapper(http.HandlerFunc(crashHandler.HandleCrashReport)))
+	log.Printf("  Crash reports: /api/agent/crash (stored in %s)", crashDir)
+
 	// Dashboard endpoints (stats requires auth, dashboard is public)
 	statsHandler := handler.NewStatsHandler(database)
 
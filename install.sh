@@ -221,13 +221,29 @@ EOF
     fi
 fi
 
-# Create systemd service
-info "Creating systemd service..."
-SYSTEMD_SERVICE="/etc/systemd/system/${SERVICE_NAME}.service"
+# Detect init system (same checks as `sennet status`)
+detect_init() {
+    if [[ -d /run/systemd/system ]]; then
+        echo "systemd"
+    elif [[ -d /run/openrc || -x /sbin/openrc-run ]]; then
+        echo "openrc"
+    elif [[ -d /run/runit || -d /etc/runit ]]; then
+        echo "runit"
+    elif [[ -d /etc/init.d ]]; then
+        echo "sysvinit"
+    else
+        echo "unknown"
+    fi
+}
+INIT_SYSTEM="$(detect_init)"
+info "Detected init system: $INIT_SYSTEM"
 
-if [[ "$DRY_RUN" == "true" ]]; then
-    info "[DRY-RUN] Would create service: $SYSTEMD_SERVICE"
-else
+install_systemd() {
+    SYSTEMD_SERVICE="/etc/systemd/system/${SERVICE_NAME}.service"
+    if [[ "$DRY_RUN" == "true" ]]; then
+        info "[DRY-RUN] Would create service: $SYSTEMD_SERVICE"
+        return
+    fi
     cat > "$SYSTEMD_SERVICE" << EOF
 [Unit]
 Description=Sennet Network Observability Agent
@@ -250,19 +266,180 @@ ReadWritePaths=${STATE_DIR}
 ReadOnlyPaths=${CONFIG_DIR}
 
 # eBPF requires these capabilities
-AmbientCapabilities=CAP_BPF CAP_NET_ADMIN CAP_SYS_ADMIN
-CapabilityBoundingSet=CAP_BPF CAP_NET_ADMIN CAP_SYS_ADMIN
+AmbientCapabilities=CAP_BPF CAP_PERFMON CAP_NET_ADMIN CAP_SYS_ADMIN CAP_SYS_RESOURCE
+CapabilityBoundingSet=CAP_BPF CAP_PERFMON CAP_NET_ADMIN CAP_SYS_ADMIN CAP_SYS_RESOURCE
 
 [Install]
 WantedBy=multi-user.target
 EOF
     success "Created systemd service"
-    
+
     # Reload and enable service
     systemctl daemon-reload
     systemctl enable "$SERVICE_NAME" 2>/dev/null || true
     success "Enabled $SERVICE_NAME service"
-fi
+}
+
+install_openrc() {
+    OPENRC_SERVICE="/etc/init.d/${SERVICE_NAME}"
+    if [[ "$DRY_RUN" == "true" ]]; then
+        info "[DRY-RUN] Would create service: $OPENRC_SERVICE"
+        return
+    fi
+    cat > "$OPENRC_SERVICE" << EOF
+#!/sbin/openrc-run
+
+description="Sennet Network Observability Agent"
+command="${INSTALL_DIR}/${BINARY_NAME}"
+command_background=true
+pidfile="/run/${SERVICE_NAME}.pid"
+output_log="/var/log/${SERVICE_NAME}.log"
+error_log="/var/log/${SERVICE_NAME}.log"
+export RUST_LOG=info
+
+# Restart the agent if it dies, like Restart=always under systemd
+supervisor=supervise-daemon
+respawn_delay=10
+
+depend() {
+    need net
+    after firewall
+}
+EOF
+    chmod 755 "$OPENRC_SERVICE"
+    success "Created OpenRC service"
+
+    rc-update add "$SERVICE_NAME" default 2>/dev/null || true
+    success "Enabled $SERVICE_NAME service"
+}
+
+install_runit() {
+    # Void uses /etc/sv + /var/service; other runit setups use /etc/service
+    RUNIT_DIR="/etc/sv/${SERVICE_NAME}"
+    RUNSVDIR="/var/service"
+    [[ -d "$RUNSVDIR" ]] || RUNSVDIR="/etc/service"
+    if [[ "$DRY_RUN" == "true" ]]; then
+        info "[DRY-RUN] Would create service: $RUNIT_DIR (linked into $RUNSVDIR)"
+        return
+    fi
+    mkdir -p "$RUNIT_DIR/log" "/var/log/${SERVICE_NAME}"
+    cat > "$RUNIT_DIR/run" << EOF
+#!/bin/sh
+export RUST_LOG=info
+exec ${INSTALL_DIR}/${BINARY_NAME} 2>&1
+EOF
+    cat > "$RUNIT_DIR/log/run" << EOF
+#!/bin/sh
+exec svlogd -tt /var/log/${SERVICE_NAME}
+EOF
+    chmod 755 "$RUNIT_DIR/run" "$RUNIT_DIR/log/run"
+    # A down file keeps runsv from starting the agent before it is configured
+    touch "$RUNIT_DIR/down"
+    success "Created runit service"
+
+    mkdir -p "$RUNSVDIR"
+    ln -sf "$RUNIT_DIR" "$RUNSVDIR/${SERVICE_NAME}"
+    success "Enabled $SERVICE_NAME service"
+}
+
+install_sysvinit() {
+    SYSV_SERVICE="/etc/init.d/${SERVICE_NAME}"
+    if [[ "$DRY_RUN" == "true" ]]; then
+        info "[DRY-RUN] Would create service: $SYSV_SERVICE"
+        return
+    fi
+    cat > "$SYSV_SERVICE" << EOF
+#!/bin/sh
+### BEGIN INIT INFO
+# Provides:          ${SERVICE_NAME}
+# Required-Start:    \$network \$remote_fs
+# Required-Stop:     \$network \$remote_fs
+# Default-Start:     2 3 4 5
+# Default-Stop:      0 1 6
+# Short-Description: Sennet Network Observability Agent
+### END INIT INFO
+
+DAEMON="${INSTALL_DIR}/${BINARY_NAME}"
+PIDFILE="/run/${SERVICE_NAME}.pid"
+LOGFILE="/var/log/${SERVICE_NAME}.log"
+
+running() {
+    [ -f "\$PIDFILE" ] && kill -0 "\$(cat "\$PIDFILE")" 2>/dev/null
+}
+
+case "\$1" in
+    start)
+        running && exit 0
+        RUST_LOG=info nohup "\$DAEMON" >> "\$LOGFILE" 2>&1 &
+        echo \$! > "\$PIDFILE"
+        ;;
+    stop)
+        running && kill "\$(cat "\$PIDFILE")"
+        rm -f "\$PIDFILE"
+        ;;
+    restart)
+        "\$0" stop
+        sleep 1
+        "\$0" start
+        ;;
+    status)
+        # LSB exit codes: 0 running, 1 dead with pid file, 3 stopped
+        if running; then
+            echo "${SERVICE_NAME} is running"; exit 0
+        elif [ -f "\$PIDFILE" ]; then
+            echo "${SERVICE_NAME} is dead"; exit 1
+        fi
+        echo "${SERVICE_NAME} is stopped"; exit 3
+        ;;
+    *)
+        echo "Usage: \$0 {start|stop|restart|status}"; exit 2
+        ;;
+esac
+EOF
+    chmod 755 "$SYSV_SERVICE"
+    success "Created sysvinit service"
+
+    if command -v update-rc.d >/dev/null 2>&1; then
+        update-rc.d "$SERVICE_NAME" defaults >/dev/null 2>&1 || true
+    elif command -v chkconfig >/dev/null 2>&1; then
+        chkconfig --add "$SERVICE_NAME" 2>/dev/null || true
+    fi
+    success "Enabled $SERVICE_NAME service"
+}
+
+info "Creating $INIT_SYSTEM service..."
+case "$INIT_SYSTEM" in
+    systemd)
+        install_systemd
+        START_CMD="sudo systemctl start $SERVICE_NAME"
+        STATUS_CMD="sudo systemctl status $SERVICE_NAME"
+        LOGS_CMD="sudo journalctl -u $SERVICE_NAME -f"
+        ;;
+    openrc)
+        install_openrc
+        START_CMD="sudo rc-service $SERVICE_NAME start"
+        STATUS_CMD="sudo sennet status"
+        LOGS_CMD="sudo tail -f /var/log/$SERVICE_NAME.log"
+        ;;
+    runit)
+        install_runit
+        START_CMD="sudo rm $RUNIT_DIR/down && sudo sv up $SERVICE_NAME"
+        STATUS_CMD="sudo sennet status"
+        LOGS_CMD="sudo tail -f /var/log/$SERVICE_NAME/current"
+        ;;
+    sysvinit)
+        install_sysvinit
+        START_CMD="sudo /etc/init.d/$SERVICE_NAME start"
+        STATUS_CMD="sudo sennet status"
+        LOGS_CMD="sudo tail -f /var/log/$SERVICE_NAME.log"
+        ;;
+    *)
+        warn "No supported init system found; the agent will not start at boot"
+        START_CMD="sudo $INSTALL_DIR/$BINARY_NAME"
+        STATUS_CMD="sudo sennet status"
+        LOGS_CMD="(logs go to the terminal)"
+        ;;
+esac
 
 # Summary
 echo ""
@@ -273,9 +450,9 @@ echo ""
 echo "Next steps:"
 echo "  1. Edit configuration: sudo nano $CONFIG_DIR/config.yaml"
 echo "  2. Add your API key and server URL"
-echo "  3. Start the agent: sudo systemctl start $SERVICE_NAME"
-echo "  4. Check status: sudo systemctl status $SERVICE_NAME"
-echo "  5. View logs: sudo journalctl -u $SERVICE_NAME -f"
+echo "  3. Start the agent: $START_CMD"
+echo "  4. Check status: $STATUS_CMD"
+echo "  5. View logs: $LOGS_CMD"
 echo ""
 info "Binary installed at: $INSTALL_DIR/$BINARY_NAME"
 info "Config file at: $CONFIG_DIR/config.yaml"